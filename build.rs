@@ -3,7 +3,12 @@
 fn main() {
     println!("cargo:rustc-link-lib=dylib=evdev");
 
-    if cfg!(feature = "systemd") {
+    if cfg!(feature = "systemd") || cfg!(feature = "dbus") {
         println!("cargo:rustc-link-lib=dylib=systemd");
     }
+
+    // On glibc versions before 2.34, dlopen() lives in a separate library.
+    if cfg!(feature = "plugins") {
+        println!("cargo:rustc-link-lib=dylib=dl");
+    }
 }
\ No newline at end of file
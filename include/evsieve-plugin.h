@@ -0,0 +1,89 @@
+/* SPDX-License-Identifier: GPL-2.0-or-later */
+
+/*
+ * The interface for plugins that can be loaded with the --plugin argument of evsieve. A plugin is a
+ * shared object that exports the function evsieve_plugin_entry(). Plugins are only supported if
+ * evsieve has been built with the "plugins" feature.
+ *
+ * A plugin sees the events at the point in the stream where its --plugin argument was specified, and
+ * decides which events continue down the stream by passing them to the emit callback. Events are
+ * handled one report at a time. All functions are called from the same thread.
+ */
+
+#ifndef EVSIEVE_PLUGIN_H
+#define EVSIEVE_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* Increased whenever the interface changes in an incompatible way. */
+#define EVSIEVE_PLUGIN_ABI_VERSION 1
+
+struct evsieve_plugin_event {
+    uint16_t type;
+    uint16_t code;
+    int32_t value;
+    /* The value that the last event with the same type, code and domain had. */
+    int32_t previous_value;
+    /* Identifies the domain of the event. Copy it from an input event when creating new events. */
+    uint64_t domain;
+};
+
+/* Says that events with the given type, code and domain may have values between min and max inclusive. */
+struct evsieve_plugin_capability {
+    uint16_t type;
+    uint16_t code;
+    int32_t min;
+    int32_t max;
+    uint64_t domain;
+};
+
+typedef void (*evsieve_emit_event_fn)(void *context, const struct evsieve_plugin_event *event);
+typedef void (*evsieve_emit_capability_fn)(void *context, const struct evsieve_plugin_capability *capability);
+
+struct evsieve_plugin {
+    /* Must be EVSIEVE_PLUGIN_ABI_VERSION. */
+    uint32_t abi_version;
+
+    /*
+     * Called once when evsieve starts, with the value of the config= clause or an empty string.
+     * Returns the state that gets passed to the other functions, or NULL on failure. May be NULL,
+     * in which case the state is NULL.
+     */
+    void *(*init)(const char *config);
+
+    /*
+     * Called with the events of a report. Usually, the events of input devices enter the stream one
+     * at a time, but if an input device has the packets flag, all events between two SYN_REPORTs of
+     * that device are passed in a single call. Every event that should continue down the stream, whether
+     * it is one of the input events or a new one, must be passed to emit() together with the context.
+     * Events that are not passed to emit() are dropped. Events with an invalid type or code are
+     * dropped as well.
+     */
+    void (*process_events)(void *state, const struct evsieve_plugin_event *events, size_t count,
+                           evsieve_emit_event_fn emit, void *context);
+
+    /*
+     * Called while evsieve decides which capabilities the output devices get. Must pass every
+     * capability the emitted events may have to emit(), and must not change the state. If NULL,
+     * evsieve assumes that the plugin emits events with the same capabilities as it receives.
+     */
+    void (*process_caps)(void *state, const struct evsieve_plugin_capability *caps, size_t count,
+                         evsieve_emit_capability_fn emit, void *context);
+
+    /* Called when evsieve exits. May be NULL. */
+    void (*destroy)(void *state);
+};
+
+/* Every plugin must export this function. The returned struct must stay valid while the plugin is loaded. */
+const struct evsieve_plugin *evsieve_plugin_entry(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EVSIEVE_PLUGIN_H */
@@ -0,0 +1,80 @@
+/* SPDX-License-Identifier: GPL-2.0-or-later */
+
+/*
+ * The C API of evsieve, provided by libevsieve.so. It allows other programs to process events using
+ * the same arguments that the evsieve command line accepts, without involving any event devices.
+ *
+ * None of these functions are thread-safe with respect to the same engine. Error messages are kept
+ * per thread and can be retrieved with evsieve_last_error().
+ */
+
+#ifndef EVSIEVE_H
+#define EVSIEVE_H
+
+#include <stddef.h>
+#include <stdint.h>
+#include <linux/input.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct evsieve_engine evsieve_engine;
+
+/*
+ * Creates an engine from arguments like those of the evsieve program, e.g. {"--map", "key:a", "key:b"}.
+ * The program name must not be included. The --input, --control-fifo and --run-as arguments are not
+ * supported. Returns NULL on failure.
+ */
+evsieve_engine *evsieve_engine_new(int argc, const char *const *argv);
+
+/* Destroys an engine. Passing NULL is allowed. */
+void evsieve_engine_free(evsieve_engine *engine);
+
+/*
+ * Adds an event to the engine. The domain may be NULL. Events of type EV_SYN mark the end of a
+ * report, just like they do for event devices. The time of the event is ignored.
+ * Returns 0 on success, -1 on failure.
+ */
+int evsieve_engine_push(evsieve_engine *engine, const char *domain, const struct input_event *event);
+
+/*
+ * Writes up to `capacity` processed events to `events` and returns how many were written. The
+ * output contains EV_SYN events at the end of each report. The time of the events is zero.
+ */
+size_t evsieve_engine_pull(evsieve_engine *engine, struct input_event *events, size_t capacity);
+
+/*
+ * Returns the amount of milliseconds until the engine wants evsieve_engine_pull() to be called
+ * because of arguments like --delay, 0 if it should be called right away, or -1 if there is no
+ * need to call it until the next event is pushed.
+ */
+int evsieve_engine_timeout(evsieve_engine *engine);
+
+/*
+ * Declares that the events pushed with the given domain may have the given type and code, with a
+ * value between min and max inclusive. Used to compute the output capabilities.
+ * Returns 0 on success, -1 on failure.
+ */
+int evsieve_engine_add_capability(evsieve_engine *engine, const char *domain,
+                                  uint16_t type, uint16_t code, int32_t min, int32_t max);
+
+/*
+ * Writes a description of the events the engine may output to `buffer`, given the declared input
+ * capabilities. Each line looks like "key:a@domain 0~2", where the domain is omitted if it has no
+ * name. Like snprintf(), at most `size` bytes including the null terminator are written, and the
+ * length of the full description is returned.
+ */
+size_t evsieve_engine_capabilities(evsieve_engine *engine, char *buffer, size_t size);
+
+/*
+ * Returns the error message of the last function that failed on this thread, or NULL. The string
+ * remains valid until the next call to an evsieve function on this thread.
+ */
+const char *evsieve_last_error(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif /* EVSIEVE_H */
@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Python bindings for the event processing engine of evsieve. They expose the same functionality as
+//! the C API from evsieve's capi.rs in a more Pythonic way.
+//!
+//! Events are represented as (type, code, value) tuples of integers. The helper functions `event_code`
+//! and `event_name` translate between those integers and names like ("key", "a").
+
+#![allow(clippy::useless_conversion)] // False positives in the code generated by PyO3.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use evsieve::ecodes;
+use evsieve::engine::Engine;
+
+fn to_py_err(error: impl std::fmt::Display) -> PyErr {
+    PyValueError::new_err(error.to_string())
+}
+
+/// Processes events according to a list of evsieve arguments.
+#[pyclass(name = "Engine", unsendable)]
+struct PyEngine {
+    engine: Engine,
+}
+
+#[pymethods]
+impl PyEngine {
+    #[new]
+    fn new(args: Vec<String>) -> PyResult<Self> {
+        let engine = Engine::new(args).map_err(to_py_err)?;
+        Ok(PyEngine { engine })
+    }
+
+    /// Pushes an event into the engine. An event of type EV_SYN ends the current report.
+    #[pyo3(signature = (ev_type, code, value, domain=None))]
+    fn push(&mut self, ev_type: u16, code: u16, value: i32, domain: Option<&str>) -> PyResult<()> {
+        self.engine.push(domain, ev_type, code, value).map_err(to_py_err)
+    }
+
+    /// Ends the current report. Equivalent to pushing an EV_SYN event.
+    fn syn(&mut self) -> PyResult<()> {
+        self.engine.push(None, ecodes::EV_SYN, 0, 0).map_err(to_py_err)
+    }
+
+    /// Returns the events that have left the engine as a list of (type, code, value) tuples.
+    #[pyo3(signature = (max_count=None))]
+    fn pull(&mut self, max_count: Option<usize>) -> Vec<(u16, u16, i32)> {
+        self.engine.pull(max_count.unwrap_or(usize::MAX)).into_iter()
+            .map(|event| (event.type_, event.code, event.value))
+            .collect()
+    }
+
+    /// Returns the amount of milliseconds after which `pull()` should be called again, or None if
+    /// the engine has nothing scheduled.
+    fn timeout(&self) -> Option<i32> {
+        self.engine.timeout()
+    }
+
+    /// Declares that events pushed with the given domain may have the given type and code.
+    #[pyo3(signature = (ev_type, code, min, max, domain=None))]
+    fn add_capability(&mut self, ev_type: u16, code: u16, min: i32, max: i32, domain: Option<&str>) -> PyResult<()> {
+        self.engine.add_capability(domain, ev_type, code, min, max).map_err(to_py_err)
+    }
+
+    /// Returns a list of strings like "key:a@domain 0~2" describing which events may leave the engine.
+    fn capabilities(&self) -> Vec<String> {
+        self.engine.describe_capabilities()
+    }
+}
+
+/// Builds the argument list for an `Engine` step by step, e.g.
+/// `Pipeline().arg("--map", "key:a", "key:b").arg("--block", "key:c").build()`.
+#[pyclass(name = "Pipeline")]
+#[derive(Default)]
+struct PyPipeline {
+    args: Vec<String>,
+}
+
+#[pymethods]
+impl PyPipeline {
+    #[new]
+    fn new() -> Self {
+        PyPipeline::default()
+    }
+
+    /// Adds an argument like "--map" followed by its clauses.
+    #[pyo3(signature = (name, *values))]
+    fn arg(mut slf: PyRefMut<'_, Self>, name: String, values: Vec<String>) -> PyRefMut<'_, Self> {
+        slf.args.push(name);
+        slf.args.extend(values);
+        slf
+    }
+
+    /// Returns the arguments added so far.
+    fn args(&self) -> Vec<String> {
+        self.args.clone()
+    }
+
+    fn build(&self) -> PyResult<PyEngine> {
+        PyEngine::new(self.args.clone())
+    }
+}
+
+/// Returns the (type, code) pair for names like ("key", "a").
+#[pyfunction]
+fn event_code(type_name: &str, code_name: &str) -> PyResult<(u16, u16)> {
+    let code = ecodes::event_code(type_name, code_name).map_err(to_py_err)?;
+    Ok((code.ev_type().into(), code.code()))
+}
+
+/// Returns the name of an event code, like "key:a".
+#[pyfunction]
+fn event_name(ev_type: u16, code: u16) -> PyResult<String> {
+    let code = ecodes::checked_event_code(ev_type, code).map_err(to_py_err)?;
+    Ok(ecodes::event_name(code).into_owned())
+}
+
+#[pymodule]
+#[pyo3(name = "evsieve")]
+fn evsieve_module(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyEngine>()?;
+    module.add_class::<PyPipeline>()?;
+    module.add_function(wrap_pyfunction!(event_code, module)?)?;
+    module.add_function(wrap_pyfunction!(event_name, module)?)?;
+    module.add("EV_SYN", ecodes::EV_SYN)?;
+    module.add("EV_KEY", ecodes::EV_KEY)?;
+    module.add("EV_REL", ecodes::EV_REL)?;
+    module.add("EV_ABS", ecodes::EV_ABS)?;
+    Ok(())
+}
+
+#[test]
+fn unittest() {
+    pyo3::prepare_freethreaded_python();
+    Python::with_gil(|py| {
+        let module = PyModule::new_bound(py, "evsieve").unwrap();
+        evsieve_module(&module).unwrap();
+        let globals = pyo3::types::PyDict::new_bound(py);
+        globals.set_item("evsieve", module).unwrap();
+        py.run_bound(r#"
+engine = evsieve.Pipeline().arg("--map", "key:a@kb", "key:b").build()
+ev_type, code = evsieve.event_code("key", "a")
+engine.push(ev_type, code, 1, domain="kb")
+engine.syn()
+events = [(evsieve.event_name(t, c), v) for t, c, v in engine.pull()]
+assert events == [("key:b", 1), ("syn:report", 0)], events
+
+engine = evsieve.Engine(["--block", "key:a"])
+engine.push(ev_type, code, 1)
+key_c = evsieve.event_code("key", "c")
+engine.push(*key_c, 1)
+engine.syn()
+events = engine.pull(1)
+assert events == [(*key_c, 1)], events
+
+try:
+    evsieve.Engine(["--map", "key:bogus"])
+    assert False
+except ValueError:
+    pass
+"#, Some(&globals), None).unwrap();
+    });
+}
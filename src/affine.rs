@@ -234,6 +234,8 @@ fn unittest() {
         value, previous_value, domain,
         code: crate::event::EventCode::new(crate::event::EventType::new(1), 1),
         namespace: crate::event::Namespace::User,
+        stage: 0,
+        annotations: crate::annotation::Annotations::empty(),
     };
     let get_test_cap = |value_range| crate::capability::Capability {
         domain, values: Set::from(value_range),
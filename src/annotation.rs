@@ -0,0 +1,110 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Annotations are user-defined names that can be attached to events, like the "macro" part of
+//! "key:a^macro". Unlike domains, an event can carry several annotations at once, and mapping an event
+//! keeps its annotations. They have no meaning to evsieve itself; they only exist so that later arguments
+//! can tell where an event came from, e.g. to drop all events that were sent by a certain hook.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use crate::error::ArgumentError;
+
+/// The maximum amount of distinct annotation names, so a set of them fits in a single integer.
+const MAX_ANNOTATIONS: usize = 64;
+
+/// A set of annotations, stored as a bitset so events remain cheap to copy.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default, Debug)]
+pub struct Annotations(u64);
+
+impl Annotations {
+    pub const fn empty() -> Annotations {
+        Annotations(0)
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if all annotations in `other` are also in `self`.
+    pub fn contains(self, other: Annotations) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn union(self, other: Annotations) -> Annotations {
+        Annotations(self.0 | other.0)
+    }
+
+    /// Returns the set of annotations with the given names, like the "macro^slow" part of "key:a^macro^slow".
+    pub fn parse(names_str: &str) -> Result<Annotations, ArgumentError> {
+        let mut result = Annotations::empty();
+        for name in names_str.split('^') {
+            result = result.union(resolve(name)?);
+        }
+        Ok(result)
+    }
+
+    /// Returns the names of the annotations in this set, in the order they were first used.
+    pub fn names(self) -> Vec<String> {
+        let registry = REGISTRY.lock().expect("Fatal error: internal mutex poisoned.");
+        registry.names.iter().enumerate()
+            .filter(|(index, _)| self.0 & (1 << index) != 0)
+            .map(|(_, name)| name.clone())
+            .collect()
+    }
+}
+
+/// Returns the set containing only the annotation with the given name. Always returns the same set
+/// for the same name in the context of a single program execution.
+fn resolve(name: &str) -> Result<Annotations, ArgumentError> {
+    if name.is_empty() {
+        return Err(ArgumentError::new("Annotations may not be empty."));
+    }
+    if ! name.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+        return Err(ArgumentError::new(format!(
+            "Invalid annotation \"{}\": annotations may only contain letters, digits, dashes and underscores.", name
+        )));
+    }
+
+    let mut registry = REGISTRY.lock().expect("Fatal error: internal mutex poisoned.");
+    if let Some(&index) = registry.indices.get(name) {
+        return Ok(Annotations(1 << index));
+    }
+    let index = registry.names.len();
+    if index >= MAX_ANNOTATIONS {
+        return Err(ArgumentError::new(format!(
+            "Cannot use the annotation \"{}\": at most {} different annotations can be used.", name, MAX_ANNOTATIONS
+        )));
+    }
+    registry.names.push(name.to_owned());
+    registry.indices.insert(name.to_owned(), index);
+    Ok(Annotations(1 << index))
+}
+
+#[derive(Default)]
+struct Registry {
+    /// The name of each annotation, indexed by its bit.
+    names: Vec<String>,
+    indices: HashMap<String, usize>,
+}
+
+lazy_static!{
+    static ref REGISTRY: Mutex<Registry> = Mutex::new(Registry::default());
+}
+
+#[test]
+fn unittest() {
+    let first = Annotations::parse("unittest-first").unwrap();
+    let second = Annotations::parse("unittest-second").unwrap();
+    let both = Annotations::parse("unittest-second^unittest-first").unwrap();
+    assert_eq!(first, Annotations::parse("unittest-first").unwrap());
+    assert_ne!(first, second);
+    assert_eq!(both, first.union(second));
+    assert!(both.contains(first));
+    assert!(! first.contains(both));
+    assert!(first.contains(Annotations::empty()));
+    assert_eq!(first.names(), vec!["unittest-first".to_owned()]);
+
+    assert!(Annotations::parse("").is_err());
+    assert!(Annotations::parse("unittest-first^").is_err());
+    assert!(Annotations::parse("unittest@kb").is_err());
+}
@@ -2,10 +2,13 @@
 
 use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
 use crate::event::EventType;
 use crate::key::{Key, KeyParser};
 use crate::range::Interval;
-use crate::stream::absrel::RelToAbs;
+use crate::stream::absrel::{BoundsMode, RelToAbs};
+use crate::time::Duration;
 
 /// Represents a --rel-to-abs argument.
 pub(super) struct RelToAbsArg {
@@ -13,13 +16,21 @@ pub(super) struct RelToAbsArg {
     pub output_key: Key,
     pub output_range: Interval,
     pub speed: f64,
+    pub bounds: BoundsMode,
+    pub recenter_after: Option<Duration>,
+    pub resolution: i32,
+    pub edge_resistance: f64,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl RelToAbsArg {
 	pub fn parse(args: Vec<String>) -> Result<RelToAbsArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["speed"],
+            &["speed", "range", "bounds", "recenter-after", "resolution", "edge-resistance", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -31,10 +42,12 @@ impl RelToAbsArg {
             default_value: "",
             allow_values: true,
             allow_transitions: false,
+            allow_crossings: false,
             allow_domains: true,
             allow_ranges: true,
             allow_types: false,
             allow_relative_values: false,
+            allow_annotations: false,
             type_whitelist: Some(vec![EventType::ABS]),
             namespace: crate::event::Namespace::User,
         };
@@ -49,10 +62,14 @@ impl RelToAbsArg {
         let output_key = abs_parser.parse(output_key_str)?;
 
         let (output_key, output_range_opt) = output_key.split_value();
-        let output_range = match output_range_opt {
-            Some(range) => range,
-            None => return Err(ArgumentError::new(
-                "You need to provide a range for the possible output values of the --abs-to-rel argument. For example, \"--abs-to-rel rel:x abs:x:0~255\" will ensure that the outputted values for abs:x stay between 0 and 255."
+        let output_range = match (output_range_opt, arg_group.get_unique_clause("range")?) {
+            (Some(range), None) => range,
+            (None, Some(range_str)) => parse_range_clause(&range_str)?,
+            (Some(_), Some(_)) => return Err(ArgumentError::new(
+                "The range of the output values of the --rel-to-abs argument can be specified either on the output key or using the range= clause, but not both."
+            )),
+            (None, None) => return Err(ArgumentError::new(
+                "You need to provide a range for the possible output values of the --rel-to-abs argument. For example, \"--rel-to-abs rel:x abs:x:0~255\" or \"--rel-to-abs rel:x abs:x range=0~255\" will ensure that the outputted values for abs:x stay between 0 and 255."
             )),
         };
 
@@ -67,11 +84,79 @@ impl RelToAbsArg {
             None => 1.0,
         };
 
-        Ok(RelToAbsArg { input_key, output_key, output_range, speed })
+        let bounds = match arg_group.get_unique_clause("bounds")?.as_deref() {
+            None | Some("clamp") => BoundsMode::Clamp,
+            Some("wrap") => BoundsMode::Wrap,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid bounds mode for --rel-to-abs: \"{}\". The bounds mode must be either \"clamp\" or \"wrap\".", other
+            ))),
+        };
+
+        let recenter_after = match arg_group.get_unique_clause("recenter-after")? {
+            Some(value) => Some(crate::arguments::delay::parse_period_value(&value)?),
+            None => None,
+        };
+
+        let resolution = arg_group.get_unique_clause_i32("resolution")?.unwrap_or(0);
+        if resolution < 0 {
+            return Err(ArgumentError::new("The resolution of a --rel-to-abs argument cannot be negative."));
+        }
+
+        let edge_resistance = match arg_group.get_unique_clause("edge-resistance")? {
+            Some(value) => {
+                if bounds != BoundsMode::Clamp {
+                    return Err(ArgumentError::new("The edge-resistance= clause of a --rel-to-abs argument cannot be combined with bounds=wrap."));
+                }
+                match value.parse::<f64>() {
+                    Ok(edge_resistance) if edge_resistance >= 0.0 && edge_resistance.is_finite() => edge_resistance,
+                    _ => return Err(ArgumentError::new(format!(
+                        "Invalid value for the edge-resistance= clause: \"{}\". The value must be a nonnegative number.", value
+                    ))),
+                }
+            },
+            None => 0.0,
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(RelToAbsArg {
+            input_key, output_key, output_range, speed, bounds, recenter_after, resolution, edge_resistance, enabled_when, stage,
+        })
     }
 
 
     pub fn compile(self) -> RelToAbs {
-        RelToAbs::new(self.input_key, self.output_key, self.output_range, self.speed)
+        RelToAbs::new(
+            self.input_key, self.output_key, self.output_range, self.speed,
+            self.bounds, self.recenter_after, self.resolution,
+        ).with_edge_resistance(self.edge_resistance)
     }
+}
+
+/// Interprets the value of a range= clause like "-512~511". Both bounds must be specified.
+fn parse_range_clause(value: &str) -> Result<Interval, ArgumentError> {
+    let error = || ArgumentError::new(format!(
+        "Cannot interpret \"{}\" as a range. A range must be of the form MIN~MAX, e.g. \"range=0~255\".", value
+    ));
+    let (min_str, max_str) = match crate::utils::split_once(value, "~") {
+        (min_str, Some(max_str)) => (min_str, max_str),
+        (_, None) => return Err(error()),
+    };
+    let min: i32 = min_str.parse().map_err(|_| error())?;
+    let max: i32 = max_str.parse().map_err(|_| error())?;
+    if min > max {
+        return Err(ArgumentError::new(format!(
+            "The upper bound of a value range may not be smaller than its lower bound. Did you intend to use the range {}~{} instead?", max, min
+        )));
+    }
+    Ok(Interval::new(min, max))
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_range_clause("-512~511").unwrap(), Interval::new(-512, 511));
+    assert!(parse_range_clause("0~").is_err());
+    assert!(parse_range_clause("5").is_err());
+    assert!(parse_range_clause("5~1").is_err());
 }
\ No newline at end of file
@@ -0,0 +1,32 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::time::Duration;
+
+/// Represents a --batch argument.
+pub(super) struct BatchArg {
+    /// How long evsieve may wait after reading events before reading events again.
+    pub max_latency: Duration,
+}
+
+impl BatchArg {
+	pub fn parse(args: Vec<String>) -> Result<BatchArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["max-latency"],
+            false,
+            false,
+        )?;
+
+        let latency_str = arg_group.require_unique_clause("max-latency")?;
+        let max_latency = match latency_str.parse::<u64>() {
+            Ok(milliseconds) if milliseconds > 0 => Duration::from_millis(milliseconds),
+            _ => return Err(ArgumentError::new(format!(
+                "Cannot interpret \"{}\" as a latency. The max-latency of --batch must be a positive integer amount of milliseconds.", latency_str
+            ))),
+        };
+
+        Ok(BatchArg { max_latency })
+    }
+}
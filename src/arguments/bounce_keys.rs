@@ -0,0 +1,54 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::key::{Key, KeyParser};
+use crate::stream::bounce_keys::{BounceKeys, BounceMode};
+use crate::time::Duration;
+
+/// Represents a --bounce-keys argument.
+pub(super) struct BounceKeysArg {
+    pub keys: Vec<Key>,
+    pub period: Duration,
+    pub mode: BounceMode,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl BounceKeysArg {
+	pub fn parse(args: Vec<String>) -> Result<BounceKeysArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period", "mode", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+        let period = crate::arguments::delay::parse_period_value(
+            &arg_group.require_unique_clause("period")?
+        )?;
+
+        let mode = match arg_group.get_unique_clause("mode")?.as_deref() {
+            None | Some("release") => BounceMode::Release,
+            Some("chatter") => BounceMode::Chatter,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid mode for --bounce-keys: \"{}\". The mode must be either \"release\" or \"chatter\".", other
+            ))),
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(BounceKeysArg { keys, period, mode, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> BounceKeys {
+        BounceKeys::new(self.keys, self.period, self.mode)
+    }
+}
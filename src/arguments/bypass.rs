@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::key::{Key, KeyParser};
+
+/// Represents a --bypass argument.
+pub(super) struct BypassArg {
+    pub keys: Vec<Key>,
+    /// The position of the output device among the --output arguments, starting at one.
+    pub output_index: usize,
+}
+
+impl BypassArg {
+	pub fn parse(args: Vec<String>) -> Result<BypassArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["output"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter().parse_all(&arg_group.keys)?;
+        if keys.is_empty() {
+            return Err(ArgumentError::new("The --bypass argument requires at least one key."));
+        }
+        let output_index = match arg_group.get_unique_clause("output")? {
+            None => 1,
+            Some(value) => match value.parse::<usize>() {
+                Ok(index) if index > 0 => index,
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid output index \"{}\". The output= clause takes the position of an --output argument, starting at one.", value
+                ))),
+            },
+        };
+
+        Ok(BypassArg { keys, output_index })
+    }
+}
@@ -45,8 +45,10 @@ impl CapabilityArg {
             // helpful error message.
             allow_domains: true,
             allow_transitions: false,
+            allow_crossings: false,
             allow_types: false,
             allow_relative_values: false,
+            allow_annotations: false,
             type_whitelist: None,
             namespace: crate::event::Namespace::Output,
         };
@@ -63,7 +65,7 @@ impl CapabilityArg {
         for key_str in &arg_group.keys {
             let key_parse_context_msg = || format!("While parsing the key \"{}\":", key_str);
             let key = parser.parse(&key_str)?;
-            if key.requires_domain().is_some() {
+            if key.specifies_domain() {
                 return Err(ArgumentError::new(format!(
                     "The --with-capability argument modifies the capabilities of an output device from the Linux kernel's perspective. Because domains are an evsieve-specific concept that do not exist in Linux, it makes no sense to specify a domain here."
                 )).with_context_of(key_parse_context_msg));
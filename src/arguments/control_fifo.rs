@@ -1,24 +1,70 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::error::ArgumentError;
+use crate::error::{ArgumentError, Context};
 use crate::arguments::lib::ComplexArgGroup;
+use crate::control_fifo::COMMAND_NAMES;
+use crate::privileges::FileOwner;
 
 /// Represents a --control-fifo argument.
 pub(super) struct ControlFifoArg {
     pub paths: Vec<String>,
+    /// The permissions the FIFOs should get, as specified by the mode= clause.
+    pub mode: Option<u32>,
+    pub owner: Option<FileOwner>,
+    /// The names of the commands that may be written to these FIFOs, or None if all commands are allowed.
+    pub allowed_commands: Option<Vec<String>>,
 }
 
 impl ControlFifoArg {
 	pub fn parse(args: Vec<String>) -> Result<ControlFifoArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &[],
+            &["mode", "owner", "allow"],
             true,
             false,
         )?;
 
+        let mode = match arg_group.get_unique_clause("mode")? {
+            Some(mode_str) => Some(parse_mode(&mode_str)?),
+            None => None,
+        };
+        let owner = match arg_group.get_unique_clause("owner")? {
+            Some(owner_str) => Some(FileOwner::parse(&owner_str)
+                .map_err(|error| error.with_context(format!("While interpreting the clause owner={}:", owner_str)))?),
+            None => None,
+        };
+        let allowed_commands = match arg_group.get_unique_clause("allow")? {
+            Some(allow_str) => Some(parse_allowed_commands(&allow_str)?),
+            None => None,
+        };
+
         Ok(ControlFifoArg {
-            paths: arg_group.paths
+            paths: arg_group.paths,
+            mode, owner, allowed_commands,
         })
     }
-}
\ No newline at end of file
+}
+
+/// Interprets an octal file mode like "0660" or "620".
+pub(super) fn parse_mode(mode_str: &str) -> Result<u32, ArgumentError> {
+    match u32::from_str_radix(mode_str, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(mode),
+        _ => Err(ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a file mode. The mode must be an octal number like 0660.", mode_str
+        ))),
+    }
+}
+
+/// Interprets a comma-separated list of command names like "toggle,stats".
+fn parse_allowed_commands(allow_str: &str) -> Result<Vec<String>, ArgumentError> {
+    let names: Vec<String> = allow_str.split(',').map(str::to_owned).collect();
+    for name in &names {
+        if ! COMMAND_NAMES.contains(&name.as_str()) {
+            return Err(ArgumentError::new(format!(
+                "Unknown command name in the allow= clause: \"{}\". The following commands exist: {}.",
+                name, COMMAND_NAMES.join(", ")
+            )));
+        }
+    }
+    Ok(names)
+}
@@ -0,0 +1,121 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::{ArgumentError, RuntimeError, SystemError, Context};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::EventType;
+use crate::key::{Key, KeyParser};
+use crate::stream::curve::Curve;
+
+/// Represents a --curve argument.
+pub(super) struct CurveArg {
+    pub input_keys: Vec<Key>,
+    /// The points of the curve, sorted by ascending input value.
+    pub points: Vec<(i32, i32)>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl CurveArg {
+    pub fn parse(args: Vec<String>) -> Result<CurveArg, RuntimeError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["points", "table", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys_str = arg_group.get_keys_or_empty_key();
+        let mut parser = KeyParser::default_filter();
+        parser.type_whitelist = Some(vec![EventType::ABS]);
+        let input_keys = parser.parse_all(&keys_str)?;
+
+        let points = match (arg_group.get_unique_clause("points")?, arg_group.get_unique_clause("table")?) {
+            (Some(points_str), None) => parse_points(points_str.split(','))
+                .with_context_of(|| format!("While parsing the clause points={}:", points_str))?,
+            (None, Some(path)) => {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(SystemError::from)
+                    .with_context_of(|| format!("While trying to read the file {}:", path))?;
+                parse_table(&content)
+                    .with_context_of(|| format!("While parsing the curve table {}:", path))?
+            },
+            (Some(_), Some(_)) => return Err(ArgumentError::new("The --curve argument cannot have both a points= and a table= clause.").into()),
+            (None, None) => return Err(ArgumentError::new("The --curve argument requires either a points= or a table= clause.").into()),
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(CurveArg { input_keys, points, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> Curve {
+        Curve::new(self.input_keys, self.points)
+    }
+}
+
+/// Parses points like "8000:2000" and checks that together they form a valid curve.
+fn parse_points<'a>(point_strs: impl Iterator<Item=&'a str>) -> Result<Vec<(i32, i32)>, ArgumentError> {
+    let parse_value = |value_str: &str| value_str.parse::<i32>().map_err(|_| ArgumentError::new(format!(
+        "Cannot interpret \"{}\" as an integer.", value_str
+    )));
+
+    let mut points = Vec::new();
+    for point_str in point_strs {
+        let point = match crate::utils::split_once(point_str, ":") {
+            (input_str, Some(output_str)) => (parse_value(input_str)?, parse_value(output_str)?),
+            (_, None) => return Err(ArgumentError::new(format!(
+                "Cannot interpret \"{}\" as a point. Points must be specified as INPUT:OUTPUT, like \"8000:2000\".", point_str
+            ))),
+        };
+        points.push(point);
+    }
+
+    if points.len() < 2 {
+        return Err(ArgumentError::new("A curve requires at least two points."));
+    }
+    for pair in points.windows(2) {
+        if pair[0].0 >= pair[1].0 {
+            return Err(ArgumentError::new(format!(
+                "The input values of the points of a curve must be in ascending order, but {} is followed by {}.", pair[0].0, pair[1].0
+            )));
+        }
+    }
+    Ok(points)
+}
+
+/// Parses a curve table, which has one point on each line. The input and output value of each point are
+/// separated by whitespace, e.g. "8000 2000". Empty lines and everything after a # are ignored.
+fn parse_table(content: &str) -> Result<Vec<(i32, i32)>, ArgumentError> {
+    let mut point_strs = Vec::new();
+    for line in content.lines() {
+        let (line, _comment) = crate::utils::split_once(line, "#");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            [input, output] => point_strs.push(format!("{}:{}", input, output)),
+            _ => return Err(ArgumentError::new(format!(
+                "Each line of a curve table must contain exactly two numbers. Encountered: \"{}\"", line.trim()
+            ))),
+        }
+    }
+    parse_points(point_strs.iter().map(String::as_str))
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_points("0:0,8000:2000,32767:32767".split(',')).unwrap(), vec![(0, 0), (8000, 2000), (32767, 32767)]);
+    assert_eq!(parse_points("-100:50,100:-50".split(',')).unwrap(), vec![(-100, 50), (100, -50)]);
+    parse_points("0:0".split(',')).unwrap_err();
+    parse_points("0:0,0:10".split(',')).unwrap_err();
+    parse_points("10:0,0:10".split(',')).unwrap_err();
+    parse_points("0:0,10".split(',')).unwrap_err();
+    parse_points("0:0,10:x".split(',')).unwrap_err();
+
+    assert_eq!(parse_table("# input output\n0 0\n\n8000\t2000 # knee\n32767 32767\n").unwrap(), vec![(0, 0), (8000, 2000), (32767, 32767)]);
+    parse_table("0 0\n8000 2000 100\n").unwrap_err();
+}
@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::key::{Key, KeyParser};
+use crate::stream::default_block::DefaultBlock;
+
+/// Represents a --default argument.
+pub(super) struct DefaultArg {
+    /// Only events matching these keys are affected.
+    pub scope: Vec<Key>,
+}
+
+impl DefaultArg {
+	pub fn parse(args: Vec<String>) -> Result<DefaultArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["block"],
+            &[],
+            false,
+            true,
+        )?;
+
+        // Only blocking is supported for now, but requiring it leaves room for other defaults later.
+        if ! arg_group.has_flag("block") {
+            return Err(ArgumentError::new("The --default argument requires the block flag, e.g. \"--default block\"."));
+        }
+
+        let scope = KeyParser::pure().parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        Ok(DefaultArg { scope })
+    }
+
+    /// `allowed_keys` are the keys of the arguments that come after this one.
+    pub fn compile(self, allowed_keys: Vec<Key>) -> DefaultBlock {
+        DefaultBlock::new(self.scope, allowed_keys)
+    }
+}
@@ -2,7 +2,10 @@
 
 use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
 use crate::key::{Key, KeyParser};
+use crate::loopback::SuspendMode;
 use crate::stream::delay::Delay;
 use crate::time::Duration;
 
@@ -10,13 +13,19 @@ use crate::time::Duration;
 pub(super) struct DelayArg {
     pub keys: Vec<Key>,
     pub period: Duration,
+    /// Specified by the suspend= clause.
+    pub suspend_mode: SuspendMode,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl DelayArg {
 	pub fn parse(args: Vec<String>) -> Result<DelayArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["period"],
+            &["period", "suspend", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -27,12 +36,29 @@ impl DelayArg {
         let period = parse_period_value(
             &arg_group.require_unique_clause("period")?
         )?;
+        let suspend_mode = parse_suspend_clause(&arg_group)?.unwrap_or_default();
 
-        Ok(DelayArg { keys, period })
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(DelayArg { keys, period, suspend_mode, enabled_when, stage })
     }
 
     pub fn compile(self) -> Delay {
-        Delay::new(self.keys, self.period)
+        Delay::new(self.keys, self.period, self.suspend_mode)
+    }
+}
+
+/// Parses the suspend= clause, which decides whether the time the system spends suspended counts
+/// towards the periods of an argument. Returns None if the clause was not specified.
+pub(super) fn parse_suspend_clause(arg_group: &ComplexArgGroup) -> Result<Option<SuspendMode>, ArgumentError> {
+    match arg_group.get_unique_clause("suspend")?.as_deref() {
+        None => Ok(None),
+        Some("pause") => Ok(Some(SuspendMode::Pause)),
+        Some("count") => Ok(Some(SuspendMode::Count)),
+        Some(other) => Err(ArgumentError::new(format!(
+            "Invalid value for the suspend= clause: \"{}\". Valid values are pause and count.", other
+        ))),
     }
 }
 
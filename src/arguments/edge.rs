@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::EventValue;
+use crate::key::{Key, KeyParser};
+use crate::stream::edge::{Edge, EdgeMode};
+
+/// Represents an --edge argument.
+pub(super) struct EdgeArg {
+    pub keys: Vec<Key>,
+    pub mode: EdgeMode,
+    pub initial_value: EventValue,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl EdgeArg {
+	pub fn parse(args: Vec<String>) -> Result<EdgeArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["mode", "initial", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::pure().parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let mode = match arg_group.get_unique_clause("mode")?.as_deref() {
+            None | Some("alternate") => EdgeMode::Alternate,
+            Some("pulse") => EdgeMode::Pulse,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid mode for --edge: \"{}\". The mode must be either \"alternate\" or \"pulse\".", other
+            ))),
+        };
+
+        let initial_value = match arg_group.get_unique_clause("initial")?.as_deref() {
+            None | Some("0") => 0,
+            Some("1") => 1,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid initial state for --edge: \"{}\". The initial state must be either 0 or 1.", other
+            ))),
+        };
+        if mode == EdgeMode::Pulse && initial_value != 0 {
+            return Err(ArgumentError::new("The initial= clause of --edge has no effect in pulse mode."));
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(EdgeArg { keys, mode, initial_value, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> Edge {
+        Edge::new(self.keys, self.mode, self.initial_value)
+    }
+}
@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::control_fifo::COMMAND_NAMES;
+use crate::utils::json_string;
+use super::lib::ArgumentSpec;
+use super::parser::probe_argument;
+
+/// All arguments that evsieve understands, in the order they are reported by --feature-info.
+const ARGUMENT_NAMES: &[&str] = &[
+    "--input", "--output", "--map", "--copy", "--block", "--toggle", "--hook", "--withhold", "--print",
+    "--merge", "--delay", "--scale", "--curve", "--rel-to-abs", "--gyro-to-rel", "--invert-scroll",
+    "--mouse-keys", "--slow-keys", "--bounce-keys", "--inhibit-while-typing", "--max-hold", "--press-duration",
+    "--pressure-click", "--macro-slot", "--sequence", "--edge", "--default", "--capability", "--mirror", "--remap-modifiers",
+    "--quirk", "--schedule", "--on-vt", "--bypass", "--stage", "--plugin", "--control-fifo", "--stats", "--status-file",
+    "--histogram", "--introspect-socket", "--recover", "--batch", "--lock-memory", "--safe-mode", "--sandbox", "--realtime",
+    "--run-as", "--summarize", "--config", "--pipeline", "--shadow",
+];
+
+/// All cargo features that can be enabled at compile time, along with whether they are enabled.
+fn features() -> Vec<(&'static str, bool)> {
+    vec![
+        ("systemd", cfg!(feature = "systemd")),
+        ("auto-scan", cfg!(feature = "auto-scan")),
+        ("dbus", cfg!(feature = "dbus")),
+        ("plugins", cfg!(feature = "plugins")),
+    ]
+}
+
+fn json_list<'a>(items: impl IntoIterator<Item=&'a str>) -> String {
+    let items: Vec<String> = items.into_iter().map(json_string).collect();
+    format!("[{}]", items.join(","))
+}
+
+fn spec_to_json(spec: &ArgumentSpec) -> String {
+    format!(
+        "{{\"paths\":{},\"keys\":{},\"flags\":{},\"clauses\":{}}}",
+        spec.paths, spec.keys,
+        json_list(spec.flags.iter().map(String::as_str)),
+        json_list(spec.clauses.iter().map(String::as_str)),
+    )
+}
+
+/// Returns a JSON document describing this build of evsieve: its version, the cargo features it was
+/// compiled with, which arguments it accepts and the clauses and flags those arguments accept, and which
+/// commands the control FIFO understands. The clauses and flags are obtained from the argument parsers
+/// themselves. Arguments that do not follow the usual key/flag/clause format are reported as null.
+pub fn feature_info(version: &str) -> String {
+    let features = features().into_iter()
+        .filter(|&(_, enabled)| enabled)
+        .map(|(name, _)| name);
+    let arguments: Vec<String> = ARGUMENT_NAMES.iter()
+        .map(|&name| {
+            let spec = match probe_argument(name) {
+                Some(spec) => spec_to_json(&spec),
+                None => "null".to_owned(),
+            };
+            format!("{}:{}", json_string(name), spec)
+        })
+        .collect();
+
+    format!(
+        "{{\"version\":{},\"features\":{},\"arguments\":{{{}}},\"control-fifo-commands\":{}}}",
+        json_string(version),
+        json_list(features),
+        arguments.join(","),
+        json_list(COMMAND_NAMES.iter().copied()),
+    )
+}
+
+#[test]
+fn unittest() {
+    for &name in ARGUMENT_NAMES {
+        if name != "--run-as" {
+            assert!(probe_argument(name).is_some(), "Failed to probe {}", name);
+        }
+    }
+    assert!(probe_argument("--nonexistent").is_none());
+
+    let spec = probe_argument("--map").unwrap();
+    assert!(spec.keys);
+    assert!(spec.flags.contains(&"yield".to_owned()));
+
+    let info = feature_info("1.2.3");
+    assert!(info.starts_with("{\"version\":\"1.2.3\",\"features\":["));
+    assert!(info.contains("\"--run-as\":null"));
+    assert!(info.contains("\"control-fifo-commands\":[\"apply\","));
+
+    // Probing must not interfere with parsing arguments afterwards.
+    assert!(crate::arguments::map::MapArg::parse(vec!["--map".to_owned(), "key:a".to_owned()]).is_ok());
+}
@@ -0,0 +1,107 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::domain::{self, Domain};
+use crate::ecodes;
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::event::EventCode;
+use crate::state::{State, ToggleIndex};
+use crate::stream::StreamEntry;
+use crate::stream::gate::{Gated, LedCondition};
+use crate::utils;
+
+/// Represents an enabled-when=ID:INDEX or enabled-when=led:NAME:VALUE clause, which can be specified on
+/// most arguments that become part of the stream. The entries created by such an argument only act upon
+/// events while the toggle with the given id has the given index active, or while the given LED is on
+/// or off respectively.
+#[derive(Clone)]
+pub(super) enum EnabledWhenClause {
+    Toggle {
+        toggle_id: String,
+        /// Counted from zero, unlike the indices the user writes.
+        index: usize,
+    },
+    Led {
+        code: EventCode,
+        /// If None, the LED of any input device counts.
+        domain: Option<Domain>,
+        on: bool,
+    },
+}
+
+impl EnabledWhenClause {
+    pub fn parse(arg_group: &ComplexArgGroup) -> Result<Option<EnabledWhenClause>, ArgumentError> {
+        let value = match arg_group.get_unique_clause("enabled-when")? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        // Toggle ids cannot contain colons, so there is no ambiguity with toggles named "led".
+        if let Some(led_str) = value.strip_prefix("led:") {
+            if led_str.contains(':') {
+                return Ok(Some(parse_led_condition(led_str)?));
+            }
+        }
+
+        let (toggle_id, index_str) = match utils::split_once(&value, ":") {
+            (toggle_id, Some(index_str)) if ! toggle_id.is_empty() => (toggle_id, index_str),
+            _ => return Err(ArgumentError::new(format!(
+                "The enabled-when clause requires a toggle's id and an index separated by a colon, like enabled-when=mode:2. Encountered: enabled-when={}", value
+            ))),
+        };
+        let index = match index_str.parse::<usize>() {
+            Ok(0) => return Err(ArgumentError::new("Cannot use toggle index 0: toggle indices start at 1.")),
+            Ok(index) => index - 1,
+            Err(_) => return Err(ArgumentError::new(format!("Cannot interpret {} as a toggle index.", index_str))),
+        };
+        Ok(Some(EnabledWhenClause::Toggle { toggle_id: toggle_id.to_owned(), index }))
+    }
+
+    /// Wraps a stream entry so it only acts while this clause's toggle has the right index active, or
+    /// while this clause's LED has the right state.
+    pub fn compile(&self, entry: StreamEntry, state: &State, toggle_indices: &HashMap<String, ToggleIndex>) -> Result<StreamEntry, ArgumentError> {
+        let mut gated = Gated::new(entry);
+        match self {
+            EnabledWhenClause::Toggle { toggle_id, index } => {
+                let toggle_index = *toggle_indices.get(toggle_id).ok_or_else(|| {
+                    ArgumentError::new(format!("No toggle with the id \"{}\" exists.", toggle_id))
+                })?;
+                if *index >= state[toggle_index].size() {
+                    return Err(ArgumentError::new(format!(
+                        "The index {} is out of range for the toggle with id \"{}\".", index + 1, toggle_id
+                    )));
+                }
+                gated.set_toggle(toggle_index, *index);
+            },
+            &EnabledWhenClause::Led { code, domain, on } => {
+                gated.set_led(LedCondition { code, domain, on });
+            },
+        }
+        Ok(StreamEntry::Gated(gated))
+    }
+}
+
+/// Parses the part after "led:" of a clause like enabled-when=led:capsl:1@kb.
+fn parse_led_condition(led_str: &str) -> Result<EnabledWhenClause, ArgumentError> {
+    let (condition_str, domain_str) = utils::split_once(led_str, "@");
+    let domain = match domain_str {
+        Some(domain_str) => Some(domain::resolve(domain_str)?),
+        None => None,
+    };
+    let (code_str, value_str) = match utils::split_once(condition_str, ":") {
+        (code_str, Some(value_str)) => (code_str, value_str),
+        _ => return Err(ArgumentError::new(format!(
+            "An LED condition requires the name of an LED and a value, like enabled-when=led:capsl:1. Encountered: enabled-when=led:{}", led_str
+        ))),
+    };
+    let code = ecodes::event_code("led", code_str)?;
+    let on = match value_str {
+        "1" => true,
+        "0" => false,
+        _ => return Err(ArgumentError::new(format!(
+            "The value of an LED condition must be 0 or 1, like enabled-when=led:capsl:1. Encountered: enabled-when=led:{}", led_str
+        ))),
+    };
+    Ok(EnabledWhenClause::Led { code, domain, on })
+}
@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::EventType;
+use crate::key::{Key, KeyParser};
+use crate::stream::gyro::{Calibration, EnableMode, GyroToRel};
+use crate::time::Duration;
+
+/// The default width of the band that the gyroscope values must stay in to be considered at rest.
+/// The gyroscopes of most game controllers report 1024 units per degree per second.
+const DEFAULT_REST_THRESHOLD: f64 = 1024.0;
+
+/// Represents a --gyro-to-rel argument.
+pub(super) struct GyroToRelArg {
+    pub input_key: Key,
+    pub output_key: Key,
+    pub speed: f64,
+    pub enable_key: Option<Key>,
+    pub enable_mode: EnableMode,
+    pub calibration: Option<Calibration>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl GyroToRelArg {
+	pub fn parse(args: Vec<String>) -> Result<GyroToRelArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["toggle"],
+            &["speed", "enable", "calibrate", "rest-threshold", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let mut abs_parser = KeyParser::default_filter();
+        abs_parser.type_whitelist = Some(vec![EventType::ABS]);
+        let mut rel_parser = KeyParser::default_mask();
+        rel_parser.type_whitelist = Some(vec![EventType::REL]);
+        rel_parser.allow_values = false;
+
+        let key_strs = arg_group.get_keys_or_empty_key();
+        let (input_key_str, output_key_str) = match key_strs.as_slice() {
+            [a, b] => (a, b),
+            _ => return Err(ArgumentError::new("The --gyro-to-rel argument needs to be provided exactly two keys, the first one matching the abs events reported by the gyroscope and the second one the rel event they get mapped to.")),
+        };
+        let input_key = abs_parser.parse(input_key_str)?;
+        let output_key = rel_parser.parse(output_key_str)?;
+
+        let speed = match arg_group.get_unique_clause("speed")? {
+            Some(speed_str) => parse_f64(&speed_str, "speed")?,
+            None => 1.0,
+        };
+
+        let enable_key = match arg_group.get_unique_clause("enable")? {
+            Some(key_str) => Some(KeyParser::default_filter().parse(&key_str)?),
+            None => None,
+        };
+        let enable_mode = match arg_group.has_flag("toggle") {
+            true => EnableMode::Toggle,
+            false => EnableMode::Hold,
+        };
+        if enable_key.is_none() && enable_mode == EnableMode::Toggle {
+            return Err(ArgumentError::new("The toggle flag of --gyro-to-rel requires an enable= clause."));
+        }
+
+        let rest_threshold = match arg_group.get_unique_clause("rest-threshold")? {
+            Some(threshold_str) => parse_f64(&threshold_str, "rest threshold")?,
+            None => DEFAULT_REST_THRESHOLD,
+        };
+        let calibration = match arg_group.get_unique_clause("calibrate")?.as_deref() {
+            Some("off") => None,
+            Some(period_str) => Some(Calibration {
+                period: crate::arguments::delay::parse_period_value(period_str)?,
+                rest_threshold,
+            }),
+            None => Some(Calibration { period: Duration::from_secs(1), rest_threshold }),
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(GyroToRelArg { input_key, output_key, speed, enable_key, enable_mode, calibration, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> GyroToRel {
+        GyroToRel::new(self.input_key, self.output_key, self.speed, self.enable_key, self.enable_mode, self.calibration)
+    }
+}
+
+pub(super) fn parse_f64(value: &str, name: &str) -> Result<f64, ArgumentError> {
+    match value.parse::<f64>() {
+        Ok(number) if number.is_finite() => Ok(number),
+        _ => Err(ArgumentError::new(format!("Cannot interpret the {} of \"{}\" as a number.", name, value))),
+    }
+}
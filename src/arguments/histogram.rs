@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::key::{Key, KeyParser};
+use crate::stream::histogram::{Histogram, HistogramFormat};
+
+const DEFAULT_BUCKETS: usize = 32;
+const MAX_BUCKETS: usize = 4096;
+
+/// Represents a --histogram argument.
+pub(super) struct HistogramArg {
+    pub keys: Vec<Key>,
+    pub buckets: usize,
+    pub format: HistogramFormat,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl HistogramArg {
+	pub fn parse(args: Vec<String>) -> Result<HistogramArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["buckets", "format", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let buckets = match arg_group.get_unique_clause("buckets")? {
+            Some(value) => match value.parse::<usize>() {
+                Ok(buckets) if (1 ..= MAX_BUCKETS).contains(&buckets) => buckets,
+                _ => return Err(ArgumentError::new(format!(
+                    "The buckets= clause of --histogram requires a number between 1 and {}, encountered: buckets={}", MAX_BUCKETS, value
+                ))),
+            },
+            None => DEFAULT_BUCKETS,
+        };
+
+        let format = match arg_group.get_unique_clause("format")? {
+            Some(value) => match value.as_str() {
+                "text" => HistogramFormat::Text,
+                "json" => HistogramFormat::Json,
+                other => return Err(ArgumentError::new(format!("Invalid --histogram format: {}", other))),
+            },
+            None => HistogramFormat::Text,
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(HistogramArg { keys, buckets, format, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> Histogram {
+        Histogram::new(self.keys, self.buckets, self.format)
+    }
+}
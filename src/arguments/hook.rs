@@ -4,22 +4,32 @@ use crate::error::{ArgumentError, InternalError, RuntimeError};
 use crate::range::Interval;
 use crate::utils;
 use crate::state::{State, ToggleIndex};
-use crate::stream::hook::{Effect, Trigger, EventDispatcher};
+use crate::stream::hook::{Burst, Effect, Trigger, EventDispatcher};
+use crate::stream::macro_slot::MacroCommand;
+use crate::effects::signal::SendSignal;
+use crate::effects::notify::NotifyTemplate;
+use crate::io::input::RepeatCommand;
 use crate::key::{Key, KeyParser};
 use crate::event::{Namespace, EventType};
+use crate::domain::Domain;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
 use std::collections::HashMap;
 use crate::time::Duration;
+use crate::loopback::SuspendMode;
 
 /// The KeyParser that is used to parse Hook keys.
 pub(super) const PARSER: KeyParser = KeyParser {
     allow_transitions: false,
+    allow_crossings: false,
     allow_values: true,
     allow_ranges: true,
     allow_domains: true,
     allow_types: false,
     default_value: "1~",
     allow_relative_values: false,
+    allow_annotations: true,
     type_whitelist: None,
     namespace: Namespace::User,
 };
@@ -34,12 +44,46 @@ pub(super) struct HookArg {
     pub toggle_action: HookToggleAction,
     pub period: Option<Duration>,
     pub sequential: bool,
+    /// Specified by the interval= clause. Only allowed on sequential hooks.
+    pub interval: Option<Duration>,
+    /// Specified by the suspend= clause.
+    pub suspend_mode: SuspendMode,
+    /// Specified by the strict-channels flag, either on this hook or on a --withhold that follows it.
+    pub strict_channels: bool,
+    /// Specified by the accumulate= clause. Only applies to keys of type EV_REL.
+    pub accumulate: Option<i32>,
     /// Specified by the send-key and send-event clauses.
     pub event_dispatcher: EventDispatcherArg,
 
     /// Specified by the breaks-on clause. Whenever an event matches one of the following
     /// keys but not one of its keys_and_str, all trackers invalidate.
     pub breaks_on: Vec<Key>,
+
+    /// Specified by the record-start, record-stop and play clauses.
+    pub macro_commands: Vec<MacroCommand>,
+
+    /// Specified by the set-repeat clause.
+    pub repeat_commands: Vec<RepeatCommand>,
+
+    /// Specified by the recenter flag and clauses. None recenters the --rel-to-abs arguments of all domains.
+    pub recenter_commands: Vec<Option<Domain>>,
+
+    /// The values of the effect clauses, each of which names a built-in effect from `crate::effects`.
+    pub effects: Vec<String>,
+
+    /// Specified by the send-signal clause.
+    pub send_signals: Vec<SendSignal>,
+
+    /// Specified by the notify clause.
+    pub notify: Option<NotifyTemplate>,
+
+    /// Specified by the id clause. The hook sends hook:ID events when it activates and releases.
+    pub id: Option<String>,
+
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 /// I'm undecided on the name of the send-event, so I'm creating a constant for it to make sure I don't forget
@@ -50,8 +94,8 @@ const SEND_KEY_CLAUSE: &str = "send-key";
 impl HookArg {
 	pub fn parse(args: Vec<String>) -> Result<HookArg, RuntimeError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &["toggle", "sequential"],
-            &["exec-shell", "toggle", "period", SEND_KEY_CLAUSE, SEND_EVENT_CLAUSE, "breaks-on"],
+            &["toggle", "sequential", "strict-channels", "record-stop", "recenter"],
+            &["exec-shell", "toggle", "recenter", "period", "interval", "suspend", "accumulate", "burst-interval", SEND_KEY_CLAUSE, SEND_EVENT_CLAUSE, "breaks-on", "record-start", "play", "set-repeat", "effect", "send-signal", "notify", "id", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -59,57 +103,145 @@ impl HookArg {
         let toggle_action = HookToggleAction::parse(arg_group.has_flag("toggle"), arg_group.get_clauses("toggle"))?;
         let keys_str = arg_group.keys.clone();
         let keys = PARSER.parse_all(&keys_str)?;
-        let keys_and_str = keys.into_iter().zip(keys_str).collect();
+        let keys_and_str: Vec<(Key, String)> = keys.into_iter().zip(keys_str).collect();
 
         let sequential = arg_group.has_flag("sequential");
+        let strict_channels = arg_group.has_flag("strict-channels");
         let period = match arg_group.get_unique_clause("period")? {
             None => None,
             Some(value) => Some(crate::arguments::delay::parse_period_value(&value)?),
         };
+        let interval = match arg_group.get_unique_clause("interval")? {
+            None => None,
+            Some(value) => {
+                if ! sequential {
+                    return Err(ArgumentError::new("The interval= clause can only be used on sequential hooks.").into());
+                }
+                let interval = crate::arguments::delay::parse_period_value(&value)?;
+                if interval == Duration::from_nanos(0) {
+                    return Err(ArgumentError::new("The interval= clause of a --hook must be positive.").into());
+                }
+                Some(interval)
+            },
+        };
+        let suspend_mode = match crate::arguments::delay::parse_suspend_clause(&arg_group)? {
+            Some(_) if period.is_none() && interval.is_none() => {
+                return Err(ArgumentError::new("The suspend= clause of a --hook requires a period= or interval= clause.").into());
+            },
+            suspend_mode => suspend_mode.unwrap_or_default(),
+        };
+        let accumulate = match arg_group.get_unique_clause("accumulate")? {
+            None => None,
+            Some(value) => {
+                if sequential {
+                    return Err(ArgumentError::new("The accumulate= clause cannot be used on sequential hooks.").into());
+                }
+                if ! keys_and_str.iter().any(|(key, _)| key.requires_event_type() == Some(EventType::REL)) {
+                    return Err(ArgumentError::new("The accumulate= clause can only be used on hooks that trigger on events of type rel, such as rel:wheel.").into());
+                }
+                match value.parse::<i32>() {
+                    Ok(threshold) if threshold > 0 => Some(threshold),
+                    _ => return Err(ArgumentError::new(format!(
+                        "Invalid value for the accumulate= clause: \"{}\". The value must be a positive integer.", value
+                    )).into()),
+                }
+            },
+        };
 
         // Parse the send-key and send-event clauses.
         let mut event_dispatcher = EventDispatcherArg::new();
         for (name, value) in arg_group.clauses() {
             match name {
                 SEND_KEY_CLAUSE => {
-                    let key = parse_send_key_clause(value)?;
-                    event_dispatcher.add_send_key(key);
+                    let (key_str, count) = parse_burst_count(value)?;
+                    let key = parse_send_key_clause(&key_str)?;
+                    event_dispatcher.add_send_key(key, count);
                 },
                 SEND_EVENT_CLAUSE => {
-                    let key = parse_send_event_clause(value)?;
-                    event_dispatcher.add_send_event(key);
+                    let (key_str, count) = parse_burst_count(value)?;
+                    let key = parse_send_event_clause(&key_str)?;
+                    event_dispatcher.add_send_event(key, count);
                 },
                 _ => (),
             }
         };
+        if let Some(value) = arg_group.get_unique_clause("burst-interval")? {
+            let burst_interval = crate::arguments::delay::parse_period_value(&value)?;
+            if burst_interval == Duration::from_nanos(0) {
+                return Err(ArgumentError::new("The burst-interval= clause of a --hook must be positive.").into());
+            }
+            event_dispatcher.burst_interval = Some(burst_interval);
+        }
+
+        // A hook with an id behaves as if it had a send-key=hook:ID clause.
+        let id = arg_group.get_unique_clause("id")?;
+        if let Some(id) = &id {
+            crate::ecodes::event_code("hook", id)?;
+            let key = KeyParser::pure().parse(&format!("hook:{}", id))?;
+            event_dispatcher.add_send_key(key, 1);
+        }
 
         let breaks_on = KeyParser::default_filter()
             .parse_all(&arg_group.get_clauses("breaks-on"))?;
 
+        let macro_commands = crate::arguments::macro_slot::parse_hook_macro_commands(&arg_group)?;
+
+        let repeat_commands = arg_group.get_clauses("set-repeat").iter()
+            .map(|value| parse_repeat_command(value))
+            .collect::<Result<Vec<RepeatCommand>, ArgumentError>>()?;
+
+        let mut recenter_commands = arg_group.get_clauses("recenter").iter()
+            .map(|domain_str| crate::domain::resolve(domain_str.strip_prefix('@').unwrap_or(domain_str)).map(Some))
+            .collect::<Result<Vec<Option<Domain>>, ArgumentError>>()?;
+        if arg_group.has_flag("recenter") {
+            recenter_commands.push(None);
+        }
+
+        // Effects cannot be cloned, so only their specifications are stored until the hook is built.
+        let effects = arg_group.get_clauses("effect");
+        for effect in &effects {
+            crate::effects::validate(effect)?;
+        }
+
+        let send_signals = arg_group.get_clauses("send-signal").iter()
+            .map(|value| SendSignal::parse(value))
+            .collect::<Result<Vec<SendSignal>, ArgumentError>>()?;
+
+        let notify = arg_group.get_unique_clause("notify")?
+            .map(|template| NotifyTemplate::parse(&template))
+            .transpose()?;
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
         if arg_group.keys.is_empty() {
             Err(ArgumentError::new("A --hook argument requires at least one key.").into())
         } else {
             Ok(HookArg {
                 keys_and_str,
                 exec_shell: arg_group.get_clauses("exec-shell"),
-                toggle_action, period, sequential, event_dispatcher, breaks_on
+                toggle_action, period, sequential, interval, suspend_mode, strict_channels, accumulate, event_dispatcher, breaks_on, macro_commands, repeat_commands, recenter_commands, effects,
+                send_signals, notify, id, enabled_when, stage,
             })
         }
     }
 
     pub fn compile_trigger(&self) -> Trigger {
         let keys: Vec<Key> = self.keys_and_str.iter().map(|(key, _)| key.clone()).collect();
-        Trigger::new(keys, self.breaks_on.clone(), self.period, self.sequential)
+        Trigger::new(keys, self.breaks_on.clone(), self.period, self.sequential, self.interval, self.strict_channels, self.accumulate)
+            .with_suspend_mode(self.suspend_mode)
     }
 }
 
 #[derive(Clone)]
 pub struct EventDispatcherArg {
     /// These events need to be sent when the hook activates in the order specified.
-    pub on_press: Vec<Key>,
+    pub on_press: Vec<Burst>,
     /// These events need to be sent when the hook activates *in the order specified*. Events that should be
     /// sent in reverse order such as from send-key will be put into this vector in reverse order.
     pub on_release: Vec<Key>,
+    /// Specified by the burst-interval= clause.
+    pub burst_interval: Option<Duration>,
 }
 
 impl EventDispatcherArg {
@@ -117,44 +249,82 @@ impl EventDispatcherArg {
         EventDispatcherArg {
             on_press: Vec::new(),
             on_release: Vec::new(),
+            burst_interval: None,
         }
     }
 
-    fn add_send_key(&mut self, key: Key) {
+    fn add_send_key(&mut self, key: Key, count: usize) {
         let mut on_press_key = key.clone();
         on_press_key.set_value(Interval::new(1, 1));
         let mut on_release_key = key;
         on_release_key.set_value(Interval::new(0, 0));
 
-        self.on_press.push(on_press_key);
+        self.on_press.push(Burst { key: on_press_key, count, release: Some(on_release_key.clone()) });
         self.on_release.insert(0, on_release_key);
     }
 
-    fn add_send_event(&mut self, key: Key) {
-        self.on_press.push(key);
+    fn add_send_event(&mut self, key: Key, count: usize) {
+        self.on_press.push(Burst { key, count, release: None });
     }
 
     pub fn compile(self) -> EventDispatcher {
-        EventDispatcher::new(self.on_press, self.on_release)
+        EventDispatcher::new(self.on_press, self.on_release, self.burst_interval)
     }
 
     /// Returns an iterator over all events that this hook might send.
     pub fn sendable_events(&self) -> impl Iterator<Item=&Key> {
-        let EventDispatcherArg { on_press, on_release } = self;
-        on_press.iter().chain(on_release)
+        let EventDispatcherArg { on_press, on_release, burst_interval: _ } = self;
+        on_press.iter().map(|burst| &burst.key).chain(on_release)
     }
 }
 
-fn parse_send_key_clause(key: &str) -> Result<Key, RuntimeError> {
+/// Splits a repeat count like the ":x10" of "key:right:x10@kb" off the value of a send-key or send-event
+/// clause. Returns the value without the count, and the count, which is 1 if none was specified.
+fn parse_burst_count(value: &str) -> Result<(String, usize), ArgumentError> {
+    let (key_str, domain_str) = utils::split_once(value, "@");
+    let (base_str, count) = match key_str.rfind(":x") {
+        // Only counts if only digits follow, because "x" is also the name of some event codes, e.g. "key:x".
+        Some(index) if key_str.len() > index + 2 && key_str[index + 2 ..].chars().all(|c| c.is_ascii_digit()) => {
+            let count_str = &key_str[index + 2 ..];
+            match count_str.parse::<usize>() {
+                Ok(count) if count >= 1 => (&key_str[.. index], count),
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid repeat count \"x{}\" in \"{}\": the count must be a positive integer.", count_str, value
+                ))),
+            }
+        },
+        _ => (key_str, 1),
+    };
+    let key_str = match domain_str {
+        Some(domain_str) => format!("{}@{}", base_str, domain_str),
+        None => base_str.to_owned(),
+    };
+    Ok((key_str, count))
+}
+
+/// Interprets the value of a set-repeat clause, e.g. "off", "toggle@kb" or "250,33@kb".
+fn parse_repeat_command(value: &str) -> Result<RepeatCommand, ArgumentError> {
+    let (setting_str, domain_str) = utils::split_once(value, "@");
+    let setting = crate::arguments::input::parse_repeat_setting(setting_str)?;
+    let domain = match domain_str {
+        Some(domain_str) => Some(crate::domain::resolve(domain_str)?),
+        None => None,
+    };
+    Ok(RepeatCommand { domain, setting })
+}
+
+pub(super) fn parse_send_key_clause(key: &str) -> Result<Key, RuntimeError> {
     KeyParser {
         allow_transitions: false,
+        allow_crossings: false,
         allow_values: false,
         allow_ranges: false,
         allow_domains: true,
         allow_types: false,
         default_value: "",
         allow_relative_values: false,
-        type_whitelist: Some(vec![EventType::KEY]),
+        allow_annotations: true,
+        type_whitelist: Some(vec![EventType::KEY, EventType::SIGNAL]),
         namespace: Namespace::User,
     }.parse(key).map_err(Into::into)
 }
@@ -167,12 +337,14 @@ fn parse_send_event_clause(key: &str) -> Result<Key, RuntimeError> {
     // As workaround, we just check locally whether this key has an event value.
     let event = KeyParser {
         allow_transitions: false,
+        allow_crossings: false,
         allow_values: true,
         allow_ranges: false,
         allow_domains: true,
         allow_types: false,
         default_value: "",
         allow_relative_values: false,
+        allow_annotations: true,
         type_whitelist: None,
         namespace: Namespace::User,
     }.parse(key)?;
@@ -281,6 +453,7 @@ impl HookToggleAction {
                     HookToggleShift::Next => state[toggle_index].advance(),
                     HookToggleShift::ToIndex(value) => state[toggle_index].set_value_wrapped(value),
                 }
+                state.sync_followers();
             }));
         }
         if let Some(shift) = self.global_action {
@@ -292,9 +465,22 @@ impl HookToggleAction {
                         HookToggleShift::ToIndex(value) => toggle.set_value_wrapped(value),
                     }
                 }
+                state.sync_followers();
             }));
         }
 
         Ok(effects)
     }
 }
+
+#[test]
+fn unittest_burst_count() {
+    let parse = |value: &str| parse_burst_count(value).unwrap();
+    assert_eq!(parse("key:right:x10"), ("key:right".to_owned(), 10));
+    assert_eq!(parse("key:right:x3@kb"), ("key:right@kb".to_owned(), 3));
+    assert_eq!(parse("rel:x:5:x2"), ("rel:x:5".to_owned(), 2));
+    assert_eq!(parse("key:x"), ("key:x".to_owned(), 1));
+    assert_eq!(parse("key:x@x2"), ("key:x@x2".to_owned(), 1));
+    assert_eq!(parse("btn:x:x4"), ("btn:x".to_owned(), 4));
+    parse_burst_count("key:a:x0").unwrap_err();
+}
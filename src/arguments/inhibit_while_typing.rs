@@ -0,0 +1,66 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::key::{Key, KeyParser};
+use crate::stream::inhibit_while_typing::InhibitWhileTyping;
+use crate::time::Duration;
+
+/// The events that get dropped while typing if no keys are specified.
+const DEFAULT_KEYS: &[&str] = &["btn:touch", "rel"];
+/// The events that count as typing if no typing clause is specified.
+const DEFAULT_TYPING_KEYS: &[&str] = &["key"];
+/// Holding a modifier while using the pointer is common, e.g. for ctrl+click, so modifiers do not count
+/// as typing unless the except clause says otherwise.
+const DEFAULT_EXCEPT_KEYS: &[&str] = &[
+    "key:leftctrl", "key:rightctrl", "key:leftshift", "key:rightshift",
+    "key:leftalt", "key:rightalt", "key:leftmeta", "key:rightmeta",
+];
+
+/// Represents an --inhibit-while-typing argument.
+pub(super) struct InhibitWhileTypingArg {
+    pub keys: Vec<Key>,
+    pub typing_keys: Vec<Key>,
+    pub except_keys: Vec<Key>,
+    pub period: Duration,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl InhibitWhileTypingArg {
+    pub fn parse(args: Vec<String>) -> Result<InhibitWhileTypingArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period", "typing", "except", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let or_default = |key_strs: Vec<String>, default: &[&str]| match key_strs.is_empty() {
+            true => default.iter().map(|&key_str| key_str.to_owned()).collect(),
+            false => key_strs,
+        };
+        let keys = KeyParser::default_filter()
+            .parse_all(&or_default(arg_group.keys.clone(), DEFAULT_KEYS))?;
+        let typing_keys = KeyParser::default_filter()
+            .parse_all(&or_default(arg_group.get_clauses("typing"), DEFAULT_TYPING_KEYS))?;
+        let except_keys = KeyParser::default_filter()
+            .parse_all(&or_default(arg_group.get_clauses("except"), DEFAULT_EXCEPT_KEYS))?;
+        let period = crate::arguments::delay::parse_period_value(
+            &arg_group.require_unique_clause("period")?
+        )?;
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(InhibitWhileTypingArg { keys, typing_keys, except_keys, period, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> InhibitWhileTyping {
+        InhibitWhileTyping::new(self.keys, self.typing_keys, self.except_keys, self.period)
+    }
+}
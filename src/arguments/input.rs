@@ -1,12 +1,17 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use std::os::unix::io::RawFd;
 use std::path::Path;
 use crate::domain;
 use crate::domain::Domain;
 use crate::persist::storage::DeviceCache;
-use crate::predevice::{GrabMode, PersistState};
-use crate::error::{ArgumentError, SystemError};
-use crate::arguments::lib::ComplexArgGroup;
+use crate::predevice::{GrabMode, OpenMode, PersistState};
+use crate::error::{ArgumentError, Context, SystemError};
+use crate::io::input::{ReadFilter, RepeatSetting, StaleAction, StaleSetting};
+use crate::key::KeyParser;
+use crate::io::replay::ReplaySpeed;
+use crate::time::Duration;
+use crate::arguments::lib::{ComplexArgGroup, parse_fd_clause};
 
 /// Represents an --input argument.
 pub(super) struct InputDevice {
@@ -16,8 +21,43 @@ pub(super) struct InputDevice {
     /// At least one path must be specified.
     /// TODO (Low Priority): Consider adding a newtype InputDevicePath for extra type safety.
 	pub paths: Vec<String>,
+    /// File descriptors of already opened event devices that were inherited from the parent process.
+    /// Each of them is treated like an additional path.
+    pub fds: Vec<RawFd>,
     pub grab_mode: GrabMode,
     pub persist_mode: PersistMode,
+    pub resync_mode: ResyncMode,
+    pub repeat_setting: Option<RepeatSetting>,
+    pub stale_setting: Option<StaleSetting>,
+    /// If true, a virtual device is automatically created at the end of the stream, to which all
+    /// events of this device are written that were not written to other output devices.
+    pub auto_output: bool,
+    pub open_mode: OpenMode,
+    /// If true, evsieve only reads events from this device and never changes anything about it.
+    pub observe_only: bool,
+    /// If Some, the paths are files containing raw input events rather than event devices.
+    pub replay: Option<ReplaySpeed>,
+    /// The intervals between replayed events are multiplied by this factor.
+    pub time_scale: f64,
+    /// If Some, only the events matching this filter are read from the devices.
+    pub read_filter: Option<ReadFilter>,
+    /// The name of the fallback group these devices belong to, as specified by the group= clause.
+    pub fallback_group: Option<String>,
+    /// The priority of these devices within their fallback group. Higher is preferred.
+    pub priority: i32,
+    /// If true, the events of these devices enter the stream one packet at a time.
+    pub packets: bool,
+}
+
+/// Determines what happens with the events that libevdev generates to resynchronise the
+/// device state after the kernel dropped some events (SYN_DROPPED).
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum ResyncMode {
+    /// Resync events enter the stream in the same way as all other events.
+    Pass,
+    /// Resync events enter the stream in the resync namespace, where only maps with the
+    /// resync flag and --output arguments act on them.
+    Isolate,
 }
 
 #[derive(Clone, Copy)]
@@ -36,8 +76,8 @@ pub enum PersistMode {
 impl InputDevice {
 	pub fn parse(args: Vec<String>) -> Result<InputDevice, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &["grab", "persist"],
-            &["domain", "grab", "persist"],
+            &["grab", "persist", "auto-output", "observe-only", "replay", "packets"],
+            &["domain", "tags", "grab", "persist", "resync", "fd", "repeat", "stale-after", "stale-action", "open-mode", "replay", "filter", "time-scale", "group", "priority"],
             true,
             false,
         )?;
@@ -57,7 +97,11 @@ impl InputDevice {
             }
         };
 
+        let auto_output = arg_group.has_flag("auto-output");
         let grab_mode = match arg_group.get_unique_clause_or_default_if_flag("grab", "auto")? {
+            // Passing the events through to another device is pointless if the original device
+            // is not grabbed, so the auto-output flag implies grab.
+            None if auto_output => GrabMode::Auto,
             None => GrabMode::None,
             Some(value) => match value.as_str() {
                 "auto" => GrabMode::Auto,
@@ -66,6 +110,27 @@ impl InputDevice {
             }
         };
 
+        let open_mode = match arg_group.get_unique_clause("open-mode")?.as_deref() {
+            None | Some("ro") => OpenMode::ReadOnly,
+            Some("rw") => OpenMode::ReadWrite,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid open mode specified: \"{}\". The open mode must be either \"ro\" or \"rw\".", other
+            ))),
+        };
+
+        let observe_only = arg_group.has_flag("observe-only");
+        if observe_only {
+            if grab_mode != GrabMode::None {
+                return Err(ArgumentError::new("An input device with the observe-only flag cannot be grabbed."));
+            }
+            if auto_output {
+                return Err(ArgumentError::new("The observe-only and auto-output flags cannot be specified on the same input device."));
+            }
+            if open_mode == OpenMode::ReadWrite {
+                return Err(ArgumentError::new("An input device with the observe-only flag cannot be opened with open-mode=rw."));
+            }
+        }
+
         let persist_mode = match arg_group.get_unique_clause_or_default_if_flag("persist", "full")? {
             None => PersistMode::None,
             Some(value) => match value.as_str() {
@@ -77,7 +142,100 @@ impl InputDevice {
             }
         };
 
-        let paths = arg_group.require_paths()?;
+        let resync_mode = match arg_group.get_unique_clause("resync")? {
+            None => ResyncMode::Pass,
+            Some(value) => match value.as_str() {
+                "pass" => ResyncMode::Pass,
+                "isolate" => ResyncMode::Isolate,
+                _ => return Err(ArgumentError::new("Invalid resync mode specified.")),
+            }
+        };
+
+        let repeat_setting = match arg_group.get_unique_clause("repeat")? {
+            None => None,
+            Some(value) => match parse_repeat_setting(&value)? {
+                setting @ (RepeatSetting::Set { .. } | RepeatSetting::Off) => Some(setting),
+                RepeatSetting::Restore | RepeatSetting::Toggle => return Err(ArgumentError::new(
+                    "The repeat= clause of an --input argument must be either \"off\" or of the form DELAY,PERIOD."
+                )),
+            },
+        };
+
+        if observe_only && repeat_setting.is_some() {
+            return Err(ArgumentError::new("The repeat= clause cannot be used on an input device with the observe-only flag, because it changes the settings of the device."));
+        }
+
+        let stale_action = match arg_group.get_unique_clause("stale-action")?.as_deref() {
+            None | Some("release") => StaleAction::Release,
+            Some("reopen") => StaleAction::Reopen,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid stale action specified: \"{}\". The stale action must be either \"release\" or \"reopen\".", other
+            ))),
+        };
+        let stale_setting = match arg_group.get_unique_clause("stale-after")? {
+            None => {
+                if ! arg_group.get_clauses("stale-action").is_empty() {
+                    return Err(ArgumentError::new("The stale-action= clause of an input argument requires a stale-after= clause."));
+                }
+                None
+            },
+            Some(value) => {
+                let timeout = crate::arguments::delay::parse_period_value(&value)?;
+                if timeout == Duration::from_nanos(0) {
+                    return Err(ArgumentError::new("The stale-after= clause of an input argument must be positive."));
+                }
+                Some(StaleSetting { timeout, action: stale_action })
+            },
+        };
+
+        let read_filter = parse_read_filter(&arg_group.get_clauses("filter"))?;
+
+        let fds = arg_group.get_clauses("fd").iter()
+            .map(|value| parse_fd_clause(value))
+            .collect::<Result<Vec<RawFd>, ArgumentError>>()?;
+        let paths = match fds.is_empty() {
+            true => arg_group.require_paths()?,
+            false => arg_group.paths.clone(),
+        };
+
+        // The tags are attached to the domain right away, so keys parsed afterwards can tell whether
+        // they may overlap with other keys.
+        let tags = parse_tags(&arg_group.get_clauses("tags"))?;
+        if ! tags.is_empty() {
+            let target_domains = match domain {
+                Some(domain) => vec![domain],
+                None => paths.iter().cloned()
+                    .chain(fds.iter().map(|fd| format!("/dev/fd/{}", fd)))
+                    .map(|path| domain::resolve(&path))
+                    .collect::<Result<Vec<Domain>, ArgumentError>>()?,
+            };
+            for target_domain in target_domains {
+                domain::add_tags(target_domain, &tags)?;
+            }
+        }
+
+        if ! fds.is_empty() {
+            if open_mode == OpenMode::ReadWrite {
+                return Err(ArgumentError::new(
+                    "The open-mode= clause cannot be used for input devices specified with an fd= clause, because their mode was decided by the program that opened them."
+                ));
+            }
+            match persist_mode {
+                PersistMode::None | PersistMode::Exit => {},
+                PersistMode::Reopen | PersistMode::Full => return Err(ArgumentError::new(
+                    "Input devices specified with an fd= clause cannot be reopened after they disconnect, so only persist=none and persist=exit are supported for them."
+                )),
+            }
+        }
+
+        if let Some(StaleSetting { action: StaleAction::Reopen, .. }) = stale_setting {
+            match persist_mode {
+                PersistMode::Reopen | PersistMode::Full => {},
+                PersistMode::None | PersistMode::Exit => return Err(ArgumentError::new(
+                    "The stale-action=reopen clause requires persist=reopen or persist=full to be specified on the same input argument."
+                )),
+            }
+        }
 
         match persist_mode {
             PersistMode::None | PersistMode::Exit => {},
@@ -88,12 +246,105 @@ impl InputDevice {
             }
         }
 
+        let fallback_group = arg_group.get_unique_clause("group")?;
+        if fallback_group.as_deref() == Some("") {
+            return Err(ArgumentError::new("The group= clause of an input argument cannot be empty."));
+        }
+        let priority = match (arg_group.get_unique_clause_i32("priority")?, &fallback_group) {
+            (Some(_), None) => return Err(ArgumentError::new("The priority= clause of an input argument requires a group= clause.")),
+            (priority, _) => priority.unwrap_or(0),
+        };
+        if fallback_group.is_some() {
+            match persist_mode {
+                PersistMode::Reopen | PersistMode::Full => {},
+                PersistMode::None | PersistMode::Exit => return Err(ArgumentError::new(
+                    "Input devices in a fallback group must have persist=reopen or persist=full, so evsieve can switch back to them when they become available again."
+                )),
+            }
+        }
+
+        let replay = match arg_group.get_unique_clause_or_default_if_flag("replay", "original")? {
+            None => None,
+            Some(value) => match value.as_str() {
+                "original" => Some(ReplaySpeed::Original),
+                "fast" => Some(ReplaySpeed::Fast),
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid replay speed \"{}\". The replay speed must be either \"original\" or \"fast\".", value
+                ))),
+            },
+        };
+        if replay.is_some() {
+            let has_clause = |name: &str| arg_group.has_flag(name) || ! arg_group.get_clauses(name).is_empty();
+            for &name in &["grab", "persist", "fd", "resync", "repeat", "stale-after", "open-mode", "observe-only", "filter", "group"] {
+                if has_clause(name) {
+                    return Err(ArgumentError::new(format!(
+                        "The {} clause cannot be used on an input argument with the replay flag, because it reads from a file instead of an event device.", name
+                    )));
+                }
+            }
+        }
+
+        let time_scale = match arg_group.get_unique_clause("time-scale")? {
+            None => 1.0,
+            Some(value) => match replay {
+                Some(ReplaySpeed::Original) => parse_time_scale(&value)?,
+                Some(ReplaySpeed::Fast) => return Err(ArgumentError::new(
+                    "The time-scale= clause cannot be used with replay=fast, because that replays events as fast as possible."
+                )),
+                None => return Err(ArgumentError::new(
+                    "The time-scale= clause of an input argument requires the replay flag."
+                )),
+            },
+        };
+
+        let packets = arg_group.has_flag("packets");
+
         Ok(InputDevice {
-            domain, grab_mode, persist_mode, paths
+            domain, grab_mode, persist_mode, resync_mode, repeat_setting, stale_setting, paths, fds,
+            auto_output, open_mode, observe_only, replay, read_filter, time_scale, fallback_group, priority, packets,
         })
     }
 }
 
+/// Interprets the values of filter= clauses like "key:power,key:sleep". Returns None if no filter= clause
+/// was specified.
+fn parse_read_filter(values: &[String]) -> Result<Option<ReadFilter>, ArgumentError> {
+    if values.is_empty() {
+        return Ok(None);
+    }
+    let parser = KeyParser {
+        allow_domains: false,
+        ..KeyParser::pure()
+    };
+    let key_strs: Vec<String> = values.iter()
+        .flat_map(|value| value.split(','))
+        .map(str::to_owned)
+        .collect();
+    if key_strs.iter().any(String::is_empty) {
+        return Err(ArgumentError::new("The filter= clause of an input argument cannot contain empty keys."));
+    }
+    let keys = parser.parse_all(&key_strs)
+        .with_context("While parsing the filter= clause of an input argument:")?;
+    Ok(Some(ReadFilter::new(keys)))
+}
+
+/// Interprets the values of tags= clauses like "kbd,left-half".
+fn parse_tags(values: &[String]) -> Result<Vec<String>, ArgumentError> {
+    let mut tags = Vec::new();
+    for tag in values.iter().flat_map(|value| value.split(',')) {
+        if tag.is_empty() {
+            return Err(ArgumentError::new("The tags= clause of an input argument cannot contain empty tags."));
+        }
+        if tag.starts_with('@') || crate::domain::DomainExpression::is_expression_str(tag) {
+            return Err(ArgumentError::new(format!(
+                "The tag \"{}\" may not start with an @ or contain any of the characters &, * and ?.", tag
+            )));
+        }
+        tags.push(tag.to_owned());
+    }
+    Ok(tags)
+}
+
 impl PersistMode {
     pub fn to_state_for_device(self, input_device_path: &Path) -> Result<PersistState, SystemError> {
         Ok(match self {
@@ -107,6 +358,37 @@ impl PersistMode {
     }
 }
 
+/// Interprets a factor by which the intervals between events get multiplied, like the 0.5 in time-scale=0.5.
+pub fn parse_time_scale(value: &str) -> Result<f64, ArgumentError> {
+    match value.parse::<f64>() {
+        Ok(factor) if factor.is_finite() && factor > 0.0 => Ok(factor),
+        _ => Err(ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a time scale. The time scale must be a positive number like 0.5 or 2.", value
+        ))),
+    }
+}
+
+/// Interprets a value like "off", "restore", "toggle" or "250,33", where the latter means a delay of
+/// 250ms and a period of 33ms.
+pub(super) fn parse_repeat_setting(value: &str) -> Result<RepeatSetting, ArgumentError> {
+    match value {
+        "off" => return Ok(RepeatSetting::Off),
+        "restore" => return Ok(RepeatSetting::Restore),
+        "toggle" => return Ok(RepeatSetting::Toggle),
+        _ => (),
+    }
+    let error = || ArgumentError::new(format!(
+        "Cannot interpret \"{}\" as a repeat setting. Expected \"off\", \"restore\", \"toggle\" or two nonnegative amounts of milliseconds like \"250,33\".", value
+    ));
+    let (delay_str, period_str) = crate::utils::split_once(value, ",");
+    let delay: i32 = delay_str.parse().map_err(|_| error())?;
+    let period: i32 = period_str.ok_or_else(error)?.parse().map_err(|_| error())?;
+    if delay < 0 || period < 0 {
+        return Err(error());
+    }
+    Ok(RepeatSetting::Set { delay, period })
+}
+
 /// Returns true if `path` is of the form `^/dev/input/event[0-9]+$`.
 fn is_direct_event_device(path: &str) -> bool {
     let path = match path.strip_prefix("/dev/input/event") {
@@ -123,4 +405,56 @@ fn unittest() {
     assert!(is_direct_event_device("/dev/input/event23"));
     assert!(! is_direct_event_device("/dev/input/by-id/event23"));
     assert!(! is_direct_event_device("/dev/input/event1foo"));
+
+    assert_eq!(parse_repeat_setting("250,33").unwrap(), RepeatSetting::Set { delay: 250, period: 33 });
+    assert_eq!(parse_repeat_setting("off").unwrap(), RepeatSetting::Off);
+    assert!(parse_repeat_setting("250").is_err());
+    assert!(parse_repeat_setting("-1,33").is_err());
+
+    let parse = |args: &[&str]| InputDevice::parse(
+        std::iter::once("--input").chain(args.iter().cloned()).map(str::to_owned).collect()
+    );
+    let device = parse(&["/dev/input/by-id/keyboard", "stale-after=1.5"]).unwrap();
+    assert_eq!(device.stale_setting, Some(StaleSetting { timeout: Duration::from_millis(1500), action: StaleAction::Release }));
+    let device = parse(&["/dev/input/by-id/keyboard", "persist=reopen", "stale-after=2", "stale-action=reopen"]).unwrap();
+    assert_eq!(device.stale_setting, Some(StaleSetting { timeout: Duration::from_secs(2), action: StaleAction::Reopen }));
+    assert!(parse(&["/dev/input/by-id/keyboard", "stale-after=2", "stale-action=reopen"]).is_err());
+    assert!(parse(&["/dev/input/by-id/keyboard", "stale-action=release"]).is_err());
+    assert!(parse(&["/dev/input/by-id/keyboard", "stale-after=0"]).is_err());
+
+    assert_eq!(parse(&["/tmp/keyboard.dump", "replay"]).unwrap().replay, Some(ReplaySpeed::Original));
+    assert_eq!(parse(&["/tmp/keyboard.dump", "replay=fast", "auto-output"]).unwrap().replay, Some(ReplaySpeed::Fast));
+    assert!(parse(&["/tmp/keyboard.dump", "replay=slow"]).is_err());
+    assert!(parse(&["/tmp/keyboard.dump", "replay", "grab"]).is_err());
+    assert!(parse(&["/tmp/keyboard.dump", "replay", "persist=reopen"]).is_err());
+    assert_eq!(parse(&["/tmp/keyboard.dump", "replay"]).unwrap().time_scale, 1.0);
+    assert_eq!(parse(&["/tmp/keyboard.dump", "replay", "time-scale=0.25"]).unwrap().time_scale, 0.25);
+    assert!(parse(&["/tmp/keyboard.dump", "replay", "time-scale=0"]).is_err());
+    assert!(parse(&["/tmp/keyboard.dump", "replay", "time-scale=-2"]).is_err());
+    assert!(parse(&["/tmp/keyboard.dump", "replay=fast", "time-scale=2"]).is_err());
+    assert!(parse(&["/dev/input/by-id/keyboard", "time-scale=2"]).is_err());
+
+    let filter = parse(&["/dev/input/by-id/keyboard", "filter=key:power,key:sleep", "filter=abs"]).unwrap().read_filter.unwrap();
+    let domain = crate::domain::get_unique_domain();
+    let code = |type_name: &str, code_name: &str| crate::ecodes::event_code(type_name, code_name).unwrap();
+    assert!(filter.accepts((code("key", "power"), domain)));
+    assert!(filter.accepts((code("key", "sleep"), domain)));
+    assert!(filter.accepts((code("abs", "x"), domain)));
+    assert!(filter.accepts((code("syn", "report"), domain)));
+    assert!(! filter.accepts((code("key", "a"), domain)));
+    assert!(parse(&["/dev/input/by-id/keyboard"]).unwrap().read_filter.is_none());
+    assert!(parse(&["/dev/input/by-id/keyboard", "filter=key:power:1"]).is_err());
+    assert!(parse(&["/dev/input/by-id/keyboard", "filter=key:power@kb"]).is_err());
+    assert!(parse(&["/dev/input/by-id/keyboard", "filter=key:power,"]).is_err());
+    assert!(parse(&["/tmp/keyboard.dump", "replay", "filter=key:power"]).is_err());
+
+    let device = parse(&["/dev/input/by-id/usb-keyboard", "persist=reopen", "group=kb", "priority=2"]).unwrap();
+    assert_eq!((device.fallback_group.as_deref(), device.priority), (Some("kb"), 2));
+    let device = parse(&["/dev/input/by-id/bt-keyboard", "persist=full", "group=kb"]).unwrap();
+    assert_eq!((device.fallback_group.as_deref(), device.priority), (Some("kb"), 0));
+    assert!(parse(&["/dev/input/by-id/usb-keyboard", "group=kb"]).is_err());
+    assert!(parse(&["/dev/input/by-id/usb-keyboard", "persist=exit", "group=kb"]).is_err());
+    assert!(parse(&["/dev/input/by-id/usb-keyboard", "persist=reopen", "priority=2"]).is_err());
+    assert!(parse(&["/dev/input/by-id/usb-keyboard", "persist=reopen", "group="]).is_err());
+    assert!(parse(&["/dev/input/by-id/usb-keyboard", "persist=reopen", "group=kb", "priority=high"]).is_err());
 }
\ No newline at end of file
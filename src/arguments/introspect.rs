@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::{ArgumentError, Context};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::control_fifo::parse_mode;
+use crate::privileges::FileOwner;
+
+/// Represents an --introspect-socket argument.
+pub(super) struct IntrospectSocketArg {
+    pub paths: Vec<String>,
+    /// The permissions the sockets should get, as specified by the mode= clause.
+    pub mode: Option<u32>,
+    pub owner: Option<FileOwner>,
+}
+
+impl IntrospectSocketArg {
+	pub fn parse(args: Vec<String>) -> Result<IntrospectSocketArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["mode", "owner"],
+            true,
+            false,
+        )?;
+
+        if arg_group.paths.is_empty() {
+            return Err(ArgumentError::new("The --introspect-socket argument requires at least one path."));
+        }
+        let mode = match arg_group.get_unique_clause("mode")? {
+            Some(mode_str) => Some(parse_mode(&mode_str)?),
+            None => None,
+        };
+        let owner = match arg_group.get_unique_clause("owner")? {
+            Some(owner_str) => Some(FileOwner::parse(&owner_str)
+                .map_err(|error| error.with_context(format!("While interpreting the clause owner={}:", owner_str)))?),
+            None => None,
+        };
+
+        Ok(IntrospectSocketArg {
+            paths: arg_group.paths,
+            mode, owner,
+        })
+    }
+}
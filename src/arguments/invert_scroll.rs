@@ -0,0 +1,64 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::{ArgumentError, RuntimeError};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::ecodes;
+use crate::event::EventCode;
+use crate::key::{Key, KeyParser};
+use crate::state::ToggleIndex;
+use crate::stream::invert_scroll::InvertScroll;
+
+/// Represents an --invert-scroll argument.
+pub(super) struct InvertScrollArg {
+    pub keys: Vec<Key>,
+    pub scroll_codes: Vec<EventCode>,
+    /// The id of the toggle that switches the inversion on and off at runtime.
+    pub id: Option<String>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl InvertScrollArg {
+    pub fn parse(args: Vec<String>) -> Result<InvertScrollArg, RuntimeError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["id", "axes", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::pure().parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let code_names: &[&str] = match arg_group.get_unique_clause("axes")?.as_deref() {
+            None | Some("both") => &["wheel", "wheel_hi_res", "hwheel", "hwheel_hi_res"],
+            Some("vertical") => &["wheel", "wheel_hi_res"],
+            Some("horizontal") => &["hwheel", "hwheel_hi_res"],
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid axes specified: {}. The axes must be one of vertical, horizontal or both.", other
+            )).into()),
+        };
+        let scroll_codes = code_names.iter()
+            .map(|name| ecodes::event_code("rel", name))
+            .collect::<Result<Vec<EventCode>, ArgumentError>>()?;
+
+        let id = arg_group.get_unique_clause("id")?;
+        if let Some(id) = &id {
+            if id.contains(':') {
+                return Err(ArgumentError::new(format!("A toggle's id cannot contain any colons. Offending id: {}", id)).into());
+            }
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(InvertScrollArg { keys, scroll_codes, id, enabled_when, stage })
+    }
+
+    pub fn compile(self, state_index: ToggleIndex) -> InvertScroll {
+        InvertScroll::new(self.keys, self.scroll_codes, state_index)
+    }
+}
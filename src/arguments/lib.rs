@@ -2,10 +2,37 @@
 
 use crate::utils::split_once;
 use crate::error::ArgumentError;
+use std::cell::RefCell;
+use std::os::unix::io::RawFd;
 use std::path::{Path, PathBuf};
 
 static DEV_ID_DIR: &str = "/dev/input/by-id";
 
+/// Describes which paths, keys, flags and clauses an argument accepts.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(super) struct ArgumentSpec {
+    pub flags: Vec<String>,
+    pub clauses: Vec<String>,
+    pub paths: bool,
+    pub keys: bool,
+}
+
+thread_local! {
+    /// While Some, ComplexArgGroup::parse() records what it supports here and then fails instead of
+    /// parsing anything.
+    static PROBED_SPECS: RefCell<Option<Vec<ArgumentSpec>>> = const { RefCell::new(None) };
+}
+
+/// Finds out what an argument accepts by running its parser while ComplexArgGroup::parse() is in probing
+/// mode. Returns None if the parser failed before it created a ComplexArgGroup, which happens for arguments
+/// that interpret their values themselves.
+pub(super) fn probe_spec(parse: impl FnOnce()) -> Option<ArgumentSpec> {
+    PROBED_SPECS.with(|specs| *specs.borrow_mut() = Some(Vec::new()));
+    parse();
+    PROBED_SPECS.with(|specs| specs.borrow_mut().take())
+        .and_then(|specs| specs.into_iter().next())
+}
+
 /// A ComplexArgGroup represents a group like "--input /dev/keyboard domain=foo grab",
 /// containing paths like "/dev/keyboard", flags like "grab" and clauses like "domain=foo".
 ///
@@ -35,7 +62,23 @@ impl ComplexArgGroup {
             supported_clauses: &[&str],
             supports_paths: bool,
             supports_keys: bool) -> Result<ComplexArgGroup, ArgumentError> {
-        
+
+        let is_probing = PROBED_SPECS.with(|specs| match specs.borrow_mut().as_mut() {
+            Some(specs) => {
+                specs.push(ArgumentSpec {
+                    flags: supported_flags.iter().map(|&flag| flag.to_owned()).collect(),
+                    clauses: supported_clauses.iter().map(|&clause| clause.to_owned()).collect(),
+                    paths: supports_paths,
+                    keys: supports_keys,
+                });
+                true
+            },
+            None => false,
+        });
+        if is_probing {
+            return Err(ArgumentError::new("Internal error: an argument was probed, not parsed."));
+        }
+
         let mut args_iter = args.into_iter();
         let arg_name = args_iter.next().expect("Internal error: created an argument group out of no arguments.");
 
@@ -244,6 +287,22 @@ impl ComplexArgGroup {
     }
 }
 
+/// Interprets the value of an fd= clause, which is either the number of a file descriptor that was
+/// inherited from the parent process, or the name of a file descriptor passed to us by systemd.
+pub(super) fn parse_fd_clause(value: &str) -> Result<RawFd, ArgumentError> {
+    if value.is_empty() {
+        return Err(ArgumentError::new("The fd= clause cannot be empty."));
+    }
+    if value.chars().all(|c| c.is_ascii_digit()) {
+        return value.parse().map_err(|_| ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a file descriptor.", value
+        )));
+    }
+    crate::io::fd::find_systemd_fd(value).ok_or_else(|| ArgumentError::new(format!(
+        "No file descriptor named \"{}\" has been passed to evsieve by systemd.", value
+    )))
+}
+
 pub(super) fn is_path(path: &str) -> bool {
     path.starts_with('/')
 }
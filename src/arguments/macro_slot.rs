@@ -0,0 +1,75 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::path::PathBuf;
+
+use crate::error::{ArgumentError, RuntimeError};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::key::{Key, KeyParser};
+use crate::stream::macro_slot::{MacroCommand, MacroSlots};
+
+/// Represents a --macro-slot argument.
+pub(super) struct MacroSlotArg {
+    /// Only events matching these keys are recorded.
+    pub keys: Vec<Key>,
+    pub num_slots: usize,
+    pub persist_path: Option<PathBuf>,
+}
+
+impl MacroSlotArg {
+    pub fn parse(args: Vec<String>) -> Result<MacroSlotArg, RuntimeError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["slots", "persist"],
+            false,
+            true,
+        )?;
+
+        // By default, only key and button events are recorded.
+        let keys_str = match arg_group.keys.is_empty() {
+            true => vec!["key".to_owned(), "btn".to_owned()],
+            false => arg_group.keys.clone(),
+        };
+        let keys = KeyParser::default_filter().parse_all(&keys_str)?;
+
+        let num_slots = match arg_group.get_unique_clause("slots")? {
+            None => 1,
+            Some(slots_str) => match slots_str.parse::<usize>() {
+                Ok(0) => return Err(ArgumentError::new("A --macro-slot argument requires at least one slot.").into()),
+                Ok(value) => value,
+                Err(error) => return Err(ArgumentError::new(format!("Cannot interpret {} as an integer: {}.", slots_str, error)).into()),
+            }
+        };
+
+        let persist_path = arg_group.get_unique_clause("persist")?.map(PathBuf::from);
+
+        Ok(MacroSlotArg { keys, num_slots, persist_path })
+    }
+
+    pub fn compile(self) -> Result<MacroSlots, RuntimeError> {
+        Ok(MacroSlots::new(self.keys, self.num_slots, self.persist_path)?)
+    }
+}
+
+/// Parses the record-start=, record-stop and play= clauses of a --hook. The slots are one-indexed
+/// in the arguments, but zero-indexed in the returned commands.
+pub(super) fn parse_hook_macro_commands(arg_group: &ComplexArgGroup) -> Result<Vec<MacroCommand>, ArgumentError> {
+    let parse_slot = |slot_str: &str| -> Result<usize, ArgumentError> {
+        match slot_str.parse::<usize>() {
+            Ok(0) => Err(ArgumentError::new("Cannot use macro slot 0: macro slots start at 1.")),
+            Ok(value) => Ok(value - 1),
+            Err(error) => Err(ArgumentError::new(format!("Cannot interpret {} as an integer: {}.", slot_str, error))),
+        }
+    };
+
+    let mut commands = Vec::new();
+    if arg_group.has_flag("record-stop") {
+        commands.push(MacroCommand::StopRecording);
+    }
+    for slot_str in arg_group.get_clauses("record-start") {
+        commands.push(MacroCommand::StartRecording(parse_slot(&slot_str)?));
+    }
+    for slot_str in arg_group.get_clauses("play") {
+        commands.push(MacroCommand::Play(parse_slot(&slot_str)?));
+    }
+    Ok(commands)
+}
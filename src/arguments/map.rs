@@ -2,20 +2,60 @@
 
 use crate::error::{ArgumentError, InternalError, RuntimeError};
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::arguments::gyro::parse_f64;
 use crate::key::{Key, KeyParser};
 use crate::event::Namespace;
+use crate::stream::map::Sampling;
+
+/// The token that separates the source keys from the target keys if a map has several source
+/// keys, e.g. "--map key:a key:b -> key:c".
+const SOURCE_SEPARATOR: &str = "->";
 
 /// Represents a --map or --copy argument.
 pub(super) struct MapArg {
-	pub input_key: Key,
+    /// Usually one key. Several keys if they were separated from the output keys by "->".
+	pub input_keys: Vec<Key>,
     pub output_keys: Vec<Key>,
+    /// If the resync flag was specified, the input and output keys of the map that acts
+    /// on events in the resync namespace.
+    pub resync_keys: Option<(Vec<Key>, Vec<Key>)>,
+    /// Specified by the nth= or chance= clause.
+    pub sampling: Option<Sampling>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl MapArg {
-	pub fn parse(args: Vec<String>) -> Result<MapArg, RuntimeError> {
+	pub fn parse(mut args: Vec<String>) -> Result<MapArg, RuntimeError> {
+        // The separator is not a key, so it has to be removed before the ComplexArgGroup sees it.
+        // We remember how many keys came before it.
+        let num_separators = args.iter().filter(|arg| *arg == SOURCE_SEPARATOR).count();
+        let num_input_keys = match args.iter().position(|arg| arg == SOURCE_SEPARATOR) {
+            None => 1,
+            Some(_) if num_separators > 1 => return Err(ArgumentError::new(format!(
+                "The {} argument can contain at most one \"{}\" separator.", args[0], SOURCE_SEPARATOR
+            )).into()),
+            Some(separator_index) => {
+                let num_keys = args[1 .. separator_index].iter()
+                    .filter(|arg| crate::key::resembles_key(arg))
+                    .count();
+                args.remove(separator_index);
+                if num_keys == 0 {
+                    return Err(ArgumentError::new(format!(
+                        "At least one source key must be specified before the \"{}\" separator of the {} argument.", SOURCE_SEPARATOR, args[0]
+                    )).into());
+                }
+                num_keys
+            },
+        };
+
         let arg_group = ComplexArgGroup::parse(args,
-            &["yield"],
-            &[],
+            &["yield", "resync"],
+            &["nth", "chance", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -28,7 +68,12 @@ impl MapArg {
 
         // Parse the keys.
         let keys_str = arg_group.require_keys()?;
-        let input_key = KeyParser::default_filter().parse(&keys_str[0])?;
+        let mut input_parser = KeyParser {
+            allow_crossings: true,
+            ..KeyParser::default_filter()
+        };
+        let input_keys = input_parser.parse_all(&keys_str[.. num_input_keys])?;
+        let output_keys_str = &keys_str[num_input_keys ..];
         
         let output_namespace = match arg_group.has_flag("yield") {
             true => Namespace::Yielded,
@@ -36,34 +81,83 @@ impl MapArg {
         };
         let mut output_keys = KeyParser::default_mask()
             .with_namespace(output_namespace)
-            .parse_all(&keys_str[1..])?;
+            .parse_all(output_keys_str)?;
 
         if copy {
             output_keys.insert(0, Key::copy());
         }
+
+        let resync_keys = match arg_group.has_flag("resync") {
+            false => None,
+            true => {
+                let resync_input_keys = input_parser
+                    .with_namespace(Namespace::Resync)
+                    .parse_all(&keys_str[.. num_input_keys])?;
+                let mut resync_output_keys = KeyParser::default_mask()
+                    .with_namespace(Namespace::Resync)
+                    .parse_all(output_keys_str)?;
+                if copy {
+                    resync_output_keys.insert(0, Key::copy());
+                }
+                Some((resync_input_keys, resync_output_keys))
+            }
+        };
         
+        let sampling = parse_sampling(&arg_group)?;
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
         Ok(MapArg {
-            input_key, output_keys,
+            input_keys, output_keys, resync_keys, sampling, enabled_when, stage,
         })
     }
 }
 
+fn parse_sampling(arg_group: &ComplexArgGroup) -> Result<Option<Sampling>, ArgumentError> {
+    let nth = arg_group.get_unique_clause("nth")?;
+    let chance = arg_group.get_unique_clause("chance")?;
+    match (nth, chance) {
+        (None, None) => Ok(None),
+        (Some(_), Some(_)) => Err(ArgumentError::new(format!(
+            "The {} argument cannot have both a nth= and a chance= clause.", arg_group.name
+        ))),
+        (Some(nth_str), None) => match nth_str.parse::<u32>() {
+            Ok(n) if n >= 1 => Ok(Some(Sampling::EveryNth(n))),
+            _ => Err(ArgumentError::new(format!(
+                "The nth= clause requires a positive integer, encountered: nth={}", nth_str
+            ))),
+        },
+        (None, Some(chance_str)) => match parse_f64(&chance_str, "chance")? {
+            probability if (0.0 ..= 1.0).contains(&probability) => Ok(Some(Sampling::Chance(probability))),
+            _ => Err(ArgumentError::new(format!(
+                "The chance= clause requires a probability between 0 and 1, encountered: chance={}", chance_str
+            ))),
+        },
+    }
+}
+
 /// Represents a --block argument.
 pub(super) struct BlockArg {
 	pub keys: Vec<Key>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl BlockArg {
 	pub fn parse(args: Vec<String>) -> Result<BlockArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &[],
+            &["enabled-when", "stage"],
             false,
             true,
         )?;
 
         let keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
 
-        Ok(BlockArg { keys })
+        Ok(BlockArg { keys, enabled_when, stage })
     }
 }
\ No newline at end of file
@@ -0,0 +1,51 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::key::{Key, KeyParser};
+use crate::stream::max_hold::MaxHold;
+use crate::time::Duration;
+
+/// Represents a --max-hold argument.
+pub(super) struct MaxHoldArg {
+    pub keys: Vec<Key>,
+    pub except_keys: Vec<Key>,
+    pub period: Duration,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl MaxHoldArg {
+    pub fn parse(args: Vec<String>) -> Result<MaxHoldArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period", "except", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+        let except_keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_clauses("except"))?;
+        let period = crate::arguments::delay::parse_period_value(
+            &arg_group.require_unique_clause("period")?
+        )?;
+        if period == Duration::from_nanos(0) {
+            return Err(ArgumentError::new("The period= clause of --max-hold must be positive."));
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(MaxHoldArg { keys, except_keys, period, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> MaxHold {
+        MaxHold::new(self.keys, self.except_keys, self.period)
+    }
+}
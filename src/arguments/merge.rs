@@ -2,20 +2,31 @@
 
 use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
 use crate::event::EventType;
+use crate::domain::{self, Domain};
 use crate::key::{Key, KeyParser};
-use crate::stream::merge::Merge;
+use crate::stream::merge::{Merge, MergePriorities};
 
 /// Represents a --merge argument.
 pub(super) struct MergeArg {
     pub keys: Vec<Key>,
+    /// Specified by the priority= clauses. If not empty, events with different domains get merged.
+    pub priorities: Vec<(Domain, i32)>,
+    /// Specified by the steal flag.
+    pub steal: bool,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl MergeArg {
 	pub fn parse(args: Vec<String>) -> Result<MergeArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &[],
-            &[],
+            &["steal"],
+            &["priority", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -26,22 +37,54 @@ impl MergeArg {
             allow_ranges: false,
             allow_domains: true,
             allow_transitions: false,
+            allow_crossings: false,
             allow_types: true,
             allow_relative_values: false,
+            allow_annotations: false,
             type_whitelist: Some(vec![EventType::KEY]),
             namespace: crate::event::Namespace::User,
         };
 
         let keys: Vec<Key> = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
 
-        Ok(MergeArg { keys })
+        let priorities = arg_group.get_clauses("priority").iter()
+            .map(|value| parse_priority_clause(value))
+            .collect::<Result<Vec<(Domain, i32)>, ArgumentError>>()?;
+        let steal = arg_group.has_flag("steal");
+        if steal && priorities.is_empty() {
+            return Err(ArgumentError::new("The steal flag of a --merge argument requires at least one priority= clause."));
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(MergeArg { keys, priorities, steal, enabled_when, stage })
     }
 
     pub fn compile(self) -> Merge {
-        Merge::new(self.keys)
+        let priorities = match self.priorities.is_empty() {
+            true => None,
+            false => Some(MergePriorities { domains: self.priorities, steal: self.steal }),
+        };
+        Merge::new(self.keys, priorities)
     }
 }
 
+/// Parses the value of a priority= clause like "kvm:2".
+fn parse_priority_clause(value: &str) -> Result<(Domain, i32), ArgumentError> {
+    let (domain_str, priority_str) = value.rsplit_once(':').ok_or_else(|| ArgumentError::new(format!(
+        "Invalid priority= clause \"priority={}\". The priority= clause must be of the form priority=DOMAIN:NUMBER, e.g. \"priority=kvm:2\".", value
+    )))?;
+    let domain_str = domain_str.strip_prefix('@').unwrap_or(domain_str);
+    if domain_str.is_empty() {
+        return Err(ArgumentError::new(format!("No domain specified in the clause \"priority={}\".", value)));
+    }
+    let priority: i32 = priority_str.parse().map_err(|_| ArgumentError::new(format!(
+        "Cannot interpret \"{}\" as the priority of the domain \"{}\". The priority must be an integer.", priority_str, domain_str
+    )))?;
+    Ok((domain::resolve(domain_str)?, priority))
+}
+
 #[test]
 fn unittest() {
     assert!(MergeArg::parse(vec!["--merge".to_string()]).is_ok());
@@ -58,4 +101,11 @@ fn unittest() {
     assert!(MergeArg::parse(vec!["--merge".to_string(), "abs:x".to_string()]).is_err());
     assert!(MergeArg::parse(vec!["--merge".to_string(), "key".to_string(), "abs".to_string()]).is_err());
     assert!(MergeArg::parse(vec!["--merge".to_string(), "abs@foo".to_string()]).is_err());
+
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "priority=foo:1".to_string()]).is_ok());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "priority=@foo:-1".to_string(), "priority=bar:2".to_string(), "steal".to_string()]).is_ok());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "steal".to_string()]).is_err());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "priority=foo".to_string()]).is_err());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "priority=:1".to_string()]).is_err());
+    assert!(MergeArg::parse(vec!["--merge".to_string(), "priority=foo:high".to_string()]).is_err());
 }
\ No newline at end of file
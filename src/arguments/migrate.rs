@@ -0,0 +1,137 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements `evsieve --migrate`, which reads an invocation written for an older version of evsieve,
+//! reports the constructs in it that are only retained for backwards compatibility, and writes a
+//! configuration file that does the same thing with the current version.
+
+use crate::error::{ArgumentError, Context, RuntimeError};
+use crate::utils::shelllex;
+use super::parser::{check_argument, read_config_file, split_into_groups};
+
+/// A construct that older invocations may rely on, and how to express it with the current version.
+struct Rule {
+    /// The name of the argument this rule applies to, like "--hook".
+    argument: &'static str,
+    /// Given all arguments and the index of an argument with the above name, returns whether that
+    /// argument relies on the construct.
+    applies: fn(&[Vec<String>], usize) -> bool,
+    /// Explains the construct to the user.
+    message: &'static str,
+    /// Rewrites the argument so that it no longer relies on the construct while still doing the same
+    /// thing, or None if that is impossible and the user has to decide what they want.
+    rewrite: Option<fn(&mut Vec<String>)>,
+}
+
+const RULES: &[Rule] = &[
+    Rule {
+        argument: "--input",
+        applies: |groups, index| {
+            let group = &groups[index];
+            ! has_flag_or_clause(group, "persist") && ! has_flag_or_clause(group, "replay")
+        },
+        message: "relies on persist=none being the default, which is only the case for the sake of backwards compatibility. The persist=none clause has been added to keep the current behaviour.",
+        rewrite: Some(|group| group.push("persist=none".to_owned())),
+    },
+    Rule {
+        argument: "--hook",
+        applies: |groups, index| ! has_strict_channels(groups, index),
+        message: "relies on the channel semantics of hooks that are only retained for backwards compatibility: an event that matches a key but not its value releases that key, even if it came from another domain than the event that pressed it. Add the strict-channels flag if that is not what you want.",
+        rewrite: None,
+    },
+];
+
+/// Returns whether an argument contains a flag or a clause with the given name.
+fn has_flag_or_clause(group: &[String], name: &str) -> bool {
+    group.iter().skip(1).any(|token| {
+        token == name || token.strip_prefix(name).map_or(false, |rest| rest.starts_with('='))
+    })
+}
+
+/// Returns whether the --hook at the given index has the strict-channels flag, either by itself or
+/// through a --withhold that applies to it.
+fn has_strict_channels(groups: &[Vec<String>], index: usize) -> bool {
+    if has_flag_or_clause(&groups[index], "strict-channels") {
+        return true;
+    }
+    // A --withhold applies to all hooks right before it.
+    match groups[index..].iter().find(|group| group[0] != "--hook") {
+        Some(group) => group[0] == "--withhold" && has_flag_or_clause(group, "strict-channels"),
+        None => false,
+    }
+}
+
+pub struct Migration {
+    /// The contents of a configuration file that works with the current version.
+    pub config: String,
+    /// Everything that the user should know about the migration, one message per item.
+    pub notes: Vec<String>,
+}
+
+/// The arguments are either the arguments of an old invocation, or a single path to a configuration file.
+pub fn migrate(args: Vec<String>) -> Result<Migration, RuntimeError> {
+    let args = match args.as_slice() {
+        [path] if ! path.starts_with("--") => read_config_file(path)?,
+        _ => args,
+    };
+    if args.is_empty() {
+        return Err(ArgumentError::new("The --migrate argument must be followed by the arguments or the configuration file to migrate.").into());
+    }
+    let groups = split_into_groups(args).with_context("While migrating the arguments:")?;
+    Ok(migrate_groups(groups))
+}
+
+fn migrate_groups(groups: Vec<Vec<String>>) -> Migration {
+    let mut notes = Vec::new();
+    let mut migrated_groups = Vec::new();
+    for (index, group) in groups.iter().enumerate() {
+        let mut migrated = group.clone();
+        for rule in RULES.iter().filter(|rule| rule.argument == group[0]) {
+            if (rule.applies)(&groups, index) {
+                notes.push(format!("The argument \"{}\" {}", group.join(" "), rule.message));
+                if let Some(rewrite) = rule.rewrite {
+                    rewrite(&mut migrated);
+                }
+            }
+        }
+        if let "--config" | "--pipeline" | "--shadow" = group[0].as_str() {
+            notes.push(format!(
+                "The argument \"{}\" refers to other files, which have not been migrated. You can migrate them by running evsieve --migrate on each of them.",
+                group.join(" ")
+            ));
+        }
+        if let Err(error) = check_argument(migrated.clone()) {
+            notes.push(format!("Warning: the argument \"{}\" could not be validated:\n{}", migrated.join(" "), error));
+        }
+        migrated_groups.push(migrated);
+    }
+
+    let config = migrated_groups.iter()
+        .map(|group| group.iter().map(|token| shelllex::quote(token)).collect::<Vec<String>>().join(" ") + "\n")
+        .collect();
+    Migration { config, notes }
+}
+
+#[test]
+fn unittest() {
+    let migrate_str = |args: &str| migrate(args.split_whitespace().map(str::to_owned).collect()).unwrap();
+
+    let migration = migrate_str("--input /dev/input/event0 grab --map key:a key:b --output");
+    assert_eq!(migration.config, "--input /dev/input/event0 grab persist=none\n--map key:a key:b\n--output\n");
+    assert_eq!(migration.notes.len(), 1);
+
+    // Constructs that cannot be rewritten are only reported.
+    let migration = migrate_str("--input /dev/input/event0 persist=reopen --hook key:a key:b send-key=key:c");
+    assert_eq!(migration.config, "--input /dev/input/event0 persist=reopen\n--hook key:a key:b send-key=key:c\n");
+    assert_eq!(migration.notes.len(), 1);
+    assert!(migrate_str("--hook key:a strict-channels").notes.is_empty());
+    assert!(migrate_str("--hook key:a --hook key:b --withhold strict-channels").notes.is_empty());
+    assert_eq!(migrate_str("--hook key:a --withhold --hook key:b --withhold strict-channels").notes.len(), 1);
+
+    // Arguments that are not valid are kept as they are.
+    let migration = migrate_str("--map key:a key:b --frobnicate");
+    assert_eq!(migration.config, "--map key:a key:b\n--frobnicate\n");
+    assert_eq!(migration.notes.len(), 1);
+
+    assert!(migrate(Vec::new()).is_err());
+    assert!(migrate(vec!["key:a".to_owned(), "--map".to_owned()]).is_err());
+}
@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::error::{ArgumentError, RuntimeError, SystemError, Context};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::{EventCode, EventType};
+use crate::key::{Key, KeyParser};
+use crate::stream::hand_mirror::HandMirror;
+
+/// The pairs of keys that get swapped by default, for a QWERTY keyboard.
+const DEFAULT_TABLE: &[(&str, &str)] = &[
+    ("1", "0"), ("2", "9"), ("3", "8"), ("4", "7"), ("5", "6"),
+    ("q", "p"), ("w", "o"), ("e", "i"), ("r", "u"), ("t", "y"),
+    ("a", "semicolon"), ("s", "l"), ("d", "k"), ("f", "j"), ("g", "h"),
+    ("z", "slash"), ("x", "dot"), ("c", "comma"), ("v", "m"), ("b", "n"),
+];
+
+/// Represents a --mirror argument.
+pub(super) struct MirrorArg {
+    pub keys: Vec<Key>,
+    pub trigger: Key,
+    pub table: HashMap<EventCode, EventCode>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl MirrorArg {
+    pub fn parse(args: Vec<String>) -> Result<MirrorArg, RuntimeError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["trigger", "table", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let mut parser = KeyParser::default_filter();
+        parser.type_whitelist = Some(vec![EventType::KEY]);
+        let keys = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
+        let trigger = parser.parse(
+            &arg_group.get_unique_clause("trigger")?.unwrap_or_else(|| "key:space".to_owned())
+        )?;
+
+        let pairs: Vec<(String, String)> = match arg_group.get_unique_clause("table")? {
+            Some(path) => {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(SystemError::from)
+                    .with_context_of(|| format!("While trying to read the file {}:", path))?;
+                parse_table(&content)
+                    .with_context_of(|| format!("While parsing the mirror table {}:", path))?
+            },
+            None => DEFAULT_TABLE.iter()
+                .map(|&(left, right)| (format!("key:{}", left), format!("key:{}", right)))
+                .collect(),
+        };
+        let table = compile_table(&pairs)?;
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(MirrorArg { keys, trigger, table, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> HandMirror {
+        HandMirror::new(self.keys, self.trigger, self.table)
+    }
+}
+
+/// Parses a mirror table, which has one pair of keys separated by whitespace on each line,
+/// e.g. "key:q key:p". Empty lines and everything after a # are ignored.
+fn parse_table(content: &str) -> Result<Vec<(String, String)>, ArgumentError> {
+    let mut pairs = Vec::new();
+    for line in content.lines() {
+        let (line, _comment) = crate::utils::split_once(line, "#");
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.as_slice() {
+            [] => (),
+            [left, right] => pairs.push((left.to_string(), right.to_string())),
+            _ => return Err(ArgumentError::new(format!(
+                "Each line of a mirror table must contain exactly two keys. Encountered: \"{}\"", line.trim()
+            ))),
+        }
+    }
+    Ok(pairs)
+}
+
+/// Turns pairs of keys like ("key:q", "key:p") into a map that contains both directions of each pair.
+fn compile_table(pairs: &[(String, String)]) -> Result<HashMap<EventCode, EventCode>, ArgumentError> {
+    let parse_code = |key_str: &str| -> Result<EventCode, ArgumentError> {
+        match crate::utils::split_once(key_str, ":") {
+            (type_name @ ("key" | "btn"), Some(code_name)) => crate::ecodes::event_code(type_name, code_name),
+            _ => Err(ArgumentError::new(format!(
+                "A mirror table can only contain keys and buttons with a code, like key:a. Encountered: \"{}\"", key_str
+            ))),
+        }
+    };
+
+    let mut table = HashMap::new();
+    for (left, right) in pairs {
+        let left_code = parse_code(left)?;
+        let right_code = parse_code(right)?;
+        if left_code == right_code {
+            return Err(ArgumentError::new(format!("Cannot mirror {} to itself.", left)));
+        }
+        for (code, key_str) in [(left_code, left), (right_code, right)] {
+            if table.contains_key(&code) {
+                return Err(ArgumentError::new(format!(
+                    "The key {} appears more than once in the mirror table.", key_str
+                )));
+            }
+        }
+        table.insert(left_code, right_code);
+        table.insert(right_code, left_code);
+    }
+    Ok(table)
+}
+
+#[test]
+fn unittest() {
+    let pairs = parse_table("key:q key:p # comment\n\n  key:a\tkey:semicolon\n# key:b key:n").unwrap();
+    assert_eq!(pairs, vec![
+        ("key:q".to_owned(), "key:p".to_owned()),
+        ("key:a".to_owned(), "key:semicolon".to_owned()),
+    ]);
+    parse_table("key:q key:p key:w").unwrap_err();
+    assert_eq!(compile_table(&pairs).unwrap().len(), 4);
+
+    let pair = |left: &str, right: &str| (left.to_owned(), right.to_owned());
+    compile_table(&[pair("key:q", "key:q")]).unwrap_err();
+    compile_table(&[pair("key:q", "key:p"), pair("key:p", "key:w")]).unwrap_err();
+    compile_table(&[pair("key:q", "rel:x")]).unwrap_err();
+    compile_table(&[pair("key:q", "key")]).unwrap_err();
+
+    let default_pairs: Vec<(String, String)> = DEFAULT_TABLE.iter()
+        .map(|&(left, right)| (format!("key:{}", left), format!("key:{}", right)))
+        .collect();
+    assert_eq!(compile_table(&default_pairs).unwrap().len(), 2 * DEFAULT_TABLE.len());
+}
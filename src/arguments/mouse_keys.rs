@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::arguments::gyro::parse_f64;
+use crate::arguments::delay::parse_period_value;
+use crate::key::{Key, KeyParser};
+use crate::stream::mouse_keys::{MouseKeys, Speed};
+use crate::time::Duration;
+
+const DEFAULT_SPEED: f64 = 200.0;
+const DEFAULT_MAX_SPEED: f64 = 1200.0;
+const DEFAULT_ACCELERATION_TIME: Duration = Duration::from_millis(1500);
+const DEFAULT_INTERVAL: Duration = Duration::from_millis(10);
+
+/// Represents a --mouse-keys argument.
+pub(super) struct MouseKeysArg {
+    pub keys: Vec<Key>,
+    pub toggle_keys: Vec<Key>,
+    pub speed: Speed,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl MouseKeysArg {
+	pub fn parse(args: Vec<String>) -> Result<MouseKeysArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["toggle", "speed", "max-speed", "acceleration", "interval", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+        let mut toggle_parser = KeyParser::default_filter();
+        toggle_parser.allow_values = false;
+        let toggle_keys = toggle_parser.parse_all(&arg_group.get_clauses("toggle"))?;
+
+        let initial = match arg_group.get_unique_clause("speed")? {
+            Some(value) => parse_f64(&value, "speed")?,
+            None => DEFAULT_SPEED,
+        };
+        let max = match arg_group.get_unique_clause("max-speed")? {
+            Some(value) => parse_f64(&value, "maximum speed")?,
+            None => DEFAULT_MAX_SPEED.max(initial),
+        };
+        if initial <= 0.0 || max < initial {
+            return Err(ArgumentError::new("The speed of --mouse-keys must be positive, and the max-speed must be at least as high as the speed."));
+        }
+        let acceleration_time = match arg_group.get_unique_clause("acceleration")? {
+            Some(value) => parse_period_value(&value)?,
+            None => DEFAULT_ACCELERATION_TIME,
+        };
+        let interval = match arg_group.get_unique_clause("interval")? {
+            Some(value) => parse_period_value(&value)?,
+            None => DEFAULT_INTERVAL,
+        };
+        if interval < Duration::from_millis(1) {
+            return Err(ArgumentError::new("The interval of --mouse-keys must be at least 0.001 seconds."));
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(MouseKeysArg {
+            keys, toggle_keys, enabled_when, stage,
+            speed: Speed { initial, max, acceleration_time, interval },
+        })
+    }
+
+    pub fn compile(self) -> Result<MouseKeys, ArgumentError> {
+        MouseKeys::new(self.keys, self.toggle_keys, self.speed)
+    }
+}
@@ -1,13 +1,20 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::predevice::RepeatMode;
+use crate::predevice::{RepeatMode, OverflowPolicy, OverflowSetting};
 use crate::error::ArgumentError;
-use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::lib::{ComplexArgGroup, parse_fd_clause};
+use crate::arguments::capability::CapabilityOverrideSpec;
 use crate::key::{Key, KeyParser};
-use crate::event::Namespace;
+use crate::event::{EventCode, EventType, Namespace};
+use crate::domain::{self, Domain};
+use crate::range::Interval;
+use std::collections::HashMap;
+use std::os::unix::io::RawFd;
 use std::path::PathBuf;
 
-const DEFAULT_NAME: &str = "Evsieve Virtual Device";
+pub(super) const DEFAULT_NAME: &str = "Evsieve Virtual Device";
+/// Tells other programs that the device is a touchscreen or similar, whose coordinates map to the screen.
+const INPUT_PROP_DIRECT: u32 = 0x01;
 
 /// Contains properties that evsieve itself does not care about, but are visible to other programs.
 #[derive(Clone)]
@@ -16,6 +23,8 @@ pub struct DeviceProperties {
     pub device_id: Option<DeviceId>,
     pub version: Option<u16>,
     pub bus: Option<u16>,
+    /// The INPUT_PROP_* properties of the device, such as INPUT_PROP_POINTER.
+    pub input_props: Vec<u32>,
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -27,20 +36,44 @@ pub struct DeviceId {
 pub(super) struct OutputDevice {
     pub create_link: Option<PathBuf>,
     pub keys: Vec<Key>,
+    /// If true, the events written to this device also remain in the stream, so later --output
+    /// arguments can write them to other devices as well.
+    pub copy: bool,
+    /// If true, only codes that can reach this device under some combination of toggle states
+    /// are added to its capabilities.
+    pub strict_caps: bool,
+    /// If true, the device gets the capabilities of every key on a keyboard, regardless of which
+    /// events can actually reach it.
+    pub full_keyboard: bool,
     pub repeat_mode: RepeatMode,
     pub properties: DeviceProperties,
+    /// If Some, the device shall be created using this already opened /dev/uinput file descriptor,
+    /// which was inherited from the parent process.
+    pub uinput_fd: Option<RawFd>,
+    /// The domain of the input devices whose properties and capabilities shall be copied, as specified
+    /// by the mirror-of= clause.
+    pub mirror_of: Option<Domain>,
+    /// Whether the name of the mirrored input device shall be used, i.e. whether no name= was specified.
+    pub mirror_name: bool,
+    pub overflow: OverflowSetting,
+    /// The width and height of the area that the device can point at, as specified by the
+    /// abs-pointer= clause.
+    pub abs_pointer: Option<(i32, i32)>,
 }
 
 impl OutputDevice {
 	pub fn parse(args: Vec<String>) -> Result<OutputDevice, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &["repeat"],
-            &["create-link", "repeat", "name", "device-id", "version", "bus"],
+            &["repeat", "copy", "strict-caps", "full-keyboard"],
+            &["create-link", "repeat", "name", "device-id", "version", "bus", "fd", "mirror-of", "overflow", "queue-size", "abs-pointer"],
             false,
             true,
         )?;
 
+        // A full keyboard is expected to repeat its keys like a real keyboard does.
+        let full_keyboard = arg_group.has_flag("full-keyboard");
         let repeat_mode = match arg_group.get_unique_clause_or_default_if_flag("repeat", "enable")? {
+            None if full_keyboard => RepeatMode::Enable,
             None => RepeatMode::Passive,
             Some(mode) => match mode.as_str() {
                 "enable" => RepeatMode::Enable,
@@ -50,8 +83,42 @@ impl OutputDevice {
             },
         };
 
+        let mut overflow = OverflowSetting::default();
+        if let Some(policy) = arg_group.get_unique_clause("overflow")? {
+            overflow.policy = match policy.as_str() {
+                "drop-oldest" => OverflowPolicy::DropOldest,
+                "drop-newest" => OverflowPolicy::DropNewest,
+                "block" => OverflowPolicy::Block,
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid overflow policy \"{}\". Valid policies are \"drop-oldest\", \"drop-newest\" and \"block\".", policy
+                ))),
+            };
+        }
+        if let Some(size_str) = arg_group.get_unique_clause("queue-size")? {
+            overflow.queue_size = match size_str.parse::<usize>() {
+                Ok(size) if size > 0 => size,
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid queue size \"{}\": the queue size must be a positive integer.", size_str
+                ))),
+            };
+        }
+
+        let mirror_of = match arg_group.get_unique_clause("mirror-of")? {
+            None => None,
+            Some(domain_str) if domain_str.is_empty() => return Err(ArgumentError::new(
+                "The mirror-of= clause of an output argument cannot be empty."
+            )),
+            Some(domain_str) if domain_str.starts_with('@') => return Err(ArgumentError::new(format!(
+                "There must be no @ in the domain name from \"mirror-of={}\". Try specifying \"mirror-of={}\" instead.",
+                domain_str, &domain_str[1..]
+            ))),
+            Some(domain_str) => Some(domain::resolve(&domain_str)?),
+        };
+
         // Parse special properties of the output device that shall be created.
-        let name = arg_group.get_unique_clause("name")?.unwrap_or_else(|| DEFAULT_NAME.to_owned());
+        let name_clause = arg_group.get_unique_clause("name")?;
+        let mirror_name = mirror_of.is_some() && name_clause.is_none();
+        let name = name_clause.unwrap_or_else(|| DEFAULT_NAME.to_owned());
         if name.is_empty() {
             return Err(ArgumentError::new("Output device name cannot be empty."));
         }
@@ -69,10 +136,24 @@ impl OutputDevice {
             None => None,
         };
 
+        let uinput_fd = match arg_group.get_unique_clause("fd")? {
+            Some(value) => Some(parse_fd_clause(&value)?),
+            None => None,
+        };
+
+        let abs_pointer = match arg_group.get_unique_clause("abs-pointer")? {
+            Some(size_str) => Some(interpret_resolution(&size_str)?),
+            None => None,
+        };
+        let input_props = match abs_pointer {
+            Some(_) => vec![INPUT_PROP_DIRECT],
+            None => Vec::new(),
+        };
+
         // Parse the keys that shall be sent to this output device.
         let key_strs = arg_group.get_keys_or_empty_key();
         let mut keys = Vec::new();
-        for &namespace in &[Namespace::User, Namespace::Yielded] {
+        for &namespace in &[Namespace::User, Namespace::Yielded, Namespace::Resync] {
             keys.append(
                 &mut KeyParser::default_filter().with_namespace(namespace).parse_all(&key_strs)?
             );
@@ -80,14 +161,28 @@ impl OutputDevice {
 
 		Ok(OutputDevice {
             create_link: arg_group.get_unique_clause("create-link")?.map(PathBuf::from),
-            keys, repeat_mode,
+            keys, repeat_mode, uinput_fd, mirror_of, mirror_name, overflow, abs_pointer, full_keyboard,
+            copy: arg_group.has_flag("copy"),
+            strict_caps: arg_group.has_flag("strict-caps"),
             properties: DeviceProperties {
-                name, device_id, version, bus
+                name, device_id, version, bus, input_props,
             },
         })
     }
 }
 
+/// Returns the capabilities that the full-keyboard flag forces upon an output device: every EV_KEY
+/// code that is not a button.
+pub(super) fn full_keyboard_capabilities() -> HashMap<EventCode, CapabilityOverrideSpec> {
+    crate::ecodes::event_codes_for(EventType::KEY)
+        .filter(|&code| ! crate::ecodes::is_button_code(code))
+        .map(|code| (code, CapabilityOverrideSpec {
+            range: Some(Interval::new(Some(0), Some(2))),
+            flat: None, fuzz: None, value: None,
+        }))
+        .collect()
+}
+
 /// Tries to parse a clause like --bus=004a. The clause can contain up to four hexadecimal characters.
 fn interpret_hex_clause(property_name: &str, value_str: &str) -> Result<u16, ArgumentError> {
     parse_hex(value_str).ok_or_else(|| ArgumentError::new(
@@ -121,3 +216,16 @@ fn interpret_device_id_inner(id_str: &str) -> Option<DeviceId> {
     let product_id = parse_hex(product_id_str)?;
     Some(DeviceId { vendor_id, product_id })
 }
+
+/// Tries to parse a WIDTHxHEIGHT resolution like "1920x1080".
+fn interpret_resolution(size_str: &str) -> Result<(i32, i32), ArgumentError> {
+    let parse_dimension = |dimension_str: &str| match dimension_str.parse::<i32>() {
+        Ok(value) if value > 0 => Some(value),
+        _ => None,
+    };
+    str::split_once(size_str, 'x')
+        .and_then(|(width_str, height_str)| Some((parse_dimension(width_str)?, parse_dimension(height_str)?)))
+        .ok_or_else(|| ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a resolution. Please provide it in the form WIDTHxHEIGHT, for example \"abs-pointer=1920x1080\".", size_str
+        )))
+}
@@ -1,60 +1,147 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::domain::{self, Domain};
-use crate::error::{ArgumentError, RuntimeError, Context, SystemError};
-use crate::io::output::UInputSystem;
+use crate::error::{ArgumentError, RuntimeError, Context, SystemError, ErrorCategory};
+use crate::io::output::{OutputSystem, UInputSystem};
 use crate::key::Key;
 use crate::event::Namespace;
 use crate::persist::blueprint::Blueprint;
 use crate::stream::hook::{Hook, HookActuator};
+use crate::stream::macro_slot::MacroCommand;
 use crate::stream::map::{Map, Toggle};
+use crate::stream::mirror::CapabilityMirror;
+use crate::stream::abs_pointer::AbsPointer;
+use crate::stream::capability_override::CapabilityOverride;
 use crate::stream::withhold::{HookGroup, Withhold};
 use crate::stream::{StreamEntry, Setup};
-use crate::predevice::{PreInputDevice, PreOutputDevice};
+use crate::predevice::{PreInputDevice, PreOutputDevice, RepeatMode, MirrorSetting, OverflowSetting, GrabMode, FallbackMembership};
+use crate::sandbox::{SandboxMode, SandboxPolicy};
+use crate::privileges::RunAs;
 use crate::state::{State, ToggleIndex};
-use crate::control_fifo::ControlFifo;
+use crate::control_fifo::{ControlFifo, PreControlFifo};
+use crate::time::{Duration, MonotonicClock};
 use crate::arguments::hook::HookArg;
 use crate::arguments::input::InputDevice;
-use crate::arguments::output::OutputDevice;
+use crate::arguments::output::{OutputDevice, DeviceProperties, DEFAULT_NAME, full_keyboard_capabilities};
 use crate::arguments::toggle::ToggleArg;
 use crate::arguments::map::{MapArg, BlockArg};
 use crate::arguments::print::PrintArg;
 use crate::arguments::delay::DelayArg;
 use crate::arguments::withhold::WithholdArg;
 use crate::arguments::control_fifo::ControlFifoArg;
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::path::PathBuf;
 
 use super::absrel::RelToAbsArg;
 use super::capability::CapabilityArg;
 use super::config::ConfigArg;
-use super::input::PersistMode;
+use super::lib::ArgumentSpec;
+use super::pipeline::PipelineArg;
+use super::input::{PersistMode, ResyncMode};
+use super::invert_scroll::InvertScrollArg;
+use super::macro_slot::MacroSlotArg;
+use super::sandbox::SandboxArg;
+use super::run_as::RunAsArg;
+use super::quirk::QuirkArg;
+use super::gyro::GyroToRelArg;
+use super::mouse_keys::MouseKeysArg;
+use super::slow_keys::SlowKeysArg;
+use super::bounce_keys::BounceKeysArg;
+use super::inhibit_while_typing::InhibitWhileTypingArg;
+use super::max_hold::MaxHoldArg;
+use super::default::DefaultArg;
+use super::gate::EnabledWhenClause;
+use super::stats::StatsArg;
+use super::status_file::StatusFileArg;
+use super::recover::RecoverArg;
+use super::batch::BatchArg;
+use super::realtime::{RealtimeArg, LockMemoryArg};
+use super::safe_mode::SafeModeArg;
+use super::summarize::SummarizeArg;
+use super::introspect::IntrospectSocketArg;
+use super::shadow::{ShadowArg, PreShadow};
+use crate::introspect::{IntrospectSocket, PreIntrospectSocket};
+use crate::status::PreStatusFile;
+use crate::summary::{SummaryFormat, SummaryInput, SummaryOutput};
+use crate::realtime::RealtimePolicy;
+use super::histogram::HistogramArg;
+use super::sequence::SequenceArg;
+use super::edge::EdgeArg;
+use super::press_duration::PressDurationArg;
+use super::schedule::ScheduleArg;
+use super::vt::OnVtArg;
+use super::bypass::BypassArg;
+use super::mirror::MirrorArg;
+use super::remap_modifiers::RemapModifiersArg;
+use super::pressure_click::PressureClickArg;
+use super::plugin::PluginArg;
+use super::stage::{StageArg, StageClause, INPUT_STAGE_NAME};
+use crate::io::replay::{PreReplayDevice, ReplayDevice};
+use crate::stream::bypass::Bypass;
 use super::merge::MergeArg;
 use super::scale::ScaleArg;
+use super::curve::CurveArg;
 
-const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
+pub const VERSION: Option<&'static str> = option_env!("CARGO_PKG_VERSION");
 
 /// Returns the help message that should be printed for the --help argument.
 fn get_usage_msg() -> String {
-"Usage: evsieve [--input PATH... [domain=DOMAIN] [grab[=auto|force]] [persist=none|reopen|exit]]...
-               [--map SOURCE [DEST...] [yield]]...
-               [--copy SOURCE [DEST...] [yield]]...
+"Usage: evsieve [--input PATH... [fd=FD]... [domain=DOMAIN] [tags=TAG,...]... [grab[=auto|force]] [persist=none|reopen|exit] [resync=pass|isolate] [repeat=DELAY,PERIOD|off] [stale-after=SECONDS [stale-action=release|reopen]] [filter=KEY,...]... [group=NAME [priority=N]] [packets]]...
+               [--map SOURCE [SOURCE... ->] [DEST...] [yield] [resync] [nth=N|chance=PROBABILITY]]...
+               [--copy SOURCE [SOURCE... ->] [DEST...] [yield] [resync] [nth=N|chance=PROBABILITY]]...
                [--block [SOURCE...]]...
-               [--toggle SOURCE DEST... [id=ID] [mode=consistent|passive]]...
-               [--hook KEY... [exec-shell=COMMAND]... [toggle[=[ID][:INDEX]]]... [sequential] [period=SECONDS] [send-key=KEY]... [breaks-on=KEY]...]...
-               [--withhold [KEY...]]...
-               [--rel-to-abs REL_KEY ABS_KEY [speed=FACTOR]]...
-               [--merge [EVENTS...]]...
+               [--default block [SCOPE...]]...
+               [--toggle SOURCE DEST... [id=ID] [mode=consistent|passive] [names=NAME,...] [follows=ID [follow-states=STATE,...]]]...
+               [--hook KEY... [exec-shell=COMMAND]... [toggle[=[ID][:INDEX]]]... [sequential [interval=SECONDS]] [strict-channels] [period=SECONDS] [suspend=pause|count] [accumulate=TOTAL] [send-key=KEY[:xCOUNT]]... [burst-interval=SECONDS] [breaks-on=KEY]... [record-start=SLOT] [record-stop] [play=SLOT] [set-repeat=SETTING[@DOMAIN]]... [recenter[=DOMAIN]]... [effect=NAME:VALUE]... [send-signal=TARGET:SIGNAL]... [notify=MESSAGE] [id=ID]]...
+               [--withhold [KEY...] [strict-channels] [passthrough-on-activate[=KEY,...]] [debug]]...
+               [--sequence KEY KEY... [timeout=SECONDS] [send-key=KEY]... [exec-shell=COMMAND]... [toggle[=[ID][:INDEX]]]... [effect=NAME:VALUE]...]...
+               [--rel-to-abs REL_KEY ABS_KEY [speed=FACTOR] [range=MIN~MAX] [bounds=clamp|wrap] [edge-resistance=UNITS] [recenter-after=SECONDS] [resolution=UNITS]]...
+               [--gyro-to-rel ABS_KEY REL_KEY [speed=FACTOR] [enable=KEY [toggle]] [calibrate=SECONDS|off] [rest-threshold=VALUE]]...
+               [--mouse-keys [KEYS...] [toggle=KEY]... [speed=UNITS] [max-speed=UNITS] [acceleration=SECONDS] [interval=SECONDS]]...
+               [--slow-keys [KEYS...] period=SECONDS [feedback=KEY]]...
+               [--bounce-keys [KEYS...] period=SECONDS [mode=release|chatter]]...
+               [--inhibit-while-typing [KEYS...] period=SECONDS [typing=KEY]... [except=KEY]...]...
+               [--max-hold [KEYS...] period=SECONDS [except=KEY]...]...
+               [--edge [KEYS...] [mode=alternate|pulse] [initial=0|1]]...
+               [--press-duration KEYS... send=EVENT:MIN~MAX]...
+               [--mirror [KEYS...] [trigger=KEY] [table=PATH]]...
+               [--remap-modifiers [KEYS...] [swap=KEY,KEY]... [remap=KEY,KEY]...]...
+               [--pressure-click [ABS_KEYS...] [threshold=[RELEASE~]PRESS] [button=KEY]]...
+               [--plugin PATH [config=STRING]]...
+               [--schedule at=HH:MM[:SS]... toggle[=[ID][:INDEX]]...]...
+               [--on-vt vt=NUMBER... [leave] toggle[=[ID][:INDEX]]...]...
+               [--bypass KEYS... [output=INDEX]]...
+               [--stage NAME]...
+               [--merge [EVENTS...] [priority=DOMAIN:NUMBER]... [steal]]...
                [--scale [EVENTS...] factor=FACTOR]...
+               [--curve [EVENTS...] points=IN:OUT,...|table=PATH]...
+               [--invert-scroll [EVENTS...] [axes=vertical|horizontal|both] [id=ID]]...
+               [--macro-slot [EVENTS...] [slots=COUNT] [persist=PATH]]...
+               [--quirk NAME [EVENTS...]]...
                [--config PATH...]...
-               [--control-fifo PATH...]...
-               [--print [EVENTS...] [format=default|direct]]...
-               [--delay [EVENTS...] period=SECONDS]...
-               [--output [EVENTS...] [create-link=PATH] [name=NAME] [device-id=VENDOR:PRODUCT] [bus=BUS] [version=VERSION] [repeat[=MODE]]]...".to_owned()
+               [--pipeline PATH...]...
+               [--shadow PATH... [domain=DOMAIN]...]...
+               [--control-fifo PATH... [mode=OCTAL] [owner=USER[:GROUP]] [allow=COMMAND,...]]...
+               [--stats interval=SECONDS]
+               [--status-file PATH [interval=SECONDS]]
+               [--batch max-latency=MILLISECONDS]
+               [--realtime[=PRIORITY]]
+               [--lock-memory]
+               [--safe-mode]
+               [--summarize[=text|man]]
+               [--introspect-socket PATH... [mode=OCTAL] [owner=USER[:GROUP]]]...
+               [--recover PATH]
+               [--run-as USER[:GROUP]]
+               [--sandbox[=strict|off]]
+               [--print [EVENTS...] [format=default|direct|binary] [file=PATH]]...
+               [--histogram [EVENTS...] [buckets=COUNT] [format=text|json]]...
+               [--delay [EVENTS...] period=SECONDS [suspend=pause|count]]...
+               [--output [EVENTS...] [copy] [strict-caps] [full-keyboard] [create-link=PATH] [name=NAME] [device-id=VENDOR:PRODUCT] [bus=BUS] [version=VERSION] [repeat[=MODE]] [overflow=POLICY] [queue-size=SIZE] [abs-pointer=WIDTHxHEIGHT] [fd=FD]]...".to_owned()
 }
 
 /// Represents all arguments an user may pass to the evsieve program, except for
 /// some special arguments like --config that may represent several other arguments.
+#[allow(clippy::large_enum_variant)]
 enum Argument {
     InputDevice(InputDevice),
     OutputDevice(OutputDevice),
@@ -66,10 +153,45 @@ enum Argument {
     MergeArg(MergeArg),
     DelayArg(DelayArg),
     ScaleArg(ScaleArg),
+    CurveArg(CurveArg),
     WithholdArg(WithholdArg),
     RelToAbsArg(RelToAbsArg),
     ControlFifoArg(ControlFifoArg),
     CapabilityArg(CapabilityArg),
+    InvertScrollArg(InvertScrollArg),
+    MacroSlotArg(MacroSlotArg),
+    SandboxArg(SandboxArg),
+    RunAsArg(RunAsArg),
+    QuirkArg(QuirkArg),
+    GyroToRelArg(GyroToRelArg),
+    MouseKeysArg(MouseKeysArg),
+    SlowKeysArg(SlowKeysArg),
+    BounceKeysArg(BounceKeysArg),
+    InhibitWhileTypingArg(InhibitWhileTypingArg),
+    MaxHoldArg(MaxHoldArg),
+    DefaultArg(DefaultArg),
+    StatsArg(StatsArg),
+    StatusFileArg(StatusFileArg),
+    RecoverArg(RecoverArg),
+    BatchArg(BatchArg),
+    RealtimeArg(RealtimeArg),
+    SummarizeArg(SummarizeArg),
+    IntrospectSocketArg(IntrospectSocketArg),
+    ShadowArg(ShadowArg),
+    LockMemoryArg(LockMemoryArg),
+    SafeModeArg(SafeModeArg),
+    HistogramArg(HistogramArg),
+    SequenceArg(SequenceArg),
+    EdgeArg(EdgeArg),
+    PressDurationArg(PressDurationArg),
+    MirrorArg(MirrorArg),
+    RemapModifiersArg(RemapModifiersArg),
+    PressureClickArg(PressureClickArg),
+    PluginArg(PluginArg),
+    ScheduleArg(ScheduleArg),
+    OnVtArg(OnVtArg),
+    BypassArg(BypassArg),
+    StageArg(StageArg),
 }
 
 /// The MetaArgument represents things that may get turned into common arguments.
@@ -78,9 +200,11 @@ enum Argument {
 /// 
 /// This distinction between Argument and MetaArgument helps us to be sure that
 /// no unhandled meta-arguments are left during later stages of parsing.
+#[allow(clippy::large_enum_variant)]
 enum MetaArgument {
     Common(Argument),
     ConfigArg(ConfigArg),
+    PipelineArg(PipelineArg),
 }
 
 impl Argument {
@@ -98,13 +222,129 @@ impl Argument {
             "--merge" => Ok(Argument::MergeArg(MergeArg::parse(args)?)),
             "--delay" => Ok(Argument::DelayArg(DelayArg::parse(args)?)),
             "--scale" => Ok(Argument::ScaleArg(ScaleArg::parse(args)?)),
+            "--curve" => Ok(Argument::CurveArg(CurveArg::parse(args)?)),
             "--withhold" => Ok(Argument::WithholdArg(WithholdArg::parse(args)?)),
             "--rel-to-abs" => Ok(Argument::RelToAbsArg(RelToAbsArg::parse(args)?)),
             "--control-fifo" => Ok(Argument::ControlFifoArg(ControlFifoArg::parse(args)?)),
+            "--introspect-socket" => Ok(Argument::IntrospectSocketArg(IntrospectSocketArg::parse(args)?)),
+            "--shadow" => Ok(Argument::ShadowArg(ShadowArg::parse(args)?)),
             "--capability" => Ok(Argument::CapabilityArg(CapabilityArg::parse(args)?)),
+            "--invert-scroll" => Ok(Argument::InvertScrollArg(InvertScrollArg::parse(args)?)),
+            "--macro-slot" => Ok(Argument::MacroSlotArg(MacroSlotArg::parse(args)?)),
+            "--run-as" => Ok(Argument::RunAsArg(RunAsArg::parse(args)?)),
+            "--quirk" => Ok(Argument::QuirkArg(QuirkArg::parse(args)?)),
+            "--gyro-to-rel" => Ok(Argument::GyroToRelArg(GyroToRelArg::parse(args)?)),
+            "--mouse-keys" => Ok(Argument::MouseKeysArg(MouseKeysArg::parse(args)?)),
+            "--slow-keys" => Ok(Argument::SlowKeysArg(SlowKeysArg::parse(args)?)),
+            "--bounce-keys" => Ok(Argument::BounceKeysArg(BounceKeysArg::parse(args)?)),
+            "--inhibit-while-typing" => Ok(Argument::InhibitWhileTypingArg(InhibitWhileTypingArg::parse(args)?)),
+            "--max-hold" => Ok(Argument::MaxHoldArg(MaxHoldArg::parse(args)?)),
+            "--default" => Ok(Argument::DefaultArg(DefaultArg::parse(args)?)),
+            "--stats" => Ok(Argument::StatsArg(StatsArg::parse(args)?)),
+            "--status-file" => Ok(Argument::StatusFileArg(StatusFileArg::parse(args)?)),
+            "--recover" => Ok(Argument::RecoverArg(RecoverArg::parse(args)?)),
+            "--batch" => Ok(Argument::BatchArg(BatchArg::parse(args)?)),
+            "--lock-memory" => Ok(Argument::LockMemoryArg(LockMemoryArg::parse(args)?)),
+            "--safe-mode" => Ok(Argument::SafeModeArg(SafeModeArg::parse(args)?)),
+            "--histogram" => Ok(Argument::HistogramArg(HistogramArg::parse(args)?)),
+            "--sequence" => Ok(Argument::SequenceArg(SequenceArg::parse(args)?)),
+            "--edge" => Ok(Argument::EdgeArg(EdgeArg::parse(args)?)),
+            "--press-duration" => Ok(Argument::PressDurationArg(PressDurationArg::parse(args)?)),
+            "--mirror" => Ok(Argument::MirrorArg(MirrorArg::parse(args)?)),
+            "--remap-modifiers" => Ok(Argument::RemapModifiersArg(RemapModifiersArg::parse(args)?)),
+            "--pressure-click" => Ok(Argument::PressureClickArg(PressureClickArg::parse(args)?)),
+            "--plugin" => Ok(Argument::PluginArg(PluginArg::parse(args)?)),
+            "--schedule" => Ok(Argument::ScheduleArg(ScheduleArg::parse(args)?)),
+            "--on-vt" => Ok(Argument::OnVtArg(OnVtArg::parse(args)?)),
+            "--bypass" => Ok(Argument::BypassArg(BypassArg::parse(args)?)),
+            "--stage" => Ok(Argument::StageArg(StageArg::parse(args)?)),
+            _ if first_arg == "--sandbox" || first_arg.starts_with("--sandbox=")
+                => Ok(Argument::SandboxArg(SandboxArg::parse(args)?)),
+            _ if first_arg == "--realtime" || first_arg.starts_with("--realtime=")
+                => Ok(Argument::RealtimeArg(RealtimeArg::parse(args)?)),
+            _ if first_arg == "--summarize" || first_arg.starts_with("--summarize=")
+                => Ok(Argument::SummarizeArg(SummarizeArg::parse(args)?)),
             _ => Err(ArgumentError::new(format!("Encountered unknown argument: {}", first_arg)).into()),
         }
     }
+
+    /// Returns the enabled-when clause of arguments that support it.
+    fn enabled_when(&self) -> Option<&EnabledWhenClause> {
+        match self {
+            Argument::MapArg(arg) => arg.enabled_when.as_ref(),
+            Argument::HookArg(arg) => arg.enabled_when.as_ref(),
+            Argument::BlockArg(arg) => arg.enabled_when.as_ref(),
+            Argument::ToggleArg(arg) => arg.enabled_when.as_ref(),
+            Argument::PrintArg(arg) => arg.enabled_when.as_ref(),
+            Argument::MergeArg(arg) => arg.enabled_when.as_ref(),
+            Argument::DelayArg(arg) => arg.enabled_when.as_ref(),
+            Argument::ScaleArg(arg) => arg.enabled_when.as_ref(),
+            Argument::CurveArg(arg) => arg.enabled_when.as_ref(),
+            Argument::RelToAbsArg(arg) => arg.enabled_when.as_ref(),
+            Argument::InvertScrollArg(arg) => arg.enabled_when.as_ref(),
+            Argument::QuirkArg(arg) => arg.enabled_when.as_ref(),
+            Argument::GyroToRelArg(arg) => arg.enabled_when.as_ref(),
+            Argument::MouseKeysArg(arg) => arg.enabled_when.as_ref(),
+            Argument::SlowKeysArg(arg) => arg.enabled_when.as_ref(),
+            Argument::BounceKeysArg(arg) => arg.enabled_when.as_ref(),
+            Argument::InhibitWhileTypingArg(arg) => arg.enabled_when.as_ref(),
+            Argument::MaxHoldArg(arg) => arg.enabled_when.as_ref(),
+            Argument::HistogramArg(arg) => arg.enabled_when.as_ref(),
+            Argument::SequenceArg(arg) => arg.enabled_when.as_ref(),
+            Argument::EdgeArg(arg) => arg.enabled_when.as_ref(),
+            Argument::PressDurationArg(arg) => arg.enabled_when.as_ref(),
+            Argument::MirrorArg(arg) => arg.enabled_when.as_ref(),
+            Argument::RemapModifiersArg(arg) => arg.enabled_when.as_ref(),
+            Argument::PressureClickArg(arg) => arg.enabled_when.as_ref(),
+            Argument::PluginArg(arg) => arg.enabled_when.as_ref(),
+            Argument::InputDevice(_) | Argument::OutputDevice(_) | Argument::WithholdArg(_)
+                | Argument::ControlFifoArg(_) | Argument::CapabilityArg(_) | Argument::MacroSlotArg(_)
+                | Argument::SandboxArg(_) | Argument::RunAsArg(_) | Argument::StatsArg(_) | Argument::RecoverArg(_)
+                | Argument::StatusFileArg(_) | Argument::BatchArg(_) | Argument::RealtimeArg(_) | Argument::LockMemoryArg(_) | Argument::SafeModeArg(_)
+                | Argument::SummarizeArg(_) | Argument::IntrospectSocketArg(_) | Argument::ShadowArg(_)
+                | Argument::ScheduleArg(_) | Argument::OnVtArg(_) | Argument::BypassArg(_)
+                | Argument::StageArg(_) | Argument::DefaultArg(_) => None,
+        }
+    }
+
+    /// Returns the stage clause of arguments that support it.
+    fn stage(&self) -> Option<&StageClause> {
+        match self {
+            Argument::MapArg(arg) => arg.stage.as_ref(),
+            Argument::HookArg(arg) => arg.stage.as_ref(),
+            Argument::BlockArg(arg) => arg.stage.as_ref(),
+            Argument::ToggleArg(arg) => arg.stage.as_ref(),
+            Argument::PrintArg(arg) => arg.stage.as_ref(),
+            Argument::MergeArg(arg) => arg.stage.as_ref(),
+            Argument::DelayArg(arg) => arg.stage.as_ref(),
+            Argument::ScaleArg(arg) => arg.stage.as_ref(),
+            Argument::CurveArg(arg) => arg.stage.as_ref(),
+            Argument::RelToAbsArg(arg) => arg.stage.as_ref(),
+            Argument::InvertScrollArg(arg) => arg.stage.as_ref(),
+            Argument::QuirkArg(arg) => arg.stage.as_ref(),
+            Argument::GyroToRelArg(arg) => arg.stage.as_ref(),
+            Argument::MouseKeysArg(arg) => arg.stage.as_ref(),
+            Argument::SlowKeysArg(arg) => arg.stage.as_ref(),
+            Argument::BounceKeysArg(arg) => arg.stage.as_ref(),
+            Argument::InhibitWhileTypingArg(arg) => arg.stage.as_ref(),
+            Argument::MaxHoldArg(arg) => arg.stage.as_ref(),
+            Argument::HistogramArg(arg) => arg.stage.as_ref(),
+            Argument::SequenceArg(arg) => arg.stage.as_ref(),
+            Argument::EdgeArg(arg) => arg.stage.as_ref(),
+            Argument::PressDurationArg(arg) => arg.stage.as_ref(),
+            Argument::MirrorArg(arg) => arg.stage.as_ref(),
+            Argument::RemapModifiersArg(arg) => arg.stage.as_ref(),
+            Argument::PressureClickArg(arg) => arg.stage.as_ref(),
+            Argument::PluginArg(arg) => arg.stage.as_ref(),
+            Argument::InputDevice(_) | Argument::OutputDevice(_) | Argument::WithholdArg(_)
+                | Argument::ControlFifoArg(_) | Argument::CapabilityArg(_) | Argument::MacroSlotArg(_)
+                | Argument::SandboxArg(_) | Argument::RunAsArg(_) | Argument::StatsArg(_) | Argument::RecoverArg(_)
+                | Argument::StatusFileArg(_) | Argument::BatchArg(_) | Argument::RealtimeArg(_) | Argument::LockMemoryArg(_) | Argument::SafeModeArg(_)
+                | Argument::SummarizeArg(_) | Argument::IntrospectSocketArg(_) | Argument::ShadowArg(_)
+                | Argument::ScheduleArg(_) | Argument::OnVtArg(_) | Argument::BypassArg(_)
+                | Argument::StageArg(_) | Argument::DefaultArg(_) => None,
+        }
+    }
 }
 
 impl MetaArgument {
@@ -113,11 +353,22 @@ impl MetaArgument {
             "--config" => {
                 Ok(MetaArgument::ConfigArg(ConfigArg::parse(args)?))
             },
+            "--pipeline" => {
+                Ok(MetaArgument::PipelineArg(PipelineArg::parse(args)?))
+            },
             _ => Argument::parse(args).map(MetaArgument::Common),
         }
     }
 }
 
+/// Returns which paths, keys, flags and clauses the argument with the given name accepts, or None if the
+/// argument does not exist or its parser does not use a ComplexArgGroup.
+pub(super) fn probe_argument(name: &str) -> Option<ArgumentSpec> {
+    super::lib::probe_spec(|| {
+        let _ = MetaArgument::parse(vec![name.to_owned()]);
+    })
+}
+
 /// If a --version or --help or something is specified, prints a helpful message.
 /// Returns true if --version or --help was requested, otherwise returns false.
 pub fn check_help_and_version(args: &[String]) -> bool {
@@ -135,20 +386,29 @@ pub fn check_help_and_version(args: &[String]) -> bool {
         return true;
     }
 
+    if args.contains(&"--feature-info".to_owned()) {
+        println!("{}", super::feature_info::feature_info(VERSION.unwrap_or("unknown")));
+        return true;
+    }
+
     false
 }
 
-/// Sorts arguments like ["--input", "/dev/foo", "--map", "key:a", "key:b"] into groups like
+/// Checks whether a single argument like ["--map", "key:a", "key:b"] is valid on its own.
+pub(super) fn check_argument(group: Vec<String>) -> Result<(), RuntimeError> {
+    MetaArgument::parse(group).map(|_| ())
+}
+
+/// Splits arguments like ["--input", "/dev/foo", "--map", "key:a", "key:b"] into groups like
 ///     [["--input", "/dev/foo"], ["--map", "key:a", "key:b"]]
-/// and uses the appropriate MetaArgument to represent each group.
-fn sort_into_groups(args: Vec<String>) -> Result<Vec<MetaArgument>, RuntimeError> {
+pub(super) fn split_into_groups(args: Vec<String>) -> Result<Vec<Vec<String>>, ArgumentError> {
     let mut groups: Vec<Vec<String>> = Vec::new();
     let mut args_iter = args.into_iter().peekable();
 	while let Some(first_arg) = args_iter.next() {
 		if ! first_arg.starts_with("--") {
 			return Err(ArgumentError::new(format!(
                 "Expected an argument starting with --, encountered \"{}\".", first_arg
-            )).into());
+            )));
         }
 
         // Take items from the arg list until we encounter the start of the next argument.
@@ -162,26 +422,38 @@ fn sort_into_groups(args: Vec<String>) -> Result<Vec<MetaArgument>, RuntimeError
 		
 		groups.push(new_group);
     }
+    Ok(groups)
+}
 
-    groups.into_iter().map(
-        |group| MetaArgument::parse(group.clone()).with_context_of(|| format!(
+/// Sorts arguments into groups and uses the appropriate MetaArgument to represent each group.
+fn sort_into_groups(args: Vec<String>) -> Result<Vec<(MetaArgument, Vec<String>)>, RuntimeError> {
+    split_into_groups(args)?.into_iter().map(|group| {
+        let meta_arg = MetaArgument::parse(group.clone()).with_context_of(|| format!(
             "While parsing the arguments \"{}\":", group.join(" ")
-        )
-    )).collect()
+        ))?;
+        Ok((meta_arg, group))
+    }).collect()
 }
 
 /// Sorts arguments that are strings into argument groups, then replaces all --config
 /// arguments with the contents of their files and sorts those as well, recursively.
+/// The strings that each argument was parsed from are written to `group_buffer`, and the
+/// paths of all --pipeline arguments are written to `pipeline_paths`.
 fn sort_and_expand_config(
     args_to_sort: Vec<String>,
     output_buffer: &mut Vec<Argument>,
+    group_buffer: &mut Vec<Vec<String>>,
+    pipeline_paths: &mut Vec<String>,
     visited_config_files: Vec<&str>,
 ) -> Result<(), RuntimeError> {
     let meta_args = sort_into_groups(args_to_sort)?;
 
-    for meta_arg in meta_args {
+    for (meta_arg, group) in meta_args {
         match meta_arg {
-            MetaArgument::Common(arg) => output_buffer.push(arg),
+            MetaArgument::Common(arg) => {
+                output_buffer.push(arg);
+                group_buffer.push(group);
+            },
             MetaArgument::ConfigArg(config) => {
                 for path in config.paths {
                     if visited_config_files.contains(&path.as_str()) {
@@ -189,47 +461,109 @@ fn sort_and_expand_config(
                             format!("The configuration file {} is getting recursively included.", path)
                         ).into());
                     }
-                    let file_content = std::fs::read_to_string(&path)
-                        .map_err(SystemError::from)
-                        .with_context_of(|| format!("While trying to read the file {}:", &path))?;
-
-                    let file_args = crate::utils::shelllex::lex(&file_content)
-                        .with_context_of(|| format!("While parsing the configuration file {}:", &path))?;
+                    let file_args = read_config_file(&path)?;
 
                     let mut local_visited_config_files = visited_config_files.clone();
                     local_visited_config_files.push(&path);
 
                     sort_and_expand_config(
-                        file_args, output_buffer, local_visited_config_files
+                        file_args, output_buffer, group_buffer, pipeline_paths, local_visited_config_files
                     ).with_context_of(|| format!("While interpreting the configuration file {}:", &path))?
                 }
-            }
+            },
+            MetaArgument::PipelineArg(pipeline) => {
+                pipeline_paths.extend(pipeline.paths);
+            },
         }
     }
 
     Ok(())
 }
 
-fn parse(args: Vec<String>) -> Result<Vec<Argument>, RuntimeError> {
+/// Reads a configuration file and splits its content into arguments.
+pub(super) fn read_config_file(path: &str) -> Result<Vec<String>, RuntimeError> {
+    let file_content = std::fs::read_to_string(path)
+        .map_err(SystemError::from)
+        .with_context_of(|| format!("While trying to read the file {}:", path))?;
+
+    let args = crate::utils::shelllex::lex(&file_content)
+        .with_context_of(|| format!("While parsing the configuration file {}:", path))?;
+    Ok(args)
+}
+
+/// The strings that a list of arguments was parsed from, with one group of strings per argument.
+type ArgumentGroups = Vec<Vec<String>>;
+
+/// Returns the arguments, the strings that each argument was parsed from, and the paths of all --pipeline arguments.
+fn parse(args: Vec<String>) -> Result<(Vec<Argument>, ArgumentGroups, Vec<String>), RuntimeError> {
     let mut output: Vec<Argument> = Vec::new();
-    sort_and_expand_config(args, &mut output, Vec::new())?;
-    Ok(output)
+    let mut groups: Vec<Vec<String>> = Vec::new();
+    let mut pipeline_paths: Vec<String> = Vec::new();
+    sort_and_expand_config(args, &mut output, &mut groups, &mut pipeline_paths, Vec::new())?;
+    Ok((output, groups, pipeline_paths))
 }
 
 pub struct PreImplementation {
     pub stream: Vec<StreamEntry>,
     pub input_devices: Vec<PreInputDevice>,
+    /// The files specified by --input arguments with the replay flag.
+    pub replay_devices: Vec<PreReplayDevice>,
     pub output_devices: Vec<PreOutputDevice>,
-    pub control_fifo_paths: Vec<String>,
+    pub control_fifos: Vec<PreControlFifo>,
     pub state: State,
     pub toggle_indices: HashMap<String, ToggleIndex>,
+    /// The names of the stages, where the index of each name is its StageIndex.
+    pub stage_names: Vec<String>,
+    pub sandbox_policy: SandboxPolicy,
+    pub run_as: Option<RunAs>,
+    /// How often the statistics of the input devices should be printed, if at all.
+    pub stats_interval: Option<Duration>,
+    /// The file specified by --status-file, if any.
+    pub status_file: Option<PreStatusFile>,
+    /// The path of the journal of pressed output keys specified by --recover, if any.
+    pub journal_path: Option<PathBuf>,
+    /// How long evsieve may wait to batch events together, as specified by --batch.
+    pub batch_latency: Option<Duration>,
+    /// The scheduling and memory policy specified by --realtime and --lock-memory.
+    pub realtime: RealtimePolicy,
+    /// Whether all input devices must be released again if one of them cannot be grabbed, as requested by --safe-mode.
+    pub safe_mode: bool,
+    /// If Some, a summary of the script shall be printed instead of running it, as requested by --summarize.
+    pub summary_format: Option<SummaryFormat>,
+    pub introspect_sockets: Vec<PreIntrospectSocket>,
+    /// The strings that the arguments were parsed from, split into one group per argument, with --config
+    /// arguments replaced by the contents of their files. Reported by introspection sockets.
+    pub argument_groups: Vec<Vec<String>>,
+    /// The shadow pipelines specified by --shadow arguments, which have not been turned into pipelines yet.
+    pub shadows: Vec<PreShadow>,
+    /// If this is a shadow pipeline, the domains of the input devices of other pipelines whose events are
+    /// copied to this pipeline.
+    pub shadow_sources: Option<Vec<Domain>>,
 }
 
 pub struct Implementation {
     pub setup: Setup<UInputSystem>,
     pub input_devices: Vec<crate::io::input::InputDevice>,
+    pub replay_devices: Vec<ReplayDevice>,
     pub blueprints: Vec<Blueprint>,
     pub control_fifos: Vec<ControlFifo>,
+    pub sandbox_policy: SandboxPolicy,
+    pub run_as: Option<RunAs>,
+    /// How often the statistics of the input devices should be printed, if at all.
+    pub stats_interval: Option<Duration>,
+    /// The file specified by --status-file, if any.
+    pub status_file: Option<PreStatusFile>,
+    /// How long evsieve may wait to batch events together, as specified by --batch.
+    pub batch_latency: Option<Duration>,
+    /// The scheduling and memory policy specified by --realtime and --lock-memory.
+    pub realtime: RealtimePolicy,
+    /// Whether all input devices must be released again if one of them cannot be grabbed, as requested by --safe-mode.
+    pub safe_mode: bool,
+    pub introspect_sockets: Vec<IntrospectSocket>,
+    /// The strings that the arguments were parsed from, as in PreImplementation.
+    pub argument_groups: Vec<Vec<String>>,
+    /// As in PreImplementation.
+    pub shadow_sources: Option<Vec<Domain>>,
 }
 
 enum OutputDomainAssignment {
@@ -243,35 +577,314 @@ enum OutputDomainAssignment {
 pub fn process(args_str: Vec<String>)
         -> Result<PreImplementation, RuntimeError>
 {
-    let mut args: Vec<Argument> = parse(args_str)?;
+    let (args, argument_groups, pipeline_paths) = parse(args_str)?;
+    if ! pipeline_paths.is_empty() {
+        return Err(ArgumentError::new("The --pipeline argument cannot be used here.").into());
+    }
+    let mut pre_implementation = process_arguments(args, None)?;
+    if ! pre_implementation.shadows.is_empty() {
+        return Err(ArgumentError::new("The --shadow argument cannot be used here.").into());
+    }
+    pre_implementation.argument_groups = argument_groups;
+    check_unique_resources(&[&pre_implementation])?;
+    Ok(pre_implementation)
+}
+
+/// Like `process()`, but additionally turns the configuration file of each --pipeline argument into a
+/// separate pipeline. The first pipeline returned is the one defined by the arguments themselves.
+///
+/// The sandbox policies of all pipelines are merged into the policy of the first one.
+pub fn process_pipelines(args_str: Vec<String>)
+        -> Result<Vec<PreImplementation>, RuntimeError>
+{
+    let (args, argument_groups, pipeline_paths) = parse(args_str)?;
+    let mut main_pipeline = process_arguments(args, None)?;
+    main_pipeline.argument_groups = argument_groups;
+    let sandbox_mode = main_pipeline.sandbox_policy.mode;
+
+    let mut pipelines: Vec<PreImplementation> = Vec::new();
+    for path in pipeline_paths {
+        let mut pipeline = process_pipeline_file(&path, sandbox_mode)
+            .with_context_of(|| format!("While interpreting the pipeline {}:", &path))?;
+        let sandbox_policy = std::mem::replace(&mut pipeline.sandbox_policy, SandboxPolicy::new(sandbox_mode));
+        main_pipeline.sandbox_policy.extend(sandbox_policy);
+        pipelines.push(pipeline);
+    }
+    pipelines.insert(0, main_pipeline);
+
+    // Shadow pipelines are processed last, because they need to know the input devices of the other pipelines.
+    let shadows = std::mem::take(&mut pipelines[0].shadows);
+    for shadow in shadows {
+        let path = shadow.path.clone();
+        let mut shadow_pipeline = process_shadow_file(shadow, sandbox_mode, &pipelines)
+            .with_context_of(|| format!("While interpreting the shadow pipeline {}:", &path))?;
+        let sandbox_policy = std::mem::replace(&mut shadow_pipeline.sandbox_policy, SandboxPolicy::new(sandbox_mode));
+        pipelines[0].sandbox_policy.extend(sandbox_policy);
+        pipelines.push(shadow_pipeline);
+    }
+
+    check_unique_resources(&pipelines.iter().collect::<Vec<_>>())?;
+    Ok(pipelines)
+}
+
+/// Turns the configuration file of a --shadow argument into a pipeline which receives a copy of the events of
+/// the input devices of the other pipelines, and which is only allowed to print or log events.
+fn process_shadow_file(shadow: PreShadow, sandbox_mode: SandboxMode, pipelines: &[PreImplementation]) -> Result<PreImplementation, RuntimeError> {
+    let (args, argument_groups, nested_pipeline_paths) = parse(read_config_file(&shadow.path)?)?;
+    if ! nested_pipeline_paths.is_empty() {
+        return Err(ArgumentError::new("The --pipeline argument cannot be used in shadow pipelines.").into());
+    }
+    for arg in &args {
+        check_shadow_argument(arg)?;
+    }
+
+    let source_devices: Vec<&PreInputDevice> = pipelines.iter()
+        .flat_map(|pipeline| pipeline.input_devices.iter())
+        .filter(|device| shadow.domains.is_empty() || shadow.domains.contains(&device.target_domain))
+        .collect();
+    for &domain in &shadow.domains {
+        if ! source_devices.iter().any(|device| device.target_domain == domain) {
+            return Err(ArgumentError::new(format!(
+                "The domain={} clause of the --shadow argument does not refer to the domain of any input device.",
+                domain::try_reverse_resolve(domain).unwrap_or_default()
+            )).into());
+        }
+    }
+
+    let mut pipeline = process_arguments(args, Some(sandbox_mode))?;
+    pipeline.argument_groups = argument_groups;
+
+    // The events enter the shadow pipeline in the same way as they enter the pipeline of their input device.
+    let mut domain_shifts: Vec<StreamEntry> = Vec::new();
+    for device in &source_devices {
+        domain_shifts.push(StreamEntry::Map(Map::domain_shift(
+            device.domain, Namespace::Input, device.target_domain, Namespace::User,
+        )));
+        if let Some(resync_domain) = device.resync_domain {
+            domain_shifts.push(StreamEntry::Map(Map::domain_shift(
+                resync_domain, Namespace::Input, device.target_domain, Namespace::Resync,
+            )));
+        }
+    }
+    pipeline.stream.splice(0 .. 0, domain_shifts);
+    pipeline.shadow_sources = Some(source_devices.iter().map(|device| device.domain).collect());
+    Ok(pipeline)
+}
+
+/// Returns an error if an argument could affect anything outside of evsieve, which shadow pipelines must not do.
+fn check_shadow_argument(arg: &Argument) -> Result<(), ArgumentError> {
+    let forbidden = match arg {
+        Argument::InputDevice(_) => Some("--input"),
+        Argument::OutputDevice(_) => Some("--output"),
+        Argument::ControlFifoArg(_) => Some("--control-fifo"),
+        Argument::BypassArg(_) => Some("--bypass"),
+        Argument::PluginArg(_) => Some("--plugin"),
+        Argument::RecoverArg(_) => Some("--recover"),
+        Argument::MacroSlotArg(arg) if arg.persist_path.is_some() => Some("--macro-slot with a persist= clause"),
+        Argument::HookArg(arg) if ! arg.exec_shell.is_empty() || ! arg.effects.is_empty() || ! arg.send_signals.is_empty()
+            || arg.notify.is_some() || ! arg.repeat_commands.is_empty() => Some("--hook with an exec-shell, effect, send-signal, notify or set-repeat clause"),
+        Argument::SequenceArg(arg) if ! arg.exec_shell.is_empty() || ! arg.effects.is_empty() => Some("--sequence with an exec-shell or effect clause"),
+        _ => None,
+    };
+    match forbidden {
+        Some(name) => Err(ArgumentError::new(format!(
+            "The {} argument cannot be used in shadow pipelines. Shadow pipelines receive their events from the input devices of the other pipelines and may only print or log events.", name
+        ))),
+        None => Ok(()),
+    }
+}
+
+fn process_pipeline_file(path: &str, sandbox_mode: SandboxMode) -> Result<PreImplementation, RuntimeError> {
+    let (args, argument_groups, nested_pipeline_paths) = parse(read_config_file(path)?)?;
+    if ! nested_pipeline_paths.is_empty() {
+        return Err(ArgumentError::new("The --pipeline argument can only be used on the command line or in files included by --config, not in other pipelines.").into());
+    }
+    let mut pipeline = process_arguments(args, Some(sandbox_mode))?;
+    pipeline.argument_groups = argument_groups;
+    Ok(pipeline)
+}
+
+/// Compiles the arguments of the apply command of a control FIFO and inserts the resulting entries into a
+/// running setup, directly after the --stage argument that declared `at_stage`, or at the end of the
+/// stream if None. `sandbox_mode` must be the sandbox mode specified on the command line.
+pub fn apply<T: OutputSystem>(setup: &mut Setup<T>, at_stage: Option<&str>, args_str: Vec<String>, sandbox_mode: SandboxMode)
+        -> Result<(), RuntimeError>
+{
+    let after_stage = match at_stage {
+        None => None,
+        Some(INPUT_STAGE_NAME) => return Err(ArgumentError::new(
+            "Arguments cannot be inserted at the input stage, because it is not declared by a --stage argument."
+        ).into()),
+        Some(name) => match setup.stage_names().iter().position(|stage_name| stage_name == name) {
+            Some(index) => Some(index),
+            None => return Err(ArgumentError::new(format!("No stage named \"{}\" has been declared.", name)).into()),
+        },
+    };
+
+    let (args, _, pipeline_paths) = parse(args_str)?;
+    if ! pipeline_paths.is_empty() {
+        return Err(ArgumentError::new("The --pipeline argument cannot be used here.").into());
+    }
+    if ! args.iter().all(can_be_applied) {
+        return Err(ArgumentError::new(
+            "Only arguments that process events can be applied while evsieve is running. Input and output devices, control FIFOs, introspection sockets and the --capability, --macro-slot, --plugin, --bypass, --on-vt, --sandbox, --run-as, --stats, --status-file, --batch, --realtime, --lock-memory, --safe-mode, --summarize and --recover arguments cannot be applied."
+        ).into());
+    }
+
+    // Pretending to be a pipeline makes sure that the new arguments respect the sandbox.
+    let PreImplementation { stream, state, toggle_indices, stage_names, .. } = process_arguments_with_state(
+        args, Some(sandbox_mode), setup.state().clone(), setup.toggle_indices().clone(), setup.stage_names().to_vec(),
+    )?;
+    setup.splice(after_stage, stream, state, toggle_indices, stage_names);
+    Ok(())
+}
+
+/// Returns whether an argument only adds entries to the stream that do not need to be set up when evsieve
+/// starts, which means that it can be applied while evsieve is running.
+fn can_be_applied(arg: &Argument) -> bool {
+    ! matches!(arg,
+        Argument::InputDevice(_) | Argument::OutputDevice(_) | Argument::CapabilityArg(_)
+        | Argument::ControlFifoArg(_) | Argument::MacroSlotArg(_) | Argument::BypassArg(_) | Argument::OnVtArg(_)
+        | Argument::SandboxArg(_) | Argument::RunAsArg(_) | Argument::StatsArg(_) | Argument::StatusFileArg(_) | Argument::BatchArg(_)
+        | Argument::RealtimeArg(_) | Argument::LockMemoryArg(_) | Argument::SafeModeArg(_) | Argument::SummarizeArg(_)
+        | Argument::RecoverArg(_) | Argument::IntrospectSocketArg(_) | Argument::ShadowArg(_)
+        // Anybody who can write to a control FIFO must not be able to make evsieve load arbitrary code.
+        | Argument::PluginArg(_)
+    )
+}
+
+/// Turns parsed arguments into a PreImplementation. If these arguments define a pipeline of a --pipeline
+/// argument, then `parent_sandbox_mode` must be the sandbox mode specified on the command line.
+fn process_arguments(args: Vec<Argument>, parent_sandbox_mode: Option<SandboxMode>)
+        -> Result<PreImplementation, RuntimeError>
+{
+    let stage_names = vec![INPUT_STAGE_NAME.to_owned()];
+    process_arguments_with_state(args, parent_sandbox_mode, State::new(), HashMap::new(), stage_names)
+}
+
+/// Like `process_arguments()`, but the toggles, toggle IDs and stages declared by the arguments get added
+/// to the given ones instead of starting from scratch.
+fn process_arguments_with_state(
+        mut args: Vec<Argument>,
+        parent_sandbox_mode: Option<SandboxMode>,
+        mut state: State,
+        mut toggle_indices: HashMap<String, ToggleIndex>,
+        mut stage_names: Vec<String>,
+    ) -> Result<PreImplementation, RuntimeError>
+{
+    let is_pipeline = parent_sandbox_mode.is_some();
     let mut input_devices: Vec<PreInputDevice> = Vec::new();
+    let mut replay_devices: Vec<PreReplayDevice> = Vec::new();
     let mut output_devices: Vec<PreOutputDevice> = Vec::new();
-    let mut control_fifo_paths: Vec<String> = Vec::new();
+    // For each output device with a mirror-of= clause: its index in `output_devices`, the domain it mirrors,
+    // and whether it copies the name of the mirrored device.
+    let mut mirrors: Vec<(usize, Domain, bool)> = Vec::new();
+    // The target domains of the input devices that have the auto-output flag.
+    let mut auto_output_domains: Vec<Domain> = Vec::new();
+    let mut bypass_args: Vec<BypassArg> = Vec::new();
+    let mut control_fifos: Vec<PreControlFifo> = Vec::new();
     let mut stream: Vec<StreamEntry> = Vec::new();
 
-    let mut state: State = State::new();
-
-    // Maps a toggle's ID to the index at which it can be found.
-    let mut toggle_indices: HashMap<String, ToggleIndex> = HashMap::new();
+    // Maps a toggle's ID to the names of its states, if the names clause was specified.
+    let mut toggle_names: HashMap<String, Vec<String>> = HashMap::new();
 
     // Reserve toggle indices ahead of time so --hooks can act upon indices of toggles
     // that will only be defined later.
+    let mut hook_ids: HashSet<&str> = HashSet::new();
     for arg in &args {
-        if let Argument::ToggleArg(toggle_arg) = arg {
-            if let Some(id) = toggle_arg.id.clone() {
-                match toggle_indices.get(&id) {
-                    Some(_) => {
-                        return Err(ArgumentError::new("Two toggles cannot have the same id.").into());
-                    },
-                    None => {
-                        let index = state.create_toggle_with_size(toggle_arg.size())?;
-                        toggle_indices.insert(id, index);
-                    }
+        if let Argument::HookArg(HookArg { id: Some(id), .. }) = arg {
+            if ! hook_ids.insert(id) {
+                return Err(ArgumentError::new(format!("Two hooks cannot have the same id: \"{}\".", id)).into());
+            }
+        }
+        if let Argument::ToggleArg(ToggleArg { id: Some(id), names: Some(names), .. }) = arg {
+            toggle_names.insert(id.clone(), names.clone());
+        }
+        let id_and_size = match arg {
+            Argument::ToggleArg(toggle_arg) => toggle_arg.id.clone().map(|id| (id, toggle_arg.size())),
+            // An --invert-scroll toggles between being inverted and not being inverted.
+            Argument::InvertScrollArg(invert_scroll_arg) => invert_scroll_arg.id.clone().map(|id| (id, 2)),
+            _ => None,
+        };
+        if let Some((id, size)) = id_and_size {
+            match toggle_indices.get(&id) {
+                Some(_) => {
+                    return Err(ArgumentError::new("Two toggles cannot have the same id.").into());
+                },
+                None => {
+                    let index = state.create_toggle_with_size(size)?;
+                    state[index].names = toggle_names.get(&id).cloned();
+                    toggle_indices.insert(id, index);
                 }
             }
         }
     }
 
+    // Find out whether the user wants evsieve to sandbox itself.
+    let mut sandbox_args = args.iter().filter_map(|arg| match arg {
+        Argument::SandboxArg(sandbox_arg) => Some(sandbox_arg),
+        _ => None,
+    });
+    let sandbox_mode = sandbox_args.next().map(|sandbox_arg| sandbox_arg.mode).unwrap_or(SandboxMode::Off);
+    if sandbox_args.next().is_some() {
+        return Err(ArgumentError::new("The --sandbox argument can only be specified once.").into());
+    }
+    let sandbox_mode = match parent_sandbox_mode {
+        None => sandbox_mode,
+        Some(parent_sandbox_mode) => {
+            if args.iter().any(|arg| matches!(arg, Argument::SandboxArg(_))) {
+                return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+            }
+            parent_sandbox_mode
+        },
+    };
+    if sandbox_mode == SandboxMode::Strict {
+        let uses_exec_shell = args.iter().any(|arg| match arg {
+            Argument::HookArg(hook_arg) => ! hook_arg.exec_shell.is_empty(),
+            Argument::SequenceArg(sequence_arg) => ! sequence_arg.exec_shell.is_empty(),
+            _ => false,
+        });
+        if uses_exec_shell {
+            return Err(ArgumentError::new("The exec-shell clause cannot be used together with --sandbox=strict, because the sandbox does not allow evsieve to execute other programs.").into());
+        }
+        let spawning_effect = args.iter().find_map(|arg| match arg {
+            Argument::HookArg(hook_arg) => hook_arg.effects.iter().find(|effect| crate::effects::spawns_processes(effect)),
+            Argument::SequenceArg(sequence_arg) => sequence_arg.effects.iter().find(|effect| crate::effects::spawns_processes(effect)),
+            _ => None,
+        });
+        if let Some(effect) = spawning_effect {
+            return Err(ArgumentError::new(format!("The effect={} clause cannot be used together with --sandbox=strict, because the sandbox does not allow evsieve to execute other programs.", effect)).into());
+        }
+        let uses_notify = args.iter().any(|arg| match arg {
+            Argument::HookArg(hook_arg) => hook_arg.notify.is_some(),
+            _ => false,
+        });
+        if uses_notify {
+            return Err(ArgumentError::new("The notify clause cannot be used together with --sandbox=strict, because the sandbox does not allow evsieve to contact the notification daemon.").into());
+        }
+    }
+    let mut sandbox_policy = SandboxPolicy::new(sandbox_mode);
+    let mut run_as: Option<RunAs> = None;
+    let mut stats_interval: Option<Duration> = None;
+    let mut status_file: Option<PreStatusFile> = None;
+    let mut journal_path: Option<PathBuf> = None;
+    let mut batch_latency: Option<Duration> = None;
+    let mut realtime = RealtimePolicy::default();
+    let mut safe_mode = false;
+    let mut summary_format: Option<SummaryFormat> = None;
+    let mut introspect_sockets: Vec<PreIntrospectSocket> = Vec::new();
+    let mut shadows: Vec<PreShadow> = Vec::new();
+
+    // Find out how many macro slots hooks can refer to.
+    let mut macro_slot_args = args.iter().filter_map(|arg| match arg {
+        Argument::MacroSlotArg(macro_slot_arg) => Some(macro_slot_arg),
+        _ => None,
+    });
+    let num_macro_slots: Option<usize> = macro_slot_args.next().map(|macro_slot_arg| macro_slot_arg.num_slots);
+    if macro_slot_args.next().is_some() {
+        return Err(ArgumentError::new("At most one --macro-slot argument can be specified. Use its slots= clause to define multiple slots.").into());
+    }
+
     // Reserve output device domains ahead of time, and for each --capability argument, declare which
     // output device follows said argument.
     let mut last_output_device = None;
@@ -306,6 +919,15 @@ pub fn process(args_str: Vec<String>)
         match arg {
             Argument::HookArg(hook_arg) => consecutive_hooks.push(hook_arg),
             Argument::WithholdArg(withhold_arg) => {
+                if consecutive_hooks.iter().any(|hook_arg| hook_arg.enabled_when.is_some()) {
+                    return Err(ArgumentError::new("The enabled-when clause cannot be used on a --hook that is followed by a --withhold argument.").into());
+                }
+                if consecutive_hooks.iter().any(|hook_arg| hook_arg.stage.is_some()) {
+                    return Err(ArgumentError::new("The stage clause cannot be used on a --hook that is followed by a --withhold argument.").into());
+                }
+                if consecutive_hooks.iter().any(|hook_arg| hook_arg.event_dispatcher.burst_interval.is_some()) {
+                    return Err(ArgumentError::new("The burst-interval= clause cannot be used on a --hook that is followed by a --withhold argument.").into());
+                }
                 withhold_arg.associate_hooks(&mut consecutive_hooks)
                     .with_context("While linking the --withhold arguments to their preceding hooks:")?;
                 consecutive_hooks.clear();
@@ -314,21 +936,86 @@ pub fn process(args_str: Vec<String>)
         }
     }
 
+    // Each --default block lets through the events that the arguments after it act upon.
+    let mut default_allowed_keys: VecDeque<Vec<Key>> = VecDeque::new();
+    for (index, arg) in args.iter().enumerate() {
+        if let Argument::DefaultArg(_) = arg {
+            let mut allowed_keys: Vec<Key> = Vec::new();
+            for later_arg in &args[index+1 ..] {
+                match later_arg {
+                    Argument::MapArg(map_arg) => {
+                        allowed_keys.extend(map_arg.input_keys.iter().cloned());
+                        if let Some((resync_input_keys, _)) = &map_arg.resync_keys {
+                            allowed_keys.extend(resync_input_keys.iter().cloned());
+                        }
+                    },
+                    Argument::HookArg(hook_arg) => {
+                        allowed_keys.extend(hook_arg.keys_and_str.iter().map(|(key, _)| key.clone()));
+                    },
+                    Argument::ToggleArg(toggle_arg) => allowed_keys.push(toggle_arg.input_key.clone()),
+                    _ => (),
+                }
+            }
+            default_allowed_keys.push_back(allowed_keys);
+        }
+    }
+
     // Keep track of the real paths for the input devices we've opened so we don't open the same
     // one twice.
     let mut input_device_real_paths: HashSet<PathBuf> = HashSet::new();
 
     // Construct the stream.
     for (arg, domain_assignment) in args.into_iter().zip(output_device_assignment) {
+        // All entries that get added to the stream by an argument with an enabled-when or stage clause get gated.
+        let enabled_when = arg.enabled_when().cloned();
+        let stage = arg.stage().cloned();
+        let first_new_entry = stream.len();
+
         match arg {
             Argument::InputDevice(device) => {
-                for path_str in &device.paths {
-                    let path: PathBuf = path_str.into();
+                // Files to replay are read when the input devices are opened. Whether they exist is only
+                // checked then, because they are not devices that may show up later.
+                if let Some(speed) = device.replay {
+                    for path_str in &device.paths {
+                        let source_domain = domain::get_unique_domain();
+                        let target_domain = match &device.domain {
+                            Some(value) => *value,
+                            None => domain::resolve(path_str)?,
+                        };
+                        if device.auto_output && ! auto_output_domains.contains(&target_domain) {
+                            auto_output_domains.push(target_domain);
+                        }
+                        replay_devices.push(PreReplayDevice {
+                            path: path_str.into(), domain: source_domain, target_domain, speed, time_scale: device.time_scale,
+                            packets: device.packets,
+                        });
+                        stream.push(StreamEntry::Map(
+                            Map::domain_shift(
+                                source_domain, Namespace::Input,
+                                target_domain, Namespace::User,
+                            )
+                        ));
+                    }
+                    continue;
+                }
+
+                // Devices opened from inherited file descriptors are referred to by their /dev/fd/ path,
+                // which also lets us find out if the same device was opened by both a path and an fd.
+                let sources = device.paths.iter().map(|path_str| (path_str.clone(), None))
+                    .chain(device.fds.iter().map(|&fd| (format!("/dev/fd/{}", fd), Some(fd))));
+                for (path_str, inherited_fd) in sources {
+                    let path: PathBuf = (&path_str).into();
                     let real_path = match std::fs::canonicalize(&path) {
                         Ok(real_path) => real_path,
                         Err(_) => match device.persist_mode {
+                            // Another member of the fallback group may be available. Whether one is, is
+                            // checked when the devices are opened.
+                            PersistMode::Reopen if device.fallback_group.is_some() => path.clone(),
                             PersistMode::None | PersistMode::Exit | PersistMode::Reopen => {
-                                return Err(ArgumentError::new(format!("The input device \"{}\" does not exist.", path_str)).into());
+                                return Err(ArgumentError::new(match inherited_fd {
+                                    Some(fd) => format!("The file descriptor {} is not open.", fd),
+                                    None => format!("The input device \"{}\" does not exist.", path_str),
+                                }).with_category(ErrorCategory::DeviceUnavailable).into());
                             },
                             // TODO (Medium Priority): this does allow the user to open the same input device twice.
                             PersistMode::Full => path.clone(),
@@ -346,16 +1033,35 @@ pub fn process(args_str: Vec<String>)
                     let source_domain = domain::get_unique_domain();
                     let target_domain = match &device.domain {
                         Some(value) => *value,
-                        None => domain::resolve(path_str)?,
+                        None => domain::resolve(&path_str)?,
+                    };
+
+                    let resync_domain = match device.resync_mode {
+                        ResyncMode::Pass => None,
+                        ResyncMode::Isolate => Some(domain::get_unique_domain()),
                     };
 
                     let persist_state = device.persist_mode.to_state_for_device(&path)?;
                     let input_device = PreInputDevice {
                         path, domain: source_domain,
                         grab_mode: device.grab_mode,
-                        persist_state,
+                        persist_state, resync_domain, inherited_fd,
+                        target_domain,
+                        repeat_setting: device.repeat_setting,
+                        stale_setting: device.stale_setting,
+                        read_filter: device.read_filter.clone(),
+                        open_mode: device.open_mode,
+                        observe_only: device.observe_only,
+                        fallback: device.fallback_group.clone().map(|group| FallbackMembership {
+                            group, priority: device.priority, order: input_devices.len(),
+                        }),
+                        packets: device.packets,
                     };
 
+                    if device.auto_output && ! auto_output_domains.contains(&target_domain) {
+                        auto_output_domains.push(target_domain);
+                    }
+
                     // Register this device for later creation.
                     input_devices.push(input_device);
                     // Create a map to put those events into the stream at the right time.
@@ -365,6 +1071,14 @@ pub fn process(args_str: Vec<String>)
                             target_domain, Namespace::User,
                         )
                     ));
+                    if let Some(resync_domain) = resync_domain {
+                        stream.push(StreamEntry::Map(
+                            Map::domain_shift(
+                                resync_domain, Namespace::Input,
+                                target_domain, Namespace::Resync,
+                            )
+                        ));
+                    }
                 }
             },
             Argument::OutputDevice(device) => {
@@ -379,16 +1093,32 @@ pub fn process(args_str: Vec<String>)
                     create_link: device.create_link,
                     repeat_mode: device.repeat_mode,
                     properties: device.properties,
+                    uinput_fd: device.uinput_fd,
+                    strict_caps: device.strict_caps,
+                    mirror: None,
+                    overflow: device.overflow,
                 };
+                if let Some(mirror_of) = device.mirror_of {
+                    mirrors.push((output_devices.len(), mirror_of, device.mirror_name));
+                }
                 output_devices.push(output_device);
                 
-                // Map the keys to this output device.
+                // Map the keys to this output device. If the events should be copied, they also
+                // remain in the stream for the next output devices.
+                let mut output_keys = vec![Key::from_domain_and_namespace(target_domain, Namespace::Output)];
+                if device.copy {
+                    output_keys.insert(0, Key::copy());
+                }
                 for key in device.keys {
-                    let map = Map::new(
-                        key,
-                        vec![Key::from_domain_and_namespace(target_domain, Namespace::Output)],
-                    );
-                    stream.push(StreamEntry::Map(map));
+                    stream.push(StreamEntry::Map(Map::new(key, output_keys.clone())));
+                }
+                if let Some((width, height)) = device.abs_pointer {
+                    stream.push(StreamEntry::AbsPointer(AbsPointer::new(target_domain, width, height)?));
+                }
+                if device.full_keyboard {
+                    stream.push(StreamEntry::CapabilityOverride(CapabilityOverride::new(
+                        target_domain, full_keyboard_capabilities()
+                    )));
                 }
             },
             Argument::CapabilityArg(capability) => {
@@ -400,8 +1130,16 @@ pub fn process(args_str: Vec<String>)
                 }
             },
             Argument::MapArg(map_arg) => {
-                let map = Map::new(map_arg.input_key, map_arg.output_keys);
+                let map = Map::any_of(map_arg.input_keys, map_arg.output_keys);
+                let map = match map_arg.sampling {
+                    Some(sampling) => map.with_sampling(sampling),
+                    None => map,
+                };
                 stream.push(StreamEntry::Map(map));
+                // Resync events are never sampled: they exist to bring the output in line with the current state.
+                if let Some((resync_input_keys, resync_output_keys)) = map_arg.resync_keys {
+                    stream.push(StreamEntry::Map(Map::any_of(resync_input_keys, resync_output_keys)));
+                }
             },
             Argument::BlockArg(block_arg) => {
                 for key in block_arg.keys {
@@ -416,10 +1154,49 @@ pub fn process(args_str: Vec<String>)
                     actuator.add_command("/bin/sh".to_owned(), vec!["-c".to_owned(), exec_shell]);
                 }
 
+                for effect in &hook_arg.effects {
+                    actuator.add_builtin_effect(effect)?;
+                }
+
+                for send_signal in hook_arg.send_signals {
+                    sandbox_policy.allow_read(send_signal.required_path());
+                    actuator.add_effect(send_signal.into_effect());
+                }
+
                 for effect in hook_arg.toggle_action.implement(&state, &toggle_indices)? {
                     actuator.add_effect(effect);
                 }
 
+                // Added after the toggle effects so the notification shows the new state.
+                if let Some(notify) = hook_arg.notify {
+                    actuator.add_effect(notify.into_effect(&toggle_indices, &toggle_names)?);
+                }
+
+                for command in hook_arg.macro_commands {
+                    let slot = match command {
+                        MacroCommand::StartRecording(slot) | MacroCommand::Play(slot) => Some(slot),
+                        MacroCommand::StopRecording => None,
+                    };
+                    match (num_macro_slots, slot) {
+                        (None, _) => return Err(ArgumentError::new(
+                            "A --hook cannot use the record-start, record-stop or play clauses unless a --macro-slot argument is specified."
+                        ).into()),
+                        (Some(num_slots), Some(slot)) if slot >= num_slots => return Err(ArgumentError::new(format!(
+                            "The macro slot {} does not exist: only {} macro slots have been defined.", slot + 1, num_slots
+                        )).into()),
+                        _ => (),
+                    }
+                    actuator.add_effect(Box::new(move |state: &mut State| state.push_macro_command(command)));
+                }
+
+                for command in hook_arg.repeat_commands {
+                    actuator.add_effect(Box::new(move |state: &mut State| state.push_repeat_command(command)));
+                }
+
+                for domain in hook_arg.recenter_commands {
+                    actuator.add_effect(Box::new(move |state: &mut State| state.push_recenter_command(domain)));
+                }
+
                 let hook = Hook::new(trigger, actuator);
                 
                 stream.push(StreamEntry::Hook(hook));
@@ -440,7 +1217,7 @@ pub fn process(args_str: Vec<String>)
                     }
                 }
 
-                let hook_group = HookGroup::new(preceding_hooks, Withhold::new(withhold_arg.keys));
+                let hook_group = HookGroup::new(preceding_hooks, Withhold::new(withhold_arg.keys, withhold_arg.lost_mode, withhold_arg.passthrough_keys, withhold_arg.debug));
                 stream.push(StreamEntry::HookGroup(hook_group));
             },
             Argument::RelToAbsArg(rel_to_abs_arg) => {
@@ -452,10 +1229,70 @@ pub fn process(args_str: Vec<String>)
                     None => None,
                 };
                 let toggle = Toggle::new(toggle_arg.input_key, toggle_arg.output_keys, toggle_arg.mode, &mut state, index)?;
+                if let Some(leader_id) = &toggle_arg.follows {
+                    let leader_index = *toggle_indices.get(leader_id).ok_or_else(|| {
+                        ArgumentError::new(format!("No toggle with the id \"{}\" exists.", leader_id))
+                    })?;
+                    // By default, the states of both toggles correspond one-to-one, wrapping around if
+                    // this toggle has fewer states.
+                    let follow_states = toggle_arg.follow_states.unwrap_or_else(|| {
+                        (0 .. state[leader_index].size()).map(|leader_state| leader_state % state[toggle.state_index].size()).collect()
+                    });
+                    state.add_follower(leader_index, toggle.state_index, follow_states)?;
+                }
                 stream.push(StreamEntry::Toggle(toggle));
             },
             Argument::PrintArg(print_arg) => {
-                stream.push(StreamEntry::Print(print_arg.compile()));
+                if let Some(path) = &print_arg.file {
+                    // The file may need to be reopened or created later.
+                    if let Some(parent) = path.parent() {
+                        sandbox_policy.allow_write(parent);
+                    }
+                }
+                stream.push(StreamEntry::Print(print_arg.compile()?));
+            },
+            Argument::HistogramArg(histogram_arg) => {
+                stream.push(StreamEntry::Histogram(histogram_arg.compile()));
+            },
+            Argument::SequenceArg(sequence_arg) => {
+                stream.push(StreamEntry::Sequence(sequence_arg.compile(&state, &toggle_indices)?));
+            },
+            Argument::EdgeArg(edge_arg) => {
+                stream.push(StreamEntry::Edge(edge_arg.compile()));
+            },
+            Argument::PressDurationArg(press_duration_arg) => {
+                stream.push(StreamEntry::PressDuration(press_duration_arg.compile()));
+            },
+            Argument::MirrorArg(mirror_arg) => {
+                stream.push(StreamEntry::HandMirror(mirror_arg.compile()));
+            },
+            Argument::RemapModifiersArg(remap_modifiers_arg) => {
+                stream.push(StreamEntry::RemapModifiers(remap_modifiers_arg.compile()));
+            },
+            Argument::PressureClickArg(pressure_click_arg) => {
+                stream.push(StreamEntry::PressureClick(pressure_click_arg.compile()));
+            },
+            Argument::PluginArg(plugin_arg) => {
+                stream.push(StreamEntry::Plugin(plugin_arg.compile()?));
+            },
+            Argument::BypassArg(bypass_arg) => {
+                // Added to the start of the stream after all output devices are known.
+                bypass_args.push(bypass_arg);
+            },
+            Argument::StageArg(stage_arg) => {
+                if stage_names.contains(&stage_arg.name) {
+                    return Err(ArgumentError::new(format!(
+                        "A stage named \"{}\" has already been declared.", stage_arg.name
+                    )).into());
+                }
+                stream.push(StreamEntry::Stage(stage_names.len()));
+                stage_names.push(stage_arg.name);
+            },
+            Argument::OnVtArg(on_vt_arg) => {
+                stream.push(StreamEntry::VtSwitch(on_vt_arg.compile(&state, &toggle_indices)?));
+            },
+            Argument::ScheduleArg(schedule_arg) => {
+                stream.push(StreamEntry::Schedule(schedule_arg.compile(&state, &toggle_indices)?));
             },
             Argument::MergeArg(merge_arg) => {
                 stream.push(StreamEntry::Merge(merge_arg.compile()));
@@ -466,37 +1303,361 @@ pub fn process(args_str: Vec<String>)
             Argument::ScaleArg(scale_arg) => {
                 stream.push(StreamEntry::Scale(scale_arg.compile()));
             },
+            Argument::CurveArg(curve_arg) => {
+                stream.push(StreamEntry::Curve(curve_arg.compile()));
+            },
+            Argument::MacroSlotArg(macro_slot_arg) => {
+                if let Some(path) = &macro_slot_arg.persist_path {
+                    if let Some(parent) = path.parent() {
+                        sandbox_policy.allow_write(parent);
+                    }
+                }
+                stream.push(StreamEntry::MacroSlots(macro_slot_arg.compile()?));
+            },
+            Argument::GyroToRelArg(gyro_to_rel_arg) => {
+                stream.push(StreamEntry::GyroToRel(gyro_to_rel_arg.compile()));
+            },
+            Argument::MouseKeysArg(mouse_keys_arg) => {
+                stream.push(StreamEntry::MouseKeys(mouse_keys_arg.compile()?));
+            },
+            Argument::SlowKeysArg(slow_keys_arg) => {
+                stream.push(StreamEntry::SlowKeys(slow_keys_arg.compile()));
+            },
+            Argument::BounceKeysArg(bounce_keys_arg) => {
+                stream.push(StreamEntry::BounceKeys(bounce_keys_arg.compile()));
+            },
+            Argument::MaxHoldArg(max_hold_arg) => {
+                stream.push(StreamEntry::MaxHold(max_hold_arg.compile()));
+            },
+            Argument::DefaultArg(default_arg) => {
+                let allowed_keys = default_allowed_keys.pop_front()
+                    .expect("Internal error: failed to find the allowed keys of a --default argument. This is a bug.");
+                stream.push(StreamEntry::DefaultBlock(default_arg.compile(allowed_keys)));
+            },
+            Argument::InhibitWhileTypingArg(inhibit_while_typing_arg) => {
+                stream.push(StreamEntry::InhibitWhileTyping(inhibit_while_typing_arg.compile()));
+            },
+            Argument::QuirkArg(quirk_arg) => {
+                stream.push(StreamEntry::Quirk(quirk_arg.compile()));
+            },
+            Argument::SandboxArg(_) => {
+                // Already handled above.
+            },
+            Argument::RunAsArg(run_as_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                if run_as.is_some() {
+                    return Err(ArgumentError::new("The --run-as argument can only be specified once.").into());
+                }
+                run_as = Some(run_as_arg.run_as);
+            },
+            Argument::InvertScrollArg(invert_scroll_arg) => {
+                let index = match &invert_scroll_arg.id {
+                    Some(id) => toggle_indices[id],
+                    None => state.create_toggle_with_size(2)?,
+                };
+                stream.push(StreamEntry::InvertScroll(invert_scroll_arg.compile(index)));
+            },
             Argument::ControlFifoArg(control_fifo) => {
-                control_fifo_paths.extend(control_fifo.paths);
+                let ControlFifoArg { paths, mode, owner, allowed_commands } = control_fifo;
+                control_fifos.extend(paths.into_iter().map(|path| PreControlFifo {
+                    path, mode, owner, allowed_commands: allowed_commands.clone(),
+                }));
+            },
+            Argument::StatsArg(stats_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                if stats_interval.is_some() {
+                    return Err(ArgumentError::new("The --stats argument can only be specified once.").into());
+                }
+                stats_interval = Some(stats_arg.interval);
+            },
+            Argument::StatusFileArg(status_file_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                if status_file.is_some() {
+                    return Err(ArgumentError::new("The --status-file argument can only be specified once.").into());
+                }
+                // The status file is replaced by renaming another file in the same directory over it.
+                let path = PathBuf::from(status_file_arg.path);
+                if let Some(directory) = path.parent() {
+                    sandbox_policy.allow_write(directory);
+                }
+                status_file = Some(PreStatusFile { path, interval: status_file_arg.interval });
+            },
+            Argument::BatchArg(batch_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                if batch_latency.is_some() {
+                    return Err(ArgumentError::new("The --batch argument can only be specified once.").into());
+                }
+                batch_latency = Some(batch_arg.max_latency);
+            },
+            Argument::RealtimeArg(realtime_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                if realtime.priority.is_some() {
+                    return Err(ArgumentError::new("The --realtime argument can only be specified once.").into());
+                }
+                realtime.priority = Some(realtime_arg.priority);
+            },
+            Argument::LockMemoryArg(_) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                realtime.lock_memory = true;
             },
+            Argument::SafeModeArg(_) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                safe_mode = true;
+            },
+            Argument::SummarizeArg(summarize_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                if summary_format.is_some() {
+                    return Err(ArgumentError::new("The --summarize argument can only be specified once.").into());
+                }
+                summary_format = Some(summarize_arg.format);
+            },
+            Argument::IntrospectSocketArg(introspect_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new(GLOBAL_ARGUMENT_IN_PIPELINE_ERROR).into());
+                }
+                let IntrospectSocketArg { paths, mode, owner } = introspect_arg;
+                introspect_sockets.extend(paths.into_iter().map(|path| PreIntrospectSocket { path, mode, owner }));
+            },
+            Argument::ShadowArg(shadow_arg) => {
+                if is_pipeline {
+                    return Err(ArgumentError::new("The --shadow argument can only be specified on the command line, not in pipelines.").into());
+                }
+                let ShadowArg { paths, domains } = shadow_arg;
+                shadows.extend(paths.into_iter().map(|path| PreShadow { path, domains: domains.clone() }));
+            },
+            Argument::RecoverArg(recover_arg) => {
+                if journal_path.is_some() {
+                    return Err(ArgumentError::new("The --recover argument can only be specified once.").into());
+                }
+                if let Some(parent) = recover_arg.path.parent() {
+                    sandbox_policy.allow_write(parent);
+                }
+                journal_path = Some(recover_arg.path);
+            },
+        }
+
+        if let Some(enabled_when) = enabled_when {
+            let new_entries: Vec<StreamEntry> = stream.drain(first_new_entry ..).collect();
+            for entry in new_entries {
+                stream.push(enabled_when.compile(entry, &state, &toggle_indices)?);
+            }
+        }
+        if let Some(stage) = stage {
+            let new_entries: Vec<StreamEntry> = stream.drain(first_new_entry ..).collect();
+            for entry in new_entries {
+                stream.push(stage.compile(entry, &stage_names)?);
+            }
+        }
+    }
+
+    // The --bypass arguments refer to the output devices by their position among the --output arguments,
+    // so this must happen before the output devices of the auto-output flag are added.
+    let bypasses = bypass_args.into_iter().map(|bypass_arg| {
+        match output_devices.get(bypass_arg.output_index - 1) {
+            Some(output_device) => Ok(StreamEntry::Bypass(Bypass::new(bypass_arg.keys, output_device.domain))),
+            None => Err(ArgumentError::new(format!(
+                "The output={} clause of a --bypass argument refers to an output device that does not exist.", bypass_arg.output_index
+            ))),
+        }
+    }).collect::<Result<Vec<StreamEntry>, ArgumentError>>()?;
+    stream.splice(0 .. 0, bypasses);
+
+    // Each input device with the auto-output flag gets an output device at the end of the stream, which
+    // receives all events of that input device that have not been written to any other output device.
+    for source_domain in auto_output_domains {
+        let target_domain = domain::get_unique_domain();
+        output_devices.push(PreOutputDevice {
+            domain: target_domain,
+            create_link: None,
+            repeat_mode: RepeatMode::Passive,
+            properties: DeviceProperties {
+                name: DEFAULT_NAME.to_owned(),
+                device_id: None, version: None, bus: None,
+                input_props: Vec::new(),
+            },
+            uinput_fd: None,
+            strict_caps: false,
+            mirror: None,
+            overflow: OverflowSetting::default(),
+        });
+        for &namespace in &[Namespace::User, Namespace::Yielded, Namespace::Resync] {
+            stream.push(StreamEntry::Map(Map::new(
+                Key::from_domain_and_namespace(source_domain, namespace),
+                vec![Key::from_domain_and_namespace(target_domain, Namespace::Output)],
+            )));
         }
     }
 
-    // Do sanity checks.
-    if ! are_unique(output_devices.iter().filter_map(|device| device.create_link.as_ref())) {
+    // The mirrored capabilities are computed from the capabilities of the input devices before their events
+    // enter the stream, so the mirrors must come first.
+    for (index, mirror_of, copy_name) in mirrors.into_iter().rev() {
+        let sources: Vec<Domain> = input_devices.iter()
+            .filter(|device| device.target_domain == mirror_of)
+            .map(|device| device.domain)
+            .collect();
+        if sources.is_empty() {
+            return Err(ArgumentError::new(format!(
+                "The mirror-of={} clause of an output device does not refer to the domain of any input device.",
+                domain::try_reverse_resolve(mirror_of).unwrap_or_default()
+            )).into());
+        }
+        let output_device = &mut output_devices[index];
+        stream.insert(0, StreamEntry::CapabilityMirror(CapabilityMirror::new(sources.clone(), output_device.domain)));
+        output_device.mirror = Some(MirrorSetting { sources, copy_name });
+    }
+
+    sandbox_policy.allow_devices(&input_devices, &output_devices);
+    for fifo in &control_fifos {
+        sandbox_policy.allow_write(&fifo.path);
+    }
+    for socket in &introspect_sockets {
+        sandbox_policy.allow_write(&socket.path);
+    }
+
+    Ok(PreImplementation {
+        stream, input_devices, replay_devices, output_devices, control_fifos, state, toggle_indices, stage_names, sandbox_policy,
+        run_as, stats_interval, status_file, journal_path, batch_latency, realtime, safe_mode, summary_format, introspect_sockets,
+        argument_groups: Vec::new(), shadows, shadow_sources: None,
+    })
+}
+
+const GLOBAL_ARGUMENT_IN_PIPELINE_ERROR: &str = "The --sandbox, --run-as, --stats, --status-file, --batch, --realtime, --lock-memory, --safe-mode, --summarize and --introspect-socket arguments affect the whole program. They can only be specified on the command line, not in pipelines.";
+
+/// Returns an error if the output devices or control FIFOs of some pipelines claim the same resources.
+fn check_unique_resources(pipelines: &[&PreImplementation]) -> Result<(), RuntimeError> {
+    let output_devices = || pipelines.iter().flat_map(|pipeline| pipeline.output_devices.iter());
+    if ! are_unique(output_devices().filter_map(|device| device.create_link.as_ref())) {
         return Err(ArgumentError::new("Multiple output devices cannot create a link at the same location.".to_owned()).into());
     }
-    if ! are_unique(control_fifo_paths.iter()) {
+    if ! are_unique(output_devices().filter_map(|device| device.uinput_fd)) {
+        return Err(ArgumentError::new("Multiple output devices cannot be created on the same file descriptor.".to_owned()).into());
+    }
+    // Each inherited file descriptor is closed by the device that takes ownership of it.
+    let input_fds = pipelines.iter().flat_map(|pipeline| pipeline.input_devices.iter())
+        .filter_map(|device| device.inherited_fd);
+    if ! are_unique(input_fds.chain(output_devices().filter_map(|device| device.uinput_fd))) {
+        return Err(ArgumentError::new("The same file descriptor cannot be used by multiple --input or --output arguments.".to_owned()).into());
+    }
+    if ! are_unique(pipelines.iter().flat_map(|pipeline| pipeline.control_fifos.iter().map(|fifo| &fifo.path))) {
         return Err(ArgumentError::new("A control fifo was specified twice at the same location.".to_owned()).into());
     }
-
-    Ok(PreImplementation { stream, input_devices, output_devices, control_fifo_paths, state, toggle_indices })
+    if ! are_unique(pipelines.iter().flat_map(|pipeline| {
+        pipeline.control_fifos.iter().map(|fifo| &fifo.path)
+            .chain(pipeline.introspect_sockets.iter().map(|socket| &socket.path))
+    })) {
+        return Err(ArgumentError::new("An introspection socket was specified twice, or at the same location as a control fifo.".to_owned()).into());
+    }
+    if ! are_unique(pipelines.iter().filter_map(|pipeline| pipeline.journal_path.as_ref())) {
+        return Err(ArgumentError::new("Multiple pipelines cannot use the same journal for --recover.".to_owned()).into());
+    }
+    Ok(())
 }
 
 /// This does the I/O that is necessary after the `process()` call.
 pub fn implement(pre_implementation: PreImplementation) -> Result<Implementation, RuntimeError> {
-    let PreImplementation { stream, input_devices, output_devices, control_fifo_paths, state, toggle_indices } = pre_implementation;
+    let PreImplementation {
+        stream, input_devices, replay_devices, mut output_devices, control_fifos: pre_control_fifos, state, toggle_indices, stage_names,
+        sandbox_policy, run_as, stats_interval, status_file, journal_path, batch_latency, realtime, safe_mode, summary_format: _,
+        introspect_sockets: pre_introspect_sockets, argument_groups, shadows: _, shadow_sources,
+    } = pre_implementation;
 
-    let control_fifos: Vec<ControlFifo> = control_fifo_paths.into_iter()
+    let packet_domains: Vec<Domain> = input_devices.iter().filter(|device| device.packets).map(|device| device.domain)
+        .chain(replay_devices.iter().filter(|device| device.packets).map(|device| device.domain))
+        .collect();
+
+    let control_fifos: Vec<ControlFifo> = pre_control_fifos.into_iter()
         .map(ControlFifo::create)
         .collect::<Result<Vec<ControlFifo>, SystemError>>()?;
+    let introspect_sockets: Vec<IntrospectSocket> = pre_introspect_sockets.into_iter()
+        .map(IntrospectSocket::create)
+        .collect::<Result<Vec<IntrospectSocket>, SystemError>>()?;
+
+    // Compute the capabilities of the output devices. The input devices are not grabbed yet: that only
+    // happens after the output devices of all pipelines have been created.
+    let (input_devices, blueprints, mut input_capabilities) = crate::io::input::open_and_query_capabilities(input_devices)?;
+    let replay_devices: Vec<ReplayDevice> = replay_devices.into_iter()
+        .map(ReplayDevice::open)
+        .collect::<Result<Vec<ReplayDevice>, SystemError>>()?;
+    for device in &replay_devices {
+        input_capabilities.insert(device.domain(), device.capabilities());
+    }
+    for output_device in &mut output_devices {
+        let sources = match &output_device.mirror {
+            Some(mirror) => &mirror.sources,
+            None => continue,
+        };
+        match input_devices.iter().find(|device| sources.contains(&device.domain())) {
+            Some(input_device) => output_device.adopt_identity(&input_device.identity()),
+            None => eprintln!("Warning: the input device mirrored by the mirror-of= clause of an output device is not available. The output device will be created without copying its name and IDs."),
+        }
+    }
+    let strict_caps_domains: Vec<Domain> = output_devices.iter()
+        .filter(|device| device.strict_caps)
+        .map(|device| device.domain)
+        .collect();
+    let output_capabilities = crate::stream::determine_output_capabilities(
+        &stream, &input_capabilities, &state, &strict_caps_domains
+    );
+    let output = UInputSystem::create(output_devices, output_capabilities, journal_path)?;
+    let mut setup = Setup::create(stream, output, state, toggle_indices, stage_names, input_capabilities, Box::new(MonotonicClock));
+    setup.set_packet_domains(packet_domains);
+    for device in &input_devices {
+        setup.update_leds(device);
+    }
+    Ok(Implementation {
+        setup, input_devices, replay_devices, blueprints, control_fifos, sandbox_policy, run_as, stats_interval, status_file,
+        batch_latency, realtime, safe_mode, introspect_sockets, argument_groups, shadow_sources,
+    })
+}
+
+/// Queries the capabilities of the input devices and returns a routing table that shows where their
+/// events end up, as requested by --summarize. The input devices are neither grabbed nor changed.
+pub fn summarize(pre_implementation: PreImplementation, format: SummaryFormat) -> Result<String, RuntimeError> {
+    let PreImplementation { stream, mut input_devices, replay_devices, output_devices, .. } = pre_implementation;
+
+    let mut input_names: Vec<(String, Domain)> = Vec::new();
+    for device in &mut input_devices {
+        device.grab_mode = GrabMode::None;
+        device.observe_only = true;
+        input_names.push((device.path.display().to_string(), device.domain));
+    }
+    for device in &replay_devices {
+        input_names.push((device.path.display().to_string(), device.domain));
+    }
+
+    let (_input_devices, _blueprints, mut input_capabilities) = crate::io::input::open_and_query_capabilities(input_devices)?;
+    for pre_device in replay_devices {
+        let device = ReplayDevice::open(pre_device)?;
+        input_capabilities.insert(device.domain(), device.capabilities());
+    }
+
+    let inputs: Vec<SummaryInput> = input_names.into_iter()
+        .filter_map(|(name, domain)| {
+            let capabilities = input_capabilities.remove(&domain)?;
+            Some(SummaryInput { name, domain, capabilities })
+        })
+        .collect();
+    let outputs: Vec<SummaryOutput> = output_devices.iter()
+        .map(|device| SummaryOutput { name: device.properties.name.clone(), domain: device.domain })
+        .collect();
 
-    // Compute the capabilities of the output devices.
-    let (input_devices, blueprints, input_capabilities) = crate::io::input::open_and_query_capabilities(input_devices)?;
-    let output_capabilities = crate::stream::determine_output_capabilities(&stream, &input_capabilities);
-    let output = UInputSystem::create(output_devices, output_capabilities)?;
-    let setup = Setup::create(stream, output, state, toggle_indices, input_capabilities);
-    Ok(Implementation { setup, input_devices, blueprints, control_fifos })
+    Ok(crate::summary::summarize(&stream, &inputs, &outputs, format))
 }
 
 /// Returns true if all items in the iterator are unique, otherwise returns false.
@@ -0,0 +1,22 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+
+/// Represents a --pipeline argument.
+pub(super) struct PipelineArg {
+    pub paths: Vec<String>,
+}
+
+impl PipelineArg {
+	pub fn parse(args: Vec<String>) -> Result<PipelineArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            true,
+            false,
+        )?;
+
+        Ok(PipelineArg { paths: arg_group.paths })
+    }
+}
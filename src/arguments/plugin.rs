@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::{ArgumentError, RuntimeError};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::stream::plugin::Plugin;
+
+/// Represents a --plugin argument.
+pub(super) struct PluginArg {
+    pub path: String,
+    /// Handed to the init function of the plugin. Empty if no config clause was specified.
+    pub config: String,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl PluginArg {
+    pub fn parse(args: Vec<String>) -> Result<PluginArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["config", "enabled-when", "stage"],
+            true,
+            false,
+        )?;
+
+        let path = match arg_group.require_paths()?.as_slice() {
+            [path] => path.clone(),
+            _ => return Err(ArgumentError::new("The --plugin argument accepts only a single path.")),
+        };
+        let config = arg_group.get_unique_clause("config")?.unwrap_or_default();
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(PluginArg { path, config, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> Result<Plugin, RuntimeError> {
+        Plugin::load(&self.path, &self.config)
+    }
+}
@@ -0,0 +1,72 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::{EventType, Namespace};
+use crate::key::{Key, KeyParser};
+use crate::range::Interval;
+use crate::stream::press_duration::PressDuration;
+
+/// Represents a --press-duration argument.
+pub(super) struct PressDurationArg {
+    pub keys: Vec<Key>,
+    pub send_key: Key,
+    pub range: Interval,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl PressDurationArg {
+	pub fn parse(args: Vec<String>) -> Result<PressDurationArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["send", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser {
+            allow_values: false,
+            allow_transitions: false,
+            allow_crossings: false,
+            type_whitelist: Some(vec![EventType::KEY]),
+            namespace: Namespace::User,
+            ..KeyParser::default_filter()
+        }.parse_all(&arg_group.require_keys()?)?;
+
+        let send_key_str = arg_group.require_unique_clause("send")?;
+        let send_key = KeyParser {
+            default_value: "",
+            allow_values: true,
+            allow_transitions: false,
+            allow_crossings: false,
+            allow_domains: true,
+            allow_ranges: true,
+            allow_types: false,
+            allow_relative_values: false,
+            allow_annotations: true,
+            type_whitelist: None,
+            namespace: Namespace::User,
+        }.parse(&send_key_str)?;
+
+        let (send_key, range) = match send_key.split_value() {
+            (send_key, Some(range)) => (send_key, range),
+            (_, None) => return Err(ArgumentError::new(format!(
+                "The send= clause of a --press-duration argument needs a range for the durations in milliseconds, e.g. \"send={}:0~1000\".", send_key_str
+            ))),
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(PressDurationArg { keys, send_key, range, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> PressDuration {
+        PressDuration::new(self.keys, self.send_key, self.range)
+    }
+}
@@ -0,0 +1,106 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::{EventType, Namespace};
+use crate::key::{Key, KeyParser};
+use crate::range::Interval;
+use crate::stream::pressure_click::PressureClick;
+use crate::utils;
+
+/// The axis that gets turned into clicks if no keys are specified.
+const DEFAULT_AXIS: &str = "abs:pressure";
+/// Suitable for touchpads whose pressure ranges from 0 to 255, which is the most common range.
+const DEFAULT_THRESHOLDS: Interval = Interval { min: 25, max: 30 };
+
+/// Represents a --pressure-click argument.
+pub(super) struct PressureClickArg {
+    pub keys: Vec<Key>,
+    pub button: Key,
+    /// The button is pressed at `thresholds.max` and released below `thresholds.min`.
+    pub thresholds: Interval,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl PressureClickArg {
+    pub fn parse(args: Vec<String>) -> Result<PressureClickArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["threshold", "button", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys_str = match arg_group.keys.is_empty() {
+            true => vec![DEFAULT_AXIS.to_owned()],
+            false => arg_group.keys.clone(),
+        };
+        let keys = KeyParser {
+            allow_values: false,
+            allow_transitions: false,
+            allow_crossings: false,
+            type_whitelist: Some(vec![EventType::ABS]),
+            namespace: Namespace::User,
+            ..KeyParser::default_filter()
+        }.parse_all(&keys_str)?;
+
+        let button = KeyParser {
+            allow_values: false,
+            allow_transitions: false,
+            allow_crossings: false,
+            allow_types: false,
+            type_whitelist: Some(vec![EventType::KEY]),
+            namespace: Namespace::User,
+            ..KeyParser::default_filter()
+        }.parse(&arg_group.get_unique_clause("button")?.unwrap_or_else(|| "btn:left".to_owned()))?;
+
+        let thresholds = match arg_group.get_unique_clause("threshold")? {
+            Some(threshold_str) => parse_thresholds(&threshold_str)?,
+            None => DEFAULT_THRESHOLDS,
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(PressureClickArg { keys, button, thresholds, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> PressureClick {
+        PressureClick::new(self.keys, self.button, self.thresholds)
+    }
+}
+
+/// Parses a threshold like "30" or "25~30", where the latter means that the button gets pressed at
+/// 30 and released below 25.
+fn parse_thresholds(threshold_str: &str) -> Result<Interval, ArgumentError> {
+    let parse_value = |value_str: &str| value_str.parse::<i32>().map_err(|_| ArgumentError::new(format!(
+        "Cannot interpret {} as an integer.", value_str
+    )));
+    let (release_str, press_str_opt) = utils::split_once(threshold_str, "~");
+    let release = parse_value(release_str)?;
+    let press = match press_str_opt {
+        Some(press_str) => parse_value(press_str)?,
+        None => release,
+    };
+    if release > press {
+        return Err(ArgumentError::new(format!(
+            "The threshold {} is invalid: the release threshold must not be higher than the press threshold.", threshold_str
+        )));
+    }
+    Ok(Interval::new(release, press))
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_thresholds("30").unwrap(), Interval::new(30, 30));
+    assert_eq!(parse_thresholds("25~30").unwrap(), Interval::new(25, 30));
+    assert_eq!(parse_thresholds("-5~5").unwrap(), Interval::new(-5, 5));
+    parse_thresholds("30~25").unwrap_err();
+    parse_thresholds("a").unwrap_err();
+    parse_thresholds("25~").unwrap_err();
+}
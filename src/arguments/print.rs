@@ -1,21 +1,32 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::error::ArgumentError;
+use std::path::PathBuf;
+
+use crate::error::{ArgumentError, SystemError};
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::io::serialization;
 use crate::key::{Key, KeyParser};
-use crate::stream::print::{EventPrinter, EventPrintMode};
+use crate::stream::print::{EventPrinter, EventPrintMode, FileTarget, PrintTarget};
 
 /// Represents a --print argument.
 pub(super) struct PrintArg {
     pub keys: Vec<Key>,
     pub mode: EventPrintMode,
+    /// If some, the events are written to this file instead of stdout.
+    pub file: Option<PathBuf>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl PrintArg {
 	pub fn parse(args: Vec<String>) -> Result<PrintArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["format"],
+            &["format", "file", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -26,15 +37,34 @@ impl PrintArg {
             Some(value) => match value.as_str() {
                 "direct" => EventPrintMode::Direct,
                 "default" => EventPrintMode::Detailed,
+                "binary" => EventPrintMode::Binary,
                 other => return Err(ArgumentError::new(format!("Invalid --print format: {}", other))),
             } ,
             None => EventPrintMode::Detailed,
         };
 
-        Ok(PrintArg { keys, mode })
+        let file = arg_group.get_unique_clause("file")?.map(PathBuf::from);
+        if matches!(mode, EventPrintMode::Binary) && file.is_none() {
+            return Err(ArgumentError::new("The --print format=binary clause requires a file= clause to be specified as well."));
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(PrintArg { keys, mode, file, enabled_when, stage })
     }
 
-    pub fn compile(self) -> EventPrinter {
-        EventPrinter::new(self.keys, self.mode)
+    pub fn compile(self) -> Result<EventPrinter, SystemError> {
+        let target = match self.file {
+            Some(path) => {
+                let preamble = match self.mode {
+                    EventPrintMode::Binary => serialization::encode_header(),
+                    EventPrintMode::Detailed | EventPrintMode::Direct => Vec::new(),
+                };
+                PrintTarget::File(FileTarget::new(path, preamble)?)
+            },
+            None => PrintTarget::Stdout,
+        };
+        Ok(EventPrinter::new(self.keys, self.mode, target))
     }
 }
\ No newline at end of file
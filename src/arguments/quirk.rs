@@ -0,0 +1,50 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::key::{Key, KeyParser};
+use crate::quirks::Quirk;
+use crate::stream::quirk::QuirkEntry;
+
+/// Represents a --quirk argument.
+pub(super) struct QuirkArg {
+    pub keys: Vec<Key>,
+    pub quirk: Box<dyn Quirk>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl QuirkArg {
+	pub fn parse(mut args: Vec<String>) -> Result<QuirkArg, ArgumentError> {
+        // The name of a quirk does not look like a key, flag or clause to a ComplexArgGroup, so we
+        // take it out before parsing the rest.
+        let name = match args.len() {
+            0 | 1 => None,
+            _ => Some(args.remove(1)),
+        };
+
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let name = name.ok_or_else(|| ArgumentError::new("The --quirk argument requires the name of a quirk."))?;
+        let quirk = crate::quirks::create(&name)?;
+        let keys = KeyParser::default_filter().parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(QuirkArg { keys, quirk, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> QuirkEntry {
+        QuirkEntry::new(self.keys, self.quirk)
+    }
+}
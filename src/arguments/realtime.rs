@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::realtime::DEFAULT_PRIORITY;
+use crate::utils::split_once;
+
+/// Represents a --realtime argument.
+pub(super) struct RealtimeArg {
+    pub priority: i32,
+}
+
+impl RealtimeArg {
+	pub fn parse(mut args: Vec<String>) -> Result<RealtimeArg, ArgumentError> {
+        // The priority is attached to the name of the argument, as in --realtime=20.
+        let (name, priority_str) = split_once(&args[0], "=");
+        let priority = match priority_str {
+            None => DEFAULT_PRIORITY,
+            Some(priority_str) => match priority_str.parse::<i32>() {
+                Ok(priority) if (1 ..= 99).contains(&priority) => priority,
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid realtime priority \"{}\". The priority must be an integer between 1 and 99.", priority_str
+                ))),
+            },
+        };
+        args[0] = name.to_owned();
+
+        ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            false,
+        )?;
+
+        Ok(RealtimeArg { priority })
+    }
+}
+
+/// Represents a --lock-memory argument.
+pub(super) struct LockMemoryArg {}
+
+impl LockMemoryArg {
+	pub fn parse(args: Vec<String>) -> Result<LockMemoryArg, ArgumentError> {
+        ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            false,
+        )?;
+        Ok(LockMemoryArg {})
+    }
+}
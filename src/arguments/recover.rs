@@ -0,0 +1,28 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use std::path::PathBuf;
+
+/// Represents a --recover argument.
+pub(super) struct RecoverArg {
+    /// The path of the journal that keeps track of the keys that are pressed on the output devices.
+    pub path: PathBuf,
+}
+
+impl RecoverArg {
+	pub fn parse(args: Vec<String>) -> Result<RecoverArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            true,
+            false,
+        )?;
+
+        match arg_group.paths.as_slice() {
+            [path] => Ok(RecoverArg { path: PathBuf::from(path) }),
+            [] => Err(ArgumentError::new("The --recover argument requires the path of a journal file.")),
+            _ => Err(ArgumentError::new("The --recover argument takes only a single path.")),
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::event::{EventCode, EventType};
+use crate::key::{Key, KeyParser};
+use crate::stream::remap_modifiers::RemapModifiers;
+
+/// Represents a --remap-modifiers argument.
+pub(super) struct RemapModifiersArg {
+    pub keys: Vec<Key>,
+    /// Maps every managed key to the key it gets sent as.
+    pub table: HashMap<EventCode, EventCode>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl RemapModifiersArg {
+    pub fn parse(args: Vec<String>) -> Result<RemapModifiersArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["swap", "remap", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let mut parser = KeyParser::default_filter();
+        parser.type_whitelist = Some(vec![EventType::KEY]);
+        let keys = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
+
+        let mut pairs: Vec<(EventCode, EventCode)> = Vec::new();
+        for swap_str in arg_group.get_clauses("swap") {
+            let (left, right) = parse_pair(&swap_str)?;
+            pairs.push((left, right));
+            pairs.push((right, left));
+        }
+        for remap_str in arg_group.get_clauses("remap") {
+            pairs.push(parse_pair(&remap_str)?);
+        }
+        let table = compile_table(&pairs)?;
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(RemapModifiersArg { keys, table, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> RemapModifiers {
+        RemapModifiers::new(self.keys, self.table)
+    }
+}
+
+/// Parses the value of a swap= or remap= clause, like "key:capslock,key:leftctrl".
+fn parse_pair(pair_str: &str) -> Result<(EventCode, EventCode), ArgumentError> {
+    let parse_code = |key_str: &str| -> Result<EventCode, ArgumentError> {
+        match crate::utils::split_once(key_str, ":") {
+            ("key", Some(code_name)) => crate::ecodes::event_code("key", code_name),
+            _ => Err(ArgumentError::new(format!(
+                "The swap= and remap= clauses of --remap-modifiers can only contain keys with a code, like key:leftctrl. Encountered: \"{}\"", key_str
+            ))),
+        }
+    };
+    match crate::utils::split_once(pair_str, ",") {
+        (left, Some(right)) if ! right.contains(',') => {
+            let left = parse_code(left)?;
+            let right = parse_code(right)?;
+            if left == right {
+                return Err(ArgumentError::new(format!("Cannot remap a key to itself: \"{}\"", pair_str)));
+            }
+            Ok((left, right))
+        },
+        _ => Err(ArgumentError::new(format!(
+            "The swap= and remap= clauses of --remap-modifiers must contain exactly two keys separated by a comma, like key:capslock,key:leftctrl. Encountered: \"{}\"", pair_str
+        ))),
+    }
+}
+
+/// Turns pairs of keys into a map from each key to the key it gets sent as. Keys that are only the
+/// target of a pair map to themselves.
+fn compile_table(pairs: &[(EventCode, EventCode)]) -> Result<HashMap<EventCode, EventCode>, ArgumentError> {
+    if pairs.is_empty() {
+        return Err(ArgumentError::new("The --remap-modifiers argument requires at least one swap= or remap= clause."));
+    }
+    let mut table = HashMap::new();
+    for &(source, target) in pairs {
+        if table.insert(source, target).is_some() {
+            return Err(ArgumentError::new(format!(
+                "The key {} gets remapped more than once by --remap-modifiers.", crate::ecodes::event_name(source)
+            )));
+        }
+    }
+    for &(_, target) in pairs {
+        table.entry(target).or_insert(target);
+    }
+    Ok(table)
+}
+
+#[test]
+fn unittest() {
+    let capslock = crate::ecodes::event_code("key", "capslock").unwrap();
+    let leftctrl = crate::ecodes::event_code("key", "leftctrl").unwrap();
+    let rightctrl = crate::ecodes::event_code("key", "rightctrl").unwrap();
+
+    assert_eq!(parse_pair("key:capslock,key:leftctrl").unwrap(), (capslock, leftctrl));
+    assert!(parse_pair("key:capslock").is_err());
+    assert!(parse_pair("key:capslock,key:leftctrl,key:rightctrl").is_err());
+    assert!(parse_pair("key:capslock,key:capslock").is_err());
+    assert!(parse_pair("key:capslock,btn:left").is_err());
+    assert!(parse_pair("key:capslock,key").is_err());
+
+    let table = compile_table(&[(capslock, leftctrl), (rightctrl, leftctrl)]).unwrap();
+    assert_eq!(table.len(), 3);
+    assert_eq!(table[&leftctrl], leftctrl);
+    assert!(compile_table(&[(capslock, leftctrl), (capslock, rightctrl)]).is_err());
+    assert!(compile_table(&[]).is_err());
+}
@@ -0,0 +1,24 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::privileges::RunAs;
+
+/// Represents a --run-as argument.
+pub(super) struct RunAsArg {
+    pub run_as: RunAs,
+}
+
+impl RunAsArg {
+	pub fn parse(args: Vec<String>) -> Result<RunAsArg, ArgumentError> {
+        // User and group names can look like keys or flags to a ComplexArgGroup, so we interpret the
+        // value ourselves.
+        let spec = match args.as_slice() {
+            [_, spec] => spec,
+            _ => return Err(ArgumentError::new("The --run-as argument requires exactly one USER or USER:GROUP value.")),
+        };
+
+        Ok(RunAsArg {
+            run_as: RunAs::parse(spec)?,
+        })
+    }
+}
@@ -0,0 +1,19 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+
+/// Represents a --safe-mode argument.
+pub(super) struct SafeModeArg {}
+
+impl SafeModeArg {
+	pub fn parse(args: Vec<String>) -> Result<SafeModeArg, ArgumentError> {
+        ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            false,
+        )?;
+        Ok(SafeModeArg {})
+    }
+}
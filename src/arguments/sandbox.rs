@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::sandbox::SandboxMode;
+use crate::utils::split_once;
+
+/// Represents a --sandbox argument.
+pub(super) struct SandboxArg {
+    pub mode: SandboxMode,
+}
+
+impl SandboxArg {
+	pub fn parse(mut args: Vec<String>) -> Result<SandboxArg, ArgumentError> {
+        // The mode is attached to the name of the argument, as in --sandbox=strict.
+        let (name, mode_str) = split_once(&args[0], "=");
+        let mode = match mode_str {
+            None | Some("strict") => SandboxMode::Strict,
+            Some("off") => SandboxMode::Off,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid sandbox mode \"{}\". The sandbox mode must be either \"strict\" or \"off\".", other
+            ))),
+        };
+        args[0] = name.to_owned();
+
+        ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            false,
+        )?;
+
+        Ok(SandboxArg { mode })
+    }
+}
@@ -6,6 +6,8 @@ use crate::key::{Key, KeyParser};
 use crate::stream::scale::Scale;
 
 use super::lib::ComplexArgGroup;
+use super::gate::EnabledWhenClause;
+use super::stage::StageClause;
 
 /// Represents a --scale argument.
 pub(super) struct ScaleArg {
@@ -15,13 +17,17 @@ pub(super) struct ScaleArg {
     // that users want to be exact such as x0.5, x0.25 and such can be represented as float, (3) using a custom Rational
     // type would also cause errors when a decimal number such as 0.33333333333333 gets converted to Rational.
     pub factor: f64,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl ScaleArg {
 	pub fn parse(args: Vec<String>) -> Result<ScaleArg, RuntimeError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["factor"],
+            &["factor", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -39,7 +45,10 @@ impl ScaleArg {
         let factor = crate::utils::parse_number(&factor_str)
             .ok_or_else(|| ArgumentError::new(format!("Cannot interpret the factor \"{}\" as a number.", factor_str)))?;
 
-        Ok(ScaleArg { input_keys, factor })
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(ScaleArg { input_keys, factor, enabled_when, stage })
     }
 
     pub fn compile(self) -> Scale {
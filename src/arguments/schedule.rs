@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::hook::HookToggleAction;
+use crate::state::{State, ToggleIndex};
+use crate::stream::schedule::{Schedule, TimeOfDay};
+
+/// Represents a --schedule argument.
+pub(super) struct ScheduleArg {
+    pub times: Vec<TimeOfDay>,
+    pub toggle_action: HookToggleAction,
+}
+
+impl ScheduleArg {
+	pub fn parse(args: Vec<String>) -> Result<ScheduleArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["toggle"],
+            &["at", "toggle"],
+            false,
+            false,
+        )?;
+
+        let times = arg_group.get_clauses("at").iter()
+            .map(|value| parse_time_of_day(value))
+            .collect::<Result<Vec<TimeOfDay>, ArgumentError>>()?;
+        if times.is_empty() {
+            return Err(ArgumentError::new("The --schedule argument requires at least one at= clause."));
+        }
+
+        let toggle_action = HookToggleAction::parse(arg_group.has_flag("toggle"), arg_group.get_clauses("toggle"))?;
+        if ! arg_group.has_flag("toggle") && arg_group.get_clauses("toggle").is_empty() {
+            return Err(ArgumentError::new("The --schedule argument requires a toggle flag or toggle= clause."));
+        }
+
+        Ok(ScheduleArg { times, toggle_action })
+    }
+
+    pub fn compile(self, state: &State, toggle_indices: &HashMap<String, ToggleIndex>) -> Result<Schedule, ArgumentError> {
+        let effects = self.toggle_action.implement(state, toggle_indices)?;
+        Ok(Schedule::new(self.times, effects))
+    }
+}
+
+/// Interprets a time like "22:00" or "07:30:15".
+fn parse_time_of_day(value: &str) -> Result<TimeOfDay, ArgumentError> {
+    let error = || ArgumentError::new(format!(
+        "Cannot interpret \"{}\" as a time of the day. Please use the format HH:MM or HH:MM:SS, e.g. \"at=22:00\".", value
+    ));
+    let parts = value.split(':')
+        .map(|part| match (part.len(), part.chars().all(|c| c.is_ascii_digit())) {
+            (1 ..= 2, true) => part.parse::<i32>().map_err(|_| error()),
+            _ => Err(error()),
+        })
+        .collect::<Result<Vec<i32>, ArgumentError>>()?;
+    let (hour, minute, second) = match *parts.as_slice() {
+        [hour, minute] => (hour, minute, 0),
+        [hour, minute, second] => (hour, minute, second),
+        _ => return Err(error()),
+    };
+    if !(0 ..= 23).contains(&hour) || !(0 ..= 59).contains(&minute) || !(0 ..= 59).contains(&second) {
+        return Err(error());
+    }
+    Ok(TimeOfDay { hour, minute, second })
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_time_of_day("22:00").unwrap(), TimeOfDay { hour: 22, minute: 0, second: 0 });
+    assert_eq!(parse_time_of_day("7:30:15").unwrap(), TimeOfDay { hour: 7, minute: 30, second: 15 });
+    assert!(parse_time_of_day("24:00").is_err());
+    assert!(parse_time_of_day("12").is_err());
+    assert!(parse_time_of_day("12:+5").is_err());
+    assert!(parse_time_of_day("12:60").is_err());
+}
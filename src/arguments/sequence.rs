@@ -0,0 +1,96 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::error::{ArgumentError, Context, RuntimeError};
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::arguments::hook::{HookToggleAction, parse_send_key_clause};
+use crate::event::{EventType, Namespace};
+use crate::key::{Key, KeyParser};
+use crate::state::{State, ToggleIndex};
+use crate::stream::hook::Effect;
+use crate::stream::sequence::Sequence;
+use crate::subprocess;
+use crate::time::Duration;
+
+const DEFAULT_TIMEOUT: Duration = Duration::from_millis(1000);
+
+/// Represents a --sequence argument.
+pub(super) struct SequenceArg {
+    pub keys: Vec<Key>,
+    pub timeout: Duration,
+    pub send_keys: Vec<Key>,
+    pub exec_shell: Vec<String>,
+    pub toggle_action: HookToggleAction,
+    /// The values of the effect clauses, each of which names a built-in effect from `crate::effects`.
+    pub effects: Vec<String>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl SequenceArg {
+	pub fn parse(args: Vec<String>) -> Result<SequenceArg, RuntimeError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["toggle"],
+            &["timeout", "send-key", "exec-shell", "toggle", "effect", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser {
+            allow_values: false,
+            allow_transitions: false,
+            allow_crossings: false,
+            type_whitelist: Some(vec![EventType::KEY]),
+            namespace: Namespace::User,
+            ..KeyParser::default_filter()
+        }.parse_all(&arg_group.keys)?;
+        if keys.len() < 2 {
+            return Err(ArgumentError::new("A --sequence requires at least two keys. To do something when a single key is pressed, use --hook instead.").into());
+        }
+
+        let timeout = match arg_group.get_unique_clause("timeout")? {
+            Some(value) => crate::arguments::delay::parse_period_value(&value)?,
+            None => DEFAULT_TIMEOUT,
+        };
+
+        let send_keys = arg_group.get_clauses("send-key").iter()
+            .map(|value| parse_send_key_clause(value))
+            .collect::<Result<Vec<Key>, RuntimeError>>()?;
+
+        let toggle_action = HookToggleAction::parse(arg_group.has_flag("toggle"), arg_group.get_clauses("toggle"))?;
+
+        let effects = arg_group.get_clauses("effect");
+        for effect in &effects {
+            crate::effects::validate(effect)?;
+        }
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(SequenceArg {
+            keys, timeout, send_keys,
+            exec_shell: arg_group.get_clauses("exec-shell"),
+            toggle_action, effects, enabled_when, stage,
+        })
+    }
+
+    pub fn compile(self, state: &State, toggle_indices: &HashMap<String, ToggleIndex>) -> Result<Sequence, ArgumentError> {
+        let mut effects: Vec<Effect> = Vec::new();
+        for command in self.exec_shell {
+            effects.push(Box::new(move |_| {
+                subprocess::try_spawn("/bin/sh".to_owned(), vec!["-c".to_owned(), command.clone()]).print_err();
+            }));
+        }
+        for effect in &self.effects {
+            effects.push(crate::effects::create(effect)?);
+        }
+        effects.extend(self.toggle_action.implement(state, toggle_indices)?);
+
+        Ok(Sequence::new(self.keys, self.timeout, self.send_keys, effects))
+    }
+}
@@ -0,0 +1,43 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::domain::{self, Domain};
+
+/// Represents a --shadow argument.
+pub(super) struct ShadowArg {
+    pub paths: Vec<String>,
+    /// The domains of the input devices whose events are copied to the shadow pipelines. If empty, the
+    /// events of all input devices are copied.
+    pub domains: Vec<Domain>,
+}
+
+impl ShadowArg {
+	pub fn parse(args: Vec<String>) -> Result<ShadowArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["domain"],
+            true,
+            false,
+        )?;
+
+        if arg_group.paths.is_empty() {
+            return Err(ArgumentError::new("The --shadow argument requires at least one path to a file containing the arguments of the shadow pipeline."));
+        }
+        let domains = arg_group.get_clauses("domain").iter()
+            .map(|domain_str| domain::resolve(domain_str))
+            .collect::<Result<Vec<Domain>, ArgumentError>>()?;
+
+        Ok(ShadowArg {
+            paths: arg_group.paths,
+            domains,
+        })
+    }
+}
+
+/// A shadow pipeline as specified by a --shadow argument, before its file has been read.
+pub struct PreShadow {
+    pub path: String,
+    /// The domains of the input devices whose events the pipeline receives, or all input devices if empty.
+    pub domains: Vec<Domain>,
+}
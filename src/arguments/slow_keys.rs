@@ -0,0 +1,53 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
+use crate::key::{Key, KeyParser};
+use crate::stream::slow_keys::SlowKeys;
+use crate::time::Duration;
+
+/// Represents a --slow-keys argument.
+pub(super) struct SlowKeysArg {
+    pub keys: Vec<Key>,
+    pub period: Duration,
+    pub feedback: Option<Key>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
+}
+
+impl SlowKeysArg {
+	pub fn parse(args: Vec<String>) -> Result<SlowKeysArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["period", "feedback", "enabled-when", "stage"],
+            false,
+            true,
+        )?;
+
+        let keys = KeyParser::default_filter()
+            .parse_all(&arg_group.get_keys_or_empty_key())?;
+        let period = crate::arguments::delay::parse_period_value(
+            &arg_group.require_unique_clause("period")?
+        )?;
+
+        let mut feedback_parser = KeyParser::default_mask();
+        feedback_parser.allow_values = false;
+        let feedback = match arg_group.get_unique_clause("feedback")? {
+            Some(key_str) => Some(feedback_parser.parse(&key_str)?),
+            None => None,
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
+        Ok(SlowKeysArg { keys, period, feedback, enabled_when, stage })
+    }
+
+    pub fn compile(self) -> SlowKeys {
+        SlowKeys::new(self.keys, self.period, self.feedback)
+    }
+}
@@ -0,0 +1,84 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::event::StageIndex;
+use crate::stream::StreamEntry;
+use crate::stream::gate::Gated;
+
+/// The name of the stage that all events start in, which does not need to be declared.
+pub(super) const INPUT_STAGE_NAME: &str = "input";
+
+/// Represents a --stage argument.
+pub(super) struct StageArg {
+    pub name: String,
+}
+
+impl StageArg {
+    pub fn parse(mut args: Vec<String>) -> Result<StageArg, ArgumentError> {
+        // The name of a stage does not look like a key, flag or clause to a ComplexArgGroup, so we
+        // take it out before parsing the rest.
+        let name = match args.len() {
+            0 | 1 => None,
+            _ => Some(args.remove(1)),
+        };
+
+        // Makes sure that no further arguments were provided.
+        ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            false,
+        )?;
+
+        let name = name.ok_or_else(|| ArgumentError::new("The --stage argument requires the name of a stage."))?;
+        if name.is_empty() || name.contains(',') || name.contains('=') {
+            return Err(ArgumentError::new(format!(
+                "Invalid name for a stage: \"{}\". Names of stages cannot be empty or contain commas or equality signs.", name
+            )));
+        }
+
+        Ok(StageArg { name })
+    }
+}
+
+/// Represents a stage=NAME[,NAME...] clause, which can be specified on the same arguments as the
+/// enabled-when clause. The entries created by such an argument only act upon events that were
+/// generated or last changed by one of the named stages. All other events pass by untouched.
+#[derive(Clone)]
+pub(super) struct StageClause {
+    pub names: Vec<String>,
+}
+
+impl StageClause {
+    pub fn parse(arg_group: &ComplexArgGroup) -> Result<Option<StageClause>, ArgumentError> {
+        let value = match arg_group.get_unique_clause("stage")? {
+            Some(value) => value,
+            None => return Ok(None),
+        };
+        let names: Vec<String> = value.split(',').map(str::to_owned).collect();
+        if names.iter().any(String::is_empty) {
+            return Err(ArgumentError::new(format!(
+                "The stage clause requires one or more names of stages separated by commas. Encountered: stage={}", value
+            )));
+        }
+        Ok(Some(StageClause { names }))
+    }
+
+    /// Wraps a stream entry so it only acts upon events from the stages of this clause. `stage_names`
+    /// must contain the names of all stages that were declared before this argument, ordered by their index.
+    pub fn compile(&self, entry: StreamEntry, stage_names: &[String]) -> Result<StreamEntry, ArgumentError> {
+        let stages = self.names.iter().map(|name| {
+            stage_names.iter().position(|stage_name| stage_name == name).ok_or_else(|| ArgumentError::new(format!(
+                "No stage named \"{}\" has been declared before this argument. Arguments can only match events from the current stage or earlier stages.", name
+            )))
+        }).collect::<Result<Vec<StageIndex>, ArgumentError>>()?;
+
+        let mut gated = match entry {
+            StreamEntry::Gated(gated) => gated,
+            entry => Gated::new(entry),
+        };
+        gated.set_stages(stages);
+        Ok(StreamEntry::Gated(gated))
+    }
+}
@@ -0,0 +1,30 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::time::Duration;
+
+/// Represents a --stats argument.
+pub(super) struct StatsArg {
+    /// How often the statistics of the input devices are printed.
+    pub interval: Duration,
+}
+
+impl StatsArg {
+	pub fn parse(args: Vec<String>) -> Result<StatsArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["interval"],
+            false,
+            false,
+        )?;
+
+        let interval = parse_period_value(&arg_group.require_unique_clause("interval")?)?;
+        if interval < Duration::from_millis(1) {
+            return Err(ArgumentError::new("The interval of --stats must be at least 0.001 seconds."));
+        }
+
+        Ok(StatsArg { interval })
+    }
+}
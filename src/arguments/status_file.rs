@@ -0,0 +1,39 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::delay::parse_period_value;
+use crate::time::Duration;
+
+/// Represents a --status-file argument.
+pub(super) struct StatusFileArg {
+    pub path: String,
+    /// How often the status file is rewritten.
+    pub interval: Duration,
+}
+
+impl StatusFileArg {
+	pub fn parse(args: Vec<String>) -> Result<StatusFileArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &[],
+            &["interval"],
+            true,
+            false,
+        )?;
+
+        let path = match arg_group.paths.as_slice() {
+            [path] => path.clone(),
+            [] => return Err(ArgumentError::new("The --status-file argument requires the path of the file to write to.")),
+            _ => return Err(ArgumentError::new("The --status-file argument accepts only a single path.")),
+        };
+        let interval = match arg_group.get_unique_clause("interval")? {
+            Some(interval_str) => parse_period_value(&interval_str)?,
+            None => Duration::from_secs(1),
+        };
+        if interval < Duration::from_millis(1) {
+            return Err(ArgumentError::new("The interval of --status-file must be at least 0.001 seconds."));
+        }
+
+        Ok(StatusFileArg { path, interval })
+    }
+}
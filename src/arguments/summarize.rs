@@ -0,0 +1,35 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::summary::SummaryFormat;
+use crate::utils::split_once;
+
+/// Represents a --summarize argument.
+pub(super) struct SummarizeArg {
+    pub format: SummaryFormat,
+}
+
+impl SummarizeArg {
+    pub fn parse(mut args: Vec<String>) -> Result<SummarizeArg, ArgumentError> {
+        // The format is attached to the name of the argument, as in --summarize=man.
+        let (name, format_str) = split_once(&args[0], "=");
+        let format = match format_str {
+            None | Some("text") => SummaryFormat::Text,
+            Some("man") => SummaryFormat::Man,
+            Some(other) => return Err(ArgumentError::new(format!(
+                "Invalid summary format \"{}\". The format must be either text or man.", other
+            ))),
+        };
+        args[0] = name.to_owned();
+
+        ComplexArgGroup::parse(args,
+            &[],
+            &[],
+            false,
+            false,
+        )?;
+
+        Ok(SummarizeArg { format })
+    }
+}
@@ -59,6 +59,16 @@ fn test_argument_validity() {
     require_err(["--map", "key:"]);
     require_err(["--map", "key::"]);
     require_err(["--map", "key:a:"]);
+
+    // Test annotations.
+    require_ok( ["--map", "key:a", "key:b^remapped"]);
+    require_ok( ["--map", "key:a:1@kb^first^second", "key:b@kb^third"]);
+    require_ok( ["--hook", "key:f1", "send-key=key:x^macro", "--block", "^macro"]);
+    require_err(["--map", "key:a^", "key:b"]);
+    require_err(["--map", "key:a^@kb", "key:b"]);
+    require_err(["--map", "key:a^mac.ro", "key:b"]);
+    require_err(["--hook", "key:a", "--withhold", "key:a^macro"]);
+    require_err(["--merge", "key:a^macro"]);
     
     // Test --withhold.
     require_ok( ["--hook", "key:a", "--withhold"]);
@@ -68,6 +78,14 @@ fn test_argument_validity() {
     require_err(["--hook", "--withhold", "key"]);
     require_err(["--hook", "@foo", "--withhold", "key"]);
     require_err(["--hook", "abs:x", "--hook", "key:a", "--withhold"]);
+    require_ok( ["--hook", "key:a", "key:b", "--withhold", "passthrough-on-activate"]);
+    require_ok( ["--hook", "key:a", "key:b", "--withhold", "debug"]);
+    require_err(["--hook", "key:a", "key:b", "--withhold", "debug=yes"]);
+    require_ok( ["--hook", "key:a", "key:b", "--withhold", "passthrough-on-activate=key:a,key:b@kb"]);
+    require_ok( ["--hook", "key:a", "key:b", "--withhold", "passthrough-on-activate=key:a", "passthrough-on-activate=key:b"]);
+    require_err(["--hook", "key:a", "key:b", "--withhold", "passthrough-on-activate", "passthrough-on-activate=key:a"]);
+    require_err(["--hook", "key:a", "key:b", "--withhold", "passthrough-on-activate=key:a,"]);
+    require_err(["--hook", "key:a", "key:b", "--withhold", "passthrough-on-activate=abs:x"]);
     require_err(["--hook", "key:a", "--hook", "abs:x", "--withhold"]);
     require_err(["--hook", "key:a", "abs:x", "--withhold"]);
     require_ok( ["--hook", "key:a", "abs:x", "--withhold", "key"]);
@@ -77,6 +95,16 @@ fn test_argument_validity() {
     require_ok( ["--hook", "key:a", "key:b:1", "--withhold", "key:a"]);
     require_ok( ["--hook", "key:a", "key:b:1", "--withhold", "btn"]);
 
+    // Test --curve
+    require_ok( ["--curve", "points=0:0,100:50"]);
+    require_ok( ["--curve", "abs:x", "abs:y@stick", "points=-32768:-32768,0:0,32767:32767"]);
+    require_err(["--curve"]);
+    require_err(["--curve", "abs:x", "points=0:0"]);
+    require_err(["--curve", "abs:x", "points=100:0,0:100"]);
+    require_err(["--curve", "rel:x", "points=0:0,100:50"]);
+    require_err(["--curve", "abs:x", "points=0:0,100:50", "table=/dev/null"]);
+    require_err(["--curve", "abs:x", "table=/nonexistent/curve.txt"]);
+
     // Test --scale
     require_err(["--scale"]);
     require_err(["--scale", "rel"]);
@@ -92,6 +120,394 @@ fn test_argument_validity() {
     require_ok( ["--scale", "@foo", "factor=1"]);
     require_err(["--scale", "key", "factor=1"]);
 
+    // Test --hook interval=
+    require_ok( ["--hook", "key:a", "key:b", "sequential", "interval=0.3"]);
+    require_err(["--hook", "key:a", "key:b", "interval=0.3"]);
+    require_err(["--hook", "key:a", "key:b", "sequential", "interval=0"]);
+    require_err(["--hook", "key:a", "key:b", "sequential", "interval=-1"]);
+
+    // Test the suspend= clause
+    require_ok( ["--delay", "key:a", "period=1", "suspend=count"]);
+    require_ok( ["--delay", "key:a", "period=1", "suspend=pause"]);
+    require_err(["--delay", "key:a", "period=1", "suspend=skip"]);
+    require_err(["--delay", "key:a", "period=1", "suspend=count", "suspend=pause"]);
+    require_ok( ["--hook", "key:a", "key:b", "period=1", "suspend=count"]);
+    require_ok( ["--hook", "key:a", "key:b", "sequential", "interval=0.3", "suspend=count"]);
+    require_err(["--hook", "key:a", "key:b", "suspend=count"]);
+
+    // Test --hook accumulate=
+    require_ok( ["--hook", "rel:wheel", "accumulate=5", "period=2"]);
+    require_ok( ["--hook", "key:leftctrl", "rel:hwheel:~-1@mouse", "accumulate=600"]);
+    require_err(["--hook", "key:a", "accumulate=5"]);
+    require_err(["--hook", "rel:wheel", "accumulate=0"]);
+    require_err(["--hook", "rel:wheel", "accumulate=-5"]);
+    require_err(["--hook", "rel:wheel", "accumulate=many"]);
+    require_err(["--hook", "rel:wheel", "rel:hwheel", "sequential", "accumulate=5"]);
+
+    // Test repeat counts on --hook send-key= and send-event=
+    require_ok( ["--hook", "key:f1", "send-key=key:right:x10"]);
+    require_ok( ["--hook", "key:f1", "send-key=key:x:x2@kb", "send-event=rel:x:5:x3", "burst-interval=0.05"]);
+    require_ok( ["--hook", "key:f1", "send-key=key:x"]);
+    require_err(["--hook", "key:f1", "send-key=key:right:x0"]);
+    require_err(["--hook", "key:f1", "send-key=key:right:x-1"]);
+    require_err(["--hook", "key:f1", "send-key=key:right:3"]);
+    require_err(["--hook", "key:f1", "send-key=key:right:x2", "burst-interval=0"]);
+    require_err(["--hook", "key:f1", "send-key=key:right:x2", "burst-interval=0.1", "--withhold"]);
+
+    // Test --input
+    require_ok( ["--input", "/dev/null", "auto-output"]);
+    require_ok( ["--input", "/dev/null", "grab=force", "auto-output"]);
+    require_err(["--input", "/dev/null", "auto-output=yes"]);
+    require_ok( ["--input", "/dev/null", "open-mode=rw", "grab"]);
+    require_ok( ["--input", "/dev/null", "open-mode=ro", "observe-only"]);
+    require_err(["--input", "/dev/null", "open-mode=wo"]);
+    require_err(["--input", "/dev/null", "observe-only", "grab"]);
+    require_err(["--input", "/dev/null", "observe-only", "auto-output"]);
+    require_err(["--input", "/dev/null", "observe-only", "open-mode=rw"]);
+    require_err(["--input", "/dev/null", "observe-only", "repeat=off"]);
+    require_err(["--input", "fd=3", "open-mode=rw"]);
+
+    // Test --input filter=
+    require_ok( ["--input", "/dev/null", "filter=key:power"]);
+    require_ok( ["--input", "/dev/null", "filter=key:power,key:sleep", "filter=btn"]);
+    require_err(["--input", "/dev/null", "filter=key:power:1"]);
+    require_err(["--input", "/dev/null", "filter=key:power@foo"]);
+    require_err(["--input", "/dev/null", "filter="]);
+
+    // Test --input resync=
+    require_ok( ["--input", "/dev/null", "resync=pass"]);
+    require_ok( ["--input", "/dev/null", "resync=isolate", "--map", "key:a", "key:b", "resync", "--output"]);
+    require_err(["--input", "/dev/null", "resync=bogus"]);
+    require_err(["--input", "/dev/null", "resync"]);
+    require_err(["--input", "/dev/null", "resync=pass", "resync=isolate"]);
+
+    // Test --map resync
+    let parse_map = |args: &[&str]| super::map::MapArg::parse(args.iter().map(|&arg| arg.to_owned()).collect());
+    assert!(parse_map(&["--map", "key:a", "key:b"]).unwrap().resync_keys.is_none());
+    let (resync_input_keys, resync_output_keys) = parse_map(&["--map", "key:a", "key:b", "resync"]).unwrap().resync_keys.unwrap();
+    assert_eq!((resync_input_keys.len(), resync_output_keys.len()), (1, 1));
+    let (resync_input_keys, resync_output_keys) = parse_map(&["--copy", "key:a", "key:b", "key:c", "->", "key:d", "resync"]).unwrap().resync_keys.unwrap();
+    assert_eq!((resync_input_keys.len(), resync_output_keys.len()), (3, 2));
+    require_ok( ["--map", "key:a", "resync"]);
+    require_ok( ["--map", "key:a", "key:b", "yield", "resync"]);
+    require_err(["--map", "key:a", "key:b", "resync=yes"]);
+    require_err(["--block", "key:a", "resync"]);
+
+    // Test --output mirror-of=
+    require_ok( ["--input", "/dev/null", "domain=kb", "--output", "mirror-of=kb"]);
+    require_ok( ["--input", "/dev/null", "--output", "mirror-of=/dev/null", "name=foo"]);
+    require_err(["--input", "/dev/null", "domain=kb", "--output", "mirror-of=@kb"]);
+    require_err(["--input", "/dev/null", "domain=kb", "--output", "mirror-of=foo"]);
+    require_err(["--input", "/dev/null", "domain=kb", "--output", "mirror-of="]);
+
+    // Test --output overflow= and queue-size=
+    require_ok( ["--input", "/dev/null", "--output", "overflow=drop-oldest"]);
+    require_ok( ["--input", "/dev/null", "--output", "overflow=drop-newest", "queue-size=16"]);
+    require_ok( ["--input", "/dev/null", "--output", "overflow=block", "queue-size=1"]);
+    require_err(["--input", "/dev/null", "--output", "overflow=drop"]);
+    require_err(["--input", "/dev/null", "--output", "overflow"]);
+    require_err(["--input", "/dev/null", "--output", "queue-size=0"]);
+    require_err(["--input", "/dev/null", "--output", "queue-size=-4"]);
+
+    // Test --output full-keyboard
+    require_ok( ["--input", "/dev/null", "--output", "full-keyboard"]);
+    require_ok( ["--input", "/dev/null", "--output", "key:a", "full-keyboard", "repeat=passive"]);
+    require_err(["--input", "/dev/null", "--output", "full-keyboard=yes"]);
+
+    // Test --output abs-pointer=
+    require_ok( ["--input", "/dev/null", "--output", "abs-pointer=1920x1080"]);
+    require_ok( ["--input", "/dev/null", "--output", "key", "abs-pointer=800x600", "name=Touch"]);
+    require_err(["--input", "/dev/null", "--output", "abs-pointer=1920"]);
+    require_err(["--input", "/dev/null", "--output", "abs-pointer=0x1080"]);
+    require_err(["--input", "/dev/null", "--output", "abs-pointer=1920x-1"]);
+    require_err(["--input", "/dev/null", "--output", "abs-pointer"]);
+
+    // Test --recover
+    require_ok( ["--recover", "/tmp/evsieve.journal"]);
+    require_err(["--recover"]);
+    require_err(["--recover", "/tmp/a", "/tmp/b"]);
+    require_err(["--recover", "/tmp/a", "--recover", "/tmp/b"]);
+
+    // Test --withhold device-lost=
+    require_ok( ["--hook", "key:a", "key:b", "--withhold", "device-lost=drop"]);
+    require_ok( ["--hook", "key:a", "key:b", "--withhold", "device-lost=flush"]);
+    require_err(["--hook", "key:a", "key:b", "--withhold", "device-lost=release"]);
+    require_err(["--hook", "key:a", "key:b", "--withhold", "device-lost"]);
+
+    // Test --batch
+    require_ok( ["--batch", "max-latency=4"]);
+    require_err(["--batch"]);
+    require_err(["--batch", "max-latency=0"]);
+    require_err(["--batch", "max-latency=0.5"]);
+    require_err(["--batch", "max-latency=4", "--batch", "max-latency=8"]);
+
+    // Test --realtime and --lock-memory
+    require_ok( ["--realtime"]);
+    require_ok( ["--realtime=99", "--lock-memory"]);
+    require_ok( ["--lock-memory", "--lock-memory"]);
+    require_err(["--realtime=0"]);
+    require_err(["--realtime=100"]);
+    require_err(["--realtime=high"]);
+    require_err(["--realtime", "--realtime=20"]);
+    require_err(["--lock-memory", "foo"]);
+
+    // Test fallback groups
+    require_ok( ["--input", "/dev/null", "persist=reopen", "group=kb", "priority=1", "--input", "/nonexistent/bt-keyboard", "persist=reopen", "group=kb", "--output"]);
+    require_err(["--input", "/dev/null", "persist=reopen", "--input", "/nonexistent/bt-keyboard", "persist=reopen", "--output"]);
+    require_err(["--input", "/dev/null", "group=kb", "--output"]);
+    require_err(["--input", "/dev/null", "persist=reopen", "priority=1", "--output"]);
+
+    // Test --safe-mode
+    require_ok( ["--input", "/dev/null", "grab=force", "--output", "--safe-mode"]);
+    require_ok( ["--safe-mode", "--safe-mode"]);
+    require_err(["--safe-mode", "all"]);
+
+    // Test --control-fifo
+    require_ok( ["--control-fifo", "/run/evsieve.fifo"]);
+    require_ok( ["--control-fifo", "/run/admin.fifo", "mode=0600", "--control-fifo", "/run/user.fifo", "mode=0620", "owner=:1000", "allow=toggle,stats"]);
+    require_ok( ["--control-fifo", "/run/a.fifo", "/run/b.fifo", "owner=1000:1000", "allow=toggle"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "mode=rw"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "mode=0800"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "mode=01777"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "owner=:"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "allow=toggle,grab"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "allow="]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "allow=toggle", "allow=stats"]);
+    require_err(["--control-fifo", "/run/evsieve.fifo", "--control-fifo", "/run/evsieve.fifo", "allow=toggle"]);
+
+    // Test --status-file
+    require_ok( ["--status-file", "/run/evsieve.status"]);
+    require_ok( ["--status-file", "/run/evsieve.status", "interval=0.1", "--toggle", "key:a", "key:b", "key:c", "id=profile", "names=day,night"]);
+    require_err(["--status-file"]);
+    require_err(["--status-file", "/run/a.status", "/run/b.status"]);
+    require_err(["--status-file", "/run/evsieve.status", "interval=0"]);
+    require_err(["--status-file", "/run/a.status", "--status-file", "/run/b.status"]);
+
+    // Test --introspect-socket
+    require_ok( ["--introspect-socket", "/run/evsieve.sock"]);
+    require_ok( ["--introspect-socket", "/run/a.sock", "/run/b.sock", "mode=0660", "owner=:1000", "--control-fifo", "/run/evsieve.fifo"]);
+    require_err(["--introspect-socket"]);
+    require_err(["--introspect-socket", "/run/evsieve.sock", "mode=0800"]);
+    require_err(["--introspect-socket", "/run/evsieve.sock", "allow=toggle"]);
+    require_err(["--introspect-socket", "/run/evsieve.sock", "/run/evsieve.sock"]);
+    require_err(["--introspect-socket", "/run/evsieve.sock", "--control-fifo", "/run/evsieve.sock"]);
+
+    // Test --summarize
+    require_ok( ["--summarize", "--output"]);
+    require_ok( ["--summarize=text", "--map", "key:a", "key:b", "--output"]);
+    require_ok( ["--summarize=man", "--output"]);
+    require_err(["--summarize=pdf", "--output"]);
+    require_err(["--summarize", "--summarize=man"]);
+    require_err(["--summarize", "key:a"]);
+
+    // Test --bypass
+    require_ok( ["--bypass", "key:leftctrl", "key:leftalt", "key:f2", "--output"]);
+    require_ok( ["--bypass", "key:f2@kb", "output=2", "--output", "key:a", "--output"]);
+    require_err(["--bypass", "key:f2", "output=2", "--output"]);
+    require_err(["--bypass", "key:f2", "output=0", "--output"]);
+    require_err(["--bypass", "key:f2"]);
+    require_err(["--bypass", "output=1", "--output"]);
+
+    // Test --on-vt
+    require_ok( ["--toggle", "key:a", "key:b", "key:c", "id=mode", "--on-vt", "vt=1", "vt=tty2", "toggle=mode:2"]);
+    require_ok( ["--toggle", "key:a", "key:b", "--on-vt", "vt=7", "leave", "toggle"]);
+    require_err(["--toggle", "key:a", "key:b", "--on-vt", "toggle"]);
+    require_err(["--toggle", "key:a", "key:b", "--on-vt", "vt=0", "toggle"]);
+    require_err(["--toggle", "key:a", "key:b", "--on-vt", "vt=ttyS0", "toggle"]);
+    require_err(["--toggle", "key:a", "key:b", "--on-vt", "vt=2"]);
+    require_err(["--on-vt", "vt=2", "toggle=mode:2"]);
+
+    // Test --toggle follows=
+    require_ok( ["--toggle", "key:a", "key:b", "key:c", "key:d", "id=t", "--toggle", "key:x", "key:y", "key:z", "follows=t", "follow-states=1,2,2"]);
+    require_ok( ["--toggle", "key:x", "key:y", "key:z", "follows=t", "--toggle", "key:a", "key:b", "key:c", "key:d", "id=t"]);
+    require_err(["--toggle", "key:x", "key:y", "key:z", "follows=t"]);
+    require_err(["--toggle", "key:x", "key:y", "key:z", "id=t", "follows=t"]);
+    require_err(["--toggle", "key:a", "key:b", "key:c", "key:d", "id=t", "--toggle", "key:x", "key:y", "key:z", "follows=t", "follow-states=1,2"]);
+    require_err(["--toggle", "key:a", "key:b", "key:c", "key:d", "id=t", "--toggle", "key:x", "key:y", "key:z", "follows=t", "follow-states=1,2,3"]);
+    require_err(["--toggle", "key:a", "key:b", "key:c", "key:d", "id=t", "--toggle", "key:x", "key:y", "key:z", "follow-states=1,2,2"]);
+    require_err(["--toggle", "key:a", "key:b", "key:c", "id=t", "--toggle", "key:x", "key:y", "key:z", "id=u", "follows=t", "--toggle", "key:p", "key:q", "key:r", "follows=u"]);
+
+    // Test --input packets
+    require_ok( ["--input", "/dev/null", "packets", "--output"]);
+    require_err(["--input", "/dev/null", "packets=yes", "--output"]);
+
+    // Test --remap-modifiers
+    require_ok( ["--remap-modifiers", "swap=key:capslock,key:leftctrl"]);
+    require_ok( ["--remap-modifiers", "@kb", "remap=key:capslock,key:leftctrl", "remap=key:rightctrl,key:leftctrl"]);
+    require_err(["--remap-modifiers"]);
+    require_err(["--remap-modifiers", "rel:x", "swap=key:capslock,key:leftctrl"]);
+    require_err(["--remap-modifiers", "swap=key:capslock,key:leftctrl", "remap=key:capslock,key:esc"]);
+    require_err(["--remap-modifiers", "remap=key:capslock"]);
+
+    // Test --mirror
+    require_ok( ["--mirror"]);
+    require_ok( ["--mirror", "key:a", "key:q@kb", "trigger=key:capslock"]);
+    require_err(["--mirror", "rel:x"]);
+    require_err(["--mirror", "trigger=rel:x"]);
+    require_err(["--mirror", "table=/nonexistent/mirror-table"]);
+
+    // Test --pressure-click
+    require_ok( ["--pressure-click"]);
+    require_ok( ["--pressure-click", "abs:pressure@pad", "abs:mt_pressure", "threshold=40~60", "button=btn:middle"]);
+    require_err(["--pressure-click", "key:a"]);
+    require_err(["--pressure-click", "abs:pressure:10"]);
+    require_err(["--pressure-click", "button=rel:x"]);
+    require_err(["--pressure-click", "threshold=60~40"]);
+
+    // Test --inhibit-while-typing
+    require_ok( ["--inhibit-while-typing", "period=0.3"]);
+    require_ok( ["--inhibit-while-typing", "btn:touch@pad", "rel@pad", "typing=key@kb", "except=key:leftctrl", "except=key:leftshift", "period=0.5"]);
+    require_err(["--inhibit-while-typing"]);
+    require_err(["--inhibit-while-typing", "period=0.3", "period=0.5"]);
+    require_err(["--inhibit-while-typing", "period=0.3", "typing=key:nonexistent"]);
+
+    // Test --max-hold
+    require_ok( ["--max-hold", "period=30"]);
+    require_ok( ["--max-hold", "key", "btn", "period=10", "except=key:f13", "except=btn:left@mouse"]);
+    require_err(["--max-hold"]);
+    require_err(["--max-hold", "period=0"]);
+    require_err(["--max-hold", "period=10", "period=20"]);
+
+    // Test --hook id=
+    require_ok( ["--hook", "key:a", "id=foo", "--hook", "hook:foo", "key:b", "id=bar"]);
+    require_err(["--hook", "key:a", "id=foo", "--hook", "key:b", "id=foo"]);
+    require_err(["--hook", "key:a", "id=foo:bar"]);
+    require_err(["--hook", "key:a", "id="]);
+
+    // Test --default
+    require_ok( ["--default", "block"]);
+    require_ok( ["--default", "block", "@kb", "key", "--map", "key:a", "key:b"]);
+    require_err(["--default"]);
+    require_err(["--default", "pass"]);
+    require_err(["--default", "block", "key:a:1"]);
+
+    // Test --plugin
+    require_err(["--plugin"]);
+    require_err(["--plugin", "config=foo"]);
+    require_err(["--plugin", "/nonexistent/plugin.so"]);
+    require_err(["--plugin", "/nonexistent/a.so", "/nonexistent/b.so"]);
+    require_err(["--plugin", "key:a"]);
+
+    // Test --print format=
+    require_ok( ["--print", "key", "format=direct"]);
+    require_err(["--print", "format=binary"]);
+    require_err(["--print", "format=unknown"]);
+
+    // Test enabled-when=led:
+    require_ok( ["--map", "key:a", "key:b", "enabled-when=led:capsl:1"]);
+    require_ok( ["--hook", "key:a", "send-key=key:x", "enabled-when=led:numl:0@kb"]);
+    require_ok( ["--toggle", "key:a", "key:b", "key:c", "id=led", "--map", "key:a", "key:b", "enabled-when=led:2"]);
+    require_err(["--map", "key:a", "key:b", "enabled-when=led:capsl:2"]);
+    require_err(["--map", "key:a", "key:b", "enabled-when=led:unknown:1"]);
+    require_err(["--map", "key:a", "key:b", "enabled-when=led:capsl@kb:1"]);
+    require_err(["--map", "key:a", "key:b", "enabled-when=led:capsl"]);
+
+    // Test --stage
+    require_ok( ["--stage", "late", "--map", "key:a", "key:b", "--map", "key:b", "key:a", "stage=input"]);
+    require_ok( ["--stage", "one", "--stage", "two", "--hook", "key:a", "stage=one,two", "--print", "stage=input"]);
+    require_ok( ["--toggle", "key:a", "key:b", "key:c", "id=mode", "--stage", "s", "--block", "enabled-when=mode:2", "stage=input"]);
+    require_err(["--map", "key:a", "key:b", "stage=late", "--stage", "late"]);
+    require_err(["--map", "key:a", "key:b", "stage=unknown"]);
+    require_err(["--map", "key:a", "key:b", "stage="]);
+    require_err(["--stage", "input"]);
+    require_err(["--stage", "a", "--stage", "a"]);
+    require_err(["--stage"]);
+    require_err(["--stage", "a", "b"]);
+    require_err(["--stage", "a", "--hook", "key:a", "stage=a", "--withhold"]);
+}
+
+#[test]
+fn test_pipelines() {
+    use crate::arguments::parser::process_pipelines;
+
+    let dir = std::env::temp_dir().join(format!("evsieve-test-pipelines-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write_file = |name: &str, content: &str| -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_owned()
+    };
+    let first = write_file("first.conf", "--toggle @source @x @y id=mode --control-fifo /run/first");
+    let second = write_file("second.conf", "--toggle @source @x @y @z id=mode --control-fifo /run/second");
+    let same_fifo = write_file("same-fifo.conf", "--control-fifo /run/first");
+    let stats = write_file("stats.conf", "--stats interval=1");
+    let nested = write_file("nested.conf", &format!("--pipeline {}", first));
+
+    // Each pipeline has its own toggles, so they may use the same ID.
+    let pipelines = process_pipelines(vec!["--pipeline".to_owned(), first.clone(), second.clone()]).unwrap();
+    assert_eq!(pipelines.len(), 3);
+    assert!(pipelines[0].toggle_indices.is_empty());
+    assert_eq!(pipelines[1].state[pipelines[1].toggle_indices["mode"]].size(), 2);
+    assert_eq!(pipelines[2].state[pipelines[2].toggle_indices["mode"]].size(), 3);
+
+    assert!(process_pipelines(vec!["--pipeline".to_owned(), first.clone(), same_fifo]).is_err());
+    assert!(process_pipelines(vec!["--pipeline".to_owned(), stats.clone()]).is_err());
+    assert!(process_pipelines(vec!["--stats".to_owned(), "interval=1".to_owned(), "--pipeline".to_owned(), first.clone()]).is_ok());
+    assert!(process_pipelines(vec!["--pipeline".to_owned(), nested]).is_err());
+    require_err(["--pipeline", &first]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_shadows() {
+    use crate::arguments::parser::process_pipelines;
+
+    let dir = std::env::temp_dir().join(format!("evsieve-test-shadows-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let write_file = |name: &str, content: &str| -> String {
+        let path = dir.join(name);
+        std::fs::write(&path, content).unwrap();
+        path.to_str().unwrap().to_owned()
+    };
+    let trial = write_file("trial.conf", "--map key:a key:b --print");
+    let with_output = write_file("output.conf", "--map key:a key:b --output");
+    let with_exec = write_file("exec.conf", "--hook key:a exec-shell=true");
+    let to_args = |args: &[&str]| args.iter().map(|&arg| arg.to_owned()).collect::<Vec<String>>();
+
+    let pipelines = process_pipelines(to_args(&["--input", "/dev/null", "domain=kb", "--output", "--shadow", &trial, "domain=kb"])).unwrap();
+    assert_eq!(pipelines.len(), 2);
+    assert!(pipelines[0].shadow_sources.is_none());
+    assert_eq!(pipelines[1].shadow_sources, Some(vec![pipelines[0].input_devices[0].domain]));
+    assert!(pipelines[1].output_devices.is_empty());
+
+    let pipelines = process_pipelines(to_args(&["--input", "/dev/null", "--shadow", &trial])).unwrap();
+    assert_eq!(pipelines[1].shadow_sources.as_ref().map(Vec::len), Some(1));
+
+    assert!(process_pipelines(to_args(&["--input", "/dev/null", "--shadow", &trial, "domain=nonexistent"])).is_err());
+    assert!(process_pipelines(to_args(&["--input", "/dev/null", "--shadow", &with_output])).is_err());
+    assert!(process_pipelines(to_args(&["--input", "/dev/null", "--shadow", &with_exec])).is_err());
+    assert!(process_pipelines(to_args(&["--shadow"])).is_err());
+    require_err(["--shadow", &trial]);
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_error_categories() {
+    use crate::error::ErrorCategory;
+    let category_of = |args: &[&str]| try_process(args.iter().copied()).err().map(|error| error.category());
+    assert_eq!(category_of(&["--map", "key:a", "--output"]), None);
+    assert_eq!(category_of(&["--map", "foo:bar"]), Some(ErrorCategory::Argument));
+    assert_eq!(category_of(&["--input", "/dev/input/evsieve-nonexistent"]), Some(ErrorCategory::DeviceUnavailable));
+    assert_eq!(ErrorCategory::Argument.exit_code(), 2);
+
+    let io_error = |errno| crate::error::SystemError::from(std::io::Error::from_raw_os_error(errno)).category();
+    assert_eq!(io_error(libc::ENOENT), ErrorCategory::DeviceUnavailable);
+    assert_eq!(io_error(libc::EACCES), ErrorCategory::PermissionDenied);
+    assert_eq!(io_error(libc::EINVAL), ErrorCategory::Other);
+}
+
+#[test]
+fn test_inherited_fds() {
+    use std::os::unix::io::AsRawFd;
+    let input_file = std::fs::File::open("/dev/null").unwrap();
+    let input_fd = format!("fd={}", input_file.as_raw_fd());
+
+    require_ok( ["--input", &input_fd, "--output", "fd=1"]);
+    require_err(["--input", &input_fd, "--input", &input_fd]);
+    require_err(["--input", &input_fd, "--output", &input_fd]);
+    require_err(["--input", "/dev/null", "--output", "fd=1", "--output", "fd=1"]);
 }
 
 fn require_ok(args: impl IntoIterator<Item=impl Into<String>>) {
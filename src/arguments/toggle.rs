@@ -2,6 +2,8 @@
 
 use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::gate::EnabledWhenClause;
+use crate::arguments::stage::StageClause;
 use crate::key::{Key, KeyParser};
 use crate::stream::map::ToggleMode;
 
@@ -11,13 +13,24 @@ pub(super) struct ToggleArg {
     pub output_keys: Vec<Key>,
     pub id: Option<String>,
     pub mode: ToggleMode,
+    /// Specified by the names clause: a name for the state belonging to each output key.
+    pub names: Option<Vec<String>>,
+    /// Specified by the follows clause: the id of the toggle whose state this toggle follows.
+    pub follows: Option<String>,
+    /// Specified by the follow-states clause: for each state of the followed toggle, the zero-indexed
+    /// state this toggle should assume.
+    pub follow_states: Option<Vec<usize>>,
+    /// Specified by the enabled-when clause.
+    pub enabled_when: Option<EnabledWhenClause>,
+    /// Specified by the stage clause.
+    pub stage: Option<StageClause>,
 }
 
 impl ToggleArg {
 	pub fn parse(args: Vec<String>) -> Result<ToggleArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
             &[],
-            &["id", "mode"],
+            &["id", "mode", "names", "follows", "follow-states", "enabled-when", "stage"],
             false,
             true,
         )?;
@@ -48,8 +61,46 @@ impl ToggleArg {
             }
         }
         
+        let names = match arg_group.get_unique_clause("names")? {
+            None => None,
+            Some(names_str) => {
+                let names: Vec<String> = names_str.split(',').map(str::to_owned).collect();
+                if names.len() != output_keys.len() {
+                    return Err(ArgumentError::new(format!(
+                        "The names= clause of a --toggle must contain one name for each output key. Expected {} names, found {}: {}",
+                        output_keys.len(), names.len(), names_str,
+                    )));
+                }
+                if id.is_none() {
+                    return Err(ArgumentError::new("A --toggle with a names= clause also requires an id= clause."));
+                }
+                Some(names)
+            },
+        };
+
+        let follows = arg_group.get_unique_clause("follows")?;
+        let follow_states = match arg_group.get_unique_clause("follow-states")? {
+            None => None,
+            Some(states_str) => {
+                if follows.is_none() {
+                    return Err(ArgumentError::new("A --toggle with a follow-states= clause also requires a follows= clause."));
+                }
+                let states = states_str.split(',').map(|state_str| match state_str.parse::<usize>() {
+                    Ok(state) if state >= 1 && state <= output_keys.len() => Ok(state - 1),
+                    _ => Err(ArgumentError::new(format!(
+                        "Invalid follow-state \"{}\": each follow-state must be an integer between 1 and the amount of output keys, {}.",
+                        state_str, output_keys.len(),
+                    ))),
+                }).collect::<Result<Vec<usize>, ArgumentError>>()?;
+                Some(states)
+            },
+        };
+
+        let enabled_when = EnabledWhenClause::parse(&arg_group)?;
+        let stage = StageClause::parse(&arg_group)?;
+
         Ok(ToggleArg {
-            input_key, output_keys, mode, id
+            input_key, output_keys, mode, id, names, follows, follow_states, enabled_when, stage,
         })
     }
 
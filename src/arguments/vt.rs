@@ -0,0 +1,52 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use std::collections::HashMap;
+
+use crate::error::ArgumentError;
+use crate::arguments::lib::ComplexArgGroup;
+use crate::arguments::hook::HookToggleAction;
+use crate::io::vt::VtNumber;
+use crate::state::{State, ToggleIndex};
+use crate::stream::vt::VtSwitch;
+
+/// Represents an --on-vt argument.
+pub(super) struct OnVtArg {
+    pub vts: Vec<VtNumber>,
+    pub leave: bool,
+    pub toggle_action: HookToggleAction,
+}
+
+impl OnVtArg {
+	pub fn parse(args: Vec<String>) -> Result<OnVtArg, ArgumentError> {
+        let arg_group = ComplexArgGroup::parse(args,
+            &["toggle", "leave"],
+            &["vt", "toggle"],
+            false,
+            false,
+        )?;
+
+        let vts = arg_group.get_clauses("vt").iter()
+            .map(|value| match value.strip_prefix("tty").unwrap_or(value).parse::<VtNumber>() {
+                Ok(vt) if vt > 0 => Ok(vt),
+                _ => Err(ArgumentError::new(format!(
+                    "Cannot interpret \"{}\" as a virtual terminal. Please specify its number, e.g. \"vt=2\" for tty2.", value
+                ))),
+            })
+            .collect::<Result<Vec<VtNumber>, ArgumentError>>()?;
+        if vts.is_empty() {
+            return Err(ArgumentError::new("The --on-vt argument requires at least one vt= clause."));
+        }
+
+        let toggle_action = HookToggleAction::parse(arg_group.has_flag("toggle"), arg_group.get_clauses("toggle"))?;
+        if ! arg_group.has_flag("toggle") && arg_group.get_clauses("toggle").is_empty() {
+            return Err(ArgumentError::new("The --on-vt argument requires a toggle flag or toggle= clause."));
+        }
+
+        Ok(OnVtArg { vts, toggle_action, leave: arg_group.has_flag("leave") })
+    }
+
+    pub fn compile(self, state: &State, toggle_indices: &HashMap<String, ToggleIndex>) -> Result<VtSwitch, ArgumentError> {
+        let effects = self.toggle_action.implement(state, toggle_indices)?;
+        Ok(VtSwitch::new(self.vts, self.leave, effects))
+    }
+}
@@ -5,6 +5,7 @@ use crate::error::ArgumentError;
 use crate::arguments::lib::ComplexArgGroup;
 use crate::arguments::hook::HookArg;
 use crate::stream::hook::Trigger;
+use crate::stream::withhold::LostMode;
 use crate::key::{Key, KeyParser};
 
 // The --withhold argument imposes the following rules upon the preceding hooks:
@@ -15,6 +16,14 @@ use crate::key::{Key, KeyParser};
 /// Represents a --withhold argument.
 pub(super) struct WithholdArg {
     pub keys: Vec<Key>,
+    /// If true, all preceding hooks track their keys with strict channels.
+    pub strict_channels: bool,
+    pub lost_mode: LostMode,
+    /// Withheld events matching these keys get released instead of dropped when a hook activates.
+    /// Specified by the passthrough-on-activate flag or clause.
+    pub passthrough_keys: Vec<Key>,
+    /// If true, the state transitions of the withheld channels get printed. Specified by the debug flag.
+    pub debug: bool,
     /// All the triggers of all --hook arguments that come before a --withhold argument.
     pub associated_triggers: Vec<Trigger>,
 }
@@ -22,8 +31,8 @@ pub(super) struct WithholdArg {
 impl WithholdArg {
 	pub fn parse(args: Vec<String>) -> Result<WithholdArg, ArgumentError> {
         let arg_group = ComplexArgGroup::parse(args,
-            &[],
-            &[],
+            &["strict-channels", "passthrough-on-activate", "debug"],
+            &["device-lost", "passthrough-on-activate"],
             false,
             true,
         )?;
@@ -32,7 +41,41 @@ impl WithholdArg {
         parser.type_whitelist = Some(vec![EventType::KEY]);
         let keys = parser.parse_all(&arg_group.get_keys_or_empty_key())?;
 
-        Ok(WithholdArg { keys, associated_triggers: Vec::new() })
+        // Without a value, the passthrough-on-activate flag applies to all keys of this --withhold.
+        if arg_group.has_flag("passthrough-on-activate") && ! arg_group.get_clauses("passthrough-on-activate").is_empty() {
+            return Err(ArgumentError::new("Cannot specify both the passthrough-on-activate flag and a passthrough-on-activate clause."));
+        }
+        let passthrough_keys = match arg_group.has_flag("passthrough-on-activate") {
+            true => keys.clone(),
+            false => {
+                let key_strs: Vec<String> = arg_group.get_clauses("passthrough-on-activate").iter()
+                    .flat_map(|value| value.split(','))
+                    .map(str::to_owned)
+                    .collect();
+                if key_strs.iter().any(String::is_empty) {
+                    return Err(ArgumentError::new("The passthrough-on-activate= clause of a --withhold argument cannot contain empty keys."));
+                }
+                parser.parse_all(&key_strs)?
+            },
+        };
+
+        let lost_mode = match arg_group.get_unique_clause("device-lost")? {
+            None => LostMode::Drop,
+            Some(mode) => match mode.as_str() {
+                "drop" => LostMode::Drop,
+                "flush" => LostMode::Flush,
+                _ => return Err(ArgumentError::new(format!(
+                    "Invalid value for device-lost: \"{}\". Valid values are \"drop\" and \"flush\".", mode
+                ))),
+            },
+        };
+
+        Ok(WithholdArg {
+            keys, lost_mode, passthrough_keys,
+            strict_channels: arg_group.has_flag("strict-channels"),
+            debug: arg_group.has_flag("debug"),
+            associated_triggers: Vec::new(),
+        })
     }
 
     pub fn associate_hooks(&mut self, hooks: &mut [&mut HookArg]) -> Result<(), ArgumentError> {
@@ -74,6 +117,12 @@ impl WithholdArg {
             }
         }
 
+        if self.strict_channels {
+            for hook_arg in hooks.iter_mut() {
+                hook_arg.strict_channels = true;
+            }
+        }
+
         self.associated_triggers.extend(
             hooks.iter_mut()
                  .map(|hook_arg| hook_arg.compile_trigger()
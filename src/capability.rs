@@ -83,7 +83,7 @@ impl RepeatInfo {
     }
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct AbsInfo {
     pub min_value: EventValue,
     pub max_value: EventValue,
@@ -170,6 +170,7 @@ impl Capabilities {
             let (value_range, abs_meta) = match abs_info {
                 None => match code.ev_type() {
                     EventType::KEY => (Interval::new(Some(0), Some(2)), None),
+                    EventType::EVSIEVE => (Interval::new(Some(0), Some(1)), None),
                     _ => (Interval::new(None, None), None),
                 },
                 Some(info) => (
@@ -278,26 +279,34 @@ impl Capabilities {
     /// To be true, `other` must have all event codes of `self` and identical absolute axes. Ignores the
     /// current value of absolute axes.
     pub fn is_compatible_with(&self, other: &Capabilities) -> bool {
-        if ! self.codes.is_subset(&other.codes) {
-            return false;
-        }
-        for (code, info) in &self.abs_info {
-            if let Some(other_info) = other.abs_info.get(code) {
+        self.diff_against(other).is_empty()
+    }
+
+    /// Explains why we cannot properly write all events corresponding to the capabilities of `self` to a
+    /// device that has output capabilities `other`. The returned diff is empty if and only if `self` is
+    /// compatible with `other`.
+    pub fn diff_against(&self, other: &Capabilities) -> CapabilitiesDiff {
+        let mut missing_codes: Vec<EventCode> = self.codes.difference(&other.codes).cloned().collect();
+        missing_codes.sort();
+        let mut diff = CapabilitiesDiff { missing_codes, changed_axes: Vec::new() };
+
+        for (code, info) in self.abs_info_to_sorted_vec() {
+            if let Some(other_info) = other.abs_info.get(&code) {
                 // Avoid getting incompatibility due to a different meta.value, but do compare all
                 // other properties of the absolute axes.
                 let mut other_info: AbsInfo = *other_info;
                 other_info.meta.value = info.meta.value;
 
-                if *info != other_info {
-                    return false;
+                if info != other_info {
+                    diff.changed_axes.push(AxisChange { code, old: other_info, new: info });
                 }
-            } else {
-                return false;
+            } else if other.codes.contains(&code) {
+                diff.missing_codes.push(code);
             }
         }
         // We don't care about self.rep_info because the kernel doesn't either.
 
-        true
+        diff
     }
 
     /// Tells you whether these capabilities are equal to the other capabilities up to the current state of
@@ -323,6 +332,74 @@ impl Capabilities {
     }
 }
 
+/// The reasons why some capabilities are not compatible with the capabilities of an existing device,
+/// as computed by `Capabilities::diff_against()`.
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct CapabilitiesDiff {
+    /// Event codes that the existing device does not have, sorted by event code.
+    pub missing_codes: Vec<EventCode>,
+    /// Absolute axes that the existing device has with different properties.
+    pub changed_axes: Vec<AxisChange>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AxisChange {
+    pub code: EventCode,
+    /// The properties of this axis on the existing device.
+    pub old: AbsInfo,
+    /// The properties that this axis needs to have.
+    pub new: AbsInfo,
+}
+
+impl CapabilitiesDiff {
+    pub fn is_empty(&self) -> bool {
+        self.missing_codes.is_empty() && self.changed_axes.is_empty()
+    }
+}
+
+/// Formats the diff like "new events: key:a, key:b; changed axes: abs:x (range 0~255 -> -512~511)".
+impl std::fmt::Display for CapabilitiesDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut parts: Vec<String> = Vec::new();
+        if ! self.missing_codes.is_empty() {
+            let names: Vec<String> = self.missing_codes.iter()
+                .map(|&code| ecodes::event_name(code).into_owned())
+                .collect();
+            parts.push(format!("new events: {}", names.join(", ")));
+        }
+        if ! self.changed_axes.is_empty() {
+            let axes: Vec<String> = self.changed_axes.iter().map(AxisChange::to_string).collect();
+            parts.push(format!("changed axes: {}", axes.join(", ")));
+        }
+        match parts.is_empty() {
+            true => write!(f, "no changes"),
+            false => write!(f, "{}", parts.join("; ")),
+        }
+    }
+}
+
+/// Formats the change like "abs:x (range 0~255 -> -512~511, resolution 0 -> 12)".
+impl std::fmt::Display for AxisChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let (old, new) = (self.old, self.new);
+        let mut changes: Vec<String> = Vec::new();
+        if (old.min_value, old.max_value) != (new.min_value, new.max_value) {
+            changes.push(format!("range {}~{} -> {}~{}", old.min_value, old.max_value, new.min_value, new.max_value));
+        }
+        let properties = [
+            ("fuzz", old.meta.fuzz, new.meta.fuzz),
+            ("flat", old.meta.flat, new.meta.flat),
+            ("resolution", old.meta.resolution, new.meta.resolution),
+        ];
+        for &(name, old_value, new_value) in &properties {
+            if old_value != new_value {
+                changes.push(format!("{} {} -> {}", name, old_value, new_value));
+            }
+        }
+        write!(f, "{} ({})", ecodes::event_name(self.code), changes.join(", "))
+    }
+}
+
 #[derive(Clone, PartialEq, Eq, Hash, Debug)]
 pub struct Capability {
     pub code: EventCode,
@@ -393,9 +470,53 @@ pub fn aggregate_capabilities(capabilities: Vec<Capability>) -> Vec<Capability>
 }
 
 /// Given an InputCapabilites, generates a vector that contains every discrete capability that can be
-/// generated by the corresponding input devices.
+/// generated by the corresponding input devices, including the evsieve:device-* events that evsieve
+/// generates on behalf of those devices.
 pub fn input_caps_to_vec(caps: &InputCapabilites) -> Vec<Capability> {
     caps.iter()
-        .flat_map(|(domain, caps)| caps.to_vec_from_domain_and_namespace(*domain, Namespace::Input))
+        .flat_map(|(domain, caps)| {
+            let status_caps = [EventCode::DEVICE_CONNECTED, EventCode::DEVICE_LOST].iter().map(
+                move |&code| Capability {
+                    code, domain: *domain, values: Set::from(Interval::new(Some(0), Some(1))),
+                    abs_meta: None, namespace: Namespace::Input,
+                }
+            );
+            caps.to_vec_from_domain_and_namespace(*domain, Namespace::Input).into_iter().chain(status_caps)
+        })
         .collect()
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_capabilities_diff() {
+    let key_a = EventCode::new(EventType::KEY, 30);
+    let key_b = EventCode::new(EventType::KEY, 48);
+    let abs_x = EventCode::new(EventType::ABS, 0);
+    let axis = |min_value, max_value, resolution| AbsInfo {
+        min_value, max_value,
+        meta: AbsMeta { fuzz: 0, flat: 0, resolution, value: 0 },
+    };
+    let caps = |codes: &[EventCode], abs_x_info: AbsInfo| {
+        let mut caps = Capabilities::new();
+        caps.codes = codes.iter().cloned().collect();
+        caps.abs_info.insert(abs_x, abs_x_info);
+        caps
+    };
+
+    let old = caps(&[key_a, abs_x], axis(0, 255, 0));
+    assert!(old.diff_against(&old).is_empty());
+    assert!(caps(&[abs_x], axis(0, 255, 0)).is_compatible_with(&old));
+    // The current value of an axis does not matter.
+    let mut moved = caps(&[key_a, abs_x], axis(0, 255, 0));
+    moved.abs_info.get_mut(&abs_x).unwrap().meta.value = 17;
+    assert!(moved.is_compatible_with(&old));
+
+    let new = caps(&[key_a, key_b, abs_x], axis(-512, 511, 12));
+    let diff = new.diff_against(&old);
+    assert_eq!(diff.missing_codes, vec![key_b]);
+    assert_eq!(diff.changed_axes, vec![AxisChange { code: abs_x, old: axis(0, 255, 0), new: axis(-512, 511, 12) }]);
+    assert!(! new.is_compatible_with(&old));
+    assert_eq!(
+        diff.to_string(),
+        "new events: key:b; changed axes: abs:x (range 0~255 -> -512~511, resolution 0 -> 12)"
+    );
+}
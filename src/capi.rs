@@ -0,0 +1,200 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! A C API that allows other programs to use the event processing engine of evsieve without any
+//! input or output devices being involved. The declarations of these functions can be found in
+//! include/evsieve.h.
+//!
+//! The program embedding evsieve pushes events into the engine and pulls the processed events out.
+//! All functions catch panics, because unwinding into C code is undefined behaviour.
+
+use std::cell::RefCell;
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_int};
+use std::panic::AssertUnwindSafe;
+
+use crate::bindings::libevdev;
+use crate::engine::Engine;
+use crate::error::{ArgumentError, RuntimeError};
+#[cfg(test)]
+use crate::{ecodes, event::{EventCode, EventType}};
+
+thread_local! {
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+fn set_last_error(message: String) {
+    // A CString cannot contain interior null bytes, so they are removed from the message.
+    let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+    LAST_ERROR.with(|last_error| *last_error.borrow_mut() = Some(message));
+}
+
+/// Runs a function, storing its error or panic as the last error. Returns `on_error` if the function failed.
+fn guard<T>(on_error: T, function: impl FnOnce() -> Result<T, RuntimeError>) -> T {
+    match std::panic::catch_unwind(AssertUnwindSafe(function)) {
+        Ok(Ok(value)) => value,
+        Ok(Err(error)) => {
+            set_last_error(error.to_string());
+            on_error
+        },
+        Err(_) => {
+            set_last_error("Internal error: a panic happened. This is a bug.".to_owned());
+            on_error
+        },
+    }
+}
+
+/// Interprets a nullable C string.
+unsafe fn optional_str<'a>(string: *const c_char) -> Result<Option<&'a str>, ArgumentError> {
+    if string.is_null() {
+        return Ok(None);
+    }
+    CStr::from_ptr(string).to_str()
+        .map(Some)
+        .map_err(|_| ArgumentError::new("Strings passed to evsieve must be valid UTF-8."))
+}
+
+/// # Safety
+/// `argv` must point to `argc` valid null-terminated strings.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_new(argc: c_int, argv: *const *const c_char) -> *mut Engine {
+    guard(std::ptr::null_mut(), || {
+        let mut args = Vec::new();
+        for index in 0 .. argc.max(0) as usize {
+            match optional_str(*argv.add(index))? {
+                Some(arg) => args.push(arg.to_owned()),
+                None => return Err(ArgumentError::new("The arguments passed to evsieve_engine_new() cannot be null.").into()),
+            }
+        }
+        Ok(Box::into_raw(Box::new(Engine::new(args)?)))
+    })
+}
+
+/// # Safety
+/// `engine` must have been returned by `evsieve_engine_new()` and not have been freed yet, or be null.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_free(engine: *mut Engine) {
+    if ! engine.is_null() {
+        guard((), || {
+            drop(Box::from_raw(engine));
+            Ok(())
+        })
+    }
+}
+
+/// # Safety
+/// `engine` and `event` must be valid pointers. `domain` must be a valid string or null.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_push(
+    engine: *mut Engine, domain: *const c_char, event: *const libevdev::input_event
+) -> c_int {
+    guard(-1, || {
+        let engine = &mut *engine;
+        let event = &*event;
+        engine.push(optional_str(domain)?, event.type_, event.code, event.value)?;
+        Ok(0)
+    })
+}
+
+/// # Safety
+/// `engine` must be a valid pointer, and `events` must have room for at least `capacity` events.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_pull(
+    engine: *mut Engine, events: *mut libevdev::input_event, capacity: usize
+) -> usize {
+    guard(0, || {
+        let pulled = (*engine).pull(capacity);
+        for (index, event) in pulled.iter().enumerate() {
+            *events.add(index) = *event;
+        }
+        Ok(pulled.len())
+    })
+}
+
+/// # Safety
+/// `engine` must be a valid pointer.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_timeout(engine: *mut Engine) -> c_int {
+    guard(-1, || {
+        Ok((*engine).timeout().unwrap_or(-1))
+    })
+}
+
+/// # Safety
+/// `engine` must be a valid pointer. `domain` must be a valid string or null.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_add_capability(
+    engine: *mut Engine, domain: *const c_char, ev_type: u16, code: u16, min: i32, max: i32
+) -> c_int {
+    guard(-1, || {
+        (*engine).add_capability(optional_str(domain)?, ev_type, code, min, max)?;
+        Ok(0)
+    })
+}
+
+/// # Safety
+/// `engine` must be a valid pointer, and `buffer` must have room for `size` bytes or be null if `size` is zero.
+#[no_mangle]
+pub unsafe extern "C" fn evsieve_engine_capabilities(engine: *mut Engine, buffer: *mut c_char, size: usize) -> usize {
+    guard(0, || {
+        let mut description = String::new();
+        for line in (*engine).describe_capabilities() {
+            description.push_str(&line);
+            description.push('\n');
+        }
+        if size > 0 {
+            let length = description.len().min(size - 1);
+            std::ptr::copy_nonoverlapping(description.as_ptr() as *const c_char, buffer, length);
+            *buffer.add(length) = 0;
+        }
+        Ok(description.len())
+    })
+}
+
+/// Returns the last error that happened on this thread, or null if none happened. The string remains
+/// valid until the next call to an evsieve function on this thread.
+#[no_mangle]
+pub extern "C" fn evsieve_last_error() -> *const c_char {
+    LAST_ERROR.with(|last_error| match &*last_error.borrow() {
+        Some(message) => message.as_ptr(),
+        None => std::ptr::null(),
+    })
+}
+
+#[test]
+fn unittest() {
+    let args: Vec<CString> = ["--map", "key:a@kb", "key:b", "--block", "key:c"].iter()
+        .map(|arg| CString::new(*arg).unwrap())
+        .collect();
+    let argv: Vec<*const c_char> = args.iter().map(|arg| arg.as_ptr()).collect();
+    let domain = CString::new("kb").unwrap();
+    let key_a = ecodes::event_code("key", "a").unwrap();
+    let key_b = ecodes::event_code("key", "b").unwrap();
+
+    unsafe {
+        let engine = evsieve_engine_new(argv.len() as c_int, argv.as_ptr());
+        assert!(! engine.is_null());
+
+        let event = |code: EventCode, value| libevdev::input_event {
+            time: libevdev::timeval { tv_sec: 0, tv_usec: 0 },
+            type_: code.ev_type().into(), code: code.code(), value,
+        };
+        assert_eq!(evsieve_engine_push(engine, domain.as_ptr(), &event(key_a, 1)), 0);
+        assert_eq!(evsieve_engine_push(engine, std::ptr::null(), &event(EventCode::new(EventType::SYN, 0), 0)), 0);
+
+        let mut output = [event(key_a, 0); 4];
+        assert_eq!(evsieve_engine_pull(engine, output.as_mut_ptr(), output.len()), 2);
+        assert_eq!((output[0].code, output[0].value), (key_b.code(), 1));
+        assert_eq!(output[1].type_, ecodes::EV_SYN);
+
+        assert_eq!(evsieve_engine_add_capability(engine, domain.as_ptr(), ecodes::EV_KEY, key_a.code(), 0, 2), 0);
+        let mut buffer = [0 as c_char; 64];
+        let length = evsieve_engine_capabilities(engine, buffer.as_mut_ptr(), buffer.len());
+        assert_eq!(CStr::from_ptr(buffer.as_ptr()).to_bytes().len(), length);
+
+        evsieve_engine_free(engine);
+
+        let bad_args = [args[0].as_ptr()];
+        assert!(evsieve_engine_new(1, bad_args.as_ptr()).is_null());
+        assert!(! evsieve_last_error().is_null());
+    }
+}
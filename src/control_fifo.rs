@@ -6,18 +6,56 @@ use crate::error::{SystemError, ArgumentError, Context};
 use crate::io::fd::HasFixedFd;
 use crate::io::fifo::Fifo;
 use crate::arguments::hook::HookToggleAction;
+use crate::arguments::input::parse_time_scale;
 use crate::stream::Setup;
+use crate::io::output::OutputSystem;
 use crate::io::fifo::LineRead;
+use crate::io::input::InputDevice;
+use crate::time::Instant;
+use crate::domain::{self, Domain};
+use crate::event::{EventCode, EventValue};
+use crate::ecodes;
+use crate::utils::split_once;
+use crate::privileges::{self, FileOwner};
+
+/// The names of all commands that can be written to a control FIFO.
+pub const COMMAND_NAMES: &[&str] = &[
+    "apply", "toggle", "stats", "send", "warp", "recenter", "time-scale", "persist", "revoke", "reacquire",
+];
+
+/// A control FIFO as specified by a --control-fifo argument, before it has been opened.
+pub struct PreControlFifo {
+    pub path: String,
+    /// If Some, the FIFO gets these permissions after it has been opened or created.
+    pub mode: Option<u32>,
+    /// If Some, the FIFO gets this owner after it has been opened or created.
+    pub owner: Option<FileOwner>,
+    /// The names of the commands that may be written to this FIFO, or None if all commands are allowed.
+    pub allowed_commands: Option<Vec<String>>,
+}
 
 pub struct ControlFifo {
     source: Box<dyn LineRead>,
     path: String,
+    allowed_commands: Option<Vec<String>>,
 }
 
 impl ControlFifo {
-    pub fn create(path: String) -> Result<ControlFifo, SystemError> {
-        let source = Box::new(Fifo::open_or_create(&path)?);
-        Ok(ControlFifo { path, source })
+    pub fn create(pre_fifo: PreControlFifo) -> Result<ControlFifo, SystemError> {
+        let PreControlFifo { path, mode, owner, allowed_commands } = pre_fifo;
+        let source = Box::new(privileges::create_with_owner_and_mode(
+            &path, owner, mode, || Fifo::open_or_create(&path),
+        )?);
+        Ok(ControlFifo { path, source, allowed_commands })
+    }
+
+    /// Returns an error if the command on the given line may not be written to this FIFO.
+    fn check_allowed(&self, line: &str) -> Result<(), ArgumentError> {
+        match &self.allowed_commands {
+            Some(allowed_commands) => check_command_allowed(line, allowed_commands)
+                .map_err(|error| error.with_context(format!("While reading from the control FIFO at {}:", self.path))),
+            None => Ok(()),
+        }
     }
 
     /// IMPORTANT: this function should never return ArgumentError, because then the fifo would
@@ -27,7 +65,7 @@ impl ControlFifo {
         let lines = self.source.read_lines()?;
         let commands = lines.into_iter()
             .filter(|line| !line.is_empty())
-            .filter_map(|line| match parse_command(&line) {
+            .filter_map(|line| match self.check_allowed(&line).and_then(|_| parse_command(&line)) {
                 Ok(effect) => Some(CommandInfo {
                     original_line: line,
                     action: effect
@@ -55,6 +93,69 @@ pub struct CommandInfo {
 
 pub enum Command {
     Toggle(HookToggleAction),
+    /// Prints statistics about the input devices to stdout.
+    DeviceStats,
+    /// Prints the histograms recorded by --histogram arguments to stdout.
+    Histograms,
+    /// Prints whether and why the output devices have been recreated to stdout.
+    OutputStats,
+    /// Closes the input devices matching the domain, or all input devices if None, so other programs
+    /// can use them. Handled by the main loop because it requires access to the epoll.
+    Revoke(Option<Domain>),
+    /// Reopens the input devices matching the domain that were previously closed by Revoke.
+    Reacquire(Option<Domain>),
+    /// Feeds events through the stream as if they were received from the input device with the given
+    /// domain, or the only input device if None. Handled by the main loop because it requires mutable
+    /// access to the input devices.
+    Send(Vec<(EventCode, EventValue, Option<Domain>)>),
+    /// Compiles the arguments and inserts them into the stream after the --stage argument of the given
+    /// stage, or at the end of the stream if None. Handled by the main loop because it needs to know
+    /// the sandbox mode.
+    Apply { at_stage: Option<String>, args: Vec<String> },
+    /// Moves the pointer of the output devices with an abs-pointer= clause to the given position,
+    /// optionally clicking there.
+    Warp { x: i32, y: i32, click: bool },
+    /// Returns the output of the --rel-to-abs arguments with the given domain, or all of them if None,
+    /// to the center of their range.
+    Recenter(Option<Domain>),
+    /// Multiplies the periods of arguments like --delay and the intervals between replayed events by the
+    /// given factor. Handled by the main loop because it requires access to the replay devices.
+    TimeScale(f64),
+    /// Prints which devices the persistence subsystem is waiting for. Handled by the main loop because the
+    /// persistence subsystem is registered with the epoll.
+    PersistStatus,
+    /// Makes the persistence subsystem try to reopen all devices it is waiting for right away.
+    PersistRetry,
+}
+
+/// Decides which input device sends the events of a send command. Given the domains that the user assigned
+/// to the open input devices, returns the index of the device that has the requested domain, or of the
+/// only open device if no domain was requested.
+pub fn find_send_target(target_domains: &[Domain], domain: Option<Domain>) -> Result<usize, ArgumentError> {
+    match domain {
+        Some(domain) => target_domains.iter().position(|&target_domain| domain::matches(target_domain, domain))
+            .ok_or_else(|| ArgumentError::new(format!(
+                "No open input device has the domain \"{}\".", domain::try_reverse_resolve(domain).unwrap_or_default()
+            ))),
+        None => match target_domains {
+            [_] => Ok(0),
+            [] => Err(ArgumentError::new("No input devices are currently open.")),
+            _ => Err(ArgumentError::new("Multiple input devices are open. Please specify the domain of the device that should send the events, like \"send key:a:1 @kb\".")),
+        },
+    }
+}
+
+/// Returns an error if the line contains a command that is not among the allowed commands. Lines with
+/// unknown commands are left for parse_command() to complain about.
+fn check_command_allowed(line: &str, allowed_commands: &[String]) -> Result<(), ArgumentError> {
+    let command = match line.split_whitespace().next() {
+        Some(command) => command,
+        None => return Ok(()),
+    };
+    if COMMAND_NAMES.contains(&command) && ! allowed_commands.iter().any(|allowed| allowed == command) {
+        return Err(ArgumentError::new(format!("The {} command is not allowed on this control FIFO.", command)));
+    }
+    Ok(())
 }
 
 fn parse_command(line: &str) -> Result<Command, ArgumentError> {
@@ -66,6 +167,20 @@ fn parse_command(line: &str) -> Result<Command, ArgumentError> {
     let args: Vec<&str> = parts.collect();
 
     match command {
+        "apply" => {
+            // The arguments may contain quoted clauses like exec-shell="echo Hello", so they are not
+            // split by whitespace.
+            let args_str = line.trim_start()[command.len() ..].trim_start();
+            let mut args = crate::utils::shelllex::lex(args_str)?;
+            let at_stage = args.first().and_then(|arg| arg.strip_prefix("at=")).map(str::to_owned);
+            if at_stage.is_some() {
+                args.remove(0);
+            }
+            if args.is_empty() {
+                return Err(ArgumentError::new("The apply command requires at least one argument like \"--map key:a key:b\"."));
+            }
+            Ok(Command::Apply { at_stage, args })
+        },
         "toggle" => {
             let has_toggle_flag = args.is_empty();
             let toggle_clauses = args.into_iter().map(str::to_owned).collect();
@@ -73,28 +188,223 @@ fn parse_command(line: &str) -> Result<Command, ArgumentError> {
                 HookToggleAction::parse(has_toggle_flag, toggle_clauses)?
             ))
         },
+        "stats" => match args.as_slice() {
+            ["devices"] => Ok(Command::DeviceStats),
+            ["histograms"] => Ok(Command::Histograms),
+            ["outputs"] => Ok(Command::OutputStats),
+            _ => Err(ArgumentError::new("The stats command requires exactly one argument: \"devices\", \"histograms\" or \"outputs\".")),
+        },
+        "send" => {
+            // A trailing @DOMAIN applies to all events that do not specify a domain themselves.
+            let (event_strs, default_domain) = match args.split_last() {
+                Some((last, rest)) if last.starts_with('@') => (rest, Some(domain::resolve(&last[1..])?)),
+                _ => (args.as_slice(), None),
+            };
+            if event_strs.is_empty() {
+                return Err(ArgumentError::new("The send command requires at least one event like \"key:a:1\"."));
+            }
+            let events = event_strs.iter()
+                .map(|event_str| parse_event(event_str, default_domain))
+                .collect::<Result<Vec<_>, ArgumentError>>()?;
+            Ok(Command::Send(events))
+        },
+        "warp" => {
+            let (position, click) = match args.as_slice() {
+                [x, y] => ((x, y), false),
+                [x, y, "click"] => ((x, y), true),
+                _ => return Err(ArgumentError::new("The warp command requires an X and Y coordinate, optionally followed by \"click\", like \"warp 960 540 click\".")),
+            };
+            let parse_coordinate = |coordinate_str: &str| coordinate_str.parse::<i32>().map_err(|_| ArgumentError::new(format!(
+                "Cannot interpret \"{}\" as a coordinate.", coordinate_str
+            )));
+            Ok(Command::Warp { x: parse_coordinate(position.0)?, y: parse_coordinate(position.1)?, click })
+        },
+        "recenter" => match args.as_slice() {
+            [] => Ok(Command::Recenter(None)),
+            [domain_str] => Ok(Command::Recenter(Some(domain::resolve(domain_str.strip_prefix('@').unwrap_or(domain_str))?))),
+            _ => Err(ArgumentError::new("The recenter command takes at most one argument: the domain of the --rel-to-abs output events to recenter.")),
+        },
+        "time-scale" => match args.as_slice() {
+            [factor_str] => Ok(Command::TimeScale(parse_time_scale(factor_str)?)),
+            _ => Err(ArgumentError::new("The time-scale command requires exactly one argument: a factor like \"0.5\" or \"2\".")),
+        },
+        "persist" => match args.as_slice() {
+            ["status"] => Ok(Command::PersistStatus),
+            ["retry"] => Ok(Command::PersistRetry),
+            _ => Err(ArgumentError::new("The persist command requires exactly one argument: \"status\" or \"retry\".")),
+        },
+        "revoke" | "reacquire" => {
+            let domain = match args.as_slice() {
+                [] => None,
+                [domain_str] => Some(domain::resolve(domain_str.strip_prefix('@').unwrap_or(domain_str))?),
+                _ => return Err(ArgumentError::new(format!("The {} command takes at most one argument: the domain of the input devices to {}.", command, command))),
+            };
+            match command {
+                "revoke" => Ok(Command::Revoke(domain)),
+                _ => Ok(Command::Reacquire(domain)),
+            }
+        },
         _ => Err(ArgumentError::new(format!("Unknown command name: {}", command))),
     }
 }
 
+/// Parses an event like "key:a:1" or "key:a:1@kb". Unlike keys, the event must have an exact type, code
+/// and value.
+fn parse_event(event_str: &str, default_domain: Option<Domain>) -> Result<(EventCode, EventValue, Option<Domain>), ArgumentError> {
+    let (event_part, domain_str) = split_once(event_str, "@");
+    let domain = match domain_str {
+        Some(domain_str) => Some(domain::resolve(domain_str)?),
+        None => default_domain,
+    };
+    let parts: Vec<&str> = event_part.split(':').collect();
+    let (type_name, code_name, value_str) = match parts.as_slice() {
+        [type_name, code_name, value_str] => (type_name, code_name, value_str),
+        _ => return Err(ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as an event. Events must be specified as TYPE:CODE:VALUE, like \"key:a:1\".", event_str
+        ))),
+    };
+    let code = ecodes::event_code(type_name, code_name)?;
+    if code.ev_type().is_internal() {
+        return Err(ArgumentError::new(format!("Cannot send the event \"{}\": only events that can be emitted by input devices can be sent.", event_str)));
+    }
+    let value: EventValue = value_str.parse().map_err(|_| ArgumentError::new(format!(
+        "Cannot interpret \"{}\" as the value of an event.", value_str
+    )))?;
+    Ok((code, value, domain))
+}
+
 impl Command {
-    pub fn execute<T>(self, setup: &mut Setup<T>) -> Result<(), ArgumentError> {
+    pub fn execute<'a, T: OutputSystem>(self, setup: &mut Setup<T>, input_devices: impl Iterator<Item=&'a InputDevice>) -> Result<(), ArgumentError> {
         match self {
             Command::Toggle(action) => {
                 let effects = action.implement(setup.state(), setup.toggle_indices())?;
                 for effect in effects {
                     effect(setup.state_mut());
                 }
-            }
+            },
+            Command::DeviceStats => {
+                print_device_stats(input_devices);
+            },
+            Command::Histograms => {
+                let reports = setup.histogram_reports();
+                if reports.is_empty() {
+                    return Err(ArgumentError::new("No --histogram arguments have been specified."));
+                }
+                println!("{}", reports.join("\n"));
+            },
+            Command::OutputStats => {
+                let reports = setup.output_recreation_reports();
+                match reports.is_empty() {
+                    true => println!("No output devices have been specified."),
+                    false => println!("{}", reports.join("\n")),
+                }
+            },
+            Command::Warp { x, y, click } => {
+                setup.warp(x, y, click)?;
+            },
+            Command::Recenter(domain) => {
+                setup.recenter(domain)?;
+            },
+            Command::Revoke(_) | Command::Reacquire(_) | Command::Send(_) | Command::Apply { .. } | Command::TimeScale(_)
+            | Command::PersistStatus | Command::PersistRetry => {
+                return Err(ArgumentError::new("Internal error: the revoke, reacquire, send, apply, time-scale and persist commands must be handled by the main loop."));
+            },
         }
 
         Ok(())
     }
 }
 
+/// Prints one line of statistics for each device. Also used by the --stats argument.
+pub fn print_device_stats<'a>(input_devices: impl Iterator<Item=&'a InputDevice>) {
+    let now = Instant::now();
+    let lines: Vec<String> = input_devices.map(|device| device.describe_stats(now)).collect();
+    match lines.is_empty() {
+        true => println!("No input devices are currently open."),
+        false => println!("{}", lines.join("\n")),
+    }
+}
+
 impl AsRawFd for ControlFifo {
     fn as_raw_fd(&self) -> RawFd {
         self.source.as_raw_fd()
     }
 }
-unsafe impl HasFixedFd for ControlFifo {}
\ No newline at end of file
+unsafe impl HasFixedFd for ControlFifo {}
+
+#[test]
+fn unittest() {
+    let key_a = ecodes::event_code("key", "a").unwrap();
+    let btn_left = ecodes::event_code("btn", "left").unwrap();
+    let kb = domain::resolve("kb").unwrap();
+    let ms = domain::resolve("ms").unwrap();
+
+    match parse_command("send key:a:1").unwrap() {
+        Command::Send(events) => assert_eq!(events, vec![(key_a, 1, None)]),
+        _ => panic!(),
+    }
+    match parse_command("send key:a:1 btn:left:0@ms @kb").unwrap() {
+        Command::Send(events) => assert_eq!(events, vec![(key_a, 1, Some(kb)), (btn_left, 0, Some(ms))]),
+        _ => panic!(),
+    }
+    assert!(parse_command("send").is_err());
+    assert!(parse_command("send @kb").is_err());
+    assert!(parse_command("send key:a").is_err());
+    assert!(parse_command("send key:a:pressed").is_err());
+    assert!(parse_command("send evsieve:device-lost:1").is_err());
+
+    match parse_command("apply --map key:a key:b --hook key:c exec-shell=\"echo Hello\"").unwrap() {
+        Command::Apply { at_stage, args } => {
+            assert_eq!(at_stage, None);
+            assert_eq!(args, vec!["--map", "key:a", "key:b", "--hook", "key:c", "exec-shell=echo Hello"]);
+        },
+        _ => panic!(),
+    }
+    match parse_command("apply at=live --block key:a").unwrap() {
+        Command::Apply { at_stage, args } => {
+            assert_eq!(at_stage.as_deref(), Some("live"));
+            assert_eq!(args, vec!["--block", "key:a"]);
+        },
+        _ => panic!(),
+    }
+    assert!(parse_command("apply").is_err());
+    assert!(parse_command("apply at=live").is_err());
+    assert!(parse_command("apply --map key:a \"key:b").is_err());
+
+    match parse_command("warp 960 540 click").unwrap() {
+        Command::Warp { x, y, click } => assert_eq!((x, y, click), (960, 540, true)),
+        _ => panic!(),
+    }
+    match parse_command("warp 0 10").unwrap() {
+        Command::Warp { x, y, click } => assert_eq!((x, y, click), (0, 10, false)),
+        _ => panic!(),
+    }
+    assert!(parse_command("warp 960").is_err());
+    assert!(parse_command("warp 960 540 tap").is_err());
+    assert!(parse_command("warp x 540").is_err());
+
+    assert!(matches!(parse_command("recenter").unwrap(), Command::Recenter(None)));
+    assert!(matches!(parse_command("recenter @kb").unwrap(), Command::Recenter(Some(domain)) if domain == kb));
+    assert!(matches!(parse_command("recenter kb").unwrap(), Command::Recenter(Some(domain)) if domain == kb));
+    assert!(parse_command("recenter kb ms").is_err());
+
+    match parse_command("time-scale 0.5").unwrap() {
+        Command::TimeScale(factor) => assert_eq!(factor, 0.5),
+        _ => panic!(),
+    }
+    assert!(parse_command("time-scale").is_err());
+    assert!(parse_command("time-scale 0").is_err());
+    assert!(parse_command("time-scale fast").is_err());
+
+    assert!(matches!(parse_command("persist status").unwrap(), Command::PersistStatus));
+    assert!(matches!(parse_command("persist retry").unwrap(), Command::PersistRetry));
+    assert!(parse_command("persist").is_err());
+    assert!(parse_command("persist status retry").is_err());
+
+    let allowed_commands = vec!["toggle".to_owned(), "stats".to_owned()];
+    assert!(check_command_allowed("toggle mode", &allowed_commands).is_ok());
+    assert!(check_command_allowed("  stats devices", &allowed_commands).is_ok());
+    assert!(check_command_allowed("apply --map key:a key:b", &allowed_commands).is_err());
+    assert!(check_command_allowed("revoke", &allowed_commands).is_err());
+    assert!(check_command_allowed("toggles", &allowed_commands).is_ok());
+}
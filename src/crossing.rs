@@ -0,0 +1,197 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! This module is intended for handling keys that trigger when an axis crosses some threshold, such as
+//!     --map abs:y:crosses=0:+ key:up:1
+
+use std::collections::HashMap;
+
+use crate::error::ArgumentError;
+use crate::event::{Channel, Event};
+use crate::range::Interval;
+use crate::utils;
+
+/// Represents a value like the "crosses=-5~5:+" part of "abs:y:crosses=-5~5:+".
+#[derive(Clone, Copy, Debug)]
+pub struct Crossing {
+    /// Values below `band.min` are below the threshold, values of at least `band.max` are above the
+    /// threshold. Values within the band are neither and do not change which side the axis is on.
+    band: Interval,
+    direction: CrossingDirection,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CrossingDirection {
+    /// Only crossings from below to above the threshold, specified by "+".
+    Upward,
+    /// Only crossings from above to below the threshold, specified by "-".
+    Downward,
+    /// Crossings in either direction, specified by omitting the direction.
+    Both,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Side {
+    Below,
+    Above,
+}
+
+impl Crossing {
+    /// Creates a crossing whose threshold lies at `band.max` when crossed upwards and at `band.min`
+    /// when crossed downwards.
+    pub fn new(band: Interval, direction: CrossingDirection) -> Crossing {
+        Crossing { band, direction }
+    }
+
+    /// Returns on which side of the threshold a value lies, or None if it lies within the tolerance band.
+    fn side_of(&self, value: i32) -> Option<Side> {
+        if value < self.band.min {
+            Some(Side::Below)
+        } else if value >= self.band.max {
+            Some(Side::Above)
+        } else {
+            None
+        }
+    }
+
+    /// Returns whether going from the first side to the second side counts as a crossing.
+    fn is_crossing(&self, from: Side, to: Side) -> bool {
+        matches!(
+            (from, to, self.direction),
+            (Side::Below, Side::Above, CrossingDirection::Upward | CrossingDirection::Both)
+            | (Side::Above, Side::Below, CrossingDirection::Downward | CrossingDirection::Both)
+        )
+    }
+
+    /// Returns whether an event crosses the threshold based solely on its previous value.
+    /// Unlike a `CrossingTracker`, this cannot see crossings that happened in several steps
+    /// through the tolerance band.
+    pub fn matches(&self, event: &Event) -> bool {
+        match (self.side_of(event.previous_value), self.side_of(event.value)) {
+            (Some(from), Some(to)) => self.is_crossing(from, to),
+            _ => false,
+        }
+    }
+}
+
+/// Remembers for each channel on which side of the threshold it was last seen, so that crossings
+/// can be detected even if the axis passes through the tolerance band over several events.
+#[derive(Clone, Debug)]
+pub struct CrossingTracker {
+    crossing: Crossing,
+    last_sides: HashMap<Channel, Side>,
+}
+
+impl CrossingTracker {
+    pub fn new(crossing: Crossing) -> CrossingTracker {
+        CrossingTracker {
+            crossing,
+            last_sides: HashMap::new(),
+        }
+    }
+
+    /// Must be called for every event on the channels this tracker is interested in. Returns true
+    /// if this event made the axis cross the threshold.
+    pub fn update(&mut self, event: &Event) -> bool {
+        self.update_direction(event).is_some()
+    }
+
+    /// Like `update()`, but returns the direction in which the threshold was crossed: either
+    /// `CrossingDirection::Upward` or `CrossingDirection::Downward`.
+    pub fn update_direction(&mut self, event: &Event) -> Option<CrossingDirection> {
+        let channel = event.channel();
+        let last_side = match self.last_sides.get(&channel) {
+            Some(&side) => Some(side),
+            None => self.crossing.side_of(event.previous_value),
+        };
+        // Values within the tolerance band leave the axis on the side it was last seen on.
+        let new_side = self.crossing.side_of(event.value).or(last_side);
+        if let Some(new_side) = new_side {
+            self.last_sides.insert(channel, new_side);
+        }
+
+        match (last_side, new_side) {
+            (Some(last_side), Some(new_side)) if self.crossing.is_crossing(last_side, new_side) => match new_side {
+                Side::Above => Some(CrossingDirection::Upward),
+                Side::Below => Some(CrossingDirection::Downward),
+            },
+            _ => None,
+        }
+    }
+}
+
+/// Returns true if the value part of a key is a crossing, e.g. "crosses=0:+".
+pub fn is_crossing_str(value_str: &str) -> bool {
+    value_str.starts_with("crosses=")
+}
+
+/// Parses a string like "crosses=0:+" or "crosses=-5~5". The threshold is parsed by `parse_band`.
+pub fn parse_crossing(
+        value_str: &str,
+        parse_band: impl Fn(&str) -> Result<Interval, ArgumentError>
+) -> Result<Crossing, ArgumentError> {
+    let crossing_str = match value_str.strip_prefix("crosses=") {
+        Some(crossing_str) => crossing_str,
+        None => return Err(ArgumentError::new(format!("Cannot interpret \"{}\" as a crossing.", value_str))),
+    };
+    let (band_str, direction_str_opt) = utils::split_once(crossing_str, ":");
+    let direction = match direction_str_opt {
+        None => CrossingDirection::Both,
+        Some("+") => CrossingDirection::Upward,
+        Some("-") => CrossingDirection::Downward,
+        Some(other) => return Err(ArgumentError::new(format!(
+            "Invalid crossing direction \"{}\": the direction must be either \"+\" or \"-\".", other
+        ))),
+    };
+
+    if band_str.is_empty() {
+        return Err(ArgumentError::new("A threshold must be specified after \"crosses=\"."));
+    }
+    let band = parse_band(band_str)?;
+    if band.min == i32::MIN || band.max == i32::MAX {
+        return Err(ArgumentError::new(format!(
+            "The threshold \"{}\" of a crossing must be bounded on both sides.", band_str
+        )));
+    }
+
+    Ok(Crossing { band, direction })
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, Namespace};
+    let parse_band = |band_str: &str| -> Result<Interval, ArgumentError> {
+        let (min, max) = utils::split_once(band_str, "~");
+        let min: i32 = min.parse().unwrap();
+        let max: i32 = max.map(|max| max.parse().unwrap()).unwrap_or(min);
+        Ok(Interval::new(min, max))
+    };
+    let event = |previous_value, value| Event::new(
+        EventCode::new(crate::event::EventType::ABS, 1), value, previous_value,
+        crate::domain::get_unique_domain(), Namespace::User,
+    );
+
+    let upward = parse_crossing("crosses=0:+", parse_band).unwrap();
+    assert!(upward.matches(&event(-3, 5)));
+    assert!(upward.matches(&event(-1, 0)));
+    assert!(! upward.matches(&event(0, 5)));
+    assert!(! upward.matches(&event(5, -3)));
+
+    let either = parse_crossing("crosses=0", parse_band).unwrap();
+    assert!(either.matches(&event(5, -3)));
+    assert!(either.matches(&event(-3, 5)));
+
+    // Crossing through the tolerance band in several steps.
+    let mut tracker = CrossingTracker::new(parse_crossing("crosses=-5~5:+", parse_band).unwrap());
+    let domain = crate::domain::get_unique_domain();
+    let mut step = |previous_value, value| tracker.update(&Event::new(
+        EventCode::new(crate::event::EventType::ABS, 1), value, previous_value, domain, Namespace::User,
+    ));
+    assert!(! step(-10, -3));
+    assert!(! step(-3, 3));
+    assert!(step(3, 8));
+    assert!(! step(8, 2));
+    assert!(! step(2, 8));
+
+    assert!(parse_crossing("crosses=0:*", parse_band).is_err());
+    assert!(parse_crossing("crosses=", parse_band).is_err());
+}
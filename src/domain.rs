@@ -1,12 +1,27 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use std::collections::HashMap;
-use std::sync::Mutex;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
 use crate::error::ArgumentError;
+use crate::utils;
 
 #[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct Domain(usize);
 
+impl Domain {
+    /// Returns a number that identifies this domain, for handing domains to plugins.
+    pub fn to_raw(self) -> u64 {
+        self.0 as u64
+    }
+
+    /// The inverse of `to_raw()`.
+    pub fn from_raw(raw: u64) -> Domain {
+        Domain(raw as usize)
+    }
+}
+
 pub fn get_unique_domain() -> Domain {
     TRACKER.lock()
         .expect("Fatal error: internal mutex poisoned.")
@@ -31,8 +46,172 @@ pub fn try_reverse_resolve(domain: Domain) -> Option<String> {
         .try_reverse_resolve(domain)
 }
 
+/// Attaches tags to a domain, like the tags= clause of --input does. Afterwards, filters like "@tag"
+/// match events of that domain as well.
+pub fn add_tags(domain: Domain, tags: &[String]) -> Result<(), ArgumentError> {
+    let tag_domains = tags.iter()
+        .map(|tag| resolve(tag))
+        .collect::<Result<Vec<Domain>, ArgumentError>>()?;
+    let mut registry = TAGS.lock().expect("Fatal error: internal lock poisoned.");
+    for (tag, tag_domain) in tags.iter().zip(tag_domains) {
+        registry.tagged.entry(tag_domain).or_insert_with(HashSet::new).insert(domain);
+        let labels = registry.labels.entry(domain).or_insert_with(Vec::new);
+        if ! labels.contains(tag) {
+            labels.push(tag.clone());
+        }
+    }
+    registry.publish();
+    ANY_TAGS.store(true, Ordering::Release);
+    Ok(())
+}
+
+/// Returns true if `domain` equals `filter` or has been tagged with the name of `filter`.
+/// This is what a filter like "@foo" checks.
+pub fn matches(domain: Domain, filter: Domain) -> bool {
+    domain == filter || (ANY_TAGS.load(Ordering::Acquire) && is_tagged(domain, filter))
+}
+
+fn is_tagged(domain: Domain, tag: Domain) -> bool {
+    with_match_table(|table| table.tagged.get(&tag)
+        .map(|domains| domains.contains(&domain))
+        .unwrap_or(false)
+    )
+}
+
+/// Returns true if some event might match both the "@left" and the "@right" filters.
+pub fn may_overlap(left: Domain, right: Domain) -> bool {
+    if left == right {
+        return true;
+    }
+    if ! ANY_TAGS.load(Ordering::Acquire) {
+        return false;
+    }
+    with_match_table(|table| {
+        let domains_matching = |filter: Domain| -> HashSet<Domain> {
+            let mut domains: HashSet<Domain> = table.tagged.get(&filter).cloned().unwrap_or_default();
+            domains.insert(filter);
+            domains
+        };
+        ! domains_matching(left).is_disjoint(&domains_matching(right))
+    })
+}
+
+/// Represents the part after the @ of keys like "@kbd&left-half" or "key:a@usb*". Each part between
+/// the ampersands is a pattern that must match the name or one of the tags of the domain of an event.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct DomainExpression(usize);
+
+impl DomainExpression {
+    /// Returns true if this string should be interpreted as a domain expression rather than the
+    /// name of a single domain.
+    pub fn is_expression_str(expression_str: &str) -> bool {
+        expression_str.contains(&['&', '*', '?'][..])
+    }
+
+    pub fn parse(expression_str: &str) -> Result<DomainExpression, ArgumentError> {
+        let patterns: Vec<String> = expression_str.split('&').map(str::to_owned).collect();
+        if patterns.iter().any(String::is_empty) {
+            return Err(ArgumentError::new(format!(
+                "The domain expression \"{}\" contains an empty pattern. Patterns must be separated by a single \"&\".", expression_str
+            )));
+        }
+        if patterns.iter().any(|pattern| pattern.starts_with('@')) {
+            return Err(ArgumentError::new(format!("The domain expression \"{}\" may not contain another @.", expression_str)));
+        }
+        let mut registry = TAGS.lock().expect("Fatal error: internal lock poisoned.");
+        registry.expressions.push(patterns);
+        registry.publish();
+        Ok(DomainExpression(registry.expressions.len() - 1))
+    }
+
+    pub fn matches(&self, domain: Domain) -> bool {
+        with_match_table(|table| table.expressions.get(self.0)
+            .map(|domains| domains.contains(&domain))
+            .unwrap_or(false)
+        )
+    }
+}
+
 lazy_static!{
     static ref TRACKER: Mutex<DomainTracker> = Mutex::new(DomainTracker::new());
+    static ref TAGS: Mutex<TagRegistry> = Mutex::new(TagRegistry::default());
+    /// The latest MatchTable published by the TagRegistry.
+    static ref MATCH_TABLE: Mutex<Arc<MatchTable>> = Mutex::new(Arc::new(MatchTable::default()));
+}
+
+/// Set as soon as any domain gets tagged. As long as it is unset, matching domains does not need to
+/// look at the MatchTable.
+static ANY_TAGS: AtomicBool = AtomicBool::new(false);
+
+/// Incremented every time a new MatchTable gets published.
+static MATCH_TABLE_VERSION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The MatchTable that this thread used last, along with its version.
+    static CACHED_MATCH_TABLE: RefCell<(usize, Arc<MatchTable>)> = RefCell::new((0, Arc::new(MatchTable::default())));
+}
+
+/// Calls `f` with the latest MatchTable. Only takes a lock the first time a thread needs the table after
+/// a new one has been published, so matching events does not contend with other threads. Old tables are
+/// freed once every thread that used them has moved on to a newer one.
+fn with_match_table<T>(f: impl FnOnce(&MatchTable) -> T) -> T {
+    let version = MATCH_TABLE_VERSION.load(Ordering::Acquire);
+    CACHED_MATCH_TABLE.with(|cache| {
+        let mut cache = cache.borrow_mut();
+        if cache.0 != version {
+            let table = MATCH_TABLE.lock().expect("Fatal error: internal lock poisoned.").clone();
+            *cache = (version, table);
+        }
+        f(&cache.1)
+    })
+}
+
+/// The tags and expressions resolved into the domains they match. Computed whenever a tag or domain
+/// expression is added, or a new domain name matches an expression, so matching an event only takes
+/// a lookup in a set.
+#[derive(Default)]
+struct MatchTable {
+    /// For each tag, the domains that have it. Tags are stored as the domain with the same name.
+    tagged: HashMap<Domain, HashSet<Domain>>,
+    /// For each DomainExpression, the domains that it matches.
+    expressions: Vec<HashSet<Domain>>,
+}
+
+/// Keeps track of the tags of domains and the expressions that match them.
+#[derive(Default)]
+struct TagRegistry {
+    /// The name and the tags of each domain that has either.
+    labels: HashMap<Domain, Vec<String>>,
+    /// For each tag, the domains that have it. Tags are stored as the domain with the same name.
+    tagged: HashMap<Domain, HashSet<Domain>>,
+    /// The patterns of each DomainExpression.
+    expressions: Vec<Vec<String>>,
+}
+
+impl TagRegistry {
+    /// Resolves the tags and expressions into a new MatchTable and makes it the one that
+    /// `with_match_table()` uses.
+    fn publish(&mut self) {
+        let expressions = self.expressions.iter().map(|patterns| {
+            self.labels.iter()
+                .filter(|(_, labels)| patterns.iter().all(
+                    |pattern| labels.iter().any(|label| utils::glob_matches(pattern, label))
+                ))
+                .map(|(&domain, _)| domain)
+                .collect()
+        }).collect();
+        let table = MatchTable { tagged: self.tagged.clone(), expressions };
+        let mut published_table = MATCH_TABLE.lock().expect("Fatal error: internal lock poisoned.");
+        *published_table = Arc::new(table);
+        MATCH_TABLE_VERSION.fetch_add(1, Ordering::Release);
+    }
+
+    /// Returns true if some expression matches a domain that has only the given name and no tags.
+    fn expressions_match_name(&self, name: &str) -> bool {
+        self.expressions.iter().any(
+            |patterns| patterns.iter().all(|pattern| utils::glob_matches(pattern, name))
+        )
+    }
 }
 
 /// The DomainTracker is a responsible for converting strings to domains (e.g. turns the
@@ -63,6 +242,13 @@ impl DomainTracker {
                 let new_domain = self.get_unique_domain();
                 self.name_map.insert(name.to_owned(), new_domain);
                 self.reveres_name_map.insert(new_domain, name.to_owned());
+                let mut registry = TAGS.lock().expect("Fatal error: internal lock poisoned.");
+                registry.labels.insert(new_domain, vec![name.to_owned()]);
+                // The name of a domain may match the patterns of an expression. Names also get resolved
+                // while events are processed, so only publish a new table if it actually changes.
+                if registry.expressions_match_name(name) {
+                    registry.publish();
+                }
                 new_domain
             }
         })
@@ -87,3 +273,40 @@ impl DomainTracker {
         }
     }
 }
+
+#[test]
+fn unittest() {
+    let left_keyboard = resolve("unittest-left").unwrap();
+    let right_keyboard = resolve("unittest-right").unwrap();
+    let mouse = get_unique_domain();
+    add_tags(left_keyboard, &["unittest-kbd".to_owned(), "unittest-left-half".to_owned(), "unittest-usb2".to_owned()]).unwrap();
+    add_tags(right_keyboard, &["unittest-kbd".to_owned(), "unittest-bluetooth".to_owned()]).unwrap();
+    add_tags(mouse, &["unittest-usb3".to_owned()]).unwrap();
+
+    let keyboard_tag = resolve("unittest-kbd").unwrap();
+    assert!(matches(left_keyboard, keyboard_tag));
+    assert!(matches(right_keyboard, keyboard_tag));
+    assert!(! matches(mouse, keyboard_tag));
+    assert!(matches(left_keyboard, left_keyboard));
+    assert!(! matches(right_keyboard, left_keyboard));
+
+    let expression = DomainExpression::parse("unittest-kbd&unittest-left-half").unwrap();
+    assert!(expression.matches(left_keyboard));
+    assert!(! expression.matches(right_keyboard));
+    let expression = DomainExpression::parse("unittest-usb*").unwrap();
+    assert!(expression.matches(left_keyboard));
+    assert!(expression.matches(mouse));
+    assert!(! expression.matches(right_keyboard));
+    // Domains that get named or tagged after the expression was parsed are matched as well.
+    assert!(expression.matches(resolve("unittest-usb-late").unwrap()));
+    let late_keyboard = get_unique_domain();
+    add_tags(late_keyboard, &["unittest-kbd".to_owned()]).unwrap();
+    assert!(matches(late_keyboard, keyboard_tag));
+    assert!(! expression.matches(late_keyboard));
+    // The name of a domain counts as a tag as well.
+    assert!(DomainExpression::parse("unittest-r?ght").unwrap().matches(right_keyboard));
+    assert!(DomainExpression::parse("unittest-kbd&").is_err());
+
+    assert!(may_overlap(keyboard_tag, left_keyboard));
+    assert!(! may_overlap(left_keyboard, right_keyboard));
+}
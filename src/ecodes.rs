@@ -7,6 +7,7 @@ use crate::utils::{split_once, parse_cstr};
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::sync::Mutex;
 
 lazy_static! {
     pub static ref EVENT_TYPES: HashMap<String, EventType> = {
@@ -34,6 +35,9 @@ lazy_static! {
             "btn".to_string(),
             *result.get("key").expect("Failed to import event type data from libevdev.")
         );
+        result.insert(EVSIEVE_TYPE_NAME.to_string(), EventType::EVSIEVE);
+        result.insert(SIGNAL_TYPE_NAME.to_string(), EventType::SIGNAL);
+        result.insert(HOOK_TYPE_NAME.to_string(), EventType::HOOK);
         result
     };
 
@@ -41,6 +45,9 @@ lazy_static! {
     pub static ref EVENT_CODES: HashMap<(String, String), EventCode> = {
         let mut result = HashMap::new();
         for (ev_type_name, &ev_type) in EVENT_TYPES.iter() {
+            if ev_type.is_internal() {
+                continue;
+            }
             let code_max = match event_type_get_max(ev_type) {
                 Some(max) => max,
                 None => continue,
@@ -66,6 +73,11 @@ lazy_static! {
             }
         }
 
+        // The codes of the evsieve type are not known to libevdev.
+        for &(code_name, code) in EVSIEVE_CODE_NAMES {
+            result.insert((EVSIEVE_TYPE_NAME.to_string(), code_name.to_string()), code);
+        }
+
         result
     };
 
@@ -78,6 +90,11 @@ lazy_static! {
         result
     };
 
+    /// The names of all signal:NAME events, where the index of each name is its event code.
+    static ref SIGNAL_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+    /// The names of all hook:NAME events, where the index of each name is its event code.
+    static ref HOOK_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
     /// For each _named_ event code (EV_KEY, code) holds: the name of this code starts with
     /// btn: if and only if it is contained in one of the following ranges.
     ///
@@ -138,10 +155,25 @@ lazy_static! {
 }
 
 pub fn event_type_get_max(ev_type: EventType) -> Option<u16> {
+    if ev_type.is_evsieve() {
+        return Some(EVSIEVE_MAX);
+    }
     let result = unsafe { libevdev::libevdev_event_type_get_max(ev_type.into()) };
     result.try_into().ok()
 }
 
+/// Returns the event code with the given raw type and code, or an error if no such event code exists.
+pub fn checked_event_code(ev_type: u16, code: u16) -> Result<EventCode, ArgumentError> {
+    if ev_type > EV_MAX {
+        return Err(ArgumentError::new(format!("Invalid event type: {}", ev_type)));
+    }
+    let ev_type = EventType::new(ev_type);
+    match event_type_get_max(ev_type) {
+        Some(max) if code <= max => Ok(EventCode::new(ev_type, code)),
+        _ => Err(ArgumentError::new(format!("Invalid event code {} for type {}.", code, type_name(ev_type)))),
+    }
+}
+
 pub fn type_name(ev_type: EventType) -> Cow<'static, str> {
     for (name, &type_) in EVENT_TYPES.iter() {
         if ev_type == type_ {
@@ -163,6 +195,14 @@ pub fn virtual_type_name(virtual_type: VirtualEventType) -> Cow<'static, str> {
 pub fn event_name(code: EventCode) -> Cow<'static, str> {
     match EVENT_NAMES.get(&code) {
         Some(name) => Cow::from(name),
+        None if named_codes(code.ev_type()).is_some() => {
+            let (type_name, names) = named_codes(code.ev_type()).unwrap();
+            let names = names.lock().expect("Fatal error: internal mutex poisoned.");
+            match names.get(code.code() as usize) {
+                Some(name) => Cow::from(format!("{}:{}", type_name, name)),
+                None => Cow::from(format!("{}:%{}", type_name, code.code())),
+            }
+        },
         None => {
             let type_name = virtual_type_name(code.virtual_ev_type());
             Cow::from(format!("{}:%{}", type_name, code.code()))
@@ -220,12 +260,67 @@ pub fn event_type(name: &str) -> Result<EventType, ArgumentError> {
     }
 }
 
+/// For the types whose codes are allocated on demand for each name, like signal:NAME and hook:NAME,
+/// returns the name of the type and the names of its codes.
+fn named_codes(ev_type: EventType) -> Option<(&'static str, &'static Mutex<Vec<String>>)> {
+    if ev_type.is_signal() {
+        Some((SIGNAL_TYPE_NAME, &SIGNAL_NAMES))
+    } else if ev_type.is_hook() {
+        Some((HOOK_TYPE_NAME, &HOOK_NAMES))
+    } else {
+        None
+    }
+}
+
+/// Returns the event code for signal:NAME or hook:NAME, allocating a new code if this name has not
+/// been seen before.
+fn named_code(ev_type: EventType, name: &str) -> Result<EventCode, ArgumentError> {
+    let (type_name, names) = named_codes(ev_type).expect("Internal error: event codes of this type cannot be named. This is a bug.");
+    if name.is_empty() || ! name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-') {
+        return Err(ArgumentError::new(format!(
+            "Invalid {} name \"{}\". The names of {} events may only contain letters, digits, dashes and underscores.", type_name, name, type_name
+        )));
+    }
+
+    let mut names = names.lock().expect("Fatal error: internal mutex poisoned.");
+    let index = match names.iter().position(|existing| existing == name) {
+        Some(index) => index,
+        None => {
+            names.push(name.to_string());
+            names.len() - 1
+        }
+    };
+    match index.try_into() {
+        Ok(code) => Ok(EventCode::new(ev_type, code)),
+        Err(_) => Err(ArgumentError::new(format!("Too many different {} names have been specified.", type_name))),
+    }
+}
+
 /// Parses an event code by name like "key","a" or by name-number pair like "key","%35".
+/// Returns all known event codes of a certain type whose name matches a glob-style pattern,
+/// e.g. ("btn", "trigger_happy*"). Returns an empty vector if nothing matches.
+pub fn event_codes_matching(type_name: &str, pattern: &str) -> Vec<EventCode> {
+    let mut result: Vec<EventCode> = EVENT_CODES.iter()
+        .filter(|((code_type_name, code_name), _)| {
+            code_type_name == type_name && crate::utils::glob_matches(pattern, code_name)
+        })
+        .map(|(_, &code)| code)
+        .collect();
+    result.sort();
+    result
+}
+
 pub fn event_code(type_name: &str, code_name: &str) -> Result<EventCode, ArgumentError> {
     // Check whether the type and code can be interpreted as names.
     if let Some(&code) = EVENT_CODES.get(&(type_name.to_string(), code_name.to_string())) {
         return Ok(code)
     }
+    if type_name == SIGNAL_TYPE_NAME {
+        return named_code(EventType::SIGNAL, code_name);
+    }
+    if type_name == HOOK_TYPE_NAME {
+        return named_code(EventType::HOOK, code_name);
+    }
 
     // Check for a (name, number) pair.
     let code_name_numstr = match code_name.strip_prefix('%') {
@@ -291,7 +386,27 @@ pub const EV_SYN: u16 = libevdev::EV_SYN as u16;
 pub const EV_REP: u16 = libevdev::EV_REP as u16;
 pub const EV_KEY: u16 = libevdev::EV_KEY as u16;
 pub const EV_MSC: u16 = libevdev::EV_MSC as u16;
+pub const EV_LED: u16 = libevdev::EV_LED as u16;
 pub const EV_MAX: u16 = libevdev::EV_MAX as u16;
+/// The type of the events that evsieve generates itself, like evsieve:device-lost. This type is
+/// deliberately outside the range of types that the kernel knows about.
+pub const EV_EVSIEVE: u16 = EV_MAX + 1;
+
+pub const EVSIEVE_DEVICE_CONNECTED: u16 = 0;
+pub const EVSIEVE_DEVICE_LOST: u16 = 1;
+pub const EVSIEVE_MAX: u16 = 1;
+/// The type of signal:NAME events, which never leave evsieve.
+pub const EV_SIGNAL: u16 = EV_MAX + 2;
+/// The type of the hook:NAME events that hooks with an id= clause send, which never leave evsieve either.
+pub const EV_HOOK: u16 = EV_MAX + 3;
+
+const EVSIEVE_TYPE_NAME: &str = "evsieve";
+const SIGNAL_TYPE_NAME: &str = "signal";
+const HOOK_TYPE_NAME: &str = "hook";
+const EVSIEVE_CODE_NAMES: &[(&str, EventCode)] = &[
+    ("device-connected", EventCode::DEVICE_CONNECTED),
+    ("device-lost", EventCode::DEVICE_LOST),
+];
 
 pub const REP_DELAY: u16 = libevdev::REP_DELAY as u16;
 pub const REP_PERIOD: u16 = libevdev::REP_PERIOD as u16;
@@ -337,4 +452,24 @@ fn unittest() {
     // Make sure the pairs are of the supposed event types.
     assert!(ABS_REL_PAIRS.iter().all(|(abs, _)| abs.ev_type().is_abs()));
     assert!(ABS_REL_PAIRS.iter().all(|(_, rel)| rel.ev_type().is_rel()));
+
+    // The evsieve type must not collide with any type the kernel knows about.
+    assert_eq!(event_code("evsieve", "device-lost").unwrap(), EventCode::DEVICE_LOST);
+    assert_eq!(event_code("evsieve", "device-connected").unwrap(), EventCode::DEVICE_CONNECTED);
+    assert_eq!(event_name(EventCode::DEVICE_LOST), "evsieve:device-lost");
+    assert!(event_types().all(|ev_type| !ev_type.is_evsieve()));
+
+    // Signals get a code for each distinct name.
+    let foo = event_code("signal", "foo").unwrap();
+    assert_eq!(event_code("signal", "foo").unwrap(), foo);
+    assert_ne!(event_code("signal", "bar").unwrap(), foo);
+    assert_eq!(event_name(foo), "signal:foo");
+    assert!(event_code("signal", "foo:bar").is_err());
+    assert!(event_code("signal", "%3").is_err());
+
+    // Hooks have names of their own.
+    let hook_foo = event_code("hook", "foo").unwrap();
+    assert_ne!(hook_foo, foo);
+    assert_eq!(event_name(hook_foo), "hook:foo");
+    assert!(event_code("hook", "").is_err());
 }
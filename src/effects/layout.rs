@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Effects that switch the keyboard layout of the desktop environment.
+//!
+//! Each effect takes a comma-separated list of layouts and switches to the next one in the list
+//! every time it is triggered, wrapping around. The commands are executed directly instead of
+//! through a shell, in the same way as the commands of exec-shell. A command that is still running
+//! when the next switch happens gets terminated first, so the switches cannot finish out of order.
+
+use std::cell::Cell;
+
+use crate::error::{ArgumentError, Context};
+use crate::state::State;
+use crate::stream::hook::Effect;
+use crate::subprocess::{self, ProcessId};
+use crate::utils;
+
+/// A program and its arguments.
+type Invocation = (String, Vec<String>);
+
+/// Creates an effect that cycles through `layouts`, running the invocation that `to_invocation`
+/// returns for the layout that has been switched to.
+fn create_cycle(layouts: &str, to_invocation: fn(&str) -> Invocation) -> Result<Effect, ArgumentError> {
+    let layouts: Vec<String> = layouts.split(',').map(str::to_owned).collect();
+    if layouts.iter().any(String::is_empty) {
+        return Err(ArgumentError::new(format!("Empty layout name in the list of layouts \"{}\".", layouts.join(","))));
+    }
+    let current_index: Cell<usize> = Cell::new(0);
+    // The command that performed the last switch, which may still be running.
+    let last_process: Cell<Option<ProcessId>> = Cell::new(None);
+    Ok(Box::new(move |_: &mut State| {
+        let next_index = (current_index.get() + 1) % layouts.len();
+        current_index.set(next_index);
+        let (program, args) = to_invocation(&layouts[next_index]);
+        match subprocess::try_spawn_replacing(program, args, last_process.take()) {
+            Ok(process) => last_process.set(Some(process)),
+            Err(error) => error.print_err(),
+        }
+    }))
+}
+
+/// Switches the XKB layout using setxkbmap. A layout may specify a variant as "de(neo)".
+pub fn create_xkb_layout(layouts: &str) -> Result<Effect, ArgumentError> {
+    for layout in layouts.split(',') {
+        if layout.contains('(') && ! layout.ends_with(')') {
+            return Err(ArgumentError::new(format!("Invalid XKB layout: \"{}\". Variants must be specified like \"de(neo)\".", layout)));
+        }
+    }
+    create_cycle(layouts, |layout| {
+        let mut args = vec!["-layout".to_owned()];
+        match utils::split_once(layout, "(") {
+            (layout, Some(variant)) => {
+                args.push(layout.to_owned());
+                args.push("-variant".to_owned());
+                args.push(variant.trim_end_matches(')').to_owned());
+            },
+            (layout, None) => args.push(layout.to_owned()),
+        }
+        ("setxkbmap".to_owned(), args)
+    })
+}
+
+/// Switches the IBus input method engine, which the ibus command does by talking to the IBus daemon
+/// over D-Bus.
+pub fn create_ibus_engine(engines: &str) -> Result<Effect, ArgumentError> {
+    create_cycle(engines, |engine| {
+        ("ibus".to_owned(), vec!["engine".to_owned(), engine.to_owned()])
+    })
+}
@@ -0,0 +1,78 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Built-in effects are small integrations that a --hook can invoke through an `effect=NAME:VALUE`
+//! clause. They exist for tasks that are commonly done using exec-shell, but can be done faster or
+//! more reliably by evsieve itself.
+//!
+//! To add an effect, write a function that creates it in a module in this directory and add an entry
+//! for it to `EFFECTS`. Effects that need more elaborate arguments, like the one behind the send-signal=
+//! clause or the notify= clause, get a clause of their own instead.
+
+pub mod layout;
+pub mod signal;
+pub mod notify;
+
+use crate::error::ArgumentError;
+use crate::stream::hook::Effect;
+use crate::utils;
+
+struct EffectInfo {
+    name: &'static str,
+    /// Whether this effect executes other programs, which is not allowed by --sandbox=strict.
+    spawns_processes: bool,
+    create: fn(&str) -> Result<Effect, ArgumentError>,
+}
+
+/// All effects that can be used with the effect= clause of --hook.
+const EFFECTS: &[EffectInfo] = &[
+    EffectInfo { name: "xkb-layout", spawns_processes: true, create: layout::create_xkb_layout },
+    EffectInfo { name: "ibus-engine", spawns_processes: true, create: layout::create_ibus_engine },
+];
+
+fn find(spec: &str) -> Result<(&'static EffectInfo, &str), ArgumentError> {
+    let (name, value) = utils::split_once(spec, ":");
+    let value = match value {
+        Some(value) if ! value.is_empty() => value,
+        _ => return Err(ArgumentError::new(format!(
+            "The effect \"{}\" requires a value, e.g. effect={}:VALUE.", name, name
+        ))),
+    };
+    match EFFECTS.iter().find(|effect| effect.name == name) {
+        Some(effect) => Ok((effect, value)),
+        None => Err(ArgumentError::new(format!(
+            "Unknown effect: \"{}\". The following effects are available: {}",
+            name,
+            EFFECTS.iter().map(|effect| effect.name).collect::<Vec<_>>().join(", "),
+        ))),
+    }
+}
+
+/// Creates the effect described by the value of an effect= clause, e.g. "xkb-layout:us,de".
+pub fn create(spec: &str) -> Result<Effect, ArgumentError> {
+    let (effect, value) = find(spec)?;
+    (effect.create)(value)
+}
+
+/// Returns an error if `spec` does not describe a valid effect.
+pub fn validate(spec: &str) -> Result<(), ArgumentError> {
+    create(spec).map(drop)
+}
+
+/// Returns whether the effect described by `spec` executes other programs. Returns false if `spec`
+/// does not describe a valid effect.
+pub fn spawns_processes(spec: &str) -> bool {
+    match find(spec) {
+        Ok((effect, _)) => effect.spawns_processes,
+        Err(_) => false,
+    }
+}
+
+#[test]
+fn unittest() {
+    assert!(create("xkb-layout:us,de").is_ok());
+    assert!(create("ibus-engine:xkb:us::eng").is_ok());
+    assert!(create("xkb-layout").is_err());
+    assert!(create("xkb-layout:").is_err());
+    assert!(create("foo:bar").is_err());
+    assert!(spawns_processes("xkb-layout:us"));
+}
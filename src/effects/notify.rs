@@ -0,0 +1,295 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! The effect behind the notify= clause of --hook, which shows a desktop notification.
+//!
+//! If evsieve was built with the dbus feature, the notifications are sent directly to the
+//! org.freedesktop.Notifications service using libsystemd's sd-bus. Otherwise, evsieve falls back
+//! on the notify-send tool. In neither case does the event processing wait for the notification
+//! daemon to respond, because it may take a while to do so: the sd-bus calls are made from a worker
+//! thread, and notify-send runs as a subprocess.
+
+use std::collections::HashMap;
+
+use crate::error::ArgumentError;
+use crate::state::{State, ToggleIndex};
+use crate::stream::hook::Effect;
+
+const APP_NAME: &str = "evsieve";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum TemplatePart {
+    Text(String),
+    /// Replaced by the name of the current state of the toggle with this id.
+    Toggle(String),
+}
+
+/// A TemplatePart whose toggle id has been looked up.
+enum ResolvedPart {
+    Text(String),
+    /// The index of the toggle and the names of its states, if it has any.
+    Toggle(ToggleIndex, Option<Vec<String>>),
+}
+
+/// The message of a notify= clause, like "Layout: {layout}".
+#[derive(Clone)]
+pub struct NotifyTemplate {
+    parts: Vec<TemplatePart>,
+}
+
+impl NotifyTemplate {
+    /// Braces refer to toggles by their id. Literal braces can be written as "{{" and "}}".
+    pub fn parse(template: &str) -> Result<NotifyTemplate, ArgumentError> {
+        let mut parts = Vec::new();
+        let mut text = String::new();
+        let mut chars = template.chars().peekable();
+        while let Some(character) = chars.next() {
+            match character {
+                '{' if chars.peek() == Some(&'{') => {
+                    chars.next();
+                    text.push('{');
+                },
+                '}' if chars.peek() == Some(&'}') => {
+                    chars.next();
+                    text.push('}');
+                },
+                '{' => {
+                    let mut id = String::new();
+                    loop {
+                        match chars.next() {
+                            Some('}') => break,
+                            Some(character) => id.push(character),
+                            None => return Err(ArgumentError::new(format!(
+                                "The notify={} clause contains a \"{{\" without a matching \"}}\". To write a literal brace, use \"{{{{\".", template
+                            ))),
+                        }
+                    }
+                    if id.is_empty() {
+                        return Err(ArgumentError::new(format!(
+                            "The notify={} clause contains \"{{}}\", but the braces must contain the id of a toggle.", template
+                        )));
+                    }
+                    if ! text.is_empty() {
+                        parts.push(TemplatePart::Text(std::mem::take(&mut text)));
+                    }
+                    parts.push(TemplatePart::Toggle(id));
+                },
+                '}' => return Err(ArgumentError::new(format!(
+                    "The notify={} clause contains a \"}}\" without a matching \"{{\". To write a literal brace, use \"}}}}\".", template
+                ))),
+                _ => text.push(character),
+            }
+        }
+        if ! text.is_empty() {
+            parts.push(TemplatePart::Text(text));
+        }
+        Ok(NotifyTemplate { parts })
+    }
+
+    /// Requires the indices of all toggles and the names of the states of the toggles that have any.
+    /// The states of toggles without names are called by their index, starting at 1.
+    pub fn into_effect(
+        self,
+        toggle_indices: &HashMap<String, ToggleIndex>,
+        toggle_names: &HashMap<String, Vec<String>>,
+    ) -> Result<Effect, ArgumentError> {
+        let mut parts: Vec<ResolvedPart> = Vec::new();
+        for part in self.parts {
+            parts.push(match part {
+                TemplatePart::Text(text) => ResolvedPart::Text(text),
+                TemplatePart::Toggle(id) => match toggle_indices.get(&id) {
+                    Some(&index) => ResolvedPart::Toggle(index, toggle_names.get(&id).cloned()),
+                    None => return Err(ArgumentError::new(format!(
+                        "The notify= clause refers to the toggle \"{}\", but no toggle with that id exists.", id
+                    ))),
+                },
+            });
+        }
+
+        Ok(Box::new(move |state: &mut State| {
+            let message: String = parts.iter().map(|part| match part {
+                ResolvedPart::Text(text) => text.clone(),
+                ResolvedPart::Toggle(index, names) => {
+                    let value = state[*index].value();
+                    match names {
+                        Some(names) => names[value].clone(),
+                        None => format!("{}", value + 1),
+                    }
+                },
+            }).collect();
+            show(message);
+        }))
+    }
+}
+
+#[cfg(feature = "dbus")]
+use self::sd_bus::show;
+
+#[cfg(feature = "dbus")]
+mod sd_bus {
+    use std::ffi::CString;
+    use std::os::raw::{c_char, c_int, c_uint};
+    use std::sync::{Condvar, Mutex};
+
+    #[repr(C)]
+    struct SdBus { _private: [u8; 0] }
+    #[repr(C)]
+    struct SdBusMessage { _private: [u8; 0] }
+    #[repr(C)]
+    struct SdBusError {
+        name: *const c_char,
+        message: *const c_char,
+        need_free: c_int,
+    }
+
+    extern "C" {
+        fn sd_bus_open_user(bus: *mut *mut SdBus) -> c_int;
+        fn sd_bus_flush_close_unref(bus: *mut SdBus) -> *mut SdBus;
+        fn sd_bus_call_method(
+            bus: *mut SdBus, destination: *const c_char, path: *const c_char, interface: *const c_char,
+            member: *const c_char, ret_error: *mut SdBusError, reply: *mut *mut SdBusMessage,
+            types: *const c_char, ...
+        ) -> c_int;
+        fn sd_bus_message_read(message: *mut SdBusMessage, types: *const c_char, ...) -> c_int;
+        fn sd_bus_message_unref(message: *mut SdBusMessage) -> *mut SdBusMessage;
+        fn sd_bus_error_free(error: *mut SdBusError);
+    }
+
+    fn cstr(bytes: &'static [u8]) -> *const c_char {
+        bytes.as_ptr() as *const c_char
+    }
+
+    /// The notification that the worker thread should show next.
+    struct Queue {
+        next_message: Option<String>,
+        worker_started: bool,
+    }
+
+    lazy_static! {
+        static ref QUEUE: (Mutex<Queue>, Condvar) = (
+            Mutex::new(Queue { next_message: None, worker_started: false }),
+            Condvar::new(),
+        );
+    }
+
+    /// Hands the message to the worker thread, so processing events never waits for the session bus.
+    /// If the worker is still busy with an earlier notification, only the last message that arrives in
+    /// the meantime gets shown, because it would replace the other ones right away anyway.
+    pub fn show(message: String) {
+        let (queue, condvar) = &*QUEUE;
+        let mut queue = match queue.lock() {
+            Ok(lock) => lock,
+            Err(_) => return eprintln!("Failed to show a notification: internal lock poisoned."),
+        };
+        queue.next_message = Some(message);
+        if ! queue.worker_started {
+            queue.worker_started = true;
+            std::thread::spawn(run_worker);
+        }
+        condvar.notify_one();
+    }
+
+    fn run_worker() {
+        let (queue, condvar) = &*QUEUE;
+        let mut notifier = Notifier::new();
+        loop {
+            let message = {
+                let mut queue = queue.lock().expect("Internal lock poisoned.");
+                loop {
+                    match queue.next_message.take() {
+                        Some(message) => break message,
+                        None => queue = condvar.wait(queue).expect("Internal lock poisoned."),
+                    }
+                }
+            };
+            notifier.send(&message);
+        }
+    }
+
+    /// Keeps a connection to the session bus open and replaces the previous notification with the
+    /// next one, so quickly switching modes does not pile up notifications. Waits for the reply of the
+    /// notification daemon, so the next notification knows the id of the one it replaces.
+    struct Notifier {
+        bus: *mut SdBus,
+        last_id: c_uint,
+    }
+
+    impl Notifier {
+        fn new() -> Notifier {
+            Notifier { bus: std::ptr::null_mut(), last_id: 0 }
+        }
+
+        fn send(&mut self, message: &str) {
+            if self.bus.is_null() {
+                let result = unsafe { sd_bus_open_user(&mut self.bus) };
+                if result < 0 {
+                    self.bus = std::ptr::null_mut();
+                    eprintln!("Failed to show a notification: could not connect to the session bus: {}", std::io::Error::from_raw_os_error(-result));
+                    return;
+                }
+            }
+
+            let message = CString::new(message.replace('\0', "")).unwrap_or_default();
+            let app_name = CString::new(super::APP_NAME).unwrap_or_default();
+            let mut error = SdBusError { name: std::ptr::null(), message: std::ptr::null(), need_free: 0 };
+            let mut reply: *mut SdBusMessage = std::ptr::null_mut();
+            let result = unsafe { sd_bus_call_method(
+                self.bus,
+                cstr(b"org.freedesktop.Notifications\0"),
+                cstr(b"/org/freedesktop/Notifications\0"),
+                cstr(b"org.freedesktop.Notifications\0"),
+                cstr(b"Notify\0"),
+                &mut error, &mut reply,
+                cstr(b"susssasa{sv}i\0"),
+                // App name, id of the notification to replace, icon, summary and body.
+                app_name.as_ptr(), self.last_id, cstr(b"\0"), message.as_ptr(), cstr(b"\0"),
+                // No actions, no hints, and the default timeout.
+                0 as c_uint, 0 as c_uint, -1 as c_int,
+            ) };
+
+            if result < 0 {
+                eprintln!("Failed to show a notification: {}", std::io::Error::from_raw_os_error(-result));
+                // The connection may have been lost, so it is reopened the next time.
+                unsafe { sd_bus_flush_close_unref(self.bus) };
+                self.bus = std::ptr::null_mut();
+            } else {
+                let mut id: c_uint = 0;
+                if unsafe { sd_bus_message_read(reply, cstr(b"u\0"), &mut id as *mut c_uint) } >= 0 {
+                    self.last_id = id;
+                }
+            }
+            unsafe {
+                sd_bus_message_unref(reply);
+                sd_bus_error_free(&mut error);
+            }
+        }
+    }
+}
+
+/// Runs notify-send through the subprocess manager, which does not wait for it to finish.
+#[cfg(not(feature = "dbus"))]
+fn show(message: String) {
+    use crate::error::Context;
+    crate::subprocess::try_spawn("notify-send".to_owned(), vec![
+        format!("--app-name={}", APP_NAME), "--".to_owned(), message,
+    ]).print_err();
+}
+
+#[test]
+fn unittest() {
+    let template = NotifyTemplate::parse("Layout: {layout} {{literal}}").unwrap();
+    assert_eq!(template.parts, vec![
+        TemplatePart::Text("Layout: ".to_owned()),
+        TemplatePart::Toggle("layout".to_owned()),
+        TemplatePart::Text(" {literal}".to_owned()),
+    ]);
+    assert!(NotifyTemplate::parse("{layout").is_err());
+    assert!(NotifyTemplate::parse("layout}").is_err());
+    assert!(NotifyTemplate::parse("{}").is_err());
+
+    let mut state = State::new();
+    let index = state.create_toggle_with_size(2).unwrap();
+    let mut toggle_indices: HashMap<String, ToggleIndex> = HashMap::new();
+    toggle_indices.insert("layout".to_owned(), index);
+    assert!(NotifyTemplate::parse("{layout}").unwrap().into_effect(&toggle_indices, &HashMap::new()).is_ok());
+    assert!(NotifyTemplate::parse("{mode}").unwrap().into_effect(&toggle_indices, &HashMap::new()).is_err());
+}
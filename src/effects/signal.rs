@@ -0,0 +1,171 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! The effect behind the send-signal= clause of --hook, which sends a signal to another process.
+//!
+//! The process is looked up every time the hook triggers rather than when evsieve starts, because
+//! the target process may be restarted while evsieve is running.
+
+use std::path::{Path, PathBuf};
+
+use crate::error::{ArgumentError, Context, SystemError};
+use crate::state::State;
+use crate::stream::hook::Effect;
+
+/// The signals that can be referred to by name. Other signals can be specified by number.
+const SIGNAL_NAMES: &[(&str, libc::c_int)] = &[
+    ("HUP", libc::SIGHUP), ("INT", libc::SIGINT), ("QUIT", libc::SIGQUIT), ("KILL", libc::SIGKILL),
+    ("USR1", libc::SIGUSR1), ("USR2", libc::SIGUSR2), ("ALRM", libc::SIGALRM), ("TERM", libc::SIGTERM),
+    ("CONT", libc::SIGCONT), ("STOP", libc::SIGSTOP), ("TSTP", libc::SIGTSTP), ("WINCH", libc::SIGWINCH),
+];
+
+/// The kernel truncates the names of processes to this many bytes.
+const MAX_PROCESS_NAME_LEN: usize = 15;
+
+#[derive(Clone)]
+enum Target {
+    /// A file containing the PID of the process, like /run/foo.pid.
+    PidFile(PathBuf),
+    /// All processes with this name, like "sway".
+    Name(String),
+}
+
+#[derive(Clone)]
+pub struct SendSignal {
+    target: Target,
+    signal: libc::c_int,
+    /// The signal as the user wrote it, for error messages.
+    signal_str: String,
+}
+
+impl SendSignal {
+    /// Interprets the value of a send-signal= clause, e.g. "/run/foo.pid:USR1" or "sway:SIGUSR1".
+    /// Targets that contain a slash are interpreted as paths to pidfiles, other targets as process names.
+    pub fn parse(value: &str) -> Result<SendSignal, ArgumentError> {
+        let (target_str, signal_str) = match value.rsplit_once(':') {
+            Some((target, signal)) if ! target.is_empty() && ! signal.is_empty() => (target, signal),
+            _ => return Err(ArgumentError::new(format!(
+                "The send-signal clause requires a target and a signal separated by a colon, like send-signal=sway:USR1. Encountered: send-signal={}", value
+            ))),
+        };
+
+        let target = match target_str.contains('/') {
+            true => Target::PidFile(target_str.into()),
+            false => Target::Name(target_str.to_owned()),
+        };
+        let signal = parse_signal(signal_str)?;
+
+        Ok(SendSignal { target, signal, signal_str: signal_str.to_owned() })
+    }
+
+    /// Returns the path that evsieve needs to be able to read to find the target process. For pidfiles,
+    /// this is their directory, because pidfiles are often replaced when the process restarts.
+    pub fn required_path(&self) -> &Path {
+        match &self.target {
+            Target::PidFile(path) => path.parent().unwrap_or(path),
+            Target::Name(_) => Path::new("/proc"),
+        }
+    }
+
+    pub fn into_effect(self) -> Effect {
+        Box::new(move |_: &mut State| {
+            self.send().with_context_of(|| match &self.target {
+                Target::PidFile(path) => format!("While sending the signal {} to the process in {}:", self.signal_str, path.display()),
+                Target::Name(name) => format!("While sending the signal {} to {}:", self.signal_str, name),
+            }).print_err();
+        })
+    }
+
+    fn send(&self) -> Result<(), SystemError> {
+        for pid in self.find_pids()? {
+            if unsafe { libc::kill(pid, self.signal) } < 0 {
+                return Err(SystemError::os_with_context(format!("Failed to signal the process {}:", pid)));
+            }
+        }
+        Ok(())
+    }
+
+    fn find_pids(&self) -> Result<Vec<libc::pid_t>, SystemError> {
+        match &self.target {
+            Target::PidFile(path) => {
+                let content = std::fs::read_to_string(path)?;
+                match content.trim().parse::<libc::pid_t>() {
+                    Ok(pid) if pid > 0 => Ok(vec![pid]),
+                    _ => Err(SystemError::new(format!("The file does not contain a valid PID: \"{}\"", content.trim()))),
+                }
+            },
+            Target::Name(name) => {
+                let name = truncate_process_name(name);
+                let own_pid = std::process::id() as libc::pid_t;
+                let mut pids = Vec::new();
+                for entry in std::fs::read_dir("/proc")? {
+                    let pid = match entry?.file_name().to_str().and_then(|pid| pid.parse::<libc::pid_t>().ok()) {
+                        Some(pid) if pid != own_pid => pid,
+                        _ => continue,
+                    };
+                    // The process may have exited since we listed the directory.
+                    if let Ok(comm) = std::fs::read_to_string(format!("/proc/{}/comm", pid)) {
+                        if comm.trim_end_matches('\n') == name {
+                            pids.push(pid);
+                        }
+                    }
+                }
+                match pids.is_empty() {
+                    true => Err(SystemError::new("No process with that name is running.")),
+                    false => Ok(pids),
+                }
+            },
+        }
+    }
+}
+
+fn parse_signal(signal_str: &str) -> Result<libc::c_int, ArgumentError> {
+    if let Ok(number) = signal_str.parse::<libc::c_int>() {
+        return match number {
+            1 ..= 64 => Ok(number),
+            _ => Err(ArgumentError::new(format!("Invalid signal number: {}", number))),
+        };
+    }
+    let uppercase = signal_str.to_ascii_uppercase();
+    let name = uppercase.strip_prefix("SIG").unwrap_or(&uppercase);
+    SIGNAL_NAMES.iter()
+        .find(|(signal_name, _)| *signal_name == name)
+        .map(|&(_, signal)| signal)
+        .ok_or_else(|| ArgumentError::new(format!(
+            "Unknown signal: \"{}\". Use a number or one of the following names: {}",
+            signal_str,
+            SIGNAL_NAMES.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(", "),
+        )))
+}
+
+fn truncate_process_name(name: &str) -> &str {
+    let mut end = name.len().min(MAX_PROCESS_NAME_LEN);
+    while ! name.is_char_boundary(end) {
+        end -= 1;
+    }
+    &name[.. end]
+}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_signal("USR1").unwrap(), libc::SIGUSR1);
+    assert_eq!(parse_signal("sigstop").unwrap(), libc::SIGSTOP);
+    assert_eq!(parse_signal("15").unwrap(), libc::SIGTERM);
+    assert!(parse_signal("0").is_err());
+    assert!(parse_signal("FOO").is_err());
+
+    assert!(matches!(SendSignal::parse("/run/foo.pid:USR1").unwrap().target, Target::PidFile(_)));
+    assert!(matches!(SendSignal::parse("sway:CONT").unwrap().target, Target::Name(_)));
+    assert!(SendSignal::parse("sway").is_err());
+    assert!(SendSignal::parse(":USR1").is_err());
+    assert_eq!(truncate_process_name("a-very-long-process-name"), "a-very-long-pro");
+
+    // Signal 0 does not actually get delivered, but checks that the process exists.
+    let own_pid = std::process::id().to_string();
+    let pid_file = std::env::temp_dir().join(format!("evsieve-signal-test-{}.pid", own_pid));
+    std::fs::write(&pid_file, format!("{}\n", own_pid)).unwrap();
+    let mut send_signal = SendSignal::parse(&format!("{}:USR1", pid_file.display())).unwrap();
+    send_signal.signal = 0;
+    assert!(send_signal.send().is_ok());
+    std::fs::remove_file(&pid_file).unwrap();
+    assert!(send_signal.send().is_err());
+}
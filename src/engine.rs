@@ -0,0 +1,232 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! An `Engine` runs events through a stream created from evsieve arguments without any input or
+//! output devices being involved. The program using it pushes events into the engine and pulls
+//! the processed events out. This is what the C API and the Python bindings are built upon.
+
+use std::cell::RefCell;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
+
+use crate::arguments::parser::PreImplementation;
+use crate::bindings::libevdev;
+use crate::capability::Capability;
+use crate::domain::{self, Domain};
+use crate::ecodes;
+use crate::error::{ArgumentError, RuntimeError};
+use crate::event::{Channel, Event, EventValue, Namespace};
+use crate::io::output::OutputSystem;
+use crate::loopback::Delay;
+use crate::range::{Interval, Set};
+use crate::sandbox::SandboxMode;
+use crate::stream::Setup;
+use crate::time::MonotonicClock;
+
+/// Collects the events that leave the stream so they can be pulled by the embedding program.
+/// The queue is shared with the `Engine` because the `Setup` owns the output system.
+struct QueueOutput {
+    queue: Rc<RefCell<VecDeque<libevdev::input_event>>>,
+    should_syn: bool,
+}
+
+impl QueueOutput {
+    fn push(&self, ev_type: u16, code: u16, value: i32) {
+        self.queue.borrow_mut().push_back(libevdev::input_event {
+            time: libevdev::timeval { tv_sec: 0, tv_usec: 0 },
+            type_: ev_type,
+            code,
+            value,
+        });
+    }
+}
+
+impl OutputSystem for QueueOutput {
+    fn update_caps(&mut self, _new_capabilities: Vec<Capability>) {
+        // The embedding program queries the capabilities itself.
+    }
+
+    fn route_events(&mut self, events: &[Event]) {
+        for event in events {
+            self.push(event.code.ev_type().into(), event.code.code(), event.value);
+        }
+        self.should_syn |= ! events.is_empty();
+    }
+
+    fn synchronize(&mut self) {
+        if self.should_syn {
+            self.push(ecodes::EV_SYN, 0, 0);
+            self.should_syn = false;
+        }
+    }
+}
+
+pub struct Engine {
+    setup: Setup<QueueOutput>,
+    queue: Rc<RefCell<VecDeque<libevdev::input_event>>>,
+    /// The domain of events that were pushed without a domain.
+    default_domain: Domain,
+    /// The capabilities declared through `add_capability()`.
+    input_caps: Vec<Capability>,
+    /// The last value that was pushed for each channel, like the state kept by input devices.
+    last_values: HashMap<Channel, EventValue>,
+}
+
+impl Engine {
+    /// Creates an engine from arguments like those of the evsieve program, excluding the program name.
+    pub fn new(args: Vec<String>) -> Result<Engine, RuntimeError> {
+        let PreImplementation {
+            stream, input_devices, replay_devices, output_devices: _, control_fifos, state, toggle_indices,
+            stage_names, sandbox_policy, run_as, stats_interval, status_file, journal_path, batch_latency, realtime, safe_mode,
+            summary_format, introspect_sockets, argument_groups: _, shadows, shadow_sources,
+        } = crate::arguments::parser::process(args)?;
+
+        if ! input_devices.is_empty() || ! replay_devices.is_empty() {
+            return Err(ArgumentError::new("The --input argument cannot be used by an embedded engine. Push the events into the engine instead.").into());
+        }
+        if ! control_fifos.is_empty() {
+            return Err(ArgumentError::new("The --control-fifo argument cannot be used by an embedded engine.").into());
+        }
+        if ! introspect_sockets.is_empty() {
+            return Err(ArgumentError::new("The --introspect-socket argument cannot be used by an embedded engine.").into());
+        }
+        if run_as.is_some() {
+            return Err(ArgumentError::new("The --run-as argument cannot be used by an embedded engine.").into());
+        }
+        if stats_interval.is_some() {
+            return Err(ArgumentError::new("The --stats argument cannot be used by an embedded engine.").into());
+        }
+        if status_file.is_some() {
+            return Err(ArgumentError::new("The --status-file argument cannot be used by an embedded engine.").into());
+        }
+        if journal_path.is_some() {
+            return Err(ArgumentError::new("The --recover argument cannot be used by an embedded engine.").into());
+        }
+        if batch_latency.is_some() {
+            return Err(ArgumentError::new("The --batch argument cannot be used by an embedded engine.").into());
+        }
+        if realtime.is_active() {
+            return Err(ArgumentError::new("The --realtime and --lock-memory arguments cannot be used by an embedded engine.").into());
+        }
+        if summary_format.is_some() {
+            return Err(ArgumentError::new("The --summarize argument cannot be used by an embedded engine.").into());
+        }
+        if sandbox_policy.mode != SandboxMode::Off {
+            return Err(ArgumentError::new("The --sandbox argument cannot be used by an embedded engine.").into());
+        }
+        if safe_mode {
+            return Err(ArgumentError::new("The --safe-mode argument cannot be used by an embedded engine.").into());
+        }
+        if ! shadows.is_empty() || shadow_sources.is_some() {
+            return Err(ArgumentError::new("The --shadow argument cannot be used by an embedded engine.").into());
+        }
+
+        let queue = Rc::new(RefCell::new(VecDeque::new()));
+        let output = QueueOutput { queue: queue.clone(), should_syn: false };
+        Ok(Engine {
+            setup: Setup::create(stream, output, state, toggle_indices, stage_names, Default::default(), Box::new(MonotonicClock)),
+            queue,
+            default_domain: domain::get_unique_domain(),
+            input_caps: Vec::new(),
+            last_values: HashMap::new(),
+        })
+    }
+
+    fn resolve_domain(&self, domain_name: Option<&str>) -> Result<Domain, ArgumentError> {
+        match domain_name {
+            Some(name) => domain::resolve(name),
+            None => Ok(self.default_domain),
+        }
+    }
+
+    /// Runs an event through the stream. Events of type EV_SYN mark the end of a report.
+    pub fn push(&mut self, domain_name: Option<&str>, ev_type: u16, code: u16, value: i32) -> Result<(), ArgumentError> {
+        let domain = self.resolve_domain(domain_name)?;
+        let now = self.setup.now();
+        self.setup.wakeup_until(now);
+        if ev_type == ecodes::EV_SYN {
+            self.setup.syn();
+        } else {
+            let code = ecodes::checked_event_code(ev_type, code)?;
+            let previous_value = self.last_values.insert((code, domain), value).unwrap_or(0);
+            self.setup.run(now, Event::new(code, value, previous_value, domain, Namespace::User));
+        }
+        Ok(())
+    }
+
+    /// Returns up to `max_count` events that have left the stream, including EV_SYN events at the end of
+    /// each report.
+    pub fn pull(&mut self, max_count: usize) -> Vec<libevdev::input_event> {
+        let now = self.setup.now();
+        self.setup.wakeup_until(now);
+        let mut queue = self.queue.borrow_mut();
+        let count = max_count.min(queue.len());
+        queue.drain(.. count).collect()
+    }
+
+    /// Returns the amount of milliseconds until the engine has events that need to be pulled because
+    /// of arguments like --delay, or None if it has nothing scheduled.
+    pub fn timeout(&self) -> Option<i32> {
+        match self.setup.time_until_next_wakeup() {
+            Delay::Now => Some(0),
+            Delay::Never => None,
+            // Round up, so the caller does not wake up slightly before the deadline.
+            Delay::Wait(time) => Some(((time.as_micros() + 999) / 1000).min(i32::MAX as u64) as i32),
+        }
+    }
+
+    /// Declares that the events pushed with the given domain may have the given type and code, with a
+    /// value between min and max inclusive.
+    pub fn add_capability(&mut self, domain_name: Option<&str>, ev_type: u16, code: u16, min: i32, max: i32) -> Result<(), ArgumentError> {
+        let domain = self.resolve_domain(domain_name)?;
+        let code = ecodes::checked_event_code(ev_type, code)?;
+        if code.ev_type().is_syn() {
+            return Ok(());
+        }
+        self.input_caps.push(Capability {
+            code, domain,
+            namespace: Namespace::User,
+            values: Set::from(Interval::spanned_between(min, max)),
+            abs_meta: None,
+        });
+        Ok(())
+    }
+
+    /// Returns the capabilities of the events that may leave the engine given the declared capabilities,
+    /// in a format like "key:a@domain 0~2". The domain is omitted if it has no name.
+    pub fn describe_capabilities(&self) -> Vec<String> {
+        self.setup.run_caps(self.input_caps.clone()).into_iter().map(|cap| {
+            let mut description = ecodes::event_name(cap.code).into_owned();
+            if let Some(domain_name) = domain::try_reverse_resolve(cap.domain) {
+                description.push('@');
+                description.push_str(&domain_name);
+            }
+            if let Some(interval) = cap.values.spanning_interval() {
+                description.push_str(&format!(" {}~{}", interval.min, interval.max));
+            }
+            description
+        }).collect()
+    }
+}
+
+#[test]
+fn unittest() {
+    let args = |args: &str| args.split_whitespace().map(str::to_owned).collect::<Vec<String>>();
+    let key_a = ecodes::event_code("key", "a").unwrap();
+    let key_b = ecodes::event_code("key", "b").unwrap();
+
+    // Transitions must see the value that was previously pushed on the same channel.
+    let mut engine = Engine::new(args("--map key:a:1..0 key:b")).unwrap();
+    for &value in &[1, 0, 0] {
+        engine.push(None, key_a.ev_type().into(), key_a.code(), value).unwrap();
+        engine.push(None, ecodes::EV_SYN, 0, 0).unwrap();
+    }
+    let events: Vec<(u16, i32)> = engine.pull(10).iter()
+        .filter(|event| event.type_ != ecodes::EV_SYN)
+        .map(|event| (event.code, event.value))
+        .collect();
+    assert_eq!(events, vec![(key_a.code(), 1), (key_b.code(), 0), (key_a.code(), 0)]);
+
+    for unsupported in &["--sandbox=strict", "--safe-mode", "--run-as=nobody"] {
+        assert!(Engine::new(args(unsupported)).is_err());
+    }
+}
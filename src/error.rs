@@ -4,6 +4,39 @@ use std::io;
 use std::fmt;
 use std::fmt::Write;
 
+/// Tells scripts what kind of problem made evsieve exit, through the exit code of the process.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorCategory {
+    /// Anything that does not fit in the other categories, including bugs.
+    Other,
+    /// The arguments are malformed or contradict each other.
+    Argument,
+    /// A device or other file that evsieve needs does not exist or is in use by another program.
+    DeviceUnavailable,
+    /// Evsieve lacks the permissions to do something.
+    PermissionDenied,
+}
+
+impl ErrorCategory {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            ErrorCategory::Other => 1,
+            ErrorCategory::Argument => 2,
+            ErrorCategory::DeviceUnavailable => 3,
+            ErrorCategory::PermissionDenied => 4,
+        }
+    }
+
+    /// Returns the category of an error number like ENOENT.
+    pub fn from_errno(errno: i32) -> ErrorCategory {
+        match errno {
+            libc::ENOENT | libc::ENODEV | libc::ENXIO | libc::EBUSY => ErrorCategory::DeviceUnavailable,
+            libc::EACCES | libc::EPERM => ErrorCategory::PermissionDenied,
+            _ => ErrorCategory::Other,
+        }
+    }
+}
+
 /// A trait for errors similiar in concept to anyhow::Error.
 pub trait Context {
     fn context(&self) -> &[String];
@@ -31,15 +64,25 @@ fn format_error_with_context(f: &mut fmt::Formatter, err_context: Vec<String>, e
 }
 
 macro_rules! context_error {
-    ($name:ident) => {
+    ($name:ident, $category:expr) => {
         #[derive(Debug)]
         pub struct $name {
             context: Vec<String>,
             message: String,
+            category: ErrorCategory,
         }
         impl $name {
             pub fn new(message: impl Into<String>) -> Self {
-                Self { message: message.into(), context: Vec::new() }
+                Self { message: message.into(), context: Vec::new(), category: $category }
+            }
+
+            pub fn with_category(mut self, category: ErrorCategory) -> Self {
+                self.category = category;
+                self
+            }
+
+            pub fn category(&self) -> ErrorCategory {
+                self.category
             }
         }
         impl Context for $name {
@@ -64,9 +107,9 @@ macro_rules! context_error {
 }
 
 macro_rules! runtime_errors {
-    ( $( $name:ident ),* ) => {
+    ( $( $name:ident => $category:expr ),* ) => {
         $(
-            context_error!($name);
+            context_error!($name, $category);
         )*
 
         #[derive(Debug)]
@@ -76,6 +119,16 @@ macro_rules! runtime_errors {
             )*
         }
 
+        impl RuntimeError {
+            pub fn category(&self) -> ErrorCategory {
+                match self {
+                    $(
+                        RuntimeError::$name(error) => error.category(),
+                    )*
+                }
+            }
+        }
+
         impl Context for RuntimeError {
             fn with_context<T: Into<String>>(self, context: T) -> RuntimeError {
                 match self {
@@ -149,14 +202,26 @@ impl SystemError {
     }
 }
 
-runtime_errors!(ArgumentError, InternalError, SystemError);
+runtime_errors!(
+    ArgumentError => ErrorCategory::Argument,
+    InternalError => ErrorCategory::Other,
+    SystemError => ErrorCategory::Other
+);
 display_error!(ArgumentError, "Invalid argument: {}");
 display_error!(InternalError, "Internal error: {}");
 display_error!(SystemError, "System error: {}");
 
 impl From<io::Error> for SystemError {
     fn from(error: io::Error) -> SystemError {
-        SystemError::new(format!("{}", error))
+        let category = match error.raw_os_error() {
+            Some(errno) => ErrorCategory::from_errno(errno),
+            None => match error.kind() {
+                io::ErrorKind::NotFound => ErrorCategory::DeviceUnavailable,
+                io::ErrorKind::PermissionDenied => ErrorCategory::PermissionDenied,
+                _ => ErrorCategory::Other,
+            },
+        };
+        SystemError::new(format!("{}", error)).with_category(category)
     }
 }
 
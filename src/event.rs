@@ -2,6 +2,7 @@
 
 use std::fmt;
 use crate::domain::Domain;
+use crate::annotation::Annotations;
 use crate::ecodes;
 
 pub type EventValue = i32;
@@ -17,6 +18,16 @@ impl EventType {
     pub const REP: EventType = EventType(ecodes::EV_REP);
     pub const SYN: EventType = EventType(ecodes::EV_SYN);
     pub const MSC: EventType = EventType(ecodes::EV_MSC);
+    pub const LED: EventType = EventType(ecodes::EV_LED);
+    /// A type that does not exist in the kernel. It is used for events that evsieve generates
+    /// itself to report on the status of devices, and can never be written to an output device.
+    pub const EVSIEVE: EventType = EventType(ecodes::EV_EVSIEVE);
+    /// Another type that does not exist in the kernel. Its codes are allocated on demand for
+    /// each name like signal:NAME that appears in the arguments.
+    pub const SIGNAL: EventType = EventType(ecodes::EV_SIGNAL);
+    /// Another type that does not exist in the kernel, used for the hook:NAME events that hooks with
+    /// an id= clause send. Like with signals, its codes are allocated on demand for each name.
+    pub const HOOK: EventType = EventType(ecodes::EV_HOOK);
 
     pub fn is_key(self) -> bool {
         self == EventType::KEY
@@ -33,11 +44,28 @@ impl EventType {
     pub fn is_syn(self) -> bool {
         self == EventType::SYN
     }
+    pub fn is_led(self) -> bool {
+        self == EventType::LED
+    }
+    pub fn is_evsieve(self) -> bool {
+        self == EventType::EVSIEVE
+    }
+    pub fn is_signal(self) -> bool {
+        self == EventType::SIGNAL
+    }
+    pub fn is_hook(self) -> bool {
+        self == EventType::HOOK
+    }
+    /// Returns true for types that only exist within evsieve and can never be written to
+    /// output devices.
+    pub fn is_internal(self) -> bool {
+        self.is_evsieve() || self.is_signal() || self.is_hook()
+    }
 }
 
 impl EventType {
     pub const fn new(value: u16) -> EventType {
-        debug_assert!(value <= ecodes::EV_MAX);
+        debug_assert!(value <= ecodes::EV_MAX || value == ecodes::EV_EVSIEVE || value == ecodes::EV_SIGNAL || value == ecodes::EV_HOOK);
         EventType(value)
     }
 }
@@ -60,6 +88,14 @@ impl EventCode {
         ev_type: EventType::MSC,
         code: ecodes::MSC_SCAN,
     };
+    pub const DEVICE_CONNECTED: EventCode = EventCode {
+        ev_type: EventType::EVSIEVE,
+        code: ecodes::EVSIEVE_DEVICE_CONNECTED,
+    };
+    pub const DEVICE_LOST: EventCode = EventCode {
+        ev_type: EventType::EVSIEVE,
+        code: ecodes::EVSIEVE_DEVICE_LOST,
+    };
 
     pub const fn new(ev_type: EventType, code: u16) -> EventCode {
         EventCode { ev_type, code }
@@ -133,6 +169,12 @@ pub struct Event {
 
     pub domain: Domain,
     pub namespace: Namespace,
+
+    /// The stage of the argument that generated or last changed this event. See `StageIndex`.
+    pub stage: StageIndex,
+
+    /// The annotations that have been attached to this event by keys like "key:a^macro".
+    pub annotations: Annotations,
 }
 
 impl Event {
@@ -142,7 +184,7 @@ impl Event {
                domain: Domain,
                namespace: Namespace
     ) -> Event {
-        Event { code, value, previous_value, domain, namespace }
+        Event { code, value, previous_value, domain, namespace, stage: 0, annotations: Annotations::empty() }
     }
 
     pub fn with_domain(mut self, new_domain: Domain) -> Event {
@@ -174,6 +216,14 @@ impl fmt::Debug for Event {
 }
 
 
+/// Identifies a stage declared by a --stage argument, counted by the order of declaration. Stage 0
+/// is the implicit stage named "input", which all events start in and which the arguments before the
+/// first --stage belong to.
+///
+/// Unlike namespaces, stages do not affect which events a filter matches by themselves. Only the
+/// arguments with a stage= clause ignore the events of stages that were not listed in that clause.
+pub type StageIndex = usize;
+
 /// Namespaces are an internal concept that is not visible to the user. They are like domains, but
 /// then on a higher level such that even a filter with an empty domain cannot match events within a
 /// different namespace.
@@ -190,4 +240,7 @@ pub enum Namespace {
     /// This event was caught by an --output and shall now be sent to an output device. It is not
     /// affected by any StreamEntry.
     Output,
+    /// This event was generated by libevdev while resynchronising an input device with resync=isolate
+    /// after the kernel dropped events. It is only affected by maps with the resync flag and by outputs.
+    Resync,
 }
\ No newline at end of file
@@ -0,0 +1,425 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Introspection sockets let other programs, such as graphical front-ends, ask a running evsieve which
+//! event names exist, which arguments it is running with and which input devices it has opened.
+//!
+//! Clients send one request per line in a format inspired by JSON-RPC, e.g. {"id":1,"method":"devices"},
+//! and receive one response per line, e.g. {"jsonrpc":"2.0","id":1,"result":[...]}.
+
+use std::io::{Read, Write};
+use std::os::unix::fs::FileTypeExt;
+use std::os::unix::io::{RawFd, AsRawFd};
+use std::os::unix::net::{UnixListener, UnixStream};
+
+use crate::domain;
+use crate::ecodes;
+use crate::error::{SystemError, Context};
+use crate::persist::blueprint::Blueprint;
+use crate::privileges::{self, FileOwner};
+use crate::utils::json::{self, JsonValue};
+use crate::utils::json_string;
+
+/// The names of all methods that can be requested through an introspection socket.
+pub const METHOD_NAMES: &[&str] = &["ecodes", "arguments", "devices", "features"];
+
+/// Requests longer than this are rejected, so a misbehaving client cannot make evsieve use arbitrary
+/// amounts of memory.
+const MAX_REQUEST_LENGTH: usize = 64 * 1024;
+
+/// If more than this many bytes of responses are waiting for a client to read them, the client is
+/// assumed to have stopped reading and gets disconnected.
+const MAX_PENDING_OUTPUT: usize = 4 * 1024 * 1024;
+
+/// The error codes as defined by the JSON-RPC 2.0 specification.
+const PARSE_ERROR: i32 = -32700;
+const INVALID_REQUEST: i32 = -32600;
+const METHOD_NOT_FOUND: i32 = -32601;
+
+/// An introspection socket as specified by an --introspect-socket argument, before it has been created.
+pub struct PreIntrospectSocket {
+    pub path: String,
+    /// If Some, the socket gets these permissions after it has been created.
+    pub mode: Option<u32>,
+    /// If Some, the socket gets this owner after it has been created.
+    pub owner: Option<FileOwner>,
+}
+
+/// Listens for programs that want to connect to an introspection socket. The socket is removed from the
+/// filesystem when this structure is dropped.
+pub struct IntrospectSocket {
+    listener: UnixListener,
+    path: String,
+}
+
+impl IntrospectSocket {
+    pub fn create(pre_socket: PreIntrospectSocket) -> Result<IntrospectSocket, SystemError> {
+        let PreIntrospectSocket { path, mode, owner } = pre_socket;
+
+        // A socket that is left over from a previous run of evsieve would make binding fail. Other kinds
+        // of files are left alone, in case the path was a typo.
+        if let Ok(metadata) = std::fs::symlink_metadata(&path) {
+            if ! metadata.file_type().is_socket() {
+                return Err(SystemError::new(format!(
+                    "Cannot create an introspection socket at {}, because a file that is not a socket already exists there.", path
+                )));
+            }
+            std::fs::remove_file(&path).map_err(SystemError::from)
+                .with_context_of(|| format!("While trying to remove the old socket at {}:", path))?;
+        }
+
+        let listener = privileges::create_with_owner_and_mode(&path, owner, mode, || {
+            UnixListener::bind(&path).map_err(SystemError::from)
+                .with_context_of(|| format!("While trying to create an introspection socket at {}:", path))
+        })?;
+        listener.set_nonblocking(true)?;
+        Ok(IntrospectSocket { listener, path })
+    }
+
+    /// Accepts all programs that are currently waiting to connect.
+    pub fn accept(&mut self) -> Result<Vec<IntrospectClient>, SystemError> {
+        let mut clients = Vec::new();
+        loop {
+            match self.listener.accept() {
+                Ok((stream, _address)) => clients.push(IntrospectClient::new(stream)?),
+                Err(error) => match error.kind() {
+                    std::io::ErrorKind::WouldBlock => return Ok(clients),
+                    std::io::ErrorKind::Interrupted => continue,
+                    // The client may have given up on connecting already.
+                    std::io::ErrorKind::ConnectionAborted => continue,
+                    _ => return Err(error.into()),
+                },
+            }
+        }
+    }
+
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl AsRawFd for IntrospectSocket {
+    fn as_raw_fd(&self) -> RawFd {
+        self.listener.as_raw_fd()
+    }
+}
+
+impl Drop for IntrospectSocket {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// A program that has connected to an introspection socket.
+pub struct IntrospectClient {
+    stream: UnixStream,
+    /// Bytes that have been received, but do not form a complete line yet.
+    buffer: Vec<u8>,
+    /// Bytes of responses that the client has not read yet.
+    output: Vec<u8>,
+}
+
+impl IntrospectClient {
+    /// The stream is made non-blocking, so a client that does not read its responses cannot stall the
+    /// processing of events. Responses it does not read right away wait until the epoll reports the
+    /// stream to be writable.
+    fn new(stream: UnixStream) -> Result<IntrospectClient, SystemError> {
+        stream.set_nonblocking(true)?;
+        Ok(IntrospectClient { stream, buffer: Vec::new(), output: Vec::new() })
+    }
+
+    /// Reads the requests that the client has sent. Returns None if the client has closed the connection.
+    pub fn poll(&mut self) -> Result<Option<Vec<Request>>, SystemError> {
+        let mut buf: [u8; 4096] = [0; 4096];
+        let num_bytes_read = match self.stream.read(&mut buf) {
+            Ok(0) => return Ok(None),
+            Ok(num_bytes_read) => num_bytes_read,
+            Err(error) => match error.kind() {
+                std::io::ErrorKind::Interrupted | std::io::ErrorKind::WouldBlock => return Ok(Some(Vec::new())),
+                _ => return Err(error.into()),
+            },
+        };
+        self.buffer.extend_from_slice(&buf[0 .. num_bytes_read]);
+
+        let mut requests = Vec::new();
+        while let Some(newline_index) = self.buffer.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = self.buffer.drain(..= newline_index).collect();
+            let line = String::from_utf8_lossy(&line);
+            if ! line.trim().is_empty() {
+                requests.push(Request::parse(&line));
+            }
+        }
+        if self.buffer.len() > MAX_REQUEST_LENGTH {
+            return Err(SystemError::new(format!(
+                "A client sent a request longer than {} bytes to an introspection socket.", MAX_REQUEST_LENGTH
+            )));
+        }
+        Ok(Some(requests))
+    }
+
+    /// Closes the connection in both directions.
+    pub fn shutdown(&mut self) {
+        let _ = self.stream.shutdown(std::net::Shutdown::Both);
+    }
+
+    /// Sends a response as returned by Request::respond() to the client. Returns an error if the client
+    /// has stopped reading its responses.
+    pub fn send(&mut self, response: &str) -> Result<(), SystemError> {
+        self.output.extend_from_slice(response.as_bytes());
+        self.output.push(b'\n');
+        self.flush()?;
+        if self.output.len() > MAX_PENDING_OUTPUT {
+            return Err(SystemError::new(format!(
+                "A client of an introspection socket did not read more than {} bytes of responses.", MAX_PENDING_OUTPUT
+            )));
+        }
+        Ok(())
+    }
+
+    /// Writes as much of the pending responses as the client accepts right now.
+    pub fn flush(&mut self) -> Result<(), SystemError> {
+        let mut num_bytes_written = 0;
+        while num_bytes_written < self.output.len() {
+            match self.stream.write(&self.output[num_bytes_written ..]) {
+                Ok(0) => break,
+                Ok(num_bytes) => num_bytes_written += num_bytes,
+                Err(error) => match error.kind() {
+                    std::io::ErrorKind::Interrupted => continue,
+                    std::io::ErrorKind::WouldBlock => break,
+                    _ => return Err(error.into()),
+                },
+            }
+        }
+        self.output.drain(.. num_bytes_written);
+        Ok(())
+    }
+
+    /// Returns whether some responses are waiting for the client to read them, in which case the epoll
+    /// should report when the stream becomes writable.
+    pub fn has_pending_output(&self) -> bool {
+        ! self.output.is_empty()
+    }
+}
+
+impl AsRawFd for IntrospectClient {
+    fn as_raw_fd(&self) -> RawFd {
+        self.stream.as_raw_fd()
+    }
+}
+
+pub enum Method {
+    /// The names and numeric values of all event types and codes.
+    Ecodes,
+    /// The arguments of each pipeline, split into groups that each start with an argument like --map.
+    Arguments,
+    /// The input devices that are currently opened or revoked.
+    Devices,
+    /// The same document that --feature-info prints.
+    Features,
+}
+
+pub struct Request {
+    /// The id member of the request, which must be included in the response.
+    id: JsonValue,
+    /// The requested method, or the error code and message that should be sent back.
+    pub method: Result<Method, (i32, String)>,
+}
+
+impl Request {
+    pub fn parse(line: &str) -> Request {
+        let value = match json::parse(line) {
+            Ok(value) => value,
+            Err(error) => return Request {
+                id: JsonValue::Null, method: Err((PARSE_ERROR, error)),
+            },
+        };
+        let id = value.get("id").cloned().unwrap_or(JsonValue::Null);
+        let method = match value.get("method").and_then(JsonValue::as_str) {
+            Some("ecodes") => Ok(Method::Ecodes),
+            Some("arguments") => Ok(Method::Arguments),
+            Some("devices") => Ok(Method::Devices),
+            Some("features") => Ok(Method::Features),
+            Some(name) => Err((METHOD_NOT_FOUND, format!(
+                "Unknown method \"{}\". The following methods exist: {}.", name, METHOD_NAMES.join(", ")
+            ))),
+            None => Err((INVALID_REQUEST, "The request must be an object with a method member.".to_owned())),
+        };
+        Request { id, method }
+    }
+
+    /// Returns the response to this request. `result` must be the JSON-encoded result of the method if the
+    /// request is valid, and is ignored otherwise.
+    pub fn respond(&self, result: &str) -> String {
+        match &self.method {
+            Ok(_) => format!("{{\"jsonrpc\":\"2.0\",\"id\":{},\"result\":{}}}", self.id, result),
+            Err((code, message)) => format!(
+                "{{\"jsonrpc\":\"2.0\",\"id\":{},\"error\":{{\"code\":{},\"message\":{}}}}}",
+                self.id, code, json_string(message)
+            ),
+        }
+    }
+}
+
+/// Describes an input device for the devices method.
+pub struct DeviceInfo {
+    pub path: String,
+    pub name: Option<String>,
+    /// The name of the domain of the device, if the user gave it one.
+    pub domain: Option<String>,
+    pub pipeline: usize,
+    pub state: DeviceState,
+}
+
+impl DeviceInfo {
+    /// Describes a device that was closed by the revoke command and can be reopened from its blueprint.
+    pub fn revoked(pipeline: usize, blueprint: &Blueprint) -> DeviceInfo {
+        DeviceInfo {
+            path: blueprint.pre_device.path.display().to_string(),
+            name: blueprint.name.as_ref().map(|name| name.to_string_lossy().into_owned()),
+            domain: domain::try_reverse_resolve(blueprint.pre_device.target_domain),
+            pipeline,
+            state: DeviceState::Revoked,
+        }
+    }
+}
+
+pub enum DeviceState {
+    /// The events of the device are sent through the stream.
+    Active,
+    /// The device is part of a fallback group and another member of that group is active.
+    Standby,
+    /// Access to the device was revoked, either by the revoke command or by logind.
+    Revoked,
+    /// The events are read from a file recorded by --print format=binary.
+    Replay,
+}
+
+impl DeviceState {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DeviceState::Active => "active",
+            DeviceState::Standby => "standby",
+            DeviceState::Revoked => "revoked",
+            DeviceState::Replay => "replay",
+        }
+    }
+}
+
+fn json_optional_string(text: &Option<String>) -> String {
+    match text {
+        Some(text) => json_string(text),
+        None => "null".to_owned(),
+    }
+}
+
+fn json_string_list(items: &[String]) -> String {
+    let items: Vec<String> = items.iter().map(|item| json_string(item)).collect();
+    format!("[{}]", items.join(","))
+}
+
+/// Returns an object that maps the name of each event type to its numeric value and its codes. Types that
+/// only exist within evsieve, such as hook, have null as numeric values.
+pub fn ecodes_result() -> String {
+    let mut type_names: Vec<&String> = ecodes::EVENT_TYPES.keys().collect();
+    type_names.sort();
+    let types: Vec<String> = type_names.into_iter().map(|type_name| {
+        let ev_type = ecodes::EVENT_TYPES[type_name];
+        let mut codes: Vec<(&String, u16)> = ecodes::EVENT_CODES.iter()
+            .filter(|((code_type_name, _), _)| code_type_name == type_name)
+            .map(|((_, code_name), code)| (code_name, code.code()))
+            .collect();
+        codes.sort();
+        let numeric_value = |value: u16| match ev_type.is_internal() {
+            true => "null".to_owned(),
+            false => value.to_string(),
+        };
+        let codes: Vec<String> = codes.into_iter()
+            .map(|(code_name, code)| format!("{}:{}", json_string(code_name), numeric_value(code)))
+            .collect();
+        format!(
+            "{}:{{\"type\":{},\"codes\":{{{}}}}}",
+            json_string(type_name), numeric_value(u16::from(ev_type)), codes.join(","),
+        )
+    }).collect();
+    format!("{{{}}}", types.join(","))
+}
+
+/// Returns an array with the argument groups of each pipeline. The first pipeline is the one defined by
+/// the command line, the others are defined by --pipeline arguments in the order they were specified.
+pub fn arguments_result(pipelines: &[Vec<Vec<String>>]) -> String {
+    let pipelines: Vec<String> = pipelines.iter().map(|groups| {
+        let groups: Vec<String> = groups.iter().map(|group| json_string_list(group)).collect();
+        format!("[{}]", groups.join(","))
+    }).collect();
+    format!("[{}]", pipelines.join(","))
+}
+
+pub fn devices_result(devices: &[DeviceInfo]) -> String {
+    let devices: Vec<String> = devices.iter().map(|device| {
+        format!(
+            "{{\"path\":{},\"name\":{},\"domain\":{},\"pipeline\":{},\"state\":{}}}",
+            json_string(&device.path), json_optional_string(&device.name), json_optional_string(&device.domain),
+            device.pipeline, json_string(device.state.name()),
+        )
+    }).collect();
+    format!("[{}]", devices.join(","))
+}
+
+#[test]
+fn unittest() {
+    // A client that does not read its responses cannot block evsieve, and gets dropped eventually.
+    let (stream, _peer) = UnixStream::pair().unwrap();
+    let mut client = IntrospectClient::new(stream).unwrap();
+    let response = "x".repeat(64 * 1024);
+    let mut num_sent = 0;
+    while client.send(&response).is_ok() {
+        num_sent += 1;
+        // Part of the responses fit in the buffer of the socket itself.
+        assert!(num_sent <= 4 * MAX_PENDING_OUTPUT / response.len());
+    }
+
+    // Responses that do not fit in the socket right away arrive intact once the client reads them.
+    let (stream, mut peer) = UnixStream::pair().unwrap();
+    let mut client = IntrospectClient::new(stream).unwrap();
+    for _ in 0 .. 32 {
+        client.send(&response).unwrap();
+    }
+    assert!(client.has_pending_output());
+    peer.set_nonblocking(true).unwrap();
+    let mut received = Vec::new();
+    let mut buf = vec![0; 4096];
+    while received.len() < 32 * (response.len() + 1) {
+        match peer.read(&mut buf) {
+            Ok(num_bytes) => received.extend_from_slice(&buf[.. num_bytes]),
+            Err(_) => client.flush().unwrap(),
+        }
+    }
+    assert!(! client.has_pending_output());
+    assert_eq!(received, format!("{}\n", response).repeat(32).into_bytes());
+
+    let request = Request::parse("{\"id\": \"a\", \"method\": \"devices\"}");
+    assert!(matches!(request.method, Ok(Method::Devices)));
+    assert_eq!(request.respond("[]"), "{\"jsonrpc\":\"2.0\",\"id\":\"a\",\"result\":[]}");
+
+    let request = Request::parse("{\"id\": 2, \"method\": \"nonexistent\"}");
+    assert!(matches!(request.method, Err((METHOD_NOT_FOUND, _))));
+    assert!(request.respond("[]").starts_with("{\"jsonrpc\":\"2.0\",\"id\":2,\"error\":{\"code\":-32601,"));
+
+    assert!(matches!(Request::parse("{\"id\": 3}").method, Err((INVALID_REQUEST, _))));
+    assert!(matches!(Request::parse("devices").method, Err((PARSE_ERROR, _))));
+
+    let ecodes = ecodes_result();
+    assert!(json::parse(&ecodes).is_ok());
+    assert!(ecodes.contains("\"key\":{\"type\":1,\"codes\":{"));
+    assert!(ecodes.contains("\"a\":30,"));
+
+    assert_eq!(
+        arguments_result(&[vec![vec!["--map".to_owned(), "key:a".to_owned(), "key:b".to_owned()]], Vec::new()]),
+        "[[[\"--map\",\"key:a\",\"key:b\"]],[]]"
+    );
+    assert_eq!(
+        devices_result(&[DeviceInfo {
+            path: "/dev/input/event0".to_owned(), name: None, domain: Some("kb".to_owned()), pipeline: 0, state: DeviceState::Standby,
+        }]),
+        "[{\"path\":\"/dev/input/event0\",\"name\":null,\"domain\":\"kb\",\"pipeline\":0,\"state\":\"standby\"}]"
+    );
+}
@@ -54,6 +54,12 @@ impl<T: HasFixedFd> Epoll<T> {
     /// # Safety
     /// The file must return a valid raw file descriptor.
     pub fn add_file(&mut self, file: T) -> Result<FileIndex, SystemError> {
+        self.add_file_with_events(file, libc::EPOLLIN as u32)
+    }
+
+    /// Like `add_file`, but waits for other events than EPOLLIN. For example, sysfs attributes signal
+    /// changes using EPOLLPRI, which is also reported as `Message::Ready`.
+    pub fn add_file_with_events(&mut self, file: T, events: u32) -> Result<FileIndex, SystemError> {
         let index = self.get_unique_index();
         let file_fd = file.as_raw_fd();
 
@@ -65,7 +71,7 @@ impl<T: HasFixedFd> Epoll<T> {
 
         // We set the data to the index of said file, so we know which file is ready for reading.
         let mut event = libc::epoll_event {
-            events: libc::EPOLLIN as u32,
+            events,
             u64: index.0,
         };
 
@@ -83,11 +89,44 @@ impl<T: HasFixedFd> Epoll<T> {
         }
     }
 
+    /// Changes which events the epoll waits for on a file that was already added. EPOLLOUT is reported
+    /// as `Message::Ready` as well.
+    pub fn modify_events(&mut self, index: FileIndex, events: u32) -> Result<(), SystemError> {
+        let file_fd = match self.files.get(&index) {
+            Some(file) => file.as_raw_fd(),
+            None => return Err(SystemError::new("Cannot modify the events of a file that does not belong to an epoll.")),
+        };
+        let mut event = libc::epoll_event {
+            events,
+            u64: index.0,
+        };
+        let result = unsafe { libc::epoll_ctl(
+            self.fd.as_raw_fd(),
+            libc::EPOLL_CTL_MOD,
+            file_fd,
+            &mut event,
+        ) };
+
+        if result < 0 {
+            Err(SystemError::os_with_context("While modifying a file in an epoll instance:"))
+        } else {
+            Ok(())
+        }
+    }
+
     /// Returns an iterator over all files belonging to this epoll.
     pub fn files(&self) -> impl Iterator<Item=&T> {
         self.files.values()
     }
 
+    pub fn files_mut(&mut self) -> impl Iterator<Item=&mut T> {
+        self.files.values_mut()
+    }
+
+    pub fn indexed_files(&self) -> impl Iterator<Item=(FileIndex, &T)> {
+        self.files.iter().map(|(&index, file)| (index, file))
+    }
+
     pub fn contains_index(&self, index: FileIndex) -> bool {
         self.files.contains_key(&index)
     }
@@ -177,10 +216,12 @@ impl<T: HasFixedFd> Epoll<T> {
         for event in events {
             let file_index = FileIndex(event.u64);
 
-            if event.events & libc::EPOLLIN as u32 != 0 {
+            if event.events & (libc::EPOLLIN | libc::EPOLLPRI | libc::EPOLLOUT) as u32 != 0 {
                 messages.push(Message::Ready(file_index));
             }
-            if event.events & libc::EPOLLERR as u32 != 0 {
+            // Sysfs attributes report EPOLLERR together with EPOLLPRI whenever they change, which
+            // does not mean that they are broken.
+            if event.events & libc::EPOLLERR as u32 != 0 && event.events & libc::EPOLLPRI as u32 == 0 {
                 messages.push(Message::Broken(file_index));
             } else if event.events & libc::EPOLLHUP as u32 != 0 {
                 messages.push(Message::Hup(file_index));
@@ -87,6 +87,65 @@ impl Read for ReadableFd {
     }
 }
 
+/// The first file descriptor that systemd passes to a service, as defined by sd_listen_fds(3).
+const SD_LISTEN_FDS_START: RawFd = 3;
+
+/// Looks up a file descriptor that systemd passed to this process under a certain name, e.g. through the
+/// `FileDescriptorName=` option of a socket unit or the `OpenFile=` option of a service unit. Returns None
+/// if systemd did not pass a file descriptor with that name to us.
+pub fn find_systemd_fd(name: &str) -> Option<RawFd> {
+    // The LISTEN_* variables are only meant for us if $LISTEN_PID is our PID. Otherwise, they may
+    // have been inherited from some parent process.
+    let listen_pid: u32 = std::env::var("LISTEN_PID").ok()?.parse().ok()?;
+    if listen_pid != std::process::id() {
+        return None;
+    }
+    let num_fds: usize = std::env::var("LISTEN_FDS").ok()?.parse().ok()?;
+    let names = std::env::var("LISTEN_FDNAMES").ok()?;
+
+    let index = names.split(':').take(num_fds).position(|fd_name| fd_name == name)?;
+    Some(SD_LISTEN_FDS_START + index as RawFd)
+}
+
+/// Prepares a file descriptor that was inherited from the parent process for use by evsieve. Checks
+/// whether it is actually open, sets the close-on-exec flag so it will not leak to the subprocesses
+/// spawned by evsieve, and adds the provided status flags such as O_NONBLOCK to it.
+pub fn prepare_inherited_fd(fd: RawFd, status_flags: libc::c_int) -> Result<(), SystemError> {
+    unsafe {
+        let fd_flags = libc::fcntl(fd, libc::F_GETFD);
+        if fd_flags < 0 {
+            return Err(SystemError::os_with_context(format!("While checking the inherited file descriptor {}:", fd)));
+        }
+        if libc::fcntl(fd, libc::F_SETFD, fd_flags | libc::FD_CLOEXEC) < 0 {
+            return Err(SystemError::os_with_context(format!("While setting the close-on-exec flag on the file descriptor {}:", fd)));
+        }
+
+        let fl_flags = libc::fcntl(fd, libc::F_GETFL);
+        if fl_flags < 0 || libc::fcntl(fd, libc::F_SETFL, fl_flags | status_flags) < 0 {
+            return Err(SystemError::os_with_context(format!("While changing the status flags of the file descriptor {}:", fd)));
+        }
+    }
+    Ok(())
+}
+
+/// The major device number of the character devices under /dev/input.
+const INPUT_MAJOR: libc::c_uint = 13;
+
+/// Returns an error unless the file descriptor refers to an event device, i.e. a character device
+/// with the input major number. Used to verify inherited file descriptors before taking ownership
+/// of them, so a mistyped fd= clause does not make evsieve claim some unrelated file.
+pub fn check_event_device(fd: RawFd) -> Result<(), SystemError> {
+    let mut stat: libc::stat = unsafe { std::mem::zeroed() };
+    if unsafe { libc::fstat(fd, &mut stat) } < 0 {
+        return Err(SystemError::os_with_context(format!("While checking the inherited file descriptor {}:", fd)));
+    }
+    let is_char_device = stat.st_mode & libc::S_IFMT == libc::S_IFCHR;
+    if ! is_char_device || unsafe { libc::major(stat.st_rdev) } != INPUT_MAJOR {
+        return Err(SystemError::new(format!("The file descriptor {} does not refer to an event device.", fd)));
+    }
+    Ok(())
+}
+
 /// # Safety
 ///
 /// An unsafe marker trait: if a structure implements this trait, it promises that its file descriptor
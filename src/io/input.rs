@@ -2,21 +2,23 @@
 
 use std::ffi::{CStr, CString};
 use std::fs::{File, OpenOptions};
-use std::os::unix::fs::OpenOptionsExt;
-use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::fs::{MetadataExt, OpenOptionsExt};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
 use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use std::mem::MaybeUninit;
 use crate::bindings::libevdev;
-use crate::event::{Event, EventType, EventValue, EventCode, Namespace};
+use crate::event::{Event, EventType, EventValue, EventCode, Channel, Namespace};
+use crate::key::Key;
 use crate::domain::Domain;
 use crate::capability::{AbsInfo, Capabilities, InputCapabilites, RepeatInfo};
 use crate::ecodes;
-use crate::predevice::{GrabMode, PersistState, PreInputDevice};
+use crate::predevice::{FallbackMembership, GrabMode, OpenMode, PersistState, PreInputDevice};
+use crate::arguments::output::{DeviceId, DeviceProperties};
 use crate::persist::storage::CachedCapabilities;
-use crate::error::{SystemError, Context};
+use crate::error::{SystemError, Context, ErrorCategory};
 use crate::persist::blueprint::Blueprint;
-use crate::time::Instant;
+use crate::time::{Duration, Instant};
 
 use super::fd::HasFixedFd;
 
@@ -27,7 +29,10 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
 {
     let mut input_devices: Vec<InputDevice> = Vec::new();
     let mut blueprints: Vec<Blueprint> = Vec::new();
-    
+    // Members of fallback groups that are not available right now. That is only an error if no other
+    // member of their group is available either.
+    let mut absent_members: Vec<(PreInputDevice, SystemError)> = Vec::new();
+
     for pre_device in pre_input_devices {
         match InputDevice::open(pre_device) {
             Ok(device) => {
@@ -40,6 +45,7 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
                 // Whether or failing to open an event device means that the whole operation fails depend on what
                 // the specified persistence mode.
                 match pre_device.persist_state {
+                    PersistState::Reopen if pre_device.fallback.is_some() => absent_members.push((pre_device, error)),
                     // The following persistence modes tell us to exit if the device is not available when the program starts.
                     PersistState::None | PersistState::Reopen | PersistState::Exit => return Err(error),
                     // Full persistence tells us to try to find the capabilities of this device cached on the hard drive.
@@ -48,11 +54,15 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
                             // If we the capabilities of this device were properly cached, then we can just create a
                             // blueprint based on those capabilities.
                             CachedCapabilities::Known(ref capabilities_ref) => {
-                                let capabilities = capabilities_ref.clone();
+                                let mut capabilities = capabilities_ref.clone();
+                                if let Some(read_filter) = &pre_device.read_filter {
+                                    read_filter.apply_to_caps(&mut capabilities, pre_device.domain);
+                                }
                                 blueprints.push(Blueprint {
                                     pre_device,
                                     capabilities,
                                     name: None,
+                                    revoked: false,
                                 });
                             },
                             // If they are not found, then the best thing we could either exit with an error, or assume
@@ -67,7 +77,7 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
                                     device_cache.location.display(),
                                 );
 
-                                blueprints.push(Blueprint { pre_device, capabilities: Capabilities::new(), name: None })
+                                blueprints.push(Blueprint { pre_device, capabilities: Capabilities::new(), name: None, revoked: false })
                             },
                             CachedCapabilities::Corrupted => {
                                 eprintln!(
@@ -76,7 +86,7 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
                                     device_cache.location.display(),
                                 );
 
-                                blueprints.push(Blueprint { pre_device, capabilities: Capabilities::new(), name: None })
+                                blueprints.push(Blueprint { pre_device, capabilities: Capabilities::new(), name: None, revoked: false })
                             },
                         }
                     },
@@ -85,9 +95,24 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
         }
     }
 
-    // Return an error if a device with grab=force cannot be grabbed.
-    for device in &mut input_devices {
-        device.grab_if_desired()?;
+    // An absent member of a fallback group is expected to have the same capabilities as the other members
+    // of its group, since they are usually different interfaces of the same physical device.
+    for (pre_device, error) in absent_members {
+        let group = pre_device.fallback.as_ref().map(|membership| &membership.group);
+        let group_capabilities = input_devices.iter()
+            .filter(|device| device.fallback.as_ref().map(|membership| &membership.group) == group)
+            .map(|device| &device.capabilities)
+            .chain(blueprints.iter()
+                .filter(|blueprint| blueprint.pre_device.fallback.as_ref().map(|membership| &membership.group) == group)
+                .map(|blueprint| &blueprint.capabilities))
+            .next()
+            .cloned();
+        match group_capabilities {
+            Some(capabilities) => blueprints.push(Blueprint { pre_device, capabilities, name: None, revoked: false }),
+            None => return Err(error.with_context(format!(
+                "None of the input devices in the fallback group \"{}\" are available:", group.map(String::as_str).unwrap_or_default()
+            ))),
+        }
     }
 
     // Precompute the capabilities of the input devices.
@@ -95,18 +120,120 @@ pub fn open_and_query_capabilities(pre_input_devices: Vec<PreInputDevice>)
     for device in &input_devices {
         // TODO: LOW-PRIORITY: Consider using an Rc instead of a clone.
         capabilities.insert(device.domain, device.capabilities.clone());
+        if let Some(resync_domain) = device.resync_domain {
+            capabilities.insert(resync_domain, device.capabilities.clone());
+        }
     }
     for blueprint in &blueprints {
         capabilities.insert(blueprint.pre_device.domain, blueprint.capabilities.clone());
+        if let Some(resync_domain) = blueprint.pre_device.resync_domain {
+            capabilities.insert(resync_domain, blueprint.capabilities.clone());
+        }
     }
 
     Ok((input_devices, blueprints, capabilities))
 }
 
+/// Grabs the input devices that should be grabbed right away. This should only happen after the output
+/// devices have been created, so the user does not lose control over their keyboard if that fails.
+///
+/// Returns an error if a device with grab=force cannot be grabbed. If `all_or_nothing` is true, all devices
+/// that were grabbed by this function get released again before that error is returned.
+pub fn grab_input_devices(input_devices: &mut [&mut InputDevice], all_or_nothing: bool) -> Result<(), SystemError> {
+    for index in 0 .. input_devices.len() {
+        if let Err(error) = input_devices[index].grab_if_desired() {
+            if all_or_nothing {
+                for device in &mut input_devices[.. index] {
+                    if device.inner.grabbed {
+                        device.inner.ungrab().with_context_of(|| format!(
+                            "While releasing the grab on {}:", device.path.display()
+                        )).print_err();
+                    }
+                }
+            }
+            return Err(error);
+        }
+    }
+    Ok(())
+}
+
 /// Represents a name as reported by libevdev_get_name().
 pub type InputDeviceName = CString;
 
+/// The highest INPUT_PROP_* property, as defined in <linux/input-event-codes.h>.
+const INPUT_PROP_MAX: u32 = 0x1f;
+
+/// The EVIOCGREP ioctl, defined as _IOR('E', 0x03, unsigned int[2]) in <linux/input.h>.
+const EVIOCGREP: libc::c_ulong = 0x8008_4503;
+/// The EVIOCSREP ioctl, defined as _IOW('E', 0x03, unsigned int[2]) in <linux/input.h>.
+const EVIOCSREP: libc::c_ulong = 0x4008_4503;
+
+/// Something that can be done with the autorepeat settings of an input device.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RepeatSetting {
+    /// Set the delay and period (both in milliseconds) of autorepeat.
+    Set { delay: EventValue, period: EventValue },
+    /// Disable autorepeat.
+    Off,
+    /// Go back to the settings the device had after evsieve opened it.
+    Restore,
+    /// Disable autorepeat if it is enabled, otherwise restore it.
+    Toggle,
+}
+
+/// Specified by the stale-after= and stale-action= clauses on an --input argument. If a device claims
+/// that some keys are pressed but does not send any events for `timeout`, it is considered stale.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct StaleSetting {
+    pub timeout: Duration,
+    pub action: StaleAction,
+}
+
+/// What should happen when a device is considered stale.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum StaleAction {
+    /// Synthesize release events for all keys that the device claims are pressed.
+    Release,
+    /// Treat the device as if it was disconnected: release its keys and try to reopen it.
+    Reopen,
+}
+
+/// Specified by the filter= clauses on an --input argument. Events that match none of its keys are dropped
+/// right after they have been read, and the device is treated as if it were not capable of them.
+#[derive(Clone)]
+pub struct ReadFilter {
+    keys: Vec<Key>,
+}
+
+impl ReadFilter {
+    /// The keys should not have any requirements on their value or domain.
+    pub fn new(keys: Vec<Key>) -> ReadFilter {
+        ReadFilter { keys }
+    }
+
+    /// EV_SYN events always pass, because they delimit the reports of the other events.
+    pub fn accepts(&self, channel: Channel) -> bool {
+        channel.0.ev_type().is_syn() || self.keys.iter().any(|key| key.matches_channel(channel))
+    }
+
+    /// Removes all codes that this filter does not accept from the capabilities of a device.
+    pub fn apply_to_caps(&self, caps: &mut Capabilities, domain: Domain) {
+        caps.codes.retain(|&code| self.accepts((code, domain)));
+        caps.abs_info.retain(|&code, _| self.accepts((code, domain)));
+    }
+}
+
+/// A request from a hook to change the autorepeat settings of the input devices. If `domain` is Some,
+/// only the input devices with that domain are affected.
+#[derive(Clone, Copy, Debug)]
+pub struct RepeatCommand {
+    pub domain: Option<Domain>,
+    pub setting: RepeatSetting,
+}
+
 pub struct InputDevice {
+    /// Must be declared before `file`, so it gets dropped while the file descriptor is still open.
+    repeat_guard: RepeatGuard,
     /// The file owns the file descriptor to the input device. Beware: InputDevice implements HasFixedFd.
     file: File,
     inner: LibevdevDevice,
@@ -132,6 +259,99 @@ pub struct InputDevice {
 
     /// What should happen if this device disconnects.
     persist_state: PersistState,
+
+    /// If Some, events that libevdev generates to resynchronise the device after a SYN_DROPPED
+    /// are given this domain instead of `domain`.
+    resync_domain: Option<Domain>,
+
+    /// The domain the events of this device have after they enter the stream.
+    target_domain: Domain,
+    /// If Some, this device was opened from a file descriptor inherited from the parent process.
+    inherited_fd: Option<RawFd>,
+    open_mode: OpenMode,
+    /// If true, hooks cannot change the autorepeat settings of this device.
+    observe_only: bool,
+    /// The autorepeat setting that was requested with the repeat= clause on the --input argument.
+    repeat_setting: Option<RepeatSetting>,
+    /// Whether autorepeat has been disabled by a hook.
+    repeat_is_off: bool,
+    /// The heartbeat setting that was requested with the stale-after= clause on the --input argument.
+    stale_setting: Option<StaleSetting>,
+    /// If Some, only the events accepted by this filter are read from this device.
+    read_filter: Option<ReadFilter>,
+    /// The last time at which this device was opened or sent events.
+    last_activity: Instant,
+    stats: DeviceStats,
+    /// If Some, this device is a member of a fallback group.
+    fallback: Option<FallbackMembership>,
+    /// If true, another member of this device's fallback group is active, so the events of this
+    /// device shall not enter the stream.
+    standby: bool,
+    /// If true, the events of this device enter the stream one packet at a time.
+    packets: bool,
+}
+
+/// The length of the periods over which the event rate of a device is measured.
+const RATE_WINDOW: Duration = Duration::from_millis(1000);
+
+/// Counters about the events that have been read from an input device, which can be shown using
+/// the "stats devices" command of a control FIFO or the --stats argument.
+pub struct DeviceStats {
+    pub events_read: u64,
+    pub bytes_read: u64,
+    last_event_time: Option<Instant>,
+    /// The start of the period over which the event rate is currently being measured.
+    window_start: Instant,
+    /// The amount of events read since `window_start`.
+    window_events: u64,
+    /// The event rate that was measured in the last completed period.
+    last_rate: f64,
+}
+
+impl DeviceStats {
+    fn new(now: Instant) -> DeviceStats {
+        DeviceStats {
+            events_read: 0,
+            bytes_read: 0,
+            last_event_time: None,
+            window_start: now,
+            window_events: 0,
+            last_rate: 0.0,
+        }
+    }
+
+    fn record(&mut self, num_events: usize, now: Instant) {
+        self.roll_window(now);
+        if num_events > 0 {
+            self.events_read += num_events as u64;
+            self.bytes_read += (num_events * std::mem::size_of::<libevdev::input_event>()) as u64;
+            self.window_events += num_events as u64;
+            self.last_event_time = Some(now);
+        }
+    }
+
+    fn roll_window(&mut self, now: Instant) {
+        let elapsed = now.checked_duration_since(self.window_start).unwrap_or(Duration::from_nanos(0));
+        if elapsed >= RATE_WINDOW {
+            self.last_rate = self.window_events as f64 / elapsed.as_secs_f64();
+            self.window_start = now;
+            self.window_events = 0;
+        }
+    }
+
+    /// Returns the amount of events per second over the last completed measuring period.
+    pub fn events_per_second(&self, now: Instant) -> f64 {
+        let elapsed = now.checked_duration_since(self.window_start).unwrap_or(Duration::from_nanos(0));
+        match elapsed >= RATE_WINDOW {
+            true => self.window_events as f64 / elapsed.as_secs_f64(),
+            false => self.last_rate,
+        }
+    }
+
+    /// Returns how long ago the last event was read, or None if no events have been read yet.
+    pub fn last_event_age(&self, now: Instant) -> Option<Duration> {
+        self.last_event_time.and_then(|time| now.checked_duration_since(time))
+    }
 }
 
 /// This is a part of InputDevice that has been put in its separate structure to make working with destructors easier;
@@ -154,16 +374,26 @@ impl InputDevice {
     #[allow(clippy::result_large_err)]
     pub fn open(pre_device: PreInputDevice) -> Result<InputDevice, (PreInputDevice, SystemError)> {
         // Open the file itself.
-        let file_res = OpenOptions::new()
-            .read(true)
-            // O_CLOEXEC is already set by default in the std source code, but I'm providing it
-            // anyway to clearly signify we _need_ that flag.
-            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
-            .open(&pre_device.path);
+        let file_res = match pre_device.inherited_fd {
+            Some(fd) => super::fd::check_event_device(fd)
+                .and_then(|()| super::fd::prepare_inherited_fd(fd, libc::O_NONBLOCK))
+                // Safety: the fd is open and refers to an event device. The argument parser rejects
+                // any fd that is claimed by more than one --input or --output argument, and the
+                // blueprint of this device forgets the fd, so this is the only File that owns it.
+                .map(|()| unsafe { File::from_raw_fd(fd) }),
+            None => OpenOptions::new()
+                .read(true)
+                .write(pre_device.open_mode == OpenMode::ReadWrite)
+                // O_CLOEXEC is already set by default in the std source code, but I'm providing it
+                // anyway to clearly signify we _need_ that flag.
+                .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+                .open(&pre_device.path)
+                .map_err(SystemError::from),
+        };
 
         let file = match file_res {
             Ok(file) => file,
-            Err(error) => return Err((pre_device, error.into())),
+            Err(error) => return Err((pre_device, error)),
         };
 
         // Turn the file into an evdev instance.
@@ -173,11 +403,14 @@ impl InputDevice {
         };
         if res < 0 {
             let error_msg = format!("Failed to open a libevdev instance: {}.", pre_device.path.to_string_lossy());
-            return Err((pre_device, SystemError::new(error_msg)));
+            return Err((pre_device, SystemError::new(error_msg).with_category(ErrorCategory::from_errno(-res))));
         }
 
         let capabilities = unsafe { get_capabilities(evdev) };
-        let state = unsafe { get_device_state(evdev, &capabilities) };
+        let mut state = unsafe { get_device_state(evdev, &capabilities) };
+        if let Some(read_filter) = &pre_device.read_filter {
+            state.retain(|&code, _| read_filter.accepts((code, pre_device.domain)));
+        }
 
         // According to the documentation, libevdev_get_name() never returns a null pointer
         // but may return an empty string. We are not sure whether the return value is guaranteed
@@ -198,17 +431,62 @@ impl InputDevice {
             eprintln!("Warning: failed to set the clock to CLOCK_MONOTONIC on the device opened from {}.\nThis is a non-fatal error, but any time-related operations such as the --delay argument will behave incorrectly.", pre_device.path.to_string_lossy());
         }
 
+        let mut capabilities = capabilities;
+        let mut repeat_guard = RepeatGuard { fd: file.as_raw_fd(), original: None };
+        if let (Some(RepeatSetting::Set { delay, period }), Some(_)) = (pre_device.repeat_setting, capabilities.rep_info) {
+            let rep_info = RepeatInfo { delay, period };
+            match repeat_guard.change(rep_info) {
+                Ok(()) => capabilities.rep_info = Some(rep_info),
+                Err(error) => error.with_context(format!(
+                    "While changing the autorepeat settings of {}:", pre_device.path.display()
+                )).print_err(),
+            }
+        }
+        let repeat_is_off = match (pre_device.repeat_setting, capabilities.rep_info) {
+            (Some(RepeatSetting::Off), Some(rep_info)) => {
+                match repeat_guard.change(RepeatInfo { period: 0, ..rep_info }) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        error.with_context(format!(
+                            "While disabling the autorepeat of {}:", pre_device.path.display()
+                        )).print_err();
+                        false
+                    },
+                }
+            },
+            _ => false,
+        };
+
         // Now that we know the real input capabilities of this device, update the cache on the
         // disk if necessary.
         let mut persist_state = pre_device.persist_state;
         persist_state.update_caps(&capabilities, &pre_device.path);
+        // The cache holds the real capabilities so it stays valid if the filter= clause gets changed.
+        if let Some(read_filter) = &pre_device.read_filter {
+            read_filter.apply_to_caps(&mut capabilities, pre_device.domain);
+        }
 
         Ok(InputDevice {
-            file, capabilities, state, name,
+            repeat_guard, file, capabilities, state, name,
             path: pre_device.path,
             domain: pre_device.domain,
             grab_mode: pre_device.grab_mode,
+            resync_domain: pre_device.resync_domain,
+            target_domain: pre_device.target_domain,
+            inherited_fd: pre_device.inherited_fd,
+            open_mode: pre_device.open_mode,
+            observe_only: pre_device.observe_only,
+            repeat_setting: pre_device.repeat_setting,
+            repeat_is_off,
             persist_state,
+            stale_setting: pre_device.stale_setting,
+            read_filter: pre_device.read_filter,
+            last_activity: Instant::now(),
+            stats: DeviceStats::new(Instant::now()),
+            // Members of a fallback group stay on standby until the main loop decides which member is active.
+            standby: pre_device.fallback.is_some(),
+            fallback: pre_device.fallback,
+            packets: pre_device.packets,
             inner: LibevdevDevice {
                 evdev, grabbed: false
             }
@@ -219,10 +497,25 @@ impl InputDevice {
         self.domain
     }
 
-    fn read_raw(&mut self) -> Result<Vec<(Instant, EventCode, EventValue)>, SystemError> {
+    pub fn target_domain(&self) -> Domain {
+        self.target_domain
+    }
+
+    /// Returns whether the domain filter of a control command like "revoke @kb" applies to this device.
+    pub fn matches_domain_filter(&self, filter: Option<Domain>) -> bool {
+        filter.map_or(true, |filter| crate::domain::matches(self.target_domain, filter))
+    }
+
+    pub fn resync_domain(&self) -> Option<Domain> {
+        self.resync_domain
+    }
+
+    /// Reads all available events from the device. The returned boolean is true for events that
+    /// were generated by libevdev to resynchronise the device state after a SYN_DROPPED.
+    fn read_raw(&mut self) -> Result<Vec<(Instant, EventCode, EventValue, bool)>, SystemError> {
         let mut event: MaybeUninit<libevdev::input_event> = MaybeUninit::uninit();
         let mut should_sync = false;
-        let mut events: Vec<(Instant, EventCode, EventValue)> = Vec::new();
+        let mut events: Vec<(Instant, EventCode, EventValue, bool)> = Vec::new();
 
         loop {
             let flags = match should_sync {
@@ -245,7 +538,7 @@ impl InputDevice {
                         let event_type = EventType::new(event.type_);
                         let event_code = EventCode::new(event_type, event.code);
                         let event_time = event.time.into();
-                        events.push((event_time, event_code, event.value));
+                        events.push((event_time, event_code, event.value, should_sync));
                     }
 
                     should_sync = res == SYNC;
@@ -279,12 +572,35 @@ impl InputDevice {
         )
     }
 
+    /// Returns the current value of all LEDs of this device.
+    pub fn led_values(&self) -> impl Iterator<Item=(EventCode, EventValue)> + '_ {
+        self.state.iter()
+            .filter(|(code, _)| code.ev_type().is_led())
+            .map(|(&code, &value)| (code, value))
+    }
+
     /// Reads the raw events from the device and attached additional information such as the
     /// domain of this device and whatever value this event had the last time it was seen.
     pub fn poll(&mut self) -> Result<Vec<(Instant, Event)>, SystemError> {
-        let events: Vec<(Instant, Event)> = self.read_raw()?
+        let mut raw_events = self.read_raw()?;
+        let now = Instant::now();
+        self.stats.record(raw_events.len(), now);
+        if ! raw_events.is_empty() {
+            self.last_activity = now;
+        }
+        if let Some(read_filter) = &self.read_filter {
+            let domain = self.domain;
+            raw_events.retain(|&(_, code, _, _)| read_filter.accepts((code, domain)));
+        }
+        let events: Vec<(Instant, Event)> = raw_events
             .into_iter()
-            .map(|(time, code, value)| (time, self.synthesize_event(code, value)))
+            .map(|(time, code, value, is_resync)| {
+                let mut event = self.synthesize_event(code, value);
+                if let (true, Some(resync_domain)) = (is_resync, self.resync_domain) {
+                    event.domain = resync_domain;
+                }
+                (time, event)
+            })
             .collect();
 
         self.grab_if_desired()?;
@@ -324,10 +640,89 @@ impl InputDevice {
             .map(|(&code, &_value)| code)
     }
 
+    /// Synthesizes release events for all keys that are currently pressed on this device.
+    pub fn release_pressed_keys(&mut self) -> Vec<Event> {
+        let pressed_keys: Vec<EventCode> = self.get_pressed_keys().collect();
+        pressed_keys.into_iter()
+            .map(|code| self.synthesize_event(code, 0))
+            .collect()
+    }
+
+    pub fn stale_setting(&self) -> Option<StaleSetting> {
+        self.stale_setting
+    }
+
+    /// Returns the time at which this device shall be considered stale if it does not send any
+    /// more events, or None if it can currently not become stale because no keys are pressed.
+    pub fn stale_deadline(&self) -> Option<Instant> {
+        let setting = self.stale_setting?;
+        // The keys of a device on standby never entered the stream, so they need not be released.
+        if self.standby {
+            return None;
+        }
+        if self.get_pressed_keys().count() == 0 {
+            return None;
+        }
+        Some(self.last_activity + setting.timeout)
+    }
+
     pub fn path(&self) -> &Path {
         &self.path
     }
 
+    /// Acts upon a request from a hook to change the autorepeat settings. Does nothing if the command
+    /// is meant for another device, if this device does not support autorepeat, or if this device
+    /// is observe-only.
+    pub fn apply_repeat_command(&mut self, command: RepeatCommand) -> Result<(), SystemError> {
+        if command.domain.map_or(false, |domain| domain != self.target_domain) {
+            return Ok(());
+        }
+        if self.observe_only {
+            return Ok(());
+        }
+        // The settings that were active right after opening the device.
+        let default_rep_info = match self.capabilities.rep_info {
+            Some(rep_info) => rep_info,
+            None => return Ok(()),
+        };
+
+        let setting = match command.setting {
+            RepeatSetting::Toggle => match self.repeat_is_off {
+                true => RepeatSetting::Restore,
+                false => RepeatSetting::Off,
+            },
+            other => other,
+        };
+        let (rep_info, is_off) = match setting {
+            RepeatSetting::Set { delay, period } => (RepeatInfo { delay, period }, period == 0),
+            // The kernel does not generate repeat events if the period is zero.
+            RepeatSetting::Off => (RepeatInfo { period: 0, ..default_rep_info }, true),
+            RepeatSetting::Restore | RepeatSetting::Toggle => (default_rep_info, false),
+        };
+
+        self.repeat_guard.change(rep_info)
+            .with_context_of(|| format!("While changing the autorepeat settings of {}:", self.path.display()))?;
+        self.repeat_is_off = is_off;
+        Ok(())
+    }
+
+    /// Returns a line like "/dev/input/event3: 12.0 events/s, 1423 events (34152 bytes) read, last event 0.250s ago".
+    pub fn describe_stats(&self, now: Instant) -> String {
+        let rate = self.stats.events_per_second(now);
+        let last_event = match self.stats.last_event_age(now) {
+            Some(age) => format!("last event {:.3}s ago", age.as_secs_f64()),
+            None => "no events yet".to_owned(),
+        };
+        format!(
+            "{}: {:.1} events/s, {} events ({} bytes) read, {}",
+            self.path.display(), rate, self.stats.events_read, self.stats.bytes_read, last_event,
+        )
+    }
+
+    pub fn stats(&self) -> &DeviceStats {
+        &self.stats
+    }
+
     pub fn capabilities(&self) -> &Capabilities {
         &self.capabilities
     }
@@ -336,20 +731,112 @@ impl InputDevice {
         &self.name
     }
 
+    /// Returns the name, IDs and input properties of this device, as used by the mirror-of= clause.
+    pub fn identity(&self) -> DeviceProperties {
+        let evdev = self.inner.evdev;
+        // Libevdev returns ints for the IDs, but they are 16-bit values in the kernel.
+        let (vendor_id, product_id, version, bus) = unsafe { (
+            libevdev::libevdev_get_id_vendor(evdev) as u16,
+            libevdev::libevdev_get_id_product(evdev) as u16,
+            libevdev::libevdev_get_id_version(evdev) as u16,
+            libevdev::libevdev_get_id_bustype(evdev) as u16,
+        ) };
+        let input_props = (0 ..= INPUT_PROP_MAX)
+            .filter(|&prop| unsafe { libevdev::libevdev_has_property(evdev, prop) } == 1)
+            .collect();
+        DeviceProperties {
+            name: self.name.to_string_lossy().into_owned(),
+            device_id: Some(DeviceId { vendor_id, product_id }),
+            version: Some(version),
+            bus: Some(bus),
+            input_props,
+        }
+    }
+
     pub fn persist_state(&self) -> &PersistState {
         &self.persist_state
     }
 
+    pub fn fallback(&self) -> Option<&FallbackMembership> {
+        self.fallback.as_ref()
+    }
+
+    pub fn is_standby(&self) -> bool {
+        self.standby
+    }
+
+    pub fn set_standby(&mut self, standby: bool) {
+        self.standby = standby;
+    }
+
+    /// Returns the path of the device node that this device was opened from, even if it was opened
+    /// through a symlink or an inherited file descriptor.
+    fn node_path(&self) -> Option<PathBuf> {
+        std::fs::read_link(format!("/proc/self/fd/{}", self.file.as_raw_fd())).ok()
+    }
+
+    /// Returns true if the file descriptor of this device no longer works even though the device itself
+    /// still exists. This happens when access to it has been revoked using EVIOCREVOKE, which logind
+    /// does to the devices of a session when switching to another VT.
+    pub fn is_revoked(&self) -> bool {
+        let node_path = match self.node_path() {
+            Some(path) => path,
+            None => return false,
+        };
+        let (node_metadata, own_metadata) = match (std::fs::metadata(&node_path), self.file.metadata()) {
+            (Ok(node_metadata), Ok(own_metadata)) => (node_metadata, own_metadata),
+            _ => return false,
+        };
+        if node_metadata.rdev() != own_metadata.rdev() {
+            return false;
+        }
+        // The device node may linger for a short while after the device has been unplugged, but opening
+        // it fails with ENODEV in that case. Failing with EACCES means the device is merely inaccessible.
+        match OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC).open(&node_path) {
+            Ok(_) => true,
+            Err(error) => error.kind() == std::io::ErrorKind::PermissionDenied,
+        }
+    }
+
+    /// Closes the device and returns a blueprint from which it can be reopened, even if the device was
+    /// opened from an inherited file descriptor. Should be used for devices that were revoked rather than
+    /// disconnected.
+    pub fn into_revoked_blueprint(self) -> Blueprint {
+        let node_path = match self.inherited_fd {
+            Some(_) => self.node_path(),
+            None => None,
+        };
+        let mut blueprint = self.into_blueprint();
+        if let Some(node_path) = node_path {
+            blueprint.pre_device.path = node_path;
+        }
+        blueprint.revoked = true;
+        blueprint
+    }
+
     // Closes the device and returns a blueprint from which it can be reopened.
     pub fn into_blueprint(self) -> Blueprint {
         Blueprint {
             capabilities: self.capabilities,
             name: Some(self.name),
+            revoked: false,
             pre_device: PreInputDevice {
                 path: self.path,
                 grab_mode: self.grab_mode,
                 domain: self.domain,
                 persist_state: self.persist_state,
+                resync_domain: self.resync_domain,
+                target_domain: self.target_domain,
+                repeat_setting: self.repeat_setting,
+                stale_setting: self.stale_setting,
+                read_filter: self.read_filter,
+                open_mode: self.open_mode,
+                observe_only: self.observe_only,
+                fallback: self.fallback,
+                packets: self.packets,
+                // Inherited file descriptors are closed together with the device and can
+                // therefore not be used to reopen it.
+                inherited_fd: None,
             },
         }
     }
@@ -363,7 +850,7 @@ impl LibevdevDevice {
         if res < 0 {
             Err(SystemError::new(
                 format!("Failed to grab input device: received libevdev status code {res}"
-            )))
+            )).with_category(ErrorCategory::from_errno(-res)))
         } else {
             self.grabbed = true;
             Ok(())
@@ -372,7 +859,7 @@ impl LibevdevDevice {
 
     fn ungrab(&mut self) -> Result<(), SystemError> {
         let res = unsafe {
-            libevdev::libevdev_grab(self.evdev, libevdev::libevdev_grab_mode_LIBEVDEV_GRAB)
+            libevdev::libevdev_grab(self.evdev, libevdev::libevdev_grab_mode_LIBEVDEV_UNGRAB)
         };
         if res < 0 {
             Err(SystemError::new(
@@ -388,6 +875,55 @@ impl LibevdevDevice {
 /// This implement is necessary becaus *mut libevdev::libevdev is not Send.
 unsafe impl Send for InputDevice {}
 
+/// Puts the autorepeat settings of an input device back the way they were before evsieve changed
+/// them when it gets dropped, i.e. when the device is closed.
+struct RepeatGuard {
+    fd: RawFd,
+    /// The settings the device had before evsieve first changed them, or None if evsieve has not
+    /// changed them.
+    original: Option<RepeatInfo>,
+}
+
+impl RepeatGuard {
+    /// Changes the autorepeat settings of the device. The first time, the current settings are read
+    /// so they can be restored later. If that fails, the settings are not changed.
+    fn change(&mut self, rep_info: RepeatInfo) -> Result<(), SystemError> {
+        if self.original.is_none() {
+            self.original = Some(get_repeat(self.fd)?);
+        }
+        set_repeat(self.fd, rep_info)
+    }
+}
+
+impl Drop for RepeatGuard {
+    fn drop(&mut self) {
+        // If the device has been disconnected, there is nothing left to restore.
+        if let Some(original) = self.original {
+            let _ = set_repeat(self.fd, original);
+        }
+    }
+}
+
+/// Reads the autorepeat settings of the event device through the EVIOCGREP ioctl.
+fn get_repeat(fd: RawFd) -> Result<RepeatInfo, SystemError> {
+    let mut values: [libc::c_uint; 2] = [0; 2];
+    let res = unsafe { libc::ioctl(fd, EVIOCGREP, values.as_mut_ptr()) };
+    if res < 0 {
+        return Err(SystemError::os_with_context("While reading the current autorepeat settings:"));
+    }
+    Ok(RepeatInfo { delay: values[0] as EventValue, period: values[1] as EventValue })
+}
+
+/// Changes the autorepeat settings of the event device through the EVIOCSREP ioctl.
+fn set_repeat(fd: RawFd, rep_info: RepeatInfo) -> Result<(), SystemError> {
+    let values: [libc::c_uint; 2] = [rep_info.delay as libc::c_uint, rep_info.period as libc::c_uint];
+    let res = unsafe { libc::ioctl(fd, EVIOCSREP, values.as_ptr()) };
+    if res < 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
 /// # Safety
 /// Exhibits undefined behaviour if evdev is not a valid pointer.
 unsafe fn get_capabilities(evdev: *mut libevdev::libevdev) -> Capabilities {
@@ -477,3 +1013,23 @@ impl Drop for LibevdevDevice {
         }
     }
 }
+
+#[test]
+fn unittest_stats() {
+    let start = Instant::now();
+    let mut stats = DeviceStats::new(start);
+    assert!(stats.last_event_age(start).is_none());
+
+    stats.record(4, start + Duration::from_millis(100));
+    stats.record(6, start + Duration::from_millis(500));
+    assert_eq!(stats.events_read, 10);
+    assert_eq!(stats.bytes_read, 10 * std::mem::size_of::<libevdev::input_event>() as u64);
+    assert_eq!(stats.last_event_age(start + Duration::from_millis(700)), Some(Duration::from_millis(200)));
+    // The first measuring period has not been completed yet.
+    assert_eq!(stats.events_per_second(start + Duration::from_millis(900)), 0.0);
+    assert_eq!(stats.events_per_second(start + Duration::from_millis(2000)), 5.0);
+
+    stats.record(0, start + Duration::from_millis(2000));
+    assert_eq!(stats.events_per_second(start + Duration::from_millis(2500)), 5.0);
+    assert_eq!(stats.events_per_second(start + Duration::from_millis(3000)), 0.0);
+}
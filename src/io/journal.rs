@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the journal of the --recover argument, which keeps track of which keys are currently
+//! pressed on the output devices, so the next instance of evsieve can release them if this one crashes.
+
+use std::collections::BTreeSet;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::ecodes;
+use crate::error::SystemError;
+use crate::event::{Event, EventCode};
+use crate::time::{Duration, Instant};
+
+/// The journal is written to disk whenever it changes, but not flushed from the page cache more often
+/// than this. It only needs to survive evsieve crashing, which leaves the page cache intact.
+const SYNC_INTERVAL: Duration = Duration::from_millis(5000);
+
+/// Identifies an output device by its position among the --output arguments, starting at one.
+pub type OutputIndex = usize;
+
+pub struct KeyJournal {
+    path: PathBuf,
+    pressed: BTreeSet<(OutputIndex, EventCode)>,
+    /// Whether `pressed` has changed since the journal was last written to disk.
+    dirty: bool,
+    last_sync: Option<Instant>,
+}
+
+impl KeyJournal {
+    /// Creates an empty journal. The file at the given path gets overwritten at the next flush.
+    pub fn new(path: PathBuf) -> KeyJournal {
+        KeyJournal {
+            path,
+            pressed: BTreeSet::new(),
+            dirty: true,
+            last_sync: None,
+        }
+    }
+
+    /// Returns the keys that the journal file claims are pressed. These were left behind by a previous
+    /// instance of evsieve and must be released by the caller.
+    pub fn read_stuck_keys(&self) -> Result<Vec<(OutputIndex, EventCode)>, SystemError> {
+        read_journal(&self.path)
+    }
+
+    /// Updates the journal to reflect that an event has been written to an output device.
+    pub fn record(&mut self, output_index: OutputIndex, event: Event) {
+        if ! event.ev_type().is_key() {
+            return;
+        }
+        let changed = match event.value {
+            0 => self.pressed.remove(&(output_index, event.code)),
+            1 => self.pressed.insert((output_index, event.code)),
+            _ => false,
+        };
+        self.dirty |= changed;
+    }
+
+    /// Writes the journal to disk if it has changed.
+    pub fn flush(&mut self) -> Result<(), SystemError> {
+        if ! self.dirty {
+            return Ok(());
+        }
+        self.dirty = false;
+
+        let mut content = String::new();
+        for (output_index, code) in &self.pressed {
+            content.push_str(&format!("{} {}\n", output_index, ecodes::event_name(*code)));
+        }
+        let mut file = std::fs::File::create(&self.path)?;
+        file.write_all(content.as_bytes())?;
+
+        let now = Instant::now();
+        let sync_due = match self.last_sync {
+            Some(last_sync) => last_sync + SYNC_INTERVAL <= now,
+            None => true,
+        };
+        if sync_due {
+            file.sync_data()?;
+            self.last_sync = Some(now);
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Removes the journal file. Should be called when evsieve exits normally, since the output devices
+    /// and the keys pressed on them disappear together with evsieve.
+    pub fn remove(&self) -> Result<(), SystemError> {
+        match std::fs::remove_file(&self.path) {
+            Ok(()) => Ok(()),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(error) => Err(error.into()),
+        }
+    }
+}
+
+/// Reads the journal file, where each line looks like
+///     OUTPUT_INDEX TYPE:CODE
+/// If the file does not exist, returns an empty vector.
+fn read_journal(path: &Path) -> Result<Vec<(OutputIndex, EventCode)>, SystemError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(error.into()),
+    };
+
+    let mut result = Vec::new();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let malformed = || SystemError::new(format!("Malformed line in journal file: {}", line));
+        let (index_str, name) = line.split_once(' ').ok_or_else(malformed)?;
+        let output_index: OutputIndex = index_str.parse().map_err(|_| malformed())?;
+        let (type_name, code_name) = name.split_once(':').ok_or_else(malformed)?;
+        let code = ecodes::event_code(type_name, code_name).map_err(|_| malformed())?;
+        result.push((output_index, code));
+    }
+    Ok(result)
+}
+
+#[test]
+fn unittest() {
+    let path = std::env::temp_dir().join(format!("evsieve-test-journal-{}", std::process::id()));
+    let key_a = ecodes::event_code("key", "a").unwrap();
+    let btn_left = ecodes::event_code("btn", "left").unwrap();
+    let event = |code, value| Event::new(
+        code, value, 0, crate::domain::get_unique_domain(), crate::event::Namespace::Output
+    );
+
+    let mut journal = KeyJournal::new(path.clone());
+    assert!(journal.read_stuck_keys().unwrap().is_empty());
+    journal.record(1, event(key_a, 1));
+    journal.record(2, event(btn_left, 1));
+    journal.record(2, event(btn_left, 2));
+    journal.record(1, event(key_a, 0));
+    journal.record(1, event(key_a, 1));
+    journal.record(2, event(btn_left, 0));
+    journal.flush().unwrap();
+
+    let journal = KeyJournal::new(path.clone());
+    assert_eq!(journal.read_stuck_keys().unwrap(), vec![(1, key_a)]);
+    journal.remove().unwrap();
+    assert!(!path.exists());
+}
@@ -5,20 +5,22 @@ use std::io;
 use std::fs;
 use std::ffi::CString;
 use std::ptr;
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
 use std::path::Path;
 use std::path::PathBuf;
+use std::os::unix::io::RawFd;
 use std::fmt::Write;
 use crate::arguments::output::DeviceProperties;
 use crate::event::EventType;
 use crate::bindings::libevdev;
-use crate::capability::{Capability, Capabilities};
+use crate::capability::{Capability, Capabilities, CapabilitiesDiff};
 use crate::event::Event;
 use crate::domain::Domain;
 use crate::ecodes;
-use crate::error::{InternalError, RuntimeError, SystemError, Context};
+use crate::error::{InternalError, RuntimeError, SystemError, Context, ErrorCategory};
 use crate::event::Namespace;
-use crate::predevice::{PreOutputDevice, RepeatMode};
+use crate::predevice::{PreOutputDevice, RepeatMode, OverflowPolicy, OverflowSetting};
+use crate::io::journal::KeyJournal;
 
 pub trait OutputSystem {
     /// Tries to make sure that all output devices have at least the given capabilities. The output 
@@ -33,17 +35,42 @@ pub trait OutputSystem {
     /// The maps may generate events without folling them up with SYN events.
     /// This function generates all SYN events for user convenience.
     fn synchronize(&mut self);
+
+    /// Returns one line for each output device that explains whether and why it has been recreated.
+    fn describe_recreations(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Returns the domains of the output devices whose capabilities shall be computed for each
+    /// combination of toggle states separately.
+    fn strict_caps_domains(&self) -> Vec<Domain> {
+        Vec::new()
+    }
+
+    /// Tries to write the events that are waiting in the queues of the output devices because the kernel
+    /// did not accept them earlier. Returns true if some events are still waiting afterwards.
+    fn flush_queues(&mut self) -> bool {
+        false
+    }
 }
 
 pub struct UInputSystem {
     pre_devices: Vec<PreOutputDevice>,
     devices: HashMap<Domain, OutputDevice>,
+    /// For each output device that has been recreated, how often that happened and why it happened
+    /// the last time.
+    recreations: HashMap<Domain, (usize, CapabilitiesDiff)>,
+    /// If Some, keeps track of the keys that are pressed on the output devices, as requested by --recover.
+    journal: Option<KeyJournal>,
 }
 
 impl UInputSystem {
+    /// If `journal_path` is Some, then the keys that the journal at that path claims are still pressed
+    /// get released on the newly created output devices, after which the journal gets maintained.
     pub fn create(
             pre_devices: Vec<PreOutputDevice>,
-            capabilities: Vec<Capability>
+            capabilities: Vec<Capability>,
+            journal_path: Option<PathBuf>,
     ) -> Result<UInputSystem, RuntimeError> {
         // Sort the capabilities based on domain.
         let mut capability_map = capabilites_by_device(&capabilities, &pre_devices);
@@ -67,7 +94,78 @@ impl UInputSystem {
             devices.insert(domain, device);
         }
 
-        Ok(UInputSystem { pre_devices, devices })
+        let mut system = UInputSystem { pre_devices, devices, recreations: HashMap::new(), journal: None };
+        if let Some(path) = journal_path {
+            system.recover_from_journal(path);
+        }
+        Ok(system)
+    }
+
+    /// Releases the keys that were left pressed by a previous instance of evsieve and starts keeping a
+    /// journal at the given path.
+    fn recover_from_journal(&mut self, path: PathBuf) {
+        let journal = KeyJournal::new(path);
+        let stuck_keys = match journal.read_stuck_keys() {
+            Ok(stuck_keys) => stuck_keys,
+            Err(error) => {
+                error.with_context(format!("While reading the journal {}:", journal.path().display())).print_err();
+                eprintln!("Warning: no keys will be recovered. The journal will be overwritten.");
+                Vec::new()
+            },
+        };
+
+        let mut num_released: usize = 0;
+        for &(output_index, code) in &stuck_keys {
+            let domain_opt = output_index.checked_sub(1)
+                .and_then(|index| self.pre_devices.get(index))
+                .map(|pre_device| pre_device.domain);
+            let device_opt = domain_opt.and_then(|domain| self.devices.get_mut(&domain));
+            if let Some(device) = device_opt {
+                if device.capabilities.codes.contains(&code) {
+                    device.write(code.ev_type().into(), code.code().into(), 0);
+                    num_released += 1;
+                }
+            }
+        }
+        self.synchronize_devices();
+        if num_released > 0 {
+            println!("Released {} key(s) that were left pressed by a previous instance of evsieve.", num_released);
+        }
+
+        self.journal = Some(journal);
+        self.flush_journal();
+    }
+
+    fn synchronize_devices(&mut self) {
+        for device in self.devices.values_mut() {
+            device.syn_if_required();
+        }
+    }
+
+    /// Writes the journal to disk if necessary. If that fails, the journal is abandoned.
+    fn flush_journal(&mut self) {
+        if let Some(journal) = &mut self.journal {
+            if let Err(error) = journal.flush() {
+                error.with_context(format!("While writing to the journal {}:", journal.path().display())).print_err();
+                eprintln!("Warning: the journal will no longer be updated.");
+                self.journal = None;
+            }
+        }
+    }
+}
+
+impl Drop for UInputSystem {
+    fn drop(&mut self) {
+        // If evsieve exits normally, the output devices disappear with it and there is nothing to recover.
+        // A panic is no normal exit, so the journal is left behind in that case.
+        if std::thread::panicking() {
+            return;
+        }
+        if let Some(journal) = &self.journal {
+            journal.remove()
+                .with_context(format!("While removing the journal {}:", journal.path().display()))
+                .print_err();
+        }
     }
 }
 
@@ -81,7 +179,7 @@ impl OutputSystem for UInputSystem {
         let mut capability_map = capabilites_by_device(&new_capabilities, &self.pre_devices);
 
         let old_output_devices = std::mem::take(&mut self.devices);
-        let mut recreated_output_devices: Vec<&PreOutputDevice> = Vec::new();
+        let mut recreated_output_devices: Vec<(&PreOutputDevice, CapabilitiesDiff)> = Vec::new();
 
         for (domain, mut old_device) in old_output_devices {
             // Find the new capabilities for this domain.
@@ -106,7 +204,8 @@ impl OutputSystem for UInputSystem {
                 },
             };
 
-            if capabilities.is_compatible_with(&old_device.capabilities) {
+            let diff = capabilities.diff_against(&old_device.capabilities);
+            if diff.is_empty() {
                 self.devices.insert(domain, old_device);
                 continue;
             }
@@ -116,6 +215,31 @@ impl OutputSystem for UInputSystem {
             let symlink = old_device.take_symlink();
             drop(symlink); // TODO: MEDIUM-PRIORITY: make this operation atomical with its recreation.
 
+            // A single uinput file descriptor can only hold a single device at a time, so devices
+            // created on an inherited file descriptor must be destroyed before they can be recreated.
+            if pre_device.uinput_fd.is_some() {
+                let old_capabilities = old_device.capabilities.clone();
+                old_device.syn_if_required();
+                drop(old_device);
+                let new_device = match create_output_device(pre_device, capabilities) {
+                    Ok(device) => device,
+                    Err(error) => {
+                        eprintln!("Error: failed to recreate an output device. Trying to restore it with its old capabilities.");
+                        error.print_err();
+                        match create_output_device(pre_device, old_capabilities) {
+                            Ok(device) => device,
+                            Err(error) => {
+                                error.print_err();
+                                continue;
+                            }
+                        }
+                    },
+                };
+                self.devices.insert(domain, new_device);
+                recreated_output_devices.push((pre_device, diff));
+                continue;
+            }
+
             let new_device = match create_output_device(pre_device, capabilities) {
                 Ok(device) => device,
                 Err(error) => {
@@ -134,7 +258,13 @@ impl OutputSystem for UInputSystem {
             drop(old_device);
 
             self.devices.insert(domain, new_device);
-            recreated_output_devices.push(pre_device);
+            recreated_output_devices.push((pre_device, diff));
+        }
+
+        for (pre_device, diff) in &recreated_output_devices {
+            let recreation = self.recreations.entry(pre_device.domain).or_insert((0, CapabilitiesDiff::default()));
+            recreation.0 += 1;
+            recreation.1 = diff.clone();
         }
 
         if ! recreated_output_devices.is_empty() {
@@ -147,16 +277,54 @@ impl OutputSystem for UInputSystem {
         }
     }
 
+    fn strict_caps_domains(&self) -> Vec<Domain> {
+        self.pre_devices.iter()
+            .filter(|pre_device| pre_device.strict_caps)
+            .map(|pre_device| pre_device.domain)
+            .collect()
+    }
+
+    fn describe_recreations(&self) -> Vec<String> {
+        self.pre_devices.iter().map(|pre_device| {
+            let description = capitalize(&describe_output_device(pre_device));
+            let report = match self.recreations.get(&pre_device.domain) {
+                None => format!("{}: never recreated", description),
+                Some((1, diff)) => format!("{}: recreated once because of {}", description, diff),
+                Some((count, diff)) => format!("{}: recreated {} times, the last time because of {}", description, count, diff),
+            };
+            match self.devices.get(&pre_device.domain).map(|device| device.queue.num_dropped) {
+                Some(num_dropped) if num_dropped > 0 => format!("{}; dropped {} events because its queue was full", report, num_dropped),
+                _ => report,
+            }
+        }).collect()
+    }
+
+    fn flush_queues(&mut self) -> bool {
+        let mut has_pending = false;
+        for device in self.devices.values_mut() {
+            has_pending |= ! device.flush_queue();
+        }
+        has_pending
+    }
+
     /// Writes all events to their respective output devices.
     fn route_events(&mut self, events: &[Event]) {
         for &event in events {
-            if event.namespace != Namespace::Output {
+            // Events of the evsieve and signal types exist only within evsieve and cannot be written to a device.
+            if event.namespace != Namespace::Output || event.ev_type().is_internal() {
                 continue;
             }
 
             let device_opt = self.devices.get_mut(&event.domain);
             match device_opt {
-                Some(device) => device.write_event(event),
+                Some(device) => {
+                    device.write_event(event);
+                    if let Some(journal) = &mut self.journal {
+                        if let Some(index) = self.pre_devices.iter().position(|pre_device| pre_device.domain == event.domain) {
+                            journal.record(index + 1, event);
+                        }
+                    }
+                },
                 None => eprintln!("Internal error: an event {} with unknown domain has been routed to output; event dropped. This is a bug.", event),
             };
         }
@@ -165,9 +333,8 @@ impl OutputSystem for UInputSystem {
     /// The maps may generate events without folling them up with SYN events.
     /// This function generates all SYN events for user convenience.
     fn synchronize(&mut self) {
-        for device in self.devices.values_mut() {
-            device.syn_if_required();
-        }
+        self.synchronize_devices();
+        self.flush_journal();
     }
 }
 
@@ -182,10 +349,14 @@ pub struct OutputDevice {
     allows_repeat: bool,
     /// The capabilities of this output device.
     capabilities: Capabilities,
+    /// Events that the kernel did not accept yet.
+    queue: EventQueue,
 }
 
 impl OutputDevice {
-    pub fn with_properties_and_capabilities(properties: DeviceProperties, caps: Capabilities) -> Result<OutputDevice, RuntimeError> {
+    /// If `uinput_fd` is Some, the device will be created on that file descriptor, which must refer to
+    /// /dev/uinput. Otherwise, /dev/uinput will be opened by libevdev.
+    pub fn with_properties_and_capabilities(properties: DeviceProperties, caps: Capabilities, uinput_fd: Option<RawFd>) -> Result<OutputDevice, RuntimeError> {
         unsafe {
             let dev = libevdev::libevdev_new();
 
@@ -208,6 +379,9 @@ impl OutputDevice {
             if let Some(version) = properties.version {
                 libevdev::libevdev_set_id_version(dev, version.into());
             }
+            for &prop in &properties.input_props {
+                libevdev::libevdev_enable_property(dev, prop);
+            }
 
             // If EV_MSC events are automatically generated, we may need to manually activate
             // their capabilities.
@@ -263,12 +437,25 @@ impl OutputDevice {
                 }
             }
 
+            let open_mode = match uinput_fd {
+                // Libevdev does not close file descriptors that it did not open itself, so the
+                // inherited file descriptor can be reused if this device needs to be recreated.
+                Some(fd) => {
+                    if let Err(error) = super::fd::prepare_inherited_fd(fd, 0) {
+                        libevdev::libevdev_free(dev);
+                        return Err(error.into());
+                    }
+                    fd
+                },
+                // In the source code of the current version of libevdev, the O_CLOEXEC will be
+                // automatically set on the created file descriptor.
+                None => libevdev::libevdev_uinput_open_mode_LIBEVDEV_UINPUT_OPEN_MANAGED,
+            };
+
             let mut uinput_dev: *mut libevdev::libevdev_uinput = ptr::null_mut();
             let res = libevdev::libevdev_uinput_create_from_device(
                 dev,
-                // In the source code of the current version of libevdev, the O_CLOEXEC will be
-                // automatically set on the created file descriptor.
-                libevdev::libevdev_uinput_open_mode_LIBEVDEV_UINPUT_OPEN_MANAGED,
+                open_mode,
                 &mut uinput_dev
             );
 
@@ -276,7 +463,20 @@ impl OutputDevice {
             libevdev::libevdev_free(dev);
 
             if res != 0 {
-                return Err(SystemError::new("Failed to create an UInput device. Does evsieve have enough permissions?").into());
+                return Err(SystemError::new("Failed to create an UInput device. Does evsieve have enough permissions?")
+                    .with_category(ErrorCategory::from_errno(-res)).into());
+            }
+
+            // Make writes non-blocking, so a device that does not accept events cannot stall the whole
+            // pipeline. Events that cannot be written immediately are queued instead. The file status
+            // flags are shared with the parent process for inherited file descriptors, so those are left
+            // in whatever mode the parent process chose.
+            if uinput_fd.is_none() {
+                let fd = libevdev::libevdev_uinput_get_fd(uinput_dev);
+                let flags = libc::fcntl(fd, libc::F_GETFL);
+                if flags < 0 || libc::fcntl(fd, libc::F_SETFL, flags | libc::O_NONBLOCK) < 0 {
+                    eprintln!("Warning: failed to make the writes to an output device non-blocking.");
+                }
             }
 
             Ok(OutputDevice {
@@ -285,6 +485,7 @@ impl OutputDevice {
                 symlink: None,
                 allows_repeat: true,
                 capabilities: caps,
+                queue: EventQueue::new(OverflowSetting::default()),
             })
         }
     }
@@ -293,11 +494,30 @@ impl OutputDevice {
         if ! self.allows_repeat && ev_type == ecodes::EV_KEY.into() && value == 2 {
             return;
         }
-        let res = unsafe { libevdev::libevdev_uinput_write_event(self.device, ev_type, code, value) };
-        if res < 0 {
+        self.should_syn = ev_type != libevdev::EV_SYN;
+
+        let device = self.device;
+        let outcome = self.queue.write((ev_type, code, value), |event| try_write(device, event));
+        self.report_write_outcome(outcome);
+    }
+
+    /// Writes as many queued events as the kernel accepts. Returns true if the queue is empty afterwards.
+    fn flush_queue(&mut self) -> bool {
+        let device = self.device;
+        let (is_empty, outcome) = self.queue.flush(|event| try_write(device, event));
+        self.report_write_outcome(outcome);
+        is_empty
+    }
+
+    /// Prints the warnings that belong to the outcome of writing to this device.
+    fn report_write_outcome(&self, outcome: WriteOutcome) {
+        if outcome.num_errors > 0 {
             eprintln!("Warning: an error occurred while writing an event to {}.", self.description());
         }
-        self.should_syn = ev_type != libevdev::EV_SYN;
+        // Only warn the first time that events get dropped.
+        if outcome.num_dropped > 0 && self.queue.num_dropped == outcome.num_dropped {
+            eprintln!("Warning: events are being dropped because {} does not accept events as fast as they are written to it.", self.description());
+        }
     }
 
     #[cfg(not(feature = "auto-scan"))]
@@ -357,6 +577,10 @@ impl OutputDevice {
         self.symlink.take()
     }
 
+    fn set_overflow(&mut self, overflow: OverflowSetting) {
+        self.queue.overflow = overflow;
+    }
+
     /// Use this function to tell the output device how to handle repeat events that are written
     /// to it from evsieve.
     fn set_repeat_mode(&mut self, mode: RepeatMode) {
@@ -374,6 +598,114 @@ impl OutputDevice {
     }
 }
 
+/// An event as it gets written to an output device: its type, code and value.
+type RawEvent = (u32, u32, i32);
+
+fn try_write(device: *mut libevdev::libevdev_uinput, (ev_type, code, value): RawEvent) -> Result<(), WriteError> {
+    let res = unsafe { libevdev::libevdev_uinput_write_event(device, ev_type, code, value) };
+    match res {
+        0 ..= i32::MAX => Ok(()),
+        _ if -res == libc::EAGAIN => Err(WriteError::WouldBlock),
+        _ => Err(WriteError::Other),
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum WriteError {
+    /// The kernel does not accept more events right now.
+    WouldBlock,
+    Other,
+}
+
+/// How long the block overflow policy waits for the kernel to accept events again after each failed
+/// attempt, in milliseconds. If the queue is still full after the last attempt, the oldest event gets
+/// dropped after all, so a device that never accepts events again cannot hang evsieve.
+const BLOCK_BACKOFF_MS: &[u64] = &[1, 2, 4, 8, 16, 32, 64];
+
+/// What happened during a write to the queue, so the caller can warn about it.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct WriteOutcome {
+    num_errors: usize,
+    num_dropped: usize,
+}
+
+/// Holds the events that the kernel did not accept yet, in the order they need to be written. The
+/// function that does the actual writing is passed to each method, so the queue can be tested without
+/// a uinput device.
+struct EventQueue {
+    events: VecDeque<RawEvent>,
+    overflow: OverflowSetting,
+    /// How many events have been dropped because the queue was full.
+    num_dropped: usize,
+}
+
+impl EventQueue {
+    fn new(overflow: OverflowSetting) -> EventQueue {
+        EventQueue { events: VecDeque::new(), overflow, num_dropped: 0 }
+    }
+
+    /// Writes an event, or queues it if the kernel does not accept it right now.
+    fn write(&mut self, event: RawEvent, mut try_write: impl FnMut(RawEvent) -> Result<(), WriteError>) -> WriteOutcome {
+        // Events must not overtake the events that are already waiting in the queue.
+        let (is_empty, mut outcome) = self.flush(&mut try_write);
+        if is_empty {
+            match try_write(event) {
+                Ok(()) => return outcome,
+                Err(WriteError::WouldBlock) => (),
+                Err(WriteError::Other) => {
+                    outcome.num_errors += 1;
+                    return outcome;
+                },
+            }
+        }
+
+        if self.events.len() >= self.overflow.queue_size {
+            match self.overflow.policy {
+                OverflowPolicy::DropOldest => {
+                    self.events.pop_front();
+                    outcome.num_dropped += 1;
+                },
+                OverflowPolicy::DropNewest => {
+                    outcome.num_dropped += 1;
+                    self.num_dropped += outcome.num_dropped;
+                    return outcome;
+                },
+                OverflowPolicy::Block => {
+                    for &delay_ms in BLOCK_BACKOFF_MS {
+                        std::thread::sleep(std::time::Duration::from_millis(delay_ms));
+                        let (_, flush_outcome) = self.flush(&mut try_write);
+                        outcome.num_errors += flush_outcome.num_errors;
+                        if self.events.len() < self.overflow.queue_size {
+                            break;
+                        }
+                    }
+                    while self.events.len() >= self.overflow.queue_size {
+                        self.events.pop_front();
+                        outcome.num_dropped += 1;
+                    }
+                },
+            }
+        }
+        self.events.push_back(event);
+        self.num_dropped += outcome.num_dropped;
+        outcome
+    }
+
+    /// Writes as many queued events as the kernel accepts. Returns true if the queue is empty afterwards.
+    fn flush(&mut self, mut try_write: impl FnMut(RawEvent) -> Result<(), WriteError>) -> (bool, WriteOutcome) {
+        let mut outcome = WriteOutcome::default();
+        while let Some(&event) = self.events.front() {
+            match try_write(event) {
+                Ok(()) => (),
+                Err(WriteError::WouldBlock) => return (false, outcome),
+                Err(WriteError::Other) => outcome.num_errors += 1,
+            }
+            self.events.pop_front();
+        }
+        (true, outcome)
+    }
+}
+
 impl Drop for OutputDevice {
     fn drop(&mut self) {
         unsafe {
@@ -430,7 +762,7 @@ fn capabilites_by_device(capabilities: &[Capability], pre_devices: &[PreOutputDe
 {
     let mut capability_map: HashMap<Domain, Capabilities> = HashMap::new();
     for capability in capabilities {
-        if capability.namespace != Namespace::Output {
+        if capability.namespace != Namespace::Output || capability.code.ev_type().is_internal() {
             continue;
         }
 
@@ -455,13 +787,14 @@ fn capabilites_by_device(capabilities: &[Capability], pre_devices: &[PreOutputDe
 }
 
 fn create_output_device(pre_device: &PreOutputDevice, capabilities: Capabilities) -> Result<OutputDevice, RuntimeError> {
-    let mut device = OutputDevice::with_properties_and_capabilities(pre_device.properties.clone(), capabilities)
+    let mut device = OutputDevice::with_properties_and_capabilities(pre_device.properties.clone(), capabilities, pre_device.uinput_fd)
         .with_context(match pre_device.create_link.clone() {
             Some(path) => format!("While creating the output device \"{}\":", path.display()),
             None => "While creating an output device:".to_string(),
         })?;
 
     device.set_repeat_mode(pre_device.repeat_mode);
+    device.set_overflow(pre_device.overflow);
 
     if let Some(ref path) = pre_device.create_link {
         device.set_link(path.clone())
@@ -472,12 +805,26 @@ fn create_output_device(pre_device: &PreOutputDevice, capabilities: Capabilities
     Ok(device)
 }
 
-fn format_output_device_recreation_warning(recreated_devices: &[&PreOutputDevice]) -> Result<String, Error>  {
+/// Returns a description like "the output device "/dev/input/by-id/keyboard"" or "the output device
+/// named "Evsieve Virtual Device"" for use in messages.
+fn describe_output_device(pre_device: &PreOutputDevice) -> String {
+    match &pre_device.create_link {
+        Some(path) => format!("the output device \"{}\"", path.display()),
+        None => format!("the output device named \"{}\"", pre_device.properties.name),
+    }
+}
+
+fn capitalize(text: &str) -> String {
+    let mut chars = text.chars();
+    chars.next().into_iter().flat_map(char::to_uppercase).chain(chars).collect()
+}
+
+fn format_output_device_recreation_warning(recreated_devices: &[(&PreOutputDevice, CapabilitiesDiff)]) -> Result<String, Error>  {
     if recreated_devices.is_empty() {
         return Ok("".to_owned());
     }
     let named_recreated_devices: Vec<String> = recreated_devices.iter().filter_map(
-        |device| device.create_link.as_ref().map(
+        |(device, _)| device.create_link.as_ref().map(
             |path| format!("\"{}\"", path.display())
         )
     ).collect();
@@ -507,6 +854,78 @@ fn format_output_device_recreation_warning(recreated_devices: &[&PreOutputDevice
         _ => write!(&mut msg, "have been destroyed and recreated. ")?,
     }
     write!(&mut msg, "This may cause other programs that have grabbed the output devices to lose track of them.")?;
+    for (device, diff) in recreated_devices {
+        let description = capitalize(&describe_output_device(device));
+        write!(&mut msg, "\n    {} had to be recreated because of {}.", description, diff)?;
+    }
 
     Ok(msg)
 }
+
+#[test]
+fn unittest() {
+    use std::cell::Cell;
+
+    let event = |value: i32| (1, 30, value);
+    let queue_with = |policy| EventQueue::new(OverflowSetting { policy, queue_size: 2 });
+    let queued = |queue: &EventQueue| queue.events.iter().map(|&(_, _, value)| value).collect::<Vec<i32>>();
+    let reject = |_| Err(WriteError::WouldBlock);
+
+    // Events are written right away if the kernel accepts them, and in order once it accepts them again.
+    let mut queue = queue_with(OverflowPolicy::DropOldest);
+    let mut written = Vec::new();
+    assert_eq!(queue.write(event(1), |event| { written.push(event.2); Ok(()) }), WriteOutcome::default());
+    queue.write(event(2), reject);
+    queue.write(event(3), reject);
+    assert_eq!(queued(&queue), vec![2, 3]);
+    queue.write(event(4), |event| { written.push(event.2); Ok(()) });
+    assert_eq!(written, vec![1, 2, 3, 4]);
+    assert!(queue.events.is_empty());
+
+    // The drop-oldest policy makes room for the new event.
+    queue.write(event(1), reject);
+    queue.write(event(2), reject);
+    assert_eq!(queue.write(event(3), reject), WriteOutcome { num_errors: 0, num_dropped: 1 });
+    assert_eq!(queued(&queue), vec![2, 3]);
+    assert_eq!(queue.num_dropped, 1);
+
+    // The drop-newest policy drops the new event.
+    let mut queue = queue_with(OverflowPolicy::DropNewest);
+    queue.write(event(1), reject);
+    queue.write(event(2), reject);
+    assert_eq!(queue.write(event(3), reject), WriteOutcome { num_errors: 0, num_dropped: 1 });
+    assert_eq!(queued(&queue), vec![1, 2]);
+    assert_eq!(queue.num_dropped, 1);
+
+    // The block policy waits for the kernel to accept events again without losing any.
+    let mut queue = queue_with(OverflowPolicy::Block);
+    queue.write(event(1), reject);
+    queue.write(event(2), reject);
+    let attempts = Cell::new(0);
+    let accept_later = |_| {
+        attempts.set(attempts.get() + 1);
+        match attempts.get() {
+            1 | 2 => Err(WriteError::WouldBlock),
+            _ => Ok(()),
+        }
+    };
+    assert_eq!(queue.write(event(3), accept_later), WriteOutcome::default());
+    assert_eq!(queued(&queue), vec![3]);
+    assert_eq!(queue.num_dropped, 0);
+
+    // ... but only for a limited time, after which the oldest event gets dropped after all.
+    let mut queue = queue_with(OverflowPolicy::Block);
+    queue.write(event(1), reject);
+    queue.write(event(2), reject);
+    let attempts = Cell::new(0);
+    let outcome = queue.write(event(3), |_| { attempts.set(attempts.get() + 1); Err(WriteError::WouldBlock) });
+    assert_eq!(outcome, WriteOutcome { num_errors: 0, num_dropped: 1 });
+    assert_eq!(queued(&queue), vec![2, 3]);
+    // One attempt before waiting and one attempt after each wait, not a busy loop.
+    assert_eq!(attempts.get(), 1 + BLOCK_BACKOFF_MS.len());
+
+    // Errors other than EAGAIN drop the event that caused them.
+    let mut queue = queue_with(OverflowPolicy::Block);
+    assert_eq!(queue.write(event(1), |_| Err(WriteError::Other)), WriteOutcome { num_errors: 1, num_dropped: 0 });
+    assert!(queue.events.is_empty());
+}
@@ -0,0 +1,312 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the replay flag of --input, which reads events from a file instead of from an event device.
+//! The file either contains raw `struct input_event` records, like those created by `cat /dev/input/event3 > file`,
+//! or events in the format of `crate::io::serialization`, like those written by `--print format=binary`.
+//!
+//! Regular files cannot be waited for using epoll, so a timerfd tells the main loop when the next event
+//! is due.
+
+use std::collections::{HashMap, VecDeque};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+use crate::capability::{AbsInfo, AbsMeta, Capabilities};
+use crate::domain::Domain;
+use crate::error::{Context, SystemError};
+use crate::event::{Event, EventCode, EventValue, Namespace};
+use crate::io::fd::{HasFixedFd, OwnedFd};
+use crate::io::serialization::{self, Record};
+use crate::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReplaySpeed {
+    /// The events are replayed with the same intervals between them as when they were recorded.
+    Original,
+    /// The events are replayed as fast as possible, one report at a time.
+    Fast,
+}
+
+pub struct PreReplayDevice {
+    pub path: PathBuf,
+    /// The domain that all events emitted by this device shall have.
+    pub domain: Domain,
+    /// The domain that the events of this device have after they enter the stream.
+    pub target_domain: Domain,
+    pub speed: ReplaySpeed,
+    /// The intervals between the events are multiplied by this factor.
+    pub time_scale: f64,
+    /// If true, the replayed events shall enter the stream one packet at a time.
+    pub packets: bool,
+}
+
+pub struct ReplayDevice {
+    path: PathBuf,
+    domain: Domain,
+    target_domain: Domain,
+    speed: ReplaySpeed,
+    /// The events that have not been replayed yet, together with how long after the first event of the
+    /// file they were recorded.
+    events: VecDeque<(Duration, EventCode, EventValue)>,
+    /// The capabilities that were stored in the file, if any.
+    recorded_caps: Capabilities,
+    /// The time at which the event recorded `position` after the first event of the file gets replayed.
+    start: Instant,
+    position: Duration,
+    /// The intervals between the events are multiplied by this factor. Only used with ReplaySpeed::Original.
+    time_scale: f64,
+    /// The most recent value of each code, as would be tracked by an input device.
+    state: HashMap<EventCode, EventValue>,
+    timer: OwnedFd,
+}
+
+impl ReplayDevice {
+    /// Reads the whole file. The replay does not begin until `start()` is called.
+    pub fn open(pre_device: PreReplayDevice) -> Result<ReplayDevice, SystemError> {
+        let context = || format!("While reading the events to replay from {}:", pre_device.path.display());
+        let content = std::fs::read(&pre_device.path).map_err(SystemError::from).with_context_of(context)?;
+        let (events, recorded_caps) = match serialization::has_header(&content) {
+            true => parse_serialized_events(&content),
+            false => parse_events(&content).map(|events| (events, Capabilities::new())),
+        }.with_context_of(context)?;
+        let timer = unsafe {
+            OwnedFd::from_syscall(libc::timerfd_create(libc::CLOCK_MONOTONIC, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC))
+                .with_context("While creating a timer:")?
+        };
+
+        Ok(ReplayDevice {
+            path: pre_device.path,
+            domain: pre_device.domain,
+            target_domain: pre_device.target_domain,
+            speed: pre_device.speed,
+            time_scale: pre_device.time_scale,
+            position: Duration::from_nanos(0),
+            events,
+            recorded_caps,
+            start: Instant::now(),
+            state: HashMap::new(),
+            timer,
+        })
+    }
+
+    /// Replays the first event of the file as soon as possible and the others relative to now.
+    pub fn start(&mut self) -> Result<(), SystemError> {
+        self.start = Instant::now();
+        self.position = Duration::from_nanos(0);
+        self.arm_timer()
+    }
+
+    /// Returns the capabilities a device would need to generate all events in the file, together with the
+    /// capabilities stored in the file. Unless stored in the file, the range of each absolute axis is the
+    /// range of the values it takes in the file.
+    pub fn capabilities(&self) -> Capabilities {
+        let mut capabilities = self.recorded_caps.clone();
+        let mut initial_values: HashMap<EventCode, EventValue> = HashMap::new();
+        for &(_, code, value) in &self.events {
+            capabilities.codes.insert(code);
+            if code.ev_type().is_abs() {
+                let initial_value = *initial_values.entry(code).or_insert(value);
+                let info = capabilities.abs_info.entry(code).or_insert(AbsInfo {
+                    min_value: value,
+                    max_value: value,
+                    meta: AbsMeta { fuzz: 0, flat: 0, resolution: 0, value: initial_value },
+                });
+                info.min_value = info.min_value.min(value);
+                info.max_value = info.max_value.max(value);
+            }
+        }
+        capabilities
+    }
+
+    /// Returns all events that are due. In fast mode, returns the events up to and including the next
+    /// SYN_REPORT instead.
+    pub fn poll(&mut self) -> Result<Vec<(Instant, Event)>, SystemError> {
+        // Acknowledge the expiration of the timer.
+        let mut expirations: u64 = 0;
+        unsafe { libc::read(self.timer.as_raw_fd(), &mut expirations as *mut u64 as *mut libc::c_void, 8) };
+
+        let now = Instant::now();
+        let mut result = Vec::new();
+        while let Some(&(offset, code, value)) = self.events.front() {
+            let time = match self.speed {
+                ReplaySpeed::Original => self.due_time(offset),
+                ReplaySpeed::Fast => now,
+            };
+            if time > now {
+                break;
+            }
+            self.events.pop_front();
+            let previous_value = self.state.insert(code, value).unwrap_or(0);
+            result.push((time, Event::new(code, value, previous_value, self.domain, Namespace::Input)));
+            if self.speed == ReplaySpeed::Fast && code.ev_type().is_syn() {
+                break;
+            }
+        }
+
+        self.arm_timer()?;
+        Ok(result)
+    }
+
+    /// Makes the timer expire when the next event is due. Disarms it if no events are left.
+    fn arm_timer(&mut self) -> Result<(), SystemError> {
+        let expiration: libc::timespec = match (self.events.front(), self.speed) {
+            (None, _) => libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            (Some(&(offset, _, _)), ReplaySpeed::Original) => self.due_time(offset).into(),
+            // A zero timespec would disarm the timer, so use the earliest moment that is not zero.
+            (Some(_), ReplaySpeed::Fast) => libc::timespec { tv_sec: 0, tv_nsec: 1 },
+        };
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: expiration,
+        };
+        let res = unsafe {
+            libc::timerfd_settime(self.timer.as_raw_fd(), libc::TFD_TIMER_ABSTIME, &spec, std::ptr::null_mut())
+        };
+        if res < 0 {
+            return Err(SystemError::os_with_context("While setting a timer:"));
+        }
+        Ok(())
+    }
+
+    /// Changes the factor by which the intervals between the events are multiplied. The events that
+    /// have already been replayed keep their timing; the remaining ones continue from the current position.
+    pub fn set_time_scale(&mut self, factor: f64) -> Result<(), SystemError> {
+        let now = Instant::now();
+        let elapsed = now.checked_duration_since(self.start).unwrap_or(Duration::from_nanos(0));
+        self.position = self.position + elapsed.mul_f64(1.0 / self.time_scale);
+        self.start = now;
+        self.time_scale = factor;
+        self.arm_timer()
+    }
+
+    /// The time at which the event recorded `offset` after the first event of the file should be replayed.
+    fn due_time(&self, offset: Duration) -> Instant {
+        self.start + offset.saturating_sub(self.position).mul_f64(self.time_scale)
+    }
+
+    /// Returns true if all events in the file have been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn domain(&self) -> Domain {
+        self.domain
+    }
+
+    pub fn target_domain(&self) -> Domain {
+        self.target_domain
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+/// Interprets the content of a file as a sequence of `struct input_event` in the format of the machine
+/// evsieve runs on.
+fn parse_events(content: &[u8]) -> Result<VecDeque<(Duration, EventCode, EventValue)>, SystemError> {
+    let record_size = std::mem::size_of::<libc::input_event>();
+    if content.len() % record_size != 0 {
+        return Err(SystemError::new(format!(
+            "The file is not a sequence of {}-byte input events. Was it recorded on a machine with a different architecture?", record_size
+        )));
+    }
+
+    let mut events = VecDeque::new();
+    let mut first_time: Option<i64> = None;
+    let mut last_offset: i64 = 0;
+    for (index, record) in content.chunks_exact(record_size).enumerate() {
+        let raw: libc::input_event = unsafe { std::ptr::read_unaligned(record.as_ptr() as *const libc::input_event) };
+        #[allow(clippy::unnecessary_cast)]
+        let time = raw.time.tv_sec as i64 * 1_000_000 + raw.time.tv_usec as i64;
+        let first_time = *first_time.get_or_insert(time);
+        // If the clock jumped backwards during the recording, the event is replayed right after the previous one.
+        last_offset = last_offset.max(time - first_time);
+        let code = crate::ecodes::checked_event_code(raw.type_, raw.code).map_err(|error| SystemError::new(format!(
+            "The event at position {} (byte offset {}) is invalid. {}", index + 1, index * record_size, error
+        )))?;
+        events.push_back((Duration::from_micros(last_offset as u64), code, raw.value));
+    }
+    Ok(events)
+}
+
+/// Interprets the content of a file in the format of `crate::io::serialization`. The domains stored in the
+/// file are ignored, because all events of a replay device get the domain of the device.
+#[allow(clippy::type_complexity)]
+fn parse_serialized_events(content: &[u8]) -> Result<(VecDeque<(Duration, EventCode, EventValue)>, Capabilities), SystemError> {
+    let mut events = VecDeque::new();
+    let mut capabilities = Capabilities::new();
+    let mut first_time: Option<Duration> = None;
+    let mut last_offset = Duration::from_micros(0);
+    for record in serialization::decode(content)? {
+        match record {
+            Record::Event { time, code, value, .. } => {
+                let first_time = *first_time.get_or_insert(time);
+                // Same as for raw input events: time never goes backwards during a replay.
+                let offset = Duration::from_micros(time.as_micros().saturating_sub(first_time.as_micros()));
+                last_offset = last_offset.max(offset);
+                events.push_back((last_offset, code, value));
+            },
+            Record::Capability { code, abs_info, .. } => {
+                capabilities.codes.insert(code);
+                if let Some(abs_info) = abs_info {
+                    capabilities.abs_info.insert(code, abs_info);
+                }
+            },
+        }
+    }
+    Ok((events, capabilities))
+}
+
+impl AsRawFd for ReplayDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timer.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for ReplayDevice {}
+
+#[test]
+fn unittest() {
+    let record = |sec: i64, usec: i64, type_: u16, code: u16, value: i32| {
+        let event = libc::input_event {
+            time: libc::timeval { tv_sec: sec as libc::time_t, tv_usec: usec as libc::suseconds_t },
+            type_, code, value,
+        };
+        let bytes = unsafe { std::slice::from_raw_parts(
+            &event as *const libc::input_event as *const u8, std::mem::size_of::<libc::input_event>()
+        ) };
+        bytes.to_vec()
+    };
+    let content: Vec<u8> = [
+        record(100, 900_000, 1, 30, 1),
+        record(100, 900_000, 0, 0, 0),
+        record(101, 100_000, 1, 30, 0),
+        record(101, 0, 0, 0, 0),
+    ].concat();
+
+    let events = parse_events(&content).unwrap();
+    let offsets: Vec<Duration> = events.iter().map(|&(offset, _, _)| offset).collect();
+    assert_eq!(offsets, vec![
+        Duration::from_micros(0), Duration::from_micros(0), Duration::from_micros(200_000), Duration::from_micros(200_000)
+    ]);
+    assert_eq!(events[0].1, crate::ecodes::event_code("key", "a").unwrap());
+    assert_eq!(events[2].2, 0);
+    assert!(parse_events(&content[1..]).is_err());
+    assert!(parse_events(&[record(100, 0, 1, 30, 1), record(100, 0, 1, 0x3ff, 1)].concat()).is_err());
+    assert!(parse_events(&record(100, 0, 0x1f, 0, 1)).is_err());
+
+    let key_a = crate::ecodes::event_code("key", "a").unwrap();
+    let abs_x = crate::ecodes::event_code("abs", "x").unwrap();
+    let domain = crate::domain::get_unique_domain();
+    let start = Instant::now();
+    let abs_info = AbsInfo { min_value: 0, max_value: 255, meta: AbsMeta { fuzz: 0, flat: 0, resolution: 0, value: 0 } };
+    let content: Vec<u8> = [
+        serialization::encode_header(),
+        serialization::encode_capability(abs_x, domain, Some(&abs_info)),
+        serialization::encode_event(start, &Event::new(key_a, 1, 0, domain, Namespace::User)),
+        serialization::encode_event(start + Duration::from_millis(30), &Event::new(abs_x, 7, 0, domain, Namespace::User)),
+    ].concat();
+    let (events, recorded_caps) = parse_serialized_events(&content).unwrap();
+    assert_eq!(events, vec![(Duration::from_micros(0), key_a, 1), (Duration::from_micros(30_000), abs_x, 7)]);
+    assert_eq!(recorded_caps.abs_info.get(&abs_x), Some(&abs_info));
+}
@@ -0,0 +1,314 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! The binary format in which evsieve writes events to files and streams, and in which it reads them back.
+//! Every feature that stores or transmits events should use this format, so that the output of one
+//! version of evsieve can be read by another.
+//!
+//! # Records
+//! A stream is a sequence of records. Each record starts with an u8 tag that tells what kind of record
+//! it is, followed by an u32 that tells how many bytes the payload of the record has, followed by the
+//! payload itself. Like all other integers, the u32 is encoded in little endian format.
+//!
+//! Readers must skip records with an unknown tag, and must ignore any bytes at the end of a payload
+//! beyond those they know about. This allows future versions to add new kinds of records and to add
+//! fields at the end of existing records without increasing the version number. Changes that would
+//! confuse older readers require a new version number.
+//!
+//! # Header record (tag 0)
+//! 1. The eight bytes "evsieve\0" in ASCII;
+//! 2. An u16 representing the version of the format, which is currently 1.
+//!
+//! Every stream must start with a header. Writers may repeat the header later in the stream, e.g. when a
+//! new reader connects to a FIFO, so readers must accept headers anywhere.
+//!
+//! # Event record (tag 1)
+//! 1. An u64 representing the time at which the event happened in microseconds. Only the differences
+//!    between the times of events in the same stream are meaningful;
+//! 2. An u16 and an u16 representing the type and code of the event;
+//! 3. An i32 and an i32 representing the value and the previous value of the event;
+//! 4. An u16 representing the length in bytes of the name of the domain of the event, followed by the
+//!    name in UTF-8. The length is zero if the domain has no name.
+//!
+//! # Capability record (tag 2)
+//! 1. An u16 and an u16 representing the type and code that a device is capable of;
+//! 2. An u16 representing the length of the name of the domain of the device, followed by the name,
+//!    as in the event record;
+//! 3. Only if the type is EV_ABS: six i32s representing the minimum value, maximum value, fuzz, flat,
+//!    resolution and current value of the axis.
+//!
+//! Capability records describe the device that the following events come from. They should appear
+//! before the first event of the device.
+
+use std::convert::TryInto;
+
+use crate::capability::{AbsInfo, AbsMeta};
+use crate::domain::{self, Domain};
+use crate::error::SystemError;
+use crate::event::{Event, EventCode, EventValue};
+use crate::time::{Duration, Instant};
+
+pub const VERSION: u16 = 1;
+const MAGIC: &[u8; 8] = b"evsieve\0";
+
+const HEADER_TAG: u8 = 0;
+const EVENT_TAG: u8 = 1;
+const CAPABILITY_TAG: u8 = 2;
+
+/// The size of the tag and length that precede every payload.
+const RECORD_PREFIX_SIZE: usize = 5;
+
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub enum Record {
+    Event {
+        /// The time relative to an arbitrary moment. Only the differences between times matter.
+        time: Duration,
+        code: EventCode,
+        value: EventValue,
+        previous_value: EventValue,
+        domain: Option<String>,
+    },
+    Capability {
+        code: EventCode,
+        domain: Option<String>,
+        /// Some if and only if the code has type EV_ABS.
+        abs_info: Option<AbsInfo>,
+    },
+}
+
+/// Returns the header that every stream must start with.
+pub fn encode_header() -> Vec<u8> {
+    let mut payload = MAGIC.to_vec();
+    payload.extend(VERSION.to_le_bytes());
+    encode_record(HEADER_TAG, payload)
+}
+
+pub fn encode_event(time: Instant, event: &Event) -> Vec<u8> {
+    let timespec: libc::timespec = time.into();
+    #[allow(clippy::unnecessary_cast)]
+    let micros = timespec.tv_sec as u64 * 1_000_000 + timespec.tv_nsec as u64 / 1_000;
+
+    let mut payload = Vec::new();
+    payload.extend(micros.to_le_bytes());
+    payload.extend(u16::from(event.code.ev_type()).to_le_bytes());
+    payload.extend(event.code.code().to_le_bytes());
+    payload.extend(event.value.to_le_bytes());
+    payload.extend(event.previous_value.to_le_bytes());
+    push_domain(&mut payload, event.domain);
+    encode_record(EVENT_TAG, payload)
+}
+
+/// `abs_info` must be Some if the code has type EV_ABS, and is ignored otherwise.
+pub fn encode_capability(code: EventCode, domain: Domain, abs_info: Option<&AbsInfo>) -> Vec<u8> {
+    let mut payload = Vec::new();
+    payload.extend(u16::from(code.ev_type()).to_le_bytes());
+    payload.extend(code.code().to_le_bytes());
+    push_domain(&mut payload, domain);
+    if code.ev_type().is_abs() {
+        let abs_info = abs_info.copied().unwrap_or(AbsInfo {
+            min_value: 0, max_value: 0,
+            meta: AbsMeta { fuzz: 0, flat: 0, resolution: 0, value: 0 },
+        });
+        for value in [abs_info.min_value, abs_info.max_value, abs_info.meta.fuzz, abs_info.meta.flat, abs_info.meta.resolution, abs_info.meta.value] {
+            payload.extend(value.to_le_bytes());
+        }
+    }
+    encode_record(CAPABILITY_TAG, payload)
+}
+
+fn encode_record(tag: u8, payload: Vec<u8>) -> Vec<u8> {
+    let mut record = Vec::with_capacity(RECORD_PREFIX_SIZE + payload.len());
+    record.push(tag);
+    record.extend((payload.len() as u32).to_le_bytes());
+    record.extend(payload);
+    record
+}
+
+fn push_domain(payload: &mut Vec<u8>, domain: Domain) {
+    let name = domain::try_reverse_resolve(domain).unwrap_or_default();
+    // Domain names are never this long in practice, but the format must stay intact if they are.
+    let name = match name.len() > u16::MAX as usize {
+        true => "",
+        false => name.as_str(),
+    };
+    payload.extend((name.len() as u16).to_le_bytes());
+    payload.extend(name.as_bytes());
+}
+
+/// Returns whether the content starts with a header, which means that it is probably in this format.
+pub fn has_header(content: &[u8]) -> bool {
+    content.first() == Some(&HEADER_TAG) && content.get(RECORD_PREFIX_SIZE .. RECORD_PREFIX_SIZE + MAGIC.len()) == Some(MAGIC)
+}
+
+/// Decodes all records of a stream, except for the headers.
+pub fn decode(content: &[u8]) -> Result<Vec<Record>, SystemError> {
+    if ! has_header(content) {
+        return Err(SystemError::new("The data does not start with the header of an evsieve event stream."));
+    }
+
+    let mut records = Vec::new();
+    let mut remaining = content;
+    while ! remaining.is_empty() {
+        let (tag, payload, rest) = split_record(remaining)?;
+        remaining = rest;
+        let mut reader = PayloadReader { payload };
+        match tag {
+            HEADER_TAG => {
+                if reader.take(MAGIC.len())? != MAGIC {
+                    return Err(SystemError::new("Encountered a header with an invalid magic number."));
+                }
+                let version = reader.read_u16()?;
+                if version > VERSION {
+                    return Err(SystemError::new(format!(
+                        "The events were written in version {} of the format, but this version of evsieve only supports up to version {}. Please upgrade evsieve.",
+                        version, VERSION
+                    )));
+                }
+            },
+            EVENT_TAG => {
+                let time = Duration::from_micros(reader.read_u64()?);
+                let code = reader.read_code()?;
+                let value = reader.read_i32()?;
+                let previous_value = reader.read_i32()?;
+                let domain = reader.read_domain()?;
+                records.push(Record::Event { time, code, value, previous_value, domain });
+            },
+            CAPABILITY_TAG => {
+                let code = reader.read_code()?;
+                let domain = reader.read_domain()?;
+                let abs_info = match code.ev_type().is_abs() {
+                    true => Some(AbsInfo {
+                        min_value: reader.read_i32()?,
+                        max_value: reader.read_i32()?,
+                        meta: AbsMeta {
+                            fuzz: reader.read_i32()?,
+                            flat: reader.read_i32()?,
+                            resolution: reader.read_i32()?,
+                            value: reader.read_i32()?,
+                        },
+                    }),
+                    false => None,
+                };
+                records.push(Record::Capability { code, domain, abs_info });
+            },
+            // Written by a newer version of evsieve.
+            _ => continue,
+        }
+    }
+    Ok(records)
+}
+
+/// Returns the tag and payload of the first record, and everything after it.
+fn split_record(content: &[u8]) -> Result<(u8, &[u8], &[u8]), SystemError> {
+    let truncated = || SystemError::new("The event stream ends in the middle of a record.");
+    if content.len() < RECORD_PREFIX_SIZE {
+        return Err(truncated());
+    }
+    let tag = content[0];
+    let length = u32::from_le_bytes(content[1 .. RECORD_PREFIX_SIZE].try_into().unwrap()) as usize;
+    let end = RECORD_PREFIX_SIZE.checked_add(length).filter(|&end| end <= content.len()).ok_or_else(truncated)?;
+    Ok((tag, &content[RECORD_PREFIX_SIZE .. end], &content[end ..]))
+}
+
+struct PayloadReader<'a> {
+    payload: &'a [u8],
+}
+
+impl<'a> PayloadReader<'a> {
+    fn take(&mut self, num_bytes: usize) -> Result<&'a [u8], SystemError> {
+        if self.payload.len() < num_bytes {
+            return Err(SystemError::new("Encountered a record that is too short."));
+        }
+        let (taken, rest) = self.payload.split_at(num_bytes);
+        self.payload = rest;
+        Ok(taken)
+    }
+
+    fn read_u16(&mut self) -> Result<u16, SystemError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    fn read_i32(&mut self) -> Result<i32, SystemError> {
+        Ok(i32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    fn read_u64(&mut self) -> Result<u64, SystemError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    fn read_code(&mut self) -> Result<EventCode, SystemError> {
+        let ev_type = self.read_u16()?;
+        let code = self.read_u16()?;
+        // Also rejects the types that only exist within evsieve.
+        crate::ecodes::checked_event_code(ev_type, code)
+            .map_err(|error| SystemError::new(format!("Encountered an invalid event. {}", error)))
+    }
+
+    fn read_domain(&mut self) -> Result<Option<String>, SystemError> {
+        let length = self.read_u16()? as usize;
+        let name = std::str::from_utf8(self.take(length)?)
+            .map_err(|_| SystemError::new("Encountered a domain name that is not valid UTF-8."))?;
+        Ok(match name.is_empty() {
+            true => None,
+            false => Some(name.to_owned()),
+        })
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::Namespace;
+
+    let key_a = crate::ecodes::event_code("key", "a").unwrap();
+    let abs_x = crate::ecodes::event_code("abs", "x").unwrap();
+    let named = domain::resolve("serialization-test").unwrap();
+    let unnamed = domain::get_unique_domain();
+    let start = Instant::now();
+    let abs_info = AbsInfo { min_value: -10, max_value: 10, meta: AbsMeta { fuzz: 1, flat: 2, resolution: 3, value: 4 } };
+
+    let content = [
+        encode_header(),
+        encode_capability(key_a, named, None),
+        encode_capability(abs_x, unnamed, Some(&abs_info)),
+        encode_event(start, &Event::new(key_a, 1, 0, named, Namespace::User)),
+        // Records of unknown kinds are skipped.
+        encode_record(200, vec![1, 2, 3]),
+        encode_header(),
+        encode_event(start + Duration::from_millis(5), &Event::new(abs_x, -3, 4, unnamed, Namespace::User)),
+    ].concat();
+    let records = decode(&content).unwrap();
+    assert_eq!(records.len(), 4);
+    assert_eq!(records[0], Record::Capability { code: key_a, domain: Some("serialization-test".to_owned()), abs_info: None });
+    assert_eq!(records[1], Record::Capability { code: abs_x, domain: None, abs_info: Some(abs_info) });
+    let time = match records[2] {
+        Record::Event { time, code, value: 1, previous_value: 0, ref domain } if code == key_a && domain.as_deref() == Some("serialization-test") => time,
+        _ => panic!(),
+    };
+    match records[3] {
+        Record::Event { time: later_time, code, value: -3, previous_value: 4, domain: None } if code == abs_x => {
+            assert_eq!(later_time.as_micros() - time.as_micros(), 5_000);
+        },
+        _ => panic!(),
+    }
+
+    // Extra bytes at the end of a payload are ignored.
+    let mut extended = encode_capability(key_a, unnamed, None);
+    extended[1] += 2;
+    extended.extend([7, 7]);
+    let records = decode(&[encode_header(), extended].concat()).unwrap();
+    assert_eq!(records, vec![Record::Capability { code: key_a, domain: None, abs_info: None }]);
+
+    assert!(decode(&content[.. content.len() - 1]).is_err());
+    assert!(decode(&content[RECORD_PREFIX_SIZE ..]).is_err());
+    let mut future_header = encode_header();
+    future_header[RECORD_PREFIX_SIZE + MAGIC.len()] = 0xff;
+    assert!(decode(&future_header).is_err());
+
+    // Events and capabilities with types or codes that do not exist are rejected.
+    let code_offset = RECORD_PREFIX_SIZE + 8 + 2;
+    let mut invalid_event = encode_event(start, &Event::new(key_a, 1, 0, named, Namespace::User));
+    invalid_event[code_offset .. code_offset + 2].copy_from_slice(&0x3ffu16.to_le_bytes());
+    assert!(decode(&[encode_header(), invalid_event].concat()).is_err());
+    let mut invalid_capability = encode_capability(key_a, named, None);
+    invalid_capability[RECORD_PREFIX_SIZE .. RECORD_PREFIX_SIZE + 2].copy_from_slice(&(crate::ecodes::EV_HOOK).to_le_bytes());
+    assert!(decode(&[encode_header(), invalid_capability].concat()).is_err());
+}
@@ -0,0 +1,70 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! The timeout of an epoll only has millisecond resolution, which is too coarse for arguments like --delay
+//! with short periods. Instead, the main loop arms a timerfd for the next scheduled wakeup, which can
+//! expire at any nanosecond.
+//!
+//! The timer runs on CLOCK_BOOTTIME so it also expires if its deadline passed while the system was
+//! suspended, which gives wakeups that count the time spent suspended a chance to happen right after
+//! resuming. Wakeups that do not count it simply get the timer armed again for the remaining time.
+
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::error::{Context, SystemError};
+use crate::io::fd::{HasFixedFd, OwnedFd};
+use crate::time::{self, Instant};
+
+pub struct WakeupTimer {
+    fd: OwnedFd,
+    /// The moment at which the timer is currently set to expire, or None if it is disarmed.
+    deadline: Option<Instant>,
+}
+
+impl WakeupTimer {
+    pub fn new() -> Result<WakeupTimer, SystemError> {
+        let fd = unsafe {
+            OwnedFd::from_syscall(libc::timerfd_create(libc::CLOCK_BOOTTIME, libc::TFD_NONBLOCK | libc::TFD_CLOEXEC))
+                .with_context("While creating a timer:")?
+        };
+        Ok(WakeupTimer { fd, deadline: None })
+    }
+
+    /// Makes the timer expire at the given moment of the monotonic clock, or disarms it if None. Avoids a
+    /// syscall if the timer was already set to that moment, which is the common case if nothing new got
+    /// scheduled.
+    pub fn set(&mut self, deadline: Option<Instant>) -> Result<(), SystemError> {
+        if deadline == self.deadline {
+            return Ok(());
+        }
+        let expiration: libc::timespec = match deadline {
+            Some(deadline) => (deadline + time::time_suspended()).into(),
+            None => libc::timespec { tv_sec: 0, tv_nsec: 0 },
+        };
+        let spec = libc::itimerspec {
+            it_interval: libc::timespec { tv_sec: 0, tv_nsec: 0 },
+            it_value: expiration,
+        };
+        let res = unsafe {
+            libc::timerfd_settime(self.fd.as_raw_fd(), libc::TFD_TIMER_ABSTIME, &spec, std::ptr::null_mut())
+        };
+        if res < 0 {
+            return Err(SystemError::os_with_context("While setting a timer:"));
+        }
+        self.deadline = deadline;
+        Ok(())
+    }
+
+    /// Must be called when the timer has expired, so the epoll stops reporting it as ready.
+    pub fn acknowledge(&mut self) {
+        let mut expirations: u64 = 0;
+        unsafe { libc::read(self.fd.as_raw_fd(), &mut expirations as *mut u64 as *mut libc::c_void, 8) };
+        self.deadline = None;
+    }
+}
+
+impl AsRawFd for WakeupTimer {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for WakeupTimer {}
@@ -0,0 +1,65 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Keeps track of which virtual terminal (VT) is currently active, as required by --on-vt.
+//!
+//! The active VT is exposed by the kernel through a sysfs attribute. Like most sysfs attributes, it
+//! does not generate inotify events when it changes. Instead, the kernel notifies those who poll it
+//! with POLLPRI, after which the attribute must be read again from the start.
+
+use std::fs::File;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::error::{Context, SystemError};
+use crate::io::fd::HasFixedFd;
+
+const ACTIVE_VT_PATH: &str = "/sys/class/tty/tty0/active";
+
+/// The number of a VT, e.g. 2 for tty2.
+pub type VtNumber = u32;
+
+pub struct VtMonitor {
+    file: File,
+}
+
+impl VtMonitor {
+    pub fn open() -> Result<VtMonitor, SystemError> {
+        let file = File::open(ACTIVE_VT_PATH)
+            .map_err(SystemError::from)
+            .with_context(format!("While opening {}:", ACTIVE_VT_PATH))?;
+        Ok(VtMonitor { file })
+    }
+
+    /// Returns the number of the currently active VT. Reading also acknowledges the notification that
+    /// made the epoll report this file as ready.
+    pub fn read_active(&mut self) -> Result<VtNumber, SystemError> {
+        let mut buffer = [0u8; 32];
+        let res = unsafe { libc::pread(self.file.as_raw_fd(), buffer.as_mut_ptr() as *mut libc::c_void, buffer.len(), 0) };
+        if res < 0 {
+            return Err(SystemError::os_with_context(format!("While reading {}:", ACTIVE_VT_PATH)));
+        }
+        let content = String::from_utf8_lossy(&buffer[.. res as usize]);
+        parse_active_vt(&content).ok_or_else(|| SystemError::new(format!(
+            "Cannot interpret \"{}\" as the name of a virtual terminal.", content.trim()
+        )))
+    }
+}
+
+/// Interprets the content of the active attribute, like "tty2\n".
+fn parse_active_vt(content: &str) -> Option<VtNumber> {
+    content.trim().strip_prefix("tty")?.parse().ok()
+}
+
+impl AsRawFd for VtMonitor {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+unsafe impl HasFixedFd for VtMonitor {}
+
+#[test]
+fn unittest() {
+    assert_eq!(parse_active_vt("tty2\n"), Some(2));
+    assert_eq!(parse_active_vt("tty12"), Some(12));
+    assert_eq!(parse_active_vt("ttyS0\n"), None);
+    assert_eq!(parse_active_vt(""), None);
+}
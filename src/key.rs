@@ -1,8 +1,10 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::affine::AffineFactor;
+use crate::crossing::{self, Crossing};
+use crate::annotation::Annotations;
 use crate::domain;
-use crate::domain::Domain;
+use crate::domain::{Domain, DomainExpression};
 use crate::event::{Event, EventType, EventCode, Channel, Namespace, VirtualEventType};
 use crate::utils;
 use crate::error::ArgumentError;
@@ -10,6 +12,7 @@ use crate::capability::{Certainty, Capability};
 use crate::range::{Interval, Set};
 use crate::ecodes;
 use crate::error::Context;
+use crate::data::hid_usage::UsageNames;
 
 #[derive(Clone, Debug)]
 pub struct Key {
@@ -112,6 +115,24 @@ impl Key {
         self.properties.push(KeyProperty::Value(value));
     }
 
+    /// If this key has a `Crossing` property, returns it and removes it from its own properties.
+    /// This allows a crossing to be tracked statefully by whoever uses this key.
+    pub fn pop_crossing(&mut self) -> Option<Crossing> {
+        let mut result: Option<Crossing> = None;
+        self.properties.retain(
+            |&property| {
+                match property {
+                    KeyProperty::Crossing(crossing) => {
+                        result = Some(crossing);
+                        false
+                    },
+                    _ => true,
+                }
+            }
+        );
+        result
+    }
+
     fn add_property(&mut self, property: KeyProperty) {
         self.properties.push(property);
     }
@@ -123,12 +144,16 @@ impl Key {
             match property {
                 KeyProperty::Code(code) => return Some(code.ev_type()),
                 KeyProperty::Type(ev_type) => return Some(*ev_type),
+                KeyProperty::CodeSet(set) => return Some(set.ev_type),
                 KeyProperty::VirtualType(v_type) => return Some(v_type.ev_type()),
                 KeyProperty::Domain(_)
+                | KeyProperty::DomainExpression(_)
                 | KeyProperty::Namespace(_)
                 | KeyProperty::Value(_)
                 | KeyProperty::PreviousValue(_)
+                | KeyProperty::Crossing(_)
                 | KeyProperty::AffineFactor(_)
+                | KeyProperty::Annotations(_)
                 => (),
             }
         }
@@ -143,36 +168,42 @@ impl Key {
                 KeyProperty::Code(code) => return Some(*code),
 
                 KeyProperty::Type(_)
+                | KeyProperty::CodeSet(_)
                 | KeyProperty::VirtualType(_)
                 | KeyProperty::Domain(_)
+                | KeyProperty::DomainExpression(_)
                 | KeyProperty::Namespace(_)
                 | KeyProperty::Value(_)
                 | KeyProperty::PreviousValue(_)
+                | KeyProperty::Crossing(_)
                 | KeyProperty::AffineFactor(_)
+                | KeyProperty::Annotations(_)
                     => (),
             }
         }
         None
     }
 
-    /// Returns Some(EventType) if this Key will only ever accept events of a certain domain.
-    /// If it may accept different types of domains, returns None.
-    pub fn requires_domain(&self) -> Option<Domain> {
+    /// Returns true if this Key only accepts events of certain domains, e.g. "key:a@foo".
+    pub fn specifies_domain(&self) -> bool {
         for property in &self.properties {
             match property {
-                KeyProperty::Domain(domain) => return Some(*domain),
+                KeyProperty::Domain(_) | KeyProperty::DomainExpression(_) => return true,
 
                 KeyProperty::Code(_)
+                | KeyProperty::CodeSet(_)
                 | KeyProperty::Namespace(_)
                 | KeyProperty::Value(_)
                 | KeyProperty::PreviousValue(_)
                 | KeyProperty::Type(_)
                 | KeyProperty::VirtualType(_)
+                | KeyProperty::Crossing(_)
                 | KeyProperty::AffineFactor(_)
+                | KeyProperty::Annotations(_)
                     => ()
             }
         }
-        None
+        false
     }
 
     /// Removes the value/range requirement from this key and returns it separately if it existed.
@@ -186,11 +217,15 @@ impl Key {
                 },
                 KeyProperty::Type(_)
                 | KeyProperty::Code(_)
+                | KeyProperty::CodeSet(_)
                 | KeyProperty::VirtualType(_)
                 | KeyProperty::Domain(_)
+                | KeyProperty::DomainExpression(_)
                 | KeyProperty::Namespace(_)
                 | KeyProperty::PreviousValue(_)
+                | KeyProperty::Crossing(_)
                 | KeyProperty::AffineFactor(_)
+                | KeyProperty::Annotations(_)
                 => true,
             }
         );
@@ -213,7 +248,7 @@ impl Key {
                     (KeyProperty::Code(left), KeyProperty::Code(right))
                         => left == right,
                     (KeyProperty::Domain(left), KeyProperty::Domain(right))
-                        => left == right,
+                        => domain::may_overlap(*left, *right),
                     (KeyProperty::Namespace(left), KeyProperty::Namespace(right))
                         => left == right,
 
@@ -223,18 +258,28 @@ impl Key {
                     | (KeyProperty::Code(code), KeyProperty::VirtualType(v_type))
                         => *v_type == code.virtual_ev_type(),
 
+                    (KeyProperty::CodeSet(set), KeyProperty::Code(code))
+                    | (KeyProperty::Code(code), KeyProperty::CodeSet(set))
+                        => set.contains(*code),
+                    (KeyProperty::CodeSet(left), KeyProperty::CodeSet(right))
+                        => left.intersects_with(right),
+
                     (KeyProperty::Value(left), KeyProperty::Value(right))
                     | (KeyProperty::PreviousValue(left), KeyProperty::PreviousValue(right))
                         => left.intersects_with(right),
                     
                     (KeyProperty::Code(_), _)
+                    | (KeyProperty::CodeSet(_), _)
                     | (KeyProperty::Domain(_), _)
+                    | (KeyProperty::DomainExpression(_), _)
                     | (KeyProperty::Namespace(_), _)
                     | (KeyProperty::Type(_), _)
                     | (KeyProperty::VirtualType(_), _)
                     | (KeyProperty::Value(_), _)
                     | (KeyProperty::PreviousValue(_), _)
+                    | (KeyProperty::Crossing(_), _)
                     | (KeyProperty::AffineFactor(_), _)
+                    | (KeyProperty::Annotations(_), _)
                         => true,
                 };
                 if ! these_properties_may_intersect {
@@ -254,13 +299,22 @@ enum KeyProperty {
     Namespace(Namespace),
     Value(Interval),
     PreviousValue(Interval),
+    /// Matches events that make an axis cross some threshold, e.g. "abs:y:crosses=0:+".
+    /// Only valid for filter keys.
+    Crossing(Crossing),
     /// Only valid for filter keys.
     Type(EventType),
+    /// Matches any of a set of event codes, e.g. "btn:trigger_happy*". Only valid for filter keys.
+    CodeSet(CodeSet),
     /// Only valid for filter keys.
     VirtualType(VirtualEventType),
+    /// Matches events whose domain has certain names or tags, e.g. "@kbd&left-half". Only valid for filter keys.
+    DomainExpression(DomainExpression),
     /// Applies an affine transformation on the input event.
     /// Only valid for mask keys.
     AffineFactor(AffineFactor),
+    /// Matches events that carry all of these annotations, or attaches them to events when used as mask.
+    Annotations(Annotations),
 }
 
 impl KeyProperty {
@@ -268,12 +322,16 @@ impl KeyProperty {
     pub fn matches(&self, event: &Event) -> bool {
         match *self {
             KeyProperty::Code(value) => event.code == value,
-            KeyProperty::Domain(value) => event.domain == value,
+            KeyProperty::Domain(value) => domain::matches(event.domain, value),
+            KeyProperty::DomainExpression(expression) => expression.matches(event.domain),
             KeyProperty::Type(value) => event.code.ev_type() == value,
+            KeyProperty::CodeSet(set) => set.contains(event.code),
             KeyProperty::VirtualType(value) => event.code.virtual_ev_type() == value,
             KeyProperty::Namespace(value) => event.namespace == value,
             KeyProperty::Value(range) => range.contains(event.value),
             KeyProperty::PreviousValue(range) => range.contains(event.previous_value),
+            KeyProperty::Crossing(crossing) => crossing.matches(event),
+            KeyProperty::Annotations(annotations) => event.annotations.contains(annotations),
             KeyProperty::AffineFactor(_) => {
                 // Similarly to `KeyProperty::merge`, benchmarks show that the mere threat of panicking
                 // during this function can significantly reduce performance, therefore this assertion
@@ -291,13 +349,17 @@ impl KeyProperty {
         let (code, domain) = channel;
         match *self {
             KeyProperty::Code(value) => code == value,
-            KeyProperty::Domain(value) => domain == value,
+            KeyProperty::Domain(value) => domain::matches(domain, value),
+            KeyProperty::DomainExpression(expression) => expression.matches(domain),
             KeyProperty::Type(value) => value == code.ev_type(),
+            KeyProperty::CodeSet(set) => set.contains(code),
             KeyProperty::VirtualType(value) => value.ev_type() == code.ev_type(),
             KeyProperty::Namespace(_)
             | KeyProperty::Value(_)
             | KeyProperty::PreviousValue(_)
+            | KeyProperty::Crossing(_)
             | KeyProperty::AffineFactor(_)
+            | KeyProperty::Annotations(_)
                 => true,
         }
     }
@@ -313,7 +375,9 @@ impl KeyProperty {
             KeyProperty::AffineFactor(factor) => {
                 event = factor.merge(event);
             },
-            KeyProperty::Type(_) | KeyProperty::VirtualType(_) => {
+            KeyProperty::Annotations(annotations) => event.annotations = event.annotations.union(annotations),
+            KeyProperty::Type(_) | KeyProperty::CodeSet(_) | KeyProperty::VirtualType(_) | KeyProperty::Crossing(_)
+            | KeyProperty::DomainExpression(_) => {
                 if cfg!(debug_assertions) {
                     panic!("Cannot change the event type of an event. Panicked during event mapping.");
                 } else {
@@ -341,14 +405,17 @@ impl KeyProperty {
 
         match *self {
             KeyProperty::Code(code) => all_or_nothing(cap.code == code),
-            KeyProperty::Domain(domain) => all_or_nothing(cap.domain == domain),
+            KeyProperty::Domain(domain) => all_or_nothing(domain::matches(cap.domain, domain)),
+            KeyProperty::DomainExpression(expression) => all_or_nothing(expression.matches(cap.domain)),
             KeyProperty::Type(ev_type) => all_or_nothing(cap.code.ev_type() == ev_type),
+            KeyProperty::CodeSet(set) => all_or_nothing(set.contains(cap.code)),
             KeyProperty::VirtualType(virtual_type) => all_or_nothing(cap.code.virtual_ev_type() == virtual_type),
             KeyProperty::Namespace(namespace) => all_or_nothing(cap.namespace == namespace),
             KeyProperty::Value(range) => {
                 (Certainty::Always, Set::from(range).intersect(&cap.values))
             },
-            KeyProperty::PreviousValue(_range) => {
+            // Capabilities do not track annotations, so any capability may carry them.
+            KeyProperty::PreviousValue(_) | KeyProperty::Crossing(_) | KeyProperty::Annotations(_) => {
                 (Certainty::Maybe, cap.values.clone())
             },
             KeyProperty::AffineFactor(_) => {
@@ -365,9 +432,10 @@ impl KeyProperty {
             KeyProperty::Value(range) => cap.values = cap.values.map(
                 |cap_range| Some(range.bound_range(&cap_range))
             ),
-            KeyProperty::PreviousValue(_range) => {},
+            KeyProperty::PreviousValue(_) | KeyProperty::Annotations(_) => {},
             KeyProperty::AffineFactor(factor) => cap = factor.merge_cap(cap),
-            KeyProperty::Type(_) | KeyProperty::VirtualType(_) => {
+            KeyProperty::Type(_) | KeyProperty::CodeSet(_) | KeyProperty::VirtualType(_) | KeyProperty::Crossing(_)
+            | KeyProperty::DomainExpression(_) => {
                 if cfg!(debug_assertions) {
                     panic!("Cannot change the event type of an event. Panicked during capability propagation.");
                 } else {
@@ -379,6 +447,40 @@ impl KeyProperty {
     }
 }
 
+/// The highest event code of any event type is KEY_MAX (0x2ff), so this many bits suffice for every type.
+const CODE_SET_WORDS: usize = 12;
+
+/// A set of event codes of the same event type, stored as a bitset so it can be cheaply copied.
+#[derive(Clone, Copy, Debug)]
+struct CodeSet {
+    ev_type: EventType,
+    bits: [u64; CODE_SET_WORDS],
+}
+
+impl CodeSet {
+    fn new(ev_type: EventType) -> CodeSet {
+        CodeSet { ev_type, bits: [0; CODE_SET_WORDS] }
+    }
+
+    fn insert(&mut self, code: EventCode) {
+        debug_assert!(code.ev_type() == self.ev_type);
+        let index = code.code() as usize;
+        self.bits[index / 64] |= 1 << (index % 64);
+    }
+
+    fn contains(&self, code: EventCode) -> bool {
+        let index = code.code() as usize;
+        code.ev_type() == self.ev_type
+            && index < CODE_SET_WORDS * 64
+            && self.bits[index / 64] & (1 << (index % 64)) != 0
+    }
+
+    fn intersects_with(&self, other: &CodeSet) -> bool {
+        self.ev_type == other.ev_type
+            && self.bits.iter().zip(other.bits.iter()).any(|(left, right)| left & right != 0)
+    }
+}
+
 /// Represents the options for how a key can be parsed in different contexts.
 pub struct KeyParser<'a> {
     /// Which values keys shall be assumed to have if not specified. E.g. if the default value is "1", then
@@ -387,6 +489,9 @@ pub struct KeyParser<'a> {
     /// Whether event values like the :1 in "key:a:1" are allowed.
     pub allow_values: bool,
     pub allow_transitions: bool,
+    /// Whether values like "crosses=0:+" are allowed. Only ever set this to true for filter keys
+    /// of arguments that know how to track crossings statefully.
+    pub allow_crossings: bool,
     pub allow_ranges: bool,
     /// Whether you are allowed to specify a specific domain, like "@foo" or "key:a@bar".
     pub allow_domains: bool,
@@ -396,6 +501,9 @@ pub struct KeyParser<'a> {
     /// Whether keys with an event value that depends on which event is getting masked, are allowed.
     /// Only ever set this to true for mask keys.
     pub allow_relative_values: bool,
+    /// Whether annotations like the "^macro" in "key:a^macro" are allowed. Keys with annotations are not pure,
+    /// so only set this to true for keys that are matched against or merged into actual events.
+    pub allow_annotations: bool,
     /// Is Some, then it only allows keys that require this type or have no type/code requirements.
     /// Forbids keys that that require a type/code outside this range.
     pub type_whitelist: Option<Vec<EventType>>,
@@ -413,8 +521,10 @@ impl<'a> KeyParser<'a> {
             allow_ranges: true,
             allow_domains: true,
             allow_transitions: true,
+            allow_crossings: false,
             allow_types: true,
             allow_relative_values: false,
+            allow_annotations: true,
             type_whitelist: None,
             namespace: Namespace::User,
         }
@@ -439,10 +549,12 @@ impl<'a> KeyParser<'a> {
             default_value: self.default_value,
             allow_values: self.allow_values && other.allow_values,
             allow_transitions: self.allow_transitions && other.allow_transitions,
+            allow_crossings: self.allow_crossings && other.allow_crossings,
             allow_domains: self.allow_domains && other.allow_domains,
             allow_ranges: self.allow_ranges && other.allow_ranges,
             allow_types: self.allow_types && other.allow_types,
             allow_relative_values: self.allow_relative_values && other.allow_relative_values,
+            allow_annotations: self.allow_annotations && other.allow_annotations,
             type_whitelist: merged_whitelist,
             namespace: self.namespace,
         }
@@ -457,8 +569,10 @@ impl<'a> KeyParser<'a> {
             allow_ranges: true,
             allow_domains: true,
             allow_transitions: false,
+            allow_crossings: false,
             allow_types: false,
             allow_relative_values: true,
+            allow_annotations: true,
             type_whitelist: None,
             namespace: Namespace::User,
         }
@@ -473,8 +587,10 @@ impl<'a> KeyParser<'a> {
             allow_ranges: false,
             allow_domains: true,
             allow_transitions: false,
+            allow_crossings: false,
             allow_types: true,
             allow_relative_values: false,
+            allow_annotations: false,
             type_whitelist: None,
             namespace: Namespace::User,
         }
@@ -518,8 +634,10 @@ pub fn resembles_key(key_str: &str) -> bool {
             allow_ranges: true,
             allow_domains: true,
             allow_transitions: true,
+            allow_crossings: true,
             allow_types: true,
             allow_relative_values: true,
+            allow_annotations: true,
             type_whitelist: None,
             namespace: Namespace::User,
         }.parse(key_str).is_ok()
@@ -528,11 +646,15 @@ pub fn resembles_key(key_str: &str) -> bool {
         || before_equals_part.contains(':')
         || before_equals_part.contains('@')
         || before_equals_part.contains('%')
+        || before_equals_part.contains('^')
     }
 }
 
-/// Interprets a key that optionally has a domain attached, like "key:a@keyboard".
+/// Interprets a key that optionally has a domain and annotations attached, like "key:a@keyboard^macro".
 fn interpret_key_with_domain(key_str: &str, parser: &KeyParser) -> Result<Key, ArgumentError> {
+    let (key_str, annotations_str) = utils::split_once(key_str, "^");
+    let resolved_key_str = resolve_hid_usage(key_str)?;
+    let key_str = resolved_key_str.as_deref().unwrap_or(key_str);
     let parts = key_str_to_parts(key_str)?;
     let mut key = interpret_key(parts, parser)?;
 
@@ -540,13 +662,83 @@ fn interpret_key_with_domain(key_str: &str, parser: &KeyParser) -> Result<Key, A
         if ! parser.allow_domains {
             return Err(ArgumentError::new("No domains are allowed for keys in this position."))
         }
-        let domain = domain::resolve(domain_str)?;
-        key.properties.push(KeyProperty::Domain(domain));
+        if DomainExpression::is_expression_str(domain_str) {
+            if ! parser.allow_types {
+                return Err(ArgumentError::new(format!(
+                    "The domain expression \"@{}\" can only be used to filter events, not to decide which domain events should get.", domain_str
+                )));
+            }
+            key.properties.push(KeyProperty::DomainExpression(DomainExpression::parse(domain_str)?));
+        } else {
+            let domain = domain::resolve(domain_str)?;
+            key.properties.push(KeyProperty::Domain(domain));
+        }
+    }
+
+    if let Some(annotations_str) = annotations_str {
+        if ! parser.allow_annotations {
+            return Err(ArgumentError::new("No annotations are allowed for keys in this position."));
+        }
+        key.properties.push(KeyProperty::Annotations(Annotations::parse(annotations_str)?));
     }
 
     Ok(key)
 }
 
+/// If the key has the form "usage:PAGE:ID:VALUE@DOMAIN", returns the equivalent key that uses the name of the
+/// event code that the HID usage maps to, e.g. "usage:0c:cd:1" becomes "key:playpause:1". Returns None if the
+/// key does not start with "usage:".
+fn resolve_hid_usage(key_str: &str) -> Result<Option<String>, ArgumentError> {
+    let (key_str, domain) = utils::split_once(key_str, "@");
+    let mut parts_iter = key_str.splitn(4, ':');
+    if parts_iter.next() != Some("usage") {
+        return Ok(None);
+    }
+    let (page_str, id_str) = match (parts_iter.next(), parts_iter.next()) {
+        (Some(page_str), Some(id_str)) => (page_str, id_str),
+        _ => return Err(ArgumentError::new(format!(
+            "Cannot interpret \"{}\": HID usages must be specified as usage:PAGE:ID, where PAGE and ID are hexadecimal numbers, e.g. usage:0c:cd.", key_str
+        ))),
+    };
+    let value = parts_iter.next();
+
+    fn parse_hex(number_str: &str, what: &str) -> Result<u16, ArgumentError> {
+        let digits = number_str.strip_prefix("0x").unwrap_or(number_str);
+        u16::from_str_radix(digits, 16).map_err(|_| ArgumentError::new(format!(
+            "Cannot interpret \"{}\" as a HID usage {}: expected a hexadecimal number between 0 and ffff.", number_str, what
+        )))
+    }
+    let page = parse_hex(page_str, "page")?;
+    let id = parse_hex(id_str, "ID")?;
+
+    let scancode = ((page as u32) << 16 | id as u32) as crate::scancodes::Scancode;
+    let code = match crate::scancodes::to_event_code(scancode) {
+        Some(code) => code,
+        None => {
+            let usage_name = match crate::data::hid_usage::HID_PAGES.get_usage_from_scancode(scancode).map(|info| info.names) {
+                Some(UsageNames::Known { page_name, usage_name }) => format!(" ({}/{})", page_name, usage_name),
+                Some(UsageNames::PageKnown { page_name }) => format!(" ({})", page_name),
+                Some(UsageNames::Unknown) | None => String::new(),
+            };
+            return Err(ArgumentError::new(format!(
+                "The HID usage {:#04x}:{:#05x}{} does not correspond to any event code known to evsieve.",
+                page, id, usage_name
+            )));
+        },
+    };
+
+    let mut result = ecodes::event_name(code).into_owned();
+    if let Some(value) = value {
+        result.push(':');
+        result.push_str(value);
+    }
+    if let Some(domain) = domain {
+        result.push('@');
+        result.push_str(domain);
+    }
+    Ok(Some(result))
+}
+
 #[derive(Clone, Copy)]
 struct KeyParts<'a> {
     /// The full string of which these parts were lexed.
@@ -565,7 +757,7 @@ struct KeyParts<'a> {
 fn key_str_to_parts(key_str: &str) -> Result<KeyParts, ArgumentError> {
     let (key_str, domain) = utils::split_once(key_str, "@");
 
-    let mut parts_iter = key_str.split(':').peekable();
+    let mut parts_iter = key_str.splitn(3, ':');
 
     // Make sure that we never store the empty string in the type, code, or value options.
     fn treat_empty_as_none(option: Option<&str>) -> Option<&str> {
@@ -573,7 +765,17 @@ fn key_str_to_parts(key_str: &str) -> Result<KeyParts, ArgumentError> {
     }
     let ev_type = treat_empty_as_none(parts_iter.next());
     let code = treat_empty_as_none(parts_iter.next());
-    let value = treat_empty_as_none(parts_iter.next());
+
+    // Crossings like "abs:y:crosses=0:+" contain a colon within their value.
+    let (value, superfluous_part) = match parts_iter.next() {
+        Some(value_str) if crossing::is_crossing_str(value_str) => (Some(value_str), None),
+        Some(value_str) => {
+            let (value, superfluous_part) = utils::split_once(value_str, ":");
+            (Some(value), superfluous_part)
+        },
+        None => (None, None),
+    };
+    let value = treat_empty_as_none(value);
 
     // This forbids keys like "key:", "key:a:" or "key::".
     if key_str.ends_with(':') {
@@ -586,8 +788,7 @@ fn key_str_to_parts(key_str: &str) -> Result<KeyParts, ArgumentError> {
     }
 
     // Make sure there is nothing after the last colon, such as in key:a:1:2.
-    if parts_iter.peek().is_some() {
-        let superfluous_part = parts_iter.collect::<Vec<_>>().join(":");
+    if let Some(superfluous_part) = superfluous_part {
         return Err(ArgumentError::new(format!(
             "Too many colons encountered in the key \"{}\". There is no way to interpret the \":{}\" part.", key_str, superfluous_part
         )));
@@ -663,6 +864,25 @@ fn interpret_key(parts: KeyParts, parser: &KeyParser) -> Result<Key, ArgumentErr
                 };
                 key.add_property(property);
             },
+            Some(event_code_name) if event_code_name.contains(['*', '?']) => {
+                if ! parser.allow_types {
+                    return Err(ArgumentError::new(format!(
+                        "Wildcards are only allowed in keys that filter events. Cannot use the key \"{}\" in this position.", parts.key_str
+                    )));
+                }
+
+                let mut set = CodeSet::new(event_type);
+                let matching_codes = ecodes::event_codes_matching(event_type_name, event_code_name);
+                if matching_codes.is_empty() {
+                    return Err(ArgumentError::new(format!(
+                        "No event codes match the pattern \"{}:{}\".", event_type_name, event_code_name
+                    )));
+                }
+                for code in matching_codes {
+                    set.insert(code);
+                }
+                key.add_property(KeyProperty::CodeSet(set));
+            },
             Some(event_code_name) => {
                 let event_code = ecodes::event_code(event_type_name, event_code_name)?;
                 key.add_property(KeyProperty::Code(event_code));
@@ -692,6 +912,21 @@ fn interpret_key(parts: KeyParts, parser: &KeyParser) -> Result<Key, ArgumentErr
         },
     };
 
+    // Check if it is a crossing.
+    if crossing::is_crossing_str(event_value_str) {
+        if ! parser.allow_crossings {
+            return Err(ArgumentError::new(format!(
+                "It is not possible to specify crossings for the key {}.", parts.key_str,
+            )));
+        }
+        let crossing = crossing::parse_crossing(
+            event_value_str,
+            |band_str| interpret_event_value(band_str, parser),
+        )?;
+        key.add_property(KeyProperty::Crossing(crossing));
+        return Ok(key);
+    }
+
     // Check if it is a relative value.
     match interpret_relative_value(event_value_str) {
         AffineParseResult::IsAffine(property) => {
@@ -767,10 +1002,19 @@ fn interpret_event_value(value_str: &str, parser: &KeyParser) -> Result<Interval
     Ok(Interval::new(min, max))
 }
 
-/// Returns None for "", an integer for integer strings, and otherwise gives an error.
+/// Names that can be used instead of the numeric values of EV_KEY events, e.g. "key:a:press".
+const VALUE_ALIASES: &[(&str, i32)] = &[
+    ("release", 0),
+    ("press", 1),
+    ("repeat", 2),
+];
+
+/// Returns None for "", an integer for integer strings or value aliases, and otherwise gives an error.
 fn parse_int_or_wildcard(value_str: &str) -> Result<Option<i32>, ArgumentError> {
     if value_str == "" {
         Ok(None)
+    } else if let Some(&(_, value)) = VALUE_ALIASES.iter().find(|(alias, _)| *alias == value_str) {
+        Ok(Some(value))
     } else {
         let value: i32 = value_str.parse().map_err(|err| ArgumentError::new(
             format!("Cannot interpret {} as an integer: {}.", value_str, err)
@@ -855,3 +1099,60 @@ fn unittest_requires_range() {
     assert!(parser.parse("abs:x:1").unwrap().split_value().1 == Some(Interval::new(1, 1)));
     assert!(parser.parse("abs:x:1~1").unwrap().split_value().1 == Some(Interval::new(1, 1)));
 }
+
+#[test]
+fn unittest_value_aliases() {
+    let parser = KeyParser::default_filter();
+    assert!(parser.parse("key:a:release").unwrap().split_value().1 == Some(Interval::new(0, 0)));
+    assert!(parser.parse("key:a:press").unwrap().split_value().1 == Some(Interval::new(1, 1)));
+    assert!(parser.parse("key:a:repeat").unwrap().split_value().1 == Some(Interval::new(2, 2)));
+    assert!(parser.parse("key:a:press~repeat").unwrap().split_value().1 == Some(Interval::new(1, 2)));
+    assert!(parser.parse("key:a:pressed").is_err());
+
+    let transition = parser.parse("key:a:release..press").unwrap();
+    let code = ecodes::event_code("key", "a").unwrap();
+    let domain = domain::get_unique_domain();
+    assert!(transition.matches(&Event::new(code, 1, 0, domain, Namespace::User)));
+    assert!(! transition.matches(&Event::new(code, 1, 2, domain, Namespace::User)));
+
+    assert!(resembles_key("key:a:release..press"));
+    assert!(! resembles_key("repeat"));
+}
+#[test]
+fn unittest_wildcards() {
+    let parser = KeyParser::default_filter();
+    let key = parser.parse("btn:trigger_happy*").unwrap();
+    let code = |type_name, code_name| ecodes::event_code(type_name, code_name).unwrap();
+    assert!(key.requires_event_type() == Some(EventType::KEY));
+    assert!(key.matches_channel((code("btn", "trigger_happy1"), domain::get_unique_domain())));
+    assert!(key.matches_channel((code("btn", "trigger_happy40"), domain::get_unique_domain())));
+    assert!(! key.matches_channel((code("btn", "left"), domain::get_unique_domain())));
+
+    assert!(key.intersects_with(&parser.parse("btn:trigger_happy3").unwrap()));
+    assert!(key.intersects_with(&parser.parse("btn:trigger_happy?").unwrap()));
+    assert!(! key.intersects_with(&parser.parse("btn:left").unwrap()));
+    assert!(! key.intersects_with(&parser.parse("key:f?").unwrap()));
+
+    assert!(parser.parse("btn:nonexistent*").is_err());
+    assert!(KeyParser::default_mask().parse("btn:trigger_happy*").is_err());
+}
+
+#[test]
+fn unittest_hid_usages() {
+    let parser = KeyParser::default_filter();
+    let code = |type_name, code_name| ecodes::event_code(type_name, code_name).unwrap();
+    let domain = domain::resolve("foo").unwrap();
+
+    let key = parser.parse("usage:0c:cd:1@foo").unwrap();
+    assert!(key.matches(&Event::new(code("key", "playpause"), 1, 0, domain, Namespace::User)));
+    assert!(! key.matches(&Event::new(code("key", "playpause"), 0, 0, domain, Namespace::User)));
+    let key = parser.parse("usage:0x07:0x04").unwrap();
+    assert!(key.matches(&Event::new(code("key", "a"), 1, 0, domain, Namespace::User)));
+    let key = parser.parse("usage:c:e9^macro").unwrap();
+    assert!(key.matches_channel((code("key", "volumeup"), domain)));
+
+    assert!(parser.parse("usage:0c").is_err());
+    assert!(parser.parse("usage:0c:xyz").is_err());
+    assert!(parser.parse("usage:10000:1").is_err());
+    assert!(parser.parse("usage:0c:238").is_err());
+}
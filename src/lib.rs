@@ -0,0 +1,191 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+// Allowed because useless default implementations are dead lines of code.
+#![allow(clippy::new_without_default)]
+
+// Allowed because assuming that Default::default() returns any specific value seems to go against
+// the documentation, which says "Sometimes, you want to fall back to some kind of default value,
+// and don’t particularly care what it is."
+#![allow(clippy::unwrap_or_default)]
+
+// Allowed because the key "" is a canonically valid key, and comparing a key to "" is more
+// idiomatic than asking whether a key is empty.
+#![allow(clippy::comparison_to_empty)]
+
+// Allowed because nested ifs allow for more-readable code.
+#![allow(clippy::collapsible_if)]
+
+// Disallowed for code uniformity.
+#![warn(clippy::explicit_iter_loop)]
+#![warn(clippy::explicit_into_iter_loop)]
+
+pub mod event;
+pub mod key;
+pub mod domain;
+pub mod annotation;
+pub mod state;
+pub mod signal;
+pub mod error;
+pub mod capability;
+pub mod affine;
+pub mod summary;
+pub mod crossing;
+pub mod range;
+pub mod ecodes;
+pub mod predevice;
+pub mod subprocess;
+pub mod sandbox;
+pub mod privileges;
+pub mod realtime;
+pub mod daemon;
+pub mod loopback;
+pub mod stream;
+pub mod quirks;
+pub mod effects;
+pub mod control_fifo;
+pub mod introspect;
+pub mod status;
+pub mod time;
+pub mod utils;
+pub mod engine;
+pub mod capi;
+
+pub mod scancodes;
+
+#[cfg(test)]
+pub mod tests;
+
+pub mod io {
+    pub mod input;
+    pub mod epoll;
+    pub mod output;
+    pub mod internal_pipe;
+    pub mod fd;
+    pub mod fifo;
+    pub mod journal;
+    pub mod vt;
+    pub mod replay;
+    pub mod serialization;
+    pub mod timer;
+}
+
+pub mod persist {
+    pub mod inotify;
+    pub mod blueprint;
+    pub mod subsystem;
+    pub mod interface;
+    pub mod format;
+    pub mod storage;
+}
+
+pub mod arguments {
+    pub mod hook;
+    pub mod parser;
+    pub mod input;
+    pub mod output;
+    pub mod lib;
+    pub mod map;
+    pub mod toggle;
+    pub mod print;
+    pub mod merge;
+    pub mod delay;
+    pub mod withhold;
+    pub mod absrel;
+    pub mod control_fifo;
+    pub mod test;
+    pub mod config;
+    pub mod pipeline;
+    pub mod scale;
+    pub mod curve;
+    pub mod capability;
+    pub mod invert_scroll;
+    pub mod macro_slot;
+    pub mod sandbox;
+    pub mod run_as;
+    pub mod quirk;
+    pub mod gyro;
+    pub mod mouse_keys;
+    pub mod slow_keys;
+    pub mod bounce_keys;
+    pub mod inhibit_while_typing;
+    pub mod max_hold;
+    pub mod default;
+    pub mod gate;
+    pub mod stats;
+    pub mod histogram;
+    pub mod sequence;
+    pub mod edge;
+    pub mod press_duration;
+    pub mod schedule;
+    pub mod vt;
+    pub mod bypass;
+    pub mod recover;
+    pub mod batch;
+    pub mod realtime;
+    pub mod safe_mode;
+    pub mod stage;
+    pub mod mirror;
+    pub mod remap_modifiers;
+    pub mod pressure_click;
+    pub mod plugin;
+    pub mod summarize;
+    pub mod feature_info;
+    pub mod introspect;
+    pub mod shadow;
+    pub mod migrate;
+    pub mod status_file;
+}
+
+pub mod bindings {
+    #[allow(warnings)]
+    pub mod libevdev;
+}
+
+pub mod data {
+    pub mod hid_usage;
+    pub mod hid_usage_parser;
+}
+
+#[macro_use]
+extern crate lazy_static;
+
+use std::os::unix::prelude::{AsRawFd, RawFd};
+
+use io::fd::HasFixedFd;
+use io::input::InputDevice;
+use io::vt::VtMonitor;
+use io::replay::ReplayDevice;
+use io::timer::WakeupTimer;
+use signal::SignalFd;
+use control_fifo::ControlFifo;
+use introspect::{IntrospectSocket, IntrospectClient};
+
+/// All files that the main loop of evsieve waits for.
+pub enum Pollable {
+    InputDevice(InputDevice),
+    SignalFd(SignalFd),
+    ControlFifo(ControlFifo),
+    IntrospectSocket(IntrospectSocket),
+    IntrospectClient(IntrospectClient),
+    VtMonitor(VtMonitor),
+    ReplayDevice(ReplayDevice),
+    PersistSubsystem(persist::interface::HostInterface),
+    WakeupTimer(WakeupTimer),
+}
+unsafe impl HasFixedFd for Pollable {}
+
+impl AsRawFd for Pollable {
+    fn as_raw_fd(&self) -> RawFd {
+        match self {
+            Pollable::InputDevice(device) => device.as_raw_fd(),
+            Pollable::SignalFd(fd) => fd.as_raw_fd(),
+            Pollable::ControlFifo(fifo) => fifo.as_raw_fd(),
+            Pollable::IntrospectSocket(socket) => socket.as_raw_fd(),
+            Pollable::IntrospectClient(client) => client.as_raw_fd(),
+            Pollable::VtMonitor(monitor) => monitor.as_raw_fd(),
+            Pollable::ReplayDevice(device) => device.as_raw_fd(),
+            Pollable::PersistSubsystem(interface) => interface.as_raw_fd(),
+            Pollable::WakeupTimer(timer) => timer.as_raw_fd(),
+        }
+    }
+}
@@ -1,8 +1,6 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::time::{Instant, Duration};
-use std::convert::TryInto;
-use std::num::NonZeroI32;
 
 /// Whenever a wakeup is scheduled, you get a `Token` back. At the desired time, a wakeup()
 /// call with the provided token shall be made.
@@ -17,12 +15,35 @@ impl Token {
     }
 }
 
+/// Determines what happens to a pending wakeup if the system suspends before it is due.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SuspendMode {
+    /// The time spent suspended does not count towards the delay, like CLOCK_MONOTONIC. A wakeup that
+    /// was due in a second when the system suspended is still due in a second after it resumes.
+    Pause,
+    /// The time spent suspended counts towards the delay, like CLOCK_BOOTTIME. Wakeups that became due
+    /// while the system was suspended happen as soon as it resumes.
+    Count,
+}
+
+impl Default for SuspendMode {
+    fn default() -> SuspendMode {
+        SuspendMode::Pause
+    }
+}
+
 pub struct Loopback {
-    schedule: Vec<(Instant, Token)>,
+    schedule: Vec<(Instant, Token, SuspendMode)>,
 
     /// A counter for the amount of `Token`s that were handed out. Ensures that all handed
     /// out tokens shall be unique except in case of integer overflow.
     token_index: u64,
+
+    /// All delays that get scheduled are multiplied by this factor. Changed by the time-scale command.
+    time_scale: f64,
+
+    /// The total time that the system had spent suspended the last time we checked.
+    time_suspended: Duration,
 }
 
 /// A LoopbackHandle contains a reference to the Loopback device, plus a virtual moment that
@@ -45,19 +66,16 @@ pub struct Loopback {
 /// 
 pub struct LoopbackHandle<'a> {
     loopback: &'a mut Loopback,
-    /// If Some, then we shall emulate the current time being a certain moment in time, even
-    /// if it isn't that time right now. If it is None, then it represents the actual time
-    /// of the current moment, but it has not been computed yet because that would cost a
-    /// syscall and we're not actually sure if we'll actually need it, and it must be computed
-    /// when we actually need it.
-    now: Option<Instant>,
+    /// We shall emulate the current time being this moment in time, even if it isn't that
+    /// time right now, e.g. because we are processing a wakeup that was scheduled earlier.
+    now: Instant,
 }
 
 pub enum Delay {
     Now,
     Never,
-    /// Wait a specified amount of milliseconds.
-    Wait(NonZeroI32),
+    /// Wait a specified nonzero amount of time.
+    Wait(Duration),
 }
 
 impl Loopback {
@@ -65,36 +83,50 @@ impl Loopback {
         Loopback {
             schedule: Vec::new(),
             token_index: 0,
+            time_scale: 1.0,
+            time_suspended: Duration::from_nanos(0),
         }
     }
 
-    pub fn time_until_next_wakeup(&self) -> Delay {
-        let next_instant_opt = self.schedule.iter()
-            .map(|(instant, _token)| instant).min();
-        
-        // If None, then then there are no events scheduled to happen.
-        let next_instant = match next_instant_opt {
-            Some(value) => value,
-            None => return Delay::Never,
-        };
-
-        // If None, then the event should've been scheduled at some time in the past.
-        let duration = match next_instant.checked_duration_since(Instant::now()) {
-            Some(value) => value,
-            None => return Delay::Now,
-        };
-
-        // If Err, then the delay is very, very far in the future. It probably means the user
-        // entered some bogus number for the delay. Let's not panic.
-        let millisecond_wait: i32 = match duration.as_millis().try_into() {
-            Ok(value) => value,
-            Err(_) => return Delay::Never,
-        };
-
-        // Ensure that we do not construct a NextEventDelay::Wait(0) result.
-        match NonZeroI32::new(millisecond_wait) {
-            Some(value) => Delay::Wait(value),
-            None => Delay::Now,
+    /// `time_suspended` is the total time the system has spent suspended according to the clock, which
+    /// brings wakeups with `SuspendMode::Count` closer if it increased since the last `observe_suspend()`.
+    pub fn time_until_next_wakeup(&self, now: Instant, time_suspended: Duration) -> Delay {
+        let newly_suspended = time_suspended.saturating_sub(self.time_suspended);
+        let next_duration_opt = self.schedule.iter()
+            .map(|(instant, _token, mode)| {
+                // If None, then the event should've been scheduled at some time in the past.
+                let duration = instant.checked_duration_since(now).unwrap_or_else(|| Duration::from_nanos(0));
+                match mode {
+                    SuspendMode::Pause => duration,
+                    SuspendMode::Count => duration.saturating_sub(newly_suspended),
+                }
+            }).min();
+
+        match next_duration_opt {
+            // If None, then then there are no events scheduled to happen.
+            None => Delay::Never,
+            // Ensure that we do not construct a Delay::Wait(0) result.
+            Some(duration) if duration > Duration::from_nanos(0) => Delay::Wait(duration),
+            Some(_) => Delay::Now,
+        }
+    }
+
+    /// Moves the wakeups with `SuspendMode::Count` forward by however much the total time spent suspended
+    /// increased since the last call. The wakeups that became due while the system was suspended are moved
+    /// to `now` rather than into the past, so periodic wakeups do not try to catch up on all periods they
+    /// missed at once.
+    pub fn observe_suspend(&mut self, now: Instant, time_suspended: Duration) {
+        let newly_suspended = time_suspended.saturating_sub(self.time_suspended);
+        self.time_suspended = self.time_suspended.max(time_suspended);
+        if newly_suspended == Duration::from_nanos(0) {
+            return;
+        }
+        for (instant, _token, mode) in &mut self.schedule {
+            if *mode == SuspendMode::Count {
+                if let Some(remaining) = instant.checked_duration_since(now) {
+                    *instant = now + remaining.saturating_sub(newly_suspended);
+                }
+            }
         }
     }
 
@@ -114,26 +146,26 @@ impl Loopback {
             return None;
         }
 
-        let mut ready_tokens: Vec<(Instant, Token)> = Vec::new();
-        let mut remaining_schedule: Vec<(Instant, Token)> = Vec::new();
+        let mut ready_tokens: Vec<(Instant, Token, SuspendMode)> = Vec::new();
+        let mut remaining_schedule: Vec<(Instant, Token, SuspendMode)> = Vec::new();
 
-        for (instant, token) in std::mem::take(&mut self.schedule) {
+        for (instant, token, mode) in std::mem::take(&mut self.schedule) {
             if instant <= now {
-                ready_tokens.push((instant, token));
+                ready_tokens.push((instant, token, mode));
             } else {
-                remaining_schedule.push((instant, token));
+                remaining_schedule.push((instant, token, mode));
             }
         }
         // Stably sort: make sure that the most overdue token is yielded first. Tokens that
         // are due at the exact same time should be yielded in the order they were added.
-        ready_tokens.sort_by_key(|(time, _token)| *time);
+        ready_tokens.sort_by_key(|(time, _token, _mode)| *time);
 
         // Take the first ready token, add the rest back to the schedule.
         let mut ready_tokens_iter = ready_tokens.into_iter();
         let first_token = ready_tokens_iter.next();
         self.schedule = ready_tokens_iter.chain(remaining_schedule).collect();
 
-        first_token
+        first_token.map(|(instant, token, _mode)| (instant, token))
     }
 
     fn generate_token(&mut self) -> Token {
@@ -145,47 +177,90 @@ impl Loopback {
         Token(self.token_index)
     }
 
-    pub fn get_handle(&mut self, now: Instant) -> LoopbackHandle {
-        LoopbackHandle {
-            loopback: self,
-            now: Some(now),
-        }
+    /// Makes all delays that get scheduled from now on last `factor` times as long. Wakeups that
+    /// have already been scheduled are not affected.
+    pub fn set_time_scale(&mut self, factor: f64) {
+        self.time_scale = factor;
     }
 
-    pub fn get_handle_lazy(&mut self) -> LoopbackHandle {
+    pub fn get_handle(&mut self, now: Instant) -> LoopbackHandle {
         LoopbackHandle {
             loopback: self,
-            now: None,
+            now,
         }
     }
 }
 
 impl<'a> LoopbackHandle<'a> {
-    fn schedule_wakeup_at(&mut self, time: Instant) -> Token {
+    fn schedule_wakeup_at(&mut self, time: Instant, mode: SuspendMode) -> Token {
         let token = self.loopback.generate_token();
-        self.loopback.schedule.push((time, token.clone()));
+        self.loopback.schedule.push((time, token.clone(), mode));
         token
     }
 
     pub fn schedule_wakeup_in(&mut self, delay: Duration) -> Token {
+        self.schedule_wakeup_in_mode(delay, SuspendMode::Pause)
+    }
+
+    /// Like `schedule_wakeup_in()`, but lets the caller decide whether time spent suspended counts
+    /// towards the delay.
+    pub fn schedule_wakeup_in_mode(&mut self, delay: Duration, mode: SuspendMode) -> Token {
         let now = self.now();
-        self.schedule_wakeup_at(now + delay)
+        let scale = self.loopback.time_scale;
+        let delay = if scale == 1.0 { delay } else { delay.mul_f64(scale) };
+        self.schedule_wakeup_at(now + delay, mode)
     }
 
     /// If a previously-scheduled wakeup no longer seems needed, you can cancel it to save some
     /// CPU cycles later.
     pub fn cancel_token(&mut self, token: Token) {
-        self.loopback.schedule.retain(|(_, other_token)| token != *other_token);
+        self.loopback.schedule.retain(|(_, other_token, _)| token != *other_token);
     }
 
-    /// Like self.now, but lazily computes the current time if it wasn't already stored
-    /// in self.now.
-    fn now(&mut self) -> Instant {
-        let time = match self.now {
-            Some(time) => time,
-            None => Instant::now(),
-        };
-        self.now = Some(time);
-        time
+    /// The time at which the events currently being processed happened.
+    pub fn now(&self) -> Instant {
+        self.now
     }
-}
\ No newline at end of file
+}
+#[test]
+fn unittest() {
+    let start = Instant::now();
+    let not_suspended = Duration::from_nanos(0);
+    let mut loopback = Loopback::new();
+    assert!(matches!(loopback.time_until_next_wakeup(start, not_suspended), Delay::Never));
+
+    // Wakeups can be scheduled with a precision finer than a millisecond.
+    let token = loopback.get_handle(start).schedule_wakeup_in(Duration::from_micros(250));
+    match loopback.time_until_next_wakeup(start + Duration::from_micros(100), not_suspended) {
+        Delay::Wait(duration) => assert_eq!(duration, Duration::from_micros(150)),
+        _ => panic!("Expected the loopback to wait 150 microseconds."),
+    }
+    assert!(loopback.poll_once(start + Duration::from_micros(249)).is_none());
+    assert!(matches!(loopback.time_until_next_wakeup(start + Duration::from_micros(250), not_suspended), Delay::Now));
+    assert!(loopback.poll_once(start + Duration::from_micros(250)).unwrap().1 == token);
+
+    // The time scale stretches the delays that get scheduled afterwards.
+    loopback.set_time_scale(2.5);
+    let token = loopback.get_handle(start).schedule_wakeup_in(Duration::from_millis(100));
+    assert!(loopback.poll_once(start + Duration::from_millis(249)).is_none());
+    assert!(loopback.poll_once(start + Duration::from_millis(250)).unwrap().1 == token);
+    loopback.set_time_scale(1.0);
+
+    // Suspending for 3 seconds only brings the wakeups that count the time spent suspended closer. The
+    // counting wakeup that became due during the suspend happens right after resuming.
+    let paused = loopback.get_handle(start).schedule_wakeup_in(Duration::from_secs(2));
+    let counted_short = loopback.get_handle(start).schedule_wakeup_in_mode(Duration::from_secs(2), SuspendMode::Count);
+    let counted_long = loopback.get_handle(start).schedule_wakeup_in_mode(Duration::from_secs(5), SuspendMode::Count);
+    let resume = start + Duration::from_secs(1);
+    let suspended = Duration::from_secs(3);
+    assert!(matches!(loopback.time_until_next_wakeup(resume, suspended), Delay::Now));
+    loopback.observe_suspend(resume, suspended);
+    assert!(loopback.poll_once(resume).unwrap() == (resume, counted_short));
+    assert!(loopback.poll_once(resume).is_none());
+    match loopback.time_until_next_wakeup(resume, suspended) {
+        Delay::Wait(duration) => assert_eq!(duration, Duration::from_secs(1)),
+        _ => panic!("Expected the loopback to wait 1 second."),
+    }
+    assert!(loopback.poll_once(start + Duration::from_secs(2)).unwrap().1 == paused);
+    assert!(loopback.poll_once(start + Duration::from_secs(2)).unwrap().1 == counted_long);
+}
@@ -1,17 +1,5 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-// Allowed because useless default implementations are dead lines of code.
-#![allow(clippy::new_without_default)]
-
-// Allowed because assuming that Default::default() returns any specific value seems to go against
-// the documentation, which says "Sometimes, you want to fall back to some kind of default value,
-// and don’t particularly care what it is."
-#![allow(clippy::unwrap_or_default)]
-
-// Allowed because the key "" is a canonically valid key, and comparing a key to "" is more
-// idiomatic than asking whether a key is empty.
-#![allow(clippy::comparison_to_empty)]
-
 // Allowed because nested ifs allow for more-readable code.
 #![allow(clippy::collapsible_if)]
 
@@ -19,99 +7,30 @@
 #![warn(clippy::explicit_iter_loop)]
 #![warn(clippy::explicit_into_iter_loop)]
 
-pub mod event;
-pub mod key;
-pub mod domain;
-pub mod state;
-pub mod signal;
-pub mod error;
-pub mod capability;
-pub mod affine;
-pub mod range;
-pub mod ecodes;
-pub mod predevice;
-pub mod subprocess;
-pub mod daemon;
-pub mod loopback;
-pub mod stream;
-pub mod control_fifo;
-pub mod time;
-pub mod utils;
-
-#[cfg(feature = "auto-scan")]
-pub mod scancodes;
-
-#[cfg(test)]
-pub mod tests;
-
-pub mod io {
-    pub mod input;
-    pub mod epoll;
-    pub mod output;
-    pub mod internal_pipe;
-    pub mod fd;
-    pub mod fifo;
-}
-
-pub mod persist {
-    pub mod inotify;
-    pub mod blueprint;
-    pub mod subsystem;
-    pub mod interface;
-    pub mod format;
-    pub mod storage;
-}
-
-pub mod arguments {
-    pub mod hook;
-    pub mod parser;
-    pub mod input;
-    pub mod output;
-    pub mod lib;
-    pub mod map;
-    pub mod toggle;
-    pub mod print;
-    pub mod merge;
-    pub mod delay;
-    pub mod withhold;
-    pub mod absrel;
-    pub mod control_fifo;
-    pub mod test;
-    pub mod config;
-    pub mod scale;
-    pub mod capability;
-}
-
-pub mod bindings {
-    #[allow(warnings)]
-    pub mod libevdev;
-}
-
-pub mod data {
-    pub mod hid_usage;
-    pub mod hid_usage_parser;
-}
-
-#[macro_use]
-extern crate lazy_static;
+use evsieve::{arguments, daemon, loopback, sandbox, signal, subprocess, Pollable};
+use evsieve::arguments::parser::Implementation;
+use evsieve::error::{ArgumentError, RuntimeError, Context, ErrorCategory};
+use evsieve::io::epoll::{Epoll, FileIndex, Message};
+use evsieve::event::{EventCode, EventValue};
+use evsieve::io::input::{self, InputDevice, StaleAction, StaleSetting};
+use evsieve::io::output::UInputSystem;
+use evsieve::io::vt::{VtMonitor, VtNumber};
+use evsieve::io::timer::WakeupTimer;
+use evsieve::persist::interface::HostInterfaceState;
+use evsieve::stream::Setup;
+use evsieve::signal::SigMask;
+use evsieve::control_fifo::{self, CommandInfo, Command};
+use evsieve::introspect::{self, DeviceInfo, DeviceState, Method};
+use evsieve::status::{self, DeviceStatus, StatusFile, ToggleStatus};
 
-use std::os::unix::prelude::{AsRawFd, RawFd};
-
-use arguments::parser::Implementation;
-use error::{RuntimeError, Context};
-use io::epoll::{Epoll, FileIndex, Message};
-use io::fd::HasFixedFd;
-use io::input::InputDevice;
-use io::output::UInputSystem;
-use persist::interface::HostInterfaceState;
-use stream::Setup;
-use signal::{SigMask, SignalFd};
-use control_fifo::{CommandInfo, ControlFifo};
-
-use crate::error::SystemError;
-use crate::event::EventCode;
-use crate::persist::subsystem::Report;
-use crate::predevice::PersistState;
+use evsieve::error::SystemError;
+use evsieve::persist::subsystem::Report;
+use evsieve::predevice::{FallbackMembership, PersistState};
+use evsieve::sandbox::SandboxMode;
+use evsieve::time::{Duration, Instant};
+use evsieve::domain::{self, Domain};
+use evsieve::persist::blueprint::Blueprint;
+use std::collections::{HashMap, HashSet};
 
 
 fn main() {
@@ -129,46 +48,65 @@ fn run_and_interpret_exit_code() -> i32 {
         // A RuntimeError happened.
         Ok(Err(error)) => {
             eprintln!("{}", error);
-            1
+            error.category().exit_code()
         },
         // A panic happened.
         Err(_) => {
             eprintln!("Internal error: a panic happened. This is a bug.");
-            1
+            ErrorCategory::Other.exit_code()
         },
     }
 }
 
-pub enum Pollable {
-    InputDevice(InputDevice),
-    SignalFd(SignalFd),
-    ControlFifo(ControlFifo),
-    PersistSubsystem(persist::interface::HostInterface),
-}
-unsafe impl HasFixedFd for Pollable {}
-
-impl AsRawFd for Pollable {
-    fn as_raw_fd(&self) -> RawFd {
-        match self {
-            Pollable::InputDevice(device) => device.as_raw_fd(),
-            Pollable::SignalFd(fd) => fd.as_raw_fd(),
-            Pollable::ControlFifo(fifo) => fifo.as_raw_fd(),
-            Pollable::PersistSubsystem(interface) => interface.as_raw_fd(),
-        }
-    }
-}
-
 struct Program {
     epoll: Epoll<Pollable>,
-    setup: Setup<UInputSystem>,
+    /// One setup for each pipeline. The first one is defined by the command line itself, the others
+    /// by --pipeline and --shadow arguments.
+    setups: Vec<Setup<UInputSystem>>,
+    /// For the domain of each input device, the index of the setup its events shall be sent to.
+    input_pipelines: HashMap<Domain, usize>,
+    /// For the path of each control FIFO, the index of the setup its commands shall act upon.
+    fifo_pipelines: HashMap<String, usize>,
+    /// For each shadow pipeline, the index of its setup and the domains of the input devices whose events
+    /// are copied to it.
+    shadow_pipelines: Vec<(usize, Vec<Domain>)>,
     persist_subsystem: HostInterfaceState,
+    /// The interval specified by --stats and the next time at which the statistics shall be printed.
+    stats_schedule: Option<(Duration, Instant)>,
+    /// The file specified by --status-file, if any.
+    status_file: Option<StatusFile>,
+    /// If Some, evsieve sleeps this long after handling events, so events that arrive in the meantime
+    /// get handled in a single batch, as requested by --batch.
+    batch_latency: Option<Duration>,
+    /// Input devices that have been closed by the revoke command, together with the index of their
+    /// pipeline. They get reopened by the reacquire command.
+    revoked_devices: Vec<(usize, Blueprint)>,
+    /// The virtual terminal that was active when the --on-vt arguments were last informed about it.
+    active_vt: Option<VtNumber>,
+    /// Arguments sent by the apply command must respect the sandbox as well.
+    sandbox_mode: SandboxMode,
+    /// The index of the timer that wakes up the main loop when the next wakeup of a setup is due.
+    wakeup_timer: FileIndex,
+    /// The strings that the arguments of each pipeline were parsed from, for the introspection sockets.
+    pipeline_arguments: Vec<Vec<Vec<String>>>,
 }
 
+/// How often to retry writing events that an output device did not accept, in milliseconds.
+const OUTPUT_QUEUE_RETRY_INTERVAL_MS: i32 = 10;
+
 const TERMINATION_SIGNALS: [libc::c_int; 3] = [libc::SIGTERM, libc::SIGINT, libc::SIGHUP];
 
 fn run() -> Result<(), RuntimeError> {
     // Check if the arguments contain --help or --version.
     let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().map(String::as_str) == Some("--migrate") {
+        let migration = arguments::migrate::migrate(args[1..].to_vec())?;
+        for note in migration.notes {
+            eprintln!("{}\n", note);
+        }
+        print!("{}", migration.config);
+        return Ok(());
+    }
     if arguments::parser::check_help_and_version(&args) {
         daemon::notify_ready_async();
         return Ok(());
@@ -183,53 +121,151 @@ fn run() -> Result<(), RuntimeError> {
     let signal_fd = signal::SignalFd::new(&sigmask)?;
     let mut epoll = Epoll::new()?;
     epoll.add_file(Pollable::SignalFd(signal_fd))?;
+    let wakeup_timer = epoll.add_file(Pollable::WakeupTimer(WakeupTimer::new()?))?;
 
     // Additionally block SIGCHLD because another thread listens for it.
     sigmask.add(libc::SIGCHLD);
     let _signal_block = unsafe { signal::SignalBlock::new(&sigmask)? };
 
-    // Parse the arguments and set up the input/output devices.
-    let pre_implementation = arguments::parser::process(args)?;
-    let Implementation { setup, input_devices, blueprints, control_fifos } = arguments::parser::implement(pre_implementation)?;
-
-    for device in input_devices {
-        epoll.add_file(Pollable::InputDevice(device))?;
+    // Parse the arguments and set up the input/output devices of each pipeline.
+    let pre_implementations = arguments::parser::process_pipelines(args)?;
+    if let Some(format) = pre_implementations[0].summary_format {
+        for pre_implementation in pre_implementations {
+            print!("{}", arguments::parser::summarize(pre_implementation, format)?);
+        }
+        daemon::notify_ready_async();
+        return Ok(());
+    }
+    let mut pre_implementations = pre_implementations.into_iter();
+    let Implementation {
+        setup, input_devices, replay_devices, blueprints, control_fifos, sandbox_policy, run_as, stats_interval, status_file,
+        batch_latency, realtime, safe_mode, introspect_sockets, argument_groups, shadow_sources: _,
+    } = arguments::parser::implement(pre_implementations.next().expect("Internal error: no pipelines defined. This is a bug."))?;
+    let mut pipelines = vec![(setup, input_devices, replay_devices, blueprints, control_fifos)];
+    let mut pipeline_arguments = vec![argument_groups];
+    let mut shadow_pipelines: Vec<(usize, Vec<Domain>)> = Vec::new();
+    for pre_implementation in pre_implementations {
+        let Implementation {
+            setup, input_devices, replay_devices, blueprints, control_fifos, argument_groups, shadow_sources, ..
+        } = arguments::parser::implement(pre_implementation)?;
+        if let Some(shadow_sources) = shadow_sources {
+            shadow_pipelines.push((pipelines.len(), shadow_sources));
+        }
+        pipelines.push((setup, input_devices, replay_devices, blueprints, control_fifos));
+        pipeline_arguments.push(argument_groups);
     }
-    for fifo in control_fifos {
-        epoll.add_file(Pollable::ControlFifo(fifo))?;
+    for socket in introspect_sockets {
+        epoll.add_file(Pollable::IntrospectSocket(socket))?;
     }
 
+    // Only grab the input devices after all output devices have been created successfully. Otherwise, a
+    // misconfigured system could leave the user with a grabbed keyboard and no output device to type on.
+    let mut all_input_devices: Vec<&mut InputDevice> = pipelines.iter_mut()
+        .flat_map(|(_, input_devices, ..)| input_devices.iter_mut())
+        .collect();
+    input::grab_input_devices(&mut all_input_devices, safe_mode)?;
+
     // If the persistence subsystem is running, this shall keep track of its index in the epoll.
     let mut persist_subsystem: HostInterfaceState = HostInterfaceState::new();
 
-    // If we were given any blueprints, we must launch the persitence subsystem right now and declare
-    // that we want those blueprints to be opened.
-    if ! blueprints.is_empty() {
-        let interface = match persist_subsystem.require(&mut epoll) {
-            Some(interface) => interface,
-            None => return Err(SystemError::new("Failed to launch the persistence subsystem, which is required to open the input devices flagged with \"persist\".").into()),
-        };
-        for blueprint in blueprints {
-            interface.add_blueprint(blueprint)
-                .with_context("While trying to register a perstent device to be opened later")?
+    let mut setups: Vec<Setup<UInputSystem>> = Vec::new();
+    let mut input_pipelines: HashMap<Domain, usize> = HashMap::new();
+    let mut fifo_pipelines: HashMap<String, usize> = HashMap::new();
+    let mut replay_indices: Vec<FileIndex> = Vec::new();
+    for (pipeline_index, (setup, input_devices, replay_devices, blueprints, control_fifos)) in pipelines.into_iter().enumerate() {
+        setups.push(setup);
+        for device in input_devices {
+            input_pipelines.insert(device.domain(), pipeline_index);
+            epoll.add_file(Pollable::InputDevice(device))?;
+        }
+        for device in replay_devices {
+            input_pipelines.insert(device.domain(), pipeline_index);
+            replay_indices.push(epoll.add_file(Pollable::ReplayDevice(device))?);
+        }
+        for fifo in control_fifos {
+            fifo_pipelines.insert(fifo.path().to_owned(), pipeline_index);
+            epoll.add_file(Pollable::ControlFifo(fifo))?;
+        }
+
+        // If we were given any blueprints, we must launch the persitence subsystem right now and declare
+        // that we want those blueprints to be opened.
+        if ! blueprints.is_empty() {
+            let interface = match persist_subsystem.require(&mut epoll) {
+                Some(interface) => interface,
+                None => return Err(SystemError::new("Failed to launch the persistence subsystem, which is required to open the input devices flagged with \"persist\".").into()),
+            };
+            for blueprint in blueprints {
+                input_pipelines.insert(blueprint.pre_device.domain, pipeline_index);
+                interface.add_blueprint(blueprint)
+                    .with_context("While trying to register a perstent device to be opened later")?
+            }
         }
     }
 
     let mut program = Program {
-        epoll, setup, persist_subsystem
+        epoll, setups, input_pipelines, fifo_pipelines, shadow_pipelines, persist_subsystem,
+        stats_schedule: stats_interval.map(|interval| (interval, Instant::now() + interval)),
+        status_file: status_file.map(|status_file| StatusFile::new(status_file, Instant::now())),
+        batch_latency,
+        revoked_devices: Vec::new(),
+        active_vt: None,
+        sandbox_mode: sandbox_policy.mode,
+        wakeup_timer,
+        pipeline_arguments,
     };
 
+    // Decide which member of each fallback group the events are taken from.
+    update_fallback_groups(&mut program);
+
+    // The --on-vt arguments must know which virtual terminal is active from the start.
+    if program.setups.iter().any(Setup::uses_vt) {
+        let mut monitor = VtMonitor::open()
+            .with_context("The --on-vt argument requires the active virtual terminal to be known:")?;
+        let active_vt = monitor.read_active()?;
+        for setup in &mut program.setups {
+            setup.vt_changed(None, active_vt);
+        }
+        program.active_vt = Some(active_vt);
+        program.epoll.add_file_with_events(Pollable::VtMonitor(monitor), libc::EPOLLPRI as u32)?;
+    }
+
+    // Setup is complete, so this is the earliest point at which page faults and preemption start to matter.
+    // This must happen before dropping privileges because it may require them.
+    realtime.apply();
+
+    // All devices have been opened and created, so we no longer need the privileges to do so.
+    if let Some(run_as) = run_as {
+        run_as.drop_privileges().with_context("While dropping privileges:")?;
+    }
+
     daemon::notify_ready_async();
 
+    // All files that are needed right away have been opened, so we can restrict ourselves now.
+    sandbox::enter(&sandbox_policy).with_context("While entering the sandbox:")?;
+
     // Make sure evsieve has something to do.
     if has_no_activity(&program.epoll) {
         println!("Warning: no input devices available. Evsieve will exit now.");
         return Ok(());
     }
 
+    // Start replaying as late as possible, so the intervals between the first few events are not
+    // shortened by the time it takes to set everything up.
+    for index in replay_indices {
+        if let Some(Pollable::ReplayDevice(device)) = program.epoll.get_mut(index) {
+            device.start()?;
+        }
+    }
+
     // Iterate over messages generated by the epoll.
     enter_main_loop(&mut program)?;
 
+    for setup in &program.setups {
+        for report in setup.histogram_reports() {
+            println!("{}", report);
+        }
+    }
+
     // Shut down the persistence system properly.
     program.persist_subsystem.await_shutdown(&mut program.epoll);
 
@@ -247,18 +283,52 @@ enum Action {
 /// is returned by `handle_ready_file()` or `handle_broken_file()`.
 fn enter_main_loop(program: &mut Program) -> Result<(), RuntimeError> {
     loop {
-        let timeout: i32 = match program.setup.time_until_next_wakeup() {
-            loopback::Delay::Now => {
-                program.setup.wakeup_until(crate::time::Instant::now());
-                continue;
-            },
-            loopback::Delay::Never => crate::io::epoll::INDEFINITE_TIMEOUT,
-            loopback::Delay::Wait(time) => time.get(),
+        apply_repeat_commands(program);
+
+        let mut timeout: i32 = evsieve::io::epoll::INDEFINITE_TIMEOUT;
+        let mut next_wakeup: Option<Instant> = None;
+        let mut has_woken_up = false;
+        for setup in &mut program.setups {
+            match setup.time_until_next_wakeup() {
+                loopback::Delay::Now => {
+                    // Handles all wakeups that are due at once, even if they were scheduled at slightly
+                    // different moments.
+                    let now = setup.now();
+                    setup.wakeup_until(now);
+                    has_woken_up = true;
+                },
+                loopback::Delay::Never => (),
+                loopback::Delay::Wait(time) => {
+                    let deadline = setup.now() + time;
+                    next_wakeup = Some(next_wakeup.map_or(deadline, |other| other.min(deadline)));
+                },
+            }
+        }
+        if has_woken_up {
+            continue;
+        }
+        // The epoll timeout only has millisecond resolution, so scheduled wakeups use a timer instead.
+        if let Some(Pollable::WakeupTimer(timer)) = program.epoll.get_mut(program.wakeup_timer) {
+            timer.set(next_wakeup)?;
+        }
+        for setup in &mut program.setups {
+            if setup.flush_output_queues() {
+                timeout = earliest_timeout(timeout, Some(OUTPUT_QUEUE_RETRY_INTERVAL_MS));
+            }
+        }
+        let timeout = earliest_timeout(timeout, print_stats_if_due(program));
+        let timeout = earliest_timeout(timeout, write_status_if_due(program));
+        let stale_timeout = match handle_stale_devices(program) {
+            (Action::Exit, _) => return Ok(()),
+            (Action::Continue, stale_timeout) => stale_timeout,
         };
+        let timeout = earliest_timeout(timeout, stale_timeout);
 
         let messages = program.epoll.poll(timeout).with_context("While polling the epoll for events:")?;
 
+        let mut has_received_messages = false;
         for message in messages {
+            has_received_messages = true;
             let action = match message {
                 Message::Ready(index) => {
                     match handle_ready_file(program, index) {
@@ -291,9 +361,167 @@ fn enter_main_loop(program: &mut Program) -> Result<(), RuntimeError> {
                 Action::Exit => return Ok(()),
             }
         }
+
+        // Give the input devices some time to accumulate events, so they get read in a single wakeup.
+        // The sleep is cut short if something is scheduled to happen before the latency has passed.
+        if let Some(batch_latency) = program.batch_latency {
+            if has_received_messages {
+                let max_sleep_micros = match timeout {
+                    evsieve::io::epoll::INDEFINITE_TIMEOUT => u64::MAX,
+                    timeout => timeout.max(0) as u64 * 1000,
+                };
+                let max_sleep_micros = match next_wakeup {
+                    Some(deadline) => deadline.checked_duration_since(Instant::now())
+                        .map_or(0, Duration::as_micros)
+                        .min(max_sleep_micros),
+                    None => max_sleep_micros,
+                };
+                let sleep_micros = batch_latency.as_micros().min(max_sleep_micros);
+                std::thread::sleep(std::time::Duration::from_micros(sleep_micros));
+            }
+        }
+    }
+}
+
+/// Combines an epoll timeout with another amount of milliseconds after which the epoll must wake up.
+fn earliest_timeout(timeout: i32, other: Option<i32>) -> i32 {
+    match other {
+        Some(other) if timeout == evsieve::io::epoll::INDEFINITE_TIMEOUT => other,
+        Some(other) => timeout.min(other),
+        None => timeout,
+    }
+}
+
+/// Converts a duration to an epoll timeout in milliseconds.
+fn duration_to_timeout(duration: Duration) -> i32 {
+    // Round up, so we do not wake up slightly before the deadline.
+    ((duration.as_micros() + 999) / 1000).min(i32::MAX as u64) as i32
+}
+
+/// Prints the statistics of the input devices if the --stats interval has passed. Returns the amount of
+/// milliseconds until they need to be printed again, or None if --stats was not specified.
+fn print_stats_if_due(program: &mut Program) -> Option<i32> {
+    let (interval, next_time) = program.stats_schedule?;
+    let now = Instant::now();
+    let next_time = match now.checked_duration_since(next_time) {
+        // The next report is not due yet.
+        None => next_time,
+        Some(_) => {
+            let input_devices = program.epoll.files().filter_map(|file| match file {
+                Pollable::InputDevice(device) => Some(device),
+                _ => None,
+            });
+            evsieve::control_fifo::print_device_stats(input_devices);
+            now + interval
+        },
+    };
+    program.stats_schedule = Some((interval, next_time));
+
+    let remaining = next_time.checked_duration_since(now).unwrap_or(Duration::from_nanos(0));
+    Some(duration_to_timeout(remaining))
+}
+
+/// Rewrites the --status-file if its interval has passed. Returns the amount of milliseconds until it needs
+/// to be rewritten again, or None if --status-file was not specified.
+fn write_status_if_due(program: &mut Program) -> Option<i32> {
+    let now = Instant::now();
+    if program.status_file.as_ref()?.is_due(now) {
+        let content = describe_status(program);
+        let status_file = program.status_file.as_mut()?;
+        // Failing to write the status file should not take the keyboard away from the user.
+        if let Err(error) = status_file.write(content, now) {
+            error.print_err();
+        }
+    }
+    Some(duration_to_timeout(program.status_file.as_ref()?.time_until_due(now)))
+}
+
+/// Handles the input devices that claim some keys are pressed, but have not sent any events for longer
+/// than their stale-after= clause allows. Returns the amount of milliseconds until the next device may
+/// become stale, or None if no device can currently become stale.
+fn handle_stale_devices(program: &mut Program) -> (Action, Option<i32>) {
+    let now = Instant::now();
+    let stale_devices: Vec<(FileIndex, StaleSetting)> = program.epoll.indexed_files()
+        .filter_map(|(index, file)| match file {
+            Pollable::InputDevice(device) => match (device.stale_deadline(), device.stale_setting()) {
+                (Some(deadline), Some(setting)) if deadline <= now => Some((index, setting)),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+
+    for (index, setting) in stale_devices {
+        match setting.action {
+            StaleAction::Release => {
+                if let Some(Pollable::InputDevice(device)) = program.epoll.get_mut(index) {
+                    eprintln!(
+                        "Warning: the device {} has not sent any events for {}s while some of its keys were pressed. Releasing those keys.",
+                        device.path().display(), setting.timeout.as_secs_f64(),
+                    );
+                    let setup = setup_for_device(&mut program.setups, &program.input_pipelines, device);
+                    for release_event in device.release_pressed_keys() {
+                        setup.run(now, release_event);
+                    }
+                    setup.syn();
+                }
+            },
+            StaleAction::Reopen => {
+                if let Some(Pollable::InputDevice(device)) = program.epoll.get(index) {
+                    eprintln!(
+                        "Warning: the device {} has not sent any events for {}s while some of its keys were pressed. Trying to reopen it.",
+                        device.path().display(), setting.timeout.as_secs_f64(),
+                    );
+                }
+                if let Action::Exit = handle_broken_file(program, index) {
+                    return (Action::Exit, None);
+                }
+            },
+        }
+    }
+
+    let next_deadline = program.epoll.files()
+        .filter_map(|file| match file {
+            Pollable::InputDevice(device) => device.stale_deadline(),
+            _ => None,
+        })
+        .min();
+    let timeout = next_deadline.map(|deadline|
+        duration_to_timeout(deadline.checked_duration_since(now).unwrap_or(Duration::from_nanos(0)))
+    );
+    (Action::Continue, timeout)
+}
+
+/// Changes the autorepeat settings of the input devices as requested by hooks.
+fn apply_repeat_commands(program: &mut Program) {
+    for (pipeline_index, setup) in program.setups.iter_mut().enumerate() {
+        for command in setup.state_mut().take_repeat_commands() {
+            for file in program.epoll.files_mut() {
+                if let Pollable::InputDevice(device) = file {
+                    if pipeline_of_device(&program.input_pipelines, device) != pipeline_index {
+                        continue;
+                    }
+                    device.apply_repeat_command(command).with_context_of(||
+                        format!("While changing the autorepeat settings of the input device {}:", device.path().display())
+                    ).print_err();
+                }
+            }
+        }
     }
 }
 
+/// Returns the index of the pipeline that the input device belongs to.
+fn pipeline_of_device(input_pipelines: &HashMap<Domain, usize>, device: &InputDevice) -> usize {
+    input_pipelines.get(&device.domain()).copied().unwrap_or(0)
+}
+
+/// Returns the setup that the events of the input device shall be sent to.
+fn setup_for_device<'a>(
+        setups: &'a mut [Setup<UInputSystem>], input_pipelines: &HashMap<Domain, usize>, device: &InputDevice
+) -> &'a mut Setup<UInputSystem> {
+    &mut setups[pipeline_of_device(input_pipelines, device)]
+}
+
 /// If this function returns Err, then `handle_broken_file` needs to be called with the same index.
 /// IMPORTANT: this function should NOT return Err if the device at `index` itself is not broken.
 /// If some other error occurs, you should handle it in this function itself and then return Ok.
@@ -310,9 +538,58 @@ fn handle_ready_file(program: &mut Program, index: FileIndex) -> Result<Action,
             let events = device.poll().with_context_of(||
                 format!("While polling the input device {}:", device.path().display())
             )?;
+            if device.is_standby() {
+                return Ok(Action::Continue);
+            }
+            let setup = setup_for_device(&mut program.setups, &program.input_pipelines, device);
+            for &(time, event) in &events {
+                setup.wakeup_until(time);
+                setup.run(time, event);
+            }
+            // The shadow pipelines receive a copy of the same events.
+            for (pipeline_index, sources) in &program.shadow_pipelines {
+                if ! sources.contains(&device.domain()) {
+                    continue;
+                }
+                let setup = &mut program.setups[*pipeline_index];
+                for &(time, event) in &events {
+                    setup.wakeup_until(time);
+                    setup.run(time, event);
+                }
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::ReplayDevice(device) => {
+            let events = device.poll().with_context_of(||
+                format!("While replaying events from {}:", device.path().display())
+            )?;
+            let setup = &mut program.setups[program.input_pipelines.get(&device.domain()).copied().unwrap_or(0)];
             for (time, event) in events {
-                program.setup.wakeup_until(time);
-                program.setup.run(time, event);
+                setup.wakeup_until(time);
+                setup.run(time, event);
+            }
+            if device.is_finished() {
+                println!("Finished replaying the events from {}.", device.path().display());
+                program.epoll.remove(index);
+                if has_no_activity(&program.epoll) {
+                    println!("No devices to poll events from. Evsieve will exit now.");
+                    return Ok(Action::Exit);
+                }
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::WakeupTimer(timer) => {
+            // The setups notice themselves that their wakeups are due in the next iteration of the main loop.
+            timer.acknowledge();
+            Ok(Action::Continue)
+        },
+        Pollable::VtMonitor(monitor) => {
+            let active_vt = monitor.read_active()?;
+            if program.active_vt != Some(active_vt) {
+                for setup in &mut program.setups {
+                    setup.vt_changed(program.active_vt, active_vt);
+                }
+                program.active_vt = Some(active_vt);
             }
             Ok(Action::Continue)
         },
@@ -326,14 +603,101 @@ fn handle_ready_file(program: &mut Program, index: FileIndex) -> Result<Action,
                 Ok(Action::Continue)
             }
         },
+        Pollable::IntrospectSocket(socket) => {
+            let clients = socket.accept().with_context_of(
+                || format!("While accepting connections to {}:", socket.path()),
+            )?;
+            for client in clients {
+                program.epoll.add_file(Pollable::IntrospectClient(client))?;
+            }
+            Ok(Action::Continue)
+        },
+        Pollable::IntrospectClient(client) => {
+            // Errors are not reported, because they are most likely caused by the client rather than by evsieve.
+            let (requests, is_closed) = match client.poll() {
+                Ok(Some(requests)) => (requests, false),
+                Ok(None) => (Vec::new(), true),
+                Err(_) => (Vec::new(), true),
+            };
+            let responses: Vec<String> = requests.iter()
+                .map(|request| request.respond(&introspection_result(program, &request.method)))
+                .collect();
+            if let Some(Pollable::IntrospectClient(client)) = program.epoll.get_mut(index) {
+                // This also gets called when the epoll reports that the client can accept pending responses.
+                let is_broken = client.flush().is_err()
+                    || responses.iter().any(|response| client.send(response).is_err());
+                // Makes the epoll report a HUP, after which the client is removed by handle_broken_file().
+                if is_closed || is_broken {
+                    client.shutdown();
+                    return Ok(Action::Continue);
+                }
+                let events = match client.has_pending_output() {
+                    true => libc::EPOLLIN | libc::EPOLLOUT,
+                    false => libc::EPOLLIN,
+                };
+                program.epoll.modify_events(index, events as u32)?;
+            }
+            Ok(Action::Continue)
+        },
         Pollable::ControlFifo(fifo) => {
             let commands = fifo.poll().with_context_of(
                 || format!("While polling commands from {}:", fifo.path()),
             )?;
+            let pipeline_index = program.fifo_pipelines.get(fifo.path()).copied().unwrap_or(0);
             for command in commands {
                 let CommandInfo { original_line, action } = command;
+                match action {
+                    Command::Revoke(filter) => {
+                        revoke_devices(program, pipeline_index, filter);
+                        continue;
+                    },
+                    Command::Reacquire(filter) => {
+                        reacquire_devices(program, pipeline_index, filter);
+                        continue;
+                    },
+                    Command::Send(events) => {
+                        send_events(program, pipeline_index, events)
+                            .with_context_of(|| format!("While executing the command \"{}\":", original_line))
+                            .print_err();
+                        continue;
+                    },
+                    Command::TimeScale(factor) => {
+                        set_time_scale(program, pipeline_index, factor)
+                            .with_context_of(|| format!("While executing the command \"{}\":", original_line))
+                            .print_err();
+                        continue;
+                    },
+                    Command::PersistStatus | Command::PersistRetry => {
+                        let request = match program.persist_subsystem.get(&mut program.epoll) {
+                            // Without a running subsystem, nothing is waiting to be reopened.
+                            None => {
+                                println!("No input devices are waiting to be reopened.");
+                                continue;
+                            },
+                            Some(interface) => match action {
+                                Command::PersistStatus => interface.request_status(),
+                                _ => interface.request_retry(),
+                            },
+                        };
+                        request.with_context_of(|| format!("While executing the command \"{}\":", original_line))
+                            .print_err();
+                        continue;
+                    },
+                    Command::Apply { at_stage, args } => {
+                        arguments::parser::apply(&mut program.setups[pipeline_index], at_stage.as_deref(), args, program.sandbox_mode)
+                            .with_context_of(|| format!("While executing the command \"{}\":", original_line))
+                            .print_err();
+                        continue;
+                    },
+                    _ => (),
+                }
 
-                action.execute(&mut program.setup)
+                let input_pipelines = &program.input_pipelines;
+                let input_devices = program.epoll.files().filter_map(|file| match file {
+                    Pollable::InputDevice(device) if pipeline_of_device(input_pipelines, device) == pipeline_index => Some(device),
+                    _ => None,
+                });
+                action.execute(&mut program.setups[pipeline_index], input_devices)
                     .with_context_of(|| format!("While executing the command \"{}\":", original_line))
                     .print_err();
             }
@@ -347,6 +711,104 @@ fn handle_ready_file(program: &mut Program, index: FileIndex) -> Result<Action,
     }
 }
 
+/// Closes all input devices of a pipeline whose domain matches the filter, so other programs can use them
+/// until the reacquire command is issued.
+fn revoke_devices(program: &mut Program, pipeline_index: usize, filter: Option<Domain>) {
+    let input_pipelines = &program.input_pipelines;
+    let indices: Vec<FileIndex> = program.epoll.indexed_files().filter_map(|(index, file)| match file {
+        Pollable::InputDevice(device) if pipeline_of_device(input_pipelines, device) == pipeline_index
+            && device.matches_domain_filter(filter) => Some(index),
+        _ => None,
+    }).collect();
+    if indices.is_empty() {
+        eprintln!("Warning: the revoke command did not match any open input devices.");
+    }
+
+    for index in indices {
+        let mut device = match program.epoll.remove(index) {
+            Some(Pollable::InputDevice(device)) => device,
+            _ => continue,
+        };
+        let now = evsieve::time::Instant::now();
+        let setup = setup_for_device(&mut program.setups, &program.input_pipelines, &device);
+        for release_event in device.release_pressed_keys() {
+            setup.run(now, release_event);
+        }
+        setup.syn();
+        run_status_event(program, &mut device, EventCode::DEVICE_LOST);
+        program.revoked_devices.push((pipeline_index, device.into_revoked_blueprint()));
+    }
+    update_fallback_groups(program);
+}
+
+/// Makes the --delay arguments of a pipeline wait `factor` times as long, and changes the speed at which
+/// the files of that pipeline get replayed accordingly.
+fn set_time_scale(program: &mut Program, pipeline_index: usize, factor: f64) -> Result<(), SystemError> {
+    program.setups[pipeline_index].set_time_scale(factor);
+    let input_pipelines = &program.input_pipelines;
+    for file in program.epoll.files_mut() {
+        if let Pollable::ReplayDevice(device) = file {
+            if input_pipelines.get(&device.domain()).copied().unwrap_or(0) == pipeline_index {
+                device.set_time_scale(factor)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Feeds events through a pipeline as if they were received from its input devices. Events without a
+/// domain are attributed to the only input device of the pipeline.
+fn send_events(program: &mut Program, pipeline_index: usize, events: Vec<(EventCode, EventValue, Option<Domain>)>) -> Result<(), ArgumentError> {
+    // Look up all devices before sending anything, so a command with an invalid domain has no effect.
+    let input_pipelines = &program.input_pipelines;
+    let (device_indices, target_domains): (Vec<FileIndex>, Vec<Domain>) = program.epoll.indexed_files().filter_map(|(index, file)| match file {
+        Pollable::InputDevice(device) if pipeline_of_device(input_pipelines, device) == pipeline_index => Some((index, device.target_domain())),
+        _ => None,
+    }).unzip();
+    let mut targets: Vec<(FileIndex, EventCode, EventValue)> = Vec::new();
+    for (code, value, domain) in events {
+        let device_index = device_indices[control_fifo::find_send_target(&target_domains, domain)?];
+        targets.push((device_index, code, value));
+    }
+
+    let now = evsieve::time::Instant::now();
+    let setup = &mut program.setups[pipeline_index];
+    setup.wakeup_until(now);
+    for (device_index, code, value) in targets {
+        if let Some(Pollable::InputDevice(device)) = program.epoll.get_mut(device_index) {
+            setup.run(now, device.synthesize_event(code, value));
+        }
+    }
+    setup.syn();
+    Ok(())
+}
+
+/// Reopens the input devices of a pipeline that were closed by the revoke command.
+fn reacquire_devices(program: &mut Program, pipeline_index: usize, filter: Option<Domain>) {
+    let (blueprints, remaining) = std::mem::take(&mut program.revoked_devices).into_iter()
+        .partition(|(blueprint_pipeline, blueprint)| *blueprint_pipeline == pipeline_index
+            && blueprint.pre_device.matches_domain_filter(filter));
+    program.revoked_devices = remaining;
+    let blueprints: Vec<(usize, Blueprint)> = blueprints;
+    if blueprints.is_empty() {
+        eprintln!("Warning: the reacquire command did not match any revoked input devices.");
+        return;
+    }
+
+    let interface = match program.persist_subsystem.require(&mut program.epoll) {
+        Some(interface) => interface,
+        None => {
+            eprintln!("Error: cannot reacquire devices: persistence subsystem not available.");
+            return;
+        }
+    };
+    for (_, blueprint) in blueprints {
+        interface.add_blueprint(blueprint)
+            .with_context("While trying to reacquire a revoked device:")
+            .print_err();
+    }
+}
+
 fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
     let broken_device = match program.epoll.remove(index) {
         Some(file) => file,
@@ -356,19 +818,32 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
         }
     };
     match broken_device {
+        Pollable::InputDevice(mut device) if device.is_revoked() => {
+            // Access to the device was revoked, which logind does when switching to another VT. The
+            // device still exists, so wait for access to be restored regardless of its persist mode.
+            eprintln!("Access to the device {} has been revoked. It will be reopened once access is restored.", device.path().display());
+
+            release_pressed_keys(&mut program.setups, &program.input_pipelines, &mut device);
+            run_status_event(program, &mut device, EventCode::DEVICE_LOST);
+
+            if let Some(interface) = program.persist_subsystem.require(&mut program.epoll) {
+                interface.add_blueprint(device.into_revoked_blueprint())
+                    .with_context("While trying to register a revoked device for reopening:")
+                    .print_err()
+            } else {
+                eprintln!("Internal error: cannot reopen device: persistence subsystem not available.")
+            }
+        },
         Pollable::InputDevice(mut device) => {
             eprintln!("The device {} has been disconnected.", device.path().display());
 
             // Release all keys that this device had pressed, so we don't end up with a key stuck on
-            // an output device.
-            let pressed_keys: Vec<EventCode> = device.get_pressed_keys().collect();
-            let now = crate::time::Instant::now();
-
-            for key_code in pressed_keys {
-                let release_event = device.synthesize_event(key_code, 0);
-                program.setup.run(now, release_event);
+            // an output device. If the device may come back, the hooks remember which keys it held first.
+            if let (PersistState::Reopen | PersistState::Full(_), false) = (device.persist_state(), device.is_standby()) {
+                setup_for_device(&mut program.setups, &program.input_pipelines, &device).suspend_hooks(&device);
             }
-            program.setup.syn();
+            release_pressed_keys(&mut program.setups, &program.input_pipelines, &mut device);
+            run_status_event(program, &mut device, EventCode::DEVICE_LOST);
 
             match device.persist_state() {
                 // Mode None: drop the device and carry on without it, if possible.
@@ -392,10 +867,25 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
         Pollable::ControlFifo(fifo) => {
             eprintln!("Error: the FIFO at {} is no longer available.", fifo.path());
         },
+        Pollable::IntrospectSocket(socket) => {
+            eprintln!("Error: the introspection socket at {} is no longer available.", socket.path());
+        },
+        // The client has disconnected.
+        Pollable::IntrospectClient(_client) => (),
+        Pollable::ReplayDevice(device) => {
+            eprintln!("Error: the replay of the events from {} has been aborted.", device.path().display());
+        },
+        Pollable::VtMonitor(_monitor) => {
+            eprintln!("Error: the active virtual terminal can no longer be determined. The --on-vt arguments will no longer have any effect.");
+        },
         Pollable::SignalFd(_fd) => {
             eprintln!("Fatal error: signal file descriptor broken.");
             return Action::Exit;
         },
+        Pollable::WakeupTimer(_timer) => {
+            eprintln!("Fatal error: the timer for scheduled events is broken.");
+            return Action::Exit;
+        },
         Pollable::PersistSubsystem(mut interface) => {
             eprintln!("Internal error: the persistence subsystem has broken. Evsieve may fail to open devices specified with the persist flag.");
             let _ = interface.request_shutdown();
@@ -403,6 +893,9 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
         },
     }
 
+    // If the active member of a fallback group broke, another member takes over.
+    update_fallback_groups(program);
+
     if has_no_activity(&program.epoll) {
         println!("No devices to poll events from. Evsieve will exit now.");
         Action::Exit
@@ -411,6 +904,65 @@ fn handle_broken_file(program: &mut Program, index: FileIndex) -> Action {
     }
 }
 
+/// Sends release events for all keys that a device has pressed through the stream. Does nothing for devices
+/// on standby, because their events never entered the stream.
+fn release_pressed_keys(setups: &mut [Setup<UInputSystem>], input_pipelines: &HashMap<Domain, usize>, device: &mut InputDevice) {
+    if device.is_standby() {
+        return;
+    }
+    let now = Instant::now();
+    let setup = setup_for_device(setups, input_pipelines, device);
+    for release_event in device.release_pressed_keys() {
+        setup.run(now, release_event);
+    }
+    setup.syn();
+}
+
+/// Makes sure that of each fallback group, only the open member with the highest priority is active.
+/// Members that lose their active status release their keys, so no keys get stuck.
+fn update_fallback_groups(program: &mut Program) {
+    let input_pipelines = &program.input_pipelines;
+    let mut best_members: HashMap<(usize, &str), (FileIndex, &FallbackMembership)> = HashMap::new();
+    for (index, file) in program.epoll.indexed_files() {
+        let (device, membership) = match file {
+            Pollable::InputDevice(device) => match device.fallback() {
+                Some(membership) => (device, membership),
+                None => continue,
+            },
+            _ => continue,
+        };
+        let group_key = (pipeline_of_device(input_pipelines, device), membership.group.as_str());
+        match best_members.get(&group_key) {
+            Some((_, best_membership)) if best_membership.rank() >= membership.rank() => (),
+            _ => { best_members.insert(group_key, (index, membership)); },
+        }
+    }
+    let active_members: HashSet<FileIndex> = best_members.values().map(|&(index, _)| index).collect();
+
+    let member_indices: Vec<FileIndex> = program.epoll.indexed_files()
+        .filter_map(|(index, file)| match file {
+            Pollable::InputDevice(device) if device.fallback().is_some() => Some(index),
+            _ => None,
+        })
+        .collect();
+    for index in member_indices {
+        let device = match program.epoll.get_mut(index) {
+            Some(Pollable::InputDevice(device)) => device,
+            _ => continue,
+        };
+        let should_be_standby = ! active_members.contains(&index);
+        if device.is_standby() == should_be_standby {
+            continue;
+        }
+        if should_be_standby {
+            release_pressed_keys(&mut program.setups, &program.input_pipelines, device);
+        } else if let Some(membership) = device.fallback() {
+            println!("The device {} is now the active device of the fallback group \"{}\".", device.path().display(), membership.group);
+        }
+        device.set_standby(should_be_standby);
+    }
+}
+
 fn handle_persist_subsystem_report(program: &mut Program, index: FileIndex, report: Report) -> Action {
     match report {
         Report::Shutdown => {
@@ -418,6 +970,10 @@ fn handle_persist_subsystem_report(program: &mut Program, index: FileIndex, repo
             program.persist_subsystem.mark_as_shutdown();
             Action::Continue
         },
+        Report::Status(status) => {
+            println!("{}", status.describe());
+            Action::Continue
+        },
         Report::BlueprintDropped => {
             if has_no_activity(&program.epoll) {
                 println!("No devices remaining that can possibly generate events. Evsieve will exit now.");
@@ -435,7 +991,11 @@ fn handle_persist_subsystem_report(program: &mut Program, index: FileIndex, repo
             }
 
             let device_path = device.path().to_owned();
-            program.setup.update_caps(&device);
+            let setup = setup_for_device(&mut program.setups, &program.input_pipelines, &device);
+            setup.update_caps(&device);
+            setup.update_leds(&device);
+            setup.restore_hooks(&device);
+            run_status_event(program, &mut device, EventCode::DEVICE_CONNECTED);
 
             match program.epoll.add_file(Pollable::InputDevice(device))
             {
@@ -444,20 +1004,107 @@ fn handle_persist_subsystem_report(program: &mut Program, index: FileIndex, repo
                     error.with_context("While adding a newly opened device to the epoll:").print_err();
                 },
             }
+            update_fallback_groups(program);
 
             Action::Continue
         }
     }
 }
 
+/// Sends an evsieve:device-* event on behalf of a device through the stream. Such events are always
+/// immediately followed by their release, so they behave like a key being tapped.
+fn run_status_event(program: &mut Program, device: &mut InputDevice, code: EventCode) {
+    let now = Instant::now();
+    let setup = setup_for_device(&mut program.setups, &program.input_pipelines, device);
+    for value in &[1, 0] {
+        let event = device.synthesize_event(code, *value);
+        setup.run(now, event);
+        setup.syn();
+    }
+}
+
 /// Returns true if evsieve has nothing to do and should just exit.
+/// Returns the JSON-encoded result of a method requested through an introspection socket, or an empty
+/// string if the request was invalid.
+fn introspection_result(program: &Program, method: &Result<Method, (i32, String)>) -> String {
+    match method {
+        Ok(Method::Ecodes) => introspect::ecodes_result(),
+        Ok(Method::Arguments) => introspect::arguments_result(&program.pipeline_arguments),
+        Ok(Method::Devices) => introspect::devices_result(&describe_devices(program)),
+        Ok(Method::Features) => arguments::feature_info::feature_info(arguments::parser::VERSION.unwrap_or("unknown")),
+        Err(_) => String::new(),
+    }
+}
+
+/// Lists the input devices that are opened or revoked, for the devices method of the introspection sockets.
+fn describe_devices(program: &Program) -> Vec<DeviceInfo> {
+    let mut devices: Vec<DeviceInfo> = program.epoll.files().filter_map(|file| match file {
+        Pollable::InputDevice(device) => Some(DeviceInfo {
+            path: device.path().display().to_string(),
+            name: Some(device.name().to_string_lossy().into_owned()),
+            domain: domain::try_reverse_resolve(device.target_domain()),
+            pipeline: pipeline_of_device(&program.input_pipelines, device),
+            state: match device.is_standby() {
+                true => DeviceState::Standby,
+                false => DeviceState::Active,
+            },
+        }),
+        Pollable::ReplayDevice(device) => Some(DeviceInfo {
+            path: device.path().display().to_string(),
+            name: None,
+            domain: domain::try_reverse_resolve(device.target_domain()),
+            pipeline: program.input_pipelines.get(&device.domain()).copied().unwrap_or(0),
+            state: DeviceState::Replay,
+        }),
+        _ => None,
+    }).collect();
+    devices.extend(program.revoked_devices.iter().map(
+        |(pipeline_index, blueprint)| DeviceInfo::revoked(*pipeline_index, blueprint)
+    ));
+    devices
+}
+
+/// Formats the contents of the --status-file.
+fn describe_status(program: &Program) -> String {
+    // Lists the devices in the same order as `describe_devices()`: first the files in the epoll, then
+    // the revoked devices.
+    let events_read = program.epoll.files().filter_map(|file| match file {
+        Pollable::InputDevice(device) => Some(Some(device.stats().events_read)),
+        Pollable::ReplayDevice(_) => Some(None),
+        _ => None,
+    }).chain(std::iter::repeat(None));
+    let devices: Vec<DeviceStatus> = describe_devices(program).into_iter().zip(events_read)
+        .map(|(info, events_read)| DeviceStatus { info, events_read })
+        .collect();
+
+    let mut toggles: Vec<ToggleStatus> = Vec::new();
+    for (pipeline, setup) in program.setups.iter().enumerate() {
+        let mut ids: Vec<&String> = setup.toggle_indices().keys().collect();
+        ids.sort();
+        for id in ids {
+            let toggle = &setup.state()[setup.toggle_indices()[id]];
+            toggles.push(ToggleStatus {
+                id: id.clone(),
+                pipeline,
+                value: toggle.value() + 1,
+                size: toggle.size(),
+                name: toggle.current_name().map(str::to_owned),
+            });
+        }
+    }
+
+    status::format_status(std::process::id(), &devices, &toggles)
+}
+
 fn has_no_activity(epoll: &Epoll<Pollable>) -> bool {
     for file in epoll.files() {
         match file {
             Pollable::InputDevice(_) => return false,
             Pollable::PersistSubsystem(_) => return false,
             Pollable::ControlFifo(_) => return false,
-            Pollable::SignalFd(_) => (),
+            Pollable::ReplayDevice(_) => return false,
+            Pollable::SignalFd(_) | Pollable::VtMonitor(_) | Pollable::WakeupTimer(_)
+                | Pollable::IntrospectSocket(_) | Pollable::IntrospectClient(_) => (),
         }
     }
     true
@@ -4,6 +4,9 @@ use crate::io::input::{InputDevice, InputDeviceName};
 use crate::predevice::PreInputDevice;
 use crate::capability::Capabilities;
 use crate::error::SystemError;
+use std::fs::OpenOptions;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::Path;
 
 /// Represents something can can be used to re-open a closed input device.
 pub struct Blueprint {
@@ -13,6 +16,9 @@ pub struct Blueprint {
     /// if the input device ends up having a different name than specified here, it is just cause to issue
     /// a warning to help find problematic setups.
     pub name: Option<InputDeviceName>,
+    /// If true, access to the device was revoked rather than the device being disconnected. Lacking the
+    /// permission to open it is then not an error, because the permission may be restored later.
+    pub revoked: bool,
 }
 
 pub enum TryOpenBlueprintResult {
@@ -30,6 +36,9 @@ impl Blueprint {
         if ! self.pre_device.path.exists() {
             return TryOpenBlueprintResult::NotOpened(self);
         }
+        if self.revoked && ! is_readable(&self.pre_device.path) {
+            return TryOpenBlueprintResult::NotOpened(self);
+        }
         let input_device = match InputDevice::open(self.pre_device) {
             Ok(device) => device,
             Err((pre_device, error)) => {
@@ -37,6 +46,7 @@ impl Blueprint {
                     pre_device,
                     capabilities: self.capabilities,
                     name: self.name,
+                    revoked: self.revoked,
                 }, error);
             },
         };
@@ -63,4 +73,12 @@ impl Blueprint {
         
         TryOpenBlueprintResult::Success(input_device)
     }
-}
\ No newline at end of file
+}
+
+/// Returns false if opening the given path fails because of a lack of permissions.
+fn is_readable(path: &Path) -> bool {
+    match OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC).open(path) {
+        Ok(_) => true,
+        Err(error) => error.kind() != std::io::ErrorKind::PermissionDenied,
+    }
+}
@@ -32,7 +32,8 @@ impl Inotify {
             libc::inotify_add_watch(
                 self.fd,
                 cstr.as_ptr(),
-                libc::IN_CREATE | libc::IN_MOVED_TO
+                // IN_ATTRIB notices the permissions of revoked devices being restored.
+                libc::IN_CREATE | libc::IN_MOVED_TO | libc::IN_ATTRIB
             )
         };
         if watch < 0 {
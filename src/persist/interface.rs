@@ -21,6 +21,17 @@ impl HostInterface {
         self.commander.send(Command::AddBlueprint(blueprint))
     }
 
+    /// Asks the subsystem to report which devices it is waiting for. The answer arrives later as
+    /// Report::Status.
+    pub fn request_status(&mut self) -> Result<(), SystemError> {
+        self.commander.send(Command::Status)
+    }
+
+    /// Asks the subsystem to immediately try to reopen all blueprints.
+    pub fn request_retry(&mut self) -> Result<(), SystemError> {
+        self.commander.send(Command::Retry)
+    }
+
     /// Asks the subsystem to start shutting down. Does not wait until it has actually shut down.
     pub fn request_shutdown(&mut self) -> Result<(), SystemError> {
         self.commander.send(Command::Shutdown)
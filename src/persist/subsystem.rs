@@ -26,6 +26,11 @@ use std::os::unix::io::{AsRawFd, RawFd};
 pub enum Command {
     /// Requests this subsystem to try to reopen this blueprint.
     AddBlueprint(Blueprint),
+    /// Requests this subsystem to report which devices it is waiting for.
+    Status,
+    /// Requests this subsystem to try to open all blueprints right now, even if nothing changed in the
+    /// watched directories.
+    Retry,
     /// Requests this subsystem to halt.
     Shutdown,
 }
@@ -37,11 +42,36 @@ pub enum Report {
     DeviceOpened(InputDevice),
     /// A blueprint has been deemed unopenable and has been dropped.
     BlueprintDropped,
+    /// The answer to Command::Status.
+    Status(PersistStatus),
     /// This subsystem has shut down or almost shut down. There are no ongoing processes or destructors
     /// left to run that could cause trouble if the program were to exit() now.
     Shutdown,
 }
 
+/// A snapshot of what the persistence subsystem is waiting for.
+pub struct PersistStatus {
+    /// The paths of the devices that have not been reopened yet.
+    pub pending_devices: Vec<PathBuf>,
+    /// The directories that are watched for those devices to show up.
+    pub watched_directories: Vec<String>,
+}
+
+impl PersistStatus {
+    /// Returns a human-readable description of this status, one fact per line.
+    pub fn describe(&self) -> String {
+        if self.pending_devices.is_empty() {
+            return "No input devices are waiting to be reopened.".to_owned();
+        }
+        let mut lines: Vec<String> = self.pending_devices.iter()
+            .map(|path| format!("Waiting for the device {} to become available.", path.display()))
+            .collect();
+        lines.extend(self.watched_directories.iter()
+            .map(|directory| format!("Watching the directory {}.", directory)));
+        lines.join("\n")
+    }
+}
+
 enum Pollable {
     Command(Receiver<Command>),
     Daemon(Daemon),
@@ -68,6 +98,10 @@ pub fn launch() -> Result<HostInterface, SystemError> {
     let (mut comm_out, reporter) = internal_pipe::channel()?;
 
     let join_handle = std::thread::spawn(move || {
+        // Reopening devices is not latency-sensitive, so this thread should not compete with the main
+        // thread if the main thread has realtime priority as requested by --realtime.
+        crate::realtime::reset_thread_priority();
+
         // Asserting unwind safety for Sender. My reasons for this are a bit wobbly, but I looked at
         // its source and all visible actions it takes appear to be atomic, e.g. a message is either sent
         // or not. I can't think of a scenario where a panic at any point could violate safety.
@@ -99,6 +133,14 @@ fn start_worker(comm_in: Receiver<Command>, comm_out: &mut Sender<Report>) -> Re
         for command in commands {
             match command {
                 Command::Shutdown => return Ok(()),
+                Command::Status => match &mut epoll[daemon_index] {
+                    Pollable::Daemon(daemon) => reports.push(Report::Status(daemon.status())),
+                    _ => unreachable!(),
+                },
+                Command::Retry => match &mut epoll[daemon_index] {
+                    Pollable::Daemon(daemon) => try_open_and_report(daemon, &mut reports)?,
+                    _ => unreachable!(),
+                },
                 Command::AddBlueprint(blueprint) => match &mut epoll[daemon_index] {
                     Pollable::Daemon(daemon) => {
                         daemon.add_blueprint(blueprint)?;
@@ -186,6 +228,17 @@ impl Daemon {
         Ok(())
     }
 
+    pub fn status(&self) -> PersistStatus {
+        let mut watched_directories: Vec<String> = self.inotify.watched_paths().cloned().collect();
+        watched_directories.sort_unstable();
+        PersistStatus {
+            pending_devices: self.blueprints.iter()
+                .map(|blueprint| blueprint.pre_device.path.clone())
+                .collect(),
+            watched_directories,
+        }
+    }
+
     /// Does nothing but clearing out the queued events. Call Daemon::try_open() to try to actually
     /// open the associated blueprints.
     pub fn poll(&mut self) -> Result<(), SystemError> {
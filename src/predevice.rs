@@ -1,8 +1,10 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::capability::Capabilities;
+use crate::io::input::{ReadFilter, RepeatSetting, StaleSetting};
 use crate::persist::storage::DeviceCache;
 use crate::{domain::Domain, arguments::output::DeviceProperties};
+use std::os::unix::io::RawFd;
 use std::path::{PathBuf, Path};
 
 /// Represents whether and how the user has requested the device to be grabbed.
@@ -53,6 +55,34 @@ impl PersistState {
     }
 }
 
+/// Set through the open-mode= clause on --input arguments.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OpenMode {
+    /// Open the event device for reading only.
+    ReadOnly,
+    /// Open the event device for both reading and writing.
+    ReadWrite,
+}
+
+/// Set through the group= and priority= clauses on --input arguments. Of all open input devices in the
+/// same fallback group, only the one with the highest priority is active. The events of the others are
+/// dropped until the active device disappears.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct FallbackMembership {
+    pub group: String,
+    pub priority: i32,
+    /// The position of the device among all input devices of its pipeline. If multiple devices of the
+    /// same group have the same priority, the one that was specified first is preferred.
+    pub order: usize,
+}
+
+impl FallbackMembership {
+    /// The active member of a group is the one with the highest rank.
+    pub fn rank(&self) -> (i32, std::cmp::Reverse<usize>) {
+        (self.priority, std::cmp::Reverse(self.order))
+    }
+}
+
 pub struct PreInputDevice {
     /// The path to this device.
     pub path: PathBuf,
@@ -62,6 +92,37 @@ pub struct PreInputDevice {
     pub grab_mode: GrabMode,
     /// What should be done if the device is disconnected while running.
     pub persist_state: PersistState,
+    /// If Some, events generated by libevdev to resynchronise the device state after a SYN_DROPPED
+    /// shall have this domain instead of `domain`.
+    pub resync_domain: Option<Domain>,
+    /// If Some, this device shall not be opened from `path`, but from this file descriptor that was
+    /// inherited from the parent process. In that case, `path` is only used for display purposes.
+    pub inherited_fd: Option<RawFd>,
+    /// The domain that the events of this device have after they enter the stream.
+    pub target_domain: Domain,
+    /// If Some, the autorepeat settings of this device shall be changed when it is opened.
+    pub repeat_setting: Option<RepeatSetting>,
+    /// If Some, the pressed keys of this device shall be released if it stays silent for too long.
+    pub stale_setting: Option<StaleSetting>,
+    /// If Some, all events that this filter does not accept shall be dropped as soon as they are read.
+    pub read_filter: Option<ReadFilter>,
+    pub open_mode: OpenMode,
+    /// If true, evsieve shall never change anything about this device, such as its grab state or its
+    /// autorepeat settings.
+    pub observe_only: bool,
+    /// If Some, this device is a member of a fallback group.
+    pub fallback: Option<FallbackMembership>,
+    /// If true, the events of this device shall enter the stream one packet at a time.
+    pub packets: bool,
+}
+
+impl PreInputDevice {
+    /// Returns whether the domain filter of a control command like "revoke @kb" applies to this device.
+    /// The filter is compared against the domain that the user assigned to the device, not against the
+    /// unique domain that its events have before entering the stream.
+    pub fn matches_domain_filter(&self, filter: Option<Domain>) -> bool {
+        filter.map_or(true, |filter| crate::domain::matches(self.target_domain, filter))
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq)]
@@ -74,6 +135,36 @@ pub enum RepeatMode {
     Passive,
 }
 
+/// Decides what happens to events written to an output device while its queue is full, which happens if
+/// the kernel does not accept events as fast as evsieve writes them.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// Drop the oldest event in the queue to make room for the new one.
+    DropOldest,
+    /// Drop the new event.
+    DropNewest,
+    /// Wait until the kernel accepts events again, stalling the whole pipeline in the meantime. Falls
+    /// back to dropping the oldest event if the kernel does not accept events for too long.
+    Block,
+}
+
+/// Set through the overflow= and queue-size= clauses on --output devices.
+#[derive(Clone, Copy)]
+pub struct OverflowSetting {
+    pub policy: OverflowPolicy,
+    /// The maximum amount of events that can wait to be written to the device.
+    pub queue_size: usize,
+}
+
+impl Default for OverflowSetting {
+    fn default() -> OverflowSetting {
+        OverflowSetting {
+            policy: OverflowPolicy::Block,
+            queue_size: 1024,
+        }
+    }
+}
+
 pub struct PreOutputDevice {
     /// All events with this domain shall be written to this device.
     pub domain: Domain,
@@ -83,4 +174,41 @@ pub struct PreOutputDevice {
     pub repeat_mode: RepeatMode,
     /// Properties visible to other programs on the system.
     pub properties: DeviceProperties,
+    /// If Some, the device shall be created on this /dev/uinput file descriptor that was inherited
+    /// from the parent process instead of opening /dev/uinput ourselves.
+    pub uinput_fd: Option<RawFd>,
+    /// If true, the capabilities of this device are computed separately for each combination of
+    /// toggle states, instead of assuming that each toggle can route events anywhere.
+    pub strict_caps: bool,
+    /// If Some, this device copies the identity of some input devices, as requested by mirror-of=.
+    pub mirror: Option<MirrorSetting>,
+    pub overflow: OverflowSetting,
+}
+
+/// Set through the mirror-of= clause on --output devices.
+pub struct MirrorSetting {
+    /// The domains of the events of the mirrored input devices before they enter the stream.
+    pub sources: Vec<Domain>,
+    /// Whether the name of the mirrored device shall be used instead of the name of the output device.
+    pub copy_name: bool,
+}
+
+impl PreOutputDevice {
+    /// Copies the name, IDs and input properties of the given device, except for those that the user
+    /// has explicitly specified for this device.
+    pub fn adopt_identity(&mut self, identity: &DeviceProperties) {
+        let copy_name = match &self.mirror {
+            Some(mirror) => mirror.copy_name,
+            None => return,
+        };
+        if copy_name {
+            self.properties.name = identity.name.clone();
+        }
+        self.properties.device_id = self.properties.device_id.or(identity.device_id);
+        self.properties.version = self.properties.version.or(identity.version);
+        self.properties.bus = self.properties.bus.or(identity.bus);
+        if self.properties.input_props.is_empty() {
+            self.properties.input_props = identity.input_props.clone();
+        }
+    }
 }
\ No newline at end of file
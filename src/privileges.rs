@@ -0,0 +1,253 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --run-as argument, which makes evsieve switch to another user and group after
+//! it has opened all devices it needs. The already opened file descriptors remain usable.
+
+use std::ffi::{CStr, CString};
+use std::path::PathBuf;
+
+use crate::error::{ArgumentError, Context, SystemError};
+
+/// The user and group that evsieve should switch to.
+pub struct RunAs {
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    /// The name of the user, if the user exists in the user database. Used to look up the supplementary
+    /// groups of the user and to update the environment of subprocesses.
+    user_name: Option<CString>,
+    home: Option<PathBuf>,
+}
+
+/// The information about a user that we care about from the user database.
+struct UserEntry {
+    name: CString,
+    uid: libc::uid_t,
+    gid: libc::gid_t,
+    home: PathBuf,
+}
+
+impl RunAs {
+    /// Interprets a string like "user", "user:group", "1000" or "1000:1000".
+    pub fn parse(spec: &str) -> Result<RunAs, ArgumentError> {
+        let (user_str, group_str) = crate::utils::split_once(spec, ":");
+        if user_str.is_empty() {
+            return Err(ArgumentError::new("No user has been provided to --run-as."));
+        }
+
+        // Numeric user IDs are allowed even if they do not exist in the user database.
+        let (uid, user_entry) = match user_str.parse::<libc::uid_t>() {
+            Ok(uid) => (uid, lookup_user(|| unsafe { libc::getpwuid(uid) })),
+            Err(_) => {
+                let user_cstring = CString::new(user_str)
+                    .map_err(|_| ArgumentError::new("The user name cannot contain null bytes."))?;
+                match lookup_user(|| unsafe { libc::getpwnam(user_cstring.as_ptr()) }) {
+                    Some(entry) => (entry.uid, Some(entry)),
+                    None => return Err(ArgumentError::new(format!("The user \"{}\" does not exist.", user_str))),
+                }
+            },
+        };
+
+        let gid = match group_str {
+            Some(group_str) => parse_group(group_str)?,
+            None => match &user_entry {
+                Some(entry) => entry.gid,
+                None => return Err(ArgumentError::new(format!(
+                    "The user ID {} does not exist in the user database, so its group cannot be determined. Please specify a group using --run-as {}:GROUP.",
+                    uid, uid
+                ))),
+            }
+        };
+
+        if uid == 0 || gid == 0 {
+            return Err(ArgumentError::new("The --run-as argument cannot be used to run evsieve as root."));
+        }
+
+        Ok(RunAs {
+            uid, gid,
+            home: user_entry.as_ref().map(|entry| entry.home.clone()),
+            user_name: user_entry.map(|entry| entry.name),
+        })
+    }
+
+    /// Irreversibly switches this process to the configured user and group. Also changes the
+    /// environment so subprocesses spawned afterwards see the new user.
+    pub fn drop_privileges(&self) -> Result<(), SystemError> {
+        unsafe {
+            // The supplementary groups must be changed before the user, otherwise we lack the
+            // privileges to do so.
+            let res = match &self.user_name {
+                Some(name) => libc::initgroups(name.as_ptr(), self.gid),
+                None => libc::setgroups(1, &self.gid),
+            };
+            if res < 0 {
+                return Err(SystemError::os_with_context("While trying to change the supplementary groups:"));
+            }
+            if libc::setresgid(self.gid, self.gid, self.gid) < 0 {
+                return Err(SystemError::os_with_context(format!("While trying to change the group to {}:", self.gid)));
+            }
+            if libc::setresuid(self.uid, self.uid, self.uid) < 0 {
+                return Err(SystemError::os_with_context(format!("While trying to change the user to {}:", self.uid)));
+            }
+
+            // Make sure that the privileges have actually been dropped and cannot be regained.
+            if libc::setuid(0) == 0 || libc::geteuid() != self.uid || libc::getegid() != self.gid {
+                return Err(SystemError::new("Failed to drop privileges."));
+            }
+        }
+
+        if let Some(name) = &self.user_name {
+            let name = name.to_string_lossy();
+            std::env::set_var("USER", name.as_ref());
+            std::env::set_var("LOGNAME", name.as_ref());
+        }
+        if let Some(home) = &self.home {
+            std::env::set_var("HOME", home);
+        }
+
+        Ok(())
+    }
+}
+
+/// The user and group that should own a file created by evsieve, like a control FIFO. Either of them
+/// may be left unchanged.
+#[derive(Clone, Copy)]
+pub struct FileOwner {
+    uid: Option<libc::uid_t>,
+    gid: Option<libc::gid_t>,
+}
+
+impl FileOwner {
+    /// Interprets a string like "user", "user:group" or ":group".
+    pub fn parse(spec: &str) -> Result<FileOwner, ArgumentError> {
+        let (user_str, group_str) = crate::utils::split_once(spec, ":");
+        let uid = match user_str {
+            "" => None,
+            _ => Some(match user_str.parse::<libc::uid_t>() {
+                Ok(uid) => uid,
+                Err(_) => {
+                    let user_cstring = CString::new(user_str)
+                        .map_err(|_| ArgumentError::new("The user name cannot contain null bytes."))?;
+                    match lookup_user(|| unsafe { libc::getpwnam(user_cstring.as_ptr()) }) {
+                        Some(entry) => entry.uid,
+                        None => return Err(ArgumentError::new(format!("The user \"{}\" does not exist.", user_str))),
+                    }
+                },
+            }),
+        };
+        let gid = match group_str {
+            Some(group_str) if !group_str.is_empty() => Some(parse_group(group_str)?),
+            _ => None,
+        };
+        if uid.is_none() && gid.is_none() {
+            return Err(ArgumentError::new("No user or group has been provided."));
+        }
+        Ok(FileOwner { uid, gid })
+    }
+
+    /// Changes the owner of the file at the given path.
+    pub fn apply(&self, path: &str) -> Result<(), SystemError> {
+        let path_cstring = CString::new(path)
+            .map_err(|_| std::io::Error::new(std::io::ErrorKind::InvalidInput, "Path may not contain any NUL bytes."))?;
+        // Passing -1 to chown() leaves the corresponding ID unchanged.
+        let uid = self.uid.unwrap_or(libc::uid_t::MAX);
+        let gid = self.gid.unwrap_or(libc::gid_t::MAX);
+        if unsafe { libc::chown(path_cstring.as_ptr(), uid, gid) } < 0 {
+            return Err(SystemError::os_with_context(format!("While trying to change the owner of {}:", path)));
+        }
+        Ok(())
+    }
+}
+
+/// Creates a file like a control FIFO or socket at `path` by calling `create`, and then gives it the
+/// requested owner and mode. If a mode was requested, the file is created under a umask that denies
+/// access to everyone but its owner, so nobody else can open it before the mode has been applied.
+pub fn create_with_owner_and_mode<T>(
+    path: &str,
+    owner: Option<FileOwner>,
+    mode: Option<u32>,
+    create: impl FnOnce() -> Result<T, SystemError>,
+) -> Result<T, SystemError> {
+    let file = match mode {
+        Some(_) => {
+            let old_umask = unsafe { libc::umask(0o077) };
+            let result = create();
+            unsafe { libc::umask(old_umask) };
+            result?
+        },
+        None => create()?,
+    };
+
+    // The owner is changed first, because changing the owner may clear the setgid bit.
+    if let Some(owner) = owner {
+        owner.apply(path)?;
+    }
+    if let Some(mode) = mode {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .map_err(SystemError::from)
+            .with_context_of(|| format!("While trying to change the permissions of {}:", path))?;
+    }
+    Ok(file)
+}
+
+/// Calls `getter`, which must be either getpwnam() or getpwuid(), and copies the result if it is found.
+fn lookup_user(getter: impl Fn() -> *mut libc::passwd) -> Option<UserEntry> {
+    use std::os::unix::ffi::OsStrExt;
+    unsafe {
+        let passwd = getter();
+        if passwd.is_null() {
+            return None;
+        }
+        Some(UserEntry {
+            name: CStr::from_ptr((*passwd).pw_name).to_owned(),
+            uid: (*passwd).pw_uid,
+            gid: (*passwd).pw_gid,
+            home: std::ffi::OsStr::from_bytes(CStr::from_ptr((*passwd).pw_dir).to_bytes()).into(),
+        })
+    }
+}
+
+/// Interprets a group name or group ID.
+fn parse_group(group_str: &str) -> Result<libc::gid_t, ArgumentError> {
+    if let Ok(gid) = group_str.parse::<libc::gid_t>() {
+        return Ok(gid);
+    }
+    let group_cstring = CString::new(group_str)
+        .map_err(|_| ArgumentError::new("The group name cannot contain null bytes."))?;
+    let group = unsafe { libc::getgrnam(group_cstring.as_ptr()) };
+    if group.is_null() {
+        return Err(ArgumentError::new(format!("The group \"{}\" does not exist.", group_str)));
+    }
+    Ok(unsafe { (*group).gr_gid })
+}
+
+#[test]
+fn unittest() {
+    let run_as = RunAs::parse("12345:23456").unwrap();
+    assert_eq!(run_as.uid, 12345);
+    assert_eq!(run_as.gid, 23456);
+    assert!(RunAs::parse("0:0").is_err());
+    assert!(RunAs::parse(":100").is_err());
+
+    let owner = FileOwner::parse("12345").unwrap();
+    assert_eq!((owner.uid, owner.gid), (Some(12345), None));
+    let owner = FileOwner::parse(":23456").unwrap();
+    assert_eq!((owner.uid, owner.gid), (None, Some(23456)));
+    let owner = FileOwner::parse("0:0").unwrap();
+    assert_eq!((owner.uid, owner.gid), (Some(0), Some(0)));
+    assert!(FileOwner::parse(":").is_err());
+    assert!(FileOwner::parse("").is_err());
+
+    use std::os::unix::fs::PermissionsExt;
+    let path = std::env::temp_dir().join(format!("evsieve-privileges-test-{}", std::process::id()));
+    let path = path.to_str().unwrap().to_owned();
+    let _ = std::fs::remove_file(&path);
+    let mode_during_creation = create_with_owner_and_mode(&path, None, Some(0o660), || {
+        std::fs::File::create(&path)?;
+        Ok(std::fs::metadata(&path)?.permissions().mode() & 0o777)
+    }).unwrap();
+    let mode_after_creation = std::fs::metadata(&path).unwrap().permissions().mode() & 0o777;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(mode_during_creation & 0o077, 0);
+    assert_eq!(mode_after_creation, 0o660);
+}
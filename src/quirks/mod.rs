@@ -0,0 +1,48 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Quirks are built-in translation modules for devices that report their events in ways that
+//! cannot be expressed using the generic arguments, usually because the translation requires
+//! state. Each quirk is enabled with a `--quirk NAME` argument.
+//!
+//! To add a quirk for another device, implement the `Quirk` trait in a new module in this
+//! directory and add an entry for it to `QUIRKS`.
+
+pub mod touchpad;
+
+use crate::capability::Capability;
+use crate::error::ArgumentError;
+use crate::event::Event;
+
+pub trait Quirk {
+    /// Translates a single event. Only called for events that match the keys of the --quirk
+    /// argument; all other events bypass the quirk. Events that should be left unchanged must
+    /// be written to `output_events` as-is.
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>);
+
+    /// Writes the capabilities of all events that `apply()` may output when it receives an
+    /// event that matches `cap`. Analogous to `Map::apply_to_all_caps()`.
+    fn apply_to_cap(&self, cap: Capability, output_caps: &mut Vec<Capability>);
+}
+
+struct QuirkInfo {
+    name: &'static str,
+    create: fn() -> Result<Box<dyn Quirk>, ArgumentError>,
+}
+
+/// All quirks that can be enabled through --quirk.
+const QUIRKS: &[QuirkInfo] = &[
+    QuirkInfo { name: "dualshock-touchpad", create: touchpad::TouchpadToMouse::create },
+    QuirkInfo { name: "dualsense-touchpad", create: touchpad::TouchpadToMouse::create },
+];
+
+/// Returns the quirk with the given name, or an error listing all known quirks if it does not exist.
+pub fn create(name: &str) -> Result<Box<dyn Quirk>, ArgumentError> {
+    match QUIRKS.iter().find(|quirk| quirk.name == name) {
+        Some(quirk) => (quirk.create)(),
+        None => Err(ArgumentError::new(format!(
+            "Unknown quirk: \"{}\". The following quirks are available: {}",
+            name,
+            QUIRKS.iter().map(|quirk| quirk.name).collect::<Vec<_>>().join(", "),
+        ))),
+    }
+}
@@ -0,0 +1,133 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! The touchpads of the DualShock 4 and DualSense controllers show up as separate event devices
+//! that report the absolute position of the fingers. This quirk turns them into a mouse by
+//! translating finger movement into relative movement, while leaving the click button as-is.
+
+use std::collections::HashMap;
+
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::ecodes;
+use crate::error::ArgumentError;
+use crate::event::{Event, EventCode};
+use crate::range::{Interval, Set};
+
+use super::Quirk;
+
+const DROPPED_ABS_CODES: &[&str] = &[
+    "mt_slot", "mt_touch_major", "mt_touch_minor", "mt_width_major", "mt_width_minor",
+    "mt_orientation", "mt_position_x", "mt_position_y", "mt_tool_type", "mt_blob_id",
+    "mt_tracking_id", "mt_pressure", "mt_distance", "mt_tool_x", "mt_tool_y",
+];
+const DROPPED_BTN_CODES: &[&str] = &[
+    "touch", "tool_finger", "tool_doubletap", "tool_tripletap", "tool_quadtap", "tool_quinttap",
+];
+
+/// What we know about the finger on a single touchpad.
+#[derive(Default)]
+struct TouchState {
+    touching: bool,
+    /// The last known position of the finger. Reset when the finger lands, because the first position
+    /// reported afterwards is where the finger landed rather than a movement. The kernel reports
+    /// BTN_TOUCH before the position of the finger, so the landing position is never mistaken for
+    /// a movement.
+    x: Option<i32>,
+    y: Option<i32>,
+}
+
+pub struct TouchpadToMouse {
+    abs_x: EventCode,
+    abs_y: EventCode,
+    rel_x: EventCode,
+    rel_y: EventCode,
+    btn_touch: EventCode,
+    /// The multitouch and tool events that have no use on a mouse.
+    dropped_codes: Vec<EventCode>,
+
+    /// Every domain is considered to be a separate touchpad.
+    states: HashMap<Domain, TouchState>,
+}
+
+impl TouchpadToMouse {
+    pub fn create() -> Result<Box<dyn Quirk>, ArgumentError> {
+        let dropped_codes = DROPPED_ABS_CODES.iter().map(|name| ecodes::event_code("abs", name))
+            .chain(DROPPED_BTN_CODES.iter().map(|name| ecodes::event_code("btn", name)))
+            .collect::<Result<Vec<EventCode>, ArgumentError>>()?;
+
+        Ok(Box::new(TouchpadToMouse {
+            abs_x: ecodes::event_code("abs", "x")?,
+            abs_y: ecodes::event_code("abs", "y")?,
+            rel_x: ecodes::event_code("rel", "x")?,
+            rel_y: ecodes::event_code("rel", "y")?,
+            btn_touch: ecodes::event_code("btn", "touch")?,
+            dropped_codes,
+            states: HashMap::new(),
+        }))
+    }
+
+    /// Returns the relative code that the given absolute code gets translated to, if any.
+    fn translated_code(&self, code: EventCode) -> Option<EventCode> {
+        if code == self.abs_x {
+            Some(self.rel_x)
+        } else if code == self.abs_y {
+            Some(self.rel_y)
+        } else {
+            None
+        }
+    }
+}
+
+impl Quirk for TouchpadToMouse {
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        let translated_code = self.translated_code(event.code);
+        let is_x = event.code == self.abs_x;
+        let state = self.states.entry(event.domain).or_default();
+
+        if event.code == self.btn_touch {
+            let touching = event.value != 0;
+            if touching && ! state.touching {
+                state.x = None;
+                state.y = None;
+            }
+            state.touching = touching;
+            return;
+        }
+
+        if let Some(rel_code) = translated_code {
+            let position = match is_x {
+                true => &mut state.x,
+                false => &mut state.y,
+            };
+            let last_position = position.replace(event.value);
+            if state.touching {
+                if let Some(last_position) = last_position {
+                    let delta = event.value.saturating_sub(last_position);
+                    if delta != 0 {
+                        output_events.push(Event { code: rel_code, value: delta, previous_value: 0, ..event });
+                    }
+                }
+            }
+            return;
+        }
+
+        if self.dropped_codes.contains(&event.code) {
+            return;
+        }
+        output_events.push(event);
+    }
+
+    fn apply_to_cap(&self, cap: Capability, output_caps: &mut Vec<Capability>) {
+        if let Some(rel_code) = self.translated_code(cap.code) {
+            output_caps.push(Capability {
+                code: rel_code,
+                values: Set::from(Interval::new(None, None)),
+                abs_meta: None,
+                ..cap
+            });
+        } else if ! self.dropped_codes.contains(&cap.code) {
+            output_caps.push(cap);
+        }
+    }
+}
+
@@ -252,6 +252,10 @@ impl Set {
         self.intervals.is_empty()
     }
 
+    pub fn contains(&self, value: i32) -> bool {
+        self.intervals.iter().any(|interval| interval.contains(value))
+    }
+
     /// Applies a function to each interval in this set. That function may return zero or more intervals.
     /// Returns the set that is the union of all returned intervals.
     pub fn map<T: IntoIterator<Item=Interval>>(&self, function: impl Fn(Interval) -> T) -> Set {
@@ -283,6 +287,28 @@ impl Set {
     }
 }
 
+/// Formats the interval in the same way as values are written in keys, e.g. "1", "0~255" or "~-1".
+impl std::fmt::Display for Interval {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let bound_to_str = |bound: i32| match bound {
+            i32::MIN | i32::MAX => String::new(),
+            _ => bound.to_string(),
+        };
+        match self.min == self.max {
+            true => write!(f, "{}", self.min),
+            false => write!(f, "{}~{}", bound_to_str(self.min), bound_to_str(self.max)),
+        }
+    }
+}
+
+/// Formats the set as its intervals separated by commas, e.g. "0~1,5".
+impl std::fmt::Display for Set {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let intervals: Vec<String> = self.intervals.iter().map(Interval::to_string).collect();
+        write!(f, "{}", intervals.join(","))
+    }
+}
+
 /// Generates pairs of intervals (interval_1, interval_2). Consecutively generated pairs will have exactly one
 /// interval different. The interval that differs will always be the one whose maximum value was the lowest.
 /// Unless the one with the lowest maximum value has reached end of iteration, then the other will change.
@@ -0,0 +1,63 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --realtime and --lock-memory arguments, which reduce the latency of evsieve on a busy
+//! system by giving the main thread a realtime scheduling policy and keeping all memory in RAM.
+
+use crate::error::{Context, SystemError};
+
+/// The priority used if --realtime is specified without a priority.
+pub const DEFAULT_PRIORITY: i32 = 50;
+
+#[derive(Clone, Copy, Default)]
+pub struct RealtimePolicy {
+    /// If Some, the main thread shall be scheduled with SCHED_FIFO at this priority.
+    pub priority: Option<i32>,
+    /// Whether all memory of evsieve shall be locked in RAM.
+    pub lock_memory: bool,
+}
+
+impl RealtimePolicy {
+    pub fn is_active(&self) -> bool {
+        self.priority.is_some() || self.lock_memory
+    }
+
+    /// Applies the policy to the calling thread and the process. Failing to do so is not fatal, because
+    /// evsieve works fine without it, so a warning is printed instead.
+    pub fn apply(&self) {
+        if let Some(priority) = self.priority {
+            if let Err(error) = set_fifo_priority(priority) {
+                error.print_err();
+                eprintln!("Warning: evsieve will run without realtime priority. Running evsieve as root or granting it the CAP_SYS_NICE capability may help.");
+            }
+        }
+        if self.lock_memory {
+            // MCL_FUTURE also locks memory that is allocated later, such as the stack of the persistence
+            // thread if it is launched after this point.
+            let res = unsafe { libc::mlockall(libc::MCL_CURRENT | libc::MCL_FUTURE) };
+            if res != 0 {
+                SystemError::os_with_context("While locking the memory of evsieve:").print_err();
+                eprintln!("Warning: evsieve will run without locking its memory. Running evsieve as root, granting it the CAP_IPC_LOCK capability or raising its RLIMIT_MEMLOCK may help.");
+            }
+        }
+    }
+}
+
+fn set_fifo_priority(priority: i32) -> Result<(), SystemError> {
+    let param = libc::sched_param { sched_priority: priority };
+    // Subprocesses started by hooks have no business running with realtime priority.
+    let policy = libc::SCHED_FIFO | libc::SCHED_RESET_ON_FORK;
+    let res = unsafe { libc::sched_setscheduler(0, policy, &param) };
+    if res != 0 {
+        return Err(SystemError::os_with_context(format!(
+            "While setting the scheduling policy of evsieve to SCHED_FIFO with priority {}:", priority
+        )));
+    }
+    Ok(())
+}
+
+/// Makes the calling thread use the default scheduling policy, undoing the priority that it may have
+/// inherited from the main thread. Lowering the priority of a thread requires no privileges.
+pub fn reset_thread_priority() {
+    let param = libc::sched_param { sched_priority: 0 };
+    unsafe { libc::sched_setscheduler(0, libc::SCHED_OTHER, &param) };
+}
@@ -0,0 +1,371 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --sandbox argument. After evsieve has opened all devices it needs, it can restrict
+//! itself using landlock to only access the files it may need later, such as input devices that may
+//! need to be reopened, and using seccomp to only use the system calls it may need later.
+
+use std::ffi::CString;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{Context, SystemError};
+use crate::predevice::{OpenMode, PersistState, PreInputDevice, PreOutputDevice};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SandboxMode {
+    /// Restrict file access with landlock and system calls with seccomp.
+    Strict,
+    /// Do not restrict anything.
+    Off,
+}
+
+/// Describes which files evsieve may still need to access after all devices have been opened.
+pub struct SandboxPolicy {
+    pub mode: SandboxMode,
+    /// Files or directories beneath which files may be read.
+    readable_paths: Vec<PathBuf>,
+    /// Files or directories beneath which files may be read, written, created and removed.
+    writable_paths: Vec<PathBuf>,
+}
+
+impl SandboxPolicy {
+    pub fn new(mode: SandboxMode) -> SandboxPolicy {
+        SandboxPolicy {
+            mode,
+            readable_paths: Vec::new(),
+            writable_paths: Vec::new(),
+        }
+    }
+
+    pub fn allow_read(&mut self, path: impl Into<PathBuf>) {
+        self.readable_paths.push(path.into());
+    }
+
+    pub fn allow_write(&mut self, path: impl Into<PathBuf>) {
+        self.writable_paths.push(path.into());
+    }
+
+    /// Allows access to all files that another policy allows access to.
+    pub fn extend(&mut self, other: SandboxPolicy) {
+        self.readable_paths.extend(other.readable_paths);
+        self.writable_paths.extend(other.writable_paths);
+    }
+
+    /// Allows access to all files that the input and output devices may need at runtime.
+    pub fn allow_devices(&mut self, input_devices: &[PreInputDevice], output_devices: &[PreOutputDevice]) {
+        for device in input_devices {
+            let may_reopen = match &device.persist_state {
+                PersistState::None | PersistState::Exit => false,
+                PersistState::Reopen => true,
+                PersistState::Full(cache) => {
+                    if let Some(cache_dir) = cache.location.parent() {
+                        self.allow_write(cache_dir);
+                    }
+                    true
+                },
+            };
+            // Persistent devices are usually opened through a symlink that points into /dev/input.
+            if may_reopen {
+                if let Some(parent) = device.path.parent() {
+                    // Directories such as /dev/input/by-id may not exist while no device is plugged in.
+                    if ! parent.starts_with("/dev/input") {
+                        self.allow_read(parent);
+                    }
+                }
+                self.allow_read("/dev/input");
+                if device.open_mode == OpenMode::ReadWrite {
+                    self.allow_write("/dev/input");
+                }
+            }
+        }
+
+        for device in output_devices {
+            // Output devices may get recreated if the capabilities of the input devices change.
+            if device.uinput_fd.is_none() {
+                self.allow_write("/dev/uinput");
+            }
+            if let Some(link) = &device.create_link {
+                // Libevdev looks in /sys to find out where the device node of a new device is.
+                self.allow_read("/sys/devices/virtual/input");
+                if let Some(parent) = link.parent() {
+                    self.allow_write(parent);
+                }
+            }
+        }
+    }
+}
+
+/// Restricts this process according to the policy. Must be called after all devices have been opened.
+pub fn enter(policy: &SandboxPolicy) -> Result<(), SystemError> {
+    match policy.mode {
+        SandboxMode::Off => return Ok(()),
+        SandboxMode::Strict => (),
+    }
+
+    // Required for unprivileged processes to use landlock and seccomp, and makes sure that we can
+    // never gain privileges by executing a setuid binary.
+    if unsafe { libc::prctl(libc::PR_SET_NO_NEW_PRIVS, 1, 0, 0, 0) } < 0 {
+        return Err(SystemError::os_with_context("While trying to set the no_new_privs flag:"));
+    }
+
+    landlock::restrict(policy)?;
+    seccomp::restrict()?;
+    Ok(())
+}
+
+/// Makes sure that `path` exists so access to it can be granted. Writable paths that do not exist yet
+/// are directories evsieve may write to later, like the persistence directory, so they get created
+/// now. We do not grant access to an existing ancestor instead, because that could be a directory as
+/// broad as the root directory.
+fn prepare_path(path: &Path, writable: bool) -> Result<(), SystemError> {
+    if path.exists() {
+        return Ok(());
+    }
+    match writable {
+        true => std::fs::create_dir_all(path).map_err(SystemError::from)
+            .with_context("While creating the directory:"),
+        false => Err(SystemError::new(format!(
+            "The path {} does not exist. Please create it before starting evsieve with --sandbox=strict.",
+            path.display()
+        ))),
+    }
+}
+
+mod landlock {
+    use super::*;
+
+    // The definitions of the landlock ABI as found in <linux/landlock.h>. We only use the rights
+    // of the first version of the ABI, which has been supported since Linux 5.13.
+    const ACCESS_FS_EXECUTE: u64 = 1 << 0;
+    const ACCESS_FS_WRITE_FILE: u64 = 1 << 1;
+    const ACCESS_FS_READ_FILE: u64 = 1 << 2;
+    const ACCESS_FS_READ_DIR: u64 = 1 << 3;
+    const ACCESS_FS_REMOVE_DIR: u64 = 1 << 4;
+    const ACCESS_FS_REMOVE_FILE: u64 = 1 << 5;
+    const ACCESS_FS_MAKE_CHAR: u64 = 1 << 6;
+    const ACCESS_FS_MAKE_DIR: u64 = 1 << 7;
+    const ACCESS_FS_MAKE_REG: u64 = 1 << 8;
+    const ACCESS_FS_MAKE_SOCK: u64 = 1 << 9;
+    const ACCESS_FS_MAKE_FIFO: u64 = 1 << 10;
+    const ACCESS_FS_MAKE_BLOCK: u64 = 1 << 11;
+    const ACCESS_FS_MAKE_SYM: u64 = 1 << 12;
+
+    const HANDLED_ACCESS: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE
+        | ACCESS_FS_READ_DIR | ACCESS_FS_REMOVE_DIR | ACCESS_FS_REMOVE_FILE | ACCESS_FS_MAKE_CHAR
+        | ACCESS_FS_MAKE_DIR | ACCESS_FS_MAKE_REG | ACCESS_FS_MAKE_SOCK | ACCESS_FS_MAKE_FIFO
+        | ACCESS_FS_MAKE_BLOCK | ACCESS_FS_MAKE_SYM;
+    /// The only rights that can be granted on paths that are not directories.
+    const FILE_ACCESS: u64 = ACCESS_FS_EXECUTE | ACCESS_FS_WRITE_FILE | ACCESS_FS_READ_FILE;
+
+    const READ_ACCESS: u64 = ACCESS_FS_READ_FILE | ACCESS_FS_READ_DIR;
+    const WRITE_ACCESS: u64 = READ_ACCESS | ACCESS_FS_WRITE_FILE | ACCESS_FS_REMOVE_DIR
+        | ACCESS_FS_REMOVE_FILE | ACCESS_FS_MAKE_DIR | ACCESS_FS_MAKE_REG | ACCESS_FS_MAKE_SYM;
+
+    const RULE_PATH_BENEATH: libc::c_int = 1;
+
+    #[repr(C)]
+    struct RulesetAttr {
+        handled_access_fs: u64,
+    }
+
+    #[repr(C, packed)]
+    struct PathBeneathAttr {
+        allowed_access: u64,
+        parent_fd: libc::c_int,
+    }
+
+    pub fn restrict(policy: &SandboxPolicy) -> Result<(), SystemError> {
+        let attr = RulesetAttr { handled_access_fs: HANDLED_ACCESS };
+        let ruleset_fd = unsafe { libc::syscall(
+            libc::SYS_landlock_create_ruleset,
+            &attr as *const RulesetAttr,
+            std::mem::size_of::<RulesetAttr>(),
+            0,
+        ) } as libc::c_int;
+        if ruleset_fd < 0 {
+            let error = std::io::Error::last_os_error();
+            return match error.raw_os_error() {
+                Some(libc::ENOSYS) | Some(libc::EOPNOTSUPP) => {
+                    eprintln!("Warning: the kernel does not support landlock. Evsieve is unable to restrict which files it can access.");
+                    Ok(())
+                },
+                _ => Err(SystemError::from(error).with_context("While creating a landlock ruleset:")),
+            };
+        }
+        // Safety: the syscall returned a valid file descriptor that nobody else owns.
+        let ruleset_fd = unsafe { crate::io::fd::OwnedFd::new(ruleset_fd) };
+
+        let rules = policy.readable_paths.iter().map(|path| (path, READ_ACCESS, false))
+            .chain(policy.writable_paths.iter().map(|path| (path, WRITE_ACCESS, true)));
+        for (path, access, writable) in rules {
+            prepare_path(path, writable)
+                .and_then(|()| add_rule(&ruleset_fd, path, access))
+                .with_context_of(|| format!(
+                "While allowing access to {} in the sandbox:", path.display()
+            ))?;
+        }
+
+        use std::os::unix::io::AsRawFd;
+        let res = unsafe { libc::syscall(libc::SYS_landlock_restrict_self, ruleset_fd.as_raw_fd(), 0) };
+        if res < 0 {
+            return Err(SystemError::os_with_context("While enforcing the landlock ruleset:"));
+        }
+        Ok(())
+    }
+
+    fn add_rule(ruleset_fd: &crate::io::fd::OwnedFd, path: &Path, access: u64) -> Result<(), SystemError> {
+        use std::os::unix::io::AsRawFd;
+        let access = match path.is_dir() {
+            true => access,
+            false => access & FILE_ACCESS,
+        };
+
+        let path_cstring = CString::new(path.as_os_str().as_bytes())
+            .map_err(|_| SystemError::new("The path contains a null byte."))?;
+        let path_fd = unsafe {
+            crate::io::fd::OwnedFd::from_syscall(
+                libc::open(path_cstring.as_ptr(), libc::O_PATH | libc::O_CLOEXEC)
+            )?
+        };
+
+        let attr = PathBeneathAttr {
+            allowed_access: access,
+            parent_fd: path_fd.as_raw_fd(),
+        };
+        let res = unsafe { libc::syscall(
+            libc::SYS_landlock_add_rule,
+            ruleset_fd.as_raw_fd(),
+            RULE_PATH_BENEATH,
+            &attr as *const PathBeneathAttr,
+            0,
+        ) };
+        if res < 0 {
+            return Err(std::io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+}
+
+mod seccomp {
+    use super::*;
+
+    #[cfg(target_arch = "x86_64")]
+    const AUDIT_ARCH: u32 = 0xC000_003E;
+    #[cfg(target_arch = "aarch64")]
+    const AUDIT_ARCH: u32 = 0xC000_00B7;
+
+    /// The offsets of the `nr` and `arch` fields of `struct seccomp_data`.
+    const OFFSET_NR: u32 = 0;
+    const OFFSET_ARCH: u32 = 4;
+
+    /// All system calls that evsieve may make after entering the sandbox, including those made by
+    /// the standard library and libevdev.
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    fn allowed_syscalls() -> Vec<libc::c_long> {
+        let mut syscalls = vec![
+            // File descriptors.
+            libc::SYS_read, libc::SYS_write, libc::SYS_readv, libc::SYS_writev, libc::SYS_pread64,
+            libc::SYS_pwrite64, libc::SYS_close, libc::SYS_lseek, libc::SYS_ioctl, libc::SYS_fcntl,
+            libc::SYS_fdatasync,
+            libc::SYS_pipe2, libc::SYS_eventfd2, libc::SYS_signalfd4, libc::SYS_timerfd_settime,
+            // Files, needed for reopening devices and writing to the persistence directory.
+            libc::SYS_openat, libc::SYS_newfstatat, libc::SYS_fstat, libc::SYS_statx,
+            libc::SYS_readlinkat, libc::SYS_getdents64, libc::SYS_unlinkat, libc::SYS_symlinkat,
+            libc::SYS_renameat2, libc::SYS_mkdirat, libc::SYS_faccessat, libc::SYS_faccessat2,
+            libc::SYS_inotify_init1, libc::SYS_inotify_add_watch, libc::SYS_inotify_rm_watch,
+            // Sockets, needed to accept connections to introspection sockets.
+            libc::SYS_accept4, libc::SYS_setsockopt, libc::SYS_shutdown,
+            // Polling.
+            libc::SYS_epoll_create1, libc::SYS_epoll_ctl, libc::SYS_epoll_pwait, libc::SYS_epoll_pwait2,
+            libc::SYS_ppoll,
+            // Memory.
+            libc::SYS_mmap, libc::SYS_munmap, libc::SYS_mremap, libc::SYS_mprotect, libc::SYS_madvise,
+            libc::SYS_brk,
+            // Signals.
+            libc::SYS_rt_sigprocmask, libc::SYS_rt_sigaction, libc::SYS_rt_sigreturn,
+            libc::SYS_rt_sigtimedwait, libc::SYS_sigaltstack, libc::SYS_kill, libc::SYS_tgkill,
+            libc::SYS_tkill,
+            // Threads, used by the persistence subsystem.
+            libc::SYS_clone, libc::SYS_clone3, libc::SYS_futex, libc::SYS_set_robust_list,
+            libc::SYS_rseq, libc::SYS_sched_getaffinity, libc::SYS_sched_yield, libc::SYS_prctl,
+            libc::SYS_sched_setscheduler,
+            libc::SYS_wait4, libc::SYS_waitid,
+            // Miscellaneous.
+            libc::SYS_clock_gettime, libc::SYS_clock_nanosleep, libc::SYS_nanosleep,
+            libc::SYS_gettimeofday, libc::SYS_getpid, libc::SYS_gettid, libc::SYS_getuid,
+            libc::SYS_geteuid, libc::SYS_getgid, libc::SYS_getegid, libc::SYS_getrandom,
+            libc::SYS_uname, libc::SYS_exit, libc::SYS_exit_group,
+        ];
+        #[cfg(target_arch = "x86_64")]
+        syscalls.extend_from_slice(&[
+            libc::SYS_open, libc::SYS_stat, libc::SYS_lstat, libc::SYS_access, libc::SYS_readlink,
+            libc::SYS_unlink, libc::SYS_symlink, libc::SYS_rename, libc::SYS_mkdir, libc::SYS_poll,
+            libc::SYS_epoll_wait, libc::SYS_pipe, libc::SYS_getdents, libc::SYS_arch_prctl,
+        ]);
+        syscalls
+    }
+
+    fn statement(code: u32, k: u32) -> libc::sock_filter {
+        libc::sock_filter { code: code as u16, jt: 0, jf: 0, k }
+    }
+
+    fn jump_if_equal(k: u32, jt: u8, jf: u8) -> libc::sock_filter {
+        libc::sock_filter { code: (libc::BPF_JMP | libc::BPF_JEQ | libc::BPF_K) as u16, jt, jf, k }
+    }
+
+    #[cfg(any(target_arch = "x86_64", target_arch = "aarch64"))]
+    pub fn restrict() -> Result<(), SystemError> {
+        let syscalls = allowed_syscalls();
+        let mut filter: Vec<libc::sock_filter> = vec![
+            // Kill the process if a system call is made using a different calling convention, otherwise
+            // the system call numbers below mean something else.
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, OFFSET_ARCH),
+            jump_if_equal(AUDIT_ARCH, 1, 0),
+            statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_KILL_PROCESS),
+            // Jump to the final "allow" statement for all allowed system calls.
+            statement(libc::BPF_LD | libc::BPF_W | libc::BPF_ABS, OFFSET_NR),
+        ];
+        for (index, &syscall) in syscalls.iter().enumerate() {
+            let distance_to_allow = (syscalls.len() - index) as u8;
+            filter.push(jump_if_equal(syscall as u32, distance_to_allow, 0));
+        }
+        // Deny all other system calls with EPERM rather than killing the process, so that evsieve
+        // can report an error if it turns out that we forgot about some system call.
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ERRNO | libc::EPERM as u32));
+        filter.push(statement(libc::BPF_RET | libc::BPF_K, libc::SECCOMP_RET_ALLOW));
+
+        let program = libc::sock_fprog {
+            len: filter.len() as libc::c_ushort,
+            filter: filter.as_mut_ptr(),
+        };
+        // The TSYNC flag applies the filter to all threads of this process, not just the current one.
+        let res = unsafe { libc::syscall(
+            libc::SYS_seccomp,
+            libc::SECCOMP_SET_MODE_FILTER,
+            libc::SECCOMP_FILTER_FLAG_TSYNC,
+            &program as *const libc::sock_fprog,
+        ) };
+        if res != 0 {
+            return Err(SystemError::os_with_context("While installing the seccomp filter:"));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    pub fn restrict() -> Result<(), SystemError> {
+        eprintln!("Warning: evsieve does not know which system calls to allow on this architecture. Evsieve is unable to restrict which system calls it can make.");
+        Ok(())
+    }
+}
+
+#[test]
+fn unittest() {
+    let directory = std::env::temp_dir().join(format!("evsieve-sandbox-test-{}", std::process::id()));
+    let nested = directory.join("persist");
+    assert!(prepare_path(&nested, false).is_err());
+    assert!(! directory.exists());
+    assert!(prepare_path(&nested, true).is_ok());
+    assert!(nested.is_dir());
+    assert!(prepare_path(&nested, false).is_ok());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
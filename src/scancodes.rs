@@ -27,126 +27,198 @@ lazy_static! {
     static ref SCANCODES: HashMap<EventCode, Scancode> = {
         // TODO: LOW-PRIORITY: the following table is still incomplete and possibly incorrect.
         let hardcoded_scancodes: &[(&'static str, Scancode)] = &[
-            (&"key:a", 458756),
-            (&"key:b", 458757),
-            (&"key:c", 458758),
-            (&"key:d", 458759),
-            (&"key:e", 458760),
-            (&"key:f", 458761),
-            (&"key:g", 458762),
-            (&"key:h", 458763),
-            (&"key:i", 458764),
-            (&"key:j", 458765),
-            (&"key:k", 458766),
-            (&"key:l", 458767),
-            (&"key:m", 458768),
-            (&"key:n", 458769),
-            (&"key:o", 458770),
-            (&"key:p", 458771),
-            (&"key:q", 458772),
-            (&"key:r", 458773),
-            (&"key:s", 458774),
-            (&"key:t", 458775),
-            (&"key:u", 458776),
-            (&"key:v", 458777),
-            (&"key:w", 458778),
-            (&"key:x", 458779),
-            (&"key:y", 458780),
-            (&"key:z", 458781),
-            (&"key:1", 458782),
-            (&"key:2", 458783),
-            (&"key:3", 458784),
-            (&"key:4", 458785),
-            (&"key:5", 458786),
-            (&"key:6", 458787),
-            (&"key:7", 458788),
-            (&"key:8", 458789),
-            (&"key:9", 458790),
-            (&"key:0", 458791),
-            (&"key:enter", 458792),
-            (&"key:esc", 458793),
-            (&"key:backspace", 458794),
-            (&"key:tab", 458795),
-            (&"key:space", 458796),
-            (&"key:minus", 458797),
-            (&"key:equal", 458798),
-            (&"key:leftbrace", 458799),
-            (&"key:rightbrace", 458800),
-            (&"key:backslash", 458801),
-            (&"key:semicolon", 458803),
-            (&"key:apostrophe", 458804),
-            (&"key:grave", 458805),
-            (&"key:comma", 458806),
-            (&"key:dot", 458807),
-            (&"key:slash", 458808),
-            (&"key:capslock", 458809),
-            (&"key:f1", 458810),
-            (&"key:f2", 458811),
-            (&"key:f3", 458812),
-            (&"key:f4", 458813),
-            (&"key:f5", 458814),
-            (&"key:f6", 458815),
-            (&"key:f7", 458816),
-            (&"key:f8", 458817),
-            (&"key:f9", 458818),
-            (&"key:f10", 458819),
-            (&"key:f11", 458820),
-            (&"key:f12", 458821),
-            (&"key:sysrq", 458822),
-            (&"key:scrolllock", 458823),
-            (&"key:pause", 458824),
-            (&"key:insert", 458825),
-            (&"key:home", 458826),
-            (&"key:pageup", 458827),
-            (&"key:delete", 458828),
-            (&"key:end", 458829),
-            (&"key:pagedown", 458830),
-            (&"key:right", 458831),
-            (&"key:left", 458832),
-            (&"key:down", 458833),
-            (&"key:up", 458834),
-            (&"key:numlock", 458835),
-            (&"key:kpslash", 458836),
-            (&"key:kpasterisk", 458837),
-            (&"key:kpminus", 458838),
-            (&"key:kpplus", 458839),
-            (&"key:kpenter", 458840),
-            (&"key:kp1", 458841),
-            (&"key:kp2", 458842),
-            (&"key:kp3", 458843),
-            (&"key:kp4", 458844),
-            (&"key:kp5", 458845),
-            (&"key:kp6", 458846),
-            (&"key:kp7", 458847),
-            (&"key:kp8", 458848),
-            (&"key:kp9", 458849),
-            (&"key:kp0", 458850),
-            (&"key:kpdot", 458851),
-            (&"key:compose", 458853),
-            (&"key:leftctrl", 458976),
-            (&"key:leftshift", 458977),
-            (&"key:leftalt", 458978),
-            (&"key:leftmeta", 458979),
-            (&"key:rightctrl", 458980),
-            (&"key:rightshift", 458981),
-            (&"key:rightalt", 458982),
+            ("key:a", 458756),
+            ("key:b", 458757),
+            ("key:c", 458758),
+            ("key:d", 458759),
+            ("key:e", 458760),
+            ("key:f", 458761),
+            ("key:g", 458762),
+            ("key:h", 458763),
+            ("key:i", 458764),
+            ("key:j", 458765),
+            ("key:k", 458766),
+            ("key:l", 458767),
+            ("key:m", 458768),
+            ("key:n", 458769),
+            ("key:o", 458770),
+            ("key:p", 458771),
+            ("key:q", 458772),
+            ("key:r", 458773),
+            ("key:s", 458774),
+            ("key:t", 458775),
+            ("key:u", 458776),
+            ("key:v", 458777),
+            ("key:w", 458778),
+            ("key:x", 458779),
+            ("key:y", 458780),
+            ("key:z", 458781),
+            ("key:1", 458782),
+            ("key:2", 458783),
+            ("key:3", 458784),
+            ("key:4", 458785),
+            ("key:5", 458786),
+            ("key:6", 458787),
+            ("key:7", 458788),
+            ("key:8", 458789),
+            ("key:9", 458790),
+            ("key:0", 458791),
+            ("key:enter", 458792),
+            ("key:esc", 458793),
+            ("key:backspace", 458794),
+            ("key:tab", 458795),
+            ("key:space", 458796),
+            ("key:minus", 458797),
+            ("key:equal", 458798),
+            ("key:leftbrace", 458799),
+            ("key:rightbrace", 458800),
+            ("key:backslash", 458801),
+            ("key:semicolon", 458803),
+            ("key:apostrophe", 458804),
+            ("key:grave", 458805),
+            ("key:comma", 458806),
+            ("key:dot", 458807),
+            ("key:slash", 458808),
+            ("key:capslock", 458809),
+            ("key:f1", 458810),
+            ("key:f2", 458811),
+            ("key:f3", 458812),
+            ("key:f4", 458813),
+            ("key:f5", 458814),
+            ("key:f6", 458815),
+            ("key:f7", 458816),
+            ("key:f8", 458817),
+            ("key:f9", 458818),
+            ("key:f10", 458819),
+            ("key:f11", 458820),
+            ("key:f12", 458821),
+            ("key:sysrq", 458822),
+            ("key:scrolllock", 458823),
+            ("key:pause", 458824),
+            ("key:insert", 458825),
+            ("key:home", 458826),
+            ("key:pageup", 458827),
+            ("key:delete", 458828),
+            ("key:end", 458829),
+            ("key:pagedown", 458830),
+            ("key:right", 458831),
+            ("key:left", 458832),
+            ("key:down", 458833),
+            ("key:up", 458834),
+            ("key:numlock", 458835),
+            ("key:kpslash", 458836),
+            ("key:kpasterisk", 458837),
+            ("key:kpminus", 458838),
+            ("key:kpplus", 458839),
+            ("key:kpenter", 458840),
+            ("key:kp1", 458841),
+            ("key:kp2", 458842),
+            ("key:kp3", 458843),
+            ("key:kp4", 458844),
+            ("key:kp5", 458845),
+            ("key:kp6", 458846),
+            ("key:kp7", 458847),
+            ("key:kp8", 458848),
+            ("key:kp9", 458849),
+            ("key:kp0", 458850),
+            ("key:kpdot", 458851),
+            ("key:compose", 458853),
+            ("key:leftctrl", 458976),
+            ("key:leftshift", 458977),
+            ("key:leftalt", 458978),
+            ("key:leftmeta", 458979),
+            ("key:rightctrl", 458980),
+            ("key:rightshift", 458981),
+            ("key:rightalt", 458982),
+        ];
+        // Usages from the consumer page that the Linux kernel maps to EV_KEY codes, as far as those codes
+        // are not already covered by the keyboard page above.
+        let consumer_scancodes: &[(&'static str, Scancode)] = &[
+            ("key:power", 786480),
+            ("key:sleep", 786482),
+            ("key:brightnessup", 786543),
+            ("key:brightnessdown", 786544),
+            ("key:kbdillumup", 786553),
+            ("key:kbdillumdown", 786554),
+            ("key:kbdillumtoggle", 786556),
+            ("key:play", 786608),
+            ("key:record", 786610),
+            ("key:fastforward", 786611),
+            ("key:rewind", 786612),
+            ("key:nextsong", 786613),
+            ("key:previoussong", 786614),
+            ("key:stopcd", 786615),
+            ("key:ejectcd", 786616),
+            ("key:playpause", 786637),
+            ("key:mute", 786658),
+            ("key:bassboost", 786661),
+            ("key:volumeup", 786665),
+            ("key:volumedown", 786666),
+            ("key:config", 786819),
+            ("key:mail", 786826),
+            ("key:calc", 786834),
+            ("key:file", 786836),
+            ("key:www", 786838),
+            ("key:coffee", 786846),
+            ("key:controlpanel", 786847),
+            ("key:appselect", 786850),
+            ("key:documents", 786855),
+            ("key:screensaver", 786865),
+            ("key:new", 786945),
+            ("key:close", 786947),
+            ("key:save", 786951),
+            ("key:print", 786952),
+            ("key:search", 786977),
+            ("key:homepage", 786979),
+            ("key:back", 786980),
+            ("key:forward", 786981),
+            ("key:refresh", 786983),
+            ("key:bookmarks", 786986),
+            ("key:zoomin", 786989),
+            ("key:zoomout", 786990),
+            ("key:zoomreset", 786991),
+            ("key:redo", 787065),
+            ("key:reply", 787081),
+            ("key:forwardmail", 787083),
+            ("key:send", 787084),
         ];
 
-        hardcoded_scancodes.into_iter().filter_map(|(key_str, scancode)| {
+        hardcoded_scancodes.iter().chain(consumer_scancodes).filter_map(|&(key_str, scancode)| {
             let (type_name, code_name_opt) = crate::utils::split_once(key_str, ":");
             let code_name = code_name_opt.unwrap(); // Unwrap ok: data is hardcoded.
 
             // We defensively check for None here because whether these codes exist might
             // depend on the version of libevdev we link against.
             if let Ok(event_code) = ecodes::event_code(type_name, code_name) {
-                Some((event_code, scancode.clone()))
+                Some((event_code, scancode))
             } else {
                 None
             }
         }).collect()
     };
+
+    static ref SCANCODES_INVERSE: HashMap<Scancode, EventCode> = {
+        SCANCODES.iter().map(|(&code, &scancode)| (scancode, code)).collect()
+    };
 }
 
 pub fn from_event_code(code: EventCode) -> Option<Scancode> {
     SCANCODES.get(&code).cloned()
 }
+
+/// The inverse of `from_event_code()`: returns the event code that corresponds to a scancode, which is
+/// composed of a HID usage page and a HID usage ID.
+pub fn to_event_code(scancode: Scancode) -> Option<EventCode> {
+    SCANCODES_INVERSE.get(&scancode).cloned()
+}
+
+#[test]
+fn unittest() {
+    let code = |name| ecodes::event_code("key", name).unwrap();
+    assert_eq!(from_event_code(code("a")), Some(0x070004));
+    assert_eq!(from_event_code(code("playpause")), Some(0x0c00cd));
+    assert_eq!(to_event_code(0x0c00e9), Some(code("volumeup")));
+    assert_eq!(to_event_code(0x0c0238), None);
+    // Every scancode must map to a single event code and vice versa.
+    assert_eq!(SCANCODES.len(), SCANCODES_INVERSE.len());
+}
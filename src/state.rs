@@ -2,22 +2,51 @@
 
 use std::ops::{Index,IndexMut};
 use std::collections::HashMap;
-use crate::error::InternalError;
-use crate::event::Channel;
+use crate::error::{ArgumentError, InternalError};
+use crate::domain::Domain;
+use crate::event::{Channel, EventCode, EventValue};
+use crate::stream::macro_slot::MacroCommand;
+use crate::io::input::RepeatCommand;
 
 /// Represents the state of the stream that can change as events flow through it.
+#[derive(Clone)]
 pub struct State {
     /// Represents the state of --toggle arguments.
     toggles: Vec<ToggleState>,
+    /// Commands sent by hooks to the --macro-slot argument that it has not yet acted upon.
+    macro_commands: Vec<MacroCommand>,
+    /// Requests sent by hooks to change the autorepeat settings of the input devices.
+    repeat_commands: Vec<RepeatCommand>,
+    /// Requests sent by hooks to recenter the channels of --rel-to-abs arguments with a certain domain, or
+    /// all channels if None.
+    recenter_commands: Vec<Option<Domain>>,
+    /// Toggles whose state is determined by the state of another toggle.
+    followers: Vec<Follower>,
+    /// The last known value of the LEDs of each input device, e.g. whether Caps Lock is on.
+    leds: HashMap<Channel, EventValue>,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+/// Represents a --toggle with a follows= clause.
+#[derive(Clone)]
+struct Follower {
+    leader: ToggleIndex,
+    follower: ToggleIndex,
+    /// For each state of the leader, the state that the follower should assume.
+    states: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
 pub struct ToggleIndex(usize);
 
 impl State {
     pub fn new() -> State {
         State {
             toggles: Vec::new(),
+            macro_commands: Vec::new(),
+            repeat_commands: Vec::new(),
+            recenter_commands: Vec::new(),
+            followers: Vec::new(),
+            leds: HashMap::new(),
         }
     }
 
@@ -36,6 +65,83 @@ impl State {
         ).map(|(_, item)| item)
     }
 
+    pub fn push_macro_command(&mut self, command: MacroCommand) {
+        self.macro_commands.push(command);
+    }
+
+    /// Returns all macro commands that have been sent since the last time this function was called.
+    pub fn take_macro_commands(&mut self) -> Vec<MacroCommand> {
+        std::mem::take(&mut self.macro_commands)
+    }
+
+    pub fn push_repeat_command(&mut self, command: RepeatCommand) {
+        self.repeat_commands.push(command);
+    }
+
+    /// Returns all autorepeat requests that have been sent since the last time this function was called.
+    pub fn take_repeat_commands(&mut self) -> Vec<RepeatCommand> {
+        std::mem::take(&mut self.repeat_commands)
+    }
+
+    pub fn push_recenter_command(&mut self, domain: Option<Domain>) {
+        self.recenter_commands.push(domain);
+    }
+
+    /// Returns all recenter requests that have been sent since the last time this function was called.
+    pub fn take_recenter_commands(&mut self) -> Vec<Option<Domain>> {
+        std::mem::take(&mut self.recenter_commands)
+    }
+
+    /// Makes the state of the follower depend on the state of the leader. `states` must contain the
+    /// state of the follower for each state of the leader.
+    pub fn add_follower(&mut self, leader: ToggleIndex, follower: ToggleIndex, states: Vec<usize>) -> Result<(), ArgumentError> {
+        if leader == follower {
+            return Err(ArgumentError::new("A toggle cannot follow itself."));
+        }
+        if self.followers.iter().any(|existing| existing.follower == leader || existing.leader == follower) {
+            return Err(ArgumentError::new("A toggle cannot follow a toggle that follows another toggle itself."));
+        }
+        if self.followers.iter().any(|existing| existing.follower == follower) {
+            return Err(ArgumentError::new("A toggle cannot follow multiple toggles."));
+        }
+        if states.len() != self[leader].size() {
+            return Err(ArgumentError::new(format!(
+                "The toggle that is being followed has {} states, but {} follow-states were specified.",
+                self[leader].size(), states.len(),
+            )));
+        }
+        if let Some(&state) = states.iter().find(|&&state| state >= self[follower].size()) {
+            return Err(ArgumentError::new(format!(
+                "The follow-state {} is out of range for a toggle with {} states.", state + 1, self[follower].size(),
+            )));
+        }
+        self.followers.push(Follower { leader, follower, states });
+        self.sync_followers();
+        Ok(())
+    }
+
+    /// Projects the state of each leader onto its followers. Must be called after any toggle changed state.
+    pub fn sync_followers(&mut self) {
+        for index in 0 .. self.followers.len() {
+            let Follower { leader, follower, .. } = self.followers[index];
+            let value = self.followers[index].states[self[leader].value()];
+            self[follower].set_value_wrapped(value);
+        }
+    }
+
+    pub fn set_led(&mut self, channel: Channel, value: EventValue) {
+        self.leds.insert(channel, value);
+    }
+
+    /// Returns whether the LED with the given code is on for the device with the given domain, or
+    /// for any device if no domain is given. LEDs we have never heard of are considered off.
+    pub fn led_is_on(&self, code: EventCode, domain: Option<Domain>) -> bool {
+        match domain {
+            Some(domain) => self.leds.get(&(code, domain)).map_or(false, |&value| value != 0),
+            None => self.leds.iter().any(|(&(led_code, _), &value)| led_code == code && value != 0),
+        }
+    }
+
     pub fn create_toggle_with_size(&mut self, size: usize) -> Result<ToggleIndex, InternalError> {
         let toggle_state = ToggleState::new(size)?;
         Ok(self.push_toggle(toggle_state))
@@ -55,6 +161,7 @@ impl IndexMut<ToggleIndex> for State {
     }
 }
 
+#[derive(Clone)]
 pub struct ToggleState {
     /// The current output of this toggle that is active.
     /// Note that this value is zero-indexed, although the user-facing interface is one-indexed.
@@ -66,12 +173,15 @@ pub struct ToggleState {
     /// If the last value of a specific channel was not zero, consistent maps will remember
     /// to which index that event was last routed.
     pub memory: HashMap<Channel, usize>,
+
+    /// The names of the states, if the toggle has a names= clause.
+    pub names: Option<Vec<String>>,
 }
 
 impl ToggleState {
     pub fn new(size: usize) -> Result<ToggleState, InternalError> {
         if size > 0 {
-            Ok(ToggleState { size, value: 0, memory: HashMap::new() })
+            Ok(ToggleState { size, value: 0, memory: HashMap::new(), names: None })
         } else {
             Err(InternalError::new("A toggle requires at least one state."))
         }
@@ -94,4 +204,35 @@ impl ToggleState {
     pub fn size(&self) -> usize {
         self.size
     }
-}
\ No newline at end of file
+
+    /// Returns the name of the current state, if the toggle has a names= clause.
+    pub fn current_name(&self) -> Option<&str> {
+        self.names.as_ref().map(|names| names[self.value].as_str())
+    }
+}
+#[test]
+fn unittest() {
+    let mut state = State::new();
+    let leader = state.create_toggle_with_size(3).unwrap();
+    let follower = state.create_toggle_with_size(2).unwrap();
+    state.add_follower(leader, follower, vec![0, 1, 1]).unwrap();
+
+    let mut observed = Vec::new();
+    for _ in 0 .. 3 {
+        observed.push(state[follower].value());
+        state[leader].advance();
+        state.sync_followers();
+    }
+    assert_eq!(observed, vec![0, 1, 1]);
+
+    // Changes to the follower itself get overwritten.
+    state[follower].advance();
+    state.sync_followers();
+    assert_eq!(state[follower].value(), 0);
+
+    let other = state.create_toggle_with_size(2).unwrap();
+    assert!(state.add_follower(follower, other, vec![0, 1]).is_err());
+    assert!(state.add_follower(other, other, vec![0, 1]).is_err());
+    assert!(state.add_follower(leader, other, vec![0, 1]).is_err());
+    assert!(state.add_follower(leader, other, vec![0, 1, 2]).is_err());
+}
@@ -0,0 +1,184 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --status-file argument: a file that is periodically replaced by a snapshot of the
+//! runtime state of evsieve, so scripts can find out which devices are open and which toggles are
+//! active by simply reading that file.
+//!
+//! The file contains one item per line. Each line starts with the kind of item, followed by its
+//! identity and a number of key=value pairs, e.g.:
+//!
+//!     pid 1234
+//!     device /dev/input/event0 pipeline=0 domain=kb state=active events=1536
+//!     toggle profile pipeline=0 value=2 size=2 name=night
+//!
+//! Tokens containing spaces or other special characters are quoted the same way as in configuration files.
+
+use std::io::Write;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+
+use crate::error::{SystemError, Context};
+use crate::introspect::DeviceInfo;
+use crate::time::{Duration, Instant};
+use crate::utils::shelllex::quote;
+
+/// Describes an input device for the status file.
+pub struct DeviceStatus {
+    pub info: DeviceInfo,
+    /// The amount of events that have been read from the device since it was last opened, or None if
+    /// the device is not currently open.
+    pub events_read: Option<u64>,
+}
+
+/// Describes a toggle that has an id= clause for the status file.
+pub struct ToggleStatus {
+    pub id: String,
+    pub pipeline: usize,
+    /// The currently active state, one-indexed like everywhere else in the user-facing interface.
+    pub value: usize,
+    pub size: usize,
+    /// The name of the currently active state, if the toggle has a names= clause.
+    pub name: Option<String>,
+}
+
+/// The status file as specified by a --status-file argument, before evsieve has started running.
+pub struct PreStatusFile {
+    pub path: PathBuf,
+    /// How often the status file gets rewritten.
+    pub interval: Duration,
+}
+
+pub struct StatusFile {
+    path: PathBuf,
+    /// The file that the status gets written to before it replaces the actual status file. It must be in
+    /// the same directory, because renaming files is only atomic within a single filesystem.
+    temp_path: PathBuf,
+    interval: Duration,
+    next_write: Instant,
+    /// The contents that were written the last time, so the file does not get replaced needlessly.
+    last_content: Option<String>,
+}
+
+impl StatusFile {
+    pub fn new(pre_status_file: PreStatusFile, now: Instant) -> StatusFile {
+        let PreStatusFile { path, interval } = pre_status_file;
+        StatusFile {
+            temp_path: temp_path_for(&path),
+            path, interval,
+            next_write: now,
+            last_content: None,
+        }
+    }
+
+    /// Returns whether the status file is due to be rewritten.
+    pub fn is_due(&self, now: Instant) -> bool {
+        now >= self.next_write
+    }
+
+    /// Returns how long it takes until the status file is due to be rewritten.
+    pub fn time_until_due(&self, now: Instant) -> Duration {
+        self.next_write.checked_duration_since(now).unwrap_or(Duration::from_nanos(0))
+    }
+
+    /// Replaces the status file with the given contents, unless they are the same as the contents that
+    /// were written the last time. Schedules the next write regardless of whether writing succeeded.
+    pub fn write(&mut self, content: String, now: Instant) -> Result<(), SystemError> {
+        self.next_write = now + self.interval;
+        if self.last_content.as_ref() == Some(&content) {
+            return Ok(());
+        }
+        self.write_atomically(content.as_bytes())
+            .with_context_of(|| format!("While writing the status file {}:", self.path.display()))?;
+        self.last_content = Some(content);
+        Ok(())
+    }
+
+    /// Writes the contents to a temporary file first and then moves that file over the status file, so
+    /// readers never get to see a partially written file.
+    fn write_atomically(&self, content: &[u8]) -> Result<(), SystemError> {
+        let mut file = std::fs::File::create(&self.temp_path)?;
+        file.write_all(content)?;
+        drop(file);
+        std::fs::rename(&self.temp_path, &self.path)?;
+        Ok(())
+    }
+}
+
+impl Drop for StatusFile {
+    /// A status file that outlives evsieve would describe devices and toggles that no longer exist.
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.temp_path);
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Returns a path like /run/.evsieve.status.tmp for the status file /run/evsieve.status.
+fn temp_path_for(path: &Path) -> PathBuf {
+    let mut file_name = std::ffi::OsString::from(".");
+    file_name.push(path.file_name().unwrap_or_else(|| std::ffi::OsStr::from_bytes(b"status")));
+    file_name.push(".tmp");
+    path.with_file_name(file_name)
+}
+
+/// Formats the contents of the status file.
+pub fn format_status(pid: u32, devices: &[DeviceStatus], toggles: &[ToggleStatus]) -> String {
+    let mut lines = vec![format!("pid {}", pid)];
+    for device in devices {
+        let mut line = format!("device {} pipeline={}", quote(&device.info.path), device.info.pipeline);
+        if let Some(domain) = &device.info.domain {
+            line += &format!(" domain={}", quote(domain));
+        }
+        line += &format!(" state={}", device.info.state.name());
+        if let Some(events_read) = device.events_read {
+            line += &format!(" events={}", events_read);
+        }
+        if let Some(name) = &device.info.name {
+            line += &format!(" name={}", quote(name));
+        }
+        lines.push(line);
+    }
+    for toggle in toggles {
+        let mut line = format!("toggle {} pipeline={} value={} size={}", quote(&toggle.id), toggle.pipeline, toggle.value, toggle.size);
+        if let Some(name) = &toggle.name {
+            line += &format!(" name={}", quote(name));
+        }
+        lines.push(line);
+    }
+    lines.into_iter().map(|line| line + "\n").collect()
+}
+
+#[test]
+fn unittest() {
+    use crate::introspect::DeviceState;
+
+    assert_eq!(temp_path_for(Path::new("/run/evsieve.status")), Path::new("/run/.evsieve.status.tmp"));
+    assert_eq!(temp_path_for(Path::new("status")), Path::new(".status.tmp"));
+
+    let devices = vec![
+        DeviceStatus {
+            info: DeviceInfo {
+                path: "/dev/input/event0".to_owned(), name: Some("My keyboard".to_owned()), domain: Some("kb".to_owned()),
+                pipeline: 0, state: DeviceState::Active,
+            },
+            events_read: Some(12),
+        },
+        DeviceStatus {
+            info: DeviceInfo {
+                path: "/dev/input/by-id/mouse".to_owned(), name: None, domain: None, pipeline: 1, state: DeviceState::Revoked,
+            },
+            events_read: None,
+        },
+    ];
+    let toggles = vec![
+        ToggleStatus { id: "profile".to_owned(), pipeline: 0, value: 2, size: 2, name: Some("night".to_owned()) },
+        ToggleStatus { id: "layer".to_owned(), pipeline: 1, value: 1, size: 3, name: None },
+    ];
+    assert_eq!(format_status(42, &devices, &toggles), concat!(
+        "pid 42\n",
+        "device /dev/input/event0 pipeline=0 domain=kb state=active events=12 name=\"My keyboard\"\n",
+        "device /dev/input/by-id/mouse pipeline=1 state=revoked\n",
+        "toggle profile pipeline=0 value=2 size=2 name=night\n",
+        "toggle layer pipeline=1 value=1 size=3\n",
+    ));
+    assert_eq!(format_status(42, &[], &[]), "pid 42\n");
+}
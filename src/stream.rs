@@ -8,10 +8,35 @@ pub mod delay;
 pub mod merge;
 pub mod absrel;
 pub mod scale;
+pub mod curve;
+pub mod invert_scroll;
+pub mod macro_slot;
 pub mod sink;
 pub mod capability_override;
+pub mod mirror;
+pub mod quirk;
+pub mod gyro;
+pub mod mouse_keys;
+pub mod slow_keys;
+pub mod bounce_keys;
+pub mod inhibit_while_typing;
+pub mod max_hold;
+pub mod abs_pointer;
+pub mod default_block;
+pub mod gate;
+pub mod histogram;
+pub mod sequence;
+pub mod edge;
+pub mod press_duration;
+pub mod schedule;
+pub mod vt;
+pub mod bypass;
+pub mod hand_mirror;
+pub mod remap_modifiers;
+pub mod pressure_click;
+pub mod plugin;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 use withhold::HookGroup;
 
@@ -20,16 +45,46 @@ use self::map::{Map, Toggle};
 use self::hook::Hook;
 use self::print::EventPrinter;
 use self::scale::Scale;
+use self::curve::Curve;
+use self::invert_scroll::InvertScroll;
+use self::macro_slot::MacroSlots;
 use self::merge::Merge;
 use self::capability_override::CapabilityOverride;
+use self::mirror::CapabilityMirror;
+use self::quirk::QuirkEntry;
+use self::gyro::GyroToRel;
+use self::mouse_keys::MouseKeys;
+use self::slow_keys::SlowKeys;
+use self::bounce_keys::BounceKeys;
+use self::inhibit_while_typing::InhibitWhileTyping;
+use self::max_hold::MaxHold;
+use self::abs_pointer::AbsPointer;
+use self::default_block::DefaultBlock;
+use self::gate::Gated;
+use self::histogram::Histogram;
+use self::sequence::Sequence;
+use self::edge::Edge;
+use self::press_duration::PressDuration;
+use self::schedule::Schedule;
+use self::vt::VtSwitch;
+use self::bypass::Bypass;
+use self::hand_mirror::HandMirror;
+use self::remap_modifiers::RemapModifiers;
+use self::pressure_click::PressureClick;
+use self::plugin::Plugin;
+use crate::io::vt::VtNumber;
+use crate::event::StageIndex;
 
 use crate::io::input::InputDevice;
 use crate::state::{State, ToggleIndex};
-use crate::event::Event;
+use crate::event::{Event, EventCode};
 use crate::capability::{Capability, InputCapabilites};
+use crate::domain::Domain;
+use crate::event::Namespace;
 use crate::io::output::OutputSystem;
+use crate::error::ArgumentError;
 use crate::loopback::{Loopback, LoopbackHandle, Delay};
-use crate::time::Instant;
+use crate::time::{Clock, Instant};
 
 /// An enum of everything that can be part of the event processing stream.
 ///
@@ -59,9 +114,37 @@ pub enum StreamEntry {
     Print(EventPrinter),
     Merge(Merge),
     Scale(Scale),
+    Curve(Curve),
+    InvertScroll(InvertScroll),
     RelToAbs(RelToAbs),
     Delay(self::delay::Delay),
+    MacroSlots(MacroSlots),
     CapabilityOverride(CapabilityOverride),
+    AbsPointer(AbsPointer),
+    CapabilityMirror(CapabilityMirror),
+    Quirk(QuirkEntry),
+    GyroToRel(GyroToRel),
+    MouseKeys(MouseKeys),
+    SlowKeys(SlowKeys),
+    BounceKeys(BounceKeys),
+    InhibitWhileTyping(InhibitWhileTyping),
+    MaxHold(MaxHold),
+    DefaultBlock(DefaultBlock),
+    Histogram(Histogram),
+    Sequence(Sequence),
+    Edge(Edge),
+    PressDuration(PressDuration),
+    HandMirror(HandMirror),
+    RemapModifiers(RemapModifiers),
+    PressureClick(PressureClick),
+    Plugin(Plugin),
+    Schedule(Schedule),
+    VtSwitch(VtSwitch),
+    Bypass(Bypass),
+    /// Marks the point where a --stage argument starts a new stage. All events that get generated
+    /// or changed by the entries after this marker get tagged with its stage.
+    Stage(StageIndex),
+    Gated(Gated),
 }
 
 pub struct Setup<T> {
@@ -69,7 +152,11 @@ pub struct Setup<T> {
     output: T,
     state: State,
     toggle_indices: HashMap<String, ToggleIndex>,
+    /// The names of the stages, where the index of each name is its StageIndex.
+    stage_names: Vec<String>,
     loopback: Loopback,
+    /// Where the stream gets the current time from.
+    clock: Box<dyn Clock>,
     /// The capabilities all input devices are capable of, and the tentative capabilites of devices that
     /// may be (re)opened in the future. If a new device gets opened, make sure to call `update_caps`
     /// with that device to keep the bookholding straight.
@@ -77,6 +164,11 @@ pub struct Setup<T> {
     /// A vector of events that have been "sent" to an output device but are not actually written
     /// to it yet because we await an EV_SYN event.
     staged_events: Vec<Event>,
+    /// The domains of the input devices with the packets flag. Their events do not enter the stream one
+    /// by one, but all events between two SYN_REPORTs enter the stream together.
+    packet_domains: HashSet<Domain>,
+    /// For each packet domain, the events that have been received since its last SYN_REPORT.
+    pending_packets: HashMap<Domain, Vec<Event>>,
 }
 
 impl<T> Setup<T> {
@@ -85,17 +177,57 @@ impl<T> Setup<T> {
         output: T,
         state: State,
         toggle_indices: HashMap<String, ToggleIndex>,
+        stage_names: Vec<String>,
         input_caps: InputCapabilites,
+        clock: Box<dyn Clock>,
     ) -> Self {
-        Self {
-            stream, output, state, toggle_indices, input_caps,
+        let num_entries = stream.len();
+        let mut setup = Self {
+            stream, output, state, toggle_indices, stage_names, input_caps, clock,
             loopback: Loopback::new(), staged_events: Vec::new(),
+            packet_domains: HashSet::new(), pending_packets: HashMap::new(),
+        };
+        setup.schedule_initial_wakeups(0 .. num_entries);
+        setup
+    }
+
+    /// The current time according to the clock of the stream. Use this as time for events that enter it.
+    pub fn now(&self) -> Instant {
+        self.clock.now()
+    }
+
+    /// Makes the events of the given domains enter the stream one packet at a time, as requested by the
+    /// packets flag on --input.
+    pub fn set_packet_domains(&mut self, domains: impl IntoIterator<Item=Domain>) {
+        self.packet_domains = domains.into_iter().collect();
+    }
+
+    /// Gives the stream entries in the given range that wake up by themselves a chance to schedule their
+    /// first wakeup.
+    fn schedule_initial_wakeups(&mut self, range: std::ops::Range<usize>) {
+        let mut loopback_handle = self.loopback.get_handle(self.clock.now());
+        for entry in &mut self.stream[range] {
+            if let StreamEntry::Schedule(schedule) = entry {
+                schedule.start(&mut loopback_handle);
+            }
         }
     }
 
 
+    /// Computes the capabilities of the events that leave the stream if the events that enter the stream
+    /// have the given capabilities.
+    pub fn run_caps(&self, caps: Vec<Capability>) -> Vec<Capability> {
+        run_caps(&self.stream, caps)
+    }
+
     pub fn time_until_next_wakeup(&self) -> Delay {
-        self.loopback.time_until_next_wakeup()
+        self.loopback.time_until_next_wakeup(self.clock.now(), self.clock.time_suspended())
+    }
+
+    /// Makes arguments like --delay wait `factor` times as long as they were configured to. Only affects
+    /// the events that get scheduled after calling this function.
+    pub fn set_time_scale(&mut self, factor: f64) {
+        self.loopback.set_time_scale(factor);
     }
 
     pub fn toggle_indices(&self) -> &HashMap<String, ToggleIndex> {
@@ -109,13 +241,90 @@ impl<T> Setup<T> {
     pub fn state_mut(&mut self) -> &mut State {
         &mut self.state
     }
+
+    pub fn stage_names(&self) -> &[String] {
+        &self.stage_names
+    }
+
+    /// Returns whether any --on-vt argument needs to know which virtual terminal is active.
+    pub fn uses_vt(&self) -> bool {
+        self.stream.iter().any(|entry| matches!(entry, StreamEntry::VtSwitch(_)))
+    }
+
+    /// Informs the --on-vt arguments that the active virtual terminal has changed. `previous` is
+    /// None if the active virtual terminal was not known before.
+    pub fn vt_changed(&mut self, previous: Option<VtNumber>, current: VtNumber) {
+        for entry in &self.stream {
+            if let StreamEntry::VtSwitch(vt_switch) = entry {
+                vt_switch.on_vt_change(previous, current, &mut self.state);
+            }
+        }
+    }
+
+    /// Returns the reports of all --histogram arguments, including disabled ones.
+    pub fn histogram_reports(&self) -> Vec<String> {
+        self.stream.iter().filter_map(|entry| match entry {
+            StreamEntry::Histogram(histogram) => Some(histogram.report()),
+            StreamEntry::Gated(gated) => match &*gated.entry {
+                StreamEntry::Histogram(histogram) => Some(histogram.report()),
+                _ => None,
+            },
+            _ => None,
+        }).collect()
+    }
 }
 
 impl<T: OutputSystem> Setup<T> {
+    /// Returns one line for each output device that explains whether and why it has been recreated.
+    pub fn output_recreation_reports(&self) -> Vec<String> {
+        self.output.describe_recreations()
+    }
+
+    /// Moves the pointer of all output devices with an abs-pointer= clause to the given position,
+    /// optionally clicking there. The events are written directly to the output devices.
+    pub fn warp(&mut self, x: i32, y: i32, click: bool) -> Result<(), ArgumentError> {
+        let mut reports = Vec::new();
+        for entry in &mut self.stream {
+            let abs_pointer = match entry {
+                StreamEntry::AbsPointer(abs_pointer) => abs_pointer,
+                StreamEntry::Gated(gated) => match &mut *gated.entry {
+                    StreamEntry::AbsPointer(abs_pointer) => abs_pointer,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            reports.push(abs_pointer.warp_events(x, y, click)?);
+        }
+        if reports.is_empty() {
+            return Err(ArgumentError::new("The warp command requires an output device with an abs-pointer= clause."));
+        }
+
+        // All devices first move, then all devices release their click.
+        let num_steps = reports.iter().map(Vec::len).max().unwrap_or(0);
+        for step in 0 .. num_steps {
+            for device_reports in &reports {
+                if let Some(report) = device_reports.get(step) {
+                    self.output.route_events(report);
+                }
+            }
+            self.output.synchronize();
+        }
+        Ok(())
+    }
+
+    /// Retries writing events that the output devices did not accept earlier. Returns true if some
+    /// events are still waiting to be written.
+    pub fn flush_output_queues(&mut self) -> bool {
+        self.output.flush_queues()
+    }
+
     /// Call this function if the capabilities of a certain input device may have changed, e.g. because
     /// it has been reopened after the program started. If the new capabilities are incompatible with
     /// its previous capabilities, then output devices may be recreated.
     pub fn update_caps(&mut self, new_device: &InputDevice) {
+        if let Some(resync_domain) = new_device.resync_domain() {
+            self.input_caps.insert(resync_domain, new_device.capabilities().clone());
+        }
         let old_caps_opt = self.input_caps.insert(
             new_device.domain(),
             new_device.capabilities().clone()
@@ -127,16 +336,85 @@ impl<T: OutputSystem> Setup<T> {
             }
         }
 
-        let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(&self.input_caps);
-        let caps_out = run_caps(&self.stream, caps_vec);
+        let caps_out = determine_output_capabilities(
+            &self.stream, &self.input_caps, &self.state, &self.output.strict_caps_domains()
+        );
+        self.output.update_caps(caps_out);
+    }
+
+    /// Inserts stream entries that were compiled while evsieve is running directly after the marker of
+    /// the given stage, or at the end of the stream if None. The entries must have been compiled against
+    /// the given state, toggle indices and stage names, which replace the current ones and must extend them.
+    ///
+    /// Afterwards, the output devices get the capabilities that the new entries may need. This may cause
+    /// output devices to be recreated.
+    pub fn splice(
+        &mut self,
+        after_stage: Option<StageIndex>,
+        entries: Vec<StreamEntry>,
+        state: State,
+        toggle_indices: HashMap<String, ToggleIndex>,
+        stage_names: Vec<String>,
+    ) {
+        let position = after_stage.and_then(|stage| self.stream.iter()
+            .position(|entry| matches!(entry, StreamEntry::Stage(index) if *index == stage))
+            .map(|marker_position| marker_position + 1)
+        ).unwrap_or(self.stream.len());
+        let num_entries = entries.len();
+        self.stream.splice(position .. position, entries);
+        self.state = state;
+        self.toggle_indices = toggle_indices;
+        self.stage_names = stage_names;
+        self.schedule_initial_wakeups(position .. position + num_entries);
+
+        let caps_out = determine_output_capabilities(
+            &self.stream, &self.input_caps, &self.state, &self.output.strict_caps_domains()
+        );
         self.output.update_caps(caps_out);
     }
 
+    /// Call this function when an input device has been (re)opened, so conditions on its LEDs start from
+    /// the state the LEDs actually have.
+    pub fn update_leds(&mut self, device: &InputDevice) {
+        for (code, value) in device.led_values() {
+            self.state.set_led((code, device.domain()), value);
+        }
+    }
+
+    /// Call this function before releasing the keys of a device that got disconnected but will be reopened
+    /// if it comes back. The hooks remember which of their keys that device was holding, so chords are not
+    /// broken if the device returns with those keys still held, e.g. after a wireless dropout.
+    ///
+    /// Hooks that are followed by a --withhold argument do not remember their keys.
+    pub fn suspend_hooks(&mut self, device: &InputDevice) {
+        let pressed_keys: Vec<EventCode> = device.get_pressed_keys().collect();
+        for hook in standalone_hooks(&mut self.stream) {
+            hook.trigger.suspend_domain(device.domain(), &pressed_keys);
+        }
+    }
+
+    /// Call this function when a device has been reopened. Restores the hook state that was saved by
+    /// `suspend_hooks()` for the keys that are still held according to the reopened device.
+    pub fn restore_hooks(&mut self, device: &InputDevice) {
+        let pressed_keys: Vec<EventCode> = device.get_pressed_keys().collect();
+        let mut loopback_handle = self.loopback.get_handle(self.clock.now());
+        for hook in standalone_hooks(&mut self.stream) {
+            hook.trigger.restore_domain(device.domain(), &pressed_keys, &mut loopback_handle);
+        }
+    }
+
     /// Handles a single event that was generated by an input device. This is the function other
     /// modules are supposed to call when they have an input event they want to get handled.
     pub fn run(&mut self, time: Instant, event: Event) {
+        // The kernel tells us when the LEDs of an input device change. Keep track of them for the
+        // arguments that are only enabled while some LED is on.
+        if event.ev_type().is_led() {
+            self.state.set_led(event.channel(), event.value);
+        }
+
         if event.ev_type().is_syn() {
-            self.syn();
+            self.run_packet(time, event.domain);
+            self.synchronize();
         } else {
             // If the auto-scan feature is enabled, MSC_SCAN events will be automatically
             // generated and are therefore blocked just like EV_SYN events are.
@@ -146,6 +424,17 @@ impl<T: OutputSystem> Setup<T> {
                 }
             }
 
+            if self.packet_domains.contains(&event.domain) {
+                self.pending_packets.entry(event.domain).or_default().push(event);
+                return;
+            }
+
+            if let Some(event) = self.try_bypass(event) {
+                self.output.route_events(&[event]);
+                self.output.synchronize();
+                return;
+            }
+
             let mut loopback_handle = self.loopback.get_handle(time);
             let mut events_out = Vec::new();
 
@@ -153,6 +442,7 @@ impl<T: OutputSystem> Setup<T> {
                 vec![event],
                 &mut events_out,
                 &mut self.stream,
+                0,
                 &mut self.state,
                 &mut loopback_handle,
             );
@@ -171,16 +461,118 @@ impl<T: OutputSystem> Setup<T> {
                 _ => {
                     for event in events_out {
                         self.staged_events.push(event);
-                        self.syn();
+                        self.synchronize();
                     }
                 }
             }
+
+            self.apply_recenter_commands(time);
+            self.apply_macro_commands(time);
         }
     }
 
+    /// Runs the events that the given domain has sent since its last SYN_REPORT through the stream at
+    /// once, so the stream entries can treat them as a whole. All resulting events get synchronised
+    /// together, unlike the events that result from a single event.
+    fn run_packet(&mut self, time: Instant, domain: Domain) {
+        let packet = match self.pending_packets.remove(&domain) {
+            Some(packet) => packet,
+            None => return,
+        };
+        let mut events_in = Vec::with_capacity(packet.len());
+        for event in packet {
+            match self.try_bypass(event) {
+                Some(event) => self.output.route_events(&[event]),
+                None => events_in.push(event),
+            }
+        }
+        if events_in.is_empty() {
+            return;
+        }
+
+        let mut loopback_handle = self.loopback.get_handle(time);
+        run_events(
+            events_in,
+            &mut self.staged_events,
+            &mut self.stream,
+            0,
+            &mut self.state,
+            &mut loopback_handle,
+        );
+        self.apply_recenter_commands(time);
+        self.apply_macro_commands(time);
+    }
+
+    /// Recenters the --rel-to-abs arguments as requested by hooks.
+    fn apply_recenter_commands(&mut self, time: Instant) {
+        for domain in self.state.take_recenter_commands() {
+            let mut loopback_handle = self.loopback.get_handle(time);
+            run_recenter(domain, &mut self.staged_events, &mut self.stream, &mut self.state, &mut loopback_handle);
+        }
+    }
+
+    /// Carries out the record-start, record-stop and play commands of hooks as soon as the events that
+    /// triggered them have been processed, regardless of whether the hooks come before or after the
+    /// --macro-slot argument.
+    fn apply_macro_commands(&mut self, time: Instant) {
+        let commands = self.state.take_macro_commands();
+        if commands.is_empty() {
+            return;
+        }
+        let mut loopback_handle = self.loopback.get_handle(time);
+        for entry in &mut self.stream {
+            let macro_slots = match entry {
+                StreamEntry::MacroSlots(macro_slots) => macro_slots,
+                StreamEntry::Gated(gated) => match &mut *gated.entry {
+                    StreamEntry::MacroSlots(macro_slots) => macro_slots,
+                    _ => continue,
+                },
+                _ => continue,
+            };
+            for &command in &commands {
+                macro_slots.execute(command, &mut loopback_handle);
+            }
+        }
+    }
+
+    /// Returns the channels of all --rel-to-abs arguments with the given domain to the center of their
+    /// output range, or the channels of all domains if None.
+    pub fn recenter(&mut self, domain: Option<Domain>) -> Result<(), ArgumentError> {
+        let has_rel_to_abs = self.stream.iter().any(|entry| match entry {
+            StreamEntry::RelToAbs(_) => true,
+            StreamEntry::Gated(gated) => matches!(*gated.entry, StreamEntry::RelToAbs(_)),
+            _ => false,
+        });
+        if ! has_rel_to_abs {
+            return Err(ArgumentError::new("The recenter command requires a --rel-to-abs argument."));
+        }
+
+        let mut loopback_handle = self.loopback.get_handle(self.clock.now());
+        run_recenter(domain, &mut self.staged_events, &mut self.stream, &mut self.state, &mut loopback_handle);
+        self.synchronize();
+        Ok(())
+    }
+
+    /// If the event is matched by a --bypass argument, returns the event as it should be written to an
+    /// output device without entering the stream. The --bypass arguments are always at the start of
+    /// the stream, possibly preceded by capability mirrors which do not process events.
+    fn try_bypass(&mut self, event: Event) -> Option<Event> {
+        for entry in &mut self.stream {
+            match entry {
+                StreamEntry::Bypass(bypass) => if let Some(event) = bypass.apply(event) {
+                    return Some(event);
+                },
+                StreamEntry::CapabilityMirror(_) => continue,
+                _ => return None,
+            }
+        }
+        None
+    }
+
     /// Runs all events from the loopback device that were due before `now`. If running such an event causes
     /// other events to get added that are due before now, then those events get processed as well.
     pub fn wakeup_until(&mut self, now: Instant) {
+        self.loopback.observe_suspend(now, self.clock.time_suspended());
         while let Some((instant, token)) = self.loopback.poll_once(now) {
             let mut loopback_handle = self.loopback.get_handle(instant);
             run_wakeup(
@@ -190,21 +582,124 @@ impl<T: OutputSystem> Setup<T> {
                 &mut self.state,
                 &mut loopback_handle,
             );
-            
-            self.syn();
+            self.apply_recenter_commands(instant);
+            self.apply_macro_commands(instant);
+
+            self.synchronize();
         };
     }
 
+    /// Call this function after running events that did not come with a SYN_REPORT of their own, such as
+    /// events that were synthesized for an input device. Ends the packets of all domains that are waiting
+    /// for one, then writes all staged events to the output devices.
     pub fn syn(&mut self) {
+        let now = self.clock.now();
+        let mut domains: Vec<Domain> = self.pending_packets.keys().copied().collect();
+        domains.sort_by_key(|domain| domain.to_raw());
+        for domain in domains {
+            self.run_packet(now, domain);
+        }
+        self.synchronize();
+    }
+
+    /// Writes all staged events to the output devices, followed by a SYN.
+    fn synchronize(&mut self) {
         self.output.route_events(&self.staged_events);
         self.staged_events.clear();
         self.output.synchronize();
     }
 }
 
-pub fn determine_output_capabilities(stream: &[StreamEntry], input_caps: &InputCapabilites) -> Vec<Capability> {
-    let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(&input_caps);
-    run_caps(&stream, caps_vec)
+/// The maximum amount of combinations of toggle states that will be analysed for output devices
+/// with the strict-caps flag. Beyond this, the analysis takes more time than it is worth.
+const MAX_TOGGLE_COMBINATIONS: usize = 256;
+
+/// Computes the capabilities of all output devices. The capabilities of the output devices whose domain
+/// is in `strict_domains` are computed separately for each combination of toggle states, so that codes
+/// which cannot reach those devices under any combination are left out.
+pub fn determine_output_capabilities(
+        stream: &[StreamEntry], input_caps: &InputCapabilites, state: &State, strict_domains: &[Domain]
+) -> Vec<Capability> {
+    let caps_vec: Vec<Capability> = crate::capability::input_caps_to_vec(input_caps);
+    if strict_domains.is_empty() {
+        return run_caps(stream, caps_vec);
+    }
+
+    let hedged_caps = run_caps(stream, caps_vec.clone());
+    let strict_caps = match run_caps_per_toggle_state(stream, caps_vec, state) {
+        Some(caps) => caps,
+        None => {
+            crate::utils::warn_once(format!(
+                "Warning: the toggles in this script can be in more than {} different combinations of states. The strict-caps flag of --output will have no effect.",
+                MAX_TOGGLE_COMBINATIONS
+            ));
+            return hedged_caps;
+        }
+    };
+
+    let is_strict = |cap: &Capability| cap.namespace == Namespace::Output && strict_domains.contains(&cap.domain);
+    hedged_caps.into_iter()
+        .filter(|cap| ! is_strict(cap))
+        .chain(strict_caps.into_iter().filter(is_strict))
+        .collect()
+}
+
+/// Computes the union of the capabilities that can leave the stream for each combination of states
+/// the toggles in the stream can be in. Returns None if there are too many such combinations.
+fn run_caps_per_toggle_state(stream: &[StreamEntry], caps: Vec<Capability>, state: &State) -> Option<Vec<Capability>> {
+    let mut indices: Vec<ToggleIndex> = Vec::new();
+    collect_toggle_indices(stream, &mut indices);
+    let sizes: Vec<usize> = indices.iter().map(|&index| state[index].size()).collect();
+    let num_combinations = sizes.iter()
+        .try_fold(1_usize, |total, &size| total.checked_mul(size))
+        .filter(|&total| total <= MAX_TOGGLE_COMBINATIONS)?;
+
+    let mut result: Vec<Capability> = Vec::new();
+    for combination in 0 .. num_combinations {
+        let mut remainder = combination;
+        let toggle_values: HashMap<ToggleIndex, usize> = indices.iter().zip(&sizes)
+            .map(|(&index, &size)| {
+                let value = remainder % size;
+                remainder /= size;
+                (index, value)
+            })
+            .collect();
+        result.extend(run_caps_with_toggle_values(stream, caps.clone(), Some(&toggle_values)));
+    }
+
+    Some(crate::capability::aggregate_capabilities(result))
+}
+
+/// Adds the indices of all toggles that can affect the path of events through the stream to `indices`.
+fn collect_toggle_indices(stream: &[StreamEntry], indices: &mut Vec<ToggleIndex>) {
+    for entry in stream {
+        let index = match entry {
+            StreamEntry::Toggle(toggle) => toggle.state_index,
+            StreamEntry::Gated(gated) => {
+                collect_toggle_indices(std::slice::from_ref(&*gated.entry), indices);
+                match gated.toggle_index() {
+                    Some(toggle_index) => toggle_index,
+                    None => continue,
+                }
+            },
+            _ => continue,
+        };
+        if ! indices.contains(&index) {
+            indices.push(index);
+        }
+    }
+}
+
+/// Returns all hooks in the stream that are not part of a HookGroup, including those behind a gate.
+fn standalone_hooks(stream: &mut [StreamEntry]) -> impl Iterator<Item=&mut Hook> {
+    stream.iter_mut().filter_map(|entry| match entry {
+        StreamEntry::Hook(hook) => Some(hook),
+        StreamEntry::Gated(gated) => match &mut *gated.entry {
+            StreamEntry::Hook(hook) => Some(hook),
+            _ => None,
+        },
+        _ => None,
+    })
 }
 
 /// Starts processing the stream at a given starting point.
@@ -216,75 +711,204 @@ pub fn determine_output_capabilities(stream: &[StreamEntry], input_caps: &InputC
 /// read from actual input devices. This advanced configurability is mainly intended for the
 /// `wakeup()` function to be able to pause and resume event processing at a later point in time.
 /// 
-/// `stream` may be the empty slice.
-fn run_events(events_in: Vec<Event>, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], state: &mut State, loopback: &mut LoopbackHandle) {
+/// `stream` may be the empty slice. `stage` is the stage that the first entry of `stream` belongs to.
+fn run_events(events_in: Vec<Event>, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], mut stage: StageIndex, state: &mut State, loopback: &mut LoopbackHandle) {
     let mut events: Vec<Event> = events_in;
     let mut buffer: Vec<Event> = Vec::new();
 
     for entry in stream {
+        let entry = match entry {
+            StreamEntry::Stage(new_stage) => {
+                stage = *new_stage;
+                continue;
+            },
+            StreamEntry::Gated(gated) => {
+                if ! gated.is_enabled(state) {
+                    continue;
+                }
+                if events.iter().all(|event| gated.accepts(event)) {
+                    &mut *gated.entry
+                } else {
+                    // Run the events through the inner entry one at a time, so the events from other
+                    // stages keep their place among the events that the inner entry generates.
+                    for event in events.drain(..) {
+                        if gated.accepts(&event) {
+                            run_events(vec![event], &mut buffer, std::slice::from_mut(&mut *gated.entry), stage, state, loopback);
+                        } else {
+                            buffer.push(event);
+                        }
+                    }
+                    std::mem::swap(&mut events, &mut buffer);
+                    continue;
+                }
+            },
+            entry => entry,
+        };
+
         // TODO: (low-priority) Maybe it is time to write a trait with some default implementations
         // for the following almost-copy-pasta?
         match entry {
             StreamEntry::Map(map) => {
-                map.apply_to_all(&events, &mut buffer);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| map.apply_to_all(events, buffer));
             },
             StreamEntry::Toggle(toggle) => {
-                toggle.apply_to_all(&events, &mut buffer, state);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| toggle.apply_to_all(events, buffer, state));
             },
             StreamEntry::Merge(merge) => {
-                merge.apply_to_all(&events, &mut buffer);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| merge.apply_to_all(events, buffer));
             },
             StreamEntry::RelToAbs(rel_to_abs) => {
-                rel_to_abs.apply_to_all(&events, &mut buffer);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| rel_to_abs.apply_to_all(events, buffer, loopback));
             },
             StreamEntry::Hook(hook) => {
-                hook.apply_to_all(&events, &mut buffer, state, loopback);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| hook.apply_to_all(events, buffer, state, loopback));
             },
             StreamEntry::HookGroup(hook_group) => {
-                hook_group.apply_to_all(&events, &mut buffer, state, loopback);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_batch(&mut events, &mut buffer, stage, |events, buffer| hook_group.apply_to_all(events, buffer, state, loopback));
             },
             StreamEntry::Scale(scale) => {
-                scale.apply_to_all(&events, &mut buffer);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| scale.apply_to_all(events, buffer));
+            },
+            StreamEntry::Curve(curve) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| curve.apply_to_all(events, buffer));
+            },
+            StreamEntry::InvertScroll(invert_scroll) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| invert_scroll.apply_to_all(events, buffer, state));
+            },
+            StreamEntry::Quirk(quirk) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| quirk.apply_to_all(events, buffer));
+            },
+            StreamEntry::GyroToRel(gyro_to_rel) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| gyro_to_rel.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::MouseKeys(mouse_keys) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| mouse_keys.apply_to_all(events, buffer, loopback));
             },
+            StreamEntry::SlowKeys(slow_keys) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| slow_keys.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::BounceKeys(bounce_keys) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| bounce_keys.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::InhibitWhileTyping(inhibit_while_typing) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| inhibit_while_typing.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::MaxHold(max_hold) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| max_hold.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::DefaultBlock(default_block) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| default_block.apply_to_all(events, buffer));
+            },
+            StreamEntry::Sequence(sequence) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| sequence.apply_to_all(events, buffer, state, loopback));
+            },
+            StreamEntry::Edge(edge) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| edge.apply_to_all(events, buffer));
+            },
+            StreamEntry::PressDuration(press_duration) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| press_duration.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::HandMirror(hand_mirror) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| hand_mirror.apply_to_all(events, buffer));
+            },
+            StreamEntry::RemapModifiers(remap_modifiers) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| remap_modifiers.apply_to_all(events, buffer));
+            },
+            StreamEntry::Plugin(plugin) => {
+                apply_batch(&mut events, &mut buffer, stage, |events, buffer| plugin.apply_to_all(events, buffer));
+            },
+            StreamEntry::PressureClick(pressure_click) => {
+                apply_each(&mut events, &mut buffer, stage, |events, buffer| pressure_click.apply_to_all(events, buffer));
+            },
+            StreamEntry::Schedule(_) => {},
+            StreamEntry::VtSwitch(_) => {},
+            StreamEntry::Bypass(_) => {},
             StreamEntry::Delay(delay) => {
-                delay.apply_to_all(&events, &mut buffer, loopback);
-                events.clear();
-                std::mem::swap(&mut events, &mut buffer);
+                apply_batch(&mut events, &mut buffer, stage, |events, buffer| delay.apply_to_all(events, buffer, loopback));
+            },
+            StreamEntry::MacroSlots(macro_slots) => {
+                apply_batch(&mut events, &mut buffer, stage, |events, buffer| macro_slots.apply_to_all(events, buffer, loopback));
             },
             StreamEntry::Print(printer) => {
-                printer.apply_to_all(&events);
+                printer.apply_to_all(&events, loopback);
+            },
+            StreamEntry::Histogram(histogram) => {
+                histogram.apply_to_all(&events);
             },
             StreamEntry::CapabilityOverride(_) => {},
+            StreamEntry::AbsPointer(_) => {},
+            StreamEntry::CapabilityMirror(_) => {},
+            StreamEntry::Stage(_) => unreachable!(),
+            StreamEntry::Gated(_) => unreachable!("Gated stream entries cannot be nested."),
         }
+
     }
 
     events_out.extend(events);
 }
 
+/// Runs an entry that handles each event independently of the other events, and replaces `events` by
+/// the events that the entry outputs. If there is a stage to tag the events with, the entry is given
+/// one event at a time, so each output only needs to be compared to the event it came from to find out
+/// whether it was generated or changed by this entry.
+fn apply_each(events: &mut Vec<Event>, buffer: &mut Vec<Event>, stage: StageIndex, mut apply: impl FnMut(&[Event], &mut Vec<Event>)) {
+    match stage {
+        0 => apply(events, buffer),
+        _ => for event in events.iter() {
+            let start = buffer.len();
+            apply(std::slice::from_ref(event), buffer);
+            tag_new_events(std::slice::from_ref(event), &mut buffer[start..], stage);
+        },
+    }
+    events.clear();
+    std::mem::swap(events, buffer);
+}
+
+/// Like `apply_each()`, but for entries whose output depends on which events arrive together, such
+/// as hook groups and delays. These get the whole batch at once, even if it is empty.
+fn apply_batch(events: &mut Vec<Event>, buffer: &mut Vec<Event>, stage: StageIndex, mut apply: impl FnMut(&[Event], &mut Vec<Event>)) {
+    apply(events, buffer);
+    if stage != 0 {
+        tag_new_events(events, buffer, stage);
+    }
+    events.clear();
+    std::mem::swap(events, buffer);
+}
+
+/// Tags all events that are not identical to one of the events from before some entry processed them
+/// with the given stage.
+fn tag_new_events(events_before: &[Event], events: &mut [Event], stage: StageIndex) {
+    let mut unchanged_events: Vec<&Event> = events_before.iter().collect();
+    for event in events {
+        match unchanged_events.iter().position(|&unchanged_event| unchanged_event == event) {
+            Some(index) => {
+                unchanged_events.swap_remove(index);
+            },
+            None => event.stage = stage,
+        }
+    }
+}
+
 fn run_wakeup(token: crate::loopback::Token, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], state: &mut State, loopback: &mut LoopbackHandle) {
     let mut events: Vec<Event> = Vec::new();
+    let mut stage: StageIndex = 0;
 
     for index in 0 .. stream.len() {
-        match &mut stream[index] {
+        // Disabled entries are still woken up so they can release the events they were holding.
+        let entry = match &mut stream[index] {
+            StreamEntry::Stage(new_stage) => {
+                stage = *new_stage;
+                continue;
+            },
+            StreamEntry::Gated(gated) => &mut *gated.entry,
+            entry => entry,
+        };
+        match entry {
             StreamEntry::Map(_) => {},
             StreamEntry::Toggle(_) => {},
             StreamEntry::Merge(_) => {},
             StreamEntry::Hook(hook) => {
-                hook.wakeup(&token);
+                hook.wakeup(&token, &mut events);
             },
             StreamEntry::HookGroup(hook_group) => {
                 hook_group.wakeup(&token, &mut events);
@@ -292,15 +916,98 @@ fn run_wakeup(token: crate::loopback::Token, events_out: &mut Vec<Event>, stream
             StreamEntry::Delay(delay) => {
                 delay.wakeup(&token, &mut events);
             },
+            StreamEntry::MacroSlots(macro_slots) => {
+                macro_slots.wakeup(&token, &mut events);
+            },
             StreamEntry::Print(_) => {},
+            StreamEntry::Histogram(_) => {},
             StreamEntry::Scale(_) => {},
-            StreamEntry::RelToAbs(_) => {},
+            StreamEntry::Curve(_) => {},
+            StreamEntry::InvertScroll(_) => {},
+            StreamEntry::Quirk(_) => {},
+            StreamEntry::GyroToRel(_) => {},
+            StreamEntry::MouseKeys(mouse_keys) => {
+                mouse_keys.wakeup(&token, &mut events, loopback);
+            },
+            StreamEntry::SlowKeys(slow_keys) => {
+                slow_keys.wakeup(&token, &mut events);
+            },
+            StreamEntry::BounceKeys(bounce_keys) => {
+                bounce_keys.wakeup(&token, &mut events, loopback);
+            },
+            StreamEntry::InhibitWhileTyping(inhibit_while_typing) => {
+                inhibit_while_typing.wakeup(&token);
+            },
+            StreamEntry::MaxHold(max_hold) => {
+                max_hold.wakeup(&token, &mut events);
+            },
+            StreamEntry::DefaultBlock(_) => {},
+            StreamEntry::Sequence(sequence) => {
+                sequence.wakeup(&token, &mut events);
+            },
+            StreamEntry::Edge(_) => {},
+            StreamEntry::PressDuration(_) => {},
+            StreamEntry::HandMirror(_) => {},
+            StreamEntry::RemapModifiers(_) => {},
+            StreamEntry::PressureClick(_) => {},
+            StreamEntry::Plugin(_) => {},
+            StreamEntry::Schedule(schedule) => {
+                schedule.wakeup(&token, state, loopback);
+            },
+            StreamEntry::VtSwitch(_) => {},
+            StreamEntry::Bypass(_) => {},
+            StreamEntry::RelToAbs(rel_to_abs) => {
+                rel_to_abs.wakeup(&token, &mut events);
+            },
             StreamEntry::CapabilityOverride(_) => {},
+            StreamEntry::AbsPointer(_) => {},
+            StreamEntry::CapabilityMirror(_) => {},
+            StreamEntry::Stage(_) => unreachable!(),
+            StreamEntry::Gated(_) => unreachable!("Gated stream entries cannot be nested."),
         }
 
         if ! events.is_empty() {
+            // Events released upon wakeup count as generated by the stage of the entry that released them.
+            if stage != 0 {
+                for event in &mut events {
+                    event.stage = stage;
+                }
+            }
             // If index+1 == stream.len(), then stream[index+1..] is the empty slice.
-            run_events(events, events_out, &mut stream[index+1..], state, loopback);
+            run_events(events, events_out, &mut stream[index+1..], stage, state, loopback);
+            events = Vec::new();
+        }
+    }
+}
+
+/// Recenters all --rel-to-abs arguments and runs the events that they generate through the rest of the stream,
+/// like run_wakeup() does for the events generated upon wakeup.
+fn run_recenter(domain: Option<Domain>, events_out: &mut Vec<Event>, stream: &mut [StreamEntry], state: &mut State, loopback: &mut LoopbackHandle) {
+    let mut events: Vec<Event> = Vec::new();
+    let mut stage: StageIndex = 0;
+
+    for index in 0 .. stream.len() {
+        let rel_to_abs = match &mut stream[index] {
+            StreamEntry::Stage(new_stage) => {
+                stage = *new_stage;
+                continue;
+            },
+            StreamEntry::RelToAbs(rel_to_abs) => rel_to_abs,
+            StreamEntry::Gated(gated) => match &mut *gated.entry {
+                StreamEntry::RelToAbs(rel_to_abs) => rel_to_abs,
+                _ => continue,
+            },
+            _ => continue,
+        };
+        rel_to_abs.recenter(domain, &mut events, loopback);
+
+        if ! events.is_empty() {
+            if stage != 0 {
+                for event in &mut events {
+                    event.stage = stage;
+                }
+            }
+            run_events(events, events_out, &mut stream[index+1..], stage, state, loopback);
             events = Vec::new();
         }
     }
@@ -308,6 +1015,14 @@ fn run_wakeup(token: crate::loopback::Token, events_out: &mut Vec<Event>, stream
 
 /// A direct analogue for run_once(), except it runs through capabilities instead of events.
 pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Capability> {
+    run_caps_with_toggle_values(stream, capabilities, None)
+}
+
+/// Like `run_caps()`. If `toggle_values` is Some, then the toggles whose index it contains are assumed
+/// to have the given value, instead of being possibly in any state.
+fn run_caps_with_toggle_values(
+        stream: &[StreamEntry], capabilities: Vec<Capability>, toggle_values: Option<&HashMap<ToggleIndex, usize>>
+) -> Vec<Capability> {
     let mut caps: Vec<Capability> = capabilities;
     let mut buffer: Vec<Capability> = Vec::new();
     let mut last_num_caps = caps.len();
@@ -320,7 +1035,10 @@ pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Ca
                 std::mem::swap(&mut caps, &mut buffer);
             },
             StreamEntry::Toggle(toggle) => {
-                toggle.apply_to_all_caps(&caps, &mut buffer);
+                match toggle_values.and_then(|values| values.get(&toggle.state_index)) {
+                    Some(&value) => toggle.apply_to_all_caps_at(&caps, &mut buffer, value),
+                    None => toggle.apply_to_all_caps(&caps, &mut buffer),
+                }
                 caps.clear();
                 std::mem::swap(&mut caps, &mut buffer);
             },
@@ -340,6 +1058,102 @@ pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Ca
                 caps.clear();
                 std::mem::swap(&mut caps, &mut buffer);
             },
+            StreamEntry::Curve(curve) => {
+                curve.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::InvertScroll(invert_scroll) => {
+                invert_scroll.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Quirk(quirk) => {
+                quirk.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::GyroToRel(gyro_to_rel) => {
+                gyro_to_rel.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::MouseKeys(mouse_keys) => {
+                mouse_keys.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::SlowKeys(slow_keys) => {
+                slow_keys.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::BounceKeys(bounce_keys) => {
+                bounce_keys.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::InhibitWhileTyping(inhibit_while_typing) => {
+                inhibit_while_typing.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::MaxHold(max_hold) => {
+                max_hold.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::AbsPointer(abs_pointer) => {
+                abs_pointer.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::DefaultBlock(default_block) => {
+                default_block.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Sequence(sequence) => {
+                sequence.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Edge(edge) => {
+                edge.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::PressDuration(press_duration) => {
+                press_duration.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::HandMirror(hand_mirror) => {
+                hand_mirror.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::RemapModifiers(remap_modifiers) => {
+                remap_modifiers.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Plugin(plugin) => {
+                plugin.apply_to_all_caps(&caps, &mut buffer);
+            },
+            StreamEntry::PressureClick(pressure_click) => {
+                pressure_click.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Schedule(_) => {},
+            StreamEntry::VtSwitch(_) => {},
+            StreamEntry::Stage(_) => {},
+            StreamEntry::Bypass(bypass) => {
+                bypass.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
             StreamEntry::RelToAbs(rel_to_abs) => {
                 rel_to_abs.apply_to_all_caps(&caps, &mut buffer);
                 caps.clear();
@@ -350,12 +1164,51 @@ pub fn run_caps(stream: &[StreamEntry], capabilities: Vec<Capability>) -> Vec<Ca
                 caps.clear();
                 std::mem::swap(&mut caps, &mut buffer);
             },
+            StreamEntry::CapabilityMirror(capability_mirror) => {
+                capability_mirror.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
             StreamEntry::Print(print) => {
                 // A --print never changes the capabilities, but may need to load some extra
                 // data from the disk depending on which capabilities it needs to print.
                 print.observe_caps(&caps);
             },
+            StreamEntry::Histogram(_) => (),
             StreamEntry::Delay(_) => (),
+            // Replayed macros consist of events that have passed this point of the stream before,
+            // except for the macros that were loaded from the disk.
+            StreamEntry::MacroSlots(macro_slots) => {
+                macro_slots.apply_to_all_caps(&caps, &mut buffer);
+                caps.clear();
+                std::mem::swap(&mut caps, &mut buffer);
+            },
+            StreamEntry::Gated(gated) => {
+                let inner_entry = std::slice::from_ref(&*gated.entry);
+                let is_enabled = match gated.toggle_index() {
+                    Some(toggle_index) => toggle_values.and_then(|values| values.get(&toggle_index))
+                        .map(|&value| gated.is_enabled_at(value)),
+                    None => Some(true),
+                };
+                // The LEDs can be in any state.
+                let is_enabled = match is_enabled {
+                    Some(true) if gated.depends_on_leds() => None,
+                    is_enabled => is_enabled,
+                };
+                match is_enabled {
+                    Some(false) => (),
+                    Some(true) if ! gated.filters_stages() => {
+                        caps = run_caps_with_toggle_values(inner_entry, caps, toggle_values);
+                    },
+                    _ => {
+                        // The toggle may be in any state or the events may be from a stage that passes by
+                        // the inner entry, so events may or may not pass through the inner entry.
+                        buffer.extend(run_caps_with_toggle_values(inner_entry, caps.clone(), toggle_values));
+                        buffer.append(&mut caps);
+                        std::mem::swap(&mut caps, &mut buffer);
+                    },
+                }
+            },
         }
 
         // Merge capabilities that differ only in value together when possible.
@@ -0,0 +1,111 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the abs-pointer= clause of --output, which turns an output device into a direct pointing
+//! device like a touchscreen. Such devices report absolute coordinates, which makes it possible to move
+//! the pointer to a specific spot on the screen using the warp command of the control FIFOs.
+
+use crate::capability::{AbsMeta, Capability};
+use crate::domain::Domain;
+use crate::ecodes;
+use crate::error::ArgumentError;
+use crate::event::{Event, EventCode, EventValue, Namespace};
+use crate::range::{Interval, Set};
+
+pub struct AbsPointer {
+    /// The domain of the output device.
+    device: Domain,
+    width: i32,
+    height: i32,
+    abs_x: EventCode,
+    abs_y: EventCode,
+    btn_touch: EventCode,
+
+    /// State: modifiable at runtime.
+    /// The last position we warped to, used as previous value for the next warp.
+    position: (EventValue, EventValue),
+}
+
+impl AbsPointer {
+    pub fn new(device: Domain, width: i32, height: i32) -> Result<AbsPointer, ArgumentError> {
+        Ok(AbsPointer {
+            device, width, height,
+            abs_x: ecodes::event_code("abs", "x")?,
+            abs_y: ecodes::event_code("abs", "y")?,
+            btn_touch: ecodes::event_code("btn", "touch")?,
+            position: (0, 0),
+        })
+    }
+
+    /// Returns the reports that move the pointer to the given position. If `click` is true, the pointer
+    /// touches the screen at that position and gets lifted in a separate report afterwards.
+    pub fn warp_events(&mut self, x: EventValue, y: EventValue, click: bool) -> Result<Vec<Vec<Event>>, ArgumentError> {
+        if x < 0 || x >= self.width || y < 0 || y >= self.height {
+            return Err(ArgumentError::new(format!(
+                "Cannot warp to ({}, {}): the position must lie within the {}x{} area of the abs-pointer device.",
+                x, y, self.width, self.height
+            )));
+        }
+        let device = self.device;
+        let event = |code, value, previous_value| Event::new(code, value, previous_value, device, Namespace::Output);
+        let mut move_report = vec![
+            event(self.abs_x, x, self.position.0),
+            event(self.abs_y, y, self.position.1),
+        ];
+        self.position = (x, y);
+
+        if ! click {
+            return Ok(vec![move_report]);
+        }
+        move_report.push(event(self.btn_touch, 1, 0));
+        Ok(vec![move_report, vec![event(self.btn_touch, 0, 1)]])
+    }
+
+    /// Adds the abs:x, abs:y and btn:touch capabilities to the output device.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+
+        let meta = AbsMeta { flat: 0, fuzz: 0, resolution: 0, value: 0 };
+        let axes = [(self.abs_x, self.width), (self.abs_y, self.height)];
+        for &(code, size) in &axes {
+            output_caps.push(Capability {
+                code,
+                values: Set::from_unordered_intervals(vec![Interval::new(Some(0), Some(size - 1))]),
+                domain: self.device,
+                namespace: Namespace::Output,
+                abs_meta: Some(meta),
+            });
+        }
+        output_caps.push(Capability {
+            code: self.btn_touch,
+            values: Set::from_unordered_intervals(vec![Interval::new(Some(0), Some(1))]),
+            domain: self.device,
+            namespace: Namespace::Output,
+            abs_meta: None,
+        });
+    }
+}
+
+#[test]
+fn unittest() {
+    let device = crate::domain::get_unique_domain();
+    let mut pointer = AbsPointer::new(device, 1920, 1080).unwrap();
+    let summarize = |reports: Vec<Vec<Event>>| -> Vec<Vec<(String, EventValue)>> {
+        reports.into_iter().map(|report| report.into_iter().map(
+            |event| (ecodes::event_name(event.code).into_owned(), event.value)
+        ).collect()).collect()
+    };
+
+    assert_eq!(
+        summarize(pointer.warp_events(100, 200, false).unwrap()),
+        vec![vec![("abs:x".to_owned(), 100), ("abs:y".to_owned(), 200)]],
+    );
+    assert_eq!(
+        summarize(pointer.warp_events(1919, 1079, true).unwrap()),
+        vec![
+            vec![("abs:x".to_owned(), 1919), ("abs:y".to_owned(), 1079), ("btn:touch".to_owned(), 1)],
+            vec![("btn:touch".to_owned(), 0)],
+        ],
+    );
+    assert!(pointer.warp_events(1920, 0, false).is_err());
+    assert!(pointer.warp_events(0, -1, false).is_err());
+}
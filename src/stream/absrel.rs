@@ -3,9 +3,21 @@
 use std::collections::HashMap;
 
 use crate::key::Key;
-use crate::event::{Event, Channel};
-use crate::capability::{Capability, Certainty};
+use crate::domain::Domain;
+use crate::event::{Event, Channel, Namespace};
+use crate::capability::{AbsMeta, Capability, Certainty};
+use crate::loopback::{LoopbackHandle, Token};
 use crate::range::{Interval, Set};
+use crate::time::Duration;
+
+/// What happens when the value of the output event would leave the output range.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BoundsMode {
+    /// The value stays at the nearest bound of the range.
+    Clamp,
+    /// The value reenters the range from the other side.
+    Wrap,
+}
 
 pub struct RelToAbs {
     input_key: Key,
@@ -15,31 +27,95 @@ pub struct RelToAbs {
     output_key: Key,
     output_range: Interval,
     speed: f64,
-    
-    // For each channel that this argument may output, keeps track of the current value it has.
+    bounds: BoundsMode,
+    /// If Some, the output value returns to the center of the output range if no events
+    /// were mapped to a channel for this long.
+    recenter_after: Option<Duration>,
+    /// The resolution that the output devices shall report for the output axis.
+    resolution: i32,
+    /// With BoundsMode::Clamp, how far the value may be pushed past a bound of the output range. Movement
+    /// away from that bound needs to make up for the overshoot before the output value leaves the bound.
+    edge_resistance: f64,
+
+    // For each channel that this argument may output, keeps track of the current value it has. This
+    // value may lie up to `edge_resistance` outside the output range.
     state: HashMap<Channel, f64>,
+    /// For each channel that will be recentered at some point, the token that will trigger the
+    /// recentering and the last event this argument sent on that channel.
+    recenter_tokens: HashMap<Channel, (Token, Event)>,
 }
 
 impl RelToAbs {
-    pub fn new(input_key: Key, output_key: Key, output_range: Interval, speed: f64) -> RelToAbs {
+    pub fn new(
+        input_key: Key, output_key: Key, output_range: Interval, speed: f64,
+        bounds: BoundsMode, recenter_after: Option<Duration>, resolution: i32,
+    ) -> RelToAbs {
         RelToAbs {
-            input_key, output_key, output_range, speed,
+            input_key, output_key, output_range, speed, bounds, recenter_after, resolution,
+            edge_resistance: 0.0,
             state: HashMap::new(),
+            recenter_tokens: HashMap::new(),
         }
     }
 
-    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+    /// Lets the value be pushed up to `edge_resistance` units past the bounds of the output range.
+    /// Only has effect with BoundsMode::Clamp.
+    pub fn with_edge_resistance(mut self, edge_resistance: f64) -> RelToAbs {
+        self.edge_resistance = edge_resistance;
+        self
+    }
+
+    /// The value that is halfway the output range.
+    fn center(&self) -> f64 {
+        (f64::from(self.output_range.min) + f64::from(self.output_range.max)) / 2.0
+    }
+
+    /// The value that a channel is assumed to have before any events have been seen on it.
+    fn initial_value(&self) -> f64 {
+        match self.recenter_after {
+            Some(_) => self.center(),
+            None => 0.0,
+        }
+    }
+
+    /// Brings a value that may lie outside the output range back into it, or within the edge resistance
+    /// of it when clamping.
+    fn bound(&self, value: f64) -> f64 {
+        match self.bounds {
+            BoundsMode::Clamp => {
+                let min = f64::from(self.output_range.min) - self.edge_resistance;
+                let max = f64::from(self.output_range.max) + self.edge_resistance;
+                value.max(min).min(max)
+            },
+            BoundsMode::Wrap => {
+                let min = f64::from(self.output_range.min);
+                let width = f64::from(self.output_range.span()) + 1.0;
+                min + (value - min).rem_euclid(width)
+            },
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
         // Check if we shoult map this event to something else.
         if self.input_key.matches(&event) {
             let mut output_event = self.output_key.merge(event);
+            let channel = output_event.channel();
 
             // Add the input event's value to the current value of the target channel.
-            // TODO: figure out initial value.
-            let channel_state = self.state.entry(output_event.channel()).or_insert(0.0);
-            *channel_state += (event.value as f64) * self.speed;
-            *channel_state = self.output_range.bound_f64(*channel_state);
+            let initial_value = self.initial_value();
+            let current_value = *self.state.get(&channel).unwrap_or(&initial_value);
+            let new_value = self.bound(current_value + (event.value as f64) * self.speed);
+            self.state.insert(channel, new_value);
             // Then set the output event's value to that of the channel.
-            output_event.value = (*channel_state).floor() as i32;
+            output_event.value = self.output_value(new_value);
+
+            if let Some(recenter_after) = self.recenter_after {
+                if let Some((token, _)) = self.recenter_tokens.remove(&channel) {
+                    loopback.cancel_token(token);
+                }
+                let token = loopback.schedule_wakeup_in(recenter_after);
+                self.recenter_tokens.insert(channel, (token, output_event));
+            }
 
             return output_events.push(output_event);
         }
@@ -54,10 +130,62 @@ impl RelToAbs {
         output_events.push(event);
     }
 
+    /// The value of the output event for a channel that has the given value.
+    fn output_value(&self, value: f64) -> i32 {
+        self.output_range.bound_f64(value).floor() as i32
+    }
+
+    /// Returns all channels of the given domain back to the center of the output range, or all channels
+    /// if the domain is None. Channels that have not been seen yet are unaffected.
+    pub fn recenter(&mut self, domain: Option<Domain>, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let center = self.center();
+        let mut channels: Vec<Channel> = self.state.keys()
+            .filter(|(_, channel_domain)| domain.map_or(true, |domain| domain == *channel_domain))
+            .copied().collect();
+        // Sorted so that e.g. abs:x is always recentered before abs:y.
+        channels.sort_by_key(|&(code, _)| code);
+
+        for channel in channels {
+            if let Some((token, _)) = self.recenter_tokens.remove(&channel) {
+                loopback.cancel_token(token);
+            }
+            let previous_value = match self.state.insert(channel, center) {
+                Some(previous_value) => self.output_value(previous_value),
+                None => continue,
+            };
+            let value = self.output_value(center);
+            if value != previous_value {
+                let (code, domain) = channel;
+                output_events.push(Event::new(code, value, previous_value, domain, Namespace::User));
+            }
+        }
+    }
+
     /// Analogue of Map::apply_to_all().
-    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
         for event in events {
-            self.apply(*event, output_events);
+            self.apply(*event, output_events, loopback);
+        }
+    }
+
+    /// Returns the channel that belongs to the token back to the center of the output range.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        let channel = match self.recenter_tokens.iter().find(|(_, (channel_token, _))| channel_token == token) {
+            Some((&channel, _)) => channel,
+            None => return,
+        };
+        let (_, last_event) = match self.recenter_tokens.remove(&channel) {
+            Some(entry) => entry,
+            None => return,
+        };
+
+        let center = self.center();
+        let previous_value = self.state.insert(channel, center)
+            .map(|value| self.output_value(value))
+            .unwrap_or(last_event.value);
+        let value = self.output_value(center);
+        if value != previous_value {
+            output_events.push(Event { value, previous_value, ..last_event });
         }
     }
 
@@ -79,6 +207,11 @@ impl RelToAbs {
             // empty. However, since we manually overwrite values here, it is important to check that
             // the matching values are nonempty.
             merged_cap.values = Set::from(self.output_range);
+            merged_cap.abs_meta = Some(AbsMeta {
+                fuzz: 0, flat: 0,
+                resolution: self.resolution,
+                value: self.output_value(self.bound(self.initial_value())),
+            });
             output_caps.push(merged_cap)
         }
 
@@ -94,3 +227,58 @@ impl RelToAbs {
         }
     }
 }
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let rel_event = |value| Event::new(EventCode::new(EventType::REL, 0), value, 0, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let values = |output: &[Event]| output.iter().map(|event| event.value).collect::<Vec<_>>();
+
+    let mut loopback = crate::loopback::Loopback::new();
+    let mut rel_to_abs = RelToAbs::new(
+        KeyParser::default_filter().parse("rel:x").unwrap(),
+        KeyParser::pure().parse("abs:x").unwrap(),
+        Interval::new(0, 100), 1.0, BoundsMode::Clamp, Some(Duration::from_millis(100)), 0,
+    );
+    let mut output = Vec::new();
+    // Channels start at the center of the range.
+    rel_to_abs.apply(rel_event(10), &mut output, &mut loopback.get_handle(at(0)));
+    rel_to_abs.apply(rel_event(10), &mut output, &mut loopback.get_handle(at(50)));
+    assert_eq!(values(&output), vec![60, 70]);
+    // The first wakeup was cancelled by the second event.
+    let (time, token) = loopback.poll_once(at(150)).unwrap();
+    assert_eq!(time, at(150));
+    rel_to_abs.wakeup(&token, &mut output);
+    assert_eq!(values(&output), vec![60, 70, 50]);
+    assert_eq!(output[2].previous_value, 70);
+    assert!(loopback.poll_once(at(10_000)).is_none());
+
+    // With edge resistance, movement past the edge must be undone before the value leaves the edge.
+    let mut rel_to_abs = RelToAbs::new(
+        KeyParser::default_filter().parse("rel:x").unwrap(),
+        KeyParser::pure().parse("abs:x").unwrap(),
+        Interval::new(0, 100), 1.0, BoundsMode::Clamp, None, 0,
+    ).with_edge_resistance(20.0);
+    let mut output = Vec::new();
+    let mut handle = loopback.get_handle(at(20_000));
+    for value in [90, 30, -10, -10, -20] {
+        rel_to_abs.apply(rel_event(value), &mut output, &mut handle);
+    }
+    assert_eq!(values(&output), vec![90, 100, 100, 100, 80]);
+
+    // Recentering only affects the channels of the given domain.
+    let mut output = Vec::new();
+    rel_to_abs.recenter(Some(crate::domain::get_unique_domain()), &mut output, &mut handle);
+    assert!(output.is_empty());
+    rel_to_abs.recenter(Some(domain), &mut output, &mut handle);
+    assert_eq!(values(&output), vec![50]);
+    assert_eq!(output[0].previous_value, 80);
+    rel_to_abs.recenter(None, &mut output, &mut handle);
+    assert_eq!(output.len(), 1);
+}
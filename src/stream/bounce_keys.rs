@@ -0,0 +1,207 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --bounce-keys argument, which has two modes:
+//!
+//! * In release mode, if a key is pressed again shortly after it has been released, that press is
+//!   ignored. This helps people with tremors, and can also work around mice whose worn-out switches
+//!   turn a single click into a double click.
+//! * In chatter mode, all changes of the state of a key that happen shortly after the previous change
+//!   are ignored. If the key ended up in another state than the one that was passed on once that period
+//!   is over, the current state is passed on at that time.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::{Duration, Instant};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BounceMode {
+    /// Only presses that follow shortly after a release are ignored.
+    Release,
+    /// All state changes that follow shortly after a previous state change are ignored.
+    Chatter,
+}
+
+/// The state of a single key in chatter mode.
+struct ChatterState {
+    /// The value of the last press or release that was passed on.
+    reported_value: i32,
+    /// The last press or release that was received, which may not have been passed on.
+    last_event: Event,
+    /// Until this token is woken up, state changes of this key are ignored.
+    lock: Option<Token>,
+}
+
+pub struct BounceKeys {
+    keys: Vec<Key>,
+    period: Duration,
+    mode: BounceMode,
+
+    /// State: modifiable at runtime.
+    /// When each key was last released. Used in release mode.
+    last_release: HashMap<Channel, Instant>,
+    /// Keys whose press has been ignored, so their repeat and release events must be ignored too.
+    /// Used in release mode.
+    bounced: HashSet<Channel>,
+    /// Used in chatter mode.
+    chatter_states: HashMap<Channel, ChatterState>,
+}
+
+impl BounceKeys {
+    pub fn new(keys: Vec<Key>, period: Duration, mode: BounceMode) -> BounceKeys {
+        BounceKeys {
+            keys, period, mode,
+            last_release: HashMap::new(),
+            bounced: HashSet::new(),
+            chatter_states: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+        match self.mode {
+            BounceMode::Release => self.apply_release_mode(event, output_events, loopback.now()),
+            BounceMode::Chatter => self.apply_chatter_mode(event, output_events, loopback),
+        }
+    }
+
+    fn apply_release_mode(&mut self, event: Event, output_events: &mut Vec<Event>, now: Instant) {
+        let channel = event.channel();
+        match event.value {
+            1 => {
+                let is_bounce = match self.last_release.get(&channel) {
+                    Some(&release_time) => match now.checked_duration_since(release_time) {
+                        Some(time_since_release) => time_since_release < self.period,
+                        None => true,
+                    },
+                    None => false,
+                };
+                if is_bounce {
+                    self.bounced.insert(channel);
+                } else {
+                    output_events.push(event);
+                }
+            },
+            0 => {
+                // The period is counted from the last release that was passed on, so holding a
+                // key down repeatedly cannot keep extending it.
+                if ! self.bounced.remove(&channel) {
+                    self.last_release.insert(channel, now);
+                    output_events.push(event);
+                }
+            },
+            _ => {
+                if ! self.bounced.contains(&channel) {
+                    output_events.push(event);
+                }
+            },
+        }
+    }
+
+    fn apply_chatter_mode(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let state = match self.chatter_states.get_mut(&event.channel()) {
+            Some(state) => state,
+            None => {
+                let lock = match event.value {
+                    2 => None,
+                    _ => Some(loopback.schedule_wakeup_in(self.period)),
+                };
+                self.chatter_states.insert(event.channel(), ChatterState {
+                    reported_value: event.value.min(1), last_event: event, lock,
+                });
+                return output_events.push(event);
+            }
+        };
+
+        if event.value == 2 {
+            if state.reported_value != 0 {
+                output_events.push(event);
+            }
+            return;
+        }
+        state.last_event = event;
+        if state.lock.is_none() && event.value != state.reported_value {
+            state.reported_value = event.value;
+            state.lock = Some(loopback.schedule_wakeup_in(self.period));
+            output_events.push(event);
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    /// Ends the period during which state changes of a key are ignored in chatter mode. If the key
+    /// changed state during that period, its current state is passed on.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let state = match self.chatter_states.values_mut().find(|state| state.lock.as_ref() == Some(token)) {
+            Some(state) => state,
+            None => return,
+        };
+        state.lock = None;
+        if state.last_event.value != state.reported_value {
+            let mut event = state.last_event;
+            event.previous_value = state.reported_value;
+            state.reported_value = event.value;
+            state.lock = Some(loopback.schedule_wakeup_in(self.period));
+            output_events.push(event);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+
+    let domain = crate::domain::get_unique_domain();
+    let key_event = |value| Event::new(EventCode::new(EventType::KEY, 30), value, 1 - value, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    let all_keys = || vec![KeyParser::default_filter().parse("").unwrap()];
+    let mut loopback = crate::loopback::Loopback::new();
+
+    let mut bounce_keys = BounceKeys::new(all_keys(), Duration::from_millis(100), BounceMode::Release);
+    let mut output = Vec::new();
+    bounce_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(0)));
+    bounce_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(10)));
+    // A bounce and its release are ignored.
+    bounce_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(50)));
+    bounce_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(60)));
+    // Counted from the release at 10 milliseconds, enough time has passed.
+    bounce_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(115)));
+    assert_eq!(output.iter().map(|event| event.value).collect::<Vec<_>>(), vec![1, 0, 1]);
+    // Holding a key for a long time does not count as bouncing, only release-to-press does.
+    bounce_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(120)));
+    assert_eq!(output.iter().map(|event| event.value).collect::<Vec<_>>(), vec![1, 0, 1, 0]);
+    assert!(loopback.poll_once(at(10_000)).is_none());
+
+    let mut bounce_keys = BounceKeys::new(all_keys(), Duration::from_millis(100), BounceMode::Chatter);
+    let mut output = Vec::new();
+    // The release after 20 milliseconds is chatter, but the key is released when the period ends.
+    bounce_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(0)));
+    bounce_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(20)));
+    bounce_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(30)));
+    bounce_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(40)));
+    assert_eq!(output.iter().map(|event| event.value).collect::<Vec<_>>(), vec![1]);
+    let (time, token) = loopback.poll_once(at(100)).unwrap();
+    bounce_keys.wakeup(&token, &mut output, &mut loopback.get_handle(time));
+    assert_eq!(output.iter().map(|event| event.value).collect::<Vec<_>>(), vec![1, 0]);
+    // Nothing changed during the second period.
+    let (time, token) = loopback.poll_once(at(200)).unwrap();
+    bounce_keys.wakeup(&token, &mut output, &mut loopback.get_handle(time));
+    assert_eq!(output.len(), 2);
+    assert!(loopback.poll_once(at(10_000)).is_none());
+}
@@ -0,0 +1,58 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --bypass argument, which writes certain events to an output device before they
+//! would otherwise enter the stream.
+
+use std::collections::HashSet;
+
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, Namespace};
+use crate::key::Key;
+use crate::stream::map::Map;
+
+pub struct Bypass {
+    keys: Vec<Key>,
+    target: Key,
+    /// The codes and domains of the events whose last value was nonzero when they bypassed the stream.
+    /// All following events with the same code and domain bypass the stream as well until one with
+    /// value zero arrives, even if they do not match any key. Otherwise a key whose press bypassed
+    /// the stream could have its release go through the stream, which would then release a key that
+    /// was never pressed and leave the bypassed one stuck.
+    held: HashSet<(EventCode, Domain)>,
+}
+
+impl Bypass {
+    pub fn new(keys: Vec<Key>, target_domain: Domain) -> Bypass {
+        Bypass {
+            keys,
+            target: Key::from_domain_and_namespace(target_domain, Namespace::Output),
+            held: HashSet::new(),
+        }
+    }
+
+    /// If the event should bypass the stream, returns the event as it should be written to the
+    /// output device.
+    pub fn apply(&mut self, event: Event) -> Option<Event> {
+        let channel = (event.code, event.domain);
+        let is_held = self.held.contains(&channel);
+        if ! is_held && ! self.keys.iter().any(|key| key.matches(&event)) {
+            return None;
+        }
+        match event.value {
+            0 => { self.held.remove(&channel); },
+            _ => if ! is_held { self.held.insert(channel); },
+        }
+        Some(self.target.merge(event))
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        let mut caps = caps.to_vec();
+        for key in &self.keys {
+            let mut buffer = Vec::new();
+            Map::new(key.clone(), vec![self.target.clone()]).apply_to_all_caps(&caps, &mut buffer);
+            caps = buffer;
+        }
+        output_caps.extend(caps);
+    }
+}
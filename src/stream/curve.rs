@@ -0,0 +1,116 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::{Capability, Certainty};
+use crate::event::{Event, EventType};
+use crate::key::Key;
+use crate::range::{Interval, Set};
+
+/// Maps the values of abs-type events through a piecewise linear curve.
+pub struct Curve {
+    input_keys: Vec<Key>,
+    /// The points of the curve as (input value, output value). Sorted by strictly ascending input value,
+    /// and contains at least two points.
+    points: Vec<(i32, i32)>,
+}
+
+impl Curve {
+    pub fn new(input_keys: Vec<Key>, points: Vec<(i32, i32)>) -> Curve {
+        debug_assert!(points.len() >= 2);
+        debug_assert!(points.windows(2).all(|pair| pair[0].0 < pair[1].0));
+        Curve { input_keys, points }
+    }
+
+    /// Returns the output value of the curve for a certain input value. Input values beyond the first or
+    /// last point of the curve get the output value of that point.
+    fn map_value(&self, value: i32) -> i32 {
+        let index = self.points.partition_point(|&(input, _)| input <= value);
+        if index == 0 {
+            return self.points[0].1;
+        }
+        if index == self.points.len() {
+            return self.points[index - 1].1;
+        }
+
+        let (x_1, y_1) = self.points[index - 1];
+        let (x_2, y_2) = self.points[index];
+        let fraction = (value as f64 - x_1 as f64) / (x_2 as f64 - x_1 as f64);
+        (y_1 as f64 + fraction * (y_2 as f64 - y_1 as f64)).round() as i32
+    }
+
+    fn apply(&self, mut event: Event, output_events: &mut Vec<Event>) {
+        if event.ev_type() == EventType::ABS && self.input_keys.iter().any(|key| key.matches(&event)) {
+            event.value = self.map_value(event.value);
+        }
+        output_events.push(event);
+    }
+
+    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    /// Returns the smallest interval containing all output values of the curve for the input values
+    /// in the given interval.
+    fn map_interval(&self, interval: Interval) -> Interval {
+        // A piecewise linear function takes its extreme values either at the bounds of the interval or
+        // at one of its points.
+        let outputs: Vec<i32> = [interval.min, interval.max].iter().copied()
+            .chain(self.points.iter().map(|&(input, _)| input).filter(|&input| interval.contains(input)))
+            .map(|input| self.map_value(input))
+            .collect();
+        Interval::spanned_between(*outputs.iter().min().unwrap(), *outputs.iter().max().unwrap())
+    }
+
+    fn apply_to_cap(&self, cap: &Capability, output_caps: &mut Vec<Capability>) {
+        if cap.code.ev_type() != EventType::ABS {
+            return output_caps.push(cap.clone());
+        }
+
+        let mut matching_values = Set::empty();
+        let mut potentially_nonmatching_values = cap.values.clone();
+        for key in &self.input_keys {
+            let (match_certainty, key_matching_values) = key.matches_cap(cap);
+            if match_certainty == Certainty::Always {
+                potentially_nonmatching_values = potentially_nonmatching_values.setminus(&key_matching_values);
+            }
+            matching_values = matching_values.union(&key_matching_values);
+        }
+
+        if ! matching_values.is_empty() {
+            let mut curved_cap = cap.with_values(matching_values.map(|interval| Some(self.map_interval(interval))));
+            if let Some(abs_meta) = &mut curved_cap.abs_meta {
+                abs_meta.value = self.map_value(abs_meta.value);
+            }
+            output_caps.push(curved_cap);
+        }
+        if ! potentially_nonmatching_values.is_empty() {
+            output_caps.push(cap.with_values(potentially_nonmatching_values));
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            self.apply_to_cap(cap, output_caps);
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    let curve = Curve::new(Vec::new(), vec![(0, 0), (8000, 2000), (32767, 32767)]);
+    assert_eq!(curve.map_value(-100), 0);
+    assert_eq!(curve.map_value(0), 0);
+    assert_eq!(curve.map_value(4000), 1000);
+    assert_eq!(curve.map_value(8000), 2000);
+    assert_eq!(curve.map_value(32767), 32767);
+    assert_eq!(curve.map_value(40000), 32767);
+    assert_eq!(curve.map_interval(Interval::new(0, 32767)), Interval::new(0, 32767));
+    assert_eq!(curve.map_interval(Interval::new(4000, 6000)), Interval::new(1000, 1500));
+
+    let curve = Curve::new(Vec::new(), vec![(-100, 0), (0, 50), (100, 0)]);
+    assert_eq!(curve.map_value(-50), 25);
+    assert_eq!(curve.map_value(51), 25);
+    assert_eq!(curve.map_interval(Interval::new(-100, 100)), Interval::new(0, 50));
+    assert_eq!(curve.map_interval(Interval::new(None, None)), Interval::new(0, 50));
+}
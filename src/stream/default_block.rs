@@ -0,0 +1,86 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements `--default block`, which drops all events that no --map, --hook or --toggle after it
+//! acts upon. This makes it possible to write scripts that only let through what they explicitly
+//! mention, without having to list every key that should be blocked.
+//!
+//! Whether an event is allowed only depends on its channel, not on its value, so a key that may be
+//! pressed can always be released as well.
+
+use std::collections::HashSet;
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event, EventCode};
+use crate::key::Key;
+
+pub struct DefaultBlock {
+    /// Only events that match one of these keys can be blocked. Must be pure keys.
+    scope: Vec<Key>,
+    /// All events with these codes are allowed. This covers the keys that specify a code and no domain,
+    /// which are most keys in practice.
+    allowed_codes: HashSet<EventCode>,
+    /// Events matching any of these keys on their channel are allowed as well.
+    allowed_keys: Vec<Key>,
+}
+
+impl DefaultBlock {
+    /// `allowed_keys` are the keys of all arguments that should let events through.
+    pub fn new(scope: Vec<Key>, allowed_keys: Vec<Key>) -> DefaultBlock {
+        let mut allowed_codes = HashSet::new();
+        let mut remaining_keys = Vec::new();
+        for key in allowed_keys {
+            match key.requires_event_code() {
+                Some(code) if ! key.specifies_domain() => {
+                    allowed_codes.insert(code);
+                },
+                _ => remaining_keys.push(key),
+            }
+        }
+        DefaultBlock { scope, allowed_codes, allowed_keys: remaining_keys }
+    }
+
+    fn is_allowed(&self, channel: Channel) -> bool {
+        ! self.scope.iter().any(|key| key.matches_channel(channel))
+            || self.allowed_codes.contains(&channel.0)
+            || self.allowed_keys.iter().any(|key| key.matches_channel(channel))
+    }
+
+    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
+        output_events.extend(events.iter().filter(|event| self.is_allowed(event.channel())));
+    }
+
+    /// Devices do not get capabilities for events that would get blocked.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().filter(|cap| self.is_allowed((cap.code, cap.domain))).cloned());
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+
+    let keyboard = crate::domain::resolve("default-block-test-kb").unwrap();
+    let mouse = crate::domain::resolve("default-block-test-mouse").unwrap();
+    let event = |key_str: &str, domain| {
+        let (type_name, code_name) = crate::utils::split_once(key_str, ":");
+        let code = crate::ecodes::event_code(type_name, code_name.unwrap()).unwrap();
+        Event::new(code, 0, 1, domain, Namespace::User)
+    };
+    let parser = KeyParser::default_filter();
+    let allowed_keys = vec![
+        parser.parse("key:a:1").unwrap(),
+        parser.parse("key:b@default-block-test-kb").unwrap(),
+        parser.parse("rel").unwrap(),
+    ];
+    let scope = vec![KeyParser::pure().parse("@default-block-test-kb").unwrap()];
+    let default_block = DefaultBlock::new(scope, allowed_keys);
+
+    // The value of key:a:1 does not matter.
+    assert!(default_block.is_allowed(event("key:a", keyboard).channel()));
+    assert!(default_block.is_allowed(event("key:b", keyboard).channel()));
+    assert!(default_block.is_allowed(event("rel:x", keyboard).channel()));
+    assert!(! default_block.is_allowed(event("key:c", keyboard).channel()));
+    // Events outside the scope are never blocked.
+    assert!(default_block.is_allowed(event("key:c", mouse).channel()));
+}
@@ -1,6 +1,6 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::loopback::{LoopbackHandle, Token};
+use crate::loopback::{LoopbackHandle, SuspendMode, Token};
 use crate::event::Event;
 use crate::key::Key;
 use crate::time::Duration;
@@ -10,6 +10,8 @@ use crate::time::Duration;
 pub struct Delay {
     keys: Vec<Key>,
     period: Duration,
+    /// Whether the time spent suspended counts towards the period.
+    suspend_mode: SuspendMode,
 
     /// State: modifiable at runtime.
     /// Events that need to be put back into thes stream when the loopback releases a certain token.
@@ -17,9 +19,9 @@ pub struct Delay {
 }
 
 impl Delay {
-    pub fn new(keys: Vec<Key>, period: Duration) -> Delay {
+    pub fn new(keys: Vec<Key>, period: Duration, suspend_mode: SuspendMode) -> Delay {
         Delay {
-            keys, period,
+            keys, period, suspend_mode,
             delayed_events: Vec::new(),
         }
     }
@@ -37,7 +39,7 @@ impl Delay {
         }
 
         if ! events_to_withhold.is_empty() {
-            let wakeup_token = loopback.schedule_wakeup_in(self.period);
+            let wakeup_token = loopback.schedule_wakeup_in_mode(self.period, self.suspend_mode);
             self.delayed_events.push((wakeup_token, events_to_withhold));
         }
     }
@@ -0,0 +1,119 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --edge argument for switches that only report one kind of event. Some footswitches
+//! for example send a key down event every time they are toggled, but never a key up event.
+//!
+//! Every key down event that reaches an --edge argument counts as an edge. Key up and repeat events
+//! of the same keys are dropped.
+
+use std::collections::HashMap;
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event, EventValue};
+use crate::key::Key;
+use crate::range::{Interval, Set};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EdgeMode {
+    /// Each edge alternately presses and releases the key.
+    Alternate,
+    /// Each edge presses and immediately releases the key.
+    Pulse,
+}
+
+pub struct Edge {
+    keys: Vec<Key>,
+    mode: EdgeMode,
+    /// The value that a key is assumed to have before its first edge arrives. Used in alternate mode.
+    initial_value: EventValue,
+
+    /// State: modifiable at runtime.
+    /// The last value that was sent for each key in alternate mode.
+    values: HashMap<Channel, EventValue>,
+}
+
+impl Edge {
+    pub fn new(keys: Vec<Key>, mode: EdgeMode, initial_value: EventValue) -> Edge {
+        Edge { keys, mode, initial_value, values: HashMap::new() }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+        if event.value != 1 {
+            return;
+        }
+
+        match self.mode {
+            EdgeMode::Alternate => {
+                let value = self.values.entry(event.channel()).or_insert(self.initial_value);
+                let previous_value = *value;
+                *value = 1 - previous_value;
+                output_events.push(Event { value: *value, previous_value, ..event });
+            },
+            EdgeMode::Pulse => {
+                output_events.push(Event { value: 1, previous_value: 0, ..event });
+                output_events.push(Event { value: 0, previous_value: 1, ..event });
+            },
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    /// Keys that this argument acts on can have the values 0 and 1 afterwards, regardless of which
+    /// values they could have before.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            if ! cap.code.ev_type().is_key() {
+                output_caps.push(cap.clone());
+                continue;
+            }
+            let matching_values = self.keys.iter()
+                .map(|key| key.matches_cap(cap).1)
+                .fold(Set::empty(), |accumulator, values| accumulator.union(&values));
+            if matching_values.is_empty() {
+                output_caps.push(cap.clone());
+                continue;
+            }
+
+            let remaining_values = cap.values.setminus(&matching_values);
+            if ! remaining_values.is_empty() {
+                output_caps.push(cap.with_values(remaining_values));
+            }
+            if matching_values.contains(1) {
+                output_caps.push(cap.with_values(Set::from(Interval::new(0, 1))));
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+
+    let domain = crate::domain::get_unique_domain();
+    let key_event = |value| Event::new(EventCode::new(EventType::KEY, 30), value, 1 - value, domain, Namespace::User);
+    let all_keys = || vec![KeyParser::default_filter().parse("").unwrap()];
+    let values = |output: &[Event]| output.iter().map(|event| (event.previous_value, event.value)).collect::<Vec<_>>();
+
+    let mut edge = Edge::new(all_keys(), EdgeMode::Alternate, 0);
+    let mut output = Vec::new();
+    edge.apply_to_all(&[key_event(1), key_event(1), key_event(0), key_event(1)], &mut output);
+    assert_eq!(values(&output), vec![(0, 1), (1, 0), (0, 1)]);
+
+    let mut edge = Edge::new(all_keys(), EdgeMode::Alternate, 1);
+    let mut output = Vec::new();
+    edge.apply_to_all(&[key_event(1), key_event(1)], &mut output);
+    assert_eq!(values(&output), vec![(1, 0), (0, 1)]);
+
+    let mut edge = Edge::new(all_keys(), EdgeMode::Pulse, 0);
+    let mut output = Vec::new();
+    edge.apply_to_all(&[key_event(1), key_event(2), key_event(1)], &mut output);
+    assert_eq!(values(&output), vec![(0, 1), (1, 0), (0, 1), (1, 0)]);
+}
@@ -0,0 +1,93 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::domain::Domain;
+use crate::event::{Event, EventCode, StageIndex};
+use crate::state::{State, ToggleIndex};
+use super::StreamEntry;
+
+/// The inner entry of a gate with a condition on an LED is only enabled while that LED is on or off.
+#[derive(Clone, Copy)]
+pub struct LedCondition {
+    pub code: EventCode,
+    /// If None, the LED of any input device counts.
+    pub domain: Option<Domain>,
+    pub on: bool,
+}
+
+/// Wraps another stream entry that was created by an argument with an enabled-when or a stage clause.
+/// Events pass by the inner entry untouched unless a certain toggle has a certain index active or a
+/// certain LED is on or off, and events from stages that were not listed in the stage clause always
+/// pass by it untouched.
+///
+/// The inner entry still gets woken up while it is disabled, so events it was already holding on to
+/// (e.g. because of a --delay) are not lost.
+pub struct Gated {
+    pub entry: Box<StreamEntry>,
+    /// The inner entry is enabled while the toggle with this index has the given value.
+    toggle: Option<(ToggleIndex, usize)>,
+    /// The inner entry is enabled while the LED has the state this condition asks for.
+    led: Option<LedCondition>,
+    /// If Some, the inner entry only acts upon events from these stages.
+    stages: Option<Vec<StageIndex>>,
+}
+
+impl Gated {
+    /// Creates a gate that lets all events through to the inner entry until restrictions are added.
+    pub fn new(entry: StreamEntry) -> Gated {
+        Gated { entry: Box::new(entry), toggle: None, led: None, stages: None }
+    }
+
+    pub fn set_toggle(&mut self, toggle_index: ToggleIndex, value: usize) {
+        self.toggle = Some((toggle_index, value));
+    }
+
+    pub fn set_led(&mut self, condition: LedCondition) {
+        self.led = Some(condition);
+    }
+
+    pub fn set_stages(&mut self, stages: Vec<StageIndex>) {
+        self.stages = Some(stages);
+    }
+
+    pub fn is_enabled(&self, state: &State) -> bool {
+        if let Some(led) = self.led {
+            if state.led_is_on(led.code, led.domain) != led.on {
+                return false;
+            }
+        }
+        match self.toggle {
+            Some((toggle_index, _)) => self.is_enabled_at(state[toggle_index].value()),
+            None => true,
+        }
+    }
+
+    /// Returns whether the inner entry is enabled depending on the LEDs, which can change at any time.
+    pub fn depends_on_leds(&self) -> bool {
+        self.led.is_some()
+    }
+
+    /// Returns whether the inner entry is enabled if the toggle had the given value.
+    pub fn is_enabled_at(&self, toggle_value: usize) -> bool {
+        match self.toggle {
+            Some((_, value)) => toggle_value == value,
+            None => true,
+        }
+    }
+
+    pub fn toggle_index(&self) -> Option<ToggleIndex> {
+        self.toggle.map(|(toggle_index, _)| toggle_index)
+    }
+
+    /// Returns whether some events may pass by the inner entry regardless of the toggle's state.
+    pub fn filters_stages(&self) -> bool {
+        self.stages.is_some()
+    }
+
+    /// Returns whether the inner entry may act upon this event, assuming the gate is enabled.
+    pub fn accepts(&self, event: &Event) -> bool {
+        match &self.stages {
+            Some(stages) => stages.contains(&event.stage),
+            None => true,
+        }
+    }
+}
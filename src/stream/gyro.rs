@@ -0,0 +1,245 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --gyro-to-rel argument, which turns the angular velocity reported by a gyroscope
+//! into relative movement by integrating it over time.
+
+use std::collections::HashMap;
+
+use crate::capability::{Capability, Certainty};
+use crate::event::{Channel, Event};
+use crate::key::Key;
+use crate::loopback::LoopbackHandle;
+use crate::range::{Interval, Set};
+use crate::time::{Duration, Instant};
+
+/// If two consecutive gyroscope events are further apart than this, the time in between is assumed to
+/// be this long. Prevents a sudden jump of the cursor after the device has been silent for a while.
+const MAX_TIME_STEP: Duration = Duration::from_millis(100);
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum EnableMode {
+    /// The gyroscope is enabled while the enable key is held down.
+    Hold,
+    /// Every press of the enable key switches the gyroscope on or off.
+    Toggle,
+}
+
+pub struct Calibration {
+    /// How long the device must be at rest before its bias is updated.
+    pub period: Duration,
+    /// The device is considered at rest if its reported values stay within a band of this width.
+    pub rest_threshold: f64,
+}
+
+/// Keeps track of a single input channel.
+struct AxisState {
+    last_value: i32,
+    last_time: Instant,
+    /// The value that this axis reports when the device is not rotating.
+    bias: f64,
+    /// The samples gathered since the device was last seen moving.
+    rest_start: Instant,
+    rest_min: i32,
+    rest_max: i32,
+    rest_sum: f64,
+    rest_count: u32,
+}
+
+impl AxisState {
+    fn new(value: i32, time: Instant) -> AxisState {
+        AxisState {
+            last_value: value,
+            last_time: time,
+            bias: 0.0,
+            rest_start: time,
+            rest_min: value,
+            rest_max: value,
+            rest_sum: value as f64,
+            rest_count: 1,
+        }
+    }
+
+    fn restart_rest(&mut self, value: i32, time: Instant) {
+        self.rest_start = time;
+        self.rest_min = value;
+        self.rest_max = value;
+        self.rest_sum = value as f64;
+        self.rest_count = 1;
+    }
+
+    /// Estimates the bias from the values that were reported while the device was at rest.
+    fn calibrate(&mut self, value: i32, time: Instant, calibration: &Calibration) {
+        let min = self.rest_min.min(value);
+        let max = self.rest_max.max(value);
+        if (max as f64 - min as f64) > calibration.rest_threshold {
+            return self.restart_rest(value, time);
+        }
+        self.rest_min = min;
+        self.rest_max = max;
+        self.rest_sum += value as f64;
+        self.rest_count += 1;
+
+        let rest_duration = time.checked_duration_since(self.rest_start).unwrap_or(Duration::from_nanos(0));
+        if rest_duration >= calibration.period {
+            self.bias = self.rest_sum / self.rest_count as f64;
+            self.restart_rest(value, time);
+        }
+    }
+}
+
+pub struct GyroToRel {
+    input_key: Key,
+    /// The output key must not contain any values.
+    output_key: Key,
+    /// The amount of output units per input unit per second.
+    speed: f64,
+    enable_key: Option<Key>,
+    enable_mode: EnableMode,
+    calibration: Option<Calibration>,
+
+    /// State: modifiable at runtime.
+    enabled: bool,
+    axes: HashMap<Channel, AxisState>,
+    /// The fractional movement that has not been written to the output channel yet.
+    residuals: HashMap<Channel, f64>,
+}
+
+impl GyroToRel {
+    pub fn new(
+        input_key: Key, output_key: Key, speed: f64,
+        enable_key: Option<Key>, enable_mode: EnableMode, calibration: Option<Calibration>,
+    ) -> GyroToRel {
+        GyroToRel {
+            input_key, output_key, speed, enable_mode, calibration,
+            enabled: enable_key.is_none(),
+            enable_key,
+            axes: HashMap::new(),
+            residuals: HashMap::new(),
+        }
+    }
+
+    fn update_enabled(&mut self, event: &Event) {
+        let enable_key = match &self.enable_key {
+            Some(key) => key,
+            None => return,
+        };
+        if ! enable_key.matches(event) {
+            return;
+        }
+        match (self.enable_mode, event.value) {
+            // Repeat events do not change anything.
+            (_, 2) => (),
+            (EnableMode::Hold, value) => self.enabled = value != 0,
+            (EnableMode::Toggle, 0) => (),
+            (EnableMode::Toggle, _) => self.enabled = ! self.enabled,
+        }
+        if ! self.enabled {
+            self.residuals.clear();
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, now: Instant) {
+        self.update_enabled(&event);
+        if ! self.input_key.matches(&event) {
+            return output_events.push(event);
+        }
+
+        let axis = match self.axes.get_mut(&event.channel()) {
+            Some(axis) => axis,
+            None => {
+                self.axes.insert(event.channel(), AxisState::new(event.value, now));
+                return;
+            }
+        };
+
+        // The device rotated with the previous velocity from the time of the previous event until now.
+        let time_step = now.checked_duration_since(axis.last_time)
+            .unwrap_or(Duration::from_nanos(0))
+            .min(MAX_TIME_STEP);
+        let movement = (axis.last_value as f64 - axis.bias) * time_step.as_secs_f64() * self.speed;
+
+        axis.last_value = event.value;
+        axis.last_time = now;
+        if let Some(calibration) = &self.calibration {
+            axis.calibrate(event.value, now, calibration);
+        }
+
+        if ! self.enabled {
+            return;
+        }
+        let mut output_event = self.output_key.merge(event);
+        let residual = self.residuals.entry(output_event.channel()).or_insert(0.0);
+        *residual += movement;
+        let whole_movement = residual.trunc();
+        *residual -= whole_movement;
+        if whole_movement != 0.0 {
+            output_event.value = whole_movement as i32;
+            output_event.previous_value = 0;
+            output_events.push(output_event);
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let now = loopback.now();
+        for &event in events {
+            self.apply(event, output_events, now);
+        }
+    }
+
+    fn apply_to_cap(&self, cap: &Capability, output_caps: &mut Vec<Capability>) {
+        let (match_certainty, matching_values) = self.input_key.matches_cap(cap);
+        let potentially_nonmatching_values = match match_certainty {
+            Certainty::Maybe => cap.values.clone(),
+            Certainty::Always => cap.values.setminus(&matching_values),
+        };
+
+        if ! matching_values.is_empty() {
+            let mut merged_cap = self.output_key.merge_cap(cap.clone());
+            merged_cap.values = Set::from(Interval::new(None, None));
+            merged_cap.abs_meta = None;
+            output_caps.push(merged_cap);
+        }
+        if ! potentially_nonmatching_values.is_empty() {
+            output_caps.push(cap.clone().with_values(potentially_nonmatching_values));
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            self.apply_to_cap(cap, output_caps);
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+
+    let domain = crate::domain::get_unique_domain();
+    let gyro_event = |value| Event::new(EventCode::new(EventType::ABS, 3), value, 0, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    let mut gyro = GyroToRel::new(
+        KeyParser::default_filter().parse("abs:rx").unwrap(),
+        KeyParser::default_filter().parse("rel:x").unwrap(),
+        0.1, None, EnableMode::Hold,
+        Some(Calibration { period: Duration::from_millis(50), rest_threshold: 2.0 }),
+    );
+    let mut output = Vec::new();
+    // Calibrate a bias of 10 while at rest.
+    for time in 0 ..= 5 {
+        gyro.apply(gyro_event(10), &mut output, at(time * 10));
+    }
+    // Until the bias was known, the movement was too small to show up.
+    let total: i32 = output.iter().map(|event| event.value).sum();
+    assert_eq!(total, 0);
+
+    output.clear();
+    gyro.apply(gyro_event(110), &mut output, at(60));
+    gyro.apply(gyro_event(110), &mut output, at(160));
+    let total: i32 = output.iter().map(|event| event.value).sum();
+    // 100 units for 100ms at a speed of 0.1.
+    assert_eq!(total, 1);
+}
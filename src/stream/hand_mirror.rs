@@ -0,0 +1,144 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --mirror argument, which makes it possible to type with one hand: while a trigger
+//! key is held, every key in a table gets replaced by its counterpart on the other half of the keyboard.
+//!
+//! The trigger key itself is withheld. If it gets released without any other key having been pressed
+//! in the meanwhile, it was tapped rather than held, and a press and release of the trigger get sent.
+
+use std::collections::HashMap;
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event, EventCode};
+use crate::key::Key;
+
+pub struct HandMirror {
+    /// Only key events matching these keys get mirrored.
+    keys: Vec<Key>,
+    trigger: Key,
+    /// Maps every key in the table to its counterpart. Contains both directions of each pair.
+    table: HashMap<EventCode, EventCode>,
+
+    /// State: modifiable at runtime.
+    /// The key down event of the trigger if the trigger is currently held.
+    trigger_down: Option<Event>,
+    /// Whether any other key has been pressed since the trigger was pressed.
+    trigger_used: bool,
+    /// The keys that were pressed while the trigger was held and the codes they got mirrored to.
+    /// Their repeat and release events get mirrored as well, even if the trigger has been released.
+    mirrored: HashMap<Channel, EventCode>,
+}
+
+impl HandMirror {
+    pub fn new(keys: Vec<Key>, trigger: Key, table: HashMap<EventCode, EventCode>) -> HandMirror {
+        HandMirror {
+            keys, trigger, table,
+            trigger_down: None,
+            trigger_used: false,
+            mirrored: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        if ! event.ev_type().is_key() {
+            return output_events.push(event);
+        }
+
+        if self.trigger.matches(&event) {
+            match event.value {
+                1 => {
+                    // If the trigger is pressed on multiple devices at the same time, only the first one counts.
+                    if self.trigger_down.is_none() {
+                        self.trigger_down = Some(event);
+                        self.trigger_used = false;
+                    }
+                },
+                0 => match self.trigger_down {
+                    Some(trigger_down) if trigger_down.channel() == event.channel() => {
+                        self.trigger_down = None;
+                        if ! self.trigger_used {
+                            output_events.push(trigger_down);
+                            output_events.push(event);
+                        }
+                    },
+                    _ => output_events.push(event),
+                },
+                _ => if self.trigger_down.is_none() {
+                    output_events.push(event);
+                },
+            }
+            return;
+        }
+
+        if let Some(&mirrored_code) = self.mirrored.get(&event.channel()) {
+            if event.value == 0 {
+                self.mirrored.remove(&event.channel());
+            }
+            return output_events.push(Event { code: mirrored_code, ..event });
+        }
+
+        if self.trigger_down.is_some() && event.value == 1 {
+            self.trigger_used = true;
+            if let Some(&mirrored_code) = self.table.get(&event.code) {
+                if self.keys.iter().any(|key| key.matches(&event)) {
+                    self.mirrored.insert(event.channel(), mirrored_code);
+                    return output_events.push(Event { code: mirrored_code, ..event });
+                }
+            }
+        }
+
+        output_events.push(event);
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    /// Keys that can get mirrored can additionally show up as their counterparts.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            output_caps.push(cap.clone());
+            let mirrored_code = match self.table.get(&cap.code) {
+                Some(&code) => code,
+                None => continue,
+            };
+            for key in &self.keys {
+                let (_, matching_values) = key.matches_cap(cap);
+                if ! matching_values.is_empty() {
+                    output_caps.push(Capability { code: mirrored_code, values: matching_values, ..cap.clone() });
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventType, Namespace};
+    use crate::key::KeyParser;
+
+    let domain = crate::domain::get_unique_domain();
+    let key_event = |code, value| Event::new(EventCode::new(EventType::KEY, code), value, 0, domain, Namespace::User);
+    let codes = |output: &[Event]| output.iter().map(|event| (event.code.code(), event.value)).collect::<Vec<_>>();
+    let parser = KeyParser::default_filter();
+    // Mirrors Q (16) and P (25) while space (57) is held.
+    let table: HashMap<EventCode, EventCode> = vec![
+        (EventCode::new(EventType::KEY, 16), EventCode::new(EventType::KEY, 25)),
+        (EventCode::new(EventType::KEY, 25), EventCode::new(EventType::KEY, 16)),
+    ].into_iter().collect();
+    let mut mirror = HandMirror::new(vec![parser.parse("").unwrap()], parser.parse("key:space").unwrap(), table);
+
+    // Tapping the trigger.
+    let mut output = Vec::new();
+    mirror.apply_to_all(&[key_event(57, 1), key_event(57, 2), key_event(57, 0)], &mut output);
+    assert_eq!(codes(&output), vec![(57, 1), (57, 0)]);
+
+    // Holding the trigger, where P gets released after the trigger.
+    let mut output = Vec::new();
+    mirror.apply_to_all(&[
+        key_event(57, 1), key_event(25, 1), key_event(30, 1), key_event(30, 0), key_event(57, 0), key_event(25, 0), key_event(25, 1),
+    ], &mut output);
+    assert_eq!(codes(&output), vec![(16, 1), (30, 1), (30, 0), (16, 0), (25, 1)]);
+}
@@ -0,0 +1,173 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Records the distribution of the values of events for the --histogram argument.
+//!
+//! The amount of memory used is bounded: each channel has a maximum amount of buckets. If a new value
+//! does not fit in any existing bucket and no more buckets can be added, then the width of all buckets
+//! is doubled and neighbouring buckets are merged.
+
+use std::collections::{BTreeMap, HashMap};
+
+use crate::domain;
+use crate::ecodes;
+use crate::event::{Channel, Event, EventValue};
+use crate::key::Key;
+use crate::utils::json_string;
+
+/// After this many channels have been recorded, events of new channels are ignored.
+const MAX_CHANNELS: usize = 256;
+
+pub enum HistogramFormat {
+    Text,
+    Json,
+}
+
+/// Created by --histogram arguments.
+pub struct Histogram {
+    keys: Vec<Key>,
+    max_buckets: usize,
+    format: HistogramFormat,
+    channels: HashMap<Channel, ChannelHistogram>,
+}
+
+struct ChannelHistogram {
+    count: u64,
+    min: EventValue,
+    max: EventValue,
+    /// Each bucket counts the values in a range of this width.
+    bucket_width: i64,
+    /// The bucket with index k counts the values v for which v.div_euclid(bucket_width) == k.
+    buckets: BTreeMap<i64, u64>,
+}
+
+impl ChannelHistogram {
+    fn new(value: EventValue) -> ChannelHistogram {
+        ChannelHistogram { count: 0, min: value, max: value, bucket_width: 1, buckets: BTreeMap::new() }
+    }
+
+    fn record(&mut self, value: EventValue, max_buckets: usize) {
+        self.count += 1;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        *self.buckets.entry((value as i64).div_euclid(self.bucket_width)).or_insert(0) += 1;
+
+        while self.buckets.len() > max_buckets {
+            self.bucket_width *= 2;
+            let mut merged_buckets = BTreeMap::new();
+            for (index, count) in std::mem::take(&mut self.buckets) {
+                *merged_buckets.entry(index.div_euclid(2)).or_insert(0) += count;
+            }
+            self.buckets = merged_buckets;
+        }
+    }
+
+    /// Returns the (lowest value, highest value, count) of each bucket in ascending order.
+    fn buckets(&self) -> impl Iterator<Item=(i64, i64, u64)> + '_ {
+        let bucket_width = self.bucket_width;
+        self.buckets.iter().map(move |(&index, &count)| {
+            let lower_bound = index * bucket_width;
+            (lower_bound, lower_bound + bucket_width - 1, count)
+        })
+    }
+}
+
+impl Histogram {
+    pub fn new(keys: Vec<Key>, max_buckets: usize, format: HistogramFormat) -> Histogram {
+        Histogram { keys, max_buckets, format, channels: HashMap::new() }
+    }
+
+    fn apply(&mut self, event: Event) {
+        if ! self.keys.iter().any(|key| key.matches(&event)) {
+            return;
+        }
+        let num_channels = self.channels.len();
+        let histogram = match self.channels.get_mut(&event.channel()) {
+            Some(histogram) => histogram,
+            None if num_channels >= MAX_CHANNELS => {
+                crate::utils::warn_once(format!(
+                    "Warning: a --histogram has recorded events of {} different channels. Events of other channels will be ignored.", MAX_CHANNELS
+                ));
+                return;
+            },
+            None => self.channels.entry(event.channel()).or_insert_with(|| ChannelHistogram::new(event.value)),
+        };
+        histogram.record(event.value, self.max_buckets);
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event]) {
+        for &event in events {
+            self.apply(event);
+        }
+    }
+
+    /// Returns the recorded histograms formatted according to the format= clause.
+    pub fn report(&self) -> String {
+        let mut channels: Vec<(String, &ChannelHistogram)> = self.channels.iter()
+            .map(|(&channel, histogram)| (channel_name(channel), histogram))
+            .collect();
+        channels.sort_by(|(left, _), (right, _)| left.cmp(right));
+
+        match self.format {
+            HistogramFormat::Text => report_text(&channels),
+            HistogramFormat::Json => report_json(&channels),
+        }
+    }
+}
+
+fn channel_name((code, domain): Channel) -> String {
+    let name = ecodes::event_name(code);
+    match domain::try_reverse_resolve(domain) {
+        Some(domain_name) => format!("{}@{}", name, domain_name),
+        None => name.into_owned(),
+    }
+}
+
+fn report_text(channels: &[(String, &ChannelHistogram)]) -> String {
+    if channels.is_empty() {
+        return "Histogram: no events have been recorded.".to_owned();
+    }
+    let mut lines = Vec::new();
+    for (name, histogram) in channels {
+        lines.push(format!(
+            "Histogram of {}: {} events, minimum {}, maximum {}.",
+            name, histogram.count, histogram.min, histogram.max,
+        ));
+        for (lower_bound, upper_bound, count) in histogram.buckets() {
+            let range = match lower_bound == upper_bound {
+                true => format!("{}", lower_bound),
+                false => format!("{}~{}", lower_bound, upper_bound),
+            };
+            lines.push(format!("    {:>23}: {}", range, count));
+        }
+    }
+    lines.join("\n")
+}
+
+fn report_json(channels: &[(String, &ChannelHistogram)]) -> String {
+    let entries: Vec<String> = channels.iter().map(|(name, histogram)| {
+        let buckets: Vec<String> = histogram.buckets().map(|(lower_bound, upper_bound, count)| {
+            format!("{{\"min\":{},\"max\":{},\"count\":{}}}", lower_bound, upper_bound, count)
+        }).collect();
+        format!(
+            "{{\"event\":{},\"count\":{},\"min\":{},\"max\":{},\"bucket_width\":{},\"buckets\":[{}]}}",
+            json_string(name), histogram.count, histogram.min, histogram.max, histogram.bucket_width, buckets.join(","),
+        )
+    }).collect();
+    format!("{{\"histograms\":[{}]}}", entries.join(","))
+}
+
+#[test]
+fn unittest() {
+    let mut histogram = ChannelHistogram::new(0);
+    for value in 0 .. 8 {
+        histogram.record(value, 4);
+    }
+    assert_eq!(histogram.bucket_width, 2);
+    assert_eq!(histogram.buckets().collect::<Vec<_>>(), vec![(0, 1, 2), (2, 3, 2), (4, 5, 2), (6, 7, 2)]);
+
+    histogram.record(-1, 4);
+    assert_eq!(histogram.bucket_width, 4);
+    assert_eq!(histogram.buckets().collect::<Vec<_>>(), vec![(-4, -1, 1), (0, 3, 4), (4, 7, 4)]);
+    assert_eq!((histogram.count, histogram.min, histogram.max), (9, -1, 7));
+
+}
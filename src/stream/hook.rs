@@ -1,16 +1,17 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
-use crate::error::Context;
+use crate::error::{ArgumentError, Context};
 use crate::range::{Interval, Set};
 use crate::key::Key;
-use crate::event::{Event, Channel};
+use crate::event::{Event, EventCode, Channel, EventType};
 use crate::state::State;
 use crate::subprocess;
 use crate::loopback;
-use crate::loopback::LoopbackHandle;
+use crate::loopback::{LoopbackHandle, SuspendMode};
 use crate::capability::{Capability, Certainty};
 use crate::time::Duration;
-use std::collections::HashSet;
+use crate::domain::Domain;
+use std::collections::{HashSet, VecDeque};
 
 use super::sink::Sink;
 
@@ -60,15 +61,30 @@ struct Tracker {
     /// The state is mutable at runtime. It reflects whether the key tracked by this tracker
     /// is currently pressed or not, as well as which event triggered it and when.
     state: TrackerState,
+    /// The channels of the events that currently hold this tracker down. Only maintained if the
+    /// trigger of this tracker uses strict channels.
+    pressed_channels: Vec<Channel>,
+
+    /// If Some, this tracker does not activate on a single event, but once the absolute values of the
+    /// matching events add up to this threshold. Specified by the accumulate= clause.
+    accumulate: Option<i32>,
+    /// The sum of the absolute values of the events counted so far. Only used if `accumulate` is Some.
+    accumulated: i32,
+    /// If Some, the accumulated total gets reset when this token wakes up, as required by a period= clause.
+    accumulation_token: Option<loopback::Token>,
 }
 
 impl Tracker {
-    fn new(mut key: Key) -> Tracker {
+    fn new(mut key: Key, accumulate: Option<i32>) -> Tracker {
         let range = key.pop_value().unwrap_or_else(|| Interval::new(Some(1), None));
         Tracker {
             key,
             range,
             state: TrackerState::Inactive,
+            pressed_channels: Vec::new(),
+            accumulate,
+            accumulated: 0,
+            accumulation_token: None,
         }
     }
 
@@ -96,12 +112,52 @@ impl Tracker {
         }
     }
 
+    /// Changes the state of this tracker. If the tracker was waiting to expire, that wakeup is
+    /// canceled so it does not wake up the program for nothing later.
+    fn set_state(&mut self, state: TrackerState, loopback: &mut LoopbackHandle) {
+        let old_state = std::mem::replace(&mut self.state, state);
+        if let TrackerState::Active(ExpirationTime::Until(token)) = old_state {
+            loopback.cancel_token(token);
+        }
+    }
+
+    /// Adds the value of an event to the total of a tracker with an accumulate= clause, and activates
+    /// this tracker once the total reaches the threshold. Events whose value is not in the range of this
+    /// tracker, such as scrolling in the opposite direction, reset the total.
+    fn accumulate(&mut self, event: Event, threshold: i32, period: Option<Duration>, suspend_mode: SuspendMode, loopback: &mut LoopbackHandle) {
+        if ! self.activates_by(event) {
+            self.reset_accumulation(loopback);
+            return;
+        }
+        if self.accumulated == 0 {
+            if let Some(period) = period {
+                self.accumulation_token = Some(loopback.schedule_wakeup_in_mode(period, suspend_mode));
+            }
+        }
+        self.accumulated = self.accumulated.saturating_add(event.value.saturating_abs());
+        if self.accumulated >= threshold {
+            self.reset_accumulation(loopback);
+            self.set_state(TrackerState::Active(ExpirationTime::Never), loopback);
+        }
+    }
+
+    fn reset_accumulation(&mut self, loopback: &mut LoopbackHandle) {
+        self.accumulated = 0;
+        if let Some(token) = self.accumulation_token.take() {
+            loopback.cancel_token(token);
+        }
+    }
+
     /// Like Clone::clone, but does not clone the runtime state of the Tracker.
     fn clone_empty(&self) -> Tracker {
         Tracker {
             key: self.key.clone(),
             range: self.range,
             state: TrackerState::Inactive,
+            pressed_channels: Vec::new(),
+            accumulate: self.accumulate,
+            accumulated: 0,
+            accumulation_token: None,
         }
     }
 }
@@ -116,10 +172,29 @@ pub struct Trigger {
     /// order. If a tracker is activated while its previous tracker is still inactive, then
     /// that tracker becomes invalid.
     sequential: bool,
+    /// If Some, then each tracker of a sequential trigger must be activated within a certain duration
+    /// from the activation of the previous tracker, otherwise all trackers become invalid.
+    interval: Option<Duration>,
+    /// Whether the time spent suspended counts towards the period= and interval= clauses.
+    suspend_mode: SuspendMode,
+    /// If true, a tracker only deactivates when all channels that pressed it have been released.
+    /// If false, any event that does not activate a tracker deactivates it, even if it has another
+    /// channel than the event that activated the tracker. The latter is the default for the sake of
+    /// backwards compatibility.
+    strict_channels: bool,
     breaks_on: Vec<Key>,
 
     trackers: Vec<Tracker>,
     state: TriggerState,
+    /// The amount of trackers that are consecutively active from the start, as far as the interval=
+    /// clause is concerned.
+    interval_progress: usize,
+    /// If Some, the sequence gets invalidated when this token wakes up, unless the next tracker
+    /// activates before then.
+    interval_token: Option<loopback::Token>,
+    /// The indices of the trackers that were active when the device that held them got disconnected,
+    /// together with the channel that held them. See `suspend_domain()`.
+    suspended: Vec<(usize, Channel)>,
 }
 
 /// Returned by Trigger::apply to inform the caller what effect the provided event had on
@@ -136,6 +211,10 @@ pub enum TriggerResponse {
     Activates,
     /// The hook has released because of this event. Its on-release effects should be triggered.
     Releases,
+    /// The hook has activated and immediately released again because of this event, which happens when
+    /// the total of an accumulate= clause was reached. Both its effects and on-release effects should be
+    /// triggered.
+    Pulses,
 }
 
 #[derive(Clone, Copy)]
@@ -147,14 +226,31 @@ enum TriggerState {
 }
 
 impl Trigger {
-    pub fn new(keys: Vec<Key>, breaks_on : Vec<Key>, period: Option<Duration>, sequential: bool) -> Trigger {
-        let trackers = keys.into_iter().map(Tracker::new).collect();
+    pub fn new(
+        keys: Vec<Key>, breaks_on : Vec<Key>, period: Option<Duration>, sequential: bool, interval: Option<Duration>,
+        strict_channels: bool, accumulate: Option<i32>,
+    ) -> Trigger {
+        // The accumulate= clause only applies to the keys of type EV_REL.
+        let trackers = keys.into_iter().map(|key| {
+            let accumulate = accumulate.filter(|_| key.requires_event_type() == Some(EventType::REL));
+            Tracker::new(key, accumulate)
+        }).collect();
         Trigger {
-            period, trackers, sequential, breaks_on,
+            period, trackers, sequential, interval, strict_channels, breaks_on,
+            suspend_mode: SuspendMode::Pause,
             state: TriggerState::Inactive,
+            interval_progress: 0,
+            interval_token: None,
+            suspended: Vec::new(),
         }
     }
 
+    /// Sets whether the time spent suspended counts towards the period= and interval= clauses.
+    pub fn with_suspend_mode(mut self, suspend_mode: SuspendMode) -> Trigger {
+        self.suspend_mode = suspend_mode;
+        self
+    }
+
     pub fn apply(&mut self, event: Event, loopback: &mut LoopbackHandle) -> TriggerResponse {
         let mut any_tracker_matched: bool = false;
 
@@ -162,21 +258,34 @@ impl Trigger {
             .filter(|tracker| tracker.matches(&event))
         {
             any_tracker_matched = true;
+            if let Some(threshold) = tracker.accumulate {
+                tracker.accumulate(event, threshold, self.period, self.suspend_mode, loopback);
+                continue;
+            }
+            let channel = event.channel();
 
             if tracker.activates_by(event) {
+                if self.strict_channels && ! tracker.pressed_channels.contains(&channel) {
+                    tracker.pressed_channels.push(channel);
+                }
                 match tracker.state {
                     // If this tracker was inactive, activate it.
                     TrackerState::Inactive => {
                         // Note: if this hook is sequential, this activation may get invalidated
                         // later in this function.
                         tracker.state = TrackerState::Active(
-                            acquire_expiration_token(self.period, loopback)
+                            acquire_expiration_token(self.period, self.suspend_mode, loopback)
                         );
                     },
                     TrackerState::Active(..) | TrackerState::Invalid => {},
                 }
+            } else if self.strict_channels {
+                tracker.pressed_channels.retain(|&pressed_channel| pressed_channel != channel);
+                if tracker.pressed_channels.is_empty() {
+                    tracker.set_state(TrackerState::Inactive, loopback);
+                }
             } else {
-                tracker.state = TrackerState::Inactive;
+                tracker.set_state(TrackerState::Inactive, loopback);
             };
         }
         
@@ -187,11 +296,14 @@ impl Trigger {
                 let mut any_tracker_invalidated = false;
 
                 for tracker in &mut self.trackers {
+                    if tracker.accumulated > 0 {
+                        tracker.reset_accumulation(loopback);
+                        any_tracker_invalidated = true;
+                    }
                     match tracker.state {
                         TrackerState::Active(_) => {
-                            tracker.state = TrackerState::Invalid;
+                            tracker.set_state(TrackerState::Invalid, loopback);
                             any_tracker_invalidated = true;
-                            // TODO: LOW-PRIORITY Cancel token.
                         },
                         TrackerState::Inactive | TrackerState::Invalid => {},
                     }
@@ -207,26 +319,36 @@ impl Trigger {
         }
 
         if self.sequential {
-            // Invalidate all trackers that activated out of order.
-            self.trackers.iter_mut()
-                // Skip all trackers that are consecutively active from the start.
-                .skip_while(|tracker| tracker.is_active())
-                // ... then find all trackers that are active but not consecutively so.
-                .filter(|tracker| tracker.is_active())
-                // ... and invalidate them.
-                // TODO: LOW-PRIORITY Consider canceling the activation token.
-                .for_each(|tracker| tracker.state = TrackerState::Invalid);
+            self.invalidate_out_of_order(loopback);
         }
 
         // Check if we transitioned between active and inactive.
         let all_trackers_active = self.trackers.iter().all(|tracker| tracker.state.is_active());
 
+        // Trackers with an accumulate= clause only count as active for the event that completed their total.
+        let mut any_accumulation_completed = false;
+        for tracker in &mut self.trackers {
+            if tracker.accumulate.is_some() && tracker.is_active() {
+                tracker.set_state(TrackerState::Inactive, loopback);
+                any_accumulation_completed = true;
+            }
+        }
+
         match (self.state, all_trackers_active) {
+            (TriggerState::Inactive, true) if any_accumulation_completed => {
+                // The other trackers stay active so the hook can activate again without pressing them again.
+                for tracker in &mut self.trackers {
+                    if tracker.is_active() {
+                        tracker.set_state(TrackerState::Active(ExpirationTime::Never), loopback);
+                    }
+                }
+                TriggerResponse::Pulses
+            },
             (TriggerState::Inactive, true) => {
                 self.state = TriggerState::Active;
-                // TODO: LOW-PRIORITY Cancel tokens?
+                // Now that the hook is active, the period= clause no longer applies.
                 for tracker in &mut self.trackers {
-                    tracker.state = TrackerState::Active(ExpirationTime::Never);
+                    tracker.set_state(TrackerState::Active(ExpirationTime::Never), loopback);
                 }
                 TriggerResponse::Activates
             },
@@ -239,13 +361,69 @@ impl Trigger {
         }
     }
 
+    /// Invalidates all trackers of a sequential trigger that activated out of order.
+    fn invalidate_out_of_order(&mut self, loopback: &mut LoopbackHandle) {
+        self.trackers.iter_mut()
+            // Skip all trackers that are consecutively active from the start.
+            .skip_while(|tracker| tracker.is_active())
+            // ... then find all trackers that are active but not consecutively so.
+            .filter(|tracker| tracker.is_active())
+            // ... and invalidate them.
+            .for_each(|tracker| tracker.set_state(TrackerState::Invalid, loopback));
+
+        self.update_interval_token(loopback);
+    }
+
+    /// If an interval= clause was specified, (re)starts the timer for the next tracker to activate
+    /// whenever the sequence progresses. Must be called after out-of-order trackers have been invalidated.
+    fn update_interval_token(&mut self, loopback: &mut LoopbackHandle) {
+        let interval = match self.interval {
+            Some(interval) => interval,
+            None => return,
+        };
+        let progress = self.trackers.iter().take_while(|tracker| tracker.is_active()).count();
+        if progress == self.interval_progress {
+            return;
+        }
+        let previous_progress = std::mem::replace(&mut self.interval_progress, progress);
+
+        if let Some(token) = self.interval_token.take() {
+            loopback.cancel_token(token);
+        }
+        // If the sequence went backwards because a key was released, the timer only restarts once the
+        // sequence progresses again. Once all trackers are active, the interval= clause no longer applies.
+        if progress > previous_progress && progress < self.trackers.len() {
+            self.interval_token = Some(loopback.schedule_wakeup_in_mode(interval, self.suspend_mode));
+        }
+    }
+
     /// Release a tracker that has expired. If a tracker expired, returns the associated key.
     /// It is important that the Tokens are unique for this function to work correctly.
     /// 
     /// Returns true if at least one tracker expired. Returns false otherwise.
     pub fn wakeup(&mut self, token: &loopback::Token) -> bool {
+        if self.interval_token.as_ref() == Some(token) {
+            // The next tracker took too long to activate, so the whole sequence is invalidated.
+            self.interval_token = None;
+            self.interval_progress = 0;
+            let mut result = false;
+            for tracker in &mut self.trackers {
+                if tracker.is_active() {
+                    tracker.state = TrackerState::Invalid;
+                    result = true;
+                }
+            }
+            return result;
+        }
+
         let mut result = false;
         for tracker in &mut self.trackers {
+            if tracker.accumulation_token.as_ref() == Some(token) {
+                // The total was not reached within the period.
+                tracker.accumulation_token = None;
+                tracker.accumulated = 0;
+                result = true;
+            }
             match tracker.state {
                 TrackerState::Inactive => {},
                 TrackerState::Invalid => {},
@@ -262,12 +440,107 @@ impl Trigger {
         result
     }
 
+    /// Returns all trackers that may have been pressed by events with the given domain to their
+    /// inactive state, as if the domain never sent any events. Does nothing while this trigger is
+    /// active, because then its release effects still need to happen.
+    pub fn reset_domain(&mut self, domain: Domain, loopback: &mut LoopbackHandle) {
+        if let TriggerState::Active = self.state {
+            return;
+        }
+        let strict_channels = self.strict_channels;
+        let mut any_tracker_reset = false;
+        for tracker in &mut self.trackers {
+            let may_match_domain = match tracker.key.requires_event_code() {
+                Some(code) => tracker.matches_channel((code, domain)),
+                None => true,
+            };
+            if tracker.accumulated > 0 && may_match_domain {
+                tracker.reset_accumulation(loopback);
+            }
+            if let TrackerState::Inactive = tracker.state {
+                continue;
+            }
+            let may_be_pressed_by_domain = match strict_channels {
+                true => tracker.pressed_channels.iter().any(|&(_, channel_domain)| channel_domain == domain),
+                false => may_match_domain,
+            };
+            if may_be_pressed_by_domain {
+                tracker.set_state(TrackerState::Inactive, loopback);
+                tracker.pressed_channels.clear();
+                any_tracker_reset = true;
+            }
+        }
+
+        if any_tracker_reset {
+            self.interval_progress = 0;
+            if let Some(token) = self.interval_token.take() {
+                loopback.cancel_token(token);
+            }
+        }
+    }
+
+    /// Call this before releasing the keys of a device that got disconnected but may be reopened soon.
+    /// Remembers which active trackers are held by the given keys of that device, so they can be
+    /// restored by `restore_domain()` if the device comes back with those keys still held.
+    pub fn suspend_domain(&mut self, domain: Domain, pressed_keys: &[EventCode]) {
+        self.suspended.retain(|&(_, (_, suspended_domain))| suspended_domain != domain);
+        for (index, tracker) in self.trackers.iter().enumerate() {
+            if ! tracker.is_active() || tracker.accumulate.is_some() {
+                continue;
+            }
+            for &code in pressed_keys {
+                let channel = (code, domain);
+                let is_held_by_channel = match self.strict_channels {
+                    true => tracker.pressed_channels.contains(&channel),
+                    false => tracker.matches_channel(channel),
+                };
+                if is_held_by_channel {
+                    self.suspended.push((index, channel));
+                }
+            }
+        }
+    }
+
+    /// Call this after a device has been reopened. Reactivates the trackers that were suspended when
+    /// that device got disconnected, as far as their keys are still held according to `pressed_keys`.
+    /// Restoring trackers never triggers the hook by itself.
+    pub fn restore_domain(&mut self, domain: Domain, pressed_keys: &[EventCode], loopback: &mut LoopbackHandle) {
+        let mut restorable = Vec::new();
+        self.suspended.retain(|&(index, channel)| match channel.1 == domain {
+            true => { restorable.push((index, channel)); false },
+            false => true,
+        });
+        if let TriggerState::Active = self.state {
+            return;
+        }
+
+        for (index, channel) in restorable {
+            if ! pressed_keys.contains(&channel.0) {
+                continue;
+            }
+            let tracker = &mut self.trackers[index];
+            if let TrackerState::Inactive = tracker.state {
+                tracker.set_state(TrackerState::Active(acquire_expiration_token(self.period, self.suspend_mode, loopback)), loopback);
+            }
+            if self.strict_channels && ! tracker.pressed_channels.contains(&channel) {
+                tracker.pressed_channels.push(channel);
+            }
+        }
+        if self.sequential {
+            self.invalidate_out_of_order(loopback);
+        }
+    }
+
     /// Returns true if any of the active trackers might have been activated by an event
-    /// with the provided channel, regardless of whether that channel actually activated them.
+    /// with the provided channel. Unless this trigger uses strict channels, this holds regardless
+    /// of whether that channel actually activated them.
     pub fn has_active_tracker_matching_channel(&self, channel: Channel) -> bool {
         self.trackers.iter()
             .filter(|tracker| tracker.is_active())
-            .any(   |tracker| tracker.matches_channel(channel))
+            .any(   |tracker| match self.strict_channels {
+                true => tracker.pressed_channels.contains(&channel),
+                false => tracker.matches_channel(channel),
+            })
     }
 
     /// Returns true if any of the might be activated by an event with the provided channel.
@@ -280,10 +553,16 @@ impl Trigger {
     pub fn clone_empty(&self) -> Trigger {
         Trigger {
             sequential: self.sequential,
+            interval: self.interval,
+            strict_channels: self.strict_channels,
             period: self.period,
+            suspend_mode: self.suspend_mode,
             breaks_on: self.breaks_on.clone(),
             trackers: self.trackers.iter().map(Tracker::clone_empty).collect(),
             state: TriggerState::Inactive,
+            interval_progress: 0,
+            interval_token: None,
+            suspended: Vec::new(),
         }
     }
 }
@@ -318,11 +597,12 @@ impl Hook {
         // If any more logic were to be added to this function, then that logic would not be executed if this
         // hook becomes part of a `HookGroup`. Which is a bad thing.
         let response = self.trigger.apply(event, loopback);
-        self.actuator.apply_response(response, event, (), events_out, state);
+        self.actuator.apply_response(response, event, (), events_out, state, loopback);
     }
 
-    pub fn wakeup(&mut self, token: &loopback::Token) {
+    pub fn wakeup(&mut self, token: &loopback::Token, events_out: &mut Vec<Event>) {
         self.trigger.wakeup(token);
+        self.actuator.event_dispatcher.wakeup(token, events_out);
     }
 
     pub fn apply_to_all(&mut self, events: &[Event], events_out: &mut Vec<Event>, state: &mut State, loopback: &mut LoopbackHandle) {
@@ -360,10 +640,11 @@ impl HookActuator {
         event: Event,
         event_data: U,
         events_out: &mut T,
-        state: &mut State
+        state: &mut State,
+        loopback: &mut LoopbackHandle,
     ) where T: Sink<AdditionalData=U>
     {
-        self.event_dispatcher.map_event(event, event_data, response, events_out);
+        self.event_dispatcher.map_event(event, event_data, response, events_out, loopback);
 
         match response {
             TriggerResponse::Activates => {
@@ -372,6 +653,10 @@ impl HookActuator {
             TriggerResponse::Releases => {
                 self.apply_release_effects(state);
             },
+            TriggerResponse::Pulses => {
+                self.apply_effects(state);
+                self.apply_release_effects(state);
+            },
             TriggerResponse::Interacts | TriggerResponse::None => (),
         }
     }
@@ -397,6 +682,12 @@ impl HookActuator {
         self.effects.push(effect);
     }
 
+    /// Makes this hook run a built-in effect from `crate::effects` when it triggers, e.g. "xkb-layout:us,de".
+    pub fn add_builtin_effect(&mut self, spec: &str) -> Result<(), ArgumentError> {
+        self.add_effect(crate::effects::create(spec)?);
+        Ok(())
+    }
+
     /// Makes this hook invoke an external subprocess when this hook is triggered.
     pub fn add_command(&mut self, program: String, args: Vec<String>) {
         self.add_effect(
@@ -407,24 +698,93 @@ impl HookActuator {
     }
 }
 
+/// An event that a hook sends when it activates, possibly several times in a row as requested by
+/// a count like send-key=key:right:x10.
+#[derive(Clone)]
+pub struct Burst {
+    pub key: Key,
+    /// How many times the event gets sent. At least one.
+    pub count: usize,
+    /// If Some, every time the event is sent except the last time, it is followed up by this event.
+    /// This turns a send-key clause into a series of taps of which only the last press is held.
+    pub release: Option<Key>,
+}
+
 /// The part of the --hook that is responsible for handling the send-key= clause.
 /// Implemented separately from the hook because it is possible we want to remove this
 /// functionality from the --hook itself and move it to a --withhold instead.
 pub struct EventDispatcher {
     /// Events that shall be sent on press in the order specified.
-    on_press: Vec<Key>,
+    on_press: Vec<Burst>,
     /// Events that shall be sent on release *in the order specified*. If you want them
     /// in another order, like reverse order, then reverse them before you put them here.
     on_release: Vec<Key>,
+    /// If Some, the repetitions of a burst are sent this far apart instead of all at once.
+    burst_interval: Option<Duration>,
     /// The last event that activated the corresponding Hook/Trigger.
     activating_event: Option<Event>,
+    /// Groups of events that wait to be sent because of the burst interval, in the order in which
+    /// they must be sent.
+    scheduled: VecDeque<(loopback::Token, Vec<Event>)>,
 }
 
 impl EventDispatcher {
-    pub fn new(on_press: Vec<Key>, on_release: Vec<Key>) -> EventDispatcher {
+    pub fn new(on_press: Vec<Burst>, on_release: Vec<Key>, burst_interval: Option<Duration>) -> EventDispatcher {
         EventDispatcher {
-            on_press, on_release,
-            activating_event: None
+            on_press, on_release, burst_interval,
+            activating_event: None,
+            scheduled: VecDeque::new(),
+        }
+    }
+
+    /// Returns the events that shall be sent when the hook activates because of `event`. Each
+    /// repetition of a burst starts a new group; the groups are sent one burst interval apart.
+    fn press_groups(&self, event: Event) -> Vec<Vec<Event>> {
+        let mut groups: Vec<Vec<Event>> = vec![Vec::new()];
+        for burst in &self.on_press {
+            for index in 0 .. burst.count {
+                if index > 0 {
+                    groups.push(Vec::new());
+                }
+                let group = groups.last_mut().expect("Internal error: no group of events to send. This is a bug.");
+                group.push(burst.key.merge(event));
+                if let (true, Some(release)) = (index + 1 < burst.count, &burst.release) {
+                    group.push(release.merge(event));
+                }
+            }
+        }
+        groups
+    }
+
+    /// Sends the first group of events right away and schedules the other groups to be sent one burst
+    /// interval apart. If some events are still waiting to be sent, all groups wait until after them.
+    fn dispatch<T: Sink>(&mut self, groups: Vec<Vec<Event>>, events_out: &mut T, loopback: &mut LoopbackHandle) {
+        let mut may_send_now = self.scheduled.is_empty();
+        for group in groups.into_iter().filter(|group| ! group.is_empty()) {
+            match self.burst_interval {
+                Some(interval) if ! may_send_now => {
+                    let delay = Duration::from_micros(interval.as_micros() * (self.scheduled.len() as u64 + 1));
+                    let token = loopback.schedule_wakeup_in(delay);
+                    self.scheduled.push_back((token, group));
+                },
+                _ => for new_event in group {
+                    events_out.push_new_event(new_event);
+                },
+            }
+            may_send_now = false;
+        }
+    }
+
+    /// Sends the events that were scheduled for this wakeup, as well as all events scheduled before them.
+    pub fn wakeup(&mut self, token: &loopback::Token, events_out: &mut Vec<Event>) {
+        if ! self.scheduled.iter().any(|(other_token, _)| other_token == token) {
+            return;
+        }
+        while let Some((other_token, group)) = self.scheduled.pop_front() {
+            events_out.extend(group);
+            if &other_token == token {
+                break;
+            }
         }
     }
 
@@ -439,15 +799,15 @@ impl EventDispatcher {
         // The response that was received when this event was given to the `Trigger`.
         trigger_response: TriggerResponse,
         // Where the original event and all generated events go.
-        events_out: &mut T
+        events_out: &mut T,
+        loopback: &mut LoopbackHandle,
     ) where T: Sink<AdditionalData = U>{
         match trigger_response {
             TriggerResponse::Activates => {
                 events_out.push_event(event, event_data);
                 self.activating_event = Some(event);
-                for key in &self.on_press {
-                    events_out.push_new_event(key.merge(event));
-                };
+                let groups = self.press_groups(event);
+                self.dispatch(groups, events_out, loopback);
             },
             TriggerResponse::Releases => {
                 let activating_event = match self.activating_event {
@@ -457,11 +817,17 @@ impl EventDispatcher {
                         event
                     }
                 };
-                for key in &self.on_release {
-                    events_out.push_new_event(key.merge(activating_event));
-                }
+                let release_events = self.on_release.iter().map(|key| key.merge(activating_event)).collect();
+                self.dispatch(vec![release_events], events_out, loopback);
                 events_out.push_event(event, event_data);
             },
+            TriggerResponse::Pulses => {
+                events_out.push_event(event, event_data);
+                self.activating_event = Some(event);
+                let mut groups = self.press_groups(event);
+                groups.push(self.on_release.iter().map(|key| key.merge(event)).collect());
+                self.dispatch(groups, events_out, loopback);
+            },
             TriggerResponse::Interacts | TriggerResponse::None => {
                 events_out.push_event(event, event_data);
             },
@@ -503,8 +869,8 @@ impl EventDispatcher {
             }
             let potentially_matching_cap = cap_in.clone().with_values(potentially_matching_values);
 
-            let EventDispatcher { on_press, on_release, activating_event: _ } = self;
-            let additional_events = on_press.iter().chain(on_release);
+            let EventDispatcher { on_press, on_release, burst_interval: _, activating_event: _, scheduled: _ } = self;
+            let additional_events = on_press.iter().map(|burst| &burst.key).chain(on_release);
             additional_caps.extend(additional_events.map(
                 |key| key.merge_cap(potentially_matching_cap.clone())
             ));
@@ -516,9 +882,183 @@ impl EventDispatcher {
 
 /// If this hook has a period set, acquires a Token from the loopback and arranges for a
 /// `wakeup()` call later. If no period is set, return `ExpirationTime::Never`.
-fn acquire_expiration_token(period: Option<Duration>, loopback: &mut LoopbackHandle) -> ExpirationTime {
+fn acquire_expiration_token(period: Option<Duration>, suspend_mode: SuspendMode, loopback: &mut LoopbackHandle) -> ExpirationTime {
     match period {
-        Some(duration) => ExpirationTime::Until(loopback.schedule_wakeup_in(duration)),
+        Some(duration) => ExpirationTime::Until(loopback.schedule_wakeup_in_mode(duration, suspend_mode)),
         None => ExpirationTime::Never,
     }
 }
+
+#[test]
+fn unittest_cancel_expiration_tokens() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let code = |name: &str| crate::ecodes::event_code("key", name).unwrap();
+    let key_event = |name: &str, value: i32| Event::new(code(name), value, 1 - value, domain, Namespace::User);
+    let keys = KeyParser::default_filter().parse_all(&["key:a".to_owned(), "key:b".to_owned()]).unwrap();
+    let start = Instant::now();
+    let mut loopback = loopback::Loopback::new();
+
+    // Releasing a key before the period ends cancels its wakeup.
+    let mut trigger = Trigger::new(keys.clone(), Vec::new(), Some(Duration::from_millis(500)), false, None, false, None);
+    trigger.apply(key_event("a", 1), &mut loopback.get_handle(start));
+    trigger.apply(key_event("a", 0), &mut loopback.get_handle(start));
+    assert!(loopback.poll_once(start + Duration::from_secs(1)).is_none());
+
+    // So does activating the trigger.
+    let mut trigger = Trigger::new(keys.clone(), Vec::new(), Some(Duration::from_millis(500)), false, None, false, None);
+    trigger.apply(key_event("a", 1), &mut loopback.get_handle(start));
+    trigger.apply(key_event("b", 1), &mut loopback.get_handle(start));
+    assert!(loopback.poll_once(start + Duration::from_secs(1)).is_none());
+
+    // So does invalidating a tracker that was pressed out of order.
+    let mut trigger = Trigger::new(keys, Vec::new(), Some(Duration::from_millis(500)), true, None, false, None);
+    trigger.apply(key_event("b", 1), &mut loopback.get_handle(start));
+    assert!(loopback.poll_once(start + Duration::from_secs(1)).is_none());
+}
+
+#[test]
+fn unittest_accumulate() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let code = crate::ecodes::event_code("rel", "wheel").unwrap();
+    let wheel_event = |value: i32| Event::new(code, value, 0, domain, Namespace::User);
+    let keys = KeyParser::default_filter().parse_all(&["rel:wheel:1~".to_owned()]).unwrap();
+    let start = Instant::now();
+    let mut loopback = loopback::Loopback::new();
+
+    // The total must be reached within the period.
+    let mut trigger = Trigger::new(keys.clone(), Vec::new(), Some(Duration::from_secs(2)), false, None, false, Some(3));
+    trigger.apply(wheel_event(1), &mut loopback.get_handle(start));
+    trigger.apply(wheel_event(1), &mut loopback.get_handle(start));
+    let (_, token) = loopback.poll_once(start + Duration::from_secs(3)).unwrap();
+    assert!(trigger.wakeup(&token));
+    assert!(matches!(trigger.apply(wheel_event(1), &mut loopback.get_handle(start)), TriggerResponse::Interacts));
+    assert!(matches!(trigger.apply(wheel_event(1), &mut loopback.get_handle(start)), TriggerResponse::Interacts));
+    assert!(matches!(trigger.apply(wheel_event(1), &mut loopback.get_handle(start)), TriggerResponse::Pulses));
+    // Reaching the total cancels the wakeup of the period.
+    assert!(loopback.poll_once(start + Duration::from_secs(3)).is_none());
+
+    // Without a period, the events may be spread out over any amount of time.
+    let mut trigger = Trigger::new(keys, Vec::new(), None, false, None, false, Some(5));
+    assert!(matches!(trigger.apply(wheel_event(2), &mut loopback.get_handle(start)), TriggerResponse::Interacts));
+    assert!(matches!(trigger.apply(wheel_event(3), &mut loopback.get_handle(start)), TriggerResponse::Pulses));
+    assert!(loopback.poll_once(start + Duration::from_secs(3)).is_none());
+}
+
+#[test]
+fn unittest_burst_interval() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let code = |name: &str| crate::ecodes::event_code("key", name).unwrap();
+    let key_event = |name: &str, value: i32| Event::new(code(name), value, 1 - value, domain, Namespace::User);
+    let parser = KeyParser::default_filter();
+    let press = parser.parse("key:right:1").unwrap();
+    let release = parser.parse("key:right:0").unwrap();
+    let mut dispatcher = EventDispatcher::new(
+        vec![Burst { key: press, count: 3, release: Some(release.clone()) }], vec![release], Some(Duration::from_millis(100)),
+    );
+    let values = |events: &[Event]| events.iter().map(|event| (event.code == code("right"), event.value)).collect::<Vec<_>>();
+    let start = Instant::now();
+    let mut loopback = loopback::Loopback::new();
+
+    let mut output: Vec<Event> = Vec::new();
+    dispatcher.map_event(key_event("f1", 1), (), TriggerResponse::Activates, &mut output, &mut loopback.get_handle(start));
+    assert_eq!(values(&output), vec![(false, 1), (true, 1), (true, 0)]);
+    // Releasing the hook before the burst is over delays the final release until after the last press.
+    output.clear();
+    dispatcher.map_event(key_event("f1", 0), (), TriggerResponse::Releases, &mut output, &mut loopback.get_handle(start));
+    assert_eq!(values(&output), vec![(false, 0)]);
+
+    let mut wakeup_times = Vec::new();
+    output.clear();
+    while let Some((time, token)) = loopback.poll_once(start + Duration::from_secs(1)) {
+        wakeup_times.push(time);
+        dispatcher.wakeup(&token, &mut output);
+    }
+    assert_eq!(values(&output), vec![(true, 1), (true, 0), (true, 1), (true, 0)]);
+    assert_eq!(wakeup_times.len(), 3);
+    assert!(wakeup_times[0] < wakeup_times[1] && wakeup_times[1] < wakeup_times[2]);
+}
+
+#[test]
+fn unittest_interval() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let code = |name: &str| crate::ecodes::event_code("key", name).unwrap();
+    let key_event = |name: &str, value: i32| Event::new(code(name), value, 1 - value, domain, Namespace::User);
+    let keys = KeyParser::default_filter().parse_all(&["key:a".to_owned(), "key:b".to_owned(), "key:c".to_owned()]).unwrap();
+    let interval = Duration::from_millis(300);
+    let start = Instant::now();
+    let mut loopback = loopback::Loopback::new();
+
+    // Each key comes within the interval of the previous one, even though all keys together take longer.
+    let mut trigger = Trigger::new(keys.clone(), Vec::new(), None, true, Some(interval), false, None);
+    trigger.apply(key_event("a", 1), &mut loopback.get_handle(start));
+    let time_b = start + Duration::from_millis(200);
+    assert!(loopback.poll_once(time_b).is_none());
+    trigger.apply(key_event("b", 1), &mut loopback.get_handle(time_b));
+    let time_c = time_b + Duration::from_millis(200);
+    assert!(loopback.poll_once(time_c).is_none());
+    assert!(matches!(trigger.apply(key_event("c", 1), &mut loopback.get_handle(time_c)), TriggerResponse::Activates));
+    assert!(loopback.poll_once(time_c + Duration::from_secs(1)).is_none());
+
+    // A step that is too slow invalidates the sequence.
+    let mut trigger = Trigger::new(keys, Vec::new(), None, true, Some(interval), false, None);
+    trigger.apply(key_event("a", 1), &mut loopback.get_handle(start));
+    trigger.apply(key_event("b", 1), &mut loopback.get_handle(start));
+    let (_, token) = loopback.poll_once(start + Duration::from_millis(400)).unwrap();
+    assert!(trigger.wakeup(&token));
+    assert!(matches!(trigger.apply(key_event("c", 1), &mut loopback.get_handle(start)), TriggerResponse::Interacts));
+}
+
+#[test]
+fn unittest_suspend_domain() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let other_domain = crate::domain::get_unique_domain();
+    let code = |name: &str| crate::ecodes::event_code("key", name).unwrap();
+    let key_event = |name: &str, value: i32| Event::new(code(name), value, 1 - value, domain, Namespace::User);
+    let keys = KeyParser::default_filter().parse_all(&["key:leftctrl".to_owned(), "key:a".to_owned()]).unwrap();
+    let start = Instant::now();
+    let mut loopback = loopback::Loopback::new();
+
+    for &strict_channels in &[false, true] {
+        // A key that is still held after reopening the device keeps counting towards the hook.
+        let mut trigger = Trigger::new(keys.clone(), Vec::new(), None, false, None, strict_channels, None);
+        trigger.apply(key_event("leftctrl", 1), &mut loopback.get_handle(start));
+        trigger.suspend_domain(domain, &[code("leftctrl")]);
+        trigger.apply(key_event("leftctrl", 0), &mut loopback.get_handle(start));
+        trigger.restore_domain(other_domain, &[code("leftctrl")], &mut loopback.get_handle(start));
+        trigger.restore_domain(domain, &[code("leftctrl")], &mut loopback.get_handle(start));
+        assert!(matches!(trigger.apply(key_event("a", 1), &mut loopback.get_handle(start)), TriggerResponse::Activates));
+
+        // A key that was released while the device was gone does not.
+        let mut trigger = Trigger::new(keys.clone(), Vec::new(), None, false, None, strict_channels, None);
+        trigger.apply(key_event("leftctrl", 1), &mut loopback.get_handle(start));
+        trigger.suspend_domain(domain, &[code("leftctrl")]);
+        trigger.apply(key_event("leftctrl", 0), &mut loopback.get_handle(start));
+        trigger.restore_domain(domain, &[], &mut loopback.get_handle(start));
+        assert!(matches!(trigger.apply(key_event("a", 1), &mut loopback.get_handle(start)), TriggerResponse::Interacts));
+
+        // The saved state is only restored once.
+        trigger.apply(key_event("a", 0), &mut loopback.get_handle(start));
+        trigger.restore_domain(domain, &[code("leftctrl")], &mut loopback.get_handle(start));
+        assert!(matches!(trigger.apply(key_event("a", 1), &mut loopback.get_handle(start)), TriggerResponse::Interacts));
+    }
+}
@@ -0,0 +1,136 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --inhibit-while-typing argument, which drops events of pointing devices for a while
+//! after a key has been typed, so a palm resting on a touchpad cannot move the cursor or click while
+//! typing.
+
+use std::collections::HashSet;
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::Duration;
+
+pub struct InhibitWhileTyping {
+    /// The events that get dropped while typing.
+    keys: Vec<Key>,
+    /// The events that count as typing.
+    typing_keys: Vec<Key>,
+    /// Events that match both a typing key and one of these do not count as typing.
+    except_keys: Vec<Key>,
+    /// How long events get dropped after the last typed key.
+    period: Duration,
+
+    /// State: modifiable at runtime.
+    /// Is Some while events are being dropped. The period ends when this token wakes up.
+    inhibit_token: Option<Token>,
+    /// EV_KEY channels whose press has been dropped, so their repeat and release events get dropped as
+    /// well, even if the period has ended by then.
+    inhibited_channels: HashSet<Channel>,
+}
+
+impl InhibitWhileTyping {
+    pub fn new(keys: Vec<Key>, typing_keys: Vec<Key>, except_keys: Vec<Key>, period: Duration) -> InhibitWhileTyping {
+        InhibitWhileTyping {
+            keys, typing_keys, except_keys, period,
+            inhibit_token: None,
+            inhibited_channels: HashSet::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if self.typing_keys.iter().any(|key| key.matches(&event))
+            && ! self.except_keys.iter().any(|key| key.matches(&event))
+        {
+            if let Some(token) = self.inhibit_token.take() {
+                loopback.cancel_token(token);
+            }
+            self.inhibit_token = Some(loopback.schedule_wakeup_in(self.period));
+            return output_events.push(event);
+        }
+        if ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+
+        if event.ev_type().is_key() {
+            // Keys that were pressed before the period started can still be released, and keys whose
+            // press was dropped stay released until they are pressed again after the period.
+            let is_inhibited = match event.value {
+                1 if self.inhibit_token.is_some() => self.inhibited_channels.insert(event.channel()),
+                0 => self.inhibited_channels.remove(&event.channel()),
+                _ => self.inhibited_channels.contains(&event.channel()),
+            };
+            if ! is_inhibited {
+                output_events.push(event);
+            }
+        } else if self.inhibit_token.is_none() {
+            output_events.push(event);
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    /// Ends the period during which events are dropped.
+    pub fn wakeup(&mut self, token: &Token) {
+        if self.inhibit_token.as_ref() == Some(token) {
+            self.inhibit_token = None;
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::time::Instant;
+
+    let parser = KeyParser::default_filter();
+    let kb = crate::domain::get_unique_domain();
+    let pad = crate::domain::get_unique_domain();
+    let event = |ev_type, code, value, domain| {
+        Event::new(crate::ecodes::event_code(ev_type, code).unwrap(), value, 0, domain, Namespace::User)
+    };
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    let mut inhibit = InhibitWhileTyping::new(
+        vec![parser.parse("btn:touch").unwrap(), parser.parse("rel").unwrap()],
+        vec![parser.parse("key").unwrap()],
+        vec![parser.parse("key:leftctrl").unwrap()],
+        Duration::from_millis(300),
+    );
+    let mut loopback = crate::loopback::Loopback::new();
+    let mut output = Vec::new();
+    // Returns whether the event was passed on.
+    let mut apply = |inhibit: &mut InhibitWhileTyping, loopback: &mut crate::loopback::Loopback, (ev_type, code), value, domain, millis| {
+        let event = event(ev_type, code, value, domain);
+        inhibit.apply(event, &mut output, &mut loopback.get_handle(at(millis)));
+        output.last() == Some(&event)
+    };
+
+    // A touch that started before typing can end while typing.
+    assert!(apply(&mut inhibit, &mut loopback, ("btn", "touch"), 1, pad, 0));
+    assert!(apply(&mut inhibit, &mut loopback, ("key", "a"), 1, kb, 10));
+    assert!(! apply(&mut inhibit, &mut loopback, ("rel", "x"), 5, pad, 20));
+    assert!(apply(&mut inhibit, &mut loopback, ("btn", "touch"), 0, pad, 30));
+    // A touch that starts while typing gets dropped until it ends, even after the period is over.
+    assert!(! apply(&mut inhibit, &mut loopback, ("btn", "touch"), 1, pad, 40));
+    let (time, token) = loopback.poll_once(at(400)).unwrap();
+    assert_eq!(time, at(310));
+    inhibit.wakeup(&token);
+    assert!(apply(&mut inhibit, &mut loopback, ("rel", "x"), 5, pad, 400));
+    assert!(! apply(&mut inhibit, &mut loopback, ("btn", "touch"), 0, pad, 410));
+    // Modifiers do not count as typing.
+    assert!(apply(&mut inhibit, &mut loopback, ("key", "leftctrl"), 1, kb, 500));
+    assert!(apply(&mut inhibit, &mut loopback, ("btn", "touch"), 1, pad, 510));
+    assert!(loopback.poll_once(at(10_000)).is_none());
+}
@@ -0,0 +1,67 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::Capability;
+use crate::event::{Event, EventCode};
+use crate::key::Key;
+use crate::range::Interval;
+use crate::state::{State, ToggleIndex};
+
+/// Represents an --invert-scroll argument.
+pub struct InvertScroll {
+    /// Only scroll events matching at least one of these keys are inverted.
+    keys: Vec<Key>,
+    /// The scroll codes that are affected, e.g. REL_WHEEL and REL_WHEEL_HI_RES.
+    scroll_codes: Vec<EventCode>,
+    /// Scrolling is inverted while this toggle has its first index active.
+    state_index: ToggleIndex,
+}
+
+impl InvertScroll {
+    pub fn new(keys: Vec<Key>, scroll_codes: Vec<EventCode>, state_index: ToggleIndex) -> InvertScroll {
+        InvertScroll { keys, scroll_codes, state_index }
+    }
+
+    fn affects(&self, code: EventCode) -> bool {
+        self.scroll_codes.contains(&code)
+    }
+
+    fn apply(&self, mut event: Event, output_events: &mut Vec<Event>, state: &State) {
+        if state[self.state_index].value() == 0
+            && self.affects(event.code)
+            && self.keys.iter().any(|key| key.matches(&event))
+        {
+            event.value = event.value.saturating_neg();
+            event.previous_value = event.previous_value.saturating_neg();
+        }
+        output_events.push(event);
+    }
+
+    /// The apply_ functions are analogous to the Map::apply_ equivalents.
+    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>, state: &State) {
+        for &event in events {
+            self.apply(event, output_events, state);
+        }
+    }
+
+    /// Because inversion can be toggled at runtime, both the original and the negated values
+    /// may leave this entry.
+    fn apply_to_cap(&self, cap: &Capability, output_caps: &mut Vec<Capability>) {
+        if ! self.affects(cap.code) {
+            output_caps.push(cap.clone());
+            return;
+        }
+        let negated_cap = cap.map_values(|set| {
+            let negated = set.map(|interval| Some(Interval::spanned_between(
+                interval.min.saturating_neg(), interval.max.saturating_neg(),
+            )));
+            set.union(&negated)
+        });
+        output_caps.push(negated_cap);
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            self.apply_to_cap(cap, output_caps);
+        }
+    }
+}
@@ -0,0 +1,255 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --macro-slot argument, which records events at runtime when a --hook tells it
+//! to, and replays them later when another --hook tells it to.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::capability::Capability;
+use crate::domain;
+use crate::ecodes;
+use crate::error::{Context, SystemError};
+use crate::event::{Channel, Event, EventValue, Namespace};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::{Interval, Set};
+use crate::time::{Duration, Instant};
+
+/// Something a --hook can tell the --macro-slot argument to do. Slots are zero-indexed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MacroCommand {
+    StartRecording(usize),
+    StopRecording,
+    Play(usize),
+}
+
+/// A recorded macro: a list of events along with how long after the start of the recording they happened.
+type Recording = Vec<(Duration, Event)>;
+
+struct ActiveRecording {
+    slot: usize,
+    start: Instant,
+    events: Recording,
+}
+
+pub struct MacroSlots {
+    /// Only events matching one of these keys are recorded.
+    keys: Vec<Key>,
+    slots: Vec<Recording>,
+    /// If Some, the recorded macros are saved to this file after each recording.
+    persist_path: Option<PathBuf>,
+    /// The capabilities of the events in the macros that were loaded from the persist_path. Unlike the
+    /// macros recorded at runtime, these events may never have passed this point of the stream.
+    loaded_caps: Vec<Capability>,
+
+    /// State: modifiable at runtime.
+    recording: Option<ActiveRecording>,
+    /// Events that need to be put back into the stream when the loopback releases a certain token.
+    scheduled_events: Vec<(Token, Event)>,
+}
+
+impl MacroSlots {
+    pub fn new(keys: Vec<Key>, num_slots: usize, persist_path: Option<PathBuf>) -> Result<MacroSlots, SystemError> {
+        let mut slots: Vec<Recording> = vec![Vec::new(); num_slots];
+        if let Some(path) = &persist_path {
+            load_recordings(path, &mut slots)
+                .with_context_of(|| format!("While loading the macros from {}:", path.display()))?;
+        }
+        let loaded_caps = slots.iter().flatten().map(|(_, event)| Capability {
+            code: event.code,
+            domain: event.domain,
+            namespace: event.namespace,
+            values: Set::from(Interval::new(event.value, event.value)),
+            abs_meta: None,
+        }).collect();
+
+        Ok(MacroSlots {
+            keys, slots, persist_path, loaded_caps,
+            recording: None,
+            scheduled_events: Vec::new(),
+        })
+    }
+
+    /// Records all events if a recording is in progress. The commands that hooks send are carried out by
+    /// `execute()` after the events that triggered them have been processed, so the events that cause a
+    /// hook to start recording are not part of the recording themselves.
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if let Some(recording) = &mut self.recording {
+            let offset = loopback.now().checked_duration_since(recording.start)
+                .unwrap_or_else(|| Duration::from_nanos(0));
+            for &event in events {
+                if self.keys.iter().any(|key| key.matches(&event)) {
+                    recording.events.push((offset, event));
+                }
+            }
+        }
+        output_events.extend_from_slice(events);
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], caps_out: &mut Vec<Capability>) {
+        caps_out.extend(caps.iter().cloned());
+        caps_out.extend(self.loaded_caps.iter().cloned());
+    }
+
+    pub fn execute(&mut self, command: MacroCommand, loopback: &mut LoopbackHandle) {
+        match command {
+            MacroCommand::StartRecording(slot) => {
+                self.stop_recording();
+                self.recording = Some(ActiveRecording {
+                    slot,
+                    start: loopback.now(),
+                    events: Vec::new(),
+                });
+            },
+            MacroCommand::StopRecording => self.stop_recording(),
+            MacroCommand::Play(slot) => {
+                for &(offset, event) in &self.slots[slot] {
+                    let token = loopback.schedule_wakeup_in(offset);
+                    self.scheduled_events.push((token, event));
+                }
+            },
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        let recording = match self.recording.take() {
+            Some(recording) => recording,
+            None => return,
+        };
+        self.slots[recording.slot] = remove_unbalanced_keys(recording.events);
+
+        if let Some(path) = &self.persist_path {
+            save_recordings(path, &self.slots)
+                .with_context_of(|| format!("While saving the macros to {}:", path.display()))
+                .print_err();
+        }
+    }
+
+    /// Puts the replayed events back into the stream when their time has come.
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        if let Some(index) = self.scheduled_events.iter().position(|(event_token, _)| event_token == token) {
+            let (_, event) = self.scheduled_events.remove(index);
+            output_events.push(event);
+        }
+    }
+}
+
+/// Removes the key_up events of keys that were already pressed when the recording started, and the
+/// key_down and repeat events of keys that were still pressed when the recording ended. This makes sure
+/// that the keys of the hooks that start and stop the recording do not end up in the recording.
+fn remove_unbalanced_keys(events: Recording) -> Recording {
+    let mut keep: Vec<bool> = vec![true; events.len()];
+    // Maps channels to the index of the event that pressed them.
+    let mut pressed: HashMap<Channel, usize> = HashMap::new();
+
+    for (index, (_, event)) in events.iter().enumerate() {
+        if ! event.ev_type().is_key() {
+            continue;
+        }
+        if event.value == 0 {
+            if pressed.remove(&event.channel()).is_none() {
+                keep[index] = false;
+            }
+        } else {
+            pressed.entry(event.channel()).or_insert(index);
+        }
+    }
+
+    for (channel, press_index) in pressed {
+        for (index, (_, event)) in events.iter().enumerate().skip(press_index) {
+            if event.channel() == channel {
+                keep[index] = false;
+            }
+        }
+    }
+
+    events.into_iter().zip(keep)
+        .filter(|(_, keep)| *keep)
+        .map(|(event, _)| event)
+        .collect()
+}
+
+/// Saves the recordings in a line-based format where each line looks like
+///     SLOT MICROSECONDS TYPE:CODE PREVIOUS_VALUE VALUE [DOMAIN]
+/// The slots are one-indexed, in line with how the user refers to them.
+fn save_recordings(path: &Path, slots: &[Recording]) -> Result<(), SystemError> {
+    let mut content = String::new();
+    for (slot_index, recording) in slots.iter().enumerate() {
+        for (offset, event) in recording {
+            content.push_str(&format!(
+                "{} {} {} {} {}",
+                slot_index + 1, offset.as_micros(), ecodes::event_name(event.code), event.previous_value, event.value,
+            ));
+            if let Some(domain_name) = domain::try_reverse_resolve(event.domain) {
+                content.push(' ');
+                content.push_str(&domain_name);
+            }
+            content.push('\n');
+        }
+    }
+    std::fs::write(path, content)?;
+    Ok(())
+}
+
+/// Loads the recordings saved by `save_recordings()`. If the file does not exist, nothing is loaded.
+fn load_recordings(path: &Path, slots: &mut [Recording]) -> Result<(), SystemError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(error) => return Err(error.into()),
+    };
+
+    // Events without a named domain get a domain that no filter can match.
+    let anonymous_domain = domain::get_unique_domain();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let malformed = || SystemError::new(format!("Malformed line in macro file: {}", line));
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let (slot_str, micros_str, name, previous_value_str, value_str, domain_str) = match parts.as_slice() {
+            [slot, micros, name, previous, value] => (slot, micros, name, previous, value, None),
+            [slot, micros, name, previous, value, domain] => (slot, micros, name, previous, value, Some(domain)),
+            _ => return Err(malformed()),
+        };
+
+        let slot: usize = slot_str.parse().map_err(|_| malformed())?;
+        let recording = match slot.checked_sub(1).and_then(|index| slots.get_mut(index)) {
+            Some(recording) => recording,
+            // Slots that no longer exist because the amount of slots was decreased are ignored.
+            None => continue,
+        };
+        let micros: u64 = micros_str.parse().map_err(|_| malformed())?;
+        let (type_name, code_name) = crate::utils::split_once(name, ":");
+        let code = ecodes::event_code(type_name, code_name.ok_or_else(malformed)?)
+            .map_err(|_| malformed())?;
+        let previous_value: EventValue = previous_value_str.parse().map_err(|_| malformed())?;
+        let value: EventValue = value_str.parse().map_err(|_| malformed())?;
+        let domain = match domain_str {
+            Some(domain_str) => domain::resolve(domain_str).map_err(|_| malformed())?,
+            None => anonymous_domain,
+        };
+
+        let event = Event::new(code, value, previous_value, domain, Namespace::User);
+        recording.push((Duration::from_micros(micros), event));
+    }
+
+    Ok(())
+}
+
+#[test]
+fn unittest() {
+    let path = std::env::temp_dir().join(format!("evsieve-macro-unittest-{}", std::process::id()));
+    std::fs::write(&path, "1 0 key:b 0 1 kb\n1 1000 key:b 1 0 kb\n").unwrap();
+    let macro_slots = MacroSlots::new(Vec::new(), 1, Some(path.clone()));
+    std::fs::remove_file(&path).unwrap();
+
+    // The outputs must be able to replay the loaded macros, even if their events never came from the inputs.
+    let mut caps = Vec::new();
+    macro_slots.unwrap().apply_to_all_caps(&[], &mut caps);
+    let key_b = ecodes::event_code("key", "b").unwrap();
+    let domain = domain::resolve("kb").unwrap();
+    let values: Vec<Set> = caps.into_iter()
+        .filter(|cap| cap.code == key_b && cap.domain == domain)
+        .map(|cap| cap.values)
+        .collect();
+    assert_eq!(values, vec![Set::from(Interval::new(1, 1)), Set::from(Interval::new(0, 0))]);
+}
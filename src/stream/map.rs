@@ -1,21 +1,68 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::key::Key;
-use crate::event::{Event, Namespace};
+use crate::crossing::CrossingTracker;
+use crate::event::{Channel, Event, Namespace};
 use crate::domain::Domain;
 use crate::capability::{Capability, Certainty};
 use crate::error::InternalError;
 use crate::state::{State, ToggleIndex};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
 
 #[derive(Clone, Debug)]
 pub struct Map {
-    input_key: Key,
+    /// The map applies to events matching any of these sources.
+    sources: Vec<MapSource>,
     output_keys: Vec<Key>,
+    /// If the nth= or chance= clause was specified, decides which matching events get mapped.
+    sampler: Option<Sampler>,
+}
+
+#[derive(Clone, Debug)]
+struct MapSource {
+    key: Key,
+    /// If the key had a crossing like "abs:y:crosses=0:+", then that crossing is tracked
+    /// here instead of being part of the key.
+    crossing: Option<CrossingTracker>,
+}
+
+impl MapSource {
+    fn new(mut key: Key) -> MapSource {
+        let crossing = key.pop_crossing().map(CrossingTracker::new);
+        MapSource { key, crossing }
+    }
+
+    /// Returns whether the map should apply to this event as far as this source is concerned.
+    fn accepts(&mut self, event: &Event) -> bool {
+        if ! self.key.matches(event) {
+            return false;
+        }
+        match &mut self.crossing {
+            Some(crossing) => crossing.update(event),
+            None => true,
+        }
+    }
 }
 
 impl Map {
     pub fn new(input_key: Key, output_keys: Vec<Key>) -> Map {
-        Map { input_key, output_keys }
+        Map::any_of(vec![input_key], output_keys)
+    }
+
+    /// Returns a map that applies to each event matching any of the input keys, like
+    /// "--map key:a key:b -> key:c" does. Each event gets mapped at most once.
+    pub fn any_of(input_keys: Vec<Key>, output_keys: Vec<Key>) -> Map {
+        let sources = input_keys.into_iter().map(MapSource::new).collect();
+        Map { sources, output_keys, sampler: None }
+    }
+
+    /// Makes this map only apply to some of the events matching its input key. The other events
+    /// pass through as if they did not match.
+    pub fn with_sampling(mut self, sampling: Sampling) -> Map {
+        self.sampler = Some(Sampler::new(sampling));
+        self
     }
 
     /// Returns a map that blocks a given input key.
@@ -35,11 +82,22 @@ impl Map {
 
     /// Checks if an event matches this map, and if so, generates corresponding events and
     /// writes those to the output. Otherwise, writes the event itself to the output.
-    fn apply(&self, event: Event, output_events: &mut Vec<Event>) {
-        if ! self.input_key.matches(&event) {
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        // Every source gets to see the event, so their crossing trackers stay up to date.
+        let mut accepted = false;
+        for source in &mut self.sources {
+            accepted |= source.accepts(&event);
+        }
+        if ! accepted {
             output_events.push(event);
             return;
         }
+        if let Some(sampler) = &mut self.sampler {
+            if ! sampler.sample(&event) {
+                output_events.push(event);
+                return;
+            }
+        }
         let generated_events = self.output_keys.iter().map(
             |key| key.merge(event)
         );
@@ -48,7 +106,7 @@ impl Map {
 
     /// Maps all events to output_events. Events that do not match this Map are mapped to themselfe.
     /// Preserves the order of the events.
-    pub fn apply_to_all(&self, events: &[Event], output_events: &mut Vec<Event>) {
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
         for &event in events {
             self.apply(event, output_events);
         }
@@ -56,28 +114,44 @@ impl Map {
 
     /// An analogue for apply() but with capabilities instead of events.
     fn apply_cap(&self, cap: Capability, output_caps: &mut Vec<Capability>) {
-        let (match_certainty, matching_values) = self.input_key.matches_cap(&cap);
+        // The part of the capability that might not have matched any of the sources considered so far.
+        let mut potentially_nonmatching_cap = cap;
+        for source in &self.sources {
+            if potentially_nonmatching_cap.values.is_empty() {
+                return;
+            }
+            let cap = potentially_nonmatching_cap;
+            let (mut match_certainty, matching_values) = source.key.matches_cap(&cap);
+            // Whether an event crosses the threshold depends on the values that came before it.
+            if source.crossing.is_some() {
+                match_certainty = Certainty::Maybe;
+            }
+            // Events that are not sampled pass through unchanged, so both outcomes are possible.
+            if let Some(sampler) = &self.sampler {
+                if ! sampler.always_samples() {
+                    match_certainty = Certainty::Maybe;
+                }
+            }
 
-        // The capability of the event values that might match the filter key.
-        let matchable_cap = cap.clone().with_values(matching_values);
+            // The capability of the event values that might match the filter key.
+            let matchable_cap = cap.clone().with_values(matching_values);
 
-        // Create the capability with all values that might not match the filter key.
-        let potentially_nonmatching_values = cap.values.setminus(&matchable_cap.values);
-        let potentially_nonmatching_cap = match match_certainty {
-            Certainty::Maybe => cap,
-            Certainty::Always => cap.with_values(potentially_nonmatching_values),
-        };
+            // Create the capability with all values that might not match the filter key.
+            let nonmatching_values = cap.values.setminus(&matchable_cap.values);
+            potentially_nonmatching_cap = match match_certainty {
+                Certainty::Maybe => cap,
+                Certainty::Always => cap.with_values(nonmatching_values),
+            };
+
+            // Add the caps we would generate if we matched.
+            output_caps.extend(self.output_keys.iter().map(
+                |key| key.merge_cap(matchable_cap.clone())
+            ).filter(|cap| !cap.values.is_empty()));
+        }
 
-        // An iterator of the caps we would add if we matched.
-        let generated_caps = self.output_keys.iter().map(
-            |key| key.merge_cap(matchable_cap.clone())
-        ).filter(|cap| !cap.values.is_empty());
-        
-        // Add all potential capabilities to the output capabilities vector.
         if !potentially_nonmatching_cap.values.is_empty() {
             output_caps.push(potentially_nonmatching_cap);
         }
-        output_caps.extend(generated_caps);
     }
 
     /// Like apply_to_all(), but for capabilities.
@@ -89,6 +163,96 @@ impl Map {
     }
 }
 
+/// Which of the matching events a map applies to.
+#[derive(Clone, Copy, Debug)]
+pub enum Sampling {
+    /// Applies to every Nth matching event, starting with the Nth one.
+    EveryNth(u32),
+    /// Applies to each matching event with a probability between 0 and 1.
+    Chance(f64),
+}
+
+/// Used to seed the random number generators of different maps differently even if they are
+/// created at the same time.
+static SAMPLER_SEED_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+#[derive(Clone, Debug)]
+struct Sampler {
+    sampling: Sampling,
+    /// How many matching events have been seen since the last one that was mapped.
+    counter: u32,
+    /// The state of a xorshift64* generator. It is not cryptographically secure, but it needs no dependencies.
+    rng_state: u64,
+
+    /// For each key that is currently pressed, whether its press was mapped. The repeat and release
+    /// events of that key get the same treatment, so a key cannot get stuck.
+    decisions: HashMap<Channel, bool>,
+}
+
+impl Sampler {
+    fn new(sampling: Sampling) -> Sampler {
+        let time_seed = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u64)
+            .unwrap_or(0);
+        let seed = time_seed ^ SAMPLER_SEED_COUNTER.fetch_add(1, Ordering::Relaxed).wrapping_mul(0x9E37_79B9_7F4A_7C15);
+        // The state of a xorshift generator must not be zero.
+        Sampler { sampling, counter: 0, rng_state: seed | 1, decisions: HashMap::new() }
+    }
+
+    /// Returns whether the current matching event should be mapped. Only presses of keys count as
+    /// separate events; their repeats and releases follow the decision made for the press.
+    fn sample(&mut self, event: &Event) -> bool {
+        if ! event.ev_type().is_key() {
+            return self.decide();
+        }
+        let channel = event.channel();
+        let recorded = match event.value {
+            1 => None,
+            0 => self.decisions.remove(&channel),
+            _ => self.decisions.get(&channel).copied(),
+        };
+        // Repeats and releases of keys that were pressed before this map saw them are decided on their own.
+        recorded.unwrap_or_else(|| {
+            let decision = self.decide();
+            if event.value == 1 {
+                self.decisions.insert(channel, decision);
+            }
+            decision
+        })
+    }
+
+    fn decide(&mut self) -> bool {
+        match self.sampling {
+            Sampling::EveryNth(n) => {
+                self.counter += 1;
+                if self.counter >= n {
+                    self.counter = 0;
+                    true
+                } else {
+                    false
+                }
+            },
+            Sampling::Chance(probability) => self.next_f64() < probability,
+        }
+    }
+
+    fn always_samples(&self) -> bool {
+        match self.sampling {
+            Sampling::EveryNth(n) => n <= 1,
+            Sampling::Chance(probability) => probability >= 1.0,
+        }
+    }
+
+    /// Returns a number in the range [0, 1).
+    fn next_f64(&mut self) -> f64 {
+        self.rng_state ^= self.rng_state >> 12;
+        self.rng_state ^= self.rng_state << 25;
+        self.rng_state ^= self.rng_state >> 27;
+        let random = self.rng_state.wrapping_mul(0x2545_F491_4F6C_DD1D);
+        (random >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ToggleMode {
     Passive,
@@ -185,4 +349,11 @@ impl Toggle {
         let self_as_map = Map::new(self.input_key.clone(), self.output_keys.clone());
         self_as_map.apply_to_all_caps(caps, output_caps);
     }
+
+    /// Like apply_to_all_caps(), but only considers the output that is active when the toggle
+    /// has the given value.
+    pub fn apply_to_all_caps_at(&self, caps: &[Capability], output_caps: &mut Vec<Capability>, value: usize) {
+        let self_as_map = Map::new(self.input_key.clone(), vec![self.output_keys[value].clone()]);
+        self_as_map.apply_to_all_caps(caps, output_caps);
+    }
 }
@@ -0,0 +1,146 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --max-hold argument, a safety net that releases keys which have been held down for
+//! longer than a certain period. If a mistake in a script causes a key to never get released, this
+//! keeps the output device from being stuck with that key pressed.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::capability::Capability;
+use crate::domain;
+use crate::ecodes;
+use crate::event::{Channel, Event};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::time::Duration;
+
+pub struct MaxHold {
+    keys: Vec<Key>,
+    /// Keys that may be held for as long as the user wants, even if they match `keys`.
+    except_keys: Vec<Key>,
+    period: Duration,
+
+    /// State: modifiable at runtime.
+    /// The keys that are currently held, the token that wakes us when they have been held too long,
+    /// and the event that pressed them.
+    held: HashMap<Channel, (Token, Event)>,
+    /// Keys that have been released by us, so their repeat and release events must be dropped.
+    released: HashSet<Channel>,
+}
+
+impl MaxHold {
+    pub fn new(keys: Vec<Key>, except_keys: Vec<Key>, period: Duration) -> MaxHold {
+        MaxHold {
+            keys, except_keys, period,
+            held: HashMap::new(),
+            released: HashSet::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key()
+            || ! self.keys.iter().any(|key| key.matches(&event))
+            || self.except_keys.iter().any(|key| key.matches(&event))
+        {
+            return output_events.push(event);
+        }
+        let channel = event.channel();
+        match event.value {
+            1 => {
+                self.released.remove(&channel);
+                if ! self.held.contains_key(&channel) {
+                    let token = loopback.schedule_wakeup_in(self.period);
+                    self.held.insert(channel, (token, event));
+                }
+                output_events.push(event);
+            },
+            0 => {
+                if let Some((token, _)) = self.held.remove(&channel) {
+                    loopback.cancel_token(token);
+                }
+                if ! self.released.remove(&channel) {
+                    output_events.push(event);
+                }
+            },
+            _ => {
+                if ! self.released.contains(&channel) {
+                    output_events.push(event);
+                }
+            },
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        let channel = match self.held.iter().find(|(_, (held_token, _))| held_token == token) {
+            Some((&channel, _)) => channel,
+            None => return,
+        };
+        let (_, event) = match self.held.remove(&channel) {
+            Some(held) => held,
+            None => return,
+        };
+        self.released.insert(channel);
+
+        let domain_description = match domain::try_reverse_resolve(event.domain) {
+            Some(name) => format!("domain {}", name),
+            None => "an unnamed domain".to_owned(),
+        };
+        eprintln!(
+            "Warning: {} from {} has been held for longer than {} seconds. Releasing it.",
+            ecodes::event_name(event.code), domain_description, self.period.as_secs_f64(),
+        );
+
+        let mut release_event = event;
+        release_event.value = 0;
+        release_event.previous_value = 1;
+        output_events.push(release_event);
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+    use crate::loopback::Loopback;
+    use crate::time::Instant;
+
+    let domain = domain::get_unique_domain();
+    let event = |code, value| Event::new(EventCode::new(EventType::KEY, code), value, 0, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    let mut max_hold = MaxHold::new(
+        vec![KeyParser::default_filter().parse("").unwrap()],
+        vec![KeyParser::default_filter().parse("key:f13").unwrap()],
+        Duration::from_millis(1000),
+    );
+    let mut loopback = Loopback::new();
+    let mut output = Vec::new();
+
+    // key:a is released after 1000 milliseconds, and its real repeats and release get dropped.
+    max_hold.apply(event(30, 1), &mut output, &mut loopback.get_handle(at(0)));
+    max_hold.apply(event(183, 1), &mut output, &mut loopback.get_handle(at(0)));
+    let (_, token) = loopback.poll_once(at(1000)).unwrap();
+    max_hold.wakeup(&token, &mut output);
+    max_hold.apply(event(30, 2), &mut output, &mut loopback.get_handle(at(1100)));
+    max_hold.apply(event(30, 0), &mut output, &mut loopback.get_handle(at(1200)));
+    // Releasing a key in time cancels the timeout.
+    max_hold.apply(event(31, 1), &mut output, &mut loopback.get_handle(at(2000)));
+    max_hold.apply(event(31, 0), &mut output, &mut loopback.get_handle(at(2500)));
+    assert!(loopback.poll_once(at(10_000)).is_none());
+
+    assert_eq!(
+        output.iter().map(|event| (event.code.code(), event.value)).collect::<Vec<_>>(),
+        vec![(30, 1), (183, 1), (30, 0), (31, 1), (31, 0)],
+    );
+}
@@ -2,6 +2,7 @@
 
 use std::collections::HashMap;
 
+use crate::domain::{self, Domain};
 use crate::key::Key;
 use crate::event::{Event, Channel};
 
@@ -9,14 +10,37 @@ use crate::event::{Event, Channel};
 pub struct Merge {
     /// The keys that are subject to getting merged by this argument.
     keys: Vec<Key>,
+
+    /// If Some, events with the same code are merged even if they have different domains, as
+    /// requested by the priority= clauses.
+    priorities: Option<MergePriorities>,
     
     /// How many down events each (type, code, domain) pair has.
     state: HashMap<Channel, usize>,
 }
 
+/// Specified by the priority= clauses and the steal flag of a --merge argument.
+pub struct MergePriorities {
+    /// The priority of the events with each domain. The first matching domain wins. Events that
+    /// match none of these domains have priority zero.
+    pub domains: Vec<(Domain, i32)>,
+    /// If true, a press by a domain with higher priority takes over the keys held down by domains
+    /// with lower priority, and presses by domains with lower priority are suppressed.
+    pub steal: bool,
+}
+
+impl MergePriorities {
+    fn priority_of(&self, domain: Domain) -> i32 {
+        self.domains.iter()
+            .find(|&&(filter, _)| domain::matches(domain, filter))
+            .map(|&(_, priority)| priority)
+            .unwrap_or(0)
+    }
+}
+
 impl Merge {
-    pub fn new(keys: Vec<Key>) -> Merge {
-        Merge { keys, state: HashMap::new() }
+    pub fn new(keys: Vec<Key>, priorities: Option<MergePriorities>) -> Merge {
+        Merge { keys, priorities, state: HashMap::new() }
     }
 
     #[allow(clippy::needless_return)]
@@ -26,6 +50,9 @@ impl Merge {
             output_events.push(event);
             return;
         }
+        if self.priorities.is_some() {
+            return self.apply_with_priorities(event, output_events);
+        }
 
         let current_down_count: &mut usize = self.state.entry(event.channel()).or_insert(0);
         let last_down_count: usize = *current_down_count;
@@ -51,9 +78,66 @@ impl Merge {
         }
     }
 
+    /// Like `apply()`, but merges all events with the same code regardless of their domain.
+    /// The down counts are still kept track of per domain, so the domains can steal keys from
+    /// each other.
+    fn apply_with_priorities(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        let priorities = match &self.priorities {
+            Some(priorities) => priorities,
+            None => return,
+        };
+        let holders: Vec<(Domain, usize)> = self.state.iter()
+            .filter(|((code, _), &count)| *code == event.code && count > 0)
+            .map(|(&(_, domain), &count)| (domain, count))
+            .collect();
+        let was_down = ! holders.is_empty();
+        let own_count = holders.iter()
+            .find(|&&(domain, _)| domain == event.domain)
+            .map(|&(_, count)| count)
+            .unwrap_or(0);
+
+        match event.value {
+            1 => {
+                if priorities.steal {
+                    let priority = priorities.priority_of(event.domain);
+                    if holders.iter().any(|&(domain, _)| priorities.priority_of(domain) > priority) {
+                        // A domain with higher priority holds this key, so this press gets suppressed.
+                        // Since it is not counted, its release will be dropped as well.
+                        return;
+                    }
+                    for &(domain, _) in &holders {
+                        if priorities.priority_of(domain) < priority {
+                            self.state.remove(&(event.code, domain));
+                        }
+                    }
+                }
+                self.state.insert(event.channel(), own_count + 1);
+                if ! was_down {
+                    output_events.push(event);
+                }
+            },
+            0 => {
+                if own_count == 0 {
+                    return;
+                }
+                self.state.insert(event.channel(), own_count - 1);
+                if own_count == 1 && holders.len() == 1 {
+                    output_events.push(event);
+                }
+            },
+            // Repeat events are only passed on for domains that actually hold the key.
+            _ => {
+                if own_count > 0 {
+                    output_events.push(event);
+                }
+            },
+        }
+    }
+
     pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
         for &event in events {
             self.apply(event, output_events);
         }
     }
 }
+
@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::event::Namespace;
+
+/// Gives an output device all capabilities of some input devices, as requested by the mirror-of=
+/// clause on --output. Must be the first entry of the stream, because it acts on the capabilities
+/// of the events before they enter the stream.
+pub struct CapabilityMirror {
+    /// The domains of the events of the mirrored input devices before they enter the stream.
+    sources: Vec<Domain>,
+    /// The domain of the output device that mirrors them.
+    device: Domain,
+}
+
+impl CapabilityMirror {
+    pub fn new(sources: Vec<Domain>, device: Domain) -> CapabilityMirror {
+        CapabilityMirror { sources, device }
+    }
+
+    // CapabilityMirror does not alter the events themselves, so there is no `apply()` or `apply_to_all()` here.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], caps_out: &mut Vec<Capability>) {
+        caps_out.extend(caps.iter().cloned());
+        for cap in caps {
+            if cap.namespace != Namespace::Input || cap.code.ev_type().is_internal()
+                    || ! self.sources.contains(&cap.domain) {
+                continue;
+            }
+            caps_out.push(Capability {
+                domain: self.device,
+                namespace: Namespace::Output,
+                ..cap.clone()
+            });
+        }
+    }
+}
@@ -0,0 +1,387 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --mouse-keys argument, which lets the numeric keypad control the mouse pointer:
+//!
+//! * the numbers around 5 move the pointer in their direction, faster the longer they are held;
+//! * 5 clicks the selected button;
+//! * + and - select the next and previous button out of the left, middle and right buttons;
+//! * 0 presses the selected button and keeps it held for dragging until 0, 5 or . is pressed.
+//!
+//! The motion is generated by waking up at a fixed interval while a movement key is held.
+
+use std::collections::HashSet;
+
+use crate::capability::{Capability, Certainty};
+use crate::domain::Domain;
+use crate::ecodes;
+use crate::error::ArgumentError;
+use crate::event::{Event, EventCode, Namespace};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::{Interval, Set};
+use crate::time::{Duration, Instant};
+
+/// The keys that move the pointer and the direction they move it in.
+const MOVEMENT_KEYS: &[(&str, i32, i32)] = &[
+    ("kp7", -1, -1), ("kp8", 0, -1), ("kp9", 1, -1),
+    ("kp4", -1,  0),                 ("kp6", 1,  0),
+    ("kp1", -1,  1), ("kp2", 0,  1), ("kp3", 1,  1),
+];
+/// The buttons that can be selected with the + and - keys, in order.
+const BUTTONS: &[&str] = &["left", "middle", "right"];
+
+pub struct Speed {
+    /// The speed at which the pointer starts moving, in units per second.
+    pub initial: f64,
+    /// The speed that the pointer accelerates to, in units per second.
+    pub max: f64,
+    /// How long it takes to accelerate from the initial to the maximum speed.
+    pub acceleration_time: Duration,
+    /// How often relative events are generated while the pointer is moving.
+    pub interval: Duration,
+}
+
+/// The event codes that mouse keys uses, resolved once when it is created.
+struct Codes {
+    movement: Vec<(EventCode, i32, i32)>,
+    click: EventCode,
+    next_button: EventCode,
+    previous_button: EventCode,
+    hold: EventCode,
+    release: EventCode,
+    buttons: Vec<EventCode>,
+    rel_x: EventCode,
+    rel_y: EventCode,
+}
+
+impl Codes {
+    fn new() -> Result<Codes, ArgumentError> {
+        Ok(Codes {
+            movement: MOVEMENT_KEYS.iter()
+                .map(|&(name, dx, dy)| Ok((ecodes::event_code("key", name)?, dx, dy)))
+                .collect::<Result<Vec<_>, ArgumentError>>()?,
+            click: ecodes::event_code("key", "kp5")?,
+            next_button: ecodes::event_code("key", "kpplus")?,
+            previous_button: ecodes::event_code("key", "kpminus")?,
+            hold: ecodes::event_code("key", "kp0")?,
+            release: ecodes::event_code("key", "kpdot")?,
+            buttons: BUTTONS.iter()
+                .map(|name| ecodes::event_code("btn", name))
+                .collect::<Result<Vec<_>, ArgumentError>>()?,
+            rel_x: ecodes::event_code("rel", "x")?,
+            rel_y: ecodes::event_code("rel", "y")?,
+        })
+    }
+
+    /// Returns whether an event with this code is consumed by mouse keys while it is enabled.
+    fn is_handled(&self, code: EventCode) -> bool {
+        self.movement.iter().any(|&(other, _, _)| other == code)
+            || [self.click, self.next_button, self.previous_button, self.hold, self.release].contains(&code)
+    }
+}
+
+pub struct MouseKeys {
+    /// Only events matching one of these keys are interpreted by mouse keys.
+    keys: Vec<Key>,
+    /// Pressing all of these keys simultaneously enables or disables mouse keys. If empty, mouse keys
+    /// is always enabled.
+    toggle_keys: Vec<Key>,
+    speed: Speed,
+    codes: Codes,
+
+    /// State: modifiable at runtime.
+    enabled: bool,
+    /// Which of the toggle keys are currently held down.
+    toggle_keys_held: Vec<bool>,
+    /// The movement keys that are currently held down.
+    movement_keys_held: HashSet<EventCode>,
+    /// Keys that were pressed while mouse keys was disabled. They keep passing through until they are
+    /// released, so they do not get stuck if mouse keys gets enabled while they are held.
+    passed_through_keys: HashSet<EventCode>,
+    /// The index in `codes.buttons` of the button that gets clicked.
+    selected_button: usize,
+    /// The button that is held down for dragging, if any.
+    held_button: Option<EventCode>,
+    /// The domain of the last key that was handled. The generated events get this domain.
+    domain: Option<Domain>,
+    /// When the pointer started moving and the wakeup that will move it further.
+    motion: Option<(Instant, Token)>,
+    /// The fractional movement that has not been written to the output yet.
+    residual: (f64, f64),
+}
+
+impl MouseKeys {
+    pub fn new(keys: Vec<Key>, toggle_keys: Vec<Key>, speed: Speed) -> Result<MouseKeys, ArgumentError> {
+        Ok(MouseKeys {
+            keys,
+            toggle_keys_held: vec![false; toggle_keys.len()],
+            toggle_keys,
+            speed,
+            codes: Codes::new()?,
+            enabled: true,
+            movement_keys_held: HashSet::new(),
+            passed_through_keys: HashSet::new(),
+            selected_button: 0,
+            held_button: None,
+            domain: None,
+            motion: None,
+            residual: (0.0, 0.0),
+        })
+    }
+
+    fn new_event(&self, code: EventCode, value: i32, previous_value: i32, domain: Domain) -> Event {
+        Event::new(code, value, previous_value, domain, Namespace::User)
+    }
+
+    fn update_toggle(&mut self, event: &Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let mut pressed_toggle_key = false;
+        for (key, held) in self.toggle_keys.iter().zip(self.toggle_keys_held.iter_mut()) {
+            if key.matches(event) && event.value != 2 {
+                *held = event.value != 0;
+                pressed_toggle_key |= event.value != 0;
+            }
+        }
+        if pressed_toggle_key && self.toggle_keys_held.iter().all(|&held| held) {
+            self.enabled = ! self.enabled;
+            if ! self.enabled {
+                self.reset(output_events, loopback);
+            }
+        }
+    }
+
+    /// Stops all motion and releases the held button, if any.
+    fn reset(&mut self, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        self.movement_keys_held.clear();
+        self.stop_motion(loopback);
+        self.release_held_button(output_events);
+    }
+
+    fn stop_motion(&mut self, loopback: &mut LoopbackHandle) {
+        if let Some((_, token)) = self.motion.take() {
+            loopback.cancel_token(token);
+        }
+        self.residual = (0.0, 0.0);
+    }
+
+    fn release_held_button(&mut self, output_events: &mut Vec<Event>) {
+        if let (Some(button), Some(domain)) = (self.held_button.take(), self.domain) {
+            output_events.push(self.new_event(button, 0, 1, domain));
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        self.update_toggle(&event, output_events, loopback);
+        let is_relevant = event.ev_type().is_key()
+            && self.codes.is_handled(event.code)
+            && self.keys.iter().any(|key| key.matches(&event));
+        if ! is_relevant {
+            return output_events.push(event);
+        }
+        if ! self.enabled || (event.value != 1 && self.passed_through_keys.contains(&event.code)) {
+            match event.value {
+                0 => { self.passed_through_keys.remove(&event.code); },
+                _ => { self.passed_through_keys.insert(event.code); },
+            }
+            return output_events.push(event);
+        }
+        self.passed_through_keys.remove(&event.code);
+        self.domain = Some(event.domain);
+
+        if let Some(&(code, _, _)) = self.codes.movement.iter().find(|&&(code, _, _)| code == event.code) {
+            match event.value {
+                0 => { self.movement_keys_held.remove(&code); },
+                1 => { self.movement_keys_held.insert(code); },
+                _ => (),
+            }
+            if self.movement_keys_held.is_empty() {
+                self.stop_motion(loopback);
+            } else if self.motion.is_none() {
+                let start = loopback.now();
+                // Move the pointer right away, so a short tap moves it by at least a bit.
+                self.residual = (0.0, 0.0);
+                self.generate_motion(Duration::from_nanos(0), output_events);
+                self.motion = Some((start, loopback.schedule_wakeup_in(self.speed.interval)));
+            }
+            return;
+        }
+
+        // All other keys act on press only.
+        if event.value != 1 {
+            return;
+        }
+        let selected_button = self.codes.buttons[self.selected_button];
+        if event.code == self.codes.click {
+            if self.held_button.is_some() {
+                self.release_held_button(output_events);
+            } else {
+                output_events.push(self.new_event(selected_button, 1, 0, event.domain));
+                output_events.push(self.new_event(selected_button, 0, 1, event.domain));
+            }
+        } else if event.code == self.codes.next_button {
+            self.selected_button = (self.selected_button + 1) % self.codes.buttons.len();
+        } else if event.code == self.codes.previous_button {
+            self.selected_button = (self.selected_button + self.codes.buttons.len() - 1) % self.codes.buttons.len();
+        } else if event.code == self.codes.hold {
+            if self.held_button.is_some() {
+                self.release_held_button(output_events);
+            } else {
+                self.held_button = Some(selected_button);
+                output_events.push(self.new_event(selected_button, 1, 0, event.domain));
+            }
+        } else if event.code == self.codes.release {
+            self.release_held_button(output_events);
+        }
+    }
+
+    /// Returns the speed of the pointer after it has been moving for `elapsed` time.
+    fn current_speed(&self, elapsed: Duration) -> f64 {
+        let acceleration_time = self.speed.acceleration_time.as_secs_f64();
+        if acceleration_time <= 0.0 {
+            return self.speed.max;
+        }
+        let progress = (elapsed.as_secs_f64() / acceleration_time).min(1.0);
+        self.speed.initial + (self.speed.max - self.speed.initial) * progress
+    }
+
+    /// Writes the relative events for moving the pointer during a single interval.
+    fn generate_motion(&mut self, elapsed: Duration, output_events: &mut Vec<Event>) {
+        let domain = match self.domain {
+            Some(domain) => domain,
+            None => return,
+        };
+        let (mut dx, mut dy) = (0, 0);
+        for &(code, x, y) in &self.codes.movement {
+            if self.movement_keys_held.contains(&code) {
+                dx += x;
+                dy += y;
+            }
+        }
+        let (dx, dy) = (dx.signum() as f64, dy.signum() as f64);
+        // Diagonal movement should not be faster than straight movement.
+        let norm = (dx * dx + dy * dy).sqrt();
+        if norm == 0.0 {
+            return;
+        }
+
+        let distance = self.current_speed(elapsed) * self.speed.interval.as_secs_f64();
+        self.residual.0 += dx / norm * distance;
+        self.residual.1 += dy / norm * distance;
+        let (whole_x, whole_y) = (self.residual.0.trunc(), self.residual.1.trunc());
+        self.residual.0 -= whole_x;
+        self.residual.1 -= whole_y;
+
+        if whole_x != 0.0 {
+            output_events.push(self.new_event(self.codes.rel_x, whole_x as i32, 0, domain));
+        }
+        if whole_y != 0.0 {
+            output_events.push(self.new_event(self.codes.rel_y, whole_y as i32, 0, domain));
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        let start = match &self.motion {
+            Some((start, motion_token)) if motion_token == token => *start,
+            _ => return,
+        };
+        let now = loopback.now();
+        let elapsed = now.checked_duration_since(start).unwrap_or(Duration::from_nanos(0));
+        self.generate_motion(elapsed, output_events);
+        self.motion = Some((start, loopback.schedule_wakeup_in(self.speed.interval)));
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        // Mouse keys may be disabled by the toggle keys, so the keypad keys may still pass through.
+        output_caps.extend(caps.iter().cloned());
+
+        let mut domains: Vec<Domain> = Vec::new();
+        for cap in caps {
+            if ! self.codes.is_handled(cap.code) || domains.contains(&cap.domain) {
+                continue;
+            }
+            let matches = self.keys.iter().any(|key| {
+                let (certainty, values) = key.matches_cap(cap);
+                certainty == Certainty::Maybe || ! values.is_empty()
+            });
+            if matches {
+                domains.push(cap.domain);
+            }
+        }
+
+        for domain in domains {
+            for &code in &[self.codes.rel_x, self.codes.rel_y] {
+                output_caps.push(Capability {
+                    code, domain,
+                    namespace: Namespace::User,
+                    values: Set::from(Interval::new(None, None)),
+                    abs_meta: None,
+                });
+            }
+            for &code in &self.codes.buttons {
+                output_caps.push(Capability {
+                    code, domain,
+                    namespace: Namespace::User,
+                    values: Set::from(Interval::new(0, 1)),
+                    abs_meta: None,
+                });
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::key::KeyParser;
+
+    let domain = crate::domain::get_unique_domain();
+    let key_event = |name, value| Event::new(ecodes::event_code("key", name).unwrap(), value, 1 - value, domain, Namespace::User);
+    let speed = Speed {
+        initial: 100.0, max: 1000.0,
+        acceleration_time: Duration::from_millis(1000),
+        interval: Duration::from_millis(10),
+    };
+    let mut mouse_keys = MouseKeys::new(
+        vec![KeyParser::default_filter().parse("").unwrap()],
+        vec![KeyParser::default_filter().parse("key:numlock").unwrap()],
+        speed,
+    ).unwrap();
+
+    let start = Instant::now();
+    let mut loopback = crate::loopback::Loopback::new();
+    let mut output = Vec::new();
+
+    // Moving right generates rel:x events.
+    mouse_keys.apply(key_event("kp6", 1), &mut output, &mut loopback.get_handle(start));
+    let (wakeup_time, token) = loopback.poll_once(start + Duration::from_millis(10)).unwrap();
+    mouse_keys.wakeup(&token, &mut output, &mut loopback.get_handle(wakeup_time));
+    assert!(output.iter().all(|event| event.code == ecodes::event_code("rel", "x").unwrap() && event.value > 0));
+    assert_eq!(output.iter().map(|event| event.value).sum::<i32>(), 2);
+    mouse_keys.apply(key_event("kp6", 0), &mut output, &mut loopback.get_handle(wakeup_time));
+    assert!(loopback.poll_once(start + Duration::from_secs(10)).is_none());
+
+    // Selecting the right button and clicking it.
+    output.clear();
+    mouse_keys.apply(key_event("kpminus", 1), &mut output, &mut loopback.get_handle(start));
+    mouse_keys.apply(key_event("kp5", 1), &mut output, &mut loopback.get_handle(start));
+    let btn_right = ecodes::event_code("btn", "right").unwrap();
+    assert_eq!(output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>(), vec![(btn_right, 1), (btn_right, 0)]);
+
+    // The held button is released when mouse keys gets disabled, and the keypad passes through afterwards.
+    output.clear();
+    mouse_keys.apply(key_event("kp0", 1), &mut output, &mut loopback.get_handle(start));
+    mouse_keys.apply(key_event("numlock", 1), &mut output, &mut loopback.get_handle(start));
+    mouse_keys.apply(key_event("kp5", 1), &mut output, &mut loopback.get_handle(start));
+    assert_eq!(
+        output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>(),
+        vec![
+            (btn_right, 1), (btn_right, 0),
+            (ecodes::event_code("key", "numlock").unwrap(), 1),
+            (ecodes::event_code("key", "kp5").unwrap(), 1),
+        ]
+    );
+}
@@ -0,0 +1,361 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --plugin argument, which hands events to a shared object through the interface
+//! declared in include/evsieve-plugin.h. Loading plugins is only possible if evsieve was built with
+//! the "plugins" feature.
+//!
+//! Only events in the user namespace are handed to the plugin. All other events pass it unchanged.
+
+use std::os::raw::c_void;
+
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::error::RuntimeError;
+use crate::event::{Event, Namespace};
+use crate::range::{Interval, Set};
+
+/// Must be equal to EVSIEVE_PLUGIN_ABI_VERSION of include/evsieve-plugin.h.
+#[cfg(feature = "plugins")]
+const ABI_VERSION: u32 = 1;
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginEvent {
+    ev_type: u16,
+    code: u16,
+    value: i32,
+    previous_value: i32,
+    domain: u64,
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct PluginCapability {
+    ev_type: u16,
+    code: u16,
+    min: i32,
+    max: i32,
+    domain: u64,
+}
+
+type EmitEventFn = unsafe extern "C" fn(context: *mut c_void, event: *const PluginEvent);
+type EmitCapabilityFn = unsafe extern "C" fn(context: *mut c_void, capability: *const PluginCapability);
+
+/// Mirrors struct evsieve_plugin of include/evsieve-plugin.h.
+#[repr(C)]
+pub struct PluginInterface {
+    abi_version: u32,
+    init: Option<unsafe extern "C" fn(config: *const std::os::raw::c_char) -> *mut c_void>,
+    process_events: Option<unsafe extern "C" fn(
+        state: *mut c_void, events: *const PluginEvent, count: usize, emit: EmitEventFn, context: *mut c_void
+    )>,
+    process_caps: Option<unsafe extern "C" fn(
+        state: *mut c_void, caps: *const PluginCapability, count: usize, emit: EmitCapabilityFn, context: *mut c_void
+    )>,
+    destroy: Option<unsafe extern "C" fn(state: *mut c_void)>,
+}
+
+pub struct Plugin {
+    library: Library,
+}
+
+impl Plugin {
+    /// Loads the shared object at `path` and initializes it with `config`.
+    pub fn load(path: &str, config: &str) -> Result<Plugin, RuntimeError> {
+        Ok(Plugin { library: Library::load(path, config)? })
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        let mut plugin_events: Vec<PluginEvent> = Vec::new();
+        for &event in events {
+            if event.namespace == Namespace::User {
+                plugin_events.push(PluginEvent {
+                    ev_type: event.code.ev_type().into(),
+                    code: event.code.code(),
+                    value: event.value,
+                    previous_value: event.previous_value,
+                    domain: event.domain.to_raw(),
+                });
+            } else {
+                // Keeps events of other namespaces in their original position relative to the user events.
+                self.process_events(&mut plugin_events, output_events);
+                output_events.push(event);
+            }
+        }
+        self.process_events(&mut plugin_events, output_events);
+    }
+
+    fn process_events(&mut self, plugin_events: &mut Vec<PluginEvent>, output_events: &mut Vec<Event>) {
+        if plugin_events.is_empty() {
+            return;
+        }
+        for plugin_event in self.library.process_events(plugin_events) {
+            match crate::ecodes::checked_event_code(plugin_event.ev_type, plugin_event.code) {
+                Ok(code) if ! code.ev_type().is_syn() => output_events.push(Event::new(
+                    code, plugin_event.value, plugin_event.previous_value,
+                    Domain::from_raw(plugin_event.domain), Namespace::User,
+                )),
+                _ => crate::utils::warn_once(format!(
+                    "Warning: a plugin emitted an event with invalid type {} and code {}. Such events are dropped.",
+                    plugin_event.ev_type, plugin_event.code,
+                )),
+            }
+        }
+        plugin_events.clear();
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        let (user_caps, other_caps): (Vec<&Capability>, Vec<&Capability>) = caps.iter()
+            .partition(|cap| cap.namespace == Namespace::User);
+        output_caps.extend(other_caps.into_iter().cloned());
+
+        let plugin_caps: Vec<PluginCapability> = user_caps.iter()
+            .filter_map(|cap| {
+                let values = cap.values.spanning_interval()?;
+                Some(PluginCapability {
+                    ev_type: cap.code.ev_type().into(),
+                    code: cap.code.code(),
+                    min: values.min,
+                    max: values.max,
+                    domain: cap.domain.to_raw(),
+                })
+            })
+            .collect();
+        let plugin_output_caps = match self.library.process_caps(&plugin_caps) {
+            Some(plugin_output_caps) => plugin_output_caps,
+            // The plugin did not tell which capabilities it has, so assume that it does not change them.
+            None => return output_caps.extend(user_caps.into_iter().cloned()),
+        };
+
+        for plugin_cap in plugin_output_caps {
+            let code = match crate::ecodes::checked_event_code(plugin_cap.ev_type, plugin_cap.code) {
+                Ok(code) if ! code.ev_type().is_syn() && plugin_cap.min <= plugin_cap.max => code,
+                _ => continue,
+            };
+            let domain = Domain::from_raw(plugin_cap.domain);
+            // If the plugin passes on a capability it received, keep the absolute axis information of it.
+            let abs_meta = user_caps.iter()
+                .find(|cap| cap.code == code && cap.domain == domain)
+                .and_then(|cap| cap.abs_meta);
+            output_caps.push(Capability {
+                code, domain, abs_meta,
+                namespace: Namespace::User,
+                values: Set::from(Interval::new(plugin_cap.min, plugin_cap.max)),
+            });
+        }
+    }
+}
+
+#[cfg(feature = "plugins")]
+use self::dl::Library;
+
+#[cfg(feature = "plugins")]
+mod dl {
+    use std::ffi::{CStr, CString};
+    use std::os::raw::c_void;
+
+    use super::{ABI_VERSION, PluginCapability, PluginEvent, PluginInterface};
+    use crate::error::{ArgumentError, RuntimeError, SystemError};
+
+    /// A plugin that has been loaded and initialized.
+    pub struct Library {
+        /// The handle returned by dlopen(), or null if the interface was not loaded from a shared object.
+        handle: *mut c_void,
+        interface: *const PluginInterface,
+        /// The value returned by the init function of the plugin.
+        state: *mut c_void,
+    }
+
+    impl Library {
+        pub fn load(path: &str, config: &str) -> Result<Library, RuntimeError> {
+            let path_cstr = CString::new(path)
+                .map_err(|_| ArgumentError::new("The path of a plugin cannot contain null bytes."))?;
+            let handle = unsafe { libc::dlopen(path_cstr.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL) };
+            if handle.is_null() {
+                return Err(SystemError::new(format!("Failed to load the plugin {}: {}", path, last_dl_error())).into());
+            }
+
+            let entry = unsafe { libc::dlsym(handle, b"evsieve_plugin_entry\0".as_ptr() as *const _) };
+            if entry.is_null() {
+                unsafe { libc::dlclose(handle) };
+                return Err(SystemError::new(format!(
+                    "The shared object {} is not an evsieve plugin: it does not export the function evsieve_plugin_entry().", path
+                )).into());
+            }
+            let entry: unsafe extern "C" fn() -> *const PluginInterface = unsafe { std::mem::transmute(entry) };
+            let interface = unsafe { entry() };
+
+            match unsafe { Library::from_interface(handle, interface, config) } {
+                Ok(library) => Ok(library),
+                Err(error) => {
+                    unsafe { libc::dlclose(handle) };
+                    Err(error)
+                },
+            }
+        }
+
+        /// # Safety
+        /// `interface` must be null or point to a valid interface that outlives the returned Library.
+        /// If `handle` is not null, it is closed when the returned Library is dropped.
+        pub(super) unsafe fn from_interface(handle: *mut c_void, interface: *const PluginInterface, config: &str)
+            -> Result<Library, RuntimeError>
+        {
+            let interface_ref = match interface.as_ref() {
+                Some(interface_ref) => interface_ref,
+                None => return Err(SystemError::new("The plugin did not provide an interface.").into()),
+            };
+            if interface_ref.abi_version != ABI_VERSION {
+                return Err(SystemError::new(format!(
+                    "The plugin was made for version {} of the plugin interface, but this version of evsieve uses version {}.",
+                    interface_ref.abi_version, ABI_VERSION
+                )).into());
+            }
+            if interface_ref.process_events.is_none() {
+                return Err(SystemError::new("The plugin does not provide a process_events function.").into());
+            }
+
+            let config_cstr = CString::new(config)
+                .map_err(|_| ArgumentError::new("The config= clause of a --plugin cannot contain null bytes."))?;
+            let state = match interface_ref.init {
+                Some(init) => {
+                    let state = init(config_cstr.as_ptr());
+                    if state.is_null() {
+                        return Err(SystemError::new("The plugin failed to initialize.").into());
+                    }
+                    state
+                },
+                None => std::ptr::null_mut(),
+            };
+
+            Ok(Library { handle, interface, state })
+        }
+
+        fn interface(&self) -> &PluginInterface {
+            unsafe { &*self.interface }
+        }
+
+        pub fn process_events(&mut self, events: &[PluginEvent]) -> Vec<PluginEvent> {
+            let mut output: Vec<PluginEvent> = Vec::new();
+            if let Some(process_events) = self.interface().process_events {
+                unsafe {
+                    process_events(self.state, events.as_ptr(), events.len(), emit_event, &mut output as *mut _ as *mut c_void);
+                }
+            }
+            output
+        }
+
+        /// Returns None if the plugin does not provide a process_caps function.
+        pub fn process_caps(&self, caps: &[PluginCapability]) -> Option<Vec<PluginCapability>> {
+            let process_caps = self.interface().process_caps?;
+            let mut output: Vec<PluginCapability> = Vec::new();
+            unsafe {
+                process_caps(self.state, caps.as_ptr(), caps.len(), emit_capability, &mut output as *mut _ as *mut c_void);
+            }
+            Some(output)
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            unsafe {
+                if let Some(destroy) = self.interface().destroy {
+                    destroy(self.state);
+                }
+                if ! self.handle.is_null() {
+                    libc::dlclose(self.handle);
+                }
+            }
+        }
+    }
+
+    unsafe extern "C" fn emit_event(context: *mut c_void, event: *const PluginEvent) {
+        if let (Some(output), Some(event)) = ((context as *mut Vec<PluginEvent>).as_mut(), event.as_ref()) {
+            output.push(*event);
+        }
+    }
+
+    unsafe extern "C" fn emit_capability(context: *mut c_void, capability: *const PluginCapability) {
+        if let (Some(output), Some(capability)) = ((context as *mut Vec<PluginCapability>).as_mut(), capability.as_ref()) {
+            output.push(*capability);
+        }
+    }
+
+    fn last_dl_error() -> String {
+        let error = unsafe { libc::dlerror() };
+        match error.is_null() {
+            true => "unknown error".to_owned(),
+            false => unsafe { CStr::from_ptr(error) }.to_string_lossy().into_owned(),
+        }
+    }
+}
+
+/// Without the "plugins" feature, no plugin can ever be loaded.
+#[cfg(not(feature = "plugins"))]
+enum Library {}
+
+#[cfg(not(feature = "plugins"))]
+impl Library {
+    fn load(_path: &str, _config: &str) -> Result<Library, RuntimeError> {
+        Err(crate::error::ArgumentError::new(
+            "This build of evsieve does not support the --plugin argument. Rebuild evsieve with the \"plugins\" feature, i.e. cargo build --release --features plugins."
+        ).into())
+    }
+
+    fn process_events(&mut self, _events: &[PluginEvent]) -> Vec<PluginEvent> {
+        match *self {}
+    }
+
+    fn process_caps(&self, _caps: &[PluginCapability]) -> Option<Vec<PluginCapability>> {
+        match *self {}
+    }
+}
+
+#[cfg(all(test, feature = "plugins"))]
+mod tests {
+    use super::*;
+
+    /// Swaps the A and B keys and drops the C key.
+    unsafe extern "C" fn swap_events(
+        _state: *mut c_void, events: *const PluginEvent, count: usize, emit: EmitEventFn, context: *mut c_void
+    ) {
+        for event in std::slice::from_raw_parts(events, count) {
+            let code = match event.code {
+                30 => 48,
+                48 => 30,
+                46 => continue,
+                code => code,
+            };
+            emit(context, &PluginEvent { code, ..*event });
+        }
+    }
+
+    static INTERFACE: PluginInterface = PluginInterface {
+        abi_version: ABI_VERSION,
+        init: None,
+        process_events: Some(swap_events),
+        process_caps: None,
+        destroy: None,
+    };
+
+    #[test]
+    fn unittest() {
+        let library = unsafe { Library::from_interface(std::ptr::null_mut(), &INTERFACE, "").unwrap() };
+        let mut plugin = Plugin { library };
+        let domain = crate::domain::get_unique_domain();
+        let key_event = |name: &str, namespace| Event::new(
+            crate::ecodes::event_code("key", name).unwrap(), 1, 0, domain, namespace
+        );
+
+        let mut output = Vec::new();
+        plugin.apply_to_all(&[
+            key_event("a", Namespace::User), key_event("c", Namespace::User),
+            key_event("a", Namespace::Input), key_event("b", Namespace::User),
+        ], &mut output);
+        assert!(output == vec![
+            key_event("b", Namespace::User), key_event("a", Namespace::Input), key_event("a", Namespace::User),
+        ]);
+
+        let old_interface = PluginInterface { abi_version: ABI_VERSION + 1, ..INTERFACE };
+        assert!(unsafe { Library::from_interface(std::ptr::null_mut(), &old_interface, "") }.is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --press-duration argument, which measures how long keys were held and sends an event
+//! whose value is that duration in milliseconds when they get released.
+
+use std::collections::HashMap;
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event, EventValue};
+use crate::key::Key;
+use crate::loopback::LoopbackHandle;
+use crate::range::{Interval, Set};
+use crate::time::Instant;
+
+pub struct PressDuration {
+    keys: Vec<Key>,
+    /// The key that the duration events get merged with. Must not contain any values.
+    send_key: Key,
+    /// The durations are clamped to this range.
+    range: Interval,
+
+    /// State: modifiable at runtime.
+    /// The time at which each currently pressed key was pressed.
+    press_times: HashMap<Channel, Instant>,
+    /// The last value that was sent for each output channel.
+    last_values: HashMap<Channel, EventValue>,
+}
+
+impl PressDuration {
+    pub fn new(keys: Vec<Key>, send_key: Key, range: Interval) -> PressDuration {
+        PressDuration {
+            keys, send_key, range,
+            press_times: HashMap::new(),
+            last_values: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        output_events.push(event);
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return;
+        }
+
+        match event.value {
+            1 => {
+                self.press_times.insert(event.channel(), loopback.now());
+            },
+            0 => {
+                let press_time = match self.press_times.remove(&event.channel()) {
+                    Some(time) => time,
+                    None => return,
+                };
+                let duration = loopback.now().checked_duration_since(press_time).map_or(0, |duration| duration.as_millis());
+                let value = self.range.bound(duration.min(i32::MAX as u64) as i32);
+
+                let mut duration_event = self.send_key.merge(event);
+                duration_event.value = value;
+                duration_event.previous_value = *self.last_values.get(&duration_event.channel()).unwrap_or(&self.range.min);
+                self.last_values.insert(duration_event.channel(), value);
+                output_events.push(duration_event);
+            },
+            _ => {},
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    /// For every key that can be released, the duration event can take any value in the range.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+        for cap in caps {
+            if ! cap.code.ev_type().is_key() {
+                continue;
+            }
+            let can_release = self.keys.iter().any(|key| key.matches_cap(cap).1.contains(0));
+            if ! can_release {
+                continue;
+            }
+            let mut duration_cap = self.send_key.merge_cap(cap.clone());
+            duration_cap.values = Set::from(self.range);
+            duration_cap.abs_meta = None;
+            output_caps.push(duration_cap);
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+    use crate::time::Duration;
+
+    let domain = crate::domain::get_unique_domain();
+    let key_event = |value| Event::new(EventCode::new(EventType::KEY, 30), value, 1 - value, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let durations = |output: &[Event]| output.iter()
+        .filter(|event| event.ev_type().is_abs())
+        .map(|event| (event.previous_value, event.value))
+        .collect::<Vec<_>>();
+
+    let mut loopback = crate::loopback::Loopback::new();
+    let mut press_duration = PressDuration::new(
+        vec![KeyParser::default_filter().parse("key:a").unwrap()],
+        KeyParser::pure().parse("abs:brake").unwrap(),
+        Interval::new(0, 1000),
+    );
+    let mut output = Vec::new();
+    press_duration.apply(key_event(1), &mut output, &mut loopback.get_handle(at(0)));
+    press_duration.apply(key_event(2), &mut output, &mut loopback.get_handle(at(250)));
+    press_duration.apply(key_event(0), &mut output, &mut loopback.get_handle(at(300)));
+    press_duration.apply(key_event(1), &mut output, &mut loopback.get_handle(at(1000)));
+    press_duration.apply(key_event(0), &mut output, &mut loopback.get_handle(at(3000)));
+    assert_eq!(durations(&output), vec![(0, 300), (300, 1000)]);
+    assert_eq!(output.len(), 7);
+}
@@ -0,0 +1,97 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --pressure-click argument for touchpads that report how hard they are pressed but
+//! cannot be clicked. Whenever the pressure rises above a threshold, a button press is sent, and when
+//! it drops below a lower threshold, the button is released again.
+
+use crate::capability::Capability;
+use crate::crossing::{Crossing, CrossingDirection, CrossingTracker};
+use crate::event::Event;
+use crate::key::Key;
+use crate::range::{Interval, Set};
+
+pub struct PressureClick {
+    keys: Vec<Key>,
+    /// The key that the button events get merged with. Must not contain any values.
+    button: Key,
+
+    /// State: modifiable at runtime.
+    /// Remembers on which side of the thresholds each axis was last seen.
+    tracker: CrossingTracker,
+}
+
+impl PressureClick {
+    /// The button gets pressed when an axis reaches `thresholds.max` and released when it drops
+    /// below `thresholds.min`.
+    pub fn new(keys: Vec<Key>, button: Key, thresholds: Interval) -> PressureClick {
+        PressureClick {
+            keys, button,
+            tracker: CrossingTracker::new(Crossing::new(thresholds, CrossingDirection::Both)),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        output_events.push(event);
+        if ! event.ev_type().is_abs() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return;
+        }
+
+        let value = match self.tracker.update_direction(&event) {
+            Some(CrossingDirection::Upward) => 1,
+            Some(CrossingDirection::Downward) => 0,
+            Some(CrossingDirection::Both) | None => return,
+        };
+        let mut button_event = self.button.merge(event);
+        button_event.value = value;
+        button_event.previous_value = 1 - value;
+        output_events.push(button_event);
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    /// Every axis that this argument acts on may cause the button to be pressed or released.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+        for cap in caps {
+            if ! cap.code.ev_type().is_abs() {
+                continue;
+            }
+            if ! self.keys.iter().any(|key| ! key.matches_cap(cap).1.is_empty()) {
+                continue;
+            }
+            let mut button_cap = self.button.merge_cap(cap.clone());
+            button_cap.values = Set::from(Interval::new(0, 1));
+            button_cap.abs_meta = None;
+            output_caps.push(button_cap);
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+
+    let domain = crate::domain::get_unique_domain();
+    let pressure_code = crate::ecodes::event_code("abs", "pressure").unwrap();
+    let pressure_event = |previous_value, value| Event::new(pressure_code, value, previous_value, domain, Namespace::User);
+    let parser = KeyParser::default_filter();
+    let mut pressure_click = PressureClick::new(
+        vec![parser.parse("abs:pressure").unwrap()], parser.parse("btn:left").unwrap(), Interval::new(25, 30)
+    );
+
+    let mut output = Vec::new();
+    pressure_click.apply_to_all(&[
+        pressure_event(0, 20), pressure_event(20, 28), pressure_event(28, 35), pressure_event(35, 27),
+        pressure_event(27, 31), pressure_event(31, 10), pressure_event(10, 0),
+    ], &mut output);
+    let buttons: Vec<_> = output.iter()
+        .filter(|event| event.ev_type().is_key())
+        .map(|event| event.value)
+        .collect();
+    assert_eq!(buttons, vec![1, 0]);
+}
@@ -1,40 +1,155 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::PathBuf;
+
 use crate::capability::Capability;
 use crate::data::hid_usage::UsageNames;
+use crate::error::{Context, SystemError};
 use crate::key::Key;
 use crate::event::{Event, EventCode, EventType, EventValue};
 use crate::ecodes;
 use crate::domain;
+use crate::io::serialization;
+use crate::loopback::LoopbackHandle;
+use crate::time::{Duration, Instant};
+
+/// If a file that we print to could not be opened or written to, wait at least this long before trying
+/// to open it again.
+const REOPEN_INTERVAL: Duration = Duration::from_millis(1000);
 
 pub enum EventPrintMode {
     Detailed,
     Direct,
+    /// Writes the events in the format of `crate::io::serialization`. Only possible for files.
+    Binary,
+}
+
+pub enum PrintTarget {
+    Stdout,
+    File(FileTarget),
+}
+
+/// A file or FIFO that a --print writes to. Writing never blocks: if a FIFO is full or has no reader,
+/// the events are dropped. If writing fails, the file is reopened later.
+pub struct FileTarget {
+    path: PathBuf,
+    file: Option<File>,
+    last_open_attempt: Option<Instant>,
+    /// Written before the first write after the file has been (re)opened.
+    preamble: Vec<u8>,
+    preamble_pending: bool,
+}
+
+impl FileTarget {
+    /// Opens the file right away so mistakes like nonexistent directories are reported on startup. A FIFO
+    /// that has no reader yet is not an error.
+    pub fn new(path: PathBuf, preamble: Vec<u8>) -> Result<FileTarget, SystemError> {
+        let mut target = FileTarget { path, file: None, last_open_attempt: None, preamble, preamble_pending: true };
+        match target.open() {
+            Ok(file) => target.file = Some(file),
+            Err(error) if error.raw_os_error() == Some(libc::ENXIO) => (),
+            Err(error) => return Err(SystemError::from(error).with_context(
+                format!("While opening the file {} for --print:", target.path.display())
+            )),
+        }
+        Ok(target)
+    }
+
+    fn open(&mut self) -> io::Result<File> {
+        self.last_open_attempt = Some(Instant::now());
+        self.preamble_pending = true;
+        OpenOptions::new()
+            .append(true)
+            .create(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(&self.path)
+    }
+
+    fn write_line(&mut self, line: &str) {
+        self.write_bytes(format!("{}\n", line).as_bytes());
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) {
+        if self.file.is_none() {
+            let may_reopen = match self.last_open_attempt {
+                Some(time) => Instant::now().checked_duration_since(time).map_or(true, |elapsed| elapsed >= REOPEN_INTERVAL),
+                None => true,
+            };
+            if ! may_reopen {
+                return;
+            }
+            match self.open() {
+                Ok(file) => self.file = Some(file),
+                // The FIFO has no reader at the moment.
+                Err(error) if error.raw_os_error() == Some(libc::ENXIO) => return,
+                Err(error) => {
+                    crate::utils::warn_once(format!("Error: failed to open {} for --print: {}", self.path.display(), error));
+                    return;
+                },
+            }
+        }
+
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => return,
+        };
+        let data = match self.preamble_pending {
+            true => [self.preamble.as_slice(), bytes].concat(),
+            false => bytes.to_vec(),
+        };
+        // Lines and records are shorter than PIPE_BUF, so writing them to a FIFO either fully succeeds or fails.
+        match file.write(&data) {
+            Ok(_) => self.preamble_pending = false,
+            Err(error) if error.kind() == io::ErrorKind::WouldBlock => (),
+            Err(error) => {
+                // Most likely the reader of the FIFO has gone away, so it is reopened when a new
+                // reader shows up.
+                if error.raw_os_error() != Some(libc::EPIPE) {
+                    crate::utils::warn_once(format!("Error: failed to write to {} for --print: {}", self.path.display(), error));
+                }
+                self.file = None;
+            },
+        }
+    }
 }
 
 /// Created by --print arguments.
 pub struct EventPrinter {
     keys: Vec<Key>,
     mode: EventPrintMode,
+    target: PrintTarget,
 }
 
 impl EventPrinter {
-    pub fn new(keys: Vec<Key>, mode: EventPrintMode) -> EventPrinter {
-        EventPrinter { keys, mode }
+    pub fn new(keys: Vec<Key>, mode: EventPrintMode, target: PrintTarget) -> EventPrinter {
+        EventPrinter { keys, mode, target }
     }
 
-    fn apply(&self, event: Event) {
+    fn apply(&mut self, event: Event, loopback: &mut LoopbackHandle) {
         if self.keys.iter().any(|key| key.matches(&event)) {
-            println!("{}", match self.mode {
+            let line = match self.mode {
                 EventPrintMode::Direct => print_event_direct(event),
                 EventPrintMode::Detailed => print_event_detailed(event),
-            });
+                EventPrintMode::Binary => {
+                    if let PrintTarget::File(file) = &mut self.target {
+                        file.write_bytes(&serialization::encode_event(loopback.now(), &event));
+                    }
+                    return;
+                },
+            };
+            match &mut self.target {
+                PrintTarget::Stdout => println!("{}", line),
+                PrintTarget::File(file) => file.write_line(&line),
+            }
         }
     }
 
-    pub fn apply_to_all(&self, events: &[Event]) {
+    pub fn apply_to_all(&mut self, events: &[Event], loopback: &mut LoopbackHandle) {
         for &event in events {
-            self.apply(event);
+            self.apply(event, loopback);
         }
     }
 
@@ -80,15 +195,52 @@ pub fn print_event_detailed(event: Event) -> String {
     if let Some(domain_name) = domain::try_reverse_resolve(event.domain) {
         result = format!("{:<80}  domain = {}", result, domain_name);
     }
+    if ! event.annotations.is_empty() {
+        result = format!("{}  annotations = {}", result, event.annotations.names().join(", "));
+    }
 
     result
 }
 
 pub fn print_event_direct(event: Event) -> String {
     let name = ecodes::event_name(event.code);
-    if let Some(domain_name) = domain::try_reverse_resolve(event.domain) {
-        format!("{}:{}@{}", name, event.value, domain_name)
-    } else {
-        format!("{}:{}", name, event.value)
+    let mut result = match domain::try_reverse_resolve(event.domain) {
+        Some(domain_name) => format!("{}:{}@{}", name, event.value, domain_name),
+        None => format!("{}:{}", name, event.value),
+    };
+    for annotation in event.annotations.names() {
+        result = format!("{}^{}", result, annotation);
     }
-}
\ No newline at end of file
+    result
+}
+#[test]
+fn unittest() {
+    let directory = std::env::temp_dir().join(format!("evsieve-print-test-{}", std::process::id()));
+    std::fs::create_dir_all(&directory).unwrap();
+
+    let file_path = directory.join("events.log");
+    let mut target = FileTarget::new(file_path.clone(), Vec::new()).unwrap();
+    target.write_line("key:a:1");
+    target.write_line("key:a:0");
+    assert_eq!(std::fs::read_to_string(&file_path).unwrap(), "key:a:1\nkey:a:0\n");
+
+    // The preamble is written once per time the file is opened.
+    let binary_path = directory.join("events.bin");
+    let mut target = FileTarget::new(binary_path.clone(), b"header;".to_vec()).unwrap();
+    target.write_bytes(b"first;");
+    target.write_bytes(b"second;");
+    target.file = None;
+    target.last_open_attempt = None;
+    target.write_bytes(b"third;");
+    assert_eq!(std::fs::read(&binary_path).unwrap(), b"header;first;second;header;third;");
+
+    // A FIFO without a reader does not block, and the events are dropped.
+    let fifo_path = directory.join("events.fifo");
+    let fifo_cstr = std::ffi::CString::new(fifo_path.to_str().unwrap()).unwrap();
+    assert_eq!(unsafe { libc::mkfifo(fifo_cstr.as_ptr(), 0o600) }, 0);
+    let mut target = FileTarget::new(fifo_path, Vec::new()).unwrap();
+    target.write_line("key:a:1");
+
+    assert!(FileTarget::new(directory.join("nonexistent").join("events.log"), Vec::new()).is_err());
+    std::fs::remove_dir_all(&directory).unwrap();
+}
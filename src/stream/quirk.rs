@@ -0,0 +1,61 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+use crate::capability::{Capability, Certainty};
+use crate::event::Event;
+use crate::key::Key;
+use crate::quirks::Quirk;
+use crate::range::Set;
+
+/// Represents a --quirk argument.
+pub struct QuirkEntry {
+    /// Only events matching at least one of these keys are passed to the quirk.
+    keys: Vec<Key>,
+    quirk: Box<dyn Quirk>,
+}
+
+impl QuirkEntry {
+    pub fn new(keys: Vec<Key>, quirk: Box<dyn Quirk>) -> QuirkEntry {
+        QuirkEntry { keys, quirk }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            if self.keys.iter().any(|key| key.matches(&event)) {
+                self.quirk.apply(event, output_events);
+            } else {
+                output_events.push(event);
+            }
+        }
+    }
+
+    fn apply_to_cap(&self, cap: &Capability, output_caps: &mut Vec<Capability>) {
+        let mut matching_values = Set::empty();
+        let mut certainty = Certainty::Maybe;
+        for key in &self.keys {
+            let (key_certainty, key_values) = key.matches_cap(cap);
+            if key_certainty == Certainty::Always && key_values == cap.values {
+                certainty = Certainty::Always;
+            }
+            matching_values = matching_values.union(&key_values);
+        }
+
+        if ! matching_values.is_empty() {
+            self.quirk.apply_to_cap(cap.clone().with_values(matching_values.clone()), output_caps);
+        }
+
+        // The part of the capability that may bypass the quirk.
+        let bypassing_values = match certainty {
+            Certainty::Always => cap.values.setminus(&matching_values),
+            Certainty::Maybe => cap.values.clone(),
+        };
+        if ! bypassing_values.is_empty() {
+            output_caps.push(cap.clone().with_values(bypassing_values));
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            self.apply_to_cap(cap, output_caps);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --remap-modifiers argument, which swaps or replaces keys like Ctrl and CapsLock.
+//!
+//! Unlike a pair of --map arguments, this keeps track of which keys it has pressed for each domain.
+//! Repeat and release events are only sent for keys that were pressed through this argument, so keys
+//! that were already held when evsieve started cannot leak repeat events of the wrong code. If several
+//! keys are mapped to the same key, that key is pressed by the first of them and released by the last
+//! of them. When an input device is lost, all keys that were held on it get released.
+
+use std::collections::HashMap;
+
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::event::{Channel, Event, EventCode};
+use crate::key::Key;
+
+pub struct RemapModifiers {
+    /// Only key events matching these keys get remapped.
+    keys: Vec<Key>,
+    /// Maps every key that this argument manages to the key it gets sent as. Keys that are only the
+    /// target of a remapping map to themselves, so their presses get counted as well.
+    table: HashMap<EventCode, EventCode>,
+
+    /// State: modifiable at runtime.
+    /// The keys that are currently held and the code that they were sent as.
+    held: HashMap<Channel, EventCode>,
+    /// For each key that has been sent as pressed, how many held keys are keeping it pressed.
+    press_counts: HashMap<Channel, usize>,
+}
+
+impl RemapModifiers {
+    pub fn new(keys: Vec<Key>, table: HashMap<EventCode, EventCode>) -> RemapModifiers {
+        RemapModifiers {
+            keys, table,
+            held: HashMap::new(),
+            press_counts: HashMap::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>) {
+        if event.code == EventCode::DEVICE_LOST && event.value == 1 {
+            self.release_domain(event, output_events);
+            return output_events.push(event);
+        }
+        let target = match self.table.get(&event.code) {
+            Some(&target) if self.keys.iter().any(|key| key.matches(&event)) => target,
+            _ => return output_events.push(event),
+        };
+
+        let channel = event.channel();
+        match event.value {
+            1 => {
+                // The same key cannot be pressed twice without being released in between.
+                if self.held.contains_key(&channel) {
+                    return;
+                }
+                self.held.insert(channel, target);
+                let count = self.press_counts.entry((target, event.domain)).or_insert(0);
+                *count += 1;
+                if *count == 1 {
+                    output_events.push(Event { code: target, value: 1, previous_value: 0, ..event });
+                }
+            },
+            0 => {
+                // Releases of keys that were pressed before we saw them are dropped.
+                if let Some(target) = self.held.remove(&channel) {
+                    self.release(target, event, output_events);
+                }
+            },
+            _ => {
+                // Repeats of keys that were pressed before we saw them are dropped.
+                if let Some(&target) = self.held.get(&channel) {
+                    output_events.push(Event { code: target, ..event });
+                }
+            },
+        }
+    }
+
+    /// Lowers the press count of the target and releases it if no other key is keeping it pressed.
+    /// The release event is based on `event`.
+    fn release(&mut self, target: EventCode, event: Event, output_events: &mut Vec<Event>) {
+        let target_channel = (target, event.domain);
+        let count = match self.press_counts.get_mut(&target_channel) {
+            Some(count) => count,
+            None => return,
+        };
+        *count -= 1;
+        if *count == 0 {
+            self.press_counts.remove(&target_channel);
+            output_events.push(Event { code: target, value: 0, previous_value: 1, ..event });
+        }
+    }
+
+    /// Releases all keys that are held on the domain of the device-lost event.
+    fn release_domain(&mut self, device_lost_event: Event, output_events: &mut Vec<Event>) {
+        let domain: Domain = device_lost_event.domain;
+        let mut lost_channels: Vec<Channel> = self.held.keys()
+            .filter(|(_, held_domain)| *held_domain == domain)
+            .copied()
+            .collect();
+        lost_channels.sort_by_key(|(code, _)| *code);
+        for channel in lost_channels {
+            if let Some(target) = self.held.remove(&channel) {
+                let (code, _) = channel;
+                self.release(target, Event { code, ..device_lost_event }, output_events);
+            }
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>) {
+        for &event in events {
+            self.apply(event, output_events);
+        }
+    }
+
+    /// Keys that get remapped can additionally show up as their targets.
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        for cap in caps {
+            output_caps.push(cap.clone());
+            let target = match self.table.get(&cap.code) {
+                Some(&target) if target != cap.code => target,
+                _ => continue,
+            };
+            for key in &self.keys {
+                let (_, matching_values) = key.matches_cap(cap);
+                if ! matching_values.is_empty() {
+                    output_caps.push(Capability { code: target, values: matching_values, ..cap.clone() });
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventType, Namespace};
+    use crate::key::KeyParser;
+
+    let kb = crate::domain::get_unique_domain();
+    let other_kb = crate::domain::get_unique_domain();
+    let key_event = |code, value, domain| Event::new(EventCode::new(EventType::KEY, code), value, 0, domain, Namespace::User);
+    let codes = |output: &[Event]| output.iter()
+        .map(|event| (event.code.code(), event.value, event.domain == kb))
+        .collect::<Vec<_>>();
+    // Swaps capslock (58) with leftctrl (29), and maps rightctrl (97) to leftctrl as well.
+    let capslock = EventCode::new(EventType::KEY, 58);
+    let leftctrl = EventCode::new(EventType::KEY, 29);
+    let rightctrl = EventCode::new(EventType::KEY, 97);
+    let table: HashMap<EventCode, EventCode> = vec![
+        (capslock, leftctrl), (leftctrl, capslock), (rightctrl, leftctrl),
+    ].into_iter().collect();
+    let mut remap = RemapModifiers::new(vec![KeyParser::default_filter().parse("").unwrap()], table);
+
+    // Repeats and releases of keys that were held before evsieve started get dropped.
+    let mut output = Vec::new();
+    remap.apply_to_all(&[
+        key_event(58, 2, kb), key_event(58, 0, kb), key_event(58, 1, kb), key_event(58, 2, kb), key_event(30, 1, kb),
+        key_event(58, 0, kb), key_event(58, 2, kb),
+    ], &mut output);
+    assert_eq!(codes(&output), vec![(29, 1, true), (29, 2, true), (30, 1, true), (29, 0, true)]);
+
+    // Leftctrl stays pressed until both keys that press it are released.
+    let mut output = Vec::new();
+    remap.apply_to_all(&[
+        key_event(58, 1, kb), key_event(97, 1, kb), key_event(58, 0, kb), key_event(97, 2, kb), key_event(97, 0, kb),
+    ], &mut output);
+    assert_eq!(codes(&output), vec![(29, 1, true), (29, 2, true), (29, 0, true)]);
+
+    // Different devices do not interfere with each other, and losing a device releases its keys.
+    let mut output = Vec::new();
+    let mut device_lost = key_event(0, 1, kb);
+    device_lost.code = EventCode::DEVICE_LOST;
+    remap.apply_to_all(&[
+        key_event(58, 1, kb), key_event(58, 1, other_kb), key_event(29, 1, kb), device_lost,
+        key_event(58, 0, kb), key_event(58, 0, other_kb),
+    ], &mut output);
+    assert_eq!(codes(&output)[.. 3], [(29, 1, true), (29, 1, false), (58, 1, true)]);
+    assert_eq!(output[5].code, EventCode::DEVICE_LOST);
+    assert_eq!(codes(&output)[3 .. 5], [(58, 0, true), (29, 0, true)]);
+    assert_eq!(codes(&output)[6 ..], [(29, 0, false)]);
+    assert!(remap.held.is_empty() && remap.press_counts.is_empty());
+}
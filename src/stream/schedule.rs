@@ -0,0 +1,142 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --schedule argument, which changes toggles at certain times of the day.
+//!
+//! The loopback only knows about the monotonic clock, which keeps running when the wall clock gets
+//! changed, e.g. because of daylight saving time or NTP. Instead of sleeping until the next scheduled
+//! time, we therefore wake up at least once every CHECK_INTERVAL and compare the wall clock against
+//! the wall clock of the last check. A scheduled time fires if it has passed since the last check.
+//! If the wall clock went backwards, nothing fires.
+
+use std::mem::MaybeUninit;
+
+use crate::loopback::{LoopbackHandle, Token};
+use crate::state::State;
+use crate::stream::hook::Effect;
+use crate::time::Duration;
+
+/// The maximum amount of seconds between two comparisons against the wall clock.
+const CHECK_INTERVAL_SECONDS: libc::time_t = 60;
+
+/// A time of the day in the local timezone.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct TimeOfDay {
+    pub hour: i32,
+    pub minute: i32,
+    pub second: i32,
+}
+
+pub struct Schedule {
+    times: Vec<TimeOfDay>,
+    effects: Vec<Effect>,
+
+    /// State: modifiable at runtime.
+    /// The wall clock time in seconds since the epoch at which we last checked whether a time passed.
+    last_check: Option<libc::time_t>,
+    /// The token that will wake us up for the next check.
+    token: Option<Token>,
+}
+
+impl Schedule {
+    pub fn new(times: Vec<TimeOfDay>, effects: Vec<Effect>) -> Schedule {
+        Schedule {
+            times, effects,
+            last_check: None,
+            token: None,
+        }
+    }
+
+    /// Must be called once before the stream starts running to schedule the first check.
+    pub fn start(&mut self, loopback: &mut LoopbackHandle) {
+        self.last_check = Some(wall_clock_now());
+        self.schedule_next_check(loopback);
+    }
+
+    pub fn wakeup(&mut self, token: &Token, state: &mut State, loopback: &mut LoopbackHandle) {
+        if self.token.as_ref() != Some(token) {
+            return;
+        }
+        self.token = None;
+
+        let now = wall_clock_now();
+        if let Some(last_check) = self.last_check {
+            let has_passed = self.times.iter().any(|&time| {
+                let occurrence = latest_occurrence(time, now);
+                last_check < occurrence && occurrence <= now
+            });
+            if has_passed {
+                for effect in &self.effects {
+                    effect(state);
+                }
+            }
+        }
+        self.last_check = Some(now);
+        self.schedule_next_check(loopback);
+    }
+
+    fn schedule_next_check(&mut self, loopback: &mut LoopbackHandle) {
+        let now = wall_clock_now();
+        let seconds_until_next = self.times.iter()
+            .map(|&time| next_occurrence(time, now) - now)
+            .min()
+            .unwrap_or(0);
+        let seconds = match seconds_until_next {
+            seconds if (0 .. CHECK_INTERVAL_SECONDS).contains(&seconds) => seconds,
+            _ => CHECK_INTERVAL_SECONDS,
+        };
+        // Wake up slightly after the scheduled time to make sure that it has passed by then.
+        let delay = Duration::from_millis(seconds as u64 * 1000 + 10);
+        self.token = Some(loopback.schedule_wakeup_in(delay));
+    }
+}
+
+fn wall_clock_now() -> libc::time_t {
+    unsafe { libc::time(std::ptr::null_mut()) }
+}
+
+/// Returns the moment at which the time of the day occurs on the same local day as `now`, with
+/// `day_offset` days added. Lets mktime() figure out daylight saving time and overflowing days.
+fn occurrence_on_day(time: TimeOfDay, now: libc::time_t, day_offset: i32) -> libc::time_t {
+    unsafe {
+        let mut tm: MaybeUninit<libc::tm> = MaybeUninit::uninit();
+        if libc::localtime_r(&now, tm.as_mut_ptr()).is_null() {
+            return now;
+        }
+        let mut tm = tm.assume_init();
+        tm.tm_hour = time.hour;
+        tm.tm_min = time.minute;
+        tm.tm_sec = time.second;
+        tm.tm_mday += day_offset;
+        tm.tm_isdst = -1;
+        libc::mktime(&mut tm)
+    }
+}
+
+/// The last moment at or before `now` at which the time of the day occurred.
+fn latest_occurrence(time: TimeOfDay, now: libc::time_t) -> libc::time_t {
+    match occurrence_on_day(time, now, 0) {
+        today if today <= now => today,
+        _ => occurrence_on_day(time, now, -1),
+    }
+}
+
+/// The first moment after `now` at which the time of the day occurs.
+fn next_occurrence(time: TimeOfDay, now: libc::time_t) -> libc::time_t {
+    match occurrence_on_day(time, now, 0) {
+        today if today > now => today,
+        _ => occurrence_on_day(time, now, 1),
+    }
+}
+
+#[test]
+fn unittest() {
+    let now = wall_clock_now();
+    let time = TimeOfDay { hour: 22, minute: 0, second: 0 };
+    let latest = latest_occurrence(time, now);
+    let next = next_occurrence(time, now);
+    assert!(latest <= now && now < next);
+    // Allow for an hour of difference due to daylight saving time.
+    assert!((next - latest - 24 * 3600).abs() <= 3600);
+    assert_eq!(next_occurrence(time, latest), next);
+    assert_eq!(latest_occurrence(time, next), next);
+}
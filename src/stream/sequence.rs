@@ -0,0 +1,212 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --sequence argument: pressing a series of keys one after another, like a leader key
+//! followed by "g" and "s", triggers some action.
+//!
+//! While a sequence is in progress, all events of the keys that are part of it are held back. If the
+//! sequence gets completed, those events are dropped. If another key gets pressed or the next key of
+//! the sequence is not pressed in time, the held back events are sent on in their original order.
+
+use std::collections::HashSet;
+
+use crate::capability::Capability;
+use crate::event::{Channel, Event};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::{Interval, Set};
+use crate::state::State;
+use crate::stream::hook::Effect;
+use crate::time::Duration;
+
+pub struct Sequence {
+    /// The keys that need to be pressed, in order.
+    keys: Vec<Key>,
+    /// How much time may pass between pressing two consecutive keys of the sequence.
+    timeout: Duration,
+    /// Keys that are pressed when the sequence completes, in order.
+    send_keys: Vec<Key>,
+    effects: Vec<Effect>,
+
+    /// State: modifiable at runtime.
+    /// How many keys of the sequence have been pressed so far.
+    progress: usize,
+    /// The events that have been held back since the first key of the sequence was pressed.
+    buffer: Vec<Event>,
+    /// The wakeup at which the current progress times out.
+    timeout_token: Option<Token>,
+    /// Keys that were pressed as part of a completed sequence. Their repeat and release events are dropped.
+    swallowed: HashSet<Channel>,
+}
+
+impl Sequence {
+    pub fn new(keys: Vec<Key>, timeout: Duration, send_keys: Vec<Key>, effects: Vec<Effect>) -> Sequence {
+        Sequence {
+            keys, timeout, send_keys, effects,
+            progress: 0,
+            buffer: Vec::new(),
+            timeout_token: None,
+            swallowed: HashSet::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, state: &mut State, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() {
+            return output_events.push(event);
+        }
+        let channel = event.channel();
+        if event.value != 1 && self.swallowed.contains(&channel) {
+            if event.value == 0 {
+                self.swallowed.remove(&channel);
+            }
+            return;
+        }
+
+        if event.value != 1 {
+            // Releases and repeats of the keys that were pressed during the sequence are held back
+            // so they cannot overtake their presses.
+            if self.buffer.iter().any(|buffered| buffered.channel() == channel) {
+                self.buffer.push(event);
+            } else {
+                output_events.push(event);
+            }
+            return;
+        }
+
+        if self.keys[self.progress].matches(&event) {
+            self.buffer.push(event);
+            self.progress += 1;
+            if let Some(token) = self.timeout_token.take() {
+                loopback.cancel_token(token);
+            }
+            if self.progress == self.keys.len() {
+                self.complete(event, output_events, state);
+            } else {
+                self.timeout_token = Some(loopback.schedule_wakeup_in(self.timeout));
+            }
+        } else if self.progress > 0 {
+            self.abort(output_events, loopback);
+            // The key that aborted the sequence may be the start of a new one.
+            self.apply(event, output_events, state, loopback);
+        } else {
+            output_events.push(event);
+        }
+    }
+
+    fn complete(&mut self, last_event: Event, output_events: &mut Vec<Event>, state: &mut State) {
+        // Keys that are still held down will have their release dropped later.
+        for event in self.buffer.drain(..) {
+            match event.value {
+                0 => { self.swallowed.remove(&event.channel()); },
+                _ => { self.swallowed.insert(event.channel()); },
+            }
+        }
+        self.progress = 0;
+
+        for key in &self.send_keys {
+            let mut event = key.merge(last_event);
+            event.value = 1;
+            event.previous_value = 0;
+            output_events.push(event);
+        }
+        for key in self.send_keys.iter().rev() {
+            let mut event = key.merge(last_event);
+            event.value = 0;
+            event.previous_value = 1;
+            output_events.push(event);
+        }
+        for effect in &self.effects {
+            effect(state);
+        }
+    }
+
+    /// Sends on all events that were held back.
+    fn abort(&mut self, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if let Some(token) = self.timeout_token.take() {
+            loopback.cancel_token(token);
+        }
+        output_events.append(&mut self.buffer);
+        self.progress = 0;
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, state: &mut State, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, state, loopback);
+        }
+    }
+
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        if self.timeout_token.as_ref() == Some(token) {
+            self.timeout_token = None;
+            output_events.append(&mut self.buffer);
+            self.progress = 0;
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+        // The send-key events take their domain from the last key of the sequence.
+        let last_key = &self.keys[self.keys.len() - 1];
+        for cap in caps {
+            let (_, values) = last_key.matches_cap(cap);
+            if ! values.contains(1) {
+                continue;
+            }
+            for key in &self.send_keys {
+                let mut send_key_cap = key.merge_cap(cap.clone());
+                send_key_cap.values = Set::from(Interval::new(0, 1));
+                send_key_cap.abs_meta = None;
+                output_caps.push(send_key_cap);
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::Namespace;
+    use crate::key::KeyParser;
+    use crate::loopback::Loopback;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let code = |name: &str| crate::ecodes::event_code("key", name).unwrap();
+    let key_event = |name: &str, value: i32| Event::new(code(name), value, 1 - value, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+    let parser = KeyParser::default_filter();
+
+    let mut sequence = Sequence::new(
+        parser.parse_all(&["key:leftmeta".to_owned(), "key:g".to_owned(), "key:s".to_owned()]).unwrap(),
+        Duration::from_millis(500),
+        vec![KeyParser::default_mask().parse("key:f5").unwrap()],
+        Vec::new(),
+    );
+    let mut state = State::new();
+    let mut loopback = Loopback::new();
+    let mut output = Vec::new();
+    let mut run = |sequence: &mut Sequence, events: &[(&str, i32)], millis: u64, output: &mut Vec<Event>| {
+        for &(name, value) in events {
+            sequence.apply(key_event(name, value), output, &mut state, &mut loopback.get_handle(at(millis)));
+        }
+    };
+    let names = |output: &[Event]| output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>();
+
+    // Completing the sequence sends F5 and drops the keys of the sequence, including later releases.
+    run(&mut sequence, &[("leftmeta", 1), ("leftmeta", 0), ("g", 1), ("g", 0), ("s", 1), ("s", 0)], 0, &mut output);
+    assert_eq!(names(&output), vec![(code("f5"), 1), (code("f5"), 0)]);
+    output.clear();
+
+    // Another key aborts the sequence and the held back keys are sent on in their original order.
+    run(&mut sequence, &[("leftmeta", 1), ("g", 1), ("x", 1), ("leftmeta", 0)], 1000, &mut output);
+    assert_eq!(names(&output), vec![(code("leftmeta"), 1), (code("g"), 1), (code("x"), 1), (code("leftmeta"), 0)]);
+    output.clear();
+    run(&mut sequence, &[("g", 0), ("x", 0)], 1000, &mut output);
+    output.clear();
+
+    // The sequence times out if the next key is not pressed in time.
+    run(&mut sequence, &[("leftmeta", 1), ("leftmeta", 0)], 2000, &mut output);
+    assert!(output.is_empty());
+    let (_, token) = loopback.poll_once(at(2600)).unwrap();
+    sequence.wakeup(&token, &mut output);
+    assert_eq!(names(&output), vec![(code("leftmeta"), 1), (code("leftmeta"), 0)]);
+}
@@ -0,0 +1,157 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --slow-keys argument: a key press is only passed on after the key has been held
+//! down for a certain period. Keys that are released before that period has passed are ignored.
+//! This helps people who tend to accidentally brush against keys.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::capability::{Capability, Certainty};
+use crate::event::{Channel, Event};
+use crate::key::Key;
+use crate::loopback::{LoopbackHandle, Token};
+use crate::range::{Interval, Set};
+use crate::time::Duration;
+
+pub struct SlowKeys {
+    keys: Vec<Key>,
+    period: Duration,
+    /// If some, this event is pressed and released whenever a key press gets accepted.
+    feedback: Option<Key>,
+
+    /// State: modifiable at runtime.
+    /// Presses that are waiting for the period to pass.
+    pending: HashMap<Channel, (Token, Event)>,
+    /// Keys whose press has been passed on, so their repeat and release events should be passed on too.
+    accepted: HashSet<Channel>,
+}
+
+impl SlowKeys {
+    pub fn new(keys: Vec<Key>, period: Duration, feedback: Option<Key>) -> SlowKeys {
+        SlowKeys {
+            keys, period, feedback,
+            pending: HashMap::new(),
+            accepted: HashSet::new(),
+        }
+    }
+
+    fn apply(&mut self, event: Event, output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        if ! event.ev_type().is_key() || ! self.keys.iter().any(|key| key.matches(&event)) {
+            return output_events.push(event);
+        }
+        let channel = event.channel();
+        match event.value {
+            1 => {
+                // A press while the key is already pressed is unusual, but should not be delayed twice.
+                if self.accepted.contains(&channel) {
+                    return output_events.push(event);
+                }
+                if ! self.pending.contains_key(&channel) {
+                    let token = loopback.schedule_wakeup_in(self.period);
+                    self.pending.insert(channel, (token, event));
+                }
+            },
+            0 => {
+                if self.accepted.remove(&channel) {
+                    output_events.push(event);
+                } else if let Some((token, _)) = self.pending.remove(&channel) {
+                    loopback.cancel_token(token);
+                }
+            },
+            _ => {
+                // Repeat events are only passed on after the press has been accepted.
+                if self.accepted.contains(&channel) {
+                    output_events.push(event);
+                }
+            },
+        }
+    }
+
+    pub fn apply_to_all(&mut self, events: &[Event], output_events: &mut Vec<Event>, loopback: &mut LoopbackHandle) {
+        for &event in events {
+            self.apply(event, output_events, loopback);
+        }
+    }
+
+    pub fn wakeup(&mut self, token: &Token, output_events: &mut Vec<Event>) {
+        let channel = match self.pending.iter().find(|(_, (pending_token, _))| pending_token == token) {
+            Some((&channel, _)) => channel,
+            None => return,
+        };
+        let (_, event) = match self.pending.remove(&channel) {
+            Some(pending) => pending,
+            None => return,
+        };
+        self.accepted.insert(channel);
+        output_events.push(event);
+
+        if let Some(feedback) = &self.feedback {
+            let mut feedback_event = feedback.merge(event);
+            feedback_event.value = 1;
+            feedback_event.previous_value = 0;
+            output_events.push(feedback_event);
+            feedback_event.value = 0;
+            feedback_event.previous_value = 1;
+            output_events.push(feedback_event);
+        }
+    }
+
+    pub fn apply_to_all_caps(&self, caps: &[Capability], output_caps: &mut Vec<Capability>) {
+        output_caps.extend(caps.iter().cloned());
+        let feedback = match &self.feedback {
+            Some(feedback) => feedback,
+            None => return,
+        };
+        for cap in caps {
+            let is_matching = self.keys.iter().any(|key| {
+                let (certainty, values) = key.matches_cap(cap);
+                certainty == Certainty::Maybe || ! values.is_empty()
+            });
+            if cap.code.ev_type().is_key() && is_matching {
+                let mut feedback_cap = feedback.merge_cap(cap.clone());
+                feedback_cap.values = Set::from(Interval::new(0, 1));
+                feedback_cap.abs_meta = None;
+                output_caps.push(feedback_cap);
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::key::KeyParser;
+    use crate::loopback::Loopback;
+    use crate::time::Instant;
+
+    let domain = crate::domain::get_unique_domain();
+    let key_event = |value| Event::new(EventCode::new(EventType::KEY, 30), value, 1 - value, domain, Namespace::User);
+    let start = Instant::now();
+    let at = |millis| start + Duration::from_millis(millis);
+
+    let mut slow_keys = SlowKeys::new(
+        vec![KeyParser::default_filter().parse("").unwrap()],
+        Duration::from_millis(100),
+        Some(KeyParser::default_mask().parse("snd:bell").unwrap()),
+    );
+    let mut loopback = Loopback::new();
+    let mut output = Vec::new();
+
+    // A key that is released too early is ignored.
+    slow_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(0)));
+    slow_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(50)));
+    assert!(loopback.poll_once(at(1000)).is_none());
+    assert!(output.is_empty());
+
+    // A key that is held long enough is passed on together with the feedback.
+    slow_keys.apply(key_event(1), &mut output, &mut loopback.get_handle(at(1000)));
+    slow_keys.apply(key_event(2), &mut output, &mut loopback.get_handle(at(1050)));
+    let (_, token) = loopback.poll_once(at(1100)).unwrap();
+    slow_keys.wakeup(&token, &mut output);
+    slow_keys.apply(key_event(0), &mut output, &mut loopback.get_handle(at(1200)));
+    let bell = crate::ecodes::event_code("snd", "bell").unwrap();
+    assert_eq!(
+        output.iter().map(|event| (event.code, event.value)).collect::<Vec<_>>(),
+        vec![(key_event(1).code, 1), (bell, 1), (bell, 0), (key_event(0).code, 0)],
+    );
+}
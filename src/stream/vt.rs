@@ -0,0 +1,36 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --on-vt argument, which changes toggles when another virtual terminal becomes active.
+
+use crate::io::vt::VtNumber;
+use crate::state::State;
+use crate::stream::hook::Effect;
+
+pub struct VtSwitch {
+    vts: Vec<VtNumber>,
+    /// If true, the effects happen when switching away from the VTs instead of switching to them.
+    leave: bool,
+    effects: Vec<Effect>,
+}
+
+impl VtSwitch {
+    pub fn new(vts: Vec<VtNumber>, leave: bool, effects: Vec<Effect>) -> VtSwitch {
+        VtSwitch { vts, leave, effects }
+    }
+
+    /// Must be called whenever the active VT changes. `previous` is None when the active VT is
+    /// determined for the first time.
+    pub fn on_vt_change(&self, previous: Option<VtNumber>, current: VtNumber, state: &mut State) {
+        let was_active = previous.map_or(false, |vt| self.vts.contains(&vt));
+        let is_active = self.vts.contains(&current);
+        let should_fire = match self.leave {
+            false => is_active && ! was_active,
+            true => was_active && ! is_active,
+        };
+        if should_fire {
+            for effect in &self.effects {
+                effect(state);
+            }
+        }
+    }
+}
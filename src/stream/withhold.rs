@@ -1,11 +1,14 @@
 // SPDX-License-Identifier: GPL-2.0-or-later
 
 use crate::capability::Capability;
-use crate::event::{Event, Channel};
+use crate::event::{Event, EventCode, Channel};
+use crate::domain::{self, Domain};
+use crate::ecodes;
 use crate::key::Key;
 use crate::loopback::{LoopbackHandle, Token};
 use crate::state::State;
 use crate::stream::hook::{Trigger, TriggerResponse};
+use crate::stream::print::print_event_direct;
 
 use super::hook::Hook;
 
@@ -19,6 +22,24 @@ pub struct Withhold {
     /// contain instructions like "last KEY_DOWN event on this channel was dropped, so drop the next
     /// KEY_UP event".
     channel_state: Vec<(WithholdChannel, ChannelState)>,
+
+    /// What to do with the events withheld from an input device when that device gets disconnected.
+    lost_mode: LostMode,
+
+    /// Withheld events matching these keys are released instead of consumed when a hook activates.
+    passthrough_keys: Vec<Key>,
+
+    /// If true, every change to `channel_state` gets printed to stderr. Specified by the debug flag.
+    debug: bool,
+}
+
+/// Determined by the device-lost= clause of --withhold.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LostMode {
+    /// Forget about the events that were withheld.
+    Drop,
+    /// Release the events that were withheld, each followed up by a KEY_UP event.
+    Flush,
 }
 
 /// Represents a group of one or more --hook arguments followed up by a single --withhold argument.
@@ -38,6 +59,18 @@ impl HookGroup {
 
 impl HookGroup {
     pub fn apply_to_all(&mut self, events_in: &[Event], events_out: &mut Vec<Event>, state: &mut State, loopback: &mut LoopbackHandle) {
+        // If an input device got disconnected, its keys have already been released, but the hooks and
+        // the withhold may still remember some of its events. Forget them so the device starts out clean
+        // if it gets reopened.
+        for event in events_in {
+            if event.code == EventCode::DEVICE_LOST && event.value == 1 {
+                for hook in &mut self.hooks {
+                    hook.trigger.reset_domain(event.domain, loopback);
+                }
+                self.withhold.forget_domain(event.domain, events_out);
+            }
+        }
+
         // This function is basically a mini-stream in the bigger `Stream` class. This mini-stream tracks
         // not only events, but also tracks additional information for each event. Specifically, for each event,
         // we want to keep track of how each hook reacted to said event.
@@ -57,8 +90,11 @@ impl HookGroup {
 
             for (event, response_record) in events.drain(..) {
                 let response = hook.trigger.apply(event, loopback);
+                if self.withhold.debug {
+                    trace_hook_response(hook_idx, event, response);
+                }
                 let record_for_current_event = response_record.with_response(&hook.trigger, hook_idx, event, response);
-                hook.actuator.apply_response(response, event, record_for_current_event, &mut buffer, state);
+                hook.actuator.apply_response(response, event, record_for_current_event, &mut buffer, state, loopback);
             }
 
             std::mem::swap(&mut events, &mut buffer);
@@ -87,9 +123,12 @@ impl HookGroup {
     pub fn wakeup(&mut self, token: &Token, events_out: &mut Vec<Event>) {
         let mut some_tracker_expired = false;
         let triggers = self.hooks.iter_mut().map(|hook| &mut hook.trigger);
-        for trigger in triggers {
+        for (hook_idx, trigger) in triggers.enumerate() {
             if trigger.wakeup(token) {
                 some_tracker_expired = true;
+                if self.withhold.debug {
+                    eprintln!("withhold: hook {}: a key expired", hook_idx + 1);
+                }
             }
         }
         if ! some_tracker_expired {
@@ -195,7 +234,8 @@ impl TriggerResponseRecord {
             | TriggerResponse::Releases => {
                 self.any_trigger_interacts = true;
             },
-            TriggerResponse::Activates => {
+            TriggerResponse::Activates
+            | TriggerResponse::Pulses => {
                 self.activated_triggers.push(hook_idx);
                 self.any_trigger_interacts = true;
             },
@@ -219,13 +259,48 @@ impl Default for TriggerResponseRecord {
 }
 
 impl Withhold {
-    pub fn new(keys: Vec<Key>) -> Withhold {
+    pub fn new(keys: Vec<Key>, lost_mode: LostMode, passthrough_keys: Vec<Key>, debug: bool) -> Withhold {
         Withhold {
             keys,
             channel_state: Vec::new(),
+            lost_mode,
+            passthrough_keys,
+            debug,
         }
     }
 
+    /// Clears the state of all channels with the given domain. Depending on the mode, the events that
+    /// were withheld on those channels are either dropped or released.
+    fn forget_domain(&mut self, domain: Domain, events_out: &mut Vec<Event>) {
+        let lost_mode = self.lost_mode;
+        let debug = self.debug;
+        self.channel_state.retain(|(channel, state)| {
+            if channel.event_channel.1 != domain {
+                return true;
+            }
+            match (lost_mode, state) {
+                (LostMode::Flush, ChannelState::Withheld { withheld_event }) => {
+                    if debug {
+                        trace_channel(channel, *withheld_event, "released because its device was lost");
+                    }
+                    events_out.push(*withheld_event);
+                    events_out.push(Event {
+                        value: 0,
+                        previous_value: withheld_event.value,
+                        ..*withheld_event
+                    });
+                },
+                (LostMode::Drop, ChannelState::Withheld { withheld_event }) => if debug {
+                    trace_channel(channel, *withheld_event, "dropped because its device was lost");
+                },
+                (_, ChannelState::Residual) => if debug {
+                    eprintln!("withhold: {}: no longer residual because its device was lost", describe_channel(channel));
+                },
+            }
+            false
+        });
+    }
+
     fn apply(&mut self, event: Event, response_record: TriggerResponseRecord, events_out: &mut Vec<Event>, triggers: &[&Trigger]) {
         // Skip all events that did not match any preceding hook.
         if ! response_record.any_trigger_interacts {
@@ -265,17 +340,30 @@ impl Withhold {
                 if event.value == 1 {
                     // Withhold the event unless an event was already being withheld.
                     match current_channel_state {
-                        None => self.channel_state.push(
-                            (withhold_channel, ChannelState::Withheld { withheld_event: event })
-                        ),
+                        None => {
+                            if self.debug {
+                                trace_channel(&withhold_channel, event, "withheld");
+                            }
+                            self.channel_state.push(
+                                (withhold_channel, ChannelState::Withheld { withheld_event: event })
+                            )
+                        },
                         Some(state @ &mut ChannelState::Residual) => {
+                            if self.debug {
+                                trace_channel(&withhold_channel, event, "withheld, no longer residual");
+                            }
                             *state = ChannelState::Withheld { withheld_event: event }
                         },
-                        Some(ChannelState::Withheld { .. }) => {},
+                        Some(ChannelState::Withheld { .. }) => if self.debug {
+                            trace_channel(&withhold_channel, event, "dropped because another event is withheld on its channel");
+                        },
                     }
                     final_event = None;
                 } else {
                     // Drop all repeat events on channels that have an active tracker.
+                    if self.debug {
+                        trace_channel(&withhold_channel, event, "dropped because a hook is active on its channel");
+                    }
                     final_event = None;
                 }
             } else { // No trackers active at the event's channel.
@@ -291,6 +379,9 @@ impl Withhold {
                         },
                         Some(ChannelState::Residual) => {
                             // Drop this event and clear the residual state.
+                            if self.debug {
+                                trace_channel(&withhold_channel, event, "dropped because its channel was residual");
+                            }
                             self.channel_state.retain(|(channel, _)| *channel != withhold_channel);
                             final_event = None;
                         }
@@ -310,20 +401,36 @@ impl Withhold {
         }
 
         // All events which were withheld by a trigger that just activated shall be considered
-        // to have been consumed and their states are to be set to Residual.
+        // to have been consumed and their states are to be set to Residual, unless they match the
+        // passthrough keys, in which case they are released and their channel is forgotten.
+        let mut passed_through: Vec<WithholdChannel> = Vec::new();
         for (channel, state) in &mut self.channel_state {
-            if let ChannelState::Withheld { .. } = state {
-                for &hook_idx in &response_record.activated_triggers {
-                    if channel.is_affected_by_hook(hook_idx) {
-                        let trigger = &triggers[hook_idx.0];
-                        if trigger.has_tracker_matching_channel(channel.event_channel) {
-                            *state = ChannelState::Residual;
-                            break;
-                        }
+            if let ChannelState::Withheld { withheld_event } = *state {
+                let consumed_by = response_record.activated_triggers.iter().find(|&&hook_idx|
+                    channel.is_affected_by_hook(hook_idx)
+                    && triggers[hook_idx.0].has_tracker_matching_channel(channel.event_channel)
+                );
+                let consumed_by = match consumed_by {
+                    Some(hook_idx) => hook_idx.0 + 1,
+                    None => continue,
+                };
+                if self.passthrough_keys.iter().any(|key| key.matches(&withheld_event)) {
+                    if self.debug {
+                        trace_channel(channel, withheld_event, format!("released because hook {} activated and it matches passthrough-on-activate", consumed_by));
+                    }
+                    events_out.push(withheld_event);
+                    passed_through.push(*channel);
+                } else {
+                    if self.debug {
+                        trace_channel(channel, withheld_event, format!("consumed by hook {}, channel is now residual", consumed_by));
                     }
+                    *state = ChannelState::Residual;
                 }
             }
         }
+        if ! passed_through.is_empty() {
+            self.channel_state.retain(|(channel, _)| ! passed_through.contains(channel));
+        }
 
         // All events which are no longer withheld by any trigger shall be released.
         self.release_events(triggers, events_out);
@@ -335,6 +442,7 @@ impl Withhold {
 
     /// Writes all events that are not withheld by any trigger to the output stream.
     fn release_events(&mut self, triggers: &[&Trigger], events_out: &mut Vec<Event>) {
+        let debug = self.debug;
         self.channel_state.retain(|(channel, state)| {
             if let ChannelState::Withheld { withheld_event } = state {
                 let mut related_triggers = triggers.iter().skip(channel.first_hook.0);
@@ -342,6 +450,9 @@ impl Withhold {
                     trigger.has_active_tracker_matching_channel(channel.event_channel)
                 );
                 if ! is_still_withheld {
+                    if debug {
+                        trace_channel(channel, *withheld_event, "released because no hook withholds it anymore");
+                    }
                     events_out.push(*withheld_event);
                     return false;
                 }
@@ -355,6 +466,31 @@ impl Withhold {
     }
 }
 
+/// Describes a channel for the traces printed by the debug flag, e.g. "key:a@kb (hook 1)", where the hook
+/// is the first hook of the group that saw the events of this channel. Hooks are counted from one.
+fn describe_channel(channel: &WithholdChannel) -> String {
+    let (code, domain) = channel.event_channel;
+    let key = match domain::try_reverse_resolve(domain) {
+        Some(domain_name) => format!("{}@{}", ecodes::event_name(code), domain_name),
+        None => ecodes::event_name(code).into_owned(),
+    };
+    format!("{} (hook {})", key, channel.first_hook.0 + 1)
+}
+
+fn trace_channel(channel: &WithholdChannel, event: Event, message: impl std::fmt::Display) {
+    eprintln!("withhold: {} (hook {}): {}", print_event_direct(event), channel.first_hook.0 + 1, message);
+}
+
+fn trace_hook_response(hook_idx: HookIdx, event: Event, response: TriggerResponse) {
+    let message = match response {
+        TriggerResponse::None | TriggerResponse::Interacts => return,
+        TriggerResponse::Activates => "activated",
+        TriggerResponse::Releases => "released",
+        TriggerResponse::Pulses => "activated and released",
+    };
+    eprintln!("withhold: hook {}: {} by {}", hook_idx.0 + 1, message, print_event_direct(event));
+}
+
 /// For each `WithholdChannel`, at most one event can be withheld. This withheld event is always
 /// a KEY_DOWN event. Subsequent KEY_DOWN events that arrive while an event is being withheld
 /// shall be dropped. The event is withheld as long as some tracker returns true for
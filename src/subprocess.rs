@@ -20,9 +20,27 @@ pub fn terminate_all() {
     }
 }
 
+/// Identifies a process that was spawned by this module.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct ProcessId(u32);
+
 /// Will spawn a process. The process will be SIGTERM'd when `subprocess::terminate_all` is called
 /// (if it is still running by then).
 pub fn try_spawn(program: String, args: Vec<String>) -> Result<(), SystemError> {
+    spawn(program, args).map(|_| ())
+}
+
+/// Like `try_spawn()`, but if the `previous` process is still running, it gets terminated and waited
+/// for before the new process is spawned. This makes sure that commands of which only the last one
+/// matters take effect in the order they were issued.
+pub fn try_spawn_replacing(program: String, args: Vec<String>, previous: Option<ProcessId>) -> Result<ProcessId, SystemError> {
+    if let Some(previous) = previous {
+        MANAGER.lock().expect("Internal lock poisoned.").terminate_and_wait(previous);
+    }
+    spawn(program, args)
+}
+
+fn spawn(program: String, args: Vec<String>) -> Result<ProcessId, SystemError> {
     // Compute a printable version of the command, so we have something to show the
     // user in case an error happens.
     let printable_cmd: String = vec![program.clone()].into_iter().chain(args.iter().map(
@@ -48,12 +66,13 @@ pub fn try_spawn(program: String, args: Vec<String>) -> Result<(), SystemError>
         }
     };
 
+    let id = ProcessId(child.id());
     let process = Subprocess {
         child, printable_cmd
     };
 
     MANAGER.lock().expect("Internal lock poisoned.").add_process(process);
-    Ok(())
+    Ok(id)
 }
 
 struct SubprocessManager {
@@ -89,6 +108,14 @@ impl SubprocessManager {
             process.terminate();
         }
     }
+
+    /// Terminates a single subprocess if it is still running and waits until it has exited. Processes
+    /// that have already been cleaned up are not running anymore, so nothing needs to happen for them.
+    fn terminate_and_wait(&mut self, id: ProcessId) {
+        if let Some(index) = self.processes.iter().position(|process| process.child.id() == id.0) {
+            self.processes.remove(index).terminate_and_wait();
+        }
+    }
 }
 
 struct Subprocess {
@@ -133,6 +160,16 @@ impl Subprocess {
             std::thread::spawn(move || process.child.wait());
         }
     }
+
+    /// Like `terminate()`, but waits for the process to exit before returning.
+    pub fn terminate_and_wait(self) {
+        if let Some(mut process) = self.try_cleanup() {
+            unsafe { libc::kill(process.child.id() as i32, libc::SIGTERM) };
+            if let Err(error) = process.child.wait() {
+                eprintln!("Error while waiting on {}: {}", process.printable_cmd, error);
+            }
+        }
+    }
 }
 
 fn start_cleanup_thread() {
@@ -170,4 +207,11 @@ fn start_cleanup_thread() {
             }
         }
     });
-}
\ No newline at end of file
+}
+#[test]
+fn unittest() {
+    let first = try_spawn_replacing("sleep".to_owned(), vec!["10".to_owned()], None).unwrap();
+    try_spawn_replacing("true".to_owned(), Vec::new(), Some(first)).unwrap();
+    // The first process must have been terminated and reaped before the second one was spawned.
+    assert_eq!(unsafe { libc::kill(first.0 as i32, 0) }, -1);
+}
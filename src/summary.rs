@@ -0,0 +1,163 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! Implements the --summarize argument, which prints a routing table that shows for every event an
+//! input device can emit which events it can turn into and to which output devices those get written.
+//!
+//! The table is derived by running the capabilities of each event through the stream on its own, so
+//! it shows the same thing that evsieve uses to decide which capabilities the output devices get.
+//! Toggles are assumed to be possibly in any state, and side effects like exec-shell are not shown.
+
+use crate::capability::{Capabilities, Capability};
+use crate::domain::Domain;
+use crate::ecodes;
+use crate::event::{EventType, Namespace};
+use crate::stream::StreamEntry;
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SummaryFormat {
+    /// A plain-text table meant to be read in a terminal.
+    Text,
+    /// A table in the tbl format of troff, which can be included in a manpage.
+    Man,
+}
+
+/// A device whose events get summarized.
+pub struct SummaryInput {
+    /// How the device is shown to the user, usually its path.
+    pub name: String,
+    pub domain: Domain,
+    pub capabilities: Capabilities,
+}
+
+/// A device that events can get written to.
+pub struct SummaryOutput {
+    pub name: String,
+    pub domain: Domain,
+}
+
+/// One row of the routing table.
+struct Route {
+    input_name: String,
+    event_name: String,
+    /// Descriptions of the events that reach the output devices, grouped by output device.
+    destinations: Vec<String>,
+}
+
+pub fn summarize(stream: &[StreamEntry], inputs: &[SummaryInput], outputs: &[SummaryOutput], format: SummaryFormat) -> String {
+    let mut routes: Vec<Route> = Vec::new();
+    for input in inputs {
+        let mut caps = input.capabilities.to_vec_from_domain_and_namespace(input.domain, Namespace::Input);
+        caps.retain(|cap| cap.code.ev_type() != EventType::SYN);
+        caps.sort_by_key(|cap| cap.code);
+        for cap in caps {
+            routes.push(Route {
+                input_name: input.name.clone(),
+                event_name: ecodes::event_name(cap.code).into_owned(),
+                destinations: destinations_of(stream, &cap, outputs),
+            });
+        }
+    }
+
+    match format {
+        SummaryFormat::Text => format_text(&routes),
+        SummaryFormat::Man => format_man(&routes),
+    }
+}
+
+/// Returns for each output device that `cap` can reach which events it can become on that device,
+/// formatted like "key:b, key:c:1 on output 1 (Evsieve Virtual Device)".
+fn destinations_of(stream: &[StreamEntry], cap: &Capability, outputs: &[SummaryOutput]) -> Vec<String> {
+    let mut output_caps: Vec<Capability> = crate::stream::run_caps(stream, vec![cap.clone()]).into_iter()
+        .filter(|output_cap| output_cap.namespace == Namespace::Output)
+        .collect();
+    output_caps.sort_by_key(|output_cap| output_cap.code);
+
+    let mut destinations = Vec::new();
+    for (index, output) in outputs.iter().enumerate() {
+        let events: Vec<String> = output_caps.iter()
+            .filter(|output_cap| output_cap.domain == output.domain)
+            .map(|output_cap| {
+                let name = ecodes::event_name(output_cap.code);
+                // Only mention the values if the stream changed which values the event can have.
+                match output_cap.values == cap.values {
+                    true => name.into_owned(),
+                    false => format!("{}:{}", name, output_cap.values),
+                }
+            })
+            .collect();
+        if ! events.is_empty() {
+            destinations.push(format!("{} on output {} ({})", events.join(", "), index + 1, output.name));
+        }
+    }
+    destinations
+}
+
+fn format_text(routes: &[Route]) -> String {
+    let event_width = routes.iter().map(|route| route.event_name.len()).max().unwrap_or(0);
+    let mut result = String::new();
+    let mut last_input_name: Option<&str> = None;
+    for route in routes {
+        if last_input_name != Some(&route.input_name) {
+            result.push_str(&format!("{}:\n", route.input_name));
+            last_input_name = Some(&route.input_name);
+        }
+        let destinations = match route.destinations.is_empty() {
+            true => "(not written to any output device)".to_owned(),
+            false => route.destinations.join("; "),
+        };
+        result.push_str(&format!("    {:width$} -> {}\n", route.event_name, destinations, width = event_width));
+    }
+    if routes.is_empty() {
+        result.push_str("No input device can emit any events.\n");
+    }
+    result
+}
+
+fn format_man(routes: &[Route]) -> String {
+    // Keeps troff from interpreting backslashes, and lines starting with a dot as requests.
+    let escape = |text: &str| format!("\\&{}", text.replace('\\', "\\e"));
+    let mut result = String::from(".TS\nallbox;\nl l l.\nInput\tEvent\tOutput\n");
+    for route in routes {
+        let destinations = match route.destinations.is_empty() {
+            true => "none".to_owned(),
+            false => route.destinations.join("; "),
+        };
+        result.push_str(&format!(
+            "{}\t{}\t{}\n", escape(&route.input_name), escape(&route.event_name), escape(&destinations)
+        ));
+    }
+    result.push_str(".TE\n");
+    result
+}
+
+#[test]
+fn unittest() {
+    let args: Vec<String> = "--map key:a key:b --block key:c --copy key:d key:e:1 --output"
+        .split_whitespace().map(str::to_owned).collect();
+    let pre_implementation = crate::arguments::parser::process(args).unwrap();
+    // Make the events of the input device enter the stream like those of a real --input would.
+    let input_domain = crate::domain::get_unique_domain();
+    let mut stream_with_input = vec![StreamEntry::Map(crate::stream::map::Map::domain_shift(
+        input_domain, Namespace::Input, input_domain, Namespace::User,
+    ))];
+    stream_with_input.extend(pre_implementation.stream);
+
+    let mut capabilities = Capabilities::new();
+    for name in &["a", "c", "d"] {
+        capabilities.codes.insert(ecodes::event_code("key", name).unwrap());
+    }
+    let inputs = vec![SummaryInput { name: "/dev/input/keyboard".to_owned(), domain: input_domain, capabilities }];
+    let output_domain = pre_implementation.output_devices[0].domain;
+    let outputs = vec![SummaryOutput { name: "Evsieve Virtual Device".to_owned(), domain: output_domain }];
+
+    let text = summarize(&stream_with_input, &inputs, &outputs, SummaryFormat::Text);
+    // Events are sorted by their numeric codes, which is why key:d (32) comes before key:c (46).
+    assert_eq!(text, "/dev/input/keyboard:\n    \
+        key:a -> key:b on output 1 (Evsieve Virtual Device)\n    \
+        key:d -> key:e:1, key:d on output 1 (Evsieve Virtual Device)\n    \
+        key:c -> (not written to any output device)\n"
+    );
+    let man = summarize(&stream_with_input, &inputs, &outputs, SummaryFormat::Man);
+    assert!(man.starts_with(".TS\n"));
+    assert!(man.contains("\\&/dev/input/keyboard\t\\&key:c\t\\&none\n"));
+}
@@ -1,20 +1,41 @@
 use crate::arguments::parser::PreImplementation;
+use crate::domain::Domain;
 use crate::event::{Event, EventCode, EventType, Namespace};
 use crate::io::output::OutputSystem;
 use crate::key::KeyParser;
 use crate::stream::Setup;
+use crate::time::{Clock, Duration, Instant, VirtualClock};
 use std::fmt::Write;
 
+/// In the strings of input and output events, this token marks the end of a SYN report.
+const SYN_MARKER: &str = "syn";
+/// In the strings of input events, a token like "+100ms" advances the clock by that many milliseconds.
+const ADVANCE_PREFIX: &str = "+";
+const ADVANCE_SUFFIX: &str = "ms";
+/// In the strings of input events, a token like "~100ms" pretends the system was suspended for that many
+/// milliseconds, during which the clock stands still.
+const SUSPEND_PREFIX: &str = "~";
+
+/// Something that was written to the output system.
+#[derive(Clone, Copy)]
+pub(super) enum Received {
+    Event(Event),
+    Syn,
+}
+
 /// A replacement for the UInputSystem that does not actually write any events to any event devices,
 /// but instead keeps track of all events it received.
-struct VirtualOutputSystem {
-    received_events: Vec<Event>,
+pub(super) struct VirtualOutputSystem {
+    pub received: Vec<Received>,
+    /// Like the real output devices, a SYN is only written if some event was written since the last SYN.
+    should_syn: bool,
 }
 
 impl VirtualOutputSystem {
-    fn new() -> Self {
+    pub fn new() -> Self {
         Self {
-            received_events: Vec::new(),
+            received: Vec::new(),
+            should_syn: false,
         }
     }
 }
@@ -25,80 +46,232 @@ impl OutputSystem for &mut VirtualOutputSystem {
     }
 
     fn route_events(&mut self, events: &[Event]) {
-        self.received_events.extend_from_slice(events);
+        self.received.extend(events.iter().copied().map(Received::Event));
+        self.should_syn |= ! events.is_empty();
     }
 
     fn synchronize(&mut self) {
-        // Do nothing.
+        if self.should_syn {
+            self.received.push(Received::Syn);
+            self.should_syn = false;
+        }
     }
 }
 
-fn process_events(args: Vec<String>, events_in: Vec<Event>) -> Vec<Event> {
-    let PreImplementation { stream, input_devices, output_devices, control_fifo_paths, state, toggle_indices } =
+/// Something that happens to the stream during a test.
+enum Input {
+    /// A single SYN report of an input device.
+    Report(Vec<Event>),
+    /// The virtual clock moves forward, giving delayed events the chance to come out.
+    Advance(Duration),
+    /// The system gets suspended for some time, then resumes.
+    Suspend(Duration),
+}
+
+/// Arguments that get applied while the stream is running, as if by the apply command of a control FIFO.
+struct Patch {
+    at_stage: Option<String>,
+    args: Vec<String>,
+    /// What happens to the stream after the arguments have been applied.
+    inputs_after: Vec<Input>,
+}
+
+fn process_events(args: Vec<String>, inputs: Vec<Input>, patch: Option<Patch>, packet_domains: Vec<Domain>) -> Vec<Received> {
+    let PreImplementation { stream, input_devices, replay_devices: _, output_devices, control_fifos, state, toggle_indices, stage_names, sandbox_policy: _, run_as: _, stats_interval: _, status_file: _, journal_path: _, batch_latency: _, realtime: _, safe_mode: _, summary_format: _, introspect_sockets: _, argument_groups: _, shadows: _, shadow_sources: _ } =
         crate::arguments::parser::process(args)
         .expect("Failed to process the arguments.");
 
     // Tests are not supposed to include any I/O devices.
     assert!(input_devices.is_empty());
-    assert!(control_fifo_paths.is_empty());
-    // They do however include an output device, which will not actually be created.
-    let _ = output_devices;
+    assert!(control_fifos.is_empty());
+    // If they include output devices, those will not actually be created. Like the real output devices,
+    // only the events in the output namespace are considered written.
+    let has_output_devices = ! output_devices.is_empty();
 
     let mut output = VirtualOutputSystem::new();
     // TODO: Use more proper way of generating input capabilities?
     // Right now they're not used for anything but recreating output devices (which doesn't happen during tests),
     // but in the future they might get used for more.
     let input_capabilities = Default::default();
-    let mut setup = Setup::create(stream, &mut output, state, toggle_indices, input_capabilities);
-    run_stream(&mut setup, events_in);
+    let clock = VirtualClock::new(Instant::now());
+    let mut setup = Setup::create(stream, &mut output, state, toggle_indices, stage_names, input_capabilities, Box::new(clock.clone()));
+    setup.set_packet_domains(packet_domains);
+    run_stream(&mut setup, &clock, inputs);
+    if let Some(patch) = patch {
+        crate::arguments::parser::apply(&mut setup, patch.at_stage.as_deref(), patch.args, crate::sandbox::SandboxMode::Off)
+            .expect("Failed to apply the arguments.");
+        run_stream(&mut setup, &clock, patch.inputs_after);
+    }
 
-    output.received_events
+    if has_output_devices {
+        output.received.retain(|received| match received {
+            Received::Event(event) => event.namespace == Namespace::Output,
+            Received::Syn => true,
+        });
+    }
+    output.received
 }
 
-fn run_stream<T: OutputSystem>(setup: &mut Setup<T>, events_in: Vec<Event>) {
-    let now = crate::time::Instant::now();
-    for event in events_in {
-        setup.wakeup_until(now);
-        setup.run(now, event);
-        setup.syn();
+fn run_stream<T: OutputSystem>(setup: &mut Setup<T>, clock: &VirtualClock, inputs: Vec<Input>) {
+    for input in inputs {
+        match input {
+            Input::Report(report) => {
+                for event in report {
+                    setup.wakeup_until(clock.now());
+                    setup.run(clock.now(), event);
+                }
+                setup.syn();
+            },
+            Input::Advance(duration) => {
+                clock.advance(duration);
+                setup.wakeup_until(clock.now());
+            },
+            Input::Suspend(duration) => {
+                clock.suspend(duration);
+                setup.wakeup_until(clock.now());
+            },
+        }
     }
 }
 
+/// Parses a token like "+100ms", or returns None if the token is not meant to advance the clock.
+fn parse_advance(token: &str) -> Option<Duration> {
+    parse_millis(token, ADVANCE_PREFIX)
+}
+
+/// Parses a token like "~100ms", or returns None if the token is not meant to suspend the system.
+fn parse_suspend(token: &str) -> Option<Duration> {
+    parse_millis(token, SUSPEND_PREFIX)
+}
+
+fn parse_millis(token: &str, prefix: &str) -> Option<Duration> {
+    let millis_str = token.strip_prefix(prefix)?;
+    let millis_str = millis_str.strip_suffix(ADVANCE_SUFFIX).expect("Malformed duration: it must look like \"+100ms\".");
+    let millis = millis_str.parse().expect("Malformed duration: it must look like \"+100ms\".");
+    Some(Duration::from_millis(millis))
+}
+
 struct EventPairResult<'a> {
     expected: Option<&'a str>,
-    received: Option<Event>,
+    received: Option<Received>,
     matches: bool,
 }
 
 /// For convenience we pass the arguments, input events and output events are all passed as a single string that will
 /// be split by whitespace. No --input or --output argument needs to be present.
+///
+/// The input and output strings may contain "syn" markers. If the input contains them, then all events between two
+/// markers are treated as a single report, otherwise every input event is followed by a SYN. If the output contains
+/// them, then the output events must be synchronised at exactly the marked places, otherwise SYN events are ignored.
+///
+/// The stream runs on a virtual clock that stands still unless the input contains a token like "+100ms", which
+/// advances the clock by that many milliseconds. Such a token also ends the current report. Likewise, a token
+/// like "~100ms" pretends that the system was suspended for that many milliseconds.
 /// 
 /// TODO: consider shellexing the string instead of splitting by whitespace.
 pub fn run_test(args: &str, events_in: &str, events_out: &str) {
+    run_test_impl(args, events_in, Namespace::User, None, events_out, false);
+}
+
+/// Like `run_test()`, but the input events are treated as if they came from input devices with the
+/// packets flag, so each report enters the stream as a whole.
+pub fn run_packet_test(args: &str, events_in: &str, events_out: &str) {
+    run_test_impl(args, events_in, Namespace::User, None, events_out, true);
+}
+
+/// Like `run_test()`, but the input events enter the stream in the resync namespace, as if libevdev
+/// generated them to resynchronise an input device with resync=isolate.
+pub fn run_resync_test(args: &str, events_in: &str, events_out: &str) {
+    run_test_impl(args, events_in, Namespace::Resync, None, events_out, false);
+}
+
+/// Like `run_test()`, but after the events `events_in` have been processed, `apply_args` get applied after
+/// the given stage as if by the apply command of a control FIFO, and then `events_after` get processed.
+pub fn run_apply_test(args: &str, events_in: &str, at_stage: Option<&str>, apply_args: &str, events_after: &str, events_out: &str) {
+    run_test_impl(args, events_in, Namespace::User, Some((at_stage, apply_args, events_after)), events_out, false);
+}
+
+/// Tries to apply arguments to a running stream without processing any events.
+pub fn try_apply(args: &str, apply_args: &str) -> Result<(), crate::error::RuntimeError> {
+    let to_vec = |string: &str| string.split_whitespace().map(str::to_owned).collect::<Vec<String>>();
+    let PreImplementation { stream, state, toggle_indices, stage_names, .. } =
+        crate::arguments::parser::process(to_vec(args)).expect("Failed to process the arguments.");
+    let mut output = VirtualOutputSystem::new();
+    let clock = VirtualClock::new(Instant::now());
+    let mut setup = Setup::create(stream, &mut output, state, toggle_indices, stage_names, Default::default(), Box::new(clock));
+    crate::arguments::parser::apply(&mut setup, None, to_vec(apply_args), crate::sandbox::SandboxMode::Off)
+}
+
+fn run_test_impl(args: &str, events_in: &str, namespace_in: Namespace, patch: Option<(Option<&str>, &str, &str)>, events_out: &str, packets: bool) {
     let to_vec = |string: &str| string.split_whitespace().filter(|x| !x.is_empty()).map(str::to_owned).collect::<Vec<String>>();
     let args: Vec<String> = to_vec(args);
 
-    let prototype_event = Event::new(EventCode::new(EventType::KEY, 0), 0, 0, crate::domain::get_unique_domain(), Namespace::User);
-    let keys_in  = KeyParser::default_mask().parse_all(&to_vec(events_in)).expect("Malformed input event.");
-    let events_in: Vec<Event> = keys_in.into_iter().map(|key| key.merge(prototype_event)).collect();
+    let prototype_event = Event::new(EventCode::new(EventType::KEY, 0), 0, 0, crate::domain::get_unique_domain(), namespace_in);
+    let mut key_in_parser = KeyParser::default_mask();
+    key_in_parser.with_namespace(namespace_in);
+    let parse_event_in = |key_str: &String| key_in_parser.parse(key_str).expect("Malformed input event.").merge(prototype_event);
+    let parse_inputs = |events_in: &str| -> Vec<Input> {
+        let key_strs_in = to_vec(events_in);
+        let has_syn_markers = key_strs_in.iter().any(|key_str| key_str == SYN_MARKER);
+        let mut inputs = Vec::new();
+        let mut report = Vec::new();
+        for key_str in &key_strs_in {
+            if key_str == SYN_MARKER {
+                inputs.push(Input::Report(std::mem::take(&mut report)));
+            } else if let Some(duration) = parse_advance(key_str) {
+                inputs.push(Input::Report(std::mem::take(&mut report)));
+                inputs.push(Input::Advance(duration));
+            } else if let Some(duration) = parse_suspend(key_str) {
+                inputs.push(Input::Report(std::mem::take(&mut report)));
+                inputs.push(Input::Suspend(duration));
+            } else if has_syn_markers {
+                report.push(parse_event_in(key_str));
+            } else {
+                inputs.push(Input::Report(vec![parse_event_in(key_str)]));
+            }
+        }
+        inputs.push(Input::Report(report));
+        inputs.retain(|input| ! matches!(input, Input::Report(report) if report.is_empty()));
+        inputs
+    };
+    let inputs = parse_inputs(events_in);
+    let patch = patch.map(|(at_stage, apply_args, events_after)| Patch {
+        at_stage: at_stage.map(str::to_owned),
+        args: to_vec(apply_args),
+        inputs_after: parse_inputs(events_after),
+    });
 
     let keys_out_str = to_vec(events_out);
     let key_out_parser = KeyParser::default_filter();
-    let events_out = process_events(args, events_in);
+    let mut packet_domains: Vec<Domain> = Vec::new();
+    if packets {
+        for input in &inputs {
+            if let Input::Report(report) = input {
+                packet_domains.extend(report.iter().map(|event| event.domain));
+            }
+        }
+    }
+    let mut received_out = process_events(args, inputs, patch, packet_domains);
+    if ! keys_out_str.iter().any(|key_str| key_str == SYN_MARKER) {
+        received_out.retain(|received| matches!(received, Received::Event(_)));
+    }
     let mut result: Vec<EventPairResult> = Vec::new();
 
-    for i in 0 .. usize::max(events_out.len(), keys_out_str.len()) {
-        let event = events_out.get(i);
+    for i in 0 .. usize::max(received_out.len(), keys_out_str.len()) {
+        let received = received_out.get(i);
         let key_str = keys_out_str.get(i);
-        let key = key_str.map(|x| key_out_parser.parse(x).expect("Malformed output event"));
-        let matches = match (event, key) {
-            (Some(event), Some(key)) => key.matches(event),
+        let matches = match (received, key_str) {
+            (Some(Received::Syn), Some(key_str)) => key_str == SYN_MARKER,
+            (Some(Received::Event(_)), Some(key_str)) if key_str == SYN_MARKER => false,
+            (Some(Received::Event(event)), Some(key_str)) =>
+                key_out_parser.parse(key_str).expect("Malformed output event")
+                    .matches(&Event { namespace: Namespace::User, ..*event }),
             _ => false,
         };
 
         result.push(EventPairResult {
             expected: key_str.map(String::as_str),
-            received: event.copied(),
+            received: received.copied(),
             matches
         });
     }
@@ -119,7 +292,8 @@ fn create_report(results: &[EventPairResult]) -> String {
             None => "(none)".to_string(),
         };
         let received = match res.received {
-            Some(event) => event.to_string(),
+            Some(Received::Event(event)) => event.to_string(),
+            Some(Received::Syn) => SYN_MARKER.to_string(),
             None => "(none)".to_string(),
         };
         let status_indicator = match res.matches {
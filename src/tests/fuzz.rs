@@ -0,0 +1,212 @@
+//! Runs randomly generated combinations of arguments and events through the stream and checks that
+//! certain invariants hold for all of them:
+//!
+//! * processing the events does not panic;
+//! * every key that was pressed on the output is eventually released if all input keys are released;
+//! * every event written to the output lies within the capabilities that were computed for the output.
+//!
+//! The amount of cases can be increased with the EVSIEVE_FUZZ_CASES environment variable. If a case
+//! fails, its seed is reported and can be reproduced with the EVSIEVE_FUZZ_SEED environment variable.
+
+use std::collections::HashMap;
+use std::panic::AssertUnwindSafe;
+
+use super::framework::{Received, VirtualOutputSystem};
+use crate::arguments::parser::PreImplementation;
+use crate::capability::Capability;
+use crate::domain::Domain;
+use crate::event::{Channel, Event, EventCode, Namespace};
+use crate::range::{Interval, Set};
+use crate::stream::Setup;
+use crate::time::{Clock, Duration, Instant, VirtualClock};
+
+const DEFAULT_NUM_CASES: u64 = 200;
+const MAX_ARGS_PER_CASE: usize = 5;
+const MAX_EVENTS_PER_CASE: usize = 40;
+
+const KEY_NAMES: &[&str] = &["a", "b", "c", "d", "e", "f", "g", "h"];
+const DOMAIN_NAMES: &[&str] = &["fuzz-a", "fuzz-b"];
+
+/// Arguments that keep the output balanced as long as the input is balanced.
+const ARGUMENT_POOL: &[&str] = &[
+    "--map key:a key:b",
+    "--map key:b key:c key:d",
+    "--map key:c@fuzz-a key:e@fuzz-b",
+    "--copy key:d key:f",
+    "--block key:e",
+    "--block @fuzz-b",
+    "--toggle key:a key:g key:h",
+    "--toggle key:f@fuzz-a @fuzz-a @fuzz-b",
+    "--hook key:g key:h toggle",
+    "--hook key:a key:c send-key=key:h",
+    "--hook key:b key:d --withhold",
+    "--hook key:f sequential key:g --withhold key:f",
+    "--merge",
+    "--merge key:h",
+    "--delay key:c period=0.01",
+];
+
+/// A xorshift64* pseudorandom number generator. It is not of high quality, but good enough for
+/// generating test cases and it does not require any dependencies.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        // The state of a xorshift generator must not be zero.
+        Rng(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+
+    fn choose<'a, T>(&mut self, items: &'a [T]) -> &'a T {
+        &items[self.below(items.len())]
+    }
+}
+
+struct Case {
+    args: Vec<String>,
+    events: Vec<Event>,
+}
+
+fn generate_case(rng: &mut Rng, channels: &[Channel]) -> Case {
+    let num_args = 1 + rng.below(MAX_ARGS_PER_CASE);
+    let args: Vec<String> = (0 .. num_args)
+        .flat_map(|_| rng.choose(ARGUMENT_POOL).split_whitespace())
+        .map(str::to_owned)
+        .collect();
+
+    // Randomly press and release keys, then release all keys that are still pressed.
+    let mut pressed: Vec<Channel> = Vec::new();
+    let mut events = Vec::new();
+    let num_events = rng.below(MAX_EVENTS_PER_CASE);
+    for _ in 0 .. num_events {
+        let channel = *rng.choose(channels);
+        let value = match pressed.iter().position(|&other| other == channel) {
+            Some(index) => { pressed.remove(index); 0 },
+            None => { pressed.push(channel); 1 },
+        };
+        events.push(key_event(channel, value));
+    }
+    for channel in pressed.into_iter().rev() {
+        events.push(key_event(channel, 0));
+    }
+
+    Case { args, events }
+}
+
+fn key_event((code, domain): Channel, value: i32) -> Event {
+    Event::new(code, value, 1 - value, domain, Namespace::User)
+}
+
+/// Returns the events written to the output and the capabilities that the stream claims the output has.
+fn run_case(case: &Case, input_caps: Vec<Capability>) -> (Vec<Event>, Vec<Capability>) {
+    let PreImplementation { stream, state, toggle_indices, stage_names, .. } =
+        crate::arguments::parser::process(case.args.clone())
+        .expect("Failed to process the arguments.");
+
+    let output_caps = crate::stream::run_caps(&stream, input_caps);
+    let mut output = VirtualOutputSystem::new();
+    let clock = VirtualClock::new(Instant::now());
+    let mut setup = Setup::create(stream, &mut output, state, toggle_indices, stage_names, Default::default(), Box::new(clock.clone()));
+
+    for &event in &case.events {
+        clock.advance(Duration::from_millis(1));
+        setup.wakeup_until(clock.now());
+        setup.run(clock.now(), event);
+        setup.syn();
+    }
+    // Give delayed events the chance to come out.
+    clock.advance(Duration::from_secs(10));
+    setup.wakeup_until(clock.now());
+    setup.syn();
+
+    let events = output.received.into_iter()
+        .filter_map(|received| match received {
+            Received::Event(event) => Some(event),
+            Received::Syn => None,
+        })
+        .collect();
+    (events, output_caps)
+}
+
+/// Returns a description of the first invariant that is violated, if any.
+fn check_invariants(events: &[Event], output_caps: &[Capability]) -> Option<String> {
+    for event in events {
+        let is_capable = output_caps.iter().any(|cap|
+            cap.code == event.code && cap.domain == event.domain && cap.values.contains(event.value)
+        );
+        if ! is_capable {
+            return Some(format!("The event {} was written, but the output is not capable of it.", event));
+        }
+    }
+
+    let mut last_values: HashMap<Channel, i32> = HashMap::new();
+    for event in events.iter().filter(|event| event.ev_type().is_key()) {
+        last_values.insert(event.channel(), event.value);
+    }
+    for ((code, domain), value) in last_values {
+        if value != 0 {
+            return Some(format!(
+                "The key {} was not released at the end.",
+                Event::new(code, value, 0, domain, Namespace::User)
+            ));
+        }
+    }
+
+    None
+}
+
+#[test]
+fn fuzz_stream() {
+    let num_cases: u64 = match std::env::var("EVSIEVE_FUZZ_CASES") {
+        Ok(value) => value.parse().expect("EVSIEVE_FUZZ_CASES must be a number."),
+        Err(_) => DEFAULT_NUM_CASES,
+    };
+    let seeds: Vec<u64> = match std::env::var("EVSIEVE_FUZZ_SEED") {
+        Ok(value) => vec![value.parse().expect("EVSIEVE_FUZZ_SEED must be a number.")],
+        Err(_) => (0 .. num_cases).collect(),
+    };
+
+    let domains: Vec<Domain> = DOMAIN_NAMES.iter()
+        .map(|name| crate::domain::resolve(name).unwrap())
+        .collect();
+    let codes: Vec<EventCode> = KEY_NAMES.iter()
+        .map(|name| crate::ecodes::event_code("key", name).unwrap())
+        .collect();
+    let channels: Vec<Channel> = domains.iter()
+        .flat_map(|&domain| codes.iter().map(move |&code| (code, domain)))
+        .collect();
+    let input_caps: Vec<Capability> = channels.iter().map(|&(code, domain)| Capability {
+        code, domain,
+        namespace: Namespace::User,
+        values: Set::from(Interval::new(0, 2)),
+        abs_meta: None,
+    }).collect();
+
+    for seed in seeds {
+        let case = generate_case(&mut Rng::new(seed), &channels);
+        let describe = || format!(
+            "Fuzz case with seed {} failed.\nArguments: {}\nEvents: {}",
+            seed, case.args.join(" "),
+            case.events.iter().map(|event| event.to_string()).collect::<Vec<_>>().join(" "),
+        );
+
+        let result = std::panic::catch_unwind(AssertUnwindSafe(|| run_case(&case, input_caps.clone())));
+        let (events, output_caps) = match result {
+            Ok(result) => result,
+            Err(_) => panic!("{}\nProcessing the events panicked.", describe()),
+        };
+        if let Some(violation) = check_invariants(&events, &output_caps) {
+            panic!("{}\n{}", describe(), violation);
+        }
+    }
+}
@@ -1,5 +1,10 @@
 mod framework;
+mod fuzz;
 pub use framework::run_test;
+use framework::run_apply_test;
+use framework::run_packet_test;
+use framework::run_resync_test;
+use framework::try_apply;
 
 #[test]
 fn rudimentary_test() {
@@ -54,6 +59,64 @@ fn test_withhold_with_two_hooks() {
     )
 }
 
+#[test]
+fn test_withhold_device_lost() {
+    // The trackers of the lost device are reset, so key:a no longer counts as held.
+    run_test(
+        "--hook key:a key:b --withhold",
+        "key:a:1@kb1 evsieve:device-lost:1@kb1 evsieve:device-lost:0@kb1 key:b:1@kb1 key:b:0@kb1",
+        "evsieve:device-lost:1@kb1 evsieve:device-lost:0@kb1 key:b:1@kb1 key:b:0@kb1",
+    );
+    // Withheld events of the lost device are either dropped or released, but those of other devices remain withheld.
+    run_test(
+        "--hook key:a key:b --withhold",
+        "key:a:1@kb1 key:a:1@kb2 evsieve:device-lost:1@kb1 evsieve:device-lost:0@kb1 key:a:0@kb2",
+        "evsieve:device-lost:1@kb1 evsieve:device-lost:0@kb1 key:a:1@kb2 key:a:0@kb2",
+    );
+    run_test(
+        "--hook key:a key:b --withhold device-lost=flush",
+        "key:a:1@kb1 key:a:1@kb2 evsieve:device-lost:1@kb1 evsieve:device-lost:0@kb1 key:a:0@kb2",
+        "key:a:1@kb1 key:a:0@kb1 evsieve:device-lost:1@kb1 evsieve:device-lost:0@kb1 key:a:1@kb2 key:a:0@kb2",
+    );
+}
+
+#[test]
+fn test_withhold_passthrough_on_activate() {
+    // The withheld key:a event fires along with the hook, while key:b is consumed as usual.
+    run_test(
+        "--hook key:a key:b send-key=key:x --withhold passthrough-on-activate=key:a",
+        "key:a:1 key:b:1 key:a:0 key:b:0",
+        "key:a:1 key:x:1 key:x:0 key:a:0",
+    );
+    // Without a value, all withheld events pass through.
+    run_test(
+        "--hook key:a key:b send-key=key:x --withhold passthrough-on-activate",
+        "key:a:1 key:b:1 key:b:0 key:a:0",
+        "key:a:1 key:b:1 key:x:1 key:x:0 key:b:0 key:a:0",
+    );
+    // Events that do not activate the hook are not affected.
+    run_test(
+        "--hook key:a key:b send-key=key:x --withhold passthrough-on-activate=key:a",
+        "key:a:1 key:a:0",
+        "key:a:1 key:a:0",
+    );
+}
+
+#[test]
+fn test_withhold_debug() {
+    // The debug flag only prints the transitions of the channels; it does not change which events come out.
+    run_test(
+        "--hook key:a key:b send-key=key:x --withhold debug",
+        "key:a:1 key:a:1@kb key:b:1 key:a:0 key:b:0 key:a:0@kb key:c:1 key:a:1 key:a:0",
+        "key:x:1 key:x:0 key:c:1 key:a:1 key:a:0",
+    );
+    run_test(
+        "--hook key:a key:b period=0.1 --withhold debug passthrough-on-activate device-lost=flush",
+        "key:a:1 +100ms key:a:0 key:b:1@kb evsieve:device-lost:1@kb",
+        "key:a:1 key:a:0 key:b:1@kb key:b:0@kb evsieve:device-lost:1@kb",
+    );
+}
+
 #[test]
 fn test_withhold_with_three_trackers() {
     run_test(
@@ -327,7 +390,8 @@ fn test_withhold_for_channelless_hooks() {
     // the key:a:1@bar event can be immediately released as well.
     //
     // These are stupid semantics which are only retained for backwards compatibility. In a
-    // hypothetical evsieve 2.0, they should be fixed.
+    // hypothetical evsieve 2.0, they should be fixed. Until then, the strict-channels flag
+    // opts into the fixed semantics; see test_strict_channels.
     run_test(
         // Arguments
         "
@@ -344,3 +408,1150 @@ fn test_withhold_for_channelless_hooks() {
         "
     )
 }
+
+#[test]
+fn test_strict_channels() {
+    // With strict channels, a tracker only deactivates once all channels that pressed it are released.
+    run_test(
+        // Arguments
+        "
+        --hook key:a key:b strict-channels
+        --withhold
+        ",
+        // Input
+        "
+        key:a:1@foo key:a:1@bar key:z:1 key:a:0@foo key:z:0 key:a:0@bar
+        ",
+        // Output
+        "
+        key:z:1 key:a:1@foo key:a:0@foo key:z:0 key:a:1@bar key:a:0@bar
+        "
+    );
+    run_test(
+        "--hook key:a key:b strict-channels --withhold key:a",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:b:1 key:b:0 key:a:0@bar",
+        "key:a:1@foo key:a:0@foo key:b:1 key:b:0",
+    );
+    // The strict-channels flag on --withhold applies to all preceding hooks.
+    run_test(
+        "--hook key:a key:b --withhold strict-channels",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:a:0@bar",
+        "key:a:1@foo key:a:0@foo key:a:1@bar key:a:0@bar",
+    );
+    run_test(
+        "--hook key:a key:b send-key=key:x strict-channels",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:b:1",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:b:1 key:x:1",
+    );
+    run_test(
+        "--hook key:a key:b send-key=key:x",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:b:1",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:b:1",
+    );
+}
+
+#[test]
+fn test_domain_expressions() {
+    run_test(
+        "--map key:a@expr-foo* key:b --map key:c@expr-foo1&expr-f?o1 @expr-bar",
+        "key:a:1@expr-foo1 key:a:1@expr-foo2 key:a:1@expr-baz key:c:1@expr-foo1",
+        "key:b:1@expr-foo1 key:b:1@expr-foo2 key:a:1@expr-baz key:c:1@expr-bar",
+    );
+}
+
+#[test]
+fn test_curve() {
+    run_test(
+        "--curve abs:x points=0:0,8000:2000,32767:32767",
+        "abs:x:0 abs:x:4000 abs:x:8000 abs:x:20383 abs:x:32767 abs:x:-5 abs:y:4000",
+        "abs:x:0 abs:x:1000 abs:x:2000 abs:x:17383 abs:x:32767 abs:x:0 abs:y:4000",
+    );
+    run_test(
+        "--curve abs:x:0~99 points=-100:0,100:200",
+        "abs:x:-100 abs:x:0 abs:x:50 abs:x:150 abs:x:100",
+        "abs:x:-100 abs:x:100 abs:x:150 abs:x:150 abs:x:100",
+    );
+}
+
+#[test]
+fn test_map_crossing() {
+    run_test(
+        // Arguments
+        "
+        --map abs:y:crosses=0:+ key:up:1
+        --map abs:x:crosses=-5~5 key:left:1
+        ",
+        // Input
+        "
+        abs:y:-10 abs:y:-3 abs:y:3 abs:y:8 abs:y:-2 abs:y:7
+        abs:x:-8 abs:x:2 abs:x:6 abs:x:-3 abs:x:-6
+        ",
+        // Output
+        "
+        abs:y:-10 abs:y:-3 key:up:1 abs:y:8 abs:y:-2 key:up:1
+        abs:x:-8 abs:x:2 key:left:1 abs:x:-3 key:left:1
+        "
+    )
+}
+
+#[test]
+fn test_invert_scroll() {
+    run_test(
+        // Arguments
+        "
+        --invert-scroll @mouse id=natural
+        --hook key:f1 toggle=natural:2
+        --hook key:f2 toggle=natural:1
+        ",
+        // Input
+        "
+        rel:wheel:1@mouse rel:hwheel_hi_res:120@mouse rel:wheel:1@other rel:x:1@mouse
+        key:f1:1 key:f1:0 rel:wheel:1@mouse
+        key:f2:1 key:f2:0 rel:wheel:-1@mouse
+        ",
+        // Output
+        "
+        rel:wheel:-1@mouse rel:hwheel_hi_res:-120@mouse rel:wheel:1@other rel:x:1@mouse
+        key:f1:1 key:f1:0 rel:wheel:1@mouse
+        key:f2:1 key:f2:0 rel:wheel:1@mouse
+        "
+    )
+}
+
+#[test]
+fn test_wildcard_codes() {
+    run_test(
+        "--map btn:trigger_happy* key:a --map key:f?:1 key:b:1",
+        "btn:trigger_happy1:1 btn:trigger_happy12:0 btn:left:1 key:f1:1 key:f10:1 key:f2:0",
+        "key:a:1 key:a:0 btn:left:1 key:b:1 key:f10:1 key:f2:0",
+    );
+    run_test(
+        "--block key:kp*",
+        "key:kp1:1 key:kpplus:1 key:a:1",
+        "key:a:1",
+    );
+}
+
+#[test]
+fn test_multiple_sources() {
+    run_test(
+        "--map key:a key:b key:c -> key:x --map key:x key:y",
+        "key:a:1 key:a:0 key:b:1 key:d:1 key:c:0",
+        "key:y:1 key:y:0 key:y:1 key:d:1 key:y:0",
+    );
+    run_test(
+        "--copy key:a key:b -> key:x:1@bar",
+        "key:a:1@foo key:b:0@foo key:c:1@foo",
+        "key:a:1@foo key:x:1@bar key:b:0@foo key:x:1@bar key:c:1@foo",
+    );
+    run_test(
+        "--map key:a key:b ->",
+        "key:a:1 key:b:1 key:c:1",
+        "key:c:1",
+    );
+    // The output of the map is not mapped again by its later source keys.
+    run_test(
+        "--map key:a key:b -> key:b key:x",
+        "key:a:1 key:a:0 key:b:1",
+        "key:b:1 key:x:1 key:b:0 key:x:0 key:b:1 key:x:1",
+    );
+    // Events matching several source keys are mapped once.
+    run_test(
+        "--copy key:a key:a:1 -> key:x",
+        "key:a:1 key:a:0",
+        "key:a:1 key:x:1 key:a:0 key:x:0",
+    );
+}
+
+#[test]
+fn test_map_sampling() {
+    run_test(
+        "--map key:a:1 key:b:1 nth=3",
+        "key:a:1 key:a:1 key:a:1 key:a:1 key:a:1 key:a:1 key:c:1",
+        "key:a:1 key:a:1 key:b:1 key:a:1 key:a:1 key:b:1 key:c:1",
+    );
+    run_test(
+        "--map key:a key:b chance=1 --map key:c key:d chance=0",
+        "key:a:1 key:c:1 key:a:0 key:c:0",
+        "key:b:1 key:c:1 key:b:0 key:c:0",
+    );
+    // Repeats and releases are mapped the same way as the press they belong to.
+    run_test(
+        "--map key:a key:b nth=2",
+        "key:a:1 key:a:2 key:a:0 key:a:1 key:a:2 key:a:0 key:a:1 key:a:0",
+        "key:a:1 key:a:2 key:a:0 key:b:1 key:b:2 key:b:0 key:a:1 key:a:0",
+    );
+    run_test(
+        "--map key:a key:b nth=2",
+        "key:a:1@foo key:a:1@bar key:a:0@foo key:a:0@bar",
+        "key:a:1@foo key:b:1@bar key:a:0@foo key:b:0@bar",
+    );
+    // Events of other types are sampled individually.
+    run_test(
+        "--map rel:x rel:y nth=2",
+        "rel:x:1 rel:x:1 rel:x:1 rel:x:1",
+        "rel:x:1 rel:y:1 rel:x:1 rel:y:1",
+    );
+}
+
+#[test]
+fn test_hook_accumulate() {
+    run_test(
+        "--hook rel:wheel accumulate=3 send-key=key:volumeup",
+        "rel:wheel:1 rel:wheel:1 rel:wheel:1 rel:wheel:2 rel:wheel:1",
+        "rel:wheel:1 rel:wheel:1 rel:wheel:1 key:volumeup:1 key:volumeup:0 rel:wheel:2 rel:wheel:1 key:volumeup:1 key:volumeup:0",
+    );
+    run_test(
+        // Scrolling in the other direction resets the total.
+        "--hook rel:wheel:~-1 accumulate=2 send-key=key:volumedown",
+        "rel:wheel:-1 rel:wheel:1 rel:wheel:-1 rel:wheel:-1",
+        "rel:wheel:-1 rel:wheel:1 rel:wheel:-1 rel:wheel:-1 key:volumedown:1 key:volumedown:0",
+    );
+    run_test(
+        "--hook key:leftctrl rel:wheel accumulate=2 send-key=key:f1",
+        "rel:wheel:1 rel:wheel:1 key:leftctrl:1 rel:wheel:1 rel:wheel:1 rel:wheel:1 rel:wheel:1 key:leftctrl:0 rel:wheel:1 rel:wheel:1",
+        "rel:wheel:1 rel:wheel:1 key:leftctrl:1 rel:wheel:1 rel:wheel:1 key:f1:1 key:f1:0 rel:wheel:1 rel:wheel:1 key:f1:1 key:f1:0 key:leftctrl:0 rel:wheel:1 rel:wheel:1",
+    );
+}
+
+#[test]
+fn test_hook_burst() {
+    run_test(
+        "--hook key:f1 send-key=key:right:x3",
+        "key:f1:1 key:f1:0",
+        "key:f1:1 key:right:1 key:right:0 key:right:1 key:right:0 key:right:1 key:right:0 key:f1:0",
+    );
+    run_test(
+        "--hook key:f1 send-event=rel:x:5:x2 send-key=key:a",
+        "key:f1:1 key:f1:0",
+        "key:f1:1 rel:x:5 rel:x:5 key:a:1 key:a:0 key:f1:0",
+    );
+}
+
+#[test]
+fn test_sequence() {
+    run_test(
+        "--sequence key:leftmeta key:g send-key=key:f5",
+        "key:leftmeta:1 key:leftmeta:0 key:g:1 key:g:0 key:a:1 key:a:0",
+        "key:f5:1 key:f5:0 key:a:1 key:a:0",
+    );
+    run_test(
+        "--sequence key:leftmeta key:g send-key=key:f5",
+        "key:leftmeta:1 key:x:1 key:leftmeta:0 key:x:0",
+        "key:leftmeta:1 key:x:1 key:leftmeta:0 key:x:0",
+    );
+}
+
+#[test]
+fn test_enabled_when() {
+    run_test(
+        // Arguments
+        "
+        --map key:a key:b enabled-when=mode:2
+        --block key:c enabled-when=mode:1
+        --hook key:f1 toggle=mode:2
+        --hook key:f2 toggle=mode:1
+        --toggle @unused @unused @unused id=mode
+        ",
+        // Input
+        "
+        key:a:1 key:a:0 key:c:1 key:c:0
+        key:f1:1 key:f1:0 key:a:1 key:a:0 key:c:1 key:c:0
+        key:f2:1 key:f2:0 key:a:1 key:a:0 key:c:1 key:c:0
+        ",
+        // Output
+        "
+        key:a:1 key:a:0
+        key:f1:1 key:f1:0 key:b:1 key:b:0 key:c:1 key:c:0
+        key:f2:1 key:f2:0 key:a:1 key:a:0
+        "
+    )
+}
+
+#[test]
+fn test_toggle_follows() {
+    run_test(
+        // Arguments
+        "
+        --hook key:f1 toggle=mode
+        --toggle @unused @unused @unused @unused id=mode
+        --toggle key:a key:a key:b follows=mode follow-states=1,2,2
+        --toggle key:c key:c key:d follows=mode
+        ",
+        // Input
+        "
+        key:a:1 key:a:0 key:c:1 key:c:0
+        key:f1:1 key:f1:0 key:a:1 key:a:0 key:c:1 key:c:0
+        key:f1:1 key:f1:0 key:a:1 key:a:0 key:c:1 key:c:0
+        key:f1:1 key:f1:0 key:a:1 key:a:0 key:c:1 key:c:0
+        ",
+        // Output
+        "
+        key:a:1 key:a:0 key:c:1 key:c:0
+        key:f1:1 key:f1:0 key:b:1 key:b:0 key:d:1 key:d:0
+        key:f1:1 key:f1:0 key:b:1 key:b:0 key:c:1 key:c:0
+        key:f1:1 key:f1:0 key:a:1 key:a:0 key:c:1 key:c:0
+        "
+    )
+}
+
+#[test]
+fn test_enabled_when_led() {
+    run_test(
+        "
+        --map key:a key:b enabled-when=led:capsl:1
+        --block key:c enabled-when=led:capsl:0
+        --block led
+        ",
+        "
+        key:a:1 key:a:0 key:c:1 key:c:0
+        led:capsl:1 key:a:1 key:a:0 key:c:1 key:c:0
+        led:capsl:0 key:a:1 key:a:0
+        ",
+        "
+        key:a:1 key:a:0
+        key:b:1 key:b:0 key:c:1 key:c:0
+        key:a:1 key:a:0
+        "
+    );
+    // Hooks can be made conditional on the LEDs of a specific device as well.
+    run_test(
+        "
+        --hook key:a send-key=key:x enabled-when=led:numl:1@kb
+        --block led
+        ",
+        "
+        led:numl:1@other key:a:1@kb key:a:0@kb
+        led:numl:1@kb key:a:1@kb key:a:0@kb
+        ",
+        "
+        key:a:1 key:a:0
+        key:a:1 key:x:1 key:x:0 key:a:0
+        ",
+    );
+}
+
+#[test]
+fn test_macro_slot() {
+    run_test(
+        // Arguments
+        "
+        --hook key:f9 record-start=1
+        --hook key:f10 record-stop
+        --hook key:f11 play=1
+        --macro-slot key
+        ",
+        // Input
+        "
+        key:a:1 key:a:0
+        key:f9:1 key:f9:0 key:b:1 rel:x:1 key:b:0 key:f10:1 key:f10:0
+        key:f11:1 key:f11:0 key:c:1
+        ",
+        // Output. The replayed events are scheduled after the key:f11:1 event and
+        // come out as soon as the stream wakes up to handle the next event.
+        "
+        key:a:1 key:a:0
+        key:f9:1 key:f9:0 key:b:1 rel:x:1 key:b:0 key:f10:1 key:f10:0
+        key:f11:1 key:b:1 key:b:0 key:f11:0 key:c:1
+        "
+    );
+    // Hooks placed after the --macro-slot get their commands carried out right away as well, so the
+    // replayed events do not wait until the key:f11:0 event reaches the --macro-slot.
+    run_test(
+        "
+        --macro-slot key
+        --hook key:f9 record-start=1
+        --hook key:f10 record-stop
+        --hook key:f11 play=1
+        ",
+        "
+        key:f9:1 key:f9:0 key:b:1 key:b:0 key:f10:1 key:f10:0
+        key:f11:1 key:f11:0 key:c:1
+        ",
+        "
+        key:f9:1 key:f9:0 key:b:1 key:b:0 key:f10:1 key:f10:0
+        key:f11:1 key:b:1 key:b:0 key:f11:0 key:c:1
+        "
+    );
+    // The hook keys are blocked before they reach the --macro-slot, and a stage makes the events pass
+    // through stream entries one at a time. The commands must still be carried out right away.
+    run_test(
+        "
+        --stage macros
+        --hook key:f9 record-start=1
+        --hook key:f10 record-stop
+        --hook key:f11 play=1
+        --block key:f9 key:f10 key:f11
+        --macro-slot key
+        ",
+        "
+        key:f9:1 key:f9:0 key:b:1 key:b:0 key:f10:1 key:f10:0
+        key:f11:1 key:f11:0 key:c:1
+        ",
+        "
+        key:b:1 key:b:0
+        key:b:1 key:b:0 key:c:1
+        "
+    );
+}
+
+#[test]
+fn test_quirk_touchpad() {
+    run_test(
+        // Arguments
+        "
+        --quirk dualshock-touchpad @touchpad
+        ",
+        // Input
+        "
+        abs:x:100@touchpad
+        btn:touch:1@touchpad btn:tool_finger:1@touchpad abs:mt_position_x:200@touchpad abs:x:200@touchpad abs:y:50@touchpad
+        abs:x:210@touchpad abs:y:45@touchpad btn:left:1@touchpad
+        abs:x:205@touchpad btn:left:0@touchpad
+        btn:touch:0@touchpad abs:x:300@touchpad abs:x:300@other
+        ",
+        // Output
+        "
+        rel:x:10@touchpad rel:y:-5@touchpad btn:left:1@touchpad
+        rel:x:-5@touchpad btn:left:0@touchpad
+        abs:x:300@other
+        "
+    )
+}
+
+#[test]
+fn test_mouse_keys() {
+    run_test(
+        // Arguments
+        "
+        --mouse-keys @kb toggle=key:numlock
+        ",
+        // Input
+        "
+        key:kp5:1@kb key:kp5:0@kb
+        key:kpplus:1@kb key:kpplus:0@kb key:kp0:1@kb key:kp0:0@kb key:kpdot:1@kb key:kpdot:0@kb
+        key:kp5:1@other
+        key:numlock:1@kb key:numlock:0@kb key:kp5:1@kb key:kp5:0@kb
+        ",
+        // Output
+        "
+        btn:left:1@kb btn:left:0@kb
+        btn:middle:1@kb btn:middle:0@kb
+        key:kp5:1@other
+        key:numlock:1@kb key:numlock:0@kb key:kp5:1@kb key:kp5:0@kb
+        "
+    )
+}
+
+#[test]
+fn test_bounce_keys_release() {
+    // All events arrive at the same time, so the second click comes right after the first one.
+    run_test(
+        "--bounce-keys btn:left btn:right period=0.08",
+        "btn:left:1 btn:left:0 btn:left:1 btn:right:1 btn:left:0 btn:right:0 btn:right:1 btn:right:0",
+        "btn:left:1 btn:left:0 btn:right:1 btn:right:0",
+    );
+    // A press is never suppressed if the key was not released before.
+    run_test(
+        "--bounce-keys period=0.08 mode=release",
+        "key:a:1 key:b:1 key:b:0 key:a:2 key:a:0",
+        "key:a:1 key:b:1 key:b:0 key:a:2 key:a:0",
+    );
+}
+
+#[test]
+fn test_bounce_keys_chatter() {
+    run_test(
+        "--bounce-keys key:a period=0.01 mode=chatter",
+        "key:a:1 key:a:0 key:a:1 key:b:1 key:b:0",
+        "key:a:1 key:b:1 key:b:0",
+    );
+}
+
+#[test]
+fn test_inhibit_while_typing() {
+    // All events arrive at the same time, so everything after the first key press is while typing.
+    run_test(
+        "--inhibit-while-typing period=0.3",
+        "btn:touch:1@pad key:a:1@kb rel:x:5@pad btn:touch:0@pad btn:touch:1@pad abs:x:10@pad btn:touch:0@pad",
+        "btn:touch:1@pad key:a:1@kb btn:touch:0@pad abs:x:10@pad",
+    );
+    // Modifiers do not count as typing by default.
+    run_test(
+        "--inhibit-while-typing btn:touch@pad typing=key@kb period=0.3",
+        "key:leftctrl:1@kb btn:touch:1@pad key:b:1@other btn:touch:0@pad",
+        "key:leftctrl:1@kb btn:touch:1@pad key:b:1@other btn:touch:0@pad",
+    );
+    run_test(
+        "--inhibit-while-typing btn:touch period=0.3 except=key:a",
+        "key:leftctrl:1 btn:touch:1 btn:touch:0 key:a:1",
+        "key:leftctrl:1 key:a:1",
+    );
+}
+
+#[test]
+fn test_max_hold() {
+    // Keys that are released in time are not affected.
+    run_test(
+        "--max-hold period=10 except=key:b",
+        "key:a:1 key:a:2 key:b:1 key:a:0 rel:x:1",
+        "key:a:1 key:a:2 key:b:1 key:a:0 rel:x:1",
+    );
+    // Keys that are held too long get released, and their real release gets dropped.
+    run_test(
+        "--max-hold period=1 except=key:b",
+        "key:a:1 key:b:1 +999ms key:c:1 +1ms key:a:2 +5000ms key:a:0 key:b:0",
+        "key:a:1 key:b:1 key:c:1 key:a:0 key:c:0 key:b:0",
+    );
+}
+
+#[test]
+fn test_virtual_clock() {
+    run_test(
+        "--delay key:a period=0.5",
+        "key:a:1 key:b:1 +499ms key:b:0 +1ms key:a:0 +500ms",
+        "key:b:1 key:b:0 key:a:1 key:a:0",
+    );
+    // Events that are still delayed when the input ends never come out.
+    run_test(
+        "--delay key:a period=0.5",
+        "key:a:1 +500ms key:a:0 +499ms",
+        "key:a:1",
+    );
+    run_test(
+        "--hook key:a key:b period=0.2 send-key=key:x",
+        "key:a:1 +100ms key:b:1 key:a:0 key:b:0 key:a:1 +201ms key:b:1",
+        "key:a:1 key:b:1 key:x:1 key:x:0 key:a:0 key:b:0 key:a:1 key:b:1",
+    );
+    // With syn markers, advancing the clock also ends the current report.
+    run_test(
+        "--delay key:a period=1",
+        "key:a:1 key:b:1 +1000ms key:c:1 syn",
+        "key:b:1 syn key:a:1 syn key:c:1 syn",
+    );
+}
+
+#[test]
+fn test_suspend_mode() {
+    // By default, the time spent suspended does not count towards the period.
+    run_test(
+        "--delay key:a period=0.5",
+        "key:a:1 +200ms ~1000ms key:b:1 +299ms key:c:1 +1ms",
+        "key:b:1 key:c:1 key:a:1",
+    );
+    run_test(
+        "--delay key:a period=0.5 suspend=pause",
+        "key:a:1 +200ms ~100ms key:b:1 +300ms",
+        "key:b:1 key:a:1",
+    );
+    // Delays that count the time spent suspended end as soon as the system resumes if they passed.
+    run_test(
+        "--delay key:a period=0.5 suspend=count",
+        "key:a:1 +200ms ~1000ms key:b:1",
+        "key:a:1 key:b:1",
+    );
+    run_test(
+        "--delay key:a period=0.5 suspend=count",
+        "key:a:1 +200ms ~100ms key:b:1 +199ms key:c:1 +1ms",
+        "key:b:1 key:c:1 key:a:1",
+    );
+    // The same goes for the period of a hook.
+    run_test(
+        "--hook key:a key:b period=1 send-key=key:x",
+        "key:a:1 ~2000ms key:b:1",
+        "key:a:1 key:b:1 key:x:1",
+    );
+    run_test(
+        "--hook key:a key:b period=1 suspend=count send-key=key:x",
+        "key:a:1 ~2000ms key:b:1",
+        "key:a:1 key:b:1",
+    );
+}
+
+#[test]
+fn test_hook_id() {
+    run_test(
+        "--hook key:a key:b id=ab --map hook:ab key:c",
+        "key:a:1 key:b:1 key:a:0 key:b:0",
+        "key:a:1 key:b:1 key:c:1 key:c:0 key:a:0 key:b:0",
+    );
+    // Hooks can be chained.
+    run_test(
+        "--hook key:a id=a --hook hook:a key:b id=chained --map hook:chained key:x --block hook:a",
+        "key:a:1 key:b:1 key:b:0 key:a:0",
+        "key:a:1 key:b:1 key:x:1 key:x:0 key:b:0 key:a:0",
+    );
+}
+
+#[test]
+fn test_default_block() {
+    run_test(
+        "--default block --map key:a:1 key:b:1 --hook key:c exec-shell=true --toggle key:d key:e key:f",
+        "key:a:1 key:a:0 key:c:1 key:d:1 key:x:1 rel:x:1",
+        "key:b:1 key:a:0 key:c:1 key:e:1",
+    );
+    // Only events within the scope are blocked.
+    run_test(
+        "--default block @kb --map key:a@kb key:b",
+        "key:a:1@kb key:c:1@kb key:c:1@mouse rel:x:1@mouse",
+        "key:b:1@kb key:c:1@mouse rel:x:1@mouse",
+    );
+    // Arguments before the --default block have no effect on it.
+    run_test(
+        "--map key:a key:b --default block --map key:c key:d",
+        "key:a:1 key:c:1",
+        "key:d:1",
+    );
+}
+
+#[test]
+fn test_edge() {
+    run_test(
+        "--edge key:a",
+        "key:a:1 key:a:1 key:a:0 key:a:1 key:b:1",
+        "key:a:1 key:a:0 key:a:1 key:b:1",
+    );
+    run_test(
+        "--edge key:a initial=1",
+        "key:a:1 key:a:1",
+        "key:a:0 key:a:1",
+    );
+    run_test(
+        "--edge key:a mode=pulse",
+        "key:a:1 key:a:2 key:a:1",
+        "key:a:1 key:a:0 key:a:1 key:a:0",
+    );
+}
+
+#[test]
+fn test_rel_to_abs() {
+    run_test(
+        "--rel-to-abs rel:x abs:x range=0~10",
+        "rel:x:4 rel:x:4 rel:x:4 rel:x:-20",
+        "abs:x:4 abs:x:8 abs:x:10 abs:x:0",
+    );
+    run_test(
+        "--rel-to-abs rel:x abs:x:0~9 bounds=wrap",
+        "rel:x:4 rel:x:4 rel:x:4 rel:x:-3",
+        "abs:x:4 abs:x:8 abs:x:2 abs:x:9",
+    );
+    run_test(
+        "--rel-to-abs rel:x abs:x range=0~10 edge-resistance=5",
+        "rel:x:8 rel:x:8 rel:x:-3 rel:x:-3",
+        "abs:x:8 abs:x:10 abs:x:10 abs:x:9",
+    );
+    run_test(
+        "--rel-to-abs rel:x abs:x range=0~10 --hook key:z recenter",
+        "rel:x:8 key:z:1 key:z:0 key:z:1 rel:x:-4",
+        "abs:x:8 key:z:1 abs:x:5 key:z:0 key:z:1 abs:x:1",
+    );
+}
+
+#[test]
+fn test_press_duration() {
+    // The events in run_test() arrive at the same time, so the duration is always zero.
+    run_test(
+        "--press-duration key:a send=abs:brake:0~1000",
+        "key:a:1 key:a:0 key:b:1 key:b:0",
+        "key:a:1 key:a:0 abs:brake:0 key:b:1 key:b:0",
+    );
+}
+
+#[test]
+fn test_output_copy() {
+    // Without the copy flag, the events written to the first output device do not reach the second.
+    run_test(
+        "--output key:a --output",
+        "key:a:1 key:b:1",
+        "key:a:1 key:b:1",
+    );
+    run_test(
+        "--output key:a copy --output",
+        "key:a:1 key:b:1",
+        "key:a:1 key:a:1 key:b:1",
+    );
+}
+
+#[test]
+fn test_resync() {
+    // Events in the resync namespace skip hooks and maps without the resync flag, but still reach the output.
+    run_resync_test(
+        "--hook key:a send-key=key:x --map key:b key:c resync --map key:d key:e --output",
+        "key:a:1 key:b:1 key:d:1",
+        "key:a:1 key:c:1 key:d:1",
+    );
+    // The same arguments act as usual on other events.
+    run_test(
+        "--hook key:a send-key=key:x --map key:b key:c resync --map key:d key:e --output",
+        "key:a:1 key:b:1 key:d:1",
+        "key:a:1 key:x:1 key:c:1 key:e:1",
+    );
+    // Maps with the resync flag support the same filters and masks as other maps.
+    run_resync_test(
+        "--map key:b:1 key:c:2 resync --copy key:d key:e resync --output",
+        "key:b:1 key:b:0 key:d:1",
+        "key:c:2 key:b:0 key:d:1 key:e:1",
+    );
+}
+
+#[test]
+fn test_bypass() {
+    // Bypassed events are written and synchronised before the other events of the same report.
+    run_test(
+        "--map key:f2 key:f3 --map key:a key:b --bypass key:f2 --output",
+        "key:a:1 key:f2:1 syn key:f2:0 key:a:0 syn",
+        "key:f2:1 syn key:b:1 syn key:f2:0 syn key:b:0 syn",
+    );
+    // Once a key has been pressed through the bypass, its other events bypass the stream as well.
+    run_test(
+        "--map key:f2 key:f3 --bypass key:f2:1 --output",
+        "key:f2:1 key:f2:2 key:f2:0 key:f2:0",
+        "key:f2:1 key:f2:2 key:f2:0 key:f3:0",
+    );
+    run_test(
+        "--bypass key:f2@kb1 --map key:f2 key:f3 --output",
+        "key:f2:1@kb1 key:f2:1@kb2",
+        "key:f2:1 key:f3:1",
+    );
+}
+
+#[test]
+fn test_mirror() {
+    run_test(
+        "--mirror",
+        "key:space:1 key:a:1 key:a:0 key:space:0 key:space:1 key:space:2 key:space:0 key:a:1",
+        "key:semicolon:1 key:semicolon:0 key:space:1 key:space:0 key:a:1",
+    );
+    // Keys that were mirrored upon being pressed also get mirrored upon release.
+    run_test(
+        "--mirror key:q trigger=key:capslock",
+        "key:capslock:1 key:q:1 key:a:1 key:capslock:0 key:q:0 key:a:0",
+        "key:p:1 key:a:1 key:p:0 key:a:0",
+    );
+}
+
+#[test]
+fn test_packets() {
+    run_test(
+        "--map key:a key:x key:y",
+        "key:a:1 key:b:1 syn",
+        "key:x:1 syn key:y:1 syn key:b:1 syn",
+    );
+    // All events that result from a packet are synchronised together.
+    run_packet_test(
+        "--map key:a key:x key:y",
+        "key:a:1 key:b:1 syn key:a:0 syn",
+        "key:x:1 key:y:1 key:b:1 syn key:x:0 key:y:0 syn",
+    );
+    run_packet_test(
+        "--hook key:a key:b send-key=key:c --block key:a key:b",
+        "key:a:1 key:b:1 syn key:a:0 key:b:0 syn",
+        "key:c:1 syn key:c:0 syn",
+    );
+}
+
+#[test]
+fn test_remap_modifiers() {
+    run_test(
+        "--remap-modifiers swap=key:capslock,key:leftctrl",
+        "key:capslock:2 key:capslock:0 key:capslock:1 key:capslock:2 key:c:1 key:c:0 key:leftctrl:1 key:capslock:0 key:leftctrl:0",
+        "key:leftctrl:1 key:leftctrl:2 key:c:1 key:c:0 key:capslock:1 key:leftctrl:0 key:capslock:0",
+    );
+    // The state is tracked separately for each domain, and a key pressed by two keys stays pressed until both are released.
+    run_test(
+        "--remap-modifiers @kb remap=key:capslock,key:leftctrl",
+        "key:capslock:1@kb key:leftctrl:1@kb key:capslock:1@other key:capslock:0@kb key:leftctrl:0@kb key:capslock:0@other",
+        "key:leftctrl:1@kb key:capslock:1@other key:leftctrl:0@kb key:capslock:0@other",
+    );
+}
+
+#[test]
+fn test_pressure_click() {
+    run_test(
+        "--pressure-click",
+        "abs:pressure:20 abs:pressure:28 abs:pressure:35 abs:pressure:27 abs:pressure:31 abs:pressure:0",
+        "abs:pressure:20 abs:pressure:28 abs:pressure:35 btn:left:1 abs:pressure:27 abs:pressure:31 abs:pressure:0 btn:left:0",
+    );
+    run_test(
+        "--pressure-click abs:mt_touch_major@pad threshold=200 button=btn:right",
+        "abs:mt_touch_major:250@pad abs:mt_touch_major:250@other abs:mt_touch_major:100@pad",
+        "abs:mt_touch_major:250@pad btn:right:1@pad abs:mt_touch_major:250@other abs:mt_touch_major:100@pad btn:right:0@pad",
+    );
+}
+
+#[test]
+fn test_stage() {
+    // Swapping two keys without the second map undoing the first one.
+    run_test(
+        "--stage swap --map key:a key:b --map key:b key:a stage=input",
+        "key:a:1 key:a:0 key:b:1 key:b:0",
+        "key:b:1 key:b:0 key:a:1 key:a:0",
+    );
+    // Events that pass by an argument unchanged keep their stage.
+    run_test(
+        "--stage extra --map key:x key:x key:a --map key:x key:y stage=input --map key:a key:b stage=input",
+        "key:x:1 key:a:1",
+        "key:y:1 key:a:1 key:b:1",
+    );
+    run_test(
+        "--stage hooks --hook key:f1 send-key=key:a --stage late --map key:a key:b stage=input --map key:a key:c stage=hooks",
+        "key:a:1 key:a:0 key:f1:1 key:f1:0",
+        "key:b:1 key:b:0 key:f1:1 key:c:1 key:c:0 key:f1:0",
+    );
+    run_test(
+        "--stage second --map key:a key:b --block stage=input,second",
+        "key:a:1 key:c:1",
+        "",
+    );
+    // The same holds for arguments that process multiple events at once, like hooks with --withhold.
+    run_test(
+        "--stage hooks --hook key:f1 key:f2 send-key=key:a --withhold --map key:a key:b stage=input",
+        "key:a:1 key:a:0 key:f1:1 key:f2:1 key:f2:0 key:f1:0",
+        "key:b:1 key:b:0 key:a:1 key:a:0",
+    );
+}
+
+#[test]
+fn test_annotations() {
+    // Events keep their annotations when they get mapped.
+    run_test(
+        "--map key:a key:b^remapped --map key:b^remapped key:c",
+        "key:a:1 key:b:1",
+        "key:c:1^remapped key:b:1",
+    );
+    // Dropping the events sent by a hook, but not the events that triggered it.
+    run_test(
+        "--hook key:f1 send-key=key:x^macro --map key:x key:y --block ^macro",
+        "key:f1:1 key:f1:0 key:x:1 key:x:0",
+        "key:f1:1 key:f1:0 key:y:1 key:y:0",
+    );
+    // A filter with multiple annotations requires the event to carry all of them.
+    run_test(
+        "--map key:a key:a^first --map key:b key:b^first^second --block key^first^second",
+        "key:a:1 key:b:1",
+        "key:a:1^first",
+    );
+}
+
+#[test]
+fn test_apply() {
+    // Applied arguments get inserted at the end of the stream by default.
+    run_apply_test(
+        "--map key:a key:b",
+        "key:a:1 key:a:0",
+        None, "--map key:b key:c",
+        "key:a:1 key:a:0 key:d:1",
+        "key:b:1 key:b:0 key:c:1 key:c:0 key:d:1",
+    );
+    // With a stage, they get inserted directly after the --stage argument.
+    run_apply_test(
+        "--stage live --map key:a key:b --output",
+        "key:x:1",
+        Some("live"), "--map key:x key:a",
+        "key:x:0",
+        "key:x:1 key:b:0",
+    );
+    // Applied arguments can use the toggles of the running stream and declare new ones.
+    run_apply_test(
+        "--toggle key:a key:b key:c id=t",
+        "key:a:1 key:a:0",
+        None, "--hook key:f1 toggle=t --toggle key:q key:r key:s id=u --hook key:f2 toggle=u",
+        "key:f1:1 key:a:1 key:a:0 key:q:1 key:q:0 key:f2:1 key:q:1",
+        "key:b:1 key:b:0 key:f1:1 key:c:1 key:c:0 key:r:1 key:r:0 key:f2:1 key:s:1",
+    );
+    // Arguments that need to be set up when evsieve starts, or that could load arbitrary code, are refused.
+    assert!(try_apply("--map key:a key:b", "--map key:b key:c").is_ok());
+    for refused in &["--plugin /tmp/plugin.so", "--input /dev/null", "--map key:a --output", "--stats interval=1"] {
+        let error = try_apply("--map key:a key:b", refused).unwrap_err();
+        assert!(error.to_string().contains("cannot be applied"), "{}", error);
+    }
+}
+
+#[test]
+fn test_device_status_events() {
+    run_test(
+        "--map evsieve:device-lost key:f13",
+        "evsieve:device-lost:1 evsieve:device-lost:0 evsieve:device-connected:1",
+        "key:f13:1 key:f13:0 evsieve:device-connected:1",
+    );
+    run_test(
+        "--hook evsieve:device-lost send-key=key:f14",
+        "evsieve:device-lost:1 evsieve:device-lost:0",
+        "evsieve:device-lost:1 key:f14:1 key:f14:0 evsieve:device-lost:0",
+    );
+}
+
+#[test]
+fn test_signals() {
+    run_test(
+        "--hook key:leftctrl key:a send-key=signal:ctrl-a --map signal:ctrl-a key:b",
+        "key:leftctrl:1 key:a:1 key:a:0",
+        "key:leftctrl:1 key:a:1 key:b:1 key:b:0 key:a:0",
+    );
+    // Signals never reach output devices, but can be routed to them by mapping them to other events.
+    run_test(
+        "--map key:a key:a signal:a --map signal:a key:c --output",
+        "key:a:1 key:a:0",
+        "key:a:1 key:c:1 key:a:0 key:c:0",
+    );
+}
+
+#[test]
+fn test_strict_caps() {
+    use crate::capability::Capabilities;
+    use crate::event::{EventCode, EventType, Namespace};
+
+    /// Returns the EV_KEY codes of the output device if an input device has the given codes.
+    fn output_key_codes(args: &str, input_codes: &[u16]) -> Vec<u16> {
+        let args = args.split_whitespace().map(str::to_owned).collect();
+        let pre_implementation = crate::arguments::parser::process(args).unwrap();
+        let strict_domains: Vec<_> = pre_implementation.output_devices.iter()
+            .filter(|device| device.strict_caps)
+            .map(|device| device.domain)
+            .collect();
+
+        let mut input_caps = Capabilities::new();
+        input_caps.codes = input_codes.iter().map(|&code| EventCode::new(EventType::KEY, code)).collect();
+        let input_caps = vec![(pre_implementation.input_devices[0].domain, input_caps)].into_iter().collect();
+
+        let output_caps = crate::stream::determine_output_capabilities(
+            &pre_implementation.stream, &input_caps, &pre_implementation.state, &strict_domains
+        );
+        let mut codes: Vec<u16> = output_caps.into_iter()
+            .filter(|cap| cap.namespace == Namespace::Output && cap.code.ev_type().is_key())
+            .map(|cap| cap.code.code())
+            .collect();
+        codes.sort_unstable();
+        codes.dedup();
+        codes
+    }
+
+    let (key_x, key_y, key_z) = (45, 21, 44);
+    let script = "--input /dev/null \
+                  --toggle @unused @unused @unused id=mode \
+                  --map key:x key:y enabled-when=mode:1 \
+                  --map key:y key:z enabled-when=mode:2 \
+                  --output";
+    // Without strict-caps, key:z is hedged for because key:x might map to key:y and then to key:z.
+    assert_eq!(output_key_codes(script, &[key_x]), vec![key_y, key_z, key_x]);
+    // No combination of toggle states can map key:x to key:z.
+    assert_eq!(output_key_codes(&format!("{} strict-caps", script), &[key_x]), vec![key_y, key_x]);
+}
+
+#[test]
+fn test_full_keyboard() {
+    use crate::capability::Capabilities;
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::predevice::RepeatMode;
+
+    let args = "--input /dev/null --map key:a btn:left --output btn full-keyboard --output key:a"
+        .split_whitespace().map(str::to_owned).collect();
+    let pre_implementation = crate::arguments::parser::process(args).unwrap();
+    let keyboard = &pre_implementation.output_devices[0];
+    assert!(keyboard.repeat_mode == RepeatMode::Enable);
+    assert!(pre_implementation.output_devices[1].repeat_mode == RepeatMode::Passive);
+
+    let mut input_caps = Capabilities::new();
+    input_caps.codes = vec![EventCode::new(EventType::KEY, 30)].into_iter().collect();
+    let input_caps = vec![(pre_implementation.input_devices[0].domain, input_caps)].into_iter().collect();
+    let output_caps = crate::stream::determine_output_capabilities(
+        &pre_implementation.stream, &input_caps, &pre_implementation.state, &[]
+    );
+    let has_cap = |domain, ev_type, code| output_caps.iter().any(|cap|
+        cap.namespace == Namespace::Output && cap.domain == domain && cap.code == EventCode::new(ev_type, code)
+    );
+    // The keyboard gets all keys even though none can reach it, and keeps its buttons.
+    for &code in &[1, 30, 58, 113, 0x160, 0x2ff] {
+        assert!(has_cap(keyboard.domain, EventType::KEY, code));
+    }
+    assert!(has_cap(keyboard.domain, EventType::KEY, 0x110));
+    assert!(! has_cap(keyboard.domain, EventType::KEY, 0x111));
+    assert!(! has_cap(pre_implementation.output_devices[1].domain, EventType::KEY, 31));
+}
+
+#[test]
+fn test_auto_output() {
+    use crate::capability::Capabilities;
+    use crate::event::{EventCode, EventType, Namespace};
+    use crate::predevice::GrabMode;
+
+    let args = "--input /dev/null auto-output --map key:volumeup @media --output @media";
+    let args = args.split_whitespace().map(str::to_owned).collect();
+    let pre_implementation = crate::arguments::parser::process(args).unwrap();
+    let input_device = &pre_implementation.input_devices[0];
+    assert!(input_device.grab_mode == GrabMode::Auto);
+    assert_eq!(pre_implementation.output_devices.len(), 2);
+
+    let (key_a, key_volumeup) = (30, 115);
+    let mut input_caps = Capabilities::new();
+    input_caps.codes = [key_a, key_volumeup].iter().map(|&code| EventCode::new(EventType::KEY, code)).collect();
+    let input_caps = vec![(input_device.domain, input_caps)].into_iter().collect();
+    let output_caps = crate::stream::determine_output_capabilities(
+        &pre_implementation.stream, &input_caps, &pre_implementation.state, &[]
+    );
+
+    // The explicit output device comes first, the automatically created one last.
+    for (output_device, expected_code) in pre_implementation.output_devices.iter().zip(&[key_volumeup, key_a]) {
+        let codes: Vec<u16> = output_caps.iter()
+            .filter(|cap| cap.namespace == Namespace::Output && cap.domain == output_device.domain)
+            .filter(|cap| cap.code.ev_type().is_key())
+            .map(|cap| cap.code.code())
+            .collect();
+        assert_eq!(codes, vec![*expected_code]);
+    }
+}
+
+#[test]
+fn test_mirror_of() {
+    use crate::capability::Capabilities;
+    use crate::event::{EventCode, EventType, Namespace};
+
+    let args = "--input /dev/null domain=kb --map key:a @other --output @other --output mirror-of=kb name=Mirror";
+    let args = args.split_whitespace().map(str::to_owned).collect();
+    let pre_implementation = crate::arguments::parser::process(args).unwrap();
+    let input_device = &pre_implementation.input_devices[0];
+    let mirror = pre_implementation.output_devices[1].mirror.as_ref().unwrap();
+    assert_eq!(mirror.sources, vec![input_device.domain]);
+    assert!(! mirror.copy_name);
+
+    let (key_a, key_b) = (30, 48);
+    let mut input_caps = Capabilities::new();
+    input_caps.codes = [key_a, key_b].iter().map(|&code| EventCode::new(EventType::KEY, code)).collect();
+    let input_caps = vec![(input_device.domain, input_caps)].into_iter().collect();
+    let output_caps = crate::stream::determine_output_capabilities(
+        &pre_implementation.stream, &input_caps, &pre_implementation.state, &[]
+    );
+
+    // The mirroring device also gets the capabilities of events that are routed elsewhere.
+    for (output_device, expected_codes) in pre_implementation.output_devices.iter().zip(&[vec![key_a], vec![key_a, key_b]]) {
+        let mut codes: Vec<u16> = output_caps.iter()
+            .filter(|cap| cap.namespace == Namespace::Output && cap.domain == output_device.domain)
+            .filter(|cap| cap.code.ev_type().is_key())
+            .map(|cap| cap.code.code())
+            .collect();
+        codes.sort_unstable();
+        codes.dedup();
+        assert_eq!(&codes, expected_codes);
+    }
+}
+
+#[test]
+fn test_merge_priority() {
+    // Without priorities, only events with the same domain get merged.
+    run_test(
+        "--merge",
+        "key:a:1@kb1 key:a:1@kb2 key:a:0@kb1 key:a:0@kb2",
+        "key:a:1@kb1 key:a:1@kb2 key:a:0@kb1 key:a:0@kb2",
+    );
+    // With priorities, events with different domains get merged as well.
+    run_test(
+        "--merge priority=kb1:1",
+        "key:a:1@kb1 key:a:1@kb2 key:a:0@kb1 key:a:0@kb2",
+        "key:a:1@kb1 key:a:0@kb2",
+    );
+    // A press with higher priority steals the key from the lower priority, whose release gets dropped.
+    run_test(
+        "--merge priority=kb1:1 steal",
+        "key:a:1@kb2 key:a:1@kb1 key:a:0@kb1 key:a:0@kb2",
+        "key:a:1@kb2 key:a:0@kb1",
+    );
+    // A press with lower priority is suppressed while the higher priority holds the key...
+    run_test(
+        "--merge priority=kb1:1 steal",
+        "key:a:1@kb1 key:a:1@kb2 key:a:0@kb2 key:a:0@kb1",
+        "key:a:1@kb1 key:a:0@kb1",
+    );
+    // ... even if it gets released after the higher priority released the key.
+    run_test(
+        "--merge priority=kb1:1 steal",
+        "key:a:1@kb1 key:a:1@kb2 key:a:0@kb1 key:a:0@kb2 key:a:1@kb2 key:a:0@kb2",
+        "key:a:1@kb1 key:a:0@kb1 key:a:1@kb2 key:a:0@kb2",
+    );
+    // Domains with equal priority share the key without stealing it from each other.
+    run_test(
+        "--merge priority=kb1:1 priority=kb2:1 steal",
+        "key:a:1@kb1 key:a:1@kb2 key:a:0@kb1 key:a:0@kb2",
+        "key:a:1@kb1 key:a:0@kb2",
+    );
+    // Repeat events only pass for domains that hold the key, and other keys are not affected.
+    run_test(
+        "--merge priority=kb1:1 steal",
+        "key:a:1@kb1 key:a:2@kb2 key:b:1@kb2 key:a:2@kb1 key:a:0@kb1 key:b:0@kb2",
+        "key:a:1@kb1 key:b:1@kb2 key:a:2@kb1 key:a:0@kb1 key:b:0@kb2",
+    );
+    // Priorities can be negative, and domains without priority= clause have priority zero.
+    run_test(
+        "--merge key:a priority=kb1:-1 steal",
+        "key:a:1@kb2 key:a:1@kb1 key:a:0@kb2 key:a:0@kb1",
+        "key:a:1@kb2 key:a:0@kb2",
+    );
+}
+
+#[test]
+fn test_syn_reports() {
+    // Events that map one-to-one stay in the report of the input device.
+    run_test(
+        "--map key:a key:b",
+        "key:a:1 key:c:1 syn key:a:0 syn",
+        "key:b:1 key:c:1 syn key:b:0 syn",
+    );
+    // A single event that maps to multiple events gets each event in its own report.
+    run_test(
+        "--map key:f12 key:leftctrl key:c",
+        "key:x:1 syn key:f12:1 syn",
+        "key:x:1 syn key:leftctrl:1 syn key:c:1 syn",
+    );
+    // Reports that end up empty are not written.
+    run_test(
+        "--block key:a",
+        "key:a:1 syn key:b:1 syn",
+        "key:b:1 syn",
+    );
+}
+
+#[test]
+fn test_domain_filters() {
+    // Control commands like "revoke @kb" refer to the domain that the user assigned to a device.
+    let args = "--input /dev/null domain=filter-kb --input /dev/zero domain=filter-ms --output"
+        .split_whitespace().map(str::to_owned).collect();
+    let pre_implementation = crate::arguments::parser::process(args).unwrap();
+    let (kb_device, ms_device) = (&pre_implementation.input_devices[0], &pre_implementation.input_devices[1]);
+    let kb = crate::domain::resolve("filter-kb").unwrap();
+    assert!(kb_device.matches_domain_filter(Some(kb)));
+    assert!(! ms_device.matches_domain_filter(Some(kb)));
+    assert!(kb_device.matches_domain_filter(None) && ms_device.matches_domain_filter(None));
+    // The unique domain of the device before it enters the stream is an implementation detail.
+    assert!(! ms_device.matches_domain_filter(Some(kb_device.domain)));
+
+    // The same goes for the send command.
+    use crate::control_fifo::find_send_target;
+    let target_domains = [kb_device.target_domain, ms_device.target_domain];
+    let ms = crate::domain::resolve("filter-ms").unwrap();
+    assert_eq!(find_send_target(&target_domains, Some(kb)).unwrap(), 0);
+    assert_eq!(find_send_target(&target_domains, Some(ms)).unwrap(), 1);
+    assert!(find_send_target(&target_domains, Some(kb_device.domain)).is_err());
+    assert!(find_send_target(&target_domains, None).is_err());
+    assert_eq!(find_send_target(&target_domains[1..], None).unwrap(), 0);
+    assert!(find_send_target(&[], None).is_err());
+}
+
+#[test]
+fn test_status_domains() {
+    use crate::capability::Capabilities;
+    use crate::introspect::DeviceInfo;
+    use crate::persist::blueprint::Blueprint;
+    use crate::status::{format_status, DeviceStatus};
+
+    // The status file reports the domain that the user assigned to a device.
+    let args = "--input /dev/null domain=status-kb --output"
+        .split_whitespace().map(str::to_owned).collect();
+    let mut pre_implementation = crate::arguments::parser::process(args).unwrap();
+    let blueprint = Blueprint {
+        pre_device: pre_implementation.input_devices.remove(0),
+        capabilities: Capabilities::new(),
+        name: None,
+        revoked: true,
+    };
+    let devices = vec![DeviceStatus { info: DeviceInfo::revoked(0, &blueprint), events_read: None }];
+    assert_eq!(format_status(1, &devices, &[]), "pid 1\ndevice /dev/null pipeline=0 domain=status-kb state=revoked\n");
+}
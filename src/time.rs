@@ -5,7 +5,9 @@
 //! as well, the documentation says that it may change over time, therefore we need our own
 //! time module.
 
+use std::cell::Cell;
 use std::mem::MaybeUninit;
+use std::rc::Rc;
 use std::convert::TryInto;
 use crate::bindings::libevdev;
 
@@ -20,7 +22,7 @@ pub struct Instant {
     nsec: i64,
 }
 
-#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
 pub struct Duration {
     // Expects invariant: 0 <= sec
     sec: u64,
@@ -30,17 +32,7 @@ pub struct Duration {
 
 impl Instant {
     pub fn now() -> Instant {
-        unsafe {
-            let mut timespec: MaybeUninit<libc::timespec> = MaybeUninit::uninit();
-            let result = libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut timespec as *mut _ as *mut libc::timespec);
-            if result < 0 {
-                panic!("Failed to determine the current time using [libc] clock_gettime(). Error code: {}",
-                    std::io::Error::last_os_error().raw_os_error().map(|x| x.to_string()).unwrap_or_else(|| "(unknown)".to_owned())
-                );
-            }
-
-            timespec.assume_init().into()
-        }
+        clock_gettime(libc::CLOCK_MONOTONIC)
     }
 
     // The checked part refers to making sure that self is after other.
@@ -60,6 +52,29 @@ impl Instant {
     }
 }
 
+fn clock_gettime(clock_id: libc::clockid_t) -> Instant {
+    unsafe {
+        let mut timespec: MaybeUninit<libc::timespec> = MaybeUninit::uninit();
+        let result = libc::clock_gettime(clock_id, &mut timespec as *mut _ as *mut libc::timespec);
+        if result < 0 {
+            panic!("Failed to determine the current time using [libc] clock_gettime(). Error code: {}",
+                std::io::Error::last_os_error().raw_os_error().map(|x| x.to_string()).unwrap_or_else(|| "(unknown)".to_owned())
+            );
+        }
+
+        timespec.assume_init().into()
+    }
+}
+
+/// The total time the system has spent suspended since it booted, which is the difference between
+/// CLOCK_BOOTTIME and CLOCK_MONOTONIC.
+pub fn time_suspended() -> Duration {
+    // The time between both reads gets counted as suspended as well, but that is negligible.
+    let monotonic = clock_gettime(libc::CLOCK_MONOTONIC);
+    let boottime = clock_gettime(libc::CLOCK_BOOTTIME);
+    boottime.checked_duration_since(monotonic).unwrap_or_else(|| Duration::from_nanos(0))
+}
+
 impl From<libc::timespec> for Instant {
     #[allow(clippy::unnecessary_cast)]
     fn from(timespec: libc::timespec) -> Self {
@@ -72,6 +87,16 @@ impl From<libc::timespec> for Instant {
     }
 }
 
+impl From<Instant> for libc::timespec {
+    #[allow(clippy::unnecessary_cast)]
+    fn from(instant: Instant) -> Self {
+        libc::timespec {
+            tv_sec: instant.sec as libc::time_t,
+            tv_nsec: instant.nsec as libc::c_long,
+        }
+    }
+}
+
 impl From<libevdev::timeval> for Instant {
     #[allow(clippy::unnecessary_cast)]
     fn from(timeval: libevdev::timeval) -> Self {
@@ -87,7 +112,7 @@ impl Duration {
         Duration::from_nanos(sec * 1_000_000_000)
     }
 
-    pub fn from_millis(msec: u64) -> Duration {
+    pub const fn from_millis(msec: u64) -> Duration {
         Duration::from_nanos(msec * 1_000_000)
     }
 
@@ -95,7 +120,7 @@ impl Duration {
         Duration::from_nanos(microsec * 1_000)
     }
 
-    pub fn from_nanos(nsec: u64) -> Duration {
+    pub const fn from_nanos(nsec: u64) -> Duration {
         Duration {
             sec: nsec / NANOSECONDS_PER_SECOND as u64,
             nsec: nsec % NANOSECONDS_PER_SECOND as u64
@@ -105,6 +130,47 @@ impl Duration {
     pub fn as_millis(self) -> u64 {
         self.sec * 1_000 + self.nsec / 1_000_000
     }
+
+    pub fn as_micros(self) -> u64 {
+        self.sec * 1_000_000 + self.nsec / 1_000
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.sec as f64 + self.nsec as f64 / NANOSECONDS_PER_SECOND as f64
+    }
+
+    /// Returns zero if `other` is longer than `self`.
+    pub fn saturating_sub(self, other: Duration) -> Duration {
+        let (sec, nsec) = match self.nsec.checked_sub(other.nsec) {
+            Some(nsec) => (self.sec.checked_sub(other.sec), nsec),
+            None => (self.sec.checked_sub(other.sec + 1), self.nsec + NANOSECONDS_PER_SECOND as u64 - other.nsec),
+        };
+        match sec {
+            Some(sec) => Duration { sec, nsec },
+            None => Duration::from_nanos(0),
+        }
+    }
+
+    /// Multiplies this duration by a nonnegative factor, rounded to the nearest nanosecond. Saturates
+    /// instead of overflowing.
+    pub fn mul_f64(self, factor: f64) -> Duration {
+        let nanoseconds = (self.sec as f64 * NANOSECONDS_PER_SECOND as f64 + self.nsec as f64) * factor;
+        Duration::from_nanos(nanoseconds.round().max(0.0).min(u64::MAX as f64) as u64)
+    }
+}
+
+impl std::ops::Add<Duration> for Duration {
+    type Output = Duration;
+    fn add(self, rhs: Duration) -> Self::Output {
+        let mut sec = self.sec + rhs.sec;
+        let mut nsec = self.nsec + rhs.nsec;
+        if nsec >= NANOSECONDS_PER_SECOND as u64 {
+            nsec -= NANOSECONDS_PER_SECOND as u64;
+            sec += 1;
+        }
+
+        Duration { sec, nsec }
+    }
 }
 
 impl std::ops::Add<Duration> for Instant {
@@ -112,7 +178,7 @@ impl std::ops::Add<Duration> for Instant {
     fn add(self, rhs: Duration) -> Self::Output {
         let mut sec = self.sec + rhs.sec as i64;
         let mut nsec = self.nsec + rhs.nsec as i64;
-        if nsec > NANOSECONDS_PER_SECOND {
+        if nsec >= NANOSECONDS_PER_SECOND {
             nsec -= NANOSECONDS_PER_SECOND;
             sec += 1;
         }
@@ -121,6 +187,63 @@ impl std::ops::Add<Duration> for Instant {
     }
 }
 
+/// The source of the current time for everything that happens inside the stream.
+pub trait Clock {
+    fn now(&self) -> Instant;
+    /// The total time the system has spent suspended. Only ever increases.
+    fn time_suspended(&self) -> Duration;
+}
+
+/// The clock that is used outside of tests: the real monotonic clock.
+pub struct MonotonicClock;
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+
+    fn time_suspended(&self) -> Duration {
+        time_suspended()
+    }
+}
+
+/// A clock that only moves forward when told to, so tests can deterministically check the behaviour
+/// of arguments that depend on the passage of time. Clones share the same time.
+#[derive(Clone)]
+pub struct VirtualClock {
+    now: Rc<Cell<Instant>>,
+    suspended: Rc<Cell<Duration>>,
+}
+
+impl VirtualClock {
+    pub fn new(start: Instant) -> VirtualClock {
+        VirtualClock {
+            now: Rc::new(Cell::new(start)),
+            suspended: Rc::new(Cell::new(Duration::from_nanos(0))),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        self.now.set(self.now.get() + duration);
+    }
+
+    /// Pretends that the system was suspended for the given duration. Like CLOCK_MONOTONIC, the
+    /// current time does not move forward while suspended.
+    pub fn suspend(&self, duration: Duration) {
+        self.suspended.set(self.suspended.get() + duration);
+    }
+}
+
+impl Clock for VirtualClock {
+    fn now(&self) -> Instant {
+        self.now.get()
+    }
+
+    fn time_suspended(&self) -> Duration {
+        self.suspended.get()
+    }
+}
+
 #[test]
 fn unittest() {
     let now = Instant::now();
@@ -137,4 +260,18 @@ fn unittest() {
         now.checked_duration_since(now + Duration::from_secs(3)),
         None
     );
+
+    assert_eq!(Duration::from_millis(1500).saturating_sub(Duration::from_millis(700)), Duration::from_millis(800));
+    assert_eq!(Duration::from_millis(700).saturating_sub(Duration::from_millis(1500)), Duration::from_nanos(0));
+    assert_eq!(Duration::from_millis(1500).mul_f64(0.5), Duration::from_millis(750));
+    assert_eq!(Duration::from_millis(300).mul_f64(4.0), Duration::from_millis(1200));
+
+    let clock = VirtualClock::new(now);
+    let shared_clock = clock.clone();
+    clock.advance(Duration::from_millis(500));
+    clock.advance(Duration::from_millis(500));
+    assert_eq!(shared_clock.now(), now + Duration::from_secs(1));
+    clock.suspend(Duration::from_secs(5));
+    assert_eq!(shared_clock.now(), now + Duration::from_secs(1));
+    assert_eq!(shared_clock.time_suspended(), Duration::from_secs(5));
 }
\ No newline at end of file
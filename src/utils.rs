@@ -6,6 +6,7 @@ use std::ffi::CStr;
 use libc::c_char;
 
 pub mod shelllex;
+pub mod json;
 
 pub fn split_once<'a>(value: &'a str, deliminator: &str) -> (&'a str, Option<&'a str>) {
     let mut splitter = value.splitn(2, deliminator);
@@ -58,6 +59,55 @@ pub fn parse_number(string: &str) -> Option<f64> {
     }
 }
 
+/// Returns `text` as a quoted JSON string.
+pub fn json_string(text: &str) -> String {
+    let mut result = String::from("\"");
+    for character in text.chars() {
+        match character {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            c if (c as u32) < 0x20 => result.push_str(&format!("\\u{:04x}", c as u32)),
+            c => result.push(c),
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Returns whether `text` matches a glob-style `pattern`, where "*" matches any sequence of
+/// characters and "?" matches any single character.
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    let (mut pattern_index, mut text_index) = (0, 0);
+    // Where to resume if the part after the last * fails to match: the index of that * in
+    // the pattern and the index in the text that it has been matched up to.
+    let mut backtrack: Option<(usize, usize)> = None;
+
+    while text_index < text.len() {
+        match pattern.get(pattern_index) {
+            Some('*') => {
+                backtrack = Some((pattern_index, text_index));
+                pattern_index += 1;
+            },
+            Some(&c) if c == '?' || c == text[text_index] => {
+                pattern_index += 1;
+                text_index += 1;
+            },
+            _ => match backtrack {
+                Some((star_index, star_text_index)) => {
+                    pattern_index = star_index + 1;
+                    text_index = star_text_index + 1;
+                    backtrack = Some((star_index, star_text_index + 1));
+                },
+                None => return false,
+            },
+        }
+    }
+
+    pattern[pattern_index ..].iter().all(|&c| c == '*')
+}
+
 /// A structure that intentionally cannot be copied or whose contents cannot be inspected without
 /// consuming it. This guards against some accidental programming errors like closing a file descriptor
 /// twice: if the i32 that represents the file descriptor is guarded by this NonCopy struct, then you
@@ -84,3 +134,20 @@ impl<T> std::fmt::Display for NonCopy<T> where T: Display {
         write!(f, "{}", self.0)
     }
 }
+
+#[test]
+fn unittest_glob_matches() {
+    assert!(glob_matches("trigger_happy*", "trigger_happy12"));
+    assert!(glob_matches("trigger_happy*", "trigger_happy"));
+    assert!(glob_matches("kp?", "kp5"));
+    assert!(! glob_matches("kp?", "kpplus"));
+    assert!(glob_matches("*a*b", "xaxxb"));
+    assert!(! glob_matches("*a*b", "xaxxbc"));
+    assert!(glob_matches("*", ""));
+    assert!(! glob_matches("a", ""));
+}
+
+#[test]
+fn unittest_json_string() {
+    assert_eq!(json_string("a\"b\\c\n"), "\"a\\\"b\\\\c\\u000a\"");
+}
@@ -0,0 +1,269 @@
+// SPDX-License-Identifier: GPL-2.0-or-later
+
+//! A small JSON parser, used to read the requests that programs send to an introspection socket.
+//! It only needs to handle short requests, so it favours simplicity over speed.
+
+use std::fmt::Display;
+use crate::utils::json_string;
+
+/// How deeply arrays and objects may be nested, to prevent a malicious request from overflowing the stack.
+const MAX_DEPTH: usize = 64;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    /// The members of an object in the order they were encountered.
+    Object(Vec<(String, JsonValue)>),
+}
+
+impl JsonValue {
+    /// If this is an object, returns the value of the member with the given name.
+    pub fn get(&self, name: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(members) => members.iter()
+                .find(|(member_name, _)| member_name == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+impl Display for JsonValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonValue::Null => write!(f, "null"),
+            JsonValue::Bool(value) => write!(f, "{}", value),
+            // Integers are written without a fractional part, so request IDs are echoed the way they were sent.
+            JsonValue::Number(value) if value.fract() == 0.0 && value.abs() < 1e15 => write!(f, "{}", *value as i64),
+            JsonValue::Number(value) => write!(f, "{}", value),
+            JsonValue::String(text) => write!(f, "{}", json_string(text)),
+            JsonValue::Array(items) => {
+                write!(f, "[")?;
+                for (index, item) in items.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", item)?;
+                }
+                write!(f, "]")
+            },
+            JsonValue::Object(members) => {
+                write!(f, "{{")?;
+                for (index, (name, value)) in members.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}:{}", json_string(name), value)?;
+                }
+                write!(f, "}}")
+            },
+        }
+    }
+}
+
+/// Parses a single JSON value. Whitespace before and after the value is allowed, anything else is not.
+pub fn parse(text: &str) -> Result<JsonValue, String> {
+    let mut parser = Parser { chars: text.chars().collect(), position: 0 };
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.position < parser.chars.len() {
+        return Err(parser.error("unexpected characters after the end of the value"));
+    }
+    Ok(value)
+}
+
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Parser {
+    fn error(&self, message: &str) -> String {
+        format!("Invalid JSON at position {}: {}.", self.position, message)
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let character = self.peek();
+        self.position += 1;
+        character
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(' ' | '\t' | '\n' | '\r') = self.peek() {
+            self.position += 1;
+        }
+    }
+
+    fn expect(&mut self, expected: char) -> Result<(), String> {
+        match self.next() {
+            Some(character) if character == expected => Ok(()),
+            _ => Err(self.error(&format!("expected '{}'", expected))),
+        }
+    }
+
+    fn expect_word(&mut self, word: &str, value: JsonValue) -> Result<JsonValue, String> {
+        for expected in word.chars() {
+            if self.next() != Some(expected) {
+                return Err(self.error(&format!("expected \"{}\"", word)));
+            }
+        }
+        Ok(value)
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, String> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("too deeply nested"));
+        }
+        self.skip_whitespace();
+        match self.peek() {
+            Some('n') => self.expect_word("null", JsonValue::Null),
+            Some('t') => self.expect_word("true", JsonValue::Bool(true)),
+            Some('f') => self.expect_word("false", JsonValue::Bool(false)),
+            Some('"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some('[') => self.parse_array(depth),
+            Some('{') => self.parse_object(depth),
+            Some('-' | '0' ..= '9') => self.parse_number(),
+            Some(_) => Err(self.error("expected a value")),
+            None => Err(self.error("unexpected end of input")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, String> {
+        let start = self.position;
+        while let Some('-' | '+' | '.' | 'e' | 'E' | '0' ..= '9') = self.peek() {
+            self.position += 1;
+        }
+        let number_str: String = self.chars[start .. self.position].iter().collect();
+        match number_str.parse::<f64>() {
+            Ok(number) if number.is_finite() => Ok(JsonValue::Number(number)),
+            _ => Err(self.error(&format!("cannot interpret \"{}\" as a number", number_str))),
+        }
+    }
+
+    fn parse_hex_escape(&mut self) -> Result<u32, String> {
+        let mut result = 0;
+        for _ in 0 .. 4 {
+            let digit = self.next().and_then(|character| character.to_digit(16))
+                .ok_or_else(|| self.error("expected four hexadecimal digits after \\u"))?;
+            result = result * 16 + digit;
+        }
+        Ok(result)
+    }
+
+    fn parse_string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let mut result = String::new();
+        loop {
+            match self.next() {
+                Some('"') => return Ok(result),
+                Some('\\') => match self.next() {
+                    Some('"') => result.push('"'),
+                    Some('\\') => result.push('\\'),
+                    Some('/') => result.push('/'),
+                    Some('b') => result.push('\u{8}'),
+                    Some('f') => result.push('\u{c}'),
+                    Some('n') => result.push('\n'),
+                    Some('r') => result.push('\r'),
+                    Some('t') => result.push('\t'),
+                    Some('u') => {
+                        let mut code_point = self.parse_hex_escape()?;
+                        // Characters outside the basic multilingual plane are written as a surrogate pair.
+                        if (0xD800 .. 0xDC00).contains(&code_point) && self.chars[self.position ..].starts_with(&['\\', 'u']) {
+                            self.position += 2;
+                            let low_surrogate = self.parse_hex_escape()?;
+                            code_point = 0x10000 + ((code_point - 0xD800) << 10) + low_surrogate.wrapping_sub(0xDC00);
+                        }
+                        result.push(char::from_u32(code_point).unwrap_or(char::REPLACEMENT_CHARACTER));
+                    },
+                    _ => return Err(self.error("invalid escape sequence")),
+                },
+                Some(character) if (character as u32) < 0x20 => return Err(self.error("control character in string")),
+                Some(character) => result.push(character),
+                None => return Err(self.error("unterminated string")),
+            }
+        }
+    }
+
+    fn parse_array(&mut self, depth: usize) -> Result<JsonValue, String> {
+        self.expect('[')?;
+        let mut items = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some(']') {
+            self.position += 1;
+            return Ok(JsonValue::Array(items));
+        }
+        loop {
+            items.push(self.parse_value(depth + 1)?);
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some(']') => return Ok(JsonValue::Array(items)),
+                _ => return Err(self.error("expected ',' or ']'")),
+            }
+        }
+    }
+
+    fn parse_object(&mut self, depth: usize) -> Result<JsonValue, String> {
+        self.expect('{')?;
+        let mut members = Vec::new();
+        self.skip_whitespace();
+        if self.peek() == Some('}') {
+            self.position += 1;
+            return Ok(JsonValue::Object(members));
+        }
+        loop {
+            self.skip_whitespace();
+            let name = self.parse_string()?;
+            self.skip_whitespace();
+            self.expect(':')?;
+            members.push((name, self.parse_value(depth + 1)?));
+            self.skip_whitespace();
+            match self.next() {
+                Some(',') => continue,
+                Some('}') => return Ok(JsonValue::Object(members)),
+                _ => return Err(self.error("expected ',' or '}'")),
+            }
+        }
+    }
+}
+
+#[test]
+fn unittest() {
+    let value = parse(r#" {"id": 3, "method": "ecodes", "params": [true, false, null, -1.5e2, "a\"é😀"]} "#).unwrap();
+    assert_eq!(value.get("id"), Some(&JsonValue::Number(3.0)));
+    assert_eq!(value.get("method").and_then(JsonValue::as_str), Some("ecodes"));
+    assert_eq!(value.get("params"), Some(&JsonValue::Array(vec![
+        JsonValue::Bool(true), JsonValue::Bool(false), JsonValue::Null, JsonValue::Number(-150.0),
+        JsonValue::String("a\"\u{e9}\u{1F600}".to_owned()),
+    ])));
+    assert_eq!(value.get("nonexistent"), None);
+    assert_eq!(value.to_string(), "{\"id\":3,\"method\":\"ecodes\",\"params\":[true,false,null,-150,\"a\\\"\u{e9}\u{1F600}\"]}");
+    assert_eq!(parse("{}").unwrap(), JsonValue::Object(Vec::new()));
+    assert_eq!(parse("[ ]").unwrap(), JsonValue::Array(Vec::new()));
+    assert_eq!(parse("0.25").unwrap().to_string(), "0.25");
+
+    assert!(parse("").is_err());
+    assert!(parse("{\"id\": 1").is_err());
+    assert!(parse("{\"id\" 1}").is_err());
+    assert!(parse("[1, 2,]").is_err());
+    assert!(parse("nul").is_err());
+    assert!(parse("\"abc").is_err());
+    assert!(parse("1 2").is_err());
+    assert!(parse("-").is_err());
+    assert!(parse(&"[".repeat(100)).is_err());
+}
@@ -179,6 +179,29 @@ pub fn lex(input: &str) -> Result<Vec<String>, ArgumentError> {
     Ok(tokens)
 }
 
+/// The inverse of `lex()` for a single token: returns a string that `lex()` turns back into `token`.
+/// Tokens that do not contain any special characters are returned as-is.
+pub fn quote(token: &str) -> String {
+    let is_plain = |character: char| character.is_ascii_alphanumeric() || "-_:@.,/=~+%^".contains(character);
+    if ! token.is_empty() && token.chars().all(is_plain) {
+        return token.to_owned();
+    }
+
+    let mut result = String::from("\"");
+    for character in token.chars() {
+        match character {
+            '\\' => result.push_str("\\\\"),
+            '\"' => result.push_str("\\\""),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            _ => result.push(character),
+        }
+    }
+    result.push('\"');
+    result
+}
+
 /// Adds a character to the token that is currently being accumulated. Creates a new
 /// token if no token is currently being accumulated.
 fn push_to_token(token: &mut Option<String>, character: char) {
@@ -268,6 +291,12 @@ fn unittest() {
     );
     
 
+    for token in &["key:a", "", "exec-shell=echo \"Hello, world!\"", "a\\b # c", "line\nbreak", "it's"] {
+        assert_eq!(lex(&quote(token)).unwrap(), vec![token.to_string()]);
+    }
+    assert_eq!(quote("--map"), "--map");
+    assert_eq!(quote("a b"), "\"a b\"");
+
     lex("foo \"bar").unwrap_err();
     lex("foo \\").unwrap_err();
     lex("foo \"'").unwrap_err();